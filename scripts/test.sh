@@ -17,7 +17,6 @@ mkdir -p "${CACHE_ROOT}"
 HASH="$(cat "${REMOTES_FILE}" "${CACHE_FORMAT_DEF}" | shasum -a 256 | head -c 64)"
 CACHE_DIR="${CACHE_ROOT}/test-cache-${HASH}"
 
-export AUTOBIB_RESPONSE_CACHE_PATH="${CACHE_DIR}/responses.dat"
 
 if [[ "${LIBSQLITE3_SYS_USE_PKG_CONFIG:-0}" != "0" ]]; then
     FEATURE_ARGS=(--no-default-features)
@@ -25,18 +24,16 @@ else
     FEATURE_ARGS=()
 fi
 
-if [[ ! -f "${AUTOBIB_RESPONSE_CACHE_PATH}" ]]; then
-    echo 2>&1 "Cache file not found. Generating cache file: ${AUTOBIB_RESPONSE_CACHE_PATH}"
-
-    mkdir -p "${CACHE_DIR}"
+if [[ ! -f "${CACHE_DIR}/responses.dat" ]]; then
+    echo 2>&1 "Cache file not found. Generating cache file: ${CACHE_DIR}/responses.dat"
 
     # Generate the cache file
-    cargo run --locked "${FEATURE_ARGS[@]}" --features write_response_cache -- -vv source --retrieve-only --ignore-null "${REMOTES_FILE}"
+    cargo run --locked "${FEATURE_ARGS[@]}" --features in_memory_database -- -vv --record-http-cache "${CACHE_DIR}" source --retrieve-only --ignore-null "${REMOTES_FILE}"
 else
-    echo 2>&1 "Cache file found: ${AUTOBIB_RESPONSE_CACHE_PATH}"
+    echo 2>&1 "Cache file found: ${CACHE_DIR}/responses.dat"
 fi
 
-cargo test --locked --no-fail-fast "${FEATURE_ARGS[@]}" --features read_response_cache -- "$@"
+AUTOBIB_REPLAY_HTTP_CACHE="${CACHE_DIR}" cargo test --locked --no-fail-fast "${FEATURE_ARGS[@]}" -- "$@"
 
 cargo doc --no-deps --locked "${FEATURE_ARGS[@]}"
 cargo clippy --locked "${FEATURE_ARGS[@]}"
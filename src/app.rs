@@ -1,72 +1,134 @@
+mod alias;
+mod backup;
+mod check;
 mod cli;
+mod completions;
+mod conflicts;
 mod delete;
+mod deps;
+mod diagnose;
 mod edit;
+mod generate;
 mod hist;
 mod import;
 mod info;
 mod log;
+mod lsp;
 mod path;
 mod picker;
+mod refactor;
 mod replace;
 mod retrieve;
+mod serve;
 mod source;
+mod stats;
+mod style;
+mod sync;
+mod tui;
 mod update;
 mod write;
 
 use std::{
+    cell::OnceCell,
+    cmp::Reverse,
     collections::{BTreeSet, HashSet},
+    env::{args_os, current_dir},
     fs::{File, OpenOptions, create_dir_all, exists},
-    io::{IsTerminal, Read, Seek, Write, copy},
+    io::{BufReader, BufWriter, IsTerminal, Read, Seek, Write, copy, stderr},
+    net::TcpListener,
     path::{Path, PathBuf},
     str::FromStr,
 };
 
 use anyhow::{Result, bail};
 use etcetera::{AppStrategy, AppStrategyArgs, choose_app_strategy};
+use nucleo_picker::Render;
 
 use crate::{
     app::{
         cli::{HistCommand, IdTarget, PruneCommand},
-        log::print_log,
+        log::{print_log, print_log_json},
     },
     cite_search::{SourceFileType, get_citekeys},
     config,
     db::{
-        DeleteAliasResult, RecordDatabase, RenameAliasResult,
+        DeleteAliasResult, FieldOverride, Identifier, LinkAliasResult, RecordDatabase,
+        RemapCanonicalResult, RenameAliasResult, lock_database,
         state::{
-            DisambiguatedRecordRow, ExistsOrUnknown, RecordIdState, RecordRowDisplay,
-            RecordRowMoveResult, RemoteIdState, SetActiveError,
+            DisambiguatedRecordRow, ExistsOrUnknown, RecordIdState, RecordRow, RecordRowDisplay,
+            RecordRowMoveResult, RemoteIdState, SetActiveError, SquashError,
         },
         user_version,
     },
-    entry::{Entry, EntryEditCommand, EntryKey, MutableEntryData, RawEntryData},
-    error::AliasErrorKind,
+    entry::{
+        Entry, EntryData, EntryEditCommand, EntryKey, FieldValue, MutableEntryData, RawEntryData,
+    },
+    error::{AliasErrorKind, FailureKind},
     format::Template,
+    fulltext::{open_access_pdf_url, sanitize_filename},
     http::{BodyBytes, Client},
-    logger::{LogDisplay, debug, error, info, suggest, warn},
-    normalize::{Normalization, Normalize},
-    output::{owriteln, stdout_lock_wrap},
-    provider::{RemoteIdCandidate, determine_key_from_data},
+    interrupt::{CatchInterrupt, interrupted},
+    logger::{LogDisplay, debug, error, info, reraise_as, suggest, warn},
+    normalize::{ArticleNumberField, Normalization, Normalize},
+    output::{owrite_json, owriteln, stdout_lock_wrap},
+    path_hash::PathHash,
+    provider::{RemoteIdCandidate, determine_key_from_data, is_canonical, set_response_dir},
     record::{Alias, Record, RecordId, RemoteId, get_record_row, get_record_row_tx},
-    term::Editor,
+    score::completeness_score,
+    term::{Confirm, Editor, Input, set_recovery_dir},
 };
 
 use self::{
-    cli::{AliasCommand, FindMode, InfoReportType, OnConflict, UtilCommand},
-    delete::{hard_delete, soft_delete},
-    edit::{create_alias_if_valid, insert, merge_record_data},
+    alias::{add_alias, export_aliases, import_aliases},
+    backup::{create_backup, list_backups, prune_backups},
+    cli::{
+        AliasCommand, CollectionCommand, ConfigCommand, FindMode, InfoReportType, ListOrder,
+        OnConflict, OverrideCommand, TagCommand, UtilCommand,
+    },
+    conflicts::{resolve_conflicts, write_conflicts},
+    delete::{HardDeleteSummary, hard_delete, hard_delete_summary, soft_delete, write_tombstone},
+    deps::emit_dependencies,
+    diagnose::{diagnose, write_checks},
+    edit::{create_alias_if_valid, insert, merge_record_data, print_edit_report},
     import::ImportConfig,
-    path::{data_from_key, data_from_path, data_from_rev, get_attachment_dir, get_attachment_root},
-    picker::{choose_attachment, choose_attachment_path, choose_canonical_id},
-    retrieve::{retrieve_and_validate_entries, retrieve_entries_read_only},
-    update::update,
-    write::{init_outfile, output_entries, output_keys},
+    lsp::run_language_server,
+    path::{
+        canonical_from_record_id, check_attachment_metadata, check_attachments, data_from_key,
+        data_from_path, data_from_rev, get_attachment_dir, get_attachment_root, layout_path,
+        layout_template, list_attachments, migrate_attachments, move_attachments,
+        open_with_default_application, relink_attachments, relocate_attachments, remove_attachment,
+        scan_attachments, track_attachment,
+    },
+    picker::{
+        choose_attachment, choose_attachment_name, choose_attachment_path, choose_canonical_id,
+    },
+    refactor::{rename_citations, update_alias},
+    retrieve::{
+        expire_null_records, partition_revisions, retrieve_and_validate_entries,
+        retrieve_entries_read_only, retrieve_related_entries, retrieve_revision_entries,
+        stream_entries_read_only, stream_valid_entries,
+    },
+    serve::serve,
+    stats::{collect_stats, write_stats},
+    style::StyleRequirements,
+    sync::sync,
+    tui::browse,
+    update::{IncomingData, update, update_stale, write_stale_update},
+    write::{
+        EntryStream, check_duplicates, init_outfile, lookup_annotations, lookup_overrides,
+        output_entries, output_keys, sort_entries, write_duplicates_report, write_nocite,
+    },
+};
+
+pub use self::{
+    cli::{Cli, Command, LogFormat},
+    completions::write_completions,
 };
 
-pub use self::cli::{Cli, Command};
+pub(crate) use self::check::required_fields;
 
 /// Run the CLI.
-pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
+pub fn run_cli<C: Client>(mut cli: Cli, client: &C) -> Result<()> {
     info!(
         "Autobib version: {} (database version: {})",
         env!("CARGO_PKG_VERSION"),
@@ -74,6 +136,9 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
     );
     info!("SQLite version: {}", rusqlite::version());
 
+    // determined before any fields of `cli` are moved
+    let lock_timeout = cli.lock_timeout();
+
     let strategy = choose_app_strategy(AppStrategyArgs {
         top_level_domain: "org".to_owned(),
         author: env!("CARGO_PKG_NAME").to_owned(),
@@ -81,15 +146,63 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
     })?;
 
     let data_dir = strategy.data_dir();
+    set_recovery_dir(data_dir.join("recovery"));
+    set_response_dir(strategy.cache_dir().join("responses"));
+
+    let (config_path, missing_ok) = cli.config.map_or_else(
+        || (strategy.config_dir().join("config.toml"), true),
+        |path| (path, false),
+    );
+
+    let project_config = if cli.no_project_config {
+        None
+    } else {
+        current_dir()
+            .ok()
+            .and_then(|dir| config::find_project_config(&dir))
+    };
+
+    // the configuration is loaded on first use, since not every command requires it; the HTTP
+    // client is configured once the configuration is loaded
+    let config = OnceCell::new();
+    let load_config = || match config.get() {
+        Some(cfg) => Ok(cfg),
+        None => config::load(
+            &config_path,
+            missing_ok,
+            project_config.as_deref(),
+            cli.profile.as_deref(),
+        )
+        .and_then(|cfg| {
+            client.configure(&cfg.http)?;
+            Ok(config.get_or_init(|| cfg))
+        }),
+    };
+
+    // the project configuration and the profile set the database and the attachment directory,
+    // unless they are provided explicitly, so the configuration must be loaded before opening the
+    // database
+    if cli.profile.is_some() || project_config.is_some() {
+        let cfg = load_config()?;
+        if cli.database.is_empty()
+            && let Some(db_path) = &cfg.database
+        {
+            cli.database.push(db_path.clone());
+        }
+        if cli.attachments_dir.is_none() {
+            cli.attachments_dir.clone_from(&cfg.attachments_dir);
+        }
+    }
 
-    // Open or create the database
-    let mut record_db = if let Some(db_path) = cli.database {
+    // Open or create the database; any additional databases are read-only overlays
+    let mut db_paths = cli.database.into_iter();
+    let db_path = if let Some(db_path) = db_paths.next() {
         // at a user-provided path
         info!("Using user-provided database file '{}'", db_path.display());
         if let Some(db_parent) = db_path.parent() {
             create_dir_all(db_parent)?;
         }
-        RecordDatabase::open(db_path, cli.read_only)?
+        db_path
     } else {
         // at the default path
         let default_db_path = data_dir.join("records.db");
@@ -98,14 +211,49 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
             default_db_path.display()
         );
         create_dir_all(&data_dir)?;
-        RecordDatabase::open(default_db_path, cli.read_only)?
+        default_db_path
+    };
+    // commands which change the database hold the lock until they finish, so that concurrent
+    // invocations do not interleave their changes
+    let _db_lock = if !cli.read_only && !cli.dry_run && cli.command.locks_database() {
+        Some(lock_database(&db_path, lock_timeout)?)
+    } else {
+        None
     };
+    let mut record_db = RecordDatabase::open(&db_path, cli.read_only)?;
     info!("On-disk database version: {}", record_db.user_version()?);
 
-    let (config_path, missing_ok) = cli.config.map_or_else(
-        || (strategy.config_dir().join("config.toml"), true),
-        |path| (path, false),
-    );
+    for overlay_path in db_paths {
+        record_db.attach_overlay(overlay_path)?;
+    }
+
+    if cli.dry_run {
+        info!("Dry run: no changes will be made to the database");
+        record_db.enable_dry_run()?;
+    }
+
+    if let Some(mirror_dir) = &cli.mirror_dir
+        && !cli.read_only
+    {
+        info!("Mirroring records to '{}'", mirror_dir.display());
+        record_db.enable_mirror(mirror_dir.clone())?;
+    }
+
+    // track the changes made to records, so that they can be shown with `autobib hist last`
+    if !cli.read_only {
+        record_db.enable_transcript()?;
+    }
+    let shows_run_summary = cli.command.shows_run_summary();
+
+    // commands which change many records at once can be rolled back with
+    // `autobib util restore-backup`
+    if !cli.read_only && !cli.dry_run && !cli.no_backup && cli.command.writes_backup() {
+        let cfg = load_config()?;
+        if cfg.backup.enabled {
+            create_backup(&record_db, &db_path)?;
+            prune_backups(&db_path, cfg.backup.keep)?;
+        }
+    }
 
     info!("Interactive: {}", !cli.no_interactive);
     info!("Read-only: {}", cli.read_only);
@@ -114,14 +262,8 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
     match cli.command {
         Command::Alias { alias_command } => match alias_command {
             AliasCommand::Add { alias, target } => {
-                info!("Creating alias '{alias}' for '{target}'");
-                let cfg = config::load(&config_path, missing_ok)?;
-                let (_, row) = get_record_row(&mut record_db, target, client, &cfg)?
-                    .exists_or_commit_null("Cannot create alias for")?;
-                if !row.add_alias(&alias)? {
-                    error!("Alias already exists: '{alias}'");
-                }
-                row.commit()?;
+                let cfg = load_config()?;
+                add_alias(&mut record_db, &alias, target, client, cfg)?;
             }
             AliasCommand::Delete { alias } => {
                 info!("Deleting alias '{alias}'");
@@ -130,6 +272,12 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                     DeleteAliasResult::Missing => {
                         bail!("Could not delete alias which does not exist: '{alias}'")
                     }
+                    DeleteAliasResult::Linked(links) => {
+                        bail!(
+                            "Could not delete alias '{alias}' which is the target of the aliases: {}",
+                            links.join(", ")
+                        )
+                    }
                 }
             }
             AliasCommand::Rename { alias, new } => {
@@ -143,8 +291,8 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
             }
             AliasCommand::Reassign { alias, target } => {
                 info!("Updating alias '{alias}' to point to '{target}'");
-                let cfg = config::load(&config_path, missing_ok)?;
-                let (_, row) = get_record_row(&mut record_db, target, client, &cfg)?
+                let cfg = load_config()?;
+                let (_, row) = get_record_row(&mut record_db, target, client, cfg)?
                     .exists_or_commit_null("Cannot create alias for")?;
                 if !row.update_alias(&alias)? {
                     error!("Alias does not exist!");
@@ -152,12 +300,76 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                 }
                 row.commit()?;
             }
+            AliasCommand::Link { alias, target } => {
+                info!("Creating alias '{alias}' which refers to alias '{target}'");
+                match record_db.link_alias(&alias, &target)? {
+                    LinkAliasResult::Linked => {}
+                    LinkAliasResult::AliasExists => bail!("Alias already exists: '{alias}'"),
+                    LinkAliasResult::TargetUndefined => bail!("Undefined alias: '{target}'"),
+                }
+            }
+            AliasCommand::Flatten { aliases } => {
+                let flattened = record_db.flatten_alias_links(&aliases)?;
+                info!("Flattened {flattened} aliases");
+            }
+            AliasCommand::List { identifier } => {
+                let canonical = match identifier {
+                    Some(identifier) => {
+                        let cfg = load_config()?;
+                        match canonical_from_record_id(&mut record_db, identifier, cfg)? {
+                            Some(canonical) => Some(canonical),
+                            None => return Ok(()),
+                        }
+                    }
+                    None => None,
+                };
+                let mut lock = stdout_lock_wrap();
+                for (alias, target) in record_db.aliases()? {
+                    match &canonical {
+                        Some(canonical) if *canonical == target => writeln!(lock, "{alias}")?,
+                        Some(_) => {}
+                        None => writeln!(lock, "{alias}\t{target}")?,
+                    }
+                }
+            }
+            AliasCommand::Of { alias } => {
+                if !record_db.contains_alias(&alias)? {
+                    bail!("Undefined alias: '{alias}'");
+                }
+                let chain = record_db.alias_chain(&alias)?;
+                let cfg = load_config()?;
+                let Some(canonical) =
+                    canonical_from_record_id(&mut record_db, RecordId::from(alias.name()), cfg)?
+                else {
+                    return Ok(());
+                };
+                let mut lock = stdout_lock_wrap();
+                write!(lock, "{alias}")?;
+                for target in chain {
+                    write!(lock, " -> {target}")?;
+                }
+                writeln!(lock, " -> {canonical}")?;
+            }
+            AliasCommand::Export { file } => {
+                let exported = export_aliases(&mut record_db, &file)?;
+                info!("Exported {exported} aliases to '{}'", file.display());
+            }
+            AliasCommand::Import { file } => {
+                let cfg = load_config()?;
+                let imported = import_aliases(&mut record_db, &file, client, cfg)?;
+                info!("Read {imported} aliases from '{}'", file.display());
+            }
         },
         Command::Attach {
             identifier,
             file,
             rename,
             force,
+            list,
+            remove,
+            auto,
+            scan,
+            move_files,
         } => {
             /// Determine the target filename from the `rename` value (if any), and otherwise
             /// use the provided fallback
@@ -188,12 +400,104 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                 Ok(())
             }
 
+            if let Some(dir) = scan {
+                let cfg = load_config()?;
+                let template = if cli.no_interactive {
+                    None
+                } else {
+                    match Template::compile(&cfg.find.default_template) {
+                        Ok(t) => Some(t),
+                        Err(err) => {
+                            bail!(
+                                "Syntax error in `find.default_template` configuration value: {err}"
+                            );
+                        }
+                    }
+                };
+                let attachment_root = get_attachment_root(&data_dir, cli.attachments_dir)?;
+                let mut stdout = stdout_lock_wrap();
+                return scan_attachments(
+                    &dir,
+                    record_db,
+                    cfg,
+                    &attachment_root,
+                    template.as_ref(),
+                    move_files,
+                    &mut stdout,
+                );
+            }
+
+            // SAFETY: the identifier is required unless `--scan` is set
+            let identifier = identifier.expect("Identifier argument is required");
+
+            if list || remove {
+                let cfg = load_config()?;
+                let Some(canonical) = canonical_from_record_id(&mut record_db, identifier, cfg)?
+                else {
+                    return Ok(());
+                };
+                let attachment_dir =
+                    get_attachment_dir(&data_dir, cli.attachments_dir, &canonical)?;
+
+                match file {
+                    Some(name) if remove => {
+                        remove_attachment(&attachment_dir, &name)?;
+                        record_db.delete_attachment(&canonical, &name)?;
+                    }
+                    _ => {
+                        for attachment in list_attachments(&attachment_dir)? {
+                            owriteln!("{}", attachment.display())?;
+                        }
+                    }
+                }
+                return Ok(());
+            }
             // Extend with the filename.
-            let cfg = config::load(&config_path, missing_ok)?;
-            let (record, row) = get_record_row(&mut record_db, identifier, client, &cfg)?
+            let cfg = load_config()?;
+            let (record, row) = get_record_row(&mut record_db, identifier, client, cfg)?
                 .exists_or_commit_null("Cannot attach file for")?;
-            row.commit()?;
-            let mut target = get_attachment_dir(&data_dir, cli.attachments_dir, &record.canonical)?;
+
+            // determine the download location and the filename from the record data
+            let (file, rename) = if auto {
+                let data = row.get_data()?;
+                row.commit()?;
+                let Some(url) = open_access_pdf_url(
+                    &data.canonical,
+                    &data.data,
+                    cfg.attach.email.as_deref(),
+                    client,
+                )?
+                else {
+                    bail!("Could not find an open-access PDF for '{}'", data.canonical);
+                };
+                let rename = match rename {
+                    Some(rename) => rename,
+                    None => {
+                        let template = match Template::compile(&cfg.attach.filename) {
+                            Ok(t) => t,
+                            Err(err) => {
+                                bail!(
+                                    "Syntax error in `attach.filename` configuration value: {err}"
+                                );
+                            }
+                        };
+                        let name = if template.has_keys_contained_in(&data) {
+                            template.render(&data)
+                        } else {
+                            format!("{}.pdf", data.canonical)
+                        };
+                        PathBuf::from(sanitize_filename(&name))
+                    }
+                };
+                (url, Some(rename))
+            } else {
+                row.commit()?;
+                // SAFETY: the file is required unless `--list` or `--auto` is set
+                (file.expect("File argument is required"), rename)
+            };
+            let attachment_dir =
+                get_attachment_dir(&data_dir, cli.attachments_dir, &record.canonical)?;
+            let mut target = attachment_dir.clone();
 
             let mut opts = OpenOptions::new();
             opts.write(true);
@@ -202,7 +506,7 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
             }
 
             // create the destination directory
-            create_dir_all(&target)?;
+            create_dir_all(&attachment_dir)?;
 
             match ureq::http::Uri::try_from(&file) {
                 Ok(uri) if uri.scheme().is_some() => {
@@ -244,6 +548,7 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                                 );
                             }
                         }
+                        return Ok(());
                     }
                 }
                 _ => {
@@ -260,12 +565,126 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                     copy(&mut source_file, &mut target_file)?;
                 }
             }
+
+            track_attachment(&mut record_db, &record.canonical, &attachment_dir, &target)?;
+        }
+        Command::Check { file, json } => {
+            let cfg = load_config()?;
+            let mut scratch = Vec::new();
+            File::open(&file)?.read_to_end(&mut scratch)?;
+            let report = check::check_bibtex(&mut record_db, &scratch, &cfg.alias_transform)?;
+            if json {
+                owrite_json(&report)?;
+            } else {
+                report.write_text(stdout_lock_wrap())?;
+            }
+            if report.problems.is_empty() {
+                info!(
+                    "Checked {} entries in '{}' with no problems",
+                    report.entries,
+                    file.display()
+                );
+            } else {
+                error!(
+                    "Found {} problems in '{}'",
+                    report.problems.len(),
+                    file.display()
+                );
+            }
+        }
+        Command::Collection { collection_command } => match collection_command {
+            CollectionCommand::Create { name } => {
+                if record_db.create_collection(&name)? {
+                    info!("Created collection '{name}'");
+                } else {
+                    error!("Collection already exists: '{name}'");
+                }
+            }
+            CollectionCommand::Delete { name } => {
+                if record_db.delete_collection(&name)? {
+                    info!("Deleted collection '{name}'");
+                } else {
+                    error!("Collection does not exist: '{name}'");
+                }
+            }
+            CollectionCommand::Add { name, identifiers } => {
+                if !record_db.collection_exists(&name)? {
+                    error!("Collection does not exist: '{name}'");
+                    suggest!("Create the collection with `autobib collection create {name}`.");
+                    return Ok(());
+                }
+                let cfg = load_config()?;
+                for identifier in identifiers {
+                    let Some(canonical) =
+                        canonical_from_record_id(&mut record_db, identifier, cfg)?
+                    else {
+                        continue;
+                    };
+                    if !record_db.insert_collection_record(&name, &canonical)? {
+                        warn!("Collection '{name}' already contains '{canonical}'");
+                    }
+                }
+            }
+            CollectionCommand::Remove { name, identifiers } => {
+                if !record_db.collection_exists(&name)? {
+                    bail!("Collection does not exist: '{name}'");
+                }
+                let cfg = load_config()?;
+                for identifier in identifiers {
+                    let Some(canonical) =
+                        canonical_from_record_id(&mut record_db, identifier, cfg)?
+                    else {
+                        continue;
+                    };
+                    if !record_db.delete_collection_record(&name, &canonical)? {
+                        error!("Collection '{name}' does not contain '{canonical}'");
+                    }
+                }
+            }
+            CollectionCommand::List { name: Some(name) } => {
+                if !record_db.collection_exists(&name)? {
+                    bail!("Collection does not exist: '{name}'");
+                }
+                let mut lock = stdout_lock_wrap();
+                for canonical in record_db.collection_records(&name)? {
+                    writeln!(lock, "{canonical}")?;
+                }
+            }
+            CollectionCommand::List { name: None } => {
+                let mut lock = stdout_lock_wrap();
+                for (name, size) in record_db.collection_sizes()? {
+                    writeln!(lock, "{name}\t{size}")?;
+                }
+            }
+        },
+        Command::CompleteKeys { prefix } => {
+            let snapshot = record_db.snapshot()?;
+            let mut lock = stdout_lock_wrap();
+            snapshot.map_identifiers(false, |key_str| {
+                if key_str.starts_with(&prefix) {
+                    writeln!(lock, "{key_str}")
+                } else {
+                    Ok(())
+                }
+            })?;
+            snapshot.commit()?;
         }
         Command::Completions { shell: _ } => {
             unreachable!(
                 "Request for completions script should have been handled earlier and the program should have exited then."
             );
         }
+        Command::Config { config_command } => match config_command {
+            ConfigCommand::Get { key } => {
+                match config::get_value(&config_path, missing_ok, &key)? {
+                    toml::Value::String(s) => owriteln!("{s}")?,
+                    value => owriteln!("{value}")?,
+                }
+            }
+            ConfigCommand::Set { key, value } => {
+                config::set_value(&config_path, &key, &value)?;
+            }
+        },
         Command::DefaultConfig => {
             config::write_default(stdout_lock_wrap())?;
         }
@@ -273,35 +692,105 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
             identifiers,
             hard,
             delete_aliases,
+            yes,
+            tombstone,
         } => {
-            let cfg = config::load(&config_path, missing_ok)?;
+            let cfg = load_config()?;
             if hard {
                 if delete_aliases {
                     warn!("Redundant flag `--delete-aliases` is implied by `--hard`");
                 }
+
+                let attachment_root = get_attachment_root(&data_dir, cli.attachments_dir)?;
+                let mut summaries: Vec<HardDeleteSummary> = Vec::new();
                 for key in identifiers {
-                    hard_delete(key, &mut record_db, &cfg)?;
+                    if let Some(summary) =
+                        hard_delete_summary(key, &mut record_db, cfg, |canonical| {
+                            let mut dir = attachment_root.clone();
+                            canonical.extend_attachments_path(&mut dir);
+                            dir
+                        })?
+                        && !summaries.iter().any(|s| s.canonical == summary.canonical)
+                    {
+                        summaries.push(summary);
+                    }
+                }
+                if summaries.is_empty() {
+                    return Ok(());
+                }
+
+                if cli.dry_run {
+                    eprintln!("The following records would be permanently deleted:");
+                    for summary in &summaries {
+                        eprint!("{summary}");
+                    }
+                    return Ok(());
+                }
+
+                if !yes {
+                    eprintln!("The following records will be permanently deleted:");
+                    for summary in &summaries {
+                        eprint!("{summary}");
+                    }
+                    if cli.no_interactive {
+                        bail!(
+                            "Hard deletion requires confirmation: use `--yes` to delete without confirmation"
+                        );
+                    }
+                    let answer = Input::new("Type 'delete' to confirm").input()?;
+                    if answer.trim() != "delete" {
+                        bail!("Hard deletion cancelled");
+                    }
+                }
+
+                if let Some(path) = tombstone {
+                    write_tombstone(&path, &summaries)?;
+                }
+
+                for summary in summaries {
+                    info!("Permanently deleting '{}'", summary.canonical);
+                    hard_delete(
+                        RecordId::from(summary.canonical.name()),
+                        &mut record_db,
+                        cfg,
+                    )?;
                 }
             } else {
                 for key in identifiers {
-                    soft_delete(key, &None, &mut record_db, &cfg, delete_aliases)?;
+                    soft_delete(key, &None, &mut record_db, cfg, delete_aliases)?;
                 }
             }
         }
         Command::Edit {
-            identifiers,
+            mut identifiers,
+            query,
             normalize_whitespace,
             set_eprint,
             strip_journal_series,
+            protect_case,
+            normalize_names,
+            eid_to_pages,
             update_entry_type,
             set_field,
             delete_field,
         } => {
-            let cfg = config::load(&config_path, missing_ok)?;
+            let cfg = load_config()?;
+            if protect_case && cfg.normalize.protected_words.is_empty() {
+                warn!("No words to protect: `normalize.protected_words` is not set");
+            }
             let nl = Normalization {
                 normalize_whitespace,
                 set_eprint,
                 strip_journal_series,
+                protect_case,
+                protected_words: cfg.normalize.protected_words.clone(),
+                normalize_names,
+                name_format: cfg.normalize.name_format,
+                article_number: if eid_to_pages {
+                    ArticleNumberField::Pages
+                } else {
+                    ArticleNumberField::Eid
+                },
             };
 
             let edit_cmd = EntryEditCommand {
@@ -312,9 +801,69 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
 
             let no_non_interactive_cmd = nl.is_identity() && edit_cmd.is_identity();
 
+            if let Some(query) = query {
+                if no_non_interactive_cmd {
+                    bail!("No edit action specified for `--where`");
+                }
+
+                record_db.map_active_records(|row| {
+                    if query.matches(&row) {
+                        identifiers.push(RecordId::from(row.canonical.name()));
+                    }
+                })?;
+                info!("Query matched {} record(s)", identifiers.len());
+            }
+
+            if cli.dry_run {
+                if no_non_interactive_cmd {
+                    bail!("No edit action specified for `--dry-run`");
+                }
+
+                for key in identifiers {
+                    match record_db.state_from_record_id(key, &cfg.alias_transform)? {
+                        RecordIdState::Entry(
+                            _,
+                            RecordRow {
+                                data, canonical, ..
+                            },
+                            state,
+                        ) => {
+                            state.commit()?;
+                            let mut editable_data = MutableEntryData::from_entry_data(&data);
+                            editable_data.normalize(&nl);
+                            editable_data.edit(&edit_cmd);
+                            print_edit_report(&canonical, &data, &editable_data)?;
+                        }
+                        RecordIdState::Deleted(key, _, state) => {
+                            state.commit()?;
+                            error!("Cannot edit deleted record '{key}'");
+                        }
+                        RecordIdState::Void(key, _, state) => {
+                            state.commit()?;
+                            error!("Cannot edit voided record '{key}'");
+                        }
+                        RecordIdState::NullRemoteId(remote_id, state) => {
+                            state.commit()?;
+                            error!(kind = FailureKind::NullRecord; "Cannot edit null record '{remote_id}'");
+                        }
+                        RecordIdState::Unknown(unknown) => {
+                            let mapped = unknown.combine_and_commit()?;
+                            error!("Database does not contain key: {mapped}");
+                        }
+                        RecordIdState::UndefinedAlias(alias) => {
+                            error!("Cannot edit undefined alias '{alias}'");
+                        }
+                        RecordIdState::InvalidRemoteId(err) => {
+                            reraise_as(FailureKind::InvalidIdentifier, &err);
+                        }
+                    }
+                }
+                return Ok(());
+            }
+
             for key in identifiers {
                 let (Record { key, data, .. }, row) =
-                    get_record_row(&mut record_db, key, client, &cfg)?
+                    get_record_row(&mut record_db, key, client, cfg)?
                         .exists_or_commit_null("Cannot edit")?;
 
                 match (cli.no_interactive, no_non_interactive_cmd) {
@@ -365,12 +914,13 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
             template: format,
             strict,
             mode: find_mode,
+            tag,
         } => {
             if cli.no_interactive {
                 bail!("`autobib find` cannot run in non-interactive mode");
             }
 
-            let cfg = config::load(&config_path, missing_ok)?;
+            let cfg = load_config()?;
 
             // read template, or load from config / use default
             let template = match format {
@@ -383,12 +933,24 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                 },
             };
 
+            let tagged = match tag {
+                Some(tag) => Some(
+                    record_db
+                        .tagged(&tag)?
+                        .into_iter()
+                        .map(|canonical| canonical.name().to_owned())
+                        .collect(),
+                ),
+                None => None,
+            };
+
             match find_mode {
                 FindMode::Attachments => {
                     let mut picker = choose_attachment_path(
                         record_db,
                         template,
                         strict,
+                        tagged,
                         get_attachment_root(&data_dir, cli.attachments_dir)?,
                         cfg.find.ignore_hidden,
                         Path::is_file,
@@ -417,10 +979,11 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                     }
                 }
                 FindMode::CanonicalId => {
-                    let (mut picker, handle) = choose_canonical_id(record_db, template, strict);
+                    let (mut picker, handle) =
+                        choose_canonical_id(record_db, template, strict, tagged);
                     match picker.pick()? {
                         Some(row_data) => {
-                            let cfg = config::load(&config_path, missing_ok)?;
+                            let cfg = load_config()?;
                             if !cfg.preferred_providers.is_empty() {
                                 // get a key from the preferred provider if possible
                                 let mut record_db =
@@ -429,10 +992,10 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                                     RemoteIdState::Entry(_, row) => {
                                         // try to find a referencing key with the expected provider
                                         let referencing_ids = row.referencing_remote_ids()?;
-                                        for provider in cfg.preferred_providers {
+                                        for provider in &cfg.preferred_providers {
                                             if let Some(remote_id) = referencing_ids
                                                 .iter()
-                                                .find(|id| id.provider() == provider)
+                                                .find(|id| id.provider() == provider.as_str())
                                             {
                                                 owriteln!("{remote_id}")?;
                                                 return Ok(());
@@ -453,15 +1016,51 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                     }
                 }
             }
+
+            // the database was moved to the picker, and no records were changed, so there is no
+            // run to save
+            return Ok(());
         }
         Command::Get {
-            identifiers,
+            mut identifiers,
             out,
             append,
             retrieve_only,
             ignore_null,
+            refresh_null,
+            rev,
+            report_duplicates,
+            flavor,
+            sort,
+            style: style_args,
+            with_parents,
+            with_related,
+            tag,
+            collection,
         } => {
-            let mut outfile = init_outfile(out, append)?;
+            if rev.is_some() && identifiers.len() != 1 {
+                bail!("Option `--rev` requires exactly one identifier");
+            }
+            let cfg = load_config()?;
+
+            // the default output is only used if no other output is requested
+            let (out, append) = match out {
+                Some(path) => (Some(path), append),
+                None if retrieve_only => (None, append),
+                None => match &cfg.get.default_out {
+                    Some(path) => {
+                        info!("Writing to default output file '{}'", path.display());
+                        (Some(path.clone()), append || cfg.get.default_append)
+                    }
+                    None => (None, append),
+                },
+            };
+            if append && out.is_none() {
+                bail!(
+                    "Option `--append` requires `--out` or the `get.default_out` configuration value"
+                );
+            }
+            let mut outfile = init_outfile(out.as_ref(), append)?;
 
             // Initialize the skipped keys to contain keys already present in the outfile (if
             // appending)
@@ -474,17 +1073,44 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                 get_citekeys(SourceFileType::Bib, &scratch, &mut skipped_ids);
             }
 
+            if let Some(tag) = tag {
+                let tagged = record_db.tagged(&tag)?;
+                if tagged.is_empty() {
+                    warn!("No records tagged with '{tag}'");
+                }
+                identifiers.extend(
+                    tagged
+                        .iter()
+                        .map(|canonical| RecordId::from(canonical.name())),
+                );
+            }
+            if let Some(collection) = collection {
+                if !record_db.collection_exists(&collection)? {
+                    bail!("Collection does not exist: '{collection}'");
+                }
+                identifiers.extend(
+                    record_db
+                        .collection_records(&collection)?
+                        .iter()
+                        .map(|canonical| RecordId::from(canonical.name())),
+                );
+            }
+
             // Collect all entries which are not null, excluding those which should be skipped
-            let cfg = config::load(&config_path, missing_ok)?;
-            let not_skipped_ids = identifiers.into_iter().filter(|k| !skipped_ids.contains(k));
+            let (current_ids, revision_ids) = partition_revisions(identifiers, rev);
+            // null records are only expired if they can be retrieved again
+            if refresh_null && !cli.read_only && !cli.offline {
+                expire_null_records(&current_ids, &mut record_db, usize::MAX, 0, cfg)?;
+            }
+            let not_skipped_ids = current_ids.into_iter().filter(|k| !skipped_ids.contains(k));
 
-            let valid_entries = if cli.read_only {
+            let mut valid_entries = if cli.read_only {
                 retrieve_entries_read_only(
                     not_skipped_ids,
                     &mut record_db,
                     retrieve_only,
                     ignore_null,
-                    &cfg,
+                    cfg,
                 )
             } else {
                 retrieve_and_validate_entries(
@@ -493,15 +1119,90 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                     client,
                     retrieve_only,
                     ignore_null,
-                    &cfg,
+                    cfg,
                 )
             };
 
             if !retrieve_only {
-                output_entries(outfile, append, valid_entries)?;
+                retrieve_revision_entries(
+                    revision_ids
+                        .into_iter()
+                        .filter(|(k, _)| !skipped_ids.contains(k)),
+                    &mut record_db,
+                    cfg,
+                    &mut valid_entries,
+                );
+
+                if with_parents || with_related {
+                    retrieve_related_entries(
+                        &mut record_db,
+                        cfg,
+                        &mut valid_entries,
+                        &skipped_ids,
+                        with_related,
+                    )?;
+                }
+
+                check_duplicates(&valid_entries, cfg.output.duplicates);
+                if let Some(path) = report_duplicates {
+                    write_duplicates_report(&path, &valid_entries)?;
+                }
+
+                let annotations = if cfg.output.annotate {
+                    Some(lookup_annotations(&mut record_db, &valid_entries)?)
+                } else {
+                    None
+                };
+                let overrides = lookup_overrides(&mut record_db, out.as_deref())?;
+                let preamble = if cfg.output.string_preamble {
+                    record_db.macros()?
+                } else {
+                    Vec::new()
+                };
+                let sorted_entries = sort_entries(
+                    &mut record_db,
+                    valid_entries,
+                    sort.unwrap_or(cfg.output.sort),
+                )?;
+                output_entries(
+                    outfile,
+                    append,
+                    sorted_entries,
+                    annotations,
+                    flavor.or(cfg.output.flavor),
+                    overrides,
+                    preamble,
+                    style_args.apply(cfg.output.style()),
+                )?;
             }
         }
         Command::Hist { hist_command } => match hist_command {
+            HistCommand::Diff {
+                identifier,
+                revisions,
+            } => {
+                let cfg = load_config()?;
+                if let Some((_, row)) = record_db
+                    .state_from_record_id(identifier, &cfg.alias_transform)?
+                    .require_record()?
+                {
+                    let (_, state) = row.forget();
+                    let mut stdout = stdout_lock_wrap();
+                    let styled = stdout.supports_styled_output();
+                    hist::write_revision_diff(&mut stdout, state, &revisions, styled)?;
+                }
+            }
+            HistCommand::Last { json } => match record_db.last_run()? {
+                Some(summary) if json => {
+                    owrite_json(&summary)?;
+                }
+                Some(summary) => {
+                    hist::write_run_summary(&mut stdout_lock_wrap(), &summary)?;
+                }
+                None => {
+                    warn!("No changes have been recorded");
+                }
+            },
             HistCommand::Prune { prune_command } => {
                 let snapshot = record_db.snapshot()?;
                 match prune_command {
@@ -520,7 +1221,7 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                 revive,
             } => {
                 let index = index.unwrap_or(-1);
-                let cfg = config::load(&config_path, missing_ok)?;
+                let cfg = load_config()?;
                 match record_db
                     .state_from_record_id(identifier, &cfg.alias_transform)?
                     .require_record()?
@@ -570,7 +1271,7 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                 }
             }
             HistCommand::Reset { identifier, rev } => {
-                let cfg = config::load(&config_path, missing_ok)?;
+                let cfg = load_config()?;
                 if let Some((_, disambiguated)) = record_db
                     .state_from_record_id(identifier, &cfg.alias_transform)?
                     .require_record()?
@@ -598,13 +1299,49 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                     }
                 }
             }
+            HistCommand::Squash {
+                identifier,
+                from,
+                to,
+            } => {
+                let cfg = load_config()?;
+                if let Some((_, disambiguated)) = record_db
+                    .state_from_record_id(identifier, &cfg.alias_transform)?
+                    .require_record()?
+                {
+                    let (_, state) = disambiguated.forget();
+
+                    match state.squash(from, to)? {
+                        Ok(removed) => {
+                            info!("Squashed {removed} revisions into {to}");
+                        }
+                        Err(SquashError::RevisionUndefined(rev)) => {
+                            error!("Revision does not exist for this record: {rev}");
+                        }
+                        Err(SquashError::NotAncestor) => {
+                            error!("Cannot squash: {from} is not an ancestor of {to}");
+                        }
+                        Err(SquashError::Active(rev)) => {
+                            error!("Cannot squash active revision: {rev}");
+                            suggest!(
+                                "Squash up to the active revision, or change the active revision with `autobib hist reset`"
+                            );
+                        }
+                        Err(SquashError::Divergent(rev)) => {
+                            error!("Cannot squash {rev} with divergent changes");
+                            suggest!("Review the changes with `autobib log --all`");
+                        }
+                    }
+                    state.commit()?;
+                }
+            }
             HistCommand::Revive {
                 identifier,
                 from_bibtex,
                 with_entry_type,
                 with_field,
             } => {
-                let cfg = config::load(&config_path, missing_ok)?;
+                let cfg = load_config()?;
                 let edit_cmd = EntryEditCommand {
                     update_entry_type: with_entry_type,
                     set_field: with_field,
@@ -627,6 +1364,7 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                             &cfg.on_insert,
                             &edit_cmd,
                             None,
+                            None,
                         )?;
                     }
                     Some((_, DisambiguatedRecordRow::Void(data, state))) => {
@@ -638,6 +1376,7 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                             &cfg.on_insert,
                             &edit_cmd,
                             None,
+                            None,
                         )?;
                     }
                     None => {}
@@ -652,7 +1391,7 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                     snapshot.rewind_all(before)?;
                     snapshot.commit()?;
                 } else if let Some(record_id) = id {
-                    let cfg = config::load(&config_path, missing_ok)?;
+                    let cfg = load_config()?;
                     if let Some((_, disambiguated)) = record_db
                         .state_from_record_id(record_id, &cfg.alias_transform)?
                         .require_record()?
@@ -666,14 +1405,35 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                     unreachable!("ArgGroup requires one of these arguments");
                 }
             }
-            HistCommand::Show { limit } => {
+            HistCommand::Show {
+                limit,
+                sessions: true,
+                json,
+            } => {
+                let sessions = record_db.runs(limit)?;
+                if json {
+                    owrite_json(&sessions)?;
+                } else {
+                    hist::write_sessions(&mut stdout_lock_wrap(), &sessions)?;
+                }
+            }
+            HistCommand::Show { limit, json, .. } => {
                 let snapshot = record_db.snapshot()?;
-                let mut stdout = stdout_lock_wrap();
-                let styled = stdout.supports_styled_output();
-                snapshot.map_history(limit, |record_row, rev_id| {
-                    let disp = RecordRowDisplay::from_borrowed_row(record_row, rev_id, styled);
-                    writeln!(&mut stdout, "{disp}\n")
-                })?;
+                if json {
+                    let mut rows = Vec::new();
+                    snapshot.map_history(limit, |record_row, rev_id| {
+                        let disp = RecordRowDisplay::from_borrowed_row(record_row, rev_id, false);
+                        serde_json::to_value(disp).map(|value| rows.push(value))
+                    })?;
+                    owrite_json(&rows)?;
+                } else {
+                    let mut stdout = stdout_lock_wrap();
+                    let styled = stdout.supports_styled_output();
+                    snapshot.map_history(limit, |record_row, rev_id| {
+                        let disp = RecordRowDisplay::from_borrowed_row(record_row, rev_id, styled);
+                        writeln!(&mut stdout, "{disp}\n")
+                    })?;
+                }
                 snapshot.commit()?;
             }
             HistCommand::Touch {
@@ -686,8 +1446,8 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                     modified
                 } else if let Some(record_id) = id {
                     let modified = chrono::Local::now();
-                    let cfg = config::load(&config_path, missing_ok)?;
-                    let (_, row) = get_record_row(&mut record_db, record_id, client, &cfg)?
+                    let cfg = load_config()?;
+                    let (_, row) = get_record_row(&mut record_db, record_id, client, cfg)?
                         .exists_or_commit_null("Cannot edit")?;
                     row.touch_with_timestamp(&modified)?.commit()?;
                     modified
@@ -696,34 +1456,50 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                 };
                 owriteln!("{modified}")?;
             }
-            HistCommand::Undo { identifier, delete } => {
-                let cfg = config::load(&config_path, missing_ok)?;
-                match record_db
-                    .state_from_record_id(identifier, &cfg.alias_transform)?
-                    .require_record()?
-                {
-                    Some((_, DisambiguatedRecordRow::Entry(_, state))) => {
-                        if delete {
-                            hist::handle_undo_result(state.undo_delete()?)?;
-                        } else {
-                            hist::handle_undo_result(state.undo()?)?;
-                        };
+            HistCommand::Undo {
+                session: Some(session),
+                ..
+            } => {
+                hist::undo_session(&mut record_db, session)?;
+            }
+            HistCommand::Undo {
+                identifiers,
+                delete,
+                since,
+                atomic,
+                session: None,
+            } => {
+                let cfg = load_config()?;
+                let (remote_ids, resolved) =
+                    hist::select_records(&mut record_db, identifiers, &cfg.alias_transform)?;
+
+                if atomic {
+                    if !resolved {
+                        bail!("Not all identifiers could be resolved; no records were changed");
                     }
-                    Some((_, DisambiguatedRecordRow::Deleted(_, state))) => {
-                        if delete {
-                            hist::handle_undo_result(state.undo_delete()?)?;
-                        } else {
-                            hist::handle_undo_result(state.undo()?)?;
-                        };
+                    let mut tx = record_db.transaction()?;
+                    let mut succeeded = true;
+                    for remote_id in &remote_ids {
+                        let undone;
+                        (tx, undone) = hist::undo_in_tx(tx, remote_id, delete, since)?;
+                        succeeded &= undone;
+                    }
+                    if succeeded {
+                        tx.commit()?;
+                    } else {
+                        tx.rollback()?;
+                        error!("Not all records could be undone; no records were changed");
                     }
-                    Some((_, DisambiguatedRecordRow::Void(_, _))) => {
-                        error!("Nothing to undo!");
+                } else {
+                    for remote_id in &remote_ids {
+                        let tx = record_db.transaction()?;
+                        let (tx, _) = hist::undo_in_tx(tx, remote_id, delete, since)?;
+                        tx.commit()?;
                     }
-                    None => {}
                 }
             }
             HistCommand::Void { identifier } => {
-                let cfg = config::load(&config_path, missing_ok)?;
+                let cfg = load_config()?;
                 match record_db
                     .state_from_record_id(identifier, &cfg.alias_transform)?
                     .require_record()?
@@ -750,7 +1526,29 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
             no_alias,
             include_files,
             file_sep,
+            from_pdf_dir,
+            keep_relations,
+            conflicts_out,
+            batch_size,
         } => {
+            if cli.dry_run && (include_files || from_pdf_dir.is_some()) {
+                bail!("Cannot attach files with `--dry-run`");
+            }
+
+            if let Some(dir) = from_pdf_dir {
+                let cfg = load_config()?;
+                let attachment_root = get_attachment_root(&data_dir, cli.attachments_dir)?;
+                let mut stdout = stdout_lock_wrap();
+                return import::from_pdf_dir(
+                    &dir,
+                    &mut record_db,
+                    client,
+                    cfg,
+                    &attachment_root,
+                    &mut stdout,
+                );
+            }
+
             let import_config = ImportConfig {
                 update,
                 resolve,
@@ -758,17 +1556,28 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                 no_alias,
                 include_files,
                 file_sep,
+                keep_relations,
+                batch_size,
             };
 
             debug!("Using import configuration: {import_config:?}");
-            let cfg = config::load(&config_path, missing_ok)?;
+            let cfg = load_config()?;
 
             let mut scratch = Vec::new();
 
             let attachment_root = get_attachment_root(&data_dir, cli.attachments_dir)?;
 
             let mut stdout = stdout_lock_wrap();
+            let mut conflicts = Vec::new();
+            let _catch = CatchInterrupt::new();
             for bibfile in targets {
+                if interrupted() {
+                    error!(
+                        "Not importing '{}' since the import was interrupted",
+                        bibfile.display()
+                    );
+                    continue;
+                }
                 scratch.clear();
                 match File::open(&bibfile).and_then(|mut file| file.read_to_end(&mut scratch)) {
                     Ok(_) => {
@@ -777,10 +1586,11 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                             &import_config,
                             &mut record_db,
                             client,
-                            &cfg,
+                            cfg,
                             &attachment_root,
                             bibfile.display(),
                             &mut stdout,
+                            &mut conflicts,
                         )?;
                     }
                     Err(err) => error!(
@@ -789,10 +1599,58 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                     ),
                 }
             }
+
+            if let Some(path) = conflicts_out {
+                write_conflicts(&path, conflicts)?;
+            }
         }
-        Command::Info { identifier, report } => {
-            let cfg = config::load(&config_path, missing_ok)?;
+        Command::ImportPdf { files } => {
+            let cfg = load_config()?;
+            let attachment_root = get_attachment_root(&data_dir, cli.attachments_dir)?;
+            let mut stdout = stdout_lock_wrap();
+            import::from_pdf_files(
+                &files,
+                &mut record_db,
+                client,
+                cfg,
+                &attachment_root,
+                &mut stdout,
+            )?;
+        }
+        Command::Info {
+            identifier,
+            report,
+            json,
+        } => {
+            let cfg = load_config()?;
+            let alias_chain = match Alias::try_from(identifier.clone()) {
+                Ok(alias) if json || matches!(report, InfoReportType::All) => {
+                    let chain = record_db.alias_chain(&alias)?;
+                    if !json && !chain.is_empty() {
+                        owriteln!("Alias chain: {alias} -> {}", chain.join(" -> "))?;
+                    }
+                    chain
+                }
+                _ => Vec::new(),
+            };
             match record_db.state_from_record_id(identifier, &cfg.alias_transform)? {
+                RecordIdState::Entry(key, _, state) if json => {
+                    info::database_report_json(key, state, alias_chain)?;
+                }
+                RecordIdState::Deleted(key, _, state) if json => {
+                    info::database_report_json(key, state, alias_chain)?;
+                }
+                RecordIdState::Void(key, _, state) if json => {
+                    info::database_report_json(key, state, alias_chain)?;
+                }
+                RecordIdState::NullRemoteId(remote_id, null_row) if json => {
+                    let null_row_data = null_row.get_data()?;
+                    owrite_json(&serde_json::json!({
+                        "identifier": remote_id.to_string(),
+                        "status": "null",
+                        "attempted": null_row_data.attempted,
+                    }))?;
+                }
                 RecordIdState::Entry(key, data, state) => {
                     info::database_report(key, data, state, report, |_, stdout| {
                         writeln!(stdout, "Record with data")
@@ -830,9 +1688,18 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                     InfoReportType::Revision => {
                         bail!("No revision for null record '{remote_id}'");
                     }
+                    InfoReportType::Provenance => {
+                        bail!("No provenance for null record '{remote_id}'");
+                    }
+                    InfoReportType::Related => {
+                        bail!("No relations for null record '{remote_id}'");
+                    }
                     InfoReportType::Modified => {
                         owriteln!("{}", null_row.get_null_attempted()?)?;
                     }
+                    InfoReportType::Added => {
+                        bail!("No added time for null record '{remote_id}'");
+                    }
                 },
                 RecordIdState::Unknown(unknown) => {
                     let maybe_normalized = unknown.combine_and_commit()?;
@@ -842,7 +1709,7 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                     bail!("Cannot obtain report for undefined alias: '{alias}'");
                 }
 
-                RecordIdState::InvalidRemoteId(err) => bail!("{err}"),
+                RecordIdState::InvalidRemoteId(err) => bail!(err),
             }
         }
         Command::Local {
@@ -885,7 +1752,7 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                     );
                 }
                 ExistsOrUnknown::Void(_, void) => {
-                    let cfg = config::load(&config_path, missing_ok)?;
+                    let cfg = load_config()?;
                     insert(
                         void,
                         from_bibtex,
@@ -894,10 +1761,11 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                         &cfg.on_insert,
                         &edit_cmd,
                         alias_opt,
+                        cfg.auto_alias.as_ref(),
                     )?;
                 }
                 ExistsOrUnknown::Unknown(missing) => {
-                    let cfg = config::load(&config_path, missing_ok)?;
+                    let cfg = load_config()?;
                     insert(
                         missing,
                         from_bibtex,
@@ -906,6 +1774,7 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                         &cfg.on_insert,
                         &edit_cmd,
                         alias_opt,
+                        cfg.auto_alias.as_ref(),
                     )?;
                 }
             };
@@ -915,28 +1784,233 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
             tree,
             all,
             reverse,
+            json,
         } => {
-            let cfg = config::load(&config_path, missing_ok)?;
+            let cfg = load_config()?;
             if let Some((_, entry_or_deleted)) = record_db
                 .state_from_record_id(identifier, &cfg.alias_transform)?
                 .require_record()?
             {
                 let (_, state) = entry_or_deleted.forget();
-                print_log(cli.no_interactive, &state, tree, all, reverse, false)?;
+                if json {
+                    print_log_json(&state, tree, all, reverse)?;
+                } else {
+                    print_log(cli.no_interactive, &state, tree, all, reverse, false)?;
+                }
                 state.commit()?;
             }
         }
-        Command::Path { identifier, mkdir } => {
-            let cfg = config::load(&config_path, missing_ok)?;
+        Command::Lsp { template } => {
+            let cfg = load_config()?;
+
+            let template = match template {
+                Some(t) => t,
+                None => match Template::compile(&cfg.find.default_template) {
+                    Ok(t) => t,
+                    Err(err) => {
+                        bail!("Syntax error in `find.default_template` configuration value: {err}");
+                    }
+                },
+            };
+
+            run_language_server(
+                &mut record_db,
+                &template,
+                &cfg.alias_transform,
+                std::io::stdin().lock(),
+                stdout_lock_wrap(),
+            )?;
+        }
+        Command::Nocite {
+            mut identifiers,
+            query,
+            out,
+            bib,
+        } => {
+            if let Some(query) = query {
+                record_db.map_active_records(|row| {
+                    if query.matches(&row) {
+                        identifiers.push(RecordId::from(row.canonical.name()));
+                    }
+                })?;
+            }
+
+            let cfg = load_config()?;
+            let valid_entries = if cli.read_only {
+                retrieve_entries_read_only(identifiers, &mut record_db, false, false, cfg)
+            } else {
+                retrieve_and_validate_entries(
+                    identifiers,
+                    &mut record_db,
+                    client,
+                    false,
+                    false,
+                    cfg,
+                )
+            };
+            write_nocite(out, &valid_entries)?;
+
+            if let Some(path) = bib {
+                let annotations = if cfg.output.annotate {
+                    Some(lookup_annotations(&mut record_db, &valid_entries)?)
+                } else {
+                    None
+                };
+                let overrides = lookup_overrides(&mut record_db, Some(&path))?;
+                let preamble = if cfg.output.string_preamble {
+                    record_db.macros()?
+                } else {
+                    Vec::new()
+                };
+                let sorted_entries = sort_entries(&mut record_db, valid_entries, cfg.output.sort)?;
+                output_entries(
+                    init_outfile(Some(path), false)?,
+                    false,
+                    sorted_entries,
+                    annotations,
+                    cfg.output.flavor,
+                    overrides,
+                    preamble,
+                    cfg.output.style(),
+                )?;
+            }
+        }
+        Command::Override { override_command } => match override_command {
+            OverrideCommand::Set {
+                identifier,
+                field,
+                value,
+                scope,
+            } => {
+                if let Err(err) = FieldValue::try_new(value.as_str()) {
+                    bail!("Invalid value for field '{field}': {err}");
+                }
+                let cfg = load_config()?;
+                let Some(canonical) = canonical_from_record_id(&mut record_db, identifier, cfg)?
+                else {
+                    return Ok(());
+                };
+                info!("Overriding '{field}' of '{canonical}' in '{scope}'");
+                record_db.insert_override(&FieldOverride {
+                    canonical: canonical.name().to_owned(),
+                    scope,
+                    field: field.as_ref().to_owned(),
+                    value,
+                })?;
+            }
+            OverrideCommand::Unset {
+                identifier,
+                field,
+                scope,
+            } => {
+                let cfg = load_config()?;
+                let Some(canonical) = canonical_from_record_id(&mut record_db, identifier, cfg)?
+                else {
+                    return Ok(());
+                };
+                if !record_db.delete_override(&canonical, &scope, field.as_ref())? {
+                    error!("No override of '{field}' for '{canonical}' in '{scope}'");
+                }
+            }
+            OverrideCommand::List { identifier } => {
+                let canonical = match identifier {
+                    Some(identifier) => {
+                        let cfg = load_config()?;
+                        match canonical_from_record_id(&mut record_db, identifier, cfg)? {
+                            Some(canonical) => Some(canonical),
+                            None => return Ok(()),
+                        }
+                    }
+                    None => None,
+                };
+                let mut lock = stdout_lock_wrap();
+                for FieldOverride {
+                    canonical,
+                    scope,
+                    field,
+                    value,
+                } in record_db.overrides(canonical.as_ref())?
+                {
+                    writeln!(lock, "{canonical} {scope} {field} = {{{value}}}")?;
+                }
+            }
+        },
+        Command::Open { identifier, name } => {
+            let cfg = load_config()?;
+            let Some(canonical) = canonical_from_record_id(&mut record_db, identifier, cfg)? else {
+                return Ok(());
+            };
+            let attachment_dir = get_attachment_dir(&data_dir, cli.attachments_dir, &canonical)?;
+
+            let attachment = match name {
+                Some(name) => PathBuf::from(name),
+                None => {
+                    let mut attachments = list_attachments(&attachment_dir)?;
+                    if attachments.len() > 1 && !cli.no_interactive {
+                        let mut picker = choose_attachment_name(attachments);
+                        match picker.pick()? {
+                            Some(attachment) => attachment.clone(),
+                            None => {
+                                error!("No attachment selected.");
+                                return Ok(());
+                            }
+                        }
+                    } else if !attachments.is_empty() {
+                        attachments.swap_remove(0)
+                    } else {
+                        bail!("No attachments for '{canonical}'");
+                    }
+                }
+            };
+
+            let target = attachment_dir.join(&attachment);
+            if !target.is_file() {
+                bail!("Attachment does not exist: '{}'", attachment.display());
+            }
+            open_with_default_application(&target)?;
+        }
+        Command::Path {
+            identifier,
+            layout: Some(layout),
+            ..
+        } => {
+            let cfg = load_config()?;
+            let template = match layout {
+                Some(template) => template,
+                None => layout_template(&cfg.attach.layout)?,
+            };
 
-            let canonical = match record_db
+            match record_db
                 .state_from_record_id(identifier, &cfg.alias_transform)?
                 .require_record()?
             {
-                Some((_, DisambiguatedRecordRow::Entry(record_row, _))) => record_row.canonical,
-                Some((_, DisambiguatedRecordRow::Deleted(record_row, _))) => record_row.canonical,
-                Some((_, DisambiguatedRecordRow::Void(record_row, _))) => record_row.canonical,
-                None => return Ok(()),
+                Some((_, DisambiguatedRecordRow::Entry(record_row, state))) => {
+                    state.commit()?;
+                    let mut target = layout_path(&template, &record_row);
+                    target.push("");
+                    owriteln!("{}", target.display())?;
+                }
+                Some((_, DisambiguatedRecordRow::Deleted(record_row, state))) => {
+                    state.commit()?;
+                    error!(
+                        "Deleted record '{}' has no layout path",
+                        record_row.canonical
+                    );
+                }
+                Some((_, DisambiguatedRecordRow::Void(record_row, state))) => {
+                    state.commit()?;
+                    error!("Void record '{}' has no layout path", record_row.canonical);
+                }
+                None => {}
+            }
+        }
+        Command::Path {
+            identifier, mkdir, ..
+        } => {
+            let cfg = load_config()?;
+
+            let Some(canonical) = canonical_from_record_id(&mut record_db, identifier, cfg)? else {
+                return Ok(());
             };
 
             let mut target = get_attachment_dir(&data_dir, cli.attachments_dir, &canonical)?;
@@ -950,6 +2024,63 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
 
             owriteln!("{}", target.display())?;
         }
+        Command::Relate {
+            identifier,
+            target,
+            kind,
+            delete,
+        } => {
+            let cfg = load_config()?;
+            let Some(canonical) = canonical_from_record_id(&mut record_db, identifier, cfg)? else {
+                return Ok(());
+            };
+            let Some(target) = canonical_from_record_id(&mut record_db, target, cfg)? else {
+                return Ok(());
+            };
+            if canonical == target {
+                bail!("Cannot relate '{canonical}' to itself");
+            }
+
+            let kind = kind.as_str();
+            if delete {
+                if record_db.delete_relation(&canonical, &target, kind)? {
+                    info!("Deleted relation '{canonical}' {kind} '{target}'");
+                } else {
+                    error!("No relation '{canonical}' {kind} '{target}'");
+                }
+            } else {
+                info!("Recording relation '{canonical}' {kind} '{target}'");
+                record_db.insert_relation(&canonical, &target, kind)?;
+            }
+        }
+        Command::RefactorKey { old, new, paths } => {
+            let cfg = load_config()?;
+            let paths = source::expand_directories(paths, None, &cfg.source);
+            let edits = rename_citations(paths, old.name(), new.name())?;
+
+            let mut stdout = stdout_lock_wrap();
+            for edit in &edits {
+                edit.write_diff(&mut stdout)?;
+            }
+            drop(stdout);
+
+            if cli.dry_run {
+                return Ok(());
+            }
+
+            update_alias(&mut record_db, old, &new, client, cfg)?;
+
+            for edit in &edits {
+                match edit.apply() {
+                    Ok(()) => info!(
+                        "Renamed {} citations in '{}'",
+                        edit.count,
+                        edit.path.display()
+                    ),
+                    Err(err) => error!("Failed to write file '{}': {err}", edit.path.display()),
+                }
+            }
+        }
         Command::Replace {
             identifier,
             with,
@@ -958,36 +2089,36 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
             on_conflict,
             update_aliases,
         } => {
-            let cfg = config::load(&config_path, missing_ok)?;
+            let cfg = load_config()?;
 
-            if let Some(target) = with {
+            let replaced = if let Some(target) = with {
                 let tx = record_db.transaction()?;
                 replace::replace(
                     identifier,
                     tx,
-                    &cfg,
+                    cfg,
                     |tx, _| {
-                        get_record_row_tx(tx, target, client, &cfg)?
+                        get_record_row_tx(tx, target, client, cfg)?
                             .exists_or_commit_null("Cannot replace with")
                     },
                     hard,
                     update_aliases,
                     on_conflict,
-                )?;
+                )?
             } else if auto {
                 let tx = record_db.transaction()?;
                 replace::replace(
                     identifier,
                     tx,
-                    &cfg,
-                    |tx, data| match determine_key_from_data(data, &cfg) {
+                    cfg,
+                    |tx, data| match determine_key_from_data(data, cfg) {
                         RemoteIdCandidate::OptimalReference(mapped_key, _)
                         | RemoteIdCandidate::OptimalCanonical(mapped_key) => {
                             let msg = format!(
                                 "Automatically determined identifier '{}' is",
                                 mapped_key.mapped
                             );
-                            get_record_row_tx(tx, mapped_key.mapped.forget(), client, &cfg)?
+                            get_record_row_tx(tx, mapped_key.mapped.forget(), client, cfg)?
                                 .exists_or_commit_null(&msg)
                         }
                         RemoteIdCandidate::None => {
@@ -997,9 +2128,47 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                     hard,
                     update_aliases,
                     on_conflict,
-                )?;
+                )?
             } else {
                 bail!("Missing replacement target: either use `--with <replacement>` or `--auto`");
+            };
+
+            // the attachment directory, the relations, the tags, the collections, the overrides,
+            // and the added time are determined by the canonical identifier, so they must also be
+            // moved
+            if let Some((original, replacement)) = replaced {
+                let attachment_root = get_attachment_root(&data_dir, cli.attachments_dir)?;
+                migrate_attachments(&mut record_db, &attachment_root, &original, &replacement)?;
+                record_db.migrate_relations(&original, &replacement)?;
+                record_db.migrate_tags(&original, &replacement)?;
+                record_db.migrate_collections(&original, &replacement)?;
+                record_db.migrate_overrides(&original, &replacement)?;
+                record_db.migrate_added(&original, &replacement)?;
+            }
+        }
+        Command::Resolve { path } => {
+            let cfg = load_config()?;
+            let num_changed = resolve_conflicts(&mut record_db, &path, &cfg.alias_transform)?;
+            info!("Resolved conflicts in {num_changed} record(s)");
+        }
+        Command::Serve { address, socket } => {
+            let cfg = load_config()?;
+            match socket {
+                #[cfg(unix)]
+                Some(path) => {
+                    let listener = serve::bind_unix_socket(&path)?;
+                    owriteln!("Listening on '{}'", path.display())?;
+                    serve(&mut record_db, listener.incoming(), &cfg.alias_transform);
+                }
+                #[cfg(not(unix))]
+                Some(_) => {
+                    bail!("Unix domain sockets are not supported on this platform");
+                }
+                None => {
+                    let listener = TcpListener::bind(&address)?;
+                    owriteln!("Listening on http://{}", listener.local_addr()?)?;
+                    serve(&mut record_db, listener.incoming(), &cfg.alias_transform);
+                }
             }
         }
         Command::Source {
@@ -1013,9 +2182,19 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
             skip_file_type,
             retrieve_only,
             ignore_null,
+            retry_null,
             print_keys,
+            diff,
+            target_style,
+            watch,
+            emit_deps,
+            report_duplicates,
+            flavor,
+            sort,
+            stream,
+            style: style_args,
         } => {
-            let mut outfile = init_outfile(out, append)?;
+            let mut outfile = init_outfile(out.as_ref(), append)?;
             let mut scratch = Vec::new();
 
             if paths.is_empty() && stdin.is_none() && !std::io::stdin().is_terminal() {
@@ -1023,13 +2202,16 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                 suggest!("Use `--stdin FILE_TYPE` to search for identifiers in standard input.");
             }
 
+            // search directories for files of a supported type
+            let paths = source::expand_directories(paths, file_type, &load_config()?.source);
+
             // initialize skipped keys with:
             // - explicitly passed keys
             // - keys from the provided files
             // - any keys in the output bibfile, if appending
             let mut skipped_keys: HashSet<RecordId> = HashSet::new();
             skipped_keys.extend(skip);
-            for skip_path in skip_from {
+            for skip_path in &skip_from {
                 source::get_citekeys_from_file(
                     skip_path,
                     skip_file_type,
@@ -1049,8 +2231,8 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                 get_citekeys(SourceFileType::Bib, &scratch, &mut skipped_keys);
             }
 
-            if print_keys {
-                // only print the keys which were found
+            if print_keys || diff.is_some() {
+                // only print the keys which were found, or compare them with the bibliography
                 let mut all_citekeys: BTreeSet<RecordId> = BTreeSet::new();
 
                 if let Some(stdin_file_type) = stdin {
@@ -1062,18 +2244,27 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                     )?;
                 }
 
-                for path in paths {
-                    source::get_citekeys_from_file_filter(
-                        path,
-                        file_type,
-                        &mut all_citekeys,
-                        &mut scratch,
-                        "--file-type",
-                        |record_id| !skipped_keys.contains(record_id),
-                    )?;
+                for keys in source::get_citekeys_from_files(&paths, file_type, |record_id| {
+                    !skipped_keys.contains(record_id)
+                }) {
+                    all_citekeys.extend(keys?);
                 }
 
-                output_keys(all_citekeys.iter())?;
+                match diff {
+                    Some(bib_path) => {
+                        let mut bib_keys: BTreeSet<RecordId> = BTreeSet::new();
+                        source::get_citekeys_from_file_filter(
+                            &bib_path,
+                            Some(SourceFileType::Bib),
+                            &mut bib_keys,
+                            &mut scratch,
+                            "--file-type",
+                            |record_id| !skipped_keys.contains(record_id),
+                        )?;
+                        source::write_key_diff(&bib_path, &all_citekeys, &bib_keys)?;
+                    }
+                    None => output_keys(all_citekeys.iter())?,
+                }
             } else {
                 // read identifiers from all of the paths, excluding those which are present in
                 // 'skipped_keys'
@@ -1091,53 +2282,344 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                     )?;
                 }
 
-                for path in paths {
-                    source::get_citekeys_from_file_filter(
-                        path,
-                        file_type,
-                        &mut all_citekeys,
-                        &mut scratch,
-                        "--file-type",
-                        |record_id| !skipped_keys.contains(record_id),
-                    )?;
+                for keys in source::get_citekeys_from_files(&paths, file_type, |record_id| {
+                    !skipped_keys.contains(record_id)
+                }) {
+                    all_citekeys.extend(keys?);
                 }
 
                 // retrieve all of the entries
-                let cfg = config::load(&config_path, missing_ok)?;
-                let keys = all_citekeys;
-                let valid_entries = if cli.read_only {
-                    retrieve_entries_read_only(
-                        keys,
-                        &mut record_db,
-                        retrieve_only,
-                        ignore_null,
-                        &cfg,
-                    )
-                } else {
-                    retrieve_and_validate_entries(
-                        keys,
-                        &mut record_db,
-                        client,
-                        retrieve_only,
-                        ignore_null,
-                        &cfg,
-                    )
+                let cfg = load_config()?;
+                let style = match target_style {
+                    Some(name) => match StyleRequirements::lookup(&name, &cfg.styles) {
+                        Some(style) => Some(style),
+                        None => {
+                            suggest!(
+                                "Define new styles in the `styles` section of the configuration."
+                            );
+                            bail!("Unknown target style '{name}'");
+                        }
+                    },
+                    None => None,
                 };
 
-                if !retrieve_only {
-                    output_entries(outfile, append, valid_entries)?;
+                // the budget for retrying cached null records is shared by the entire run
+                let mut null_budget = retry_null.unwrap_or(cfg.source.retry_null);
+
+                let mut retrieve_and_output =
+                    |keys: HashSet<RecordId>, outfile: Option<File>, append: bool| -> Result<()> {
+                        if !cli.read_only && !cli.offline {
+                            null_budget -= expire_null_records(
+                                &keys,
+                                &mut record_db,
+                                null_budget,
+                                cfg.source.null_ttl,
+                                cfg,
+                            )?;
+                        }
+
+                        if stream {
+                            // the entries are written in a deterministic order
+                            let mut keys: Vec<RecordId> = keys.into_iter().collect();
+                            keys.sort();
+
+                            let overrides = lookup_overrides(&mut record_db, out.as_deref())?;
+                            let preamble = if cfg.output.string_preamble {
+                                record_db.macros()?
+                            } else {
+                                Vec::new()
+                            };
+                            let mut entry_stream = EntryStream::new(
+                                outfile,
+                                append,
+                                cfg.output.annotate,
+                                flavor.or(cfg.output.flavor),
+                                overrides,
+                                preamble,
+                                style_args.apply(cfg.output.style()),
+                            );
+                            let write_entry =
+                                |record_db: &mut RecordDatabase,
+                                 entry: Entry<RawEntryData>,
+                                 canonical: RemoteId| {
+                                    if let Some(style) = &style {
+                                        style.check_entry(&entry);
+                                    }
+                                    entry_stream.write(record_db, entry, canonical)
+                                };
+                            if cli.read_only {
+                                stream_entries_read_only(
+                                    keys,
+                                    &mut record_db,
+                                    retrieve_only,
+                                    ignore_null,
+                                    cfg,
+                                    write_entry,
+                                )?;
+                            } else {
+                                stream_valid_entries(
+                                    keys,
+                                    &mut record_db,
+                                    client,
+                                    retrieve_only,
+                                    ignore_null,
+                                    cfg,
+                                    write_entry,
+                                )?;
+                            }
+                            return entry_stream
+                                .finish(cfg.output.duplicates, report_duplicates.as_ref());
+                        }
+
+                        let valid_entries = if cli.read_only {
+                            retrieve_entries_read_only(
+                                keys,
+                                &mut record_db,
+                                retrieve_only,
+                                ignore_null,
+                                cfg,
+                            )
+                        } else {
+                            retrieve_and_validate_entries(
+                                keys,
+                                &mut record_db,
+                                client,
+                                retrieve_only,
+                                ignore_null,
+                                cfg,
+                            )
+                        };
+
+                        if let Some(style) = &style {
+                            style.check_entries(&valid_entries);
+                        }
+
+                        if !retrieve_only {
+                            check_duplicates(&valid_entries, cfg.output.duplicates);
+                            if let Some(path) = report_duplicates.as_ref() {
+                                write_duplicates_report(path, &valid_entries)?;
+                            }
+
+                            let annotations = if cfg.output.annotate {
+                                Some(lookup_annotations(&mut record_db, &valid_entries)?)
+                            } else {
+                                None
+                            };
+                            let overrides = lookup_overrides(&mut record_db, out.as_deref())?;
+                            let preamble = if cfg.output.string_preamble {
+                                record_db.macros()?
+                            } else {
+                                Vec::new()
+                            };
+                            let sorted_entries = sort_entries(
+                                &mut record_db,
+                                valid_entries,
+                                sort.unwrap_or(cfg.output.sort),
+                            )?;
+                            output_entries(
+                                outfile,
+                                append,
+                                sorted_entries,
+                                annotations,
+                                flavor.or(cfg.output.flavor),
+                                overrides,
+                                preamble,
+                                style_args.apply(cfg.output.style()),
+                            )?;
+                        }
+                        Ok(())
+                    };
+
+                if let Some(deps_path) = emit_deps
+                    && let Some(out) = out.as_ref()
+                {
+                    let prerequisites: Vec<&PathBuf> = paths.iter().chain(&skip_from).collect();
+                    emit_dependencies(&deps_path, out, &prerequisites)?;
+                }
+
+                if !watch {
+                    retrieve_and_output(all_citekeys, outfile, append)?;
+                } else {
+                    // keys which were already processed are skipped, whether or not they
+                    // resulted in a valid entry, so that only new keys trigger network requests
+                    skipped_keys.extend(all_citekeys.iter().cloned());
+
+                    // the database is only locked while the output is rebuilt, so that other
+                    // commands can be run while the files are being watched
+                    let lock_for_rebuild = || {
+                        (!cli.read_only)
+                            .then(|| lock_database(&db_path, lock_timeout))
+                            .transpose()
+                    };
+                    {
+                        let _db_lock = lock_for_rebuild()?;
+                        retrieve_and_output(all_citekeys, outfile, append)?;
+                    }
+
+                    let mut watcher = source::FileWatcher::new(&paths);
+                    eprintln!(
+                        "Watching {} file(s) for changes; press Ctrl-C to stop",
+                        paths.len()
+                    );
+                    loop {
+                        watcher.wait();
+
+                        let mut new_citekeys: HashSet<RecordId> = HashSet::new();
+                        for keys in
+                            source::get_citekeys_from_files(&paths, file_type, |record_id| {
+                                !skipped_keys.contains(record_id)
+                            })
+                        {
+                            // the file may be temporarily missing while it is being saved
+                            match keys {
+                                Ok(keys) => new_citekeys.extend(keys),
+                                Err(err) => error!("{err}"),
+                            }
+                        }
+
+                        if new_citekeys.is_empty() {
+                            info!("No new citation keys found");
+                            continue;
+                        }
+
+                        info!("Found {} new citation key(s)", new_citekeys.len());
+                        skipped_keys.extend(new_citekeys.iter().cloned());
+                        let _db_lock = lock_for_rebuild()?;
+                        retrieve_and_output(new_citekeys, init_outfile(out.as_ref(), true)?, true)?;
+                    }
+                }
+            }
+        }
+        Command::Stats { json } => {
+            let stats = collect_stats(&mut record_db)?;
+            if json {
+                owrite_json(&stats)?;
+            } else {
+                write_stats(&mut stdout_lock_wrap(), &stats)?;
+            }
+        }
+        Command::Sync { remote } => {
+            // load the configuration so that the HTTP client is configured
+            load_config()?;
+            sync(&mut record_db, remote, &data_dir, client)?;
+        }
+        Command::Tag { tag_command } => match tag_command {
+            TagCommand::Add { identifier, tags } => {
+                let cfg = load_config()?;
+                let Some(canonical) = canonical_from_record_id(&mut record_db, identifier, cfg)?
+                else {
+                    return Ok(());
+                };
+                for tag in tags {
+                    if !record_db.insert_tag(&canonical, &tag)? {
+                        warn!("Record '{canonical}' is already tagged with '{tag}'");
+                    }
+                }
+            }
+            TagCommand::Remove { identifier, tags } => {
+                let cfg = load_config()?;
+                let Some(canonical) = canonical_from_record_id(&mut record_db, identifier, cfg)?
+                else {
+                    return Ok(());
+                };
+                for tag in tags {
+                    if !record_db.delete_tag(&canonical, &tag)? {
+                        error!("Record '{canonical}' is not tagged with '{tag}'");
+                    }
+                }
+            }
+            TagCommand::List {
+                identifier: Some(identifier),
+            } => {
+                let cfg = load_config()?;
+                let Some(canonical) = canonical_from_record_id(&mut record_db, identifier, cfg)?
+                else {
+                    return Ok(());
+                };
+                let tags = record_db.tags(&canonical)?;
+                if tags.is_empty() {
+                    info!("No tags for '{canonical}'");
+                }
+                let mut lock = stdout_lock_wrap();
+                for tag in tags {
+                    writeln!(lock, "{tag}")?;
+                }
+            }
+            TagCommand::List { identifier: None } => {
+                let mut lock = stdout_lock_wrap();
+                for (tag, count) in record_db.tag_counts()? {
+                    writeln!(lock, "{tag}\t{count}")?;
                 }
             }
+        },
+        Command::Tui { template } => {
+            if cli.no_interactive {
+                bail!("`autobib tui` cannot run in non-interactive mode");
+            }
+
+            let cfg = load_config()?;
+            let template = match template {
+                Some(t) => t,
+                None => match Template::compile(&cfg.find.default_template) {
+                    Ok(t) => t,
+                    Err(err) => {
+                        bail!("Syntax error in `find.default_template` configuration value: {err}");
+                    }
+                },
+            };
+            let attachment_root = get_attachment_root(&data_dir, cli.attachments_dir)?;
+            record_db = browse(record_db, template, cfg, &attachment_root)?;
         }
         Command::Update {
-            identifier,
+            all: true,
+            older_than,
+            on_conflict,
+            conflicts_out,
+            ..
+        } => {
+            let cfg = load_config()?;
+            let mut conflicts = Vec::new();
+            let outcome = update_stale(
+                &mut record_db,
+                older_than,
+                on_conflict,
+                &cfg.merge,
+                &cfg.on_insert,
+                client,
+                &mut conflicts,
+            )?;
+            write_stale_update(&mut stderr(), &outcome)?;
+            if let Some(path) = conflicts_out {
+                write_conflicts(&path, conflicts)?;
+            }
+            if !outcome.remaining.is_empty() {
+                error!(
+                    "Interrupted after retrieving {} of {} records",
+                    outcome.num_retrieved,
+                    outcome.num_retrieved + outcome.remaining.len()
+                );
+                let remaining: Vec<String> =
+                    outcome.remaining.iter().map(ToString::to_string).collect();
+                suggest!("Remaining records: {}", remaining.join(", "));
+            }
+        }
+        Command::Update {
+            identifier: None, ..
+        } => {
+            unreachable!("clap requires an identifier unless `--all` is set");
+        }
+        Command::Update {
+            identifier: Some(identifier),
             from_bibtex,
             from_record,
             from_rev,
             on_conflict,
             revive,
+            conflicts_out,
+            ..
         } => {
-            let cfg = config::load(&config_path, missing_ok)?;
+            let cfg = load_config()?;
+            let mut conflicts = Vec::new();
             let tx = record_db.transaction()?;
 
             // this has to be done first since we need a mutable reference to
@@ -1146,40 +2628,74 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
             // at this point since we would like to defer filesystem / network
             // operations, unless they are strictly required
             let (provided_data, tx) = if let Some(record_id) = from_record {
-                let (data, tx) = data_from_key(tx, record_id, &cfg)?;
-                (Some(data), tx)
+                let source = format!("record {record_id}");
+                let (data, tx) = data_from_key(tx, record_id, cfg)?;
+                (
+                    Some(IncomingData {
+                        data,
+                        source,
+                        provenance: None,
+                    }),
+                    tx,
+                )
             } else if let Some(rev) = from_rev {
                 let data = data_from_rev(&tx, rev)?;
-                (Some(data), tx)
+                (
+                    Some(IncomingData {
+                        data,
+                        source: format!("revision {rev}"),
+                        provenance: None,
+                    }),
+                    tx,
+                )
             } else {
                 (None, tx)
             };
 
             update(
                 on_conflict,
+                &cfg.merge,
                 RecordIdState::determine(tx, identifier, &cfg.alias_transform)?,
                 provided_data,
                 &cfg.on_insert,
                 revive,
+                &mut conflicts,
                 |canonical| {
                     if let Some(path) = from_bibtex {
-                        Ok(data_from_path(path)?)
+                        Ok(IncomingData {
+                            data: data_from_path(&path)?,
+                            source: format!("file {}", path.display()),
+                            provenance: None,
+                        })
                     } else if canonical.is_local() {
                         bail!(
                             "Cannot update local record using remote data: use `autobib edit` or the `--from-bibtex` or `--from-key` options."
                         );
                     } else {
-                        Ok(update::data_from_remote(canonical, client)?.0)
+                        let source = format!("retrieved {canonical}");
+                        let (data, _, provenance) = update::data_from_remote(canonical, client)?;
+                        Ok(IncomingData {
+                            data,
+                            source,
+                            provenance,
+                        })
                     }
                 },
             )?;
+
+            if let Some(path) = conflicts_out {
+                write_conflicts(&path, conflicts)?;
+            }
         }
         Command::Util { util_command } => match util_command {
-            UtilCommand::Check { fix } => {
+            UtilCommand::Check { fix, attachments } => {
                 info!(
                     "Validating record binary data and consistency, and checking for dangling records."
                 );
-                let faults = record_db.recover(fix)?;
+                let faults = record_db.recover(fix, |message| {
+                    !cli.no_interactive
+                        && matches!(Confirm::new(message, false).confirm(), Ok(true))
+                })?;
                 if !faults.is_empty() {
                     error!("Erroneous data found in the database.");
                     for fault in faults {
@@ -1187,9 +2703,130 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                     }
                 }
 
+                info!("Checking for orphaned attachments.");
+                let attachment_root = get_attachment_root(&data_dir, cli.attachments_dir)?;
+                check_attachments(&mut record_db, &attachment_root, fix)?;
+
+                if attachments {
+                    info!("Checking attachments against the stored metadata.");
+                    check_attachment_metadata(&mut record_db, &attachment_root, fix)?;
+                }
+
                 info!("Validating configuration.");
                 config::validate(&config_path)?;
             }
+            UtilCommand::Diagnose { provider } => {
+                // load the configuration so that the HTTP client is configured
+                load_config()?;
+                let checks = diagnose(client, provider.as_deref())?;
+                write_checks(&mut stdout_lock_wrap(), &checks)?;
+                let num_failed = checks.iter().filter(|check| !check.is_ok()).count();
+                if num_failed > 0 {
+                    error!("{num_failed} of {} providers failed", checks.len());
+                }
+            }
+            UtilCommand::MigrateAttachments => {
+                let attachment_root = get_attachment_root(&data_dir, cli.attachments_dir)?;
+                let relocated =
+                    relocate_attachments(&mut record_db, &attachment_root, cli.dry_run)?;
+                if cli.dry_run {
+                    info!("Would migrate {relocated} attachment directories");
+                } else {
+                    info!("Migrated {relocated} attachment directories");
+                }
+            }
+            UtilCommand::Remap { from, to } => {
+                // the identifiers are parsed after loading the configuration, so that custom
+                // providers are registered
+                load_config()?;
+                let from = RemoteId::from_str(&from)?;
+                let to = RemoteId::from_str(&to)?;
+                if from == to {
+                    bail!("Identifier '{to}' is the current canonical identifier");
+                }
+                if !is_canonical(to.provider()) {
+                    bail!("Identifier '{to}' is not a canonical identifier");
+                }
+                match record_db.remap_canonical(&from, &to)? {
+                    RemapCanonicalResult::Remapped => {
+                        // the attachment directory is determined by the canonical identifier
+                        let attachment_root = get_attachment_root(&data_dir, cli.attachments_dir)?;
+                        move_attachments(&attachment_root, &from, &to)?;
+                        info!("Changed canonical identifier '{from}' to '{to}'");
+                    }
+                    RemapCanonicalResult::Missing => {
+                        bail!("Record does not exist: '{from}'");
+                    }
+                    RemapCanonicalResult::NotCanonical(canonical) => {
+                        suggest!("The canonical identifier of the record is '{canonical}'.");
+                        bail!("Identifier '{from}' is not the canonical identifier of its record");
+                    }
+                    RemapCanonicalResult::TargetExists => {
+                        bail!("Identifier '{to}' already refers to a different record");
+                    }
+                }
+            }
+            UtilCommand::RelinkAttachments { dir, layout } => {
+                let template = match layout {
+                    Some(template) => template,
+                    None => layout_template(&load_config()?.attach.layout)?,
+                };
+                let attachment_root = get_attachment_root(&data_dir, cli.attachments_dir)?;
+                let created =
+                    relink_attachments(&mut record_db, &attachment_root, &dir, &template)?;
+                info!("Created {created} links in '{}'", dir.display());
+            }
+            UtilCommand::Export { file } => {
+                let writer = BufWriter::new(File::create(&file)?);
+                record_db.export_dump(writer)?;
+            }
+            UtilCommand::ImportDump { file } => {
+                let reader = BufReader::new(File::open(&file)?);
+                let num_rows = record_db.import_dump(reader)?;
+                info!("Imported {num_rows} rows from '{}'", file.display());
+            }
+            UtilCommand::RestoreBackup { list: true, .. } => {
+                for path in list_backups(&db_path)? {
+                    owriteln!("{}", path.display())?;
+                }
+            }
+            UtilCommand::RestoreBackup {
+                backup,
+                list: false,
+            } => {
+                let backup = match backup {
+                    Some(backup) => backup,
+                    None => match list_backups(&db_path)?.pop() {
+                        Some(backup) => backup,
+                        None => bail!("No backups of the database were found"),
+                    },
+                };
+                // the backup to restore is determined first, since it is not necessarily the most
+                // recent backup once the current contents are saved
+                let cfg = load_config()?;
+                let current = if cfg.backup.enabled && !cli.no_backup {
+                    Some(create_backup(&record_db, &db_path)?)
+                } else {
+                    None
+                };
+                record_db.restore_backup(&backup)?;
+                info!("Restored backup '{}'", backup.display());
+                if let Some(current) = current {
+                    prune_backups(&db_path, cfg.backup.keep)?;
+                    suggest!(
+                        "Undo the restore with `autobib util restore-backup '{}'`",
+                        current.display()
+                    );
+                }
+            }
+            UtilCommand::Mirror => {
+                let Some(mirror_dir) = cli.mirror_dir else {
+                    suggest!("Set the mirror directory with `--mirror-dir`.");
+                    bail!("No mirror directory");
+                };
+                info!("Writing records to '{}'", mirror_dir.display());
+                record_db.write_mirror(&mirror_dir)?;
+            }
             UtilCommand::Optimize => {
                 info!("Optimizing database.");
                 record_db.vacuum()?;
@@ -1202,20 +2839,158 @@ pub fn run_cli<C: Client>(cli: Cli, client: &C) -> Result<()> {
                     record_db.evict_cache()?;
                 }
             },
-            UtilCommand::List { canonical, deleted } => {
-                let mut lock = stdout_lock_wrap();
+            UtilCommand::List {
+                json,
+                score_below,
+                provider,
+                entry_type,
+                field,
+                modified_since,
+                tag,
+                template,
+                sort,
+                limit,
+                ..
+            } if score_below.is_some()
+                || provider.is_some()
+                || entry_type.is_some()
+                || !field.is_empty()
+                || modified_since.is_some()
+                || tag.is_some()
+                || template.is_some()
+                || sort.is_some()
+                || limit.is_some() =>
+            {
+                let tagged: Option<HashSet<RemoteId>> = match tag {
+                    Some(tag) => Some(record_db.tagged(&tag)?.into_iter().collect()),
+                    None => None,
+                };
+                let mut rows = Vec::new();
+                record_db.map_active_records(|row| {
+                    let score = completeness_score(&row.data);
+                    if score_below.is_none_or(|threshold| score < threshold)
+                        && provider
+                            .as_ref()
+                            .is_none_or(|provider| provider == row.canonical.provider())
+                        && entry_type
+                            .as_ref()
+                            .is_none_or(|entry_type| entry_type.as_ref() == row.data.entry_type())
+                        && field.iter().all(|query| query.matches(&row))
+                        && modified_since.is_none_or(|date| row.modified.date_naive() >= date)
+                        && tagged
+                            .as_ref()
+                            .is_none_or(|tagged| tagged.contains(&row.canonical))
+                    {
+                        rows.push((score, row));
+                    }
+                })?;
+                rows.sort_by(|(_, left), (_, right)| {
+                    left.canonical.name().cmp(right.canonical.name())
+                });
+                let order = sort.unwrap_or(if score_below.is_some() {
+                    ListOrder::Score
+                } else {
+                    ListOrder::Canonical
+                });
+                match order {
+                    ListOrder::Canonical => {}
+                    ListOrder::Added => {
+                        // records without an added time are listed last
+                        let added = record_db.added_times()?;
+                        rows.sort_by_key(|(_, row)| Reverse(added.get(&row.canonical).copied()));
+                    }
+                    ListOrder::Modified => rows.sort_by_key(|(_, row)| Reverse(row.modified)),
+                    ListOrder::Score => {
+                        rows.sort_by(|(left, _), (right, _)| left.total_cmp(right));
+                    }
+                }
+                if let Some(limit) = limit {
+                    rows.truncate(limit);
+                }
+
+                if json {
+                    let identifiers: Vec<String> = rows
+                        .into_iter()
+                        .map(|(_, row)| row.canonical.to_string())
+                        .collect();
+                    owrite_json(&identifiers)?;
+                } else {
+                    let mut lock = stdout_lock_wrap();
+                    for (_, row) in rows {
+                        match &template {
+                            Some(template) => writeln!(lock, "{}", template.render(&row))?,
+                            None => writeln!(lock, "{}", row.canonical)?,
+                        }
+                    }
+                }
+            }
+            UtilCommand::List {
+                canonical,
+                deleted,
+                json,
+                ..
+            } => {
                 let snapshot = record_db.snapshot()?;
-                if canonical {
-                    snapshot.map_canonical_identifiers(deleted, |key_str| {
-                        writeln!(lock, "{key_str}")
-                    })?;
+                if json {
+                    let mut identifiers: Vec<String> = Vec::new();
+                    if canonical {
+                        snapshot.map_canonical_identifiers(deleted, |remote_id| {
+                            identifiers.push(remote_id.to_string());
+                            Ok::<_, std::io::Error>(())
+                        })?;
+                    } else {
+                        snapshot.map_identifiers(deleted, |key_str| {
+                            identifiers.push(key_str.to_owned());
+                            Ok::<_, std::io::Error>(())
+                        })?;
+                    }
+                    owrite_json(&identifiers)?;
                 } else {
-                    snapshot.map_identifiers(deleted, |key_str| writeln!(lock, "{key_str}"))?;
+                    let mut lock = stdout_lock_wrap();
+                    if canonical {
+                        snapshot.map_canonical_identifiers(deleted, |key_str| {
+                            writeln!(lock, "{key_str}")
+                        })?;
+                    } else {
+                        snapshot.map_identifiers(deleted, |key_str| writeln!(lock, "{key_str}"))?;
+                    }
                 }
                 snapshot.commit()?;
             }
+            UtilCommand::Generate {
+                records,
+                revisions,
+                attachments,
+            } => {
+                let attachment_root = if attachments {
+                    Some(get_attachment_root(&data_dir, cli.attachments_dir)?)
+                } else {
+                    None
+                };
+                let inserted = generate::generate(
+                    &mut record_db,
+                    records,
+                    revisions,
+                    attachment_root.as_deref(),
+                )?;
+                info!("Generated {inserted} synthetic records");
+            }
         },
     };
 
+    if !cli.read_only && !cli.dry_run {
+        let command = args_os()
+            .skip(1)
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if let Some(summary) = record_db.save_run(command)?
+            && shows_run_summary
+            && summary.num_records() > 1
+        {
+            hist::write_run_summary(&mut stderr(), &summary)?;
+        }
+    }
+
     Ok(())
 }
@@ -0,0 +1,122 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::bail;
+
+use crate::{
+    Alias, Config, Identifier, RecordId,
+    db::RecordDatabase,
+    http::Client,
+    logger::{debug, error, info},
+    record::get_record_row,
+};
+
+/// Create the alias for the record with the provided identifier, retrieving the record if
+/// necessary. If the alias already exists, an error is reported and the alias is not changed.
+pub fn add_alias<F, C>(
+    record_db: &mut RecordDatabase,
+    alias: &Alias,
+    target: RecordId,
+    client: &C,
+    config: &Config<F>,
+) -> Result<(), anyhow::Error>
+where
+    F: FnOnce() -> Vec<(regex::Regex, String)>,
+    C: Client,
+{
+    info!("Creating alias '{alias}' for '{target}'");
+    let (_, row) = get_record_row(record_db, target, client, config)?
+        .exists_or_commit_null("Cannot create alias for")?;
+    if !row.add_alias(alias)? {
+        error!("Alias already exists: '{alias}'");
+    }
+    row.commit()?;
+    Ok(())
+}
+
+/// Write every alias along with the canonical identifier of its record to the file, one alias
+/// per line, separated by a tab. Returns the number of aliases which were written.
+pub fn export_aliases(record_db: &mut RecordDatabase, path: &Path) -> Result<usize, anyhow::Error> {
+    let aliases = record_db.aliases()?;
+    let file = match File::create(path) {
+        Ok(file) => file,
+        Err(e) => bail!("Failed to create alias file '{}': {e}", path.display()),
+    };
+    let mut writer = BufWriter::new(file);
+    for (alias, canonical) in &aliases {
+        writeln!(writer, "{alias}\t{canonical}")?;
+    }
+    writer.flush()?;
+    Ok(aliases.len())
+}
+
+/// Read the aliases from a file in the format written by [`export_aliases`]. Empty lines and
+/// lines starting with `#` are ignored.
+fn read_alias_file(path: &Path) -> Result<Vec<(Alias, RecordId)>, anyhow::Error> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => bail!("Failed to read alias file '{}': {e}", path.display()),
+    };
+    let mut aliases = Vec::new();
+    for (idx, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((alias, target)) = line.split_once('\t') else {
+            bail!(
+                "Invalid line {} in alias file '{}': expected an alias and an identifier separated by a tab",
+                idx + 1,
+                path.display()
+            );
+        };
+        let alias = match Alias::from_str(alias) {
+            Ok(alias) => alias,
+            Err(e) => bail!(
+                "Invalid line {} in alias file '{}': {e}",
+                idx + 1,
+                path.display()
+            ),
+        };
+        aliases.push((alias, RecordId::from(target.trim())));
+    }
+    Ok(aliases)
+}
+
+/// Create the aliases in the file written by [`export_aliases`], in the same way as `autobib
+/// alias add`. Aliases which already refer to the same record are skipped. Returns the number of
+/// aliases which were read.
+pub fn import_aliases<F, C>(
+    record_db: &mut RecordDatabase,
+    path: &Path,
+    client: &C,
+    config: &Config<F>,
+) -> Result<usize, anyhow::Error>
+where
+    F: FnOnce() -> Vec<(regex::Regex, String)>,
+    C: Client,
+{
+    let aliases = read_alias_file(path)?;
+    let existing: HashMap<String, String> = record_db
+        .aliases()?
+        .into_iter()
+        .map(|(alias, canonical)| (alias, canonical.name().to_owned()))
+        .collect();
+
+    for (alias, target) in &aliases {
+        if existing
+            .get(alias.name())
+            .is_some_and(|canonical| canonical == target.name())
+        {
+            debug!("Alias '{alias}' already refers to '{target}'");
+            continue;
+        }
+        add_alias(record_db, alias, target.clone(), client, config)?;
+    }
+    Ok(aliases.len())
+}
@@ -0,0 +1,82 @@
+//! # Automatic backups
+//!
+//! Commands which change many records at once in a way which is difficult to undo with
+//! `autobib hist undo` first write a backup of the database. The backups are stored in the
+//! `backups` directory next to the database file, and are named after the database file and
+//! the time at which the backup was written, so that sorting the names sorts the backups from
+//! oldest to newest.
+use std::{
+    ffi::OsStr,
+    fs::{create_dir_all, read_dir, remove_file},
+    io,
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+
+use crate::{db::RecordDatabase, logger::info};
+
+/// The directory containing the backups of the database file.
+fn backup_dir(db_path: &Path) -> PathBuf {
+    db_path
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join("backups")
+}
+
+/// The prefix of the names of the backups of the database file.
+fn backup_prefix(db_path: &Path) -> String {
+    let stem = db_path
+        .file_stem()
+        .unwrap_or_else(|| OsStr::new("records"))
+        .to_string_lossy();
+    format!("{stem}-")
+}
+
+/// List the backups of the database file, from oldest to newest.
+pub fn list_backups(db_path: &Path) -> io::Result<Vec<PathBuf>> {
+    let dir = backup_dir(db_path);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let prefix = backup_prefix(db_path);
+    let mut backups = Vec::new();
+    for dir_entry in read_dir(&dir)? {
+        let dir_entry = dir_entry?;
+        let name = dir_entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(&prefix) && name.ends_with(".db") && dir_entry.file_type()?.is_file() {
+            backups.push(dir_entry.path());
+        }
+    }
+    backups.sort();
+    Ok(backups)
+}
+
+/// Write a backup of the database, returning the path of the backup.
+pub fn create_backup(record_db: &RecordDatabase, db_path: &Path) -> anyhow::Result<PathBuf> {
+    let dir = backup_dir(db_path);
+    create_dir_all(&dir)?;
+
+    let timestamp = Local::now().format("%Y%m%d-%H%M%S%3f");
+    let path = dir.join(format!("{}{timestamp}.db", backup_prefix(db_path)));
+    record_db.write_backup(&path)?;
+    Ok(path)
+}
+
+/// Remove the oldest backups of the database file so that at most `keep` backups remain. If
+/// `keep` is 0, no backups are removed.
+pub fn prune_backups(db_path: &Path, keep: usize) -> io::Result<()> {
+    if keep == 0 {
+        return Ok(());
+    }
+
+    let backups = list_backups(db_path)?;
+    let num_removed = backups.len().saturating_sub(keep);
+    for old in &backups[..num_removed] {
+        info!("Removing old backup '{}'", old.display());
+        remove_file(old)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,283 @@
+use std::{collections::BTreeMap, fmt, io::Write, iter::once};
+
+use serde::Serialize;
+
+use super::style::STANDARD_ENTRY_TYPES;
+use crate::{
+    config::AliasTransform,
+    db::{RecordDatabase, state::RecordIdState},
+    entry::{EntryData, entries_from_bibtex},
+    record::RecordId,
+};
+
+/// Fields which can be provided instead of a required field of a standard entry type, as pairs of
+/// the BibTeX field and the biblatex field.
+const BIBLATEX_ALTERNATIVES: &[(&str, &str)] = &[
+    ("journal", "journaltitle"),
+    ("school", "institution"),
+    ("year", "date"),
+];
+
+/// The biblatex entry types which are not standard BibTeX entry types, along with their required
+/// fields.
+const BIBLATEX_ENTRY_TYPES: &[(&str, &[&[&str]])] = &[
+    ("collection", &[&["editor"], &["title"], &["year"]]),
+    ("dataset", &[&["author", "editor"], &["title"], &["year"]]),
+    (
+        "mvbook",
+        &[&["author", "editor"], &["title"], &["publisher"], &["year"]],
+    ),
+    ("mvcollection", &[&["editor"], &["title"], &["year"]]),
+    (
+        "online",
+        &[
+            &["author", "editor"],
+            &["title"],
+            &["url", "doi", "eprint"],
+            &["year"],
+        ],
+    ),
+    (
+        "report",
+        &[&["author"], &["title"], &["institution"], &["year"]],
+    ),
+    ("software", &[&["author", "editor"], &["title"], &["year"]]),
+    ("thesis", &[&["author"], &["title"], &["school"], &["year"]]),
+];
+
+/// The fields which are required for a given entry type. Each element is a list of alternatives,
+/// at least one of which must be present.
+///
+/// The required fields of the standard entry types are shared with the built-in styles of
+/// `--target-style`, with the biblatex equivalents of the fields as additional alternatives. The
+/// same fields are used by the completeness score of a record.
+pub(crate) fn required_fields(entry_type: &str) -> Vec<Vec<&'static str>> {
+    let Some((_, required)) = STANDARD_ENTRY_TYPES
+        .iter()
+        .chain(BIBLATEX_ENTRY_TYPES)
+        .find(|(ty, _)| *ty == entry_type)
+    else {
+        return Vec::new();
+    };
+
+    required
+        .iter()
+        .map(|alternatives| {
+            alternatives
+                .iter()
+                .flat_map(|&field| {
+                    once(field).chain(
+                        BIBLATEX_ALTERNATIVES
+                            .iter()
+                            .filter(move |(bibtex, _)| *bibtex == field)
+                            .map(|(_, biblatex)| *biblatex),
+                    )
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// A problem found when checking a bibliography file.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Problem {
+    /// The entry could not be parsed.
+    Invalid { message: String },
+    /// The key appears more than once in the file.
+    DuplicateKey { key: String, count: usize },
+    /// The key does not correspond to a record in the database.
+    NotInDatabase { key: String },
+    /// The key corresponds to a record which was deleted.
+    Deleted { key: String },
+    /// The entry type differs from the entry type of the record in the database.
+    DivergentEntryType {
+        key: String,
+        file: String,
+        database: String,
+    },
+    /// The field differs from the field of the record in the database.
+    DivergentField {
+        key: String,
+        field: String,
+        file: Option<String>,
+        database: Option<String>,
+    },
+    /// The entry is missing a field which is required for its entry type.
+    MissingField {
+        key: String,
+        entry_type: String,
+        fields: Vec<&'static str>,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid { message } => write!(f, "invalid entry: {message}"),
+            Self::DuplicateKey { key, count } => {
+                write!(f, "{key}: key appears {count} times")
+            }
+            Self::NotInDatabase { key } => write!(f, "{key}: not in database"),
+            Self::Deleted { key } => write!(f, "{key}: deleted from database"),
+            Self::DivergentEntryType {
+                key,
+                file,
+                database,
+            } => write!(
+                f,
+                "{key}: entry type '{file}' differs from database entry type '{database}'"
+            ),
+            Self::DivergentField {
+                key,
+                field,
+                file,
+                database,
+            } => match (file, database) {
+                (Some(file), Some(database)) => write!(
+                    f,
+                    "{key}: field '{field}' has value {{{file}}} but database has {{{database}}}"
+                ),
+                (Some(_), None) => write!(f, "{key}: field '{field}' is not in database"),
+                (None, _) => write!(f, "{key}: field '{field}' is missing"),
+            },
+            Self::MissingField {
+                key,
+                entry_type,
+                fields,
+            } => write!(
+                f,
+                "{key}: entry type '{entry_type}' requires field '{}'",
+                fields.join("' or '")
+            ),
+        }
+    }
+}
+
+/// The result of checking a bibliography file.
+#[derive(Debug, Default, Serialize)]
+pub struct CheckReport {
+    /// The number of entries in the file.
+    pub entries: usize,
+    /// The problems which were found.
+    pub problems: Vec<Problem>,
+}
+
+impl CheckReport {
+    /// Write the report in a human-readable format.
+    pub fn write_text<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        for problem in &self.problems {
+            writeln!(writer, "{problem}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the entries in a bibliography file against the database.
+///
+/// This never retrieves data from remote providers: keys which are not present in the database
+/// are reported as missing.
+pub fn check_bibtex<A: AliasTransform>(
+    record_db: &mut RecordDatabase,
+    bibtex: &[u8],
+    alias_transform: &A,
+) -> Result<CheckReport, rusqlite::Error> {
+    let mut report = CheckReport::default();
+    let mut key_counts: BTreeMap<String, usize> = BTreeMap::new();
+
+    for res in entries_from_bibtex(bibtex) {
+        report.entries += 1;
+        let entry = match res {
+            Ok(entry) => entry,
+            Err(err) => {
+                report.problems.push(Problem::Invalid {
+                    message: err.to_string(),
+                });
+                continue;
+            }
+        };
+        let key = entry.key().as_ref().to_owned();
+
+        let count = key_counts.entry(key.clone()).or_default();
+        *count += 1;
+        if *count > 1 {
+            // only check the first occurrence of each key
+            continue;
+        }
+
+        let entry_type = entry.entry_type().to_ascii_lowercase();
+        for alternatives in required_fields(&entry_type) {
+            if !alternatives
+                .iter()
+                .any(|field| entry.data().contains_field(field))
+            {
+                report.problems.push(Problem::MissingField {
+                    key: key.clone(),
+                    entry_type: entry_type.clone(),
+                    fields: alternatives,
+                });
+            }
+        }
+
+        match record_db.state_from_record_id(RecordId::from(key.as_str()), alias_transform)? {
+            RecordIdState::Entry(_, row, state) => {
+                state.commit()?;
+                if !row.data.entry_type().eq_ignore_ascii_case(&entry_type) {
+                    report.problems.push(Problem::DivergentEntryType {
+                        key: key.clone(),
+                        file: entry_type.clone(),
+                        database: row.data.entry_type().to_owned(),
+                    });
+                }
+                for (field, database) in row.data.fields() {
+                    match entry.data().get_field(field) {
+                        Some(file) if file == database => {}
+                        file => report.problems.push(Problem::DivergentField {
+                            key: key.clone(),
+                            field: field.to_owned(),
+                            file: file.map(ToOwned::to_owned),
+                            database: Some(database.to_owned()),
+                        }),
+                    }
+                }
+                for (field, file) in entry.fields() {
+                    if !row.data.contains_field(field) {
+                        report.problems.push(Problem::DivergentField {
+                            key: key.clone(),
+                            field: field.to_owned(),
+                            file: Some(file.to_owned()),
+                            database: None,
+                        });
+                    }
+                }
+            }
+            RecordIdState::Deleted(_, _, state) => {
+                state.commit()?;
+                report.problems.push(Problem::Deleted { key });
+            }
+            RecordIdState::Void(_, _, state) => {
+                state.commit()?;
+                report.problems.push(Problem::NotInDatabase { key });
+            }
+            RecordIdState::NullRemoteId(_, state) => {
+                state.commit()?;
+                report.problems.push(Problem::NotInDatabase { key });
+            }
+            RecordIdState::Unknown(unknown) => {
+                unknown.combine_and_commit()?;
+                report.problems.push(Problem::NotInDatabase { key });
+            }
+            RecordIdState::UndefinedAlias(_) | RecordIdState::InvalidRemoteId(_) => {
+                report.problems.push(Problem::NotInDatabase { key });
+            }
+        }
+    }
+
+    for (key, count) in key_counts {
+        if count > 1 {
+            report.problems.push(Problem::DuplicateKey { key, count });
+        }
+    }
+
+    Ok(report)
+}
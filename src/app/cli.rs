@@ -1,11 +1,13 @@
 use std::{
     io::{self, IsTerminal},
+    num::NonZeroUsize,
     path::PathBuf,
     str::FromStr,
+    time::Duration,
 };
 
 use anyhow::Result;
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, NaiveDate, TimeDelta};
 use clap::{
     Args, CommandFactory, Parser, Subcommand, ValueEnum, builder::ArgPredicate, error::ErrorKind,
 };
@@ -14,11 +16,14 @@ use clap_verbosity_flag::{Verbosity, WarnLevel};
 use crossterm::style::Stylize;
 
 use crate::{
+    app::sync::SyncRemote,
     cite_search::SourceFileType,
+    config::{SortOrder, parse_age},
     db::state::RevisionId,
-    entry::{EntryType, FieldKey, SetFieldCommand},
+    entry::{EntryStyle, EntryType, FieldKey, Flavor, SetFieldCommand},
     error::ShortError,
     format::Template,
+    query::Query,
     record::{Alias, RecordId},
 };
 
@@ -34,6 +39,11 @@ pub struct Cli {
     pub command: Command,
 
     /// Use record database.
+    ///
+    /// If this option is provided more than once, the first database is used to store records,
+    /// and the others are read-only overlays. Records which are not in the first database are
+    /// copied from the first overlay which contains them, before retrieving them from the remote
+    /// provider. The overlays are never modified.
     #[arg(
         short = 'D',
         long,
@@ -41,7 +51,7 @@ pub struct Cli {
         env = "AUTOBIB_DATABASE_PATH",
         global = true
     )]
-    pub database: Option<PathBuf>,
+    pub database: Vec<PathBuf>,
     /// Use configuration file.
     #[arg(
         short = 'C',
@@ -51,9 +61,38 @@ pub struct Cli {
         global = true
     )]
     pub config: Option<PathBuf>,
+    /// Use a profile defined in the configuration file.
+    ///
+    /// The profile is a `[profile.<name>]` section of the configuration, which can set the
+    /// `database` file and the `attachments_dir` directory, and replace the
+    /// `preferred_providers` and `output` values of the configuration. The database and the
+    /// attachment directory of the profile are only used if they are not set explicitly.
+    #[arg(long, value_name = "NAME", env = "AUTOBIB_PROFILE", global = true)]
+    pub profile: Option<String>,
+    /// Do not use a project configuration file.
+    ///
+    /// By default, the current directory and its parent directories are searched for a
+    /// `.autobib.toml` file. The closest file can set the `database` file and the
+    /// `attachments_dir` directory, and replace the `preferred_providers` and `output` values of
+    /// the configuration, in the same way as a profile. The values of a profile selected with
+    /// `--profile` take precedence over the values of the project configuration file.
+    #[arg(long, env = "AUTOBIB_NO_PROJECT_CONFIG", global = true)]
+    pub no_project_config: bool,
     /// Use directory for attachments.
     #[arg(long, value_name = "PATH", env = "AUTOBIB_ATTACHMENTS_DIRECTORY")]
     pub attachments_dir: Option<PathBuf>,
+    /// Also write every record as a BibTeX file in this directory.
+    ///
+    /// The files are updated whenever a record is changed, so that the directory can be tracked
+    /// with version control. Use `autobib util mirror` to write the files of the records which
+    /// already exist.
+    #[arg(
+        long,
+        value_name = "PATH",
+        env = "AUTOBIB_MIRROR_DIRECTORY",
+        global = true
+    )]
+    pub mirror_dir: Option<PathBuf>,
     /// Do not require user action.
     ///
     /// This option is set by default if the standard input is not a terminal.
@@ -62,10 +101,101 @@ pub struct Cli {
     /// Open the database in read-only mode.
     #[arg(long)]
     pub read_only: bool,
+    /// Report the changes which would be made to the database without making them.
+    ///
+    /// Every change to the database is rolled back instead of committed, and the records which
+    /// would be inserted, changed, or deleted are logged at the info level, which is shown with
+    /// `--verbose`, along with the fields which would be changed. This is supported by `autobib
+    /// import`, `autobib update`, `autobib edit`, `autobib delete`, `autobib refactor-key`, and
+    /// `autobib util migrate-attachments`.
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    /// Do not write a backup of the database before commands which change many records.
+    ///
+    /// By default, a backup is written before `autobib delete --hard`, `autobib hist prune`,
+    /// `autobib hist rewind --all`, `autobib util check --fix`, and `autobib util
+    /// restore-backup`, unless disabled with the `backup.enabled` configuration value.
+    #[arg(long, global = true)]
+    pub no_backup: bool,
+    /// Wait at most this many seconds for another autobib process to finish using the database.
+    ///
+    /// Commands which change the database lock it for as long as they run. By default,
+    /// `autobib import`, `autobib update`, `autobib source`, and `autobib sync` wait until the
+    /// other process finishes, and the other commands wait for 10 seconds.
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        env = "AUTOBIB_LOCK_TIMEOUT",
+        global = true
+    )]
+    pub lock_timeout: Option<u64>,
+    /// Do not access the network.
+    ///
+    /// Only data which is already in the database is used, and identifiers which would have to
+    /// be retrieved are reported as errors without caching them as null records.
+    #[arg(long, env = "AUTOBIB_OFFLINE", global = true)]
+    pub offline: bool,
+    /// Record the responses to HTTP requests in a directory.
+    ///
+    /// The responses are written to the file `responses.dat` in the directory once the command
+    /// finishes, even if it fails, so that they can be attached to a bug report or replayed with
+    /// `--replay-http-cache`.
+    #[arg(
+        long,
+        value_name = "DIR",
+        global = true,
+        conflicts_with_all = ["offline", "replay_http_cache"]
+    )]
+    pub record_http_cache: Option<PathBuf>,
+    /// Replay the responses to HTTP requests from a directory written by `--record-http-cache`
+    /// instead of accessing the network.
+    ///
+    /// Requests whose responses were not recorded fail.
+    #[arg(
+        long,
+        value_name = "DIR",
+        env = "AUTOBIB_REPLAY_HTTP_CACHE",
+        global = true
+    )]
+    pub replay_http_cache: Option<PathBuf>,
+    /// Also append log messages, with timestamps, to a file.
+    ///
+    /// The verbosity of the messages written to the file is the same as the verbosity of the
+    /// messages written to standard error.
+    #[arg(long, value_name = "PATH", env = "AUTOBIB_LOG_FILE", global = true)]
+    pub log_file: Option<PathBuf>,
+    /// Also write log messages to the system log.
+    ///
+    /// On systems running systemd, the messages are forwarded to the journal. This is only
+    /// supported on Unix platforms.
+    #[arg(long, env = "AUTOBIB_SYSLOG", global = true)]
+    pub syslog: bool,
+    /// The format of the log messages written to standard error.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        env = "AUTOBIB_LOG_FORMAT",
+        global = true,
+        default_value_t
+    )]
+    pub log_format: LogFormat,
     #[command(flatten)]
     pub verbose: Verbosity<WarnLevel>,
 }
 
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable messages.
+    #[default]
+    Text,
+    /// A JSON object on a single line for each message, with the keys `time`, `level`, and
+    /// `message`.
+    ///
+    /// The level is one of `error`, `warning`, `info`, `debug`, `trace`, or `suggestion`.
+    Json,
+}
+
 #[derive(Debug, Copy, Clone, ValueEnum, Default)]
 pub enum InfoReportType {
     /// Show all info.
@@ -84,9 +214,59 @@ pub enum InfoReportType {
     /// Print the last modified time.
     #[value(alias("m"))]
     Modified,
+    /// Print the time at which the record was added.
+    #[value(alias("d"))]
+    Added,
     /// Print the revision number.
     #[value(alias("r"))]
     Revision,
+    /// Print where the data was retrieved from.
+    #[value(alias("p"))]
+    Provenance,
+    /// Print the relations from and to the record.
+    #[value(alias("rel"))]
+    Related,
+}
+
+/// The order in which `autobib util list` lists records.
+#[derive(Debug, Copy, Clone, ValueEnum)]
+pub enum ListOrder {
+    /// Sort by canonical identifier.
+    Canonical,
+    /// Sort by the time at which the records were added, starting with the most recent.
+    Added,
+    /// Sort by the time at which the records were last modified, starting with the most recent.
+    Modified,
+    /// Sort by completeness score, starting with the lowest.
+    Score,
+}
+
+/// The kinds of relations which can be created with `autobib relate`.
+#[derive(Debug, Copy, Clone, ValueEnum)]
+pub enum RelationKind {
+    /// The record cites the target.
+    Cites,
+    /// The record extends the target.
+    Extends,
+    /// The record is a translation of the target.
+    TranslationOf,
+    /// The record is an erratum of the target.
+    ErratumOf,
+    /// The record is a republication of the target.
+    RepublicationOf,
+}
+
+impl RelationKind {
+    /// The name of the kind, as stored in the database.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Cites => "cites",
+            Self::Extends => "extends",
+            Self::TranslationOf => "translation-of",
+            Self::ErratumOf => "erratum-of",
+            Self::RepublicationOf => "republication-of",
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, ValueEnum)]
@@ -135,17 +315,83 @@ pub enum Command {
     /// Add a new file to the directory associated with a record, as determined by the `path`
     /// subcommand. The original file is copied to the new directory, or can be renamed
     /// with the `--rename` option.
+    ///
+    /// Use `--list` to list the files attached to a record, and `--remove` to remove one.
+    ///
+    /// Use `--auto` to download an open-access PDF of the record from arXiv, or from the location
+    /// found in the Unpaywall database using the DOI of the record. The file is named using the
+    /// `attach.filename` template in your configuration, unless `--rename` is set.
+    ///
+    /// Use `--scan` to attach the PDF files in a directory to existing records. Each file is
+    /// matched to a record by the DOI or arXiv identifier in its contents, or by its name, such as
+    /// `hochman2014.pdf` for the alias `hochman2014` or `arxiv_1212.1873.pdf` for the identifier
+    /// `arxiv:1212.1873`. In interactive mode, the record for the remaining files can be picked
+    /// using the `find.default_template` template. Files which could not be matched are printed to
+    /// STDOUT.
     Attach {
         /// The record to associate the file with.
-        identifier: RecordId,
-        /// The path or URL for the file to add.
-        file: String,
+        #[arg(required_unless_present = "scan")]
+        identifier: Option<RecordId>,
+        /// The path or URL for the file to add, or the name of the file to remove.
+        #[arg(required_unless_present_any = ["list", "auto", "scan"])]
+        file: Option<String>,
         /// Rename the file.
-        #[arg(short, long)]
+        #[arg(short, long, conflicts_with_all = ["list", "remove"])]
         rename: Option<PathBuf>,
         /// Overwrite an existing file with the same name.
-        #[arg(short, long)]
+        #[arg(short, long, conflicts_with_all = ["list", "remove"])]
         force: bool,
+        /// List the files attached to the record.
+        #[arg(short, long, conflicts_with_all = ["file", "remove"])]
+        list: bool,
+        /// Remove the attached file with the provided name.
+        #[arg(long)]
+        remove: bool,
+        /// Download an open-access PDF of the record.
+        #[arg(long, conflicts_with_all = ["file", "list", "remove"])]
+        auto: bool,
+        /// Attach the PDF files in this directory to existing records.
+        #[arg(
+            long,
+            value_name = "DIR",
+            conflicts_with_all = ["identifier", "file", "rename", "force", "list", "remove", "auto"]
+        )]
+        scan: Option<PathBuf>,
+        /// Move the scanned files instead of copying them.
+        #[arg(long = "move", requires = "scan")]
+        move_files: bool,
+    },
+    /// Check a BibTeX file against the database.
+    ///
+    /// This reports entries whose keys do not correspond to a record in the database, entries
+    /// whose entry type or fields differ from the record in the database, entries which are
+    /// missing a field required by their entry type, and keys which appear more than once.
+    ///
+    /// No data is retrieved from remote providers. If any problems are found, the exit code is
+    /// non-zero.
+    Check {
+        /// The BibTeX file to check.
+        file: PathBuf,
+        /// Write the report as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Manage collections of records.
+    ///
+    /// A collection is a named set of records, such as the references of a thesis, which is
+    /// written as a unit with `autobib get --collection`. Records are stored in a collection by
+    /// their canonical identifier, which is updated when the record is replaced by `autobib
+    /// replace`.
+    Collection {
+        #[command(subcommand)]
+        collection_command: CollectionCommand,
+    },
+    /// Print the identifiers which start with the prefix, for shell completions.
+    #[command(name = "__complete-keys", hide = true)]
+    CompleteKeys {
+        /// The prefix of the identifiers.
+        #[arg(default_value = "")]
+        prefix: String,
     },
     /// Generate a shell completions script.
     #[clap(hide = true)]
@@ -153,6 +399,11 @@ pub enum Command {
         /// The shell for which to generate the script.
         shell: Shell,
     },
+    /// Read and modify configuration values.
+    Config {
+        #[command(subcommand)]
+        config_command: ConfigCommand,
+    },
     /// Generate configuration file.
     #[clap(hide = true)]
     DefaultConfig,
@@ -167,11 +418,22 @@ pub enum Command {
         /// The records to delete.
         identifiers: Vec<RecordId>,
         /// Hard deletion, which removes all history and aliases, and cannot be undone.
+        ///
+        /// The identifiers, aliases, revisions, and attachment directory of each record are
+        /// listed, and deletion requires typed confirmation unless `--yes` is set.
         #[arg(long, group = "delete_mode")]
         hard: bool,
         /// Also delete aliases.
         #[arg(long)]
         delete_aliases: bool,
+        /// Hard delete without asking for confirmation.
+        #[arg(short, long, requires = "hard")]
+        yes: bool,
+        /// Before hard deletion, append the current data of the records to a BibTeX file.
+        ///
+        /// The records can be restored from the file with `autobib import`.
+        #[arg(long, value_name = "PATH", requires = "hard")]
+        tombstone: Option<PathBuf>,
     },
     /// Edit existing records.
     ///
@@ -181,9 +443,22 @@ pub enum Command {
     ///
     /// Non-interactive edit methods are also supported. If any are specified, they will
     /// modify the record.
+    ///
+    /// With `--dry-run`, the changes which would be made to each record are printed. This
+    /// requires at least one non-interactive edit action, and records which are not present in
+    /// the database are not retrieved.
     Edit {
         /// The record(s) to edit.
         identifiers: Vec<RecordId>,
+        /// Edit every record which matches the query, instead of the provided records.
+        ///
+        /// The query consists of comparisons `FIELD OP VALUE` with the operators `=`, `!=`, `~`
+        /// (matches the regular expression), `!~`, `<`, `<=`, `>`, and `>=`, which are combined
+        /// with `and`, `or`, `not`, and parentheses. Besides the fields of the record, the names
+        /// `entrytype`, `provider`, and `modified` (in the format YYYY-MM-DD) can be compared.
+        /// This requires at least one non-interactive edit action.
+        #[arg(long = "where", value_name = "QUERY", conflicts_with = "identifiers")]
+        query: Option<Query>,
         /// Normalize whitespace.
         ///
         /// This converts whitespace blocks into a single ASCII space.
@@ -198,6 +473,23 @@ pub enum Command {
         /// Strip trailing journal series
         #[arg(long)]
         strip_journal_series: bool,
+        /// Protect capitalization in titles.
+        ///
+        /// This wraps the words in the `normalize.protected_words` configuration value in
+        /// braces, such as `{BibTeX}`.
+        #[arg(long)]
+        protect_case: bool,
+        /// Rewrite names in the "author" and "editor" fields.
+        ///
+        /// The convention is set by the `normalize.name_format` configuration value, which
+        /// defaults to "last-first", writing names as "von Last, First".
+        #[arg(long)]
+        normalize_names: bool,
+        /// Move the article number in the "eid" field into the "pages" field.
+        ///
+        /// This is only done if the "pages" field is not set.
+        #[arg(long)]
+        eid_to_pages: bool,
         /// Set the entry type.
         #[arg(long, value_name = "ENTRY_TYPE")]
         update_entry_type: Option<EntryType>,
@@ -223,16 +515,25 @@ pub enum Command {
         /// The type of search to perform.
         #[arg(short, long, value_enum, default_value_t)]
         mode: FindMode,
+        /// Only include records with the tag.
+        #[arg(long, value_name = "TAG", value_parser = parse_label)]
+        tag: Option<String>,
     },
     /// Retrieve records given identifiers.
+    ///
+    /// To output the data of a past revision of a record without changing the active version, use
+    /// `--rev` or append the revision to the identifier, as in `zbl:1337.28015@0006`.
     Get {
         /// The identifiers to retrieve.
         identifiers: Vec<RecordId>,
         /// Write output to file.
+        ///
+        /// If neither this option nor `--retrieve-only` is provided, the output is written to the
+        /// file set by the `get.default_out` configuration value, if any.
         #[arg(short, long, group = "output", value_name = "PATH")]
         out: Option<PathBuf>,
         /// Append new entries to the output, skipping existing entries.
-        #[arg(short, long, requires = "out")]
+        #[arg(short, long, conflicts_with = "retrieve_only")]
         append: bool,
         /// Retrieve records but do not output BibTeX or check the validity of identifiers as
         /// valid BibTeX keys.
@@ -241,6 +542,39 @@ pub enum Command {
         /// Ignore null records and aliases.
         #[arg(long)]
         ignore_null: bool,
+        /// Retrieve identifiers which are cached as null again.
+        #[arg(long)]
+        refresh_null: bool,
+        /// Output the data of a specific revision of the record.
+        #[arg(long, value_name = "REV", conflicts_with = "retrieve_only")]
+        rev: Option<RevisionId>,
+        /// Write the groups of keys which refer to the same record to a file, in JSON format.
+        #[arg(long, value_name = "PATH", conflicts_with = "retrieve_only")]
+        report_duplicates: Option<PathBuf>,
+        /// Convert the entry types and the fields to BibTeX or biblatex, overriding the
+        /// `output.flavor` configuration value.
+        #[arg(long, value_enum, conflicts_with = "retrieve_only")]
+        flavor: Option<Flavor>,
+        /// The order in which the records are written, overriding the `output.sort`
+        /// configuration value.
+        #[arg(long, value_enum, conflicts_with = "retrieve_only")]
+        sort: Option<SortOrder>,
+        #[command(flatten)]
+        style: StyleArgs,
+        /// Also write the parents of the records which were recorded by `autobib import
+        /// --keep-relations`.
+        #[arg(long, conflicts_with = "retrieve_only")]
+        with_parents: bool,
+        /// Also write the records which are related to the records, following chains of
+        /// relations in both directions.
+        #[arg(long, conflicts_with = "retrieve_only")]
+        with_related: bool,
+        /// Also retrieve the records with the tag.
+        #[arg(long, value_name = "TAG", value_parser = parse_label, conflicts_with = "rev")]
+        tag: Option<String>,
+        /// Also retrieve the records in the collection.
+        #[arg(long, value_name = "NAME", conflicts_with = "rev")]
+        collection: Option<String>,
     },
     /// Manipulate version history.
     Hist {
@@ -264,9 +598,21 @@ pub enum Command {
     ///
     /// If you use the `--resolve` option, the determined identifier can be a reference identifier,
     /// which will be converted into a canonical identifier using a remote API call.
+    ///
+    /// With `--from-pdf-dir`, import records from a directory of PDF files instead. The DOI or
+    /// arXiv identifier of each file is determined from its contents, and the file is attached to
+    /// the corresponding record. Files which are already attached are skipped, and files for which
+    /// no record could be determined are printed to STDOUT.
     Import {
         /// The BibTeX file(s) from which to import.
         targets: Vec<PathBuf>,
+        /// Import records from the PDF files in this directory.
+        #[arg(
+            long,
+            value_name = "DIR",
+            conflicts_with_all = ["targets", "local_fallback", "update", "resolve", "include_files"]
+        )]
+        from_pdf_dir: Option<PathBuf>,
         #[arg(short, long)]
         /// Map the citation keys to local identifiers if provenance could not be determined.
         local_fallback: bool,
@@ -285,6 +631,35 @@ pub enum Command {
         /// A separator for the `files` BibTeX field.
         #[arg(long, requires = "include_files")]
         file_sep: Option<String>,
+        /// Record the parents referenced by the `crossref` and `xdata` fields, so that they can
+        /// be written along with the records by `autobib get --with-parents`.
+        #[arg(long)]
+        keep_relations: bool,
+        /// Write the conflicting field values to a TOML file, for use with `autobib resolve`.
+        ///
+        /// Conflicts which are resolved by a prompt or by a merge rule are not written.
+        #[arg(long, value_name = "PATH", requires = "update")]
+        conflicts_out: Option<PathBuf>,
+        /// The number of entries which are imported in a single transaction.
+        ///
+        /// An entry which cannot be imported does not affect the other entries in its batch.
+        /// Smaller batches make the imported records visible to other processes sooner, at
+        /// the cost of a slower import.
+        #[arg(long, value_name = "N", default_value = "500")]
+        batch_size: NonZeroUsize,
+    },
+    /// Import records from PDF files, and attach each file to its record.
+    ///
+    /// The DOI or arXiv identifier of each file is determined from its metadata and its text, the
+    /// corresponding record is retrieved, and the file is attached to the record. The canonical
+    /// identifier of the record is printed for each file. Files which are already attached to the
+    /// record are not attached again.
+    ///
+    /// To import every PDF file in a directory, use `autobib import --from-pdf-dir`.
+    ImportPdf {
+        /// The PDF file(s) to import.
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
     },
     /// Show metadata associated with an identifier.
     Info {
@@ -293,6 +668,9 @@ pub enum Command {
         /// The type of information to display.
         #[arg(short, long, value_enum, default_value_t)]
         report: InfoReportType,
+        /// Print all information as JSON.
+        #[arg(long, conflicts_with = "report")]
+        json: bool,
     },
     /// Create a local record with the given handle.
     ///
@@ -336,6 +714,63 @@ pub enum Command {
         /// Display oldest changes first.
         #[arg(short, long)]
         reverse: bool,
+        /// Print the changes as a JSON array.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Run a language server for citation keys.
+    ///
+    /// The language server communicates over standard input and output, and provides completion
+    /// and hover information for citation keys inside citation macros such as `\cite{...}` in TeX
+    /// documents, and inside Pandoc-style citations such as `[@...]` in Markdown documents.
+    ///
+    /// Hover information is rendered using the template from the `find.default_template`
+    /// configuration value. No data is retrieved from remote providers.
+    Lsp {
+        /// Set the template used to render hover information.
+        #[arg(short, long)]
+        template: Option<Template>,
+    },
+    /// Write a `\nocite` command for a list of records.
+    ///
+    /// This is useful to include records in the bibliography which are not cited in the text,
+    /// such as the reference list of a survey. The records are retrieved as with `autobib get`,
+    /// and the `\nocite` command contains the first key of each record.
+    Nocite {
+        /// The records to include.
+        #[arg(required_unless_present = "query")]
+        identifiers: Vec<RecordId>,
+        /// Also include every record which matches the query.
+        ///
+        /// The query syntax is the same as for `autobib edit --where`.
+        #[arg(long = "where", value_name = "QUERY")]
+        query: Option<Query>,
+        /// Write the `\nocite` command to file.
+        #[arg(short, long, value_name = "PATH")]
+        out: Option<PathBuf>,
+        /// Write the BibTeX entries of the records to file.
+        #[arg(long, value_name = "PATH")]
+        bib: Option<PathBuf>,
+    },
+    /// Open a file attached to a record with the default application.
+    ///
+    /// If there are multiple attachments and no name is provided, you will be prompted to
+    /// select one. If the terminal is not interactive, the first attachment is opened.
+    Open {
+        /// The record whose attachment should be opened.
+        identifier: RecordId,
+        /// The name of the attachment, as shown by `autobib attach --list`.
+        name: Option<String>,
+    },
+    /// Override fields of records in specific output files.
+    ///
+    /// An override replaces the value of a field when the record is written to an output file
+    /// whose path ends with the scope, such as `--scope project.bib`, without changing the data
+    /// of the record. Overrides apply to the output files of `autobib get --out`, `autobib source
+    /// --out`, and `autobib nocite --bib`.
+    Override {
+        #[command(subcommand)]
+        override_command: OverrideCommand,
     },
     /// Show attachment directory associated with record.
     Path {
@@ -344,11 +779,70 @@ pub enum Command {
         /// Also create the directory if it does not exist.
         #[arg(short, long)]
         mkdir: bool,
+        /// Show the path of the record in the human-readable layout instead.
+        ///
+        /// The path is rendered from the template, where every `/` separates directories, and
+        /// ends with the canonical identifier of the record. The path is relative to the
+        /// directory passed to `autobib util relink-attachments`. If no template is provided,
+        /// the `attach.layout` configuration value is used.
+        #[arg(
+            long,
+            value_name = "TEMPLATE",
+            num_args = 0..=1,
+            require_equals = true,
+            conflicts_with = "mkdir"
+        )]
+        layout: Option<Option<Template>>,
+    },
+    /// Record a relation between two records, such as an erratum or a translation.
+    ///
+    /// The relation is read as `<IDENTIFIER> <KIND> <TARGET>`, such as `autobib relate a b
+    /// --kind translation-of` if `a` is a translation of `b`. The relations of a record are
+    /// shown by `autobib info --report related`, and the related records are written along with
+    /// the record by `autobib get --with-related`.
+    Relate {
+        /// The record from which the relation starts.
+        identifier: RecordId,
+        /// The target of the relation.
+        target: RecordId,
+        /// The kind of the relation.
+        #[arg(short, long, value_enum)]
+        kind: RelationKind,
+        /// Delete the relation instead.
+        #[arg(short, long)]
+        delete: bool,
+    },
+    /// Rename a citation key in source files and update the corresponding alias.
+    ///
+    /// Citations of the old key in TeX, Markdown, and Typst files are replaced with the new key,
+    /// and a diff of the changes is printed. Directories are searched recursively. Only keys
+    /// inside citations are renamed, so other text which happens to match the old key is left
+    /// unchanged.
+    ///
+    /// If the old key is an alias, it is renamed to the new key. Otherwise, the new key is
+    /// created as an alias for the record with the old identifier, unless the new alias already
+    /// exists. In particular, to migrate a citation key which is not in the database, first
+    /// create the new alias with `autobib alias add`.
+    RefactorKey {
+        /// The citation key to rename.
+        old: RecordId,
+        /// The new alias.
+        #[arg(value_parser = with_short_err::<Alias>)]
+        new: Alias,
+        /// The files and directories in which to rename the key.
+        ///
+        /// With `--dry-run`, the changes are printed without modifying any files or the
+        /// database.
+        #[arg(long = "in", value_name = "PATH", required = true, num_args = 1..)]
+        paths: Vec<PathBuf>,
     },
     /// Replace an identifier with another one and merge the data.
     ///
     /// The original identifier must be present in the database. If the target identifier is not in
     /// the database, its data will be retrieved first.
+    ///
+    /// Any attachments associated with the original identifier are moved to the attachment
+    /// directory of the replacement.
     Replace {
         /// The identifier to replace.
         #[clap(value_name = "IDENTIFIER")]
@@ -379,6 +873,46 @@ pub enum Command {
         #[arg(long)]
         update_aliases: bool,
     },
+    /// Apply the resolved values in a conflict file.
+    ///
+    /// The conflict file is written by the `--conflicts-out` option of `autobib import` and
+    /// `autobib update`. Each conflict contains the `current` value of a field before the data was
+    /// merged, the `incoming` value, and the `resolved` value which was kept. Edit the `resolved`
+    /// values and then run this command to apply them. An empty `resolved` value removes the
+    /// field.
+    ///
+    /// Conflicts whose field was changed after the conflict file was written are skipped.
+    Resolve {
+        /// The conflict file.
+        path: PathBuf,
+    },
+    /// Serve a JSON API for looking up records over HTTP.
+    ///
+    /// The database remains open while the server is running, so that editor integrations can
+    /// perform fast lookups. The server answers `GET` requests to the following endpoints:
+    ///
+    /// - `/get?id=IDENTIFIER`: the data associated with an identifier.
+    ///
+    /// - `/search?q=TERMS&limit=N`: records whose canonical identifier or field values contain
+    ///   every search term.
+    ///
+    /// - `/list?canonical=BOOL&deleted=BOOL`: the identifiers in the database.
+    ///
+    /// - `/dump`: a dump of the entire database in the format of `autobib util export`, which is
+    ///   used by `autobib sync`.
+    ///
+    /// No data is retrieved from remote providers. The address on which the server is listening
+    /// is printed to standard output.
+    Serve {
+        /// The address on which to listen.
+        #[arg(long, default_value = "127.0.0.1:5010")]
+        address: String,
+        /// Listen on a Unix domain socket at the provided path instead.
+        ///
+        /// A socket file left behind by a server which is no longer running is replaced.
+        #[arg(long, value_name = "PATH", conflicts_with = "address")]
+        socket: Option<PathBuf>,
+    },
     /// Generate records by searching for identifiers inside files.
     ///
     /// This is essentially a call to `autobib get`, except with a custom search which attempts
@@ -387,6 +921,9 @@ pub enum Command {
     /// type, which is determined purely based on the extension.
     Source {
         /// The files in which to search.
+        ///
+        /// Directories are searched recursively for files of a supported type, other than BibTeX
+        /// and text files.
         paths: Vec<PathBuf>,
         /// Override file type detection.
         #[arg(long, value_name = "FILETYPE")]
@@ -406,6 +943,13 @@ pub enum Command {
         /// Only print the identifiers keys which were found (sorted and deduplicated).
         #[arg(long, group = "output")]
         print_keys: bool,
+        /// Compare the keys which were found with the entries of a BibTeX file, without retrieving
+        /// any records.
+        ///
+        /// This prints the keys which are cited but missing from the file, and the keys of the
+        /// entries in the file which are not cited.
+        #[arg(long, value_name = "PATH", group = "output", conflicts_with_all = ["target_style", "report_duplicates", "flavor", "sort"])]
+        diff: Option<PathBuf>,
         /// Skip an identifier (if present).
         #[arg(short, long, value_name = "IDENTIFIERS")]
         skip: Vec<RecordId>,
@@ -418,6 +962,116 @@ pub enum Command {
         /// Ignore null records and aliases.
         #[arg(long)]
         ignore_null: bool,
+        /// Retry at most this many identifiers cached as null, overriding the `source.retry_null`
+        /// configuration value.
+        ///
+        /// Only identifiers which were attempted at least `source.null_ttl` seconds ago are
+        /// retried, starting with the oldest attempts.
+        #[arg(long, value_name = "N")]
+        retry_null: Option<usize>,
+        /// Warn about entries which would not be formatted correctly by a BibTeX style.
+        ///
+        /// The built-in styles are `abbrv`, `acm`, `alpha`, `ieeetr`, `plain`, `siam`, and
+        /// `unsrt`. Additional styles can be defined in the `styles` section of the configuration.
+        #[arg(long, value_name = "STYLE", conflicts_with_all = ["retrieve_only", "print_keys"])]
+        target_style: Option<String>,
+        /// Watch the files for changes and append entries for new identifiers to the output.
+        ///
+        /// Identifiers which were already processed are not retrieved again.
+        #[arg(long, requires_all = ["out", "paths"], conflicts_with_all = ["stdin", "retrieve_only", "print_keys"])]
+        watch: bool,
+        /// Write a dependency list of the files which were read, in Makefile syntax.
+        ///
+        /// The output file is the target and the searched files, as well as any files passed to
+        /// `--skip-from`, are the prerequisites.
+        #[arg(long, value_name = "PATH", requires = "out", conflicts_with_all = ["retrieve_only", "print_keys"])]
+        emit_deps: Option<PathBuf>,
+        /// Write the groups of keys which refer to the same record to a file, in JSON format.
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["retrieve_only", "print_keys", "watch"])]
+        report_duplicates: Option<PathBuf>,
+        /// Convert the entry types and the fields to BibTeX or biblatex, overriding the
+        /// `output.flavor` configuration value.
+        #[arg(long, value_enum, conflicts_with_all = ["retrieve_only", "print_keys"])]
+        flavor: Option<Flavor>,
+        /// The order in which the records are written, overriding the `output.sort`
+        /// configuration value.
+        #[arg(long, value_enum, conflicts_with_all = ["retrieve_only", "print_keys"])]
+        sort: Option<SortOrder>,
+        /// Write each entry as soon as it is retrieved, rather than once every identifier has been
+        /// retrieved.
+        ///
+        /// The entries are written in the order of their keys, ignoring the `output.sort`
+        /// configuration value, so the entries of a record with multiple keys are not necessarily
+        /// adjacent.
+        #[arg(long, conflicts_with_all = ["retrieve_only", "print_keys", "diff", "sort"])]
+        stream: bool,
+        #[command(flatten)]
+        style: StyleArgs,
+    },
+    /// Print statistics of the records in the database.
+    ///
+    /// This prints the number of records, the number of records with and without attachments,
+    /// the number of cached null records, the size of the edit history, and the number of records
+    /// for each provider, entry type, and year.
+    Stats {
+        /// Print the statistics as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Synchronize the database with another database.
+    ///
+    /// The revisions which are missing from either database are copied into the other database.
+    /// If a record was changed in both databases, the changes are merged field by field relative
+    /// to the most recent common revision, and the merged data is inserted as a new revision.
+    /// Changes which cannot be merged automatically are reported as conflicts, and the
+    /// corresponding records are left unchanged. Identifiers and aliases which are only present
+    /// in one database are added to the other database.
+    ///
+    /// The remote database is one of the following:
+    ///
+    /// - A path to a database file, for instance on a mounted network drive.
+    ///
+    /// - An `ssh://HOST/PATH` URL. The remote database is locked, copied with `scp`, and then
+    ///   restored from the updated copy after synchronization. This requires `flock` and `sqlite3`
+    ///   on the remote machine. Use `ssh://HOST/~/PATH` for a path relative to the home
+    ///   directory.
+    ///
+    /// - The `http://` URL of a server started with `autobib serve`. Changes are only copied into
+    ///   the local database, and the database of the server is not modified.
+    Sync {
+        /// The remote database.
+        remote: SyncRemote,
+    },
+    /// Manage the tags of records.
+    ///
+    /// A tag is a label such as `reading-list` which is attached to the canonical identifier of a
+    /// record, so that the tags are preserved when the record is edited or updated. Records with
+    /// a tag are selected with the `--tag` option of `autobib get`, `autobib find`, and `autobib
+    /// util list`.
+    Tag {
+        #[command(subcommand)]
+        tag_command: TagCommand,
+    },
+    /// Browse and curate records in an interactive terminal interface.
+    ///
+    /// The records are searched incrementally using the fields rendered by the template provided
+    /// by the `--template` option, falling back to the `find.default_template` configuration
+    /// value. Press `Enter` to preview the BibTeX entry of the highlighted record, and `Esc` to
+    /// quit. The following keybindings act on the highlighted record:
+    ///
+    /// - `Alt-e`: edit the record.
+    ///
+    /// - `Alt-d`: delete the record, after confirmation.
+    ///
+    /// - `Alt-a`: attach a file to the record.
+    ///
+    /// - `Alt-o`: open an attachment of the record.
+    ///
+    /// The same actions are available in the preview using `e`, `d`, `a`, and `o`.
+    Tui {
+        /// Set the format template.
+        #[arg(short, long)]
+        template: Option<Template>,
     },
     /// Update data associated with an identifier.
     ///
@@ -427,9 +1081,26 @@ pub enum Command {
     /// To override this behaviour, use `-n prefer-current` or `-n prefer-incoming`.
     /// If the terminal is not interactive or the `--no-interactive` global option is set, this
     /// will result in an error if the `-n prefer-current` or `-n prefer-incoming` is not explicitly set.
+    ///
+    /// With `--all`, every record which is not a local record is retrieved again from its
+    /// provider, and the fields which changed are summarized. Use `--older-than` to only
+    /// retrieve records which were last modified before the provided age, such as `180d`.
     Update {
         /// The identifier for the update operation.
-        identifier: RecordId,
+        #[arg(required_unless_present = "all")]
+        identifier: Option<RecordId>,
+        /// Update every record which is not a local record using remote data.
+        #[arg(
+            long,
+            conflicts_with_all = ["identifier", "update_from", "revive"]
+        )]
+        all: bool,
+        /// Only update records last modified longer ago than the provided age.
+        ///
+        /// The age is a number followed by a unit, which is one of `h` (hours), `d` (days), `w`
+        /// (weeks), or `y` (years of 365 days).
+        #[arg(long, value_name = "AGE", requires = "all", value_parser = parse_age)]
+        older_than: Option<TimeDelta>,
         /// Read update data from a BibTeX entry in a file.
         #[arg(short = 'b', long, value_name = "PATH", group = "update_from")]
         from_bibtex: Option<PathBuf>,
@@ -451,6 +1122,11 @@ pub enum Command {
         /// Retrieve new data if the record is deleted.
         #[arg(long)]
         revive: bool,
+        /// Write the conflicting field values to a TOML file, for use with `autobib resolve`.
+        ///
+        /// Conflicts which are resolved by a prompt or by a merge rule are not written.
+        #[arg(long, value_name = "PATH")]
+        conflicts_out: Option<PathBuf>,
     },
     /// Utilities to manage database.
     Util {
@@ -471,6 +1147,148 @@ where
     T::from_str(input).map_err(|err| err.short_err())
 }
 
+/// Parse the name of a tag or a collection, which must be non-empty and cannot contain whitespace
+/// or commas.
+fn parse_label(input: &str) -> Result<String, &'static str> {
+    if input.is_empty() || input.contains(|c: char| c.is_whitespace() || c == ',') {
+        Err("must be non-empty and cannot contain whitespace or commas")
+    } else {
+        Ok(input.to_owned())
+    }
+}
+
+/// Read and modify configuration values.
+///
+/// Configuration keys are a sequence of table names and a field name separated by `.`, such as
+/// `normalize.name_format`.
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the value of a configuration key.
+    ///
+    /// Values which are not set in the configuration file are taken from the default
+    /// configuration.
+    Get {
+        /// The configuration key.
+        key: String,
+    },
+    /// Set the value of a configuration key, preserving the comments in the configuration file.
+    ///
+    /// The value is interpreted as a TOML value, such as `true` or `["doi", "zbl"]`. A value which
+    /// is not valid TOML is interpreted as a string.
+    Set {
+        /// The configuration key.
+        key: String,
+        /// The new value.
+        value: String,
+    },
+}
+
+/// Manage collections.
+#[derive(Debug, Subcommand)]
+pub enum CollectionCommand {
+    /// Create a new empty collection.
+    Create {
+        /// The name of the collection.
+        #[arg(value_parser = parse_label)]
+        name: String,
+    },
+    /// Delete a collection. The records in the collection are not deleted.
+    Delete {
+        /// The name of the collection.
+        name: String,
+    },
+    /// Add records to a collection.
+    Add {
+        /// The name of the collection.
+        name: String,
+        /// The records to add.
+        #[arg(required = true)]
+        identifiers: Vec<RecordId>,
+    },
+    /// Remove records from a collection.
+    #[command(alias = "rm")]
+    Remove {
+        /// The name of the collection.
+        name: String,
+        /// The records to remove.
+        #[arg(required = true)]
+        identifiers: Vec<RecordId>,
+    },
+    /// List the records in a collection.
+    ///
+    /// If no name is provided, list every collection along with the number of records in the
+    /// collection.
+    #[command(alias = "ls")]
+    List {
+        /// The name of the collection.
+        name: Option<String>,
+    },
+}
+
+/// Manage overrides.
+#[derive(Debug, Subcommand)]
+pub enum OverrideCommand {
+    /// Set the value of a field in an output file.
+    Set {
+        /// The record whose field to override.
+        identifier: RecordId,
+        /// The field to override.
+        field: FieldKey,
+        /// The value of the field in the output file.
+        value: String,
+        /// The output file in which to override the field.
+        #[arg(long, value_name = "PATH")]
+        scope: String,
+    },
+    /// Delete an override.
+    #[command(alias = "rm")]
+    Unset {
+        /// The record whose field is overridden.
+        identifier: RecordId,
+        /// The overridden field.
+        field: FieldKey,
+        /// The output file in which the field is overridden.
+        #[arg(long, value_name = "PATH")]
+        scope: String,
+    },
+    /// List the overrides.
+    #[command(alias = "ls")]
+    List {
+        /// Only list the overrides of the record.
+        identifier: Option<RecordId>,
+    },
+}
+
+/// Manage tags.
+#[derive(Debug, Subcommand)]
+pub enum TagCommand {
+    /// Add tags to a record.
+    Add {
+        /// The record to tag.
+        identifier: RecordId,
+        /// The tags to add.
+        #[arg(required = true, value_parser = parse_label)]
+        tags: Vec<String>,
+    },
+    /// Remove tags from a record.
+    #[command(alias = "rm")]
+    Remove {
+        /// The tagged record.
+        identifier: RecordId,
+        /// The tags to remove.
+        #[arg(required = true, value_parser = parse_label)]
+        tags: Vec<String>,
+    },
+    /// List the tags of a record.
+    ///
+    /// If no identifier is provided, list every tag along with the number of records with the tag.
+    #[command(alias = "ls")]
+    List {
+        /// The record whose tags to list.
+        identifier: Option<RecordId>,
+    },
+}
+
 /// Manage aliases.
 #[derive(Debug, Subcommand)]
 pub enum AliasCommand {
@@ -506,6 +1324,54 @@ pub enum AliasCommand {
         /// What the alias should point to.
         target: RecordId,
     },
+    /// Add a new alias which refers to another alias.
+    ///
+    /// The new alias is resolved by following the target alias, so that reassigning the
+    /// target alias also changes the record that the new alias refers to.
+    Link {
+        /// The new alias to create.
+        #[arg(value_parser = with_short_err::<Alias>)]
+        alias: Alias,
+        /// The existing alias to refer to.
+        #[arg(value_parser = with_short_err::<Alias>)]
+        target: Alias,
+    },
+    /// Convert aliases which refer to other aliases into aliases which refer directly to the
+    /// corresponding record.
+    Flatten {
+        /// The aliases to flatten. If none are provided, all aliases are flattened.
+        #[arg(value_parser = with_short_err::<Alias>)]
+        aliases: Vec<Alias>,
+    },
+    /// List aliases along with the canonical identifier of the record that they refer to.
+    #[command(alias = "ls")]
+    List {
+        /// Only list the aliases which refer to the record.
+        identifier: Option<RecordId>,
+    },
+    /// Show the chain of aliases from an alias to the canonical identifier of its record.
+    Of {
+        /// The alias to resolve.
+        #[arg(value_parser = with_short_err::<Alias>)]
+        alias: Alias,
+    },
+    /// Write every alias along with the canonical identifier of its record to a file.
+    ///
+    /// Each line of the file contains an alias and an identifier separated by a tab. The file can
+    /// be read into another database with `autobib alias import`.
+    Export {
+        /// The file to write.
+        file: PathBuf,
+    },
+    /// Create the aliases in a file written by `autobib alias export`.
+    ///
+    /// The aliases are created in the same way as by `autobib alias add`, retrieving the records
+    /// if necessary. Aliases which already exist are not changed, and an error is reported unless
+    /// the alias already refers to the same record.
+    Import {
+        /// The file to read.
+        file: PathBuf,
+    },
 }
 
 pub enum ReadOnlyInvalid {
@@ -514,10 +1380,29 @@ pub enum ReadOnlyInvalid {
 }
 
 impl Cli {
+    /// The time to wait for another process to release the database lock, or `None` to wait
+    /// until it is released.
+    pub fn lock_timeout(&self) -> Option<Duration> {
+        match self.lock_timeout {
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None if self.command.queues_for_lock() => None,
+            None => Some(Duration::from_secs(10)),
+        }
+    }
+
     /// Perform argument validation that Clap cannot do.
     pub fn validate(&self) {
+        if self.dry_run && !self.command.supports_dry_run() {
+            let mut cmd = Self::command();
+            let err_msg = format!(
+                "the argument '{}' is only supported by the subcommands 'import', 'update', 'edit', 'delete', 'refactor-key', and 'util migrate-attachments'",
+                "--dry-run".stylize().yellow(),
+            );
+            cmd.error(ErrorKind::ArgumentConflict, err_msg).exit();
+        }
+
         if self.read_only
-            && let Err(invalid) = self.command.validate_read_only_compatibility()
+            && let Err(invalid) = self.command.validate_read_only_compatibility(self.dry_run)
         {
             let mut cmd = Self::command();
             let (name, s) = match invalid {
@@ -537,43 +1422,175 @@ impl Cli {
 
 impl UtilCommand {
     /// Check if the command is read-only compatible.
-    pub fn validate_read_only_compatibility(&self) -> Result<(), ReadOnlyInvalid> {
+    pub fn validate_read_only_compatibility(&self, dry_run: bool) -> Result<(), ReadOnlyInvalid> {
         match self {
-            Self::List { .. } | Self::Check { fix: false } => Ok(()),
+            Self::List { .. }
+            | Self::Check { fix: false, .. }
+            | Self::Diagnose { .. }
+            | Self::Export { .. }
+            | Self::Mirror
+            | Self::RelinkAttachments { .. } => Ok(()),
             Self::Check { fix: true, .. } => Err(ReadOnlyInvalid::Argument("--fix")),
+            Self::MigrateAttachments if dry_run => Ok(()),
+            Self::MigrateAttachments => Err(ReadOnlyInvalid::Command("util migrate-attachments")),
+            Self::ImportDump { .. } => Err(ReadOnlyInvalid::Command("util import-dump")),
+            Self::Remap { .. } => Err(ReadOnlyInvalid::Command("util remap")),
             Self::Optimize => Err(ReadOnlyInvalid::Command("util optimize")),
             Self::Evict { .. } => Err(ReadOnlyInvalid::Command("util evict")),
+            Self::RestoreBackup { list: true, .. } => Ok(()),
+            Self::RestoreBackup { list: false, .. } => {
+                Err(ReadOnlyInvalid::Command("util restore-backup"))
+            }
+            Self::Generate { .. } => Err(ReadOnlyInvalid::Command("util generate")),
         }
     }
 }
 
 impl Command {
+    /// Check if the command supports `--dry-run`.
+    pub fn supports_dry_run(&self) -> bool {
+        matches!(
+            self,
+            Self::Delete { .. }
+                | Self::Edit { .. }
+                | Self::Import { .. }
+                | Self::RefactorKey { .. }
+                | Self::Update { .. }
+                | Self::Util {
+                    util_command: UtilCommand::MigrateAttachments,
+                }
+        )
+    }
+
     /// Check if the command is read-only compatible.
-    pub fn validate_read_only_compatibility(&self) -> Result<(), ReadOnlyInvalid> {
+    ///
+    /// Commands which only report changes with `--dry-run` are read-only compatible.
+    pub fn validate_read_only_compatibility(&self, dry_run: bool) -> Result<(), ReadOnlyInvalid> {
         // exhaustive matching so that there is a compile error if the `Cli` struct changes
         let invalid_cmd = match self {
-            Self::Get { .. }
+            Self::Check { .. }
+            | Self::Get { .. }
             | Self::Info { .. }
             | Self::Source { .. }
+            | Self::CompleteKeys { .. }
             | Self::Completions { .. }
+            | Self::Config { .. }
             | Self::DefaultConfig
             | Self::Find { .. }
             | Self::Log { .. }
+            | Self::Lsp { .. }
+            | Self::Nocite { .. }
+            | Self::Serve { .. }
+            | Self::Stats { .. }
+            | Self::Open { .. }
             | Self::Path { mkdir: false, .. } => return Ok(()),
             Self::Path { mkdir: true, .. } => return Err(ReadOnlyInvalid::Argument("--mkdir")),
+            Self::Alias {
+                alias_command: AliasCommand::List { .. } | AliasCommand::Of { .. },
+            } => return Ok(()),
+            Self::Alias {
+                alias_command: AliasCommand::Export { .. },
+            } => return Ok(()),
             Self::Alias { .. } => "alias",
-            Self::Attach { .. } => "attach",
+            Self::Attach { list: true, .. } => return Ok(()),
+            Self::Attach { list: false, .. } => "attach",
+            Self::Collection {
+                collection_command: CollectionCommand::List { .. },
+            } => return Ok(()),
+            Self::Collection { .. } => "collection",
             Self::Delete { .. } => "delete",
             Self::Import { .. } => "import",
+            Self::ImportPdf { .. } => "import-pdf",
             Self::Local { .. } => "local",
+            Self::Override {
+                override_command: OverrideCommand::List { .. },
+            } => return Ok(()),
+            Self::Override { .. } => "override",
+            Self::Relate { .. } => "relate",
+            Self::RefactorKey { .. } if dry_run => return Ok(()),
+            Self::RefactorKey { .. } => "refactor-key",
             Self::Replace { .. } => "replace",
+            Self::Resolve { .. } => "resolve",
+            Self::Sync { .. } => "sync",
+            Self::Tag {
+                tag_command: TagCommand::List { .. },
+            } => return Ok(()),
+            Self::Tag { .. } => "tag",
+            Self::Tui { .. } => "tui",
             Self::Update { .. } => "update",
+            Self::Edit { .. } if dry_run => return Ok(()),
             Self::Edit { .. } => "edit",
+            Self::Hist {
+                hist_command: HistCommand::Diff { .. } | HistCommand::Last { .. },
+            } => return Ok(()),
             Self::Hist { .. } => "hist",
-            Self::Util { util_command } => return util_command.validate_read_only_compatibility(),
+            Self::Util { util_command } => {
+                return util_command.validate_read_only_compatibility(dry_run);
+            }
         };
         Err(ReadOnlyInvalid::Command(invalid_cmd))
     }
+
+    /// Check if a backup of the database should be written before running the command, since it
+    /// changes many records in a way which is difficult to undo.
+    pub fn writes_backup(&self) -> bool {
+        matches!(
+            self,
+            Self::Delete { hard: true, .. }
+                | Self::Hist {
+                    hist_command: HistCommand::Prune { .. }
+                        | HistCommand::Rewind {
+                            target: IdTarget { all: true, .. },
+                            ..
+                        },
+                }
+                | Self::Util {
+                    util_command: UtilCommand::Check { fix: true, .. },
+                }
+        )
+    }
+
+    /// Check if the command locks the database while it runs. Commands which run until they are
+    /// stopped, such as `autobib serve`, do not lock the database, so that other commands can
+    /// be run at the same time. `autobib source --watch` instead locks the database while it
+    /// rebuilds the output.
+    pub fn locks_database(&self) -> bool {
+        !matches!(
+            self,
+            Self::Completions { .. }
+                | Self::Config { .. }
+                | Self::DefaultConfig
+                | Self::Lsp { .. }
+                | Self::Serve { .. }
+                | Self::Source { watch: true, .. }
+                | Self::Tui { .. }
+        )
+    }
+
+    /// Check if the command waits for another process to release the database lock without a
+    /// timeout. These are the batch commands, which may take a long time in any case.
+    pub fn queues_for_lock(&self) -> bool {
+        matches!(
+            self,
+            Self::Import { .. } | Self::Update { .. } | Self::Source { .. } | Self::Sync { .. }
+        )
+    }
+
+    /// Check if the summary of the changes made by the command should be printed when it changes
+    /// more than one record.
+    pub fn shows_run_summary(&self) -> bool {
+        matches!(
+            self,
+            Self::Import { .. }
+                | Self::ImportPdf { .. }
+                | Self::Update { .. }
+                | Self::Edit { .. }
+                | Self::Delete { .. }
+                | Self::Sync { .. }
+                | Self::Tui { .. }
+                | Self::Hist { .. }
+        )
+    }
 }
 
 #[derive(Debug, Args)]
@@ -587,9 +1604,72 @@ pub struct IdTarget {
     pub all: bool,
 }
 
+/// Options for the formatting of the written entries, which override the values in the
+/// `[output]` section of the configuration.
+#[derive(Debug, Args)]
+pub struct StyleArgs {
+    /// The number of spaces used to indent the fields.
+    #[arg(long, value_name = "N")]
+    pub indent: Option<usize>,
+    /// Pad the field keys so that the values of the fields are aligned.
+    #[arg(long)]
+    pub align: bool,
+    /// Break the values of the fields at whitespace so that lines do not exceed the column, or
+    /// never break values with 0.
+    #[arg(long, value_name = "COLUMN")]
+    pub wrap: Option<usize>,
+    /// Do not write a comma after the last field of each entry.
+    #[arg(long)]
+    pub no_trailing_comma: bool,
+}
+
+impl StyleArgs {
+    /// Override the provided style with the options which were set.
+    pub fn apply(&self, mut style: EntryStyle) -> EntryStyle {
+        if let Some(indent) = self.indent {
+            style.indent = indent;
+        }
+        if self.align {
+            style.align = true;
+        }
+        if let Some(wrap) = self.wrap {
+            style.wrap = wrap;
+        }
+        if self.no_trailing_comma {
+            style.trailing_comma = false;
+        }
+        style
+    }
+}
+
 /// Commands to manipulate version history.
 #[derive(Debug, Subcommand)]
 pub enum HistCommand {
+    /// Show the changes to the fields of a record between two revisions.
+    ///
+    /// If no revisions are provided, this shows the most recent change, that is, the changes
+    /// between the active revision and its parent. If one revision is provided, it is compared
+    /// with the active revision.
+    ///
+    /// View the revisions of a record using `autobib log --tree`.
+    Diff {
+        /// The identifier of the record.
+        identifier: RecordId,
+        /// A revision to compare. Provide this option twice to compare two revisions.
+        #[arg(long = "rev", value_name = "REV")]
+        revisions: Vec<RevisionId>,
+    },
+    /// Show the changes made by the most recent command which changed records.
+    ///
+    /// Every command which changes records stores the canonical identifier of each changed
+    /// record, the change which was made, and the resulting revision. The same summary is printed
+    /// after `autobib import`, `autobib update`, `autobib edit`, `autobib delete`, `autobib sync`,
+    /// and the `autobib hist` commands, if they changed more than one record.
+    Last {
+        /// Print the changes as JSON.
+        #[arg(long)]
+        json: bool,
+    },
     /// Clean up edit history without impacting the active record.
     Prune {
         #[command(subcommand)]
@@ -666,10 +1746,37 @@ pub enum HistCommand {
         target: IdTarget,
     },
     /// Show all database changes in descending order by time.
+    ///
+    /// With `--sessions`, show the sessions instead. A session consists of all changes made by
+    /// a single command, and can be undone with `autobib hist undo --session`.
     Show {
         /// Only show LIMIT most recent changes
         #[arg(long, value_name = "LIMIT")]
         limit: Option<u32>,
+        /// Show the sessions which changed records, instead of the individual changes.
+        #[arg(long)]
+        sessions: bool,
+        /// Print the changes as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Collapse a chain of revisions into a single revision.
+    ///
+    /// The revisions from `--from` up to and including `--to` are replaced by a single revision
+    /// with the data of `--to`, whose parent is the parent of `--from`. The revision `--from`
+    /// must be an ancestor of `--to`, and the revisions which are removed must not be active or
+    /// have any divergent changes.
+    ///
+    /// View revisions using `autobib log`.
+    Squash {
+        /// The identifier for the squash operation.
+        identifier: RecordId,
+        /// The first revision in the chain.
+        #[arg(long)]
+        from: RevisionId,
+        /// The last revision in the chain, whose data is retained.
+        #[arg(long)]
+        to: RevisionId,
     },
     /// Update the modification time of records.
     ///
@@ -678,13 +1785,43 @@ pub enum HistCommand {
         #[command(flatten)]
         target: IdTarget,
     },
-    /// Undo the most recent change associated with an identifier.
+    /// Undo the most recent change associated with identifiers.
+    ///
+    /// By default, each record is undone independently, so that a record which cannot be undone
+    /// does not prevent the other records from being undone.
+    ///
+    /// With `--session`, undo every change made during a session instead. The records are
+    /// returned to the state before the session, and records created during the session are
+    /// voided. No changes are made if any record was changed after the session.
     Undo {
-        /// The identifier for the undo operation.
-        identifier: RecordId,
+        /// The identifiers for the undo operation.
+        ///
+        /// An identifier containing `*` or `?` is a glob pattern which is matched against the
+        /// canonical identifiers of the records in the database, such as `arxiv:*`. Remember to
+        /// quote the pattern so that it is not expanded by your shell.
+        #[arg(required_unless_present = "session")]
+        identifiers: Vec<RecordId>,
+        /// Undo the changes made during the session with this id.
+        ///
+        /// See `autobib hist show --sessions` for the session ids.
+        #[arg(
+            long,
+            value_name = "ID",
+            conflicts_with_all = ["identifiers", "delete", "since", "atomic"]
+        )]
+        session: Option<i64>,
         /// Undo into a deleted state.
         #[arg(short, long)]
         delete: bool,
+        /// Only undo records which were last modified at or after this datetime.
+        ///
+        /// This is a RFC3339 date-time formatted like YYYY-MM-DD HH:MM:SS+HH:MM, with a trailing
+        /// timezone. See, for example, the output of `autobib info -r modified`.
+        #[arg(long, value_name = "DATETIME")]
+        since: Option<DateTime<Local>>,
+        /// Do not change any records unless every record can be undone.
+        #[arg(long)]
+        atomic: bool,
     },
     /// Void a record.
     ///
@@ -717,12 +1854,96 @@ pub enum PruneCommand {
 /// Utilities to manage database.
 #[derive(Debug, Subcommand)]
 pub enum UtilCommand {
-    /// Check database for errors.
+    /// Check database and attachments for errors.
+    ///
+    /// Attachments associated with replaced records can be migrated with `--fix`. Identifiers
+    /// which are not normalized are also normalized with `--fix`, merging the records if the
+    /// normalized identifier already refers to another record.
     Check {
         /// Attempt to fix errors, printing any errors which could not be fixed.
+        ///
+        /// If a fix is ambiguous, for instance when deleting an invalid identifier, you will be
+        /// prompted to confirm it. If the terminal is not interactive, ambiguous fixes are not
+        /// applied.
         #[arg(short, long)]
         fix: bool,
+        /// Also compare the attachments against the metadata stored in the database.
+        ///
+        /// This reports attachments which are missing or whose contents changed, and attachments
+        /// which are not tracked in the database. With `--fix`, the metadata is updated to match
+        /// the attachments.
+        #[arg(long)]
+        attachments: bool,
+    },
+    /// Check that every provider can retrieve records.
+    ///
+    /// For every provider, or only the provided provider, a record which is known to exist is
+    /// retrieved. The outcome, the latency, and the HTTP status of the last request are reported
+    /// for each provider, along with a message explaining how to resolve any failure.
+    Diagnose {
+        /// Only check this provider.
+        provider: Option<String>,
+    },
+    /// Move attachment directories to the directory of the current canonical identifier.
+    ///
+    /// The attachment directory of a record is determined by its canonical identifier, so
+    /// attachments are no longer found when the canonical identifier of a record changes, for
+    /// instance when the record is replaced. This moves every attachment directory to the
+    /// directory of the canonical identifier of the record which currently exists for it,
+    /// merging directories when the target directory already exists. Attachments which would
+    /// overwrite an existing file are left in place.
+    ///
+    /// With `--dry-run`, the directories which would be moved are printed without moving them.
+    MigrateAttachments,
+    /// Change the canonical identifier of a record, for instance after a provider reassigned it.
+    ///
+    /// The canonical identifier of every revision of the record is changed, so that the history
+    /// of the record is preserved. The attachments, relations, tags, collections, and overrides
+    /// of the record are moved to the new canonical identifier. The old canonical identifier is
+    /// kept as a reference to the record, so that it can still be used to look up the record.
+    Remap {
+        /// The current canonical identifier of the record.
+        from: String,
+        /// The new canonical identifier of the record.
+        to: String,
     },
+    /// Create a tree of symbolic links to the attachment directories in a human-readable layout.
+    ///
+    /// For every record with attachments, a symbolic link to its attachment directory is
+    /// created inside the provided directory, at the path shown by `autobib path --layout`.
+    /// Symbolic links in the directory which no longer correspond to a record, or whose
+    /// target changed, are removed, so that the tree can be updated by running this again.
+    /// Other files in the directory are never modified.
+    RelinkAttachments {
+        /// The directory in which to create the symbolic links.
+        dir: PathBuf,
+        /// Set the template of the layout, instead of the `attach.layout` configuration value.
+        #[arg(long)]
+        layout: Option<Template>,
+    },
+    /// Write a dump of the entire database.
+    ///
+    /// The dump is a text file in the JSON lines format containing every record and its history,
+    /// along with the identifiers, aliases, cached null records, and attachment metadata. Rows are
+    /// written in a deterministic order, so the dump is suitable for backups and can be tracked
+    /// with version control.
+    Export {
+        /// The file to write the dump to.
+        file: PathBuf,
+    },
+    /// Read a dump written by `autobib util export`.
+    ///
+    /// The database must not contain any records. Either the entire dump is read, or the
+    /// database is not changed.
+    ImportDump {
+        /// The dump file to read.
+        file: PathBuf,
+    },
+    /// Write every record to the mirror directory set with `--mirror-dir`.
+    ///
+    /// The file of every record is rewritten, and files which do not correspond to a record are
+    /// removed.
+    Mirror,
     /// Optimize database to (potentially) reduce storage size.
     Optimize,
     /// Clear all local caches.
@@ -732,6 +1953,27 @@ pub enum UtilCommand {
         max_age: Option<u32>,
     },
     /// List all valid identifiers.
+    ///
+    /// With any of the filters `--provider`, `--entry-type`, `--field`, `--modified-since`, and
+    /// `--score-below`, only the canonical identifiers of the records which satisfy every filter
+    /// are listed. With `--template`, each record is printed using the template instead of its
+    /// canonical identifier.
+    ///
+    /// With `--score-below`, only the canonical identifiers of the records with a completeness
+    /// score below the threshold are listed, starting with the lowest score. The completeness
+    /// score is a number between 0 and 1, which is the sum of the following components:
+    ///
+    /// - 0.5 times the fraction of the required fields for the entry type which are present,
+    ///   using the same required fields as `autobib check`,
+    ///
+    /// - 0.2 if the `doi` field is present,
+    ///
+    /// - 0.1 if the `abstract` field is present, and
+    ///
+    /// - 0.2 if the `author` (or `editor`) field is present and each name is written consistently
+    ///   as `von Last, First` or `First von Last`.
+    ///
+    /// The score of a record can also be printed using the `{%score}` template variable.
     List {
         /// Only list the canonical identifiers.
         #[arg(short, long)]
@@ -739,5 +1981,69 @@ pub enum UtilCommand {
         /// List deleted identifiers instead of those with data.
         #[arg(short, long)]
         deleted: bool,
+        /// Print the identifiers as a JSON array.
+        #[arg(long)]
+        json: bool,
+        /// Only list records with completeness score below the threshold.
+        #[arg(long, value_name = "SCORE", conflicts_with = "deleted")]
+        score_below: Option<f64>,
+        /// Only list records from the provider.
+        #[arg(long, conflicts_with = "deleted")]
+        provider: Option<String>,
+        /// Only list records with the entry type.
+        #[arg(long, conflicts_with = "deleted")]
+        entry_type: Option<EntryType>,
+        /// Only list records which satisfy the query, such as `year>=2020`.
+        ///
+        /// The query syntax is the same as for `autobib edit --where`. If this option is provided
+        /// more than once, every query must be satisfied.
+        #[arg(long, value_name = "QUERY", conflicts_with = "deleted")]
+        field: Vec<Query>,
+        /// Only list records which were modified on or after the date, in the format YYYY-MM-DD.
+        #[arg(long, value_name = "DATE", conflicts_with = "deleted")]
+        modified_since: Option<NaiveDate>,
+        /// Only list records with the tag.
+        #[arg(long, value_name = "TAG", value_parser = parse_label, conflicts_with = "deleted")]
+        tag: Option<String>,
+        /// Print each record using the template.
+        #[arg(short, long, conflicts_with_all = ["deleted", "json"])]
+        template: Option<Template>,
+        /// The order in which the records are listed.
+        ///
+        /// By default, the records are sorted by canonical identifier, or by completeness score
+        /// if `--score-below` is provided.
+        #[arg(long, value_enum, conflicts_with = "deleted")]
+        sort: Option<ListOrder>,
+        /// Only list the first records, after sorting.
+        #[arg(long, value_name = "N", conflicts_with = "deleted")]
+        limit: Option<usize>,
+    },
+    /// Restore a backup of the database.
+    ///
+    /// Backups are written automatically to the `backups` directory next to the database file
+    /// before commands which change many records at once. The contents of the database are
+    /// replaced by the contents of the backup, after writing a backup of the current contents.
+    RestoreBackup {
+        /// The backup file to restore. Defaults to the most recent backup.
+        backup: Option<PathBuf>,
+        /// List the backups, from oldest to newest, instead of restoring a backup.
+        #[arg(long, conflicts_with = "backup")]
+        list: bool,
+    },
+    /// Populate the database with synthetic records for benchmarking.
+    ///
+    /// Each record is a local record `local:synthetic-N` with an alias `synthetic-N`. Records
+    /// which already exist are skipped.
+    #[clap(hide = true)]
+    Generate {
+        /// The number of records to generate.
+        #[arg(long)]
+        records: usize,
+        /// The number of additional revisions to create for each record.
+        #[arg(long, default_value_t = 0)]
+        revisions: usize,
+        /// Also create an attachment for each record.
+        #[arg(long)]
+        attachments: bool,
     },
 }
@@ -0,0 +1,285 @@
+//! # Shell completions
+//!
+//! The completion scripts are generated by [`clap_complete`]. For Bash, Zsh, and Fish, the
+//! scripts are extended with a function which completes the identifiers in the database at the
+//! positions of the arguments which expect an existing identifier. The identifiers are printed by
+//! the hidden `autobib __complete-keys` command.
+use std::{
+    collections::BTreeSet,
+    io::{self, Write},
+};
+
+use clap::{Arg, Command as ClapCommand, CommandFactory};
+use clap_complete::aot::{Shell, generate};
+
+use super::Cli;
+
+/// The positional arguments which expect an existing identifier.
+const KEY_ARGS: &[&str] = &[
+    "identifier",
+    "identifiers",
+    "old",
+    "target",
+    "alias",
+    "aliases",
+];
+
+/// The positional arguments in [`KEY_ARGS`] which expect a new identifier, as pairs of the
+/// subcommand and the argument.
+const NEW_KEY_ARGS: &[(&str, &str)] = &[("alias add", "alias")];
+
+/// The positions of the arguments of a subcommand which expect an existing identifier.
+struct KeyPositions {
+    /// The names of the subcommand and its parent commands, separated by spaces.
+    subcommand: String,
+    /// The indices of the positional arguments, separated by spaces, or `*` if every positional
+    /// argument expects an identifier.
+    positions: String,
+}
+
+impl KeyPositions {
+    /// The representation used by the completion functions, which is `subcommand:positions`.
+    fn entry(&self) -> String {
+        format!("'{}:{}'", self.subcommand, self.positions)
+    }
+}
+
+/// Collect the positions of the identifier arguments of every subcommand of the command.
+fn key_positions(cmd: &ClapCommand, parents: &[&str], out: &mut Vec<KeyPositions>) {
+    for sub in cmd.get_subcommands() {
+        let mut names = parents.to_vec();
+        names.push(sub.get_name());
+        let subcommand = names.join(" ");
+
+        let mut indices = Vec::new();
+        let mut variadic = false;
+        for (idx, arg) in sub.get_positionals().enumerate() {
+            let id = arg.get_id().as_str();
+            if KEY_ARGS.contains(&id) && !NEW_KEY_ARGS.contains(&(subcommand.as_str(), id)) {
+                indices.push(idx.to_string());
+                variadic |= arg.get_num_args().is_some_and(|num| num.max_values() > 1);
+            }
+        }
+
+        if !indices.is_empty() {
+            let positions = if variadic {
+                "*".to_owned()
+            } else {
+                indices.join(" ")
+            };
+            out.push(KeyPositions {
+                subcommand: subcommand.clone(),
+                positions,
+            });
+        }
+
+        key_positions(sub, &names, out);
+    }
+}
+
+/// Collect the names of the options which take a value, so that the values are not counted as
+/// positional arguments.
+fn value_options(cmd: &ClapCommand, out: &mut BTreeSet<String>) {
+    let takes_value = |arg: &&Arg| !arg.is_positional() && arg.get_action().takes_values();
+    for arg in cmd.get_arguments().filter(takes_value) {
+        if let Some(longs) = arg.get_long_and_visible_aliases() {
+            out.extend(longs.into_iter().map(|long| format!("--{long}")));
+        }
+        if let Some(shorts) = arg.get_short_and_visible_aliases() {
+            out.extend(shorts.into_iter().map(|short| format!("-{short}")));
+        }
+    }
+    for sub in cmd.get_subcommands() {
+        value_options(sub, out);
+    }
+}
+
+/// The Bash function which completes identifiers, and replaces the completion function generated
+/// by `clap_complete`.
+fn bash_keys(bin_name: &str, entries: &str, options: &str) -> String {
+    let fn_name = bin_name.replace('-', "__");
+    format!(
+        r#"
+_{fn_name}_keys() {{
+    local line="${{COMP_LINE:0:COMP_POINT}}"
+    local cur="${{line##*[[:space:]]}}"
+    [[ "$cur" == -* ]] && return 1
+
+    local -a tokens args=()
+    read -ra tokens <<< "${{line%"$cur"}}"
+    local i
+    for (( i = 1; i < ${{#tokens[@]}}; i++ )); do
+        case "${{tokens[i]}}" in
+            {options}) (( i++ )) ;;
+            -*) ;;
+            *) args+=("${{tokens[i]}}") ;;
+        esac
+    done
+
+    local entry subcommand positions index
+    for entry in {entries}; do
+        subcommand="${{entry%%:*}}"
+        positions="${{entry#*:}}"
+        local -a parts=($subcommand)
+        [[ "${{args[*]:0:${{#parts[@]}}}}" == "$subcommand" ]] || continue
+        index=$(( ${{#args[@]}} - ${{#parts[@]}} ))
+        if [[ "$positions" == "*" || " $positions " == *" $index "* ]]; then
+            COMPREPLY=( $(compgen -W "$({bin_name} --read-only __complete-keys "$cur" 2>/dev/null)" -- "$cur") )
+            # identifiers contain colons, which separate words in Bash
+            COMPREPLY=( "${{COMPREPLY[@]#"${{cur%"${{cur##*:}}"}}"}}" )
+            return 0
+        fi
+        return 1
+    done
+    return 1
+}}
+
+_{fn_name}_with_keys() {{
+    _{fn_name}_keys || _{fn_name} "$@"
+}}
+
+if [[ "${{BASH_VERSINFO[0]}}" -eq 4 && "${{BASH_VERSINFO[1]}}" -ge 4 || "${{BASH_VERSINFO[0]}}" -gt 4 ]]; then
+    complete -F _{fn_name}_with_keys -o nosort -o bashdefault -o default {bin_name}
+else
+    complete -F _{fn_name}_with_keys -o bashdefault -o default {bin_name}
+fi
+"#
+    )
+}
+
+/// The Zsh function which completes identifiers, and replaces the completion function generated
+/// by `clap_complete`.
+fn zsh_keys(bin_name: &str, entries: &str, options: &str) -> String {
+    format!(
+        r#"
+_{bin_name}_keys() {{
+    [[ "$PREFIX" == -* ]] && return 1
+
+    local -a args
+    local i
+    for (( i = 2; i < CURRENT; i++ )); do
+        case "$words[i]" in
+            ({options}) (( i++ )) ;;
+            (-*) ;;
+            (*) args+=("$words[i]") ;;
+        esac
+    done
+
+    local entry subcommand positions index
+    local -a parts keys
+    for entry in {entries}; do
+        subcommand="${{entry%%:*}}"
+        positions="${{entry#*:}}"
+        parts=(${{=subcommand}})
+        [[ "${{args[1,$#parts]}}" == "$subcommand" ]] || continue
+        index=$(( $#args - $#parts ))
+        if [[ "$positions" == "*" || " $positions " == *" $index "* ]]; then
+            keys=(${{(f)"$({bin_name} --read-only __complete-keys "$PREFIX" 2>/dev/null)"}})
+            compadd -a keys
+            return 0
+        fi
+        return 1
+    done
+    return 1
+}}
+
+_{bin_name}_with_keys() {{
+    _{bin_name}_keys || _{bin_name} "$@"
+}}
+
+if [ "$funcstack[1]" = "_{bin_name}" ]; then
+    _{bin_name}_with_keys "$@"
+else
+    compdef _{bin_name}_with_keys {bin_name}
+fi
+"#
+    )
+}
+
+/// The Fish function which checks if the current argument expects an identifier, and the
+/// completions of the identifiers.
+fn fish_keys(bin_name: &str, key_positions: &[KeyPositions], options: &str) -> String {
+    let fn_name = format!("__fish_{}_key_position", bin_name.replace('-', "_"));
+    let mut script = format!(
+        r#"
+function {fn_name} --argument-names subcommand positions
+    set -l args
+    set -l tokens (commandline -opc)
+    set -l skip 0
+    for token in $tokens[2..-1]
+        if test $skip -eq 1
+            set skip 0
+            continue
+        end
+        switch $token
+            case {options}
+                set skip 1
+            case '-*'
+            case '*'
+                set -a args $token
+        end
+    end
+
+    set -l parts (string split ' ' -- $subcommand)
+    test (count $args) -ge (count $parts); or return 1
+    test "$args[1..(count $parts)]" = "$subcommand"; or return 1
+    test "$positions" = '*'; and return 0
+    contains -- (math (count $args) - (count $parts)) (string split ' ' -- $positions)
+end
+"#
+    );
+    for key_position in key_positions {
+        script.push_str(&format!(
+            "complete -c {bin_name} -n \"{fn_name} '{}' '{}'\" -f -a \"({bin_name} --read-only __complete-keys (commandline -ct) 2>/dev/null)\"\n",
+            key_position.subcommand, key_position.positions
+        ));
+    }
+    script
+}
+
+/// Write the completion script for the shell.
+pub fn write_completions<W: Write>(shell: Shell, writer: &mut W) -> io::Result<()> {
+    let mut clap_command = Cli::command();
+    let bin_name = clap_command.get_name().to_owned();
+
+    let mut buffer = Vec::new();
+    generate(shell, &mut clap_command, bin_name.clone(), &mut buffer);
+    let mut script = String::from_utf8(buffer).map_err(io::Error::other)?;
+
+    // the arguments are only fully determined once the command is built
+    clap_command.build();
+    let mut positions = Vec::new();
+    key_positions(&clap_command, &[], &mut positions);
+    let mut options = BTreeSet::new();
+    value_options(&clap_command, &mut options);
+
+    let entries = positions
+        .iter()
+        .map(KeyPositions::entry)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    match shell {
+        Shell::Bash => {
+            let options = options.into_iter().collect::<Vec<_>>().join("|");
+            script.push_str(&bash_keys(&bin_name, &entries, &options));
+        }
+        Shell::Zsh => {
+            // the generated script registers its own completion function at the end, which is
+            // replaced by the function which also completes identifiers
+            let registration = format!(
+                "if [ \"$funcstack[1]\" = \"_{bin_name}\" ]; then\n    _{bin_name} \"$@\"\nelse\n    compdef _{bin_name} {bin_name}\nfi\n"
+            );
+            script = script.replace(&registration, "");
+            let options = options.into_iter().collect::<Vec<_>>().join("|");
+            script.push_str(&zsh_keys(&bin_name, &entries, &options));
+        }
+        Shell::Fish => {
+            let options = options.into_iter().collect::<Vec<_>>().join(" ");
+            script.push_str(&fish_keys(&bin_name, &positions, &options));
+        }
+        _ => {}
+    }
+
+    writer.write_all(script.as_bytes())
+}
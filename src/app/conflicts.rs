@@ -0,0 +1,215 @@
+//! # Conflicts resolved without prompting
+//!
+//! When data is merged into a record without prompting, conflicting values are resolved by
+//! keeping either the current or the incoming value. These conflicts can be written to a TOML
+//! file, in which the `resolved` value of each conflict can be edited before the file is applied
+//! with `autobib resolve`.
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    RecordId,
+    config::AliasTransform,
+    db::{
+        RecordDatabase,
+        state::{RecordIdState, RecordRow},
+    },
+    entry::{EntryData, EntryType, MutableEntryData, RawEntryData, changed_fields},
+    error::FailureKind,
+    logger::{error, info, reraise_as, suggest, warn},
+};
+
+/// A conflicting value which was resolved without prompting when merging data into a record.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordConflict {
+    /// The identifier of the record.
+    pub identifier: String,
+    /// The field with conflicting values, or `None` if the entry types conflict.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// The value before the data was merged.
+    pub current: String,
+    /// The value in the merged data.
+    pub incoming: String,
+    /// The value which was kept. An empty value removes the field.
+    pub resolved: String,
+}
+
+impl RecordConflict {
+    /// A conflict which was resolved by keeping the current value if `keep_current` is set, and
+    /// otherwise by taking the incoming value.
+    pub fn new(
+        identifier: &impl fmt::Display,
+        field: Option<&str>,
+        current: &str,
+        incoming: &str,
+        keep_current: bool,
+    ) -> Self {
+        Self {
+            identifier: identifier.to_string(),
+            field: field.map(ToOwned::to_owned),
+            current: current.to_owned(),
+            incoming: incoming.to_owned(),
+            resolved: if keep_current { current } else { incoming }.to_owned(),
+        }
+    }
+
+    /// A description of the conflicting value, for use in messages.
+    fn describe(&self) -> String {
+        match &self.field {
+            Some(field) => format!("field '{field}' of '{}'", self.identifier),
+            None => format!("entry type of '{}'", self.identifier),
+        }
+    }
+}
+
+/// The contents of a conflict file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ConflictFile {
+    #[serde(default, rename = "conflict")]
+    conflicts: Vec<RecordConflict>,
+}
+
+/// Write the conflicts to a TOML file, replacing the file if it exists.
+pub fn write_conflicts(path: &Path, conflicts: Vec<RecordConflict>) -> Result<(), anyhow::Error> {
+    let num_conflicts = conflicts.len();
+    let contents = toml::to_string(&ConflictFile { conflicts })?;
+    if let Err(e) = fs::write(path, contents) {
+        bail!("Failed to write conflict file '{}': {e}", path.display());
+    }
+    if num_conflicts > 0 {
+        info!("Wrote {num_conflicts} conflict(s) to '{}'", path.display());
+        suggest!(
+            "Edit the `resolved` values and apply them with `autobib resolve {}`.",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Set the value of the conflict to the resolved value.
+///
+/// The conflict is skipped if the value is neither the current nor the incoming value, since the
+/// record was changed after the conflict was written.
+fn apply_resolution(
+    data: &mut MutableEntryData,
+    conflict: &RecordConflict,
+) -> Result<(), anyhow::Error> {
+    let value = match &conflict.field {
+        Some(field) => data.get_str(field.as_str()).unwrap_or_default(),
+        None => data.entry_type(),
+    };
+    if value == conflict.resolved {
+        return Ok(());
+    }
+    if value != conflict.current && value != conflict.incoming {
+        warn!(
+            "Skipping the {}: the value was changed after the conflict was written",
+            conflict.describe()
+        );
+        return Ok(());
+    }
+
+    match &conflict.field {
+        Some(field) if conflict.resolved.is_empty() => {
+            data.remove(field.as_str());
+        }
+        Some(field) => data.check_and_insert(field.clone(), conflict.resolved.clone())?,
+        None => {
+            data.update_entry_type(&EntryType::try_new(conflict.resolved.clone())?);
+        }
+    }
+    Ok(())
+}
+
+/// Apply the resolved values in a file written by [`write_conflicts`]. The resolved values of
+/// each record are applied as a single revision. Returns the number of records which were changed.
+pub fn resolve_conflicts<A: AliasTransform>(
+    record_db: &mut RecordDatabase,
+    path: &Path,
+    alias_transform: &A,
+) -> Result<usize, anyhow::Error> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => bail!("Failed to read conflict file '{}': {e}", path.display()),
+    };
+    let file: ConflictFile = match toml::from_str(&contents) {
+        Ok(file) => file,
+        Err(e) => bail!("Invalid conflict file '{}': {e}", path.display()),
+    };
+
+    let mut grouped: BTreeMap<String, Vec<RecordConflict>> = BTreeMap::new();
+    for conflict in file.conflicts {
+        grouped
+            .entry(conflict.identifier.clone())
+            .or_default()
+            .push(conflict);
+    }
+
+    let mut num_changed = 0;
+    for (identifier, conflicts) in grouped {
+        let (data, canonical, state) = match record_db
+            .state_from_record_id(RecordId::from(identifier.as_str()), alias_transform)?
+        {
+            RecordIdState::Entry(
+                _,
+                RecordRow {
+                    data, canonical, ..
+                },
+                state,
+            ) => (data, canonical, state),
+            RecordIdState::Deleted(key, _, state) => {
+                state.commit()?;
+                error!("Cannot resolve conflicts of deleted record '{key}'");
+                continue;
+            }
+            RecordIdState::Void(key, _, state) => {
+                state.commit()?;
+                error!("Cannot resolve conflicts of voided record '{key}'");
+                continue;
+            }
+            RecordIdState::NullRemoteId(remote_id, state) => {
+                state.commit()?;
+                error!(kind = FailureKind::NullRecord; "Cannot resolve conflicts of null record '{remote_id}'");
+                continue;
+            }
+            RecordIdState::Unknown(unknown) => {
+                let mapped = unknown.combine_and_commit()?;
+                error!("Database does not contain key: {mapped}");
+                continue;
+            }
+            RecordIdState::UndefinedAlias(alias) => {
+                error!("Cannot resolve conflicts of undefined alias '{alias}'");
+                continue;
+            }
+            RecordIdState::InvalidRemoteId(err) => {
+                reraise_as(FailureKind::InvalidIdentifier, &err);
+                continue;
+            }
+        };
+
+        let mut resolved = MutableEntryData::from_entry_data(&data);
+        for conflict in &conflicts {
+            if let Err(err) = apply_resolution(&mut resolved, conflict) {
+                error!(kind = FailureKind::Conflict; "Cannot resolve the {}: {err}", conflict.describe());
+            }
+        }
+
+        let fields = changed_fields(&data, &resolved);
+        if fields.is_empty() {
+            state.commit()?;
+            continue;
+        }
+        info!("Resolving conflicts of '{canonical}'");
+        let state = state.modify(&RawEntryData::from_entry_data(&resolved))?;
+        state.set_field_sources(
+            fields.iter().map(String::as_str),
+            &format!("resolved conflicts in {}", path.display()),
+        )?;
+        state.commit()?;
+        num_changed += 1;
+    }
+    Ok(num_changed)
+}
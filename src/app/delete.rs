@@ -1,12 +1,148 @@
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
 use crate::{
     Config, RecordId, RemoteId,
     db::{
-        RecordDatabase,
-        state::{self, RecordIdState},
+        Identifier, RecordDatabase,
+        state::{self, InRecordsTable, RecordIdState},
     },
-    logger::{error, reraise, suggest},
+    entry::{Entry, EntryKey, RawEntryData},
+    error::FailureKind,
+    logger::{error, reraise_as, suggest, warn},
 };
 
+/// The data which is permanently removed by hard-deleting a record.
+pub struct HardDeleteSummary {
+    /// The canonical identifier of the record.
+    pub canonical: RemoteId,
+    /// The identifiers and aliases which refer to the record.
+    pub keys: Vec<String>,
+    /// The aliases which refer to the record through another alias.
+    pub linked_aliases: Vec<String>,
+    /// The number of revisions of the record.
+    pub revisions: usize,
+    /// The attachment directory of the record, if it exists.
+    pub attachment_dir: Option<PathBuf>,
+    /// The current data of the record, unless the record is deleted or void.
+    pub data: Option<RawEntryData>,
+}
+
+impl fmt::Display for HardDeleteSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} ({} revisions)", self.canonical, self.revisions)?;
+        writeln!(f, "  identifiers: {}", self.keys.join(", "))?;
+        if !self.linked_aliases.is_empty() {
+            writeln!(f, "  linked aliases: {}", self.linked_aliases.join(", "))?;
+        }
+        if let Some(dir) = &self.attachment_dir {
+            writeln!(f, "  attachments (not deleted): {}", dir.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Determine the data which would be permanently removed by hard-deleting the record
+/// associated with the provided identifier, without deleting it.
+///
+/// If the identifier does not correspond to a record, an error is reported and this returns
+/// `None`. The `attachment_dir` callback determines the attachment directory of the record.
+pub fn hard_delete_summary<F: FnOnce() -> Vec<(regex::Regex, String)>>(
+    id: RecordId,
+    record_db: &mut RecordDatabase,
+    config: &Config<F>,
+    attachment_dir: impl FnOnce(&RemoteId) -> PathBuf,
+) -> Result<Option<HardDeleteSummary>, rusqlite::Error> {
+    fn summarize<I: InRecordsTable>(
+        state: &state::State<'_, I>,
+        canonical: RemoteId,
+        data: Option<RawEntryData>,
+        attachment_dir: impl FnOnce(&RemoteId) -> PathBuf,
+    ) -> Result<HardDeleteSummary, rusqlite::Error> {
+        let dir = attachment_dir(&canonical);
+        Ok(HardDeleteSummary {
+            keys: state.referencing_keys()?,
+            linked_aliases: state
+                .linked_aliases()?
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect(),
+            revisions: state.num_revisions()?,
+            attachment_dir: dir.is_dir().then_some(dir),
+            canonical,
+            data,
+        })
+    }
+
+    let summary = match record_db.state_from_record_id(id, &config.alias_transform)? {
+        RecordIdState::Entry(_, row, state) => {
+            let summary = summarize(&state, row.canonical, Some(row.data), attachment_dir)?;
+            state.commit()?;
+            summary
+        }
+        RecordIdState::Deleted(_, row, state) => {
+            let summary = summarize(&state, row.canonical, None, attachment_dir)?;
+            state.commit()?;
+            summary
+        }
+        RecordIdState::Void(_, row, state) => {
+            let summary = summarize(&state, row.canonical, None, attachment_dir)?;
+            state.commit()?;
+            summary
+        }
+        RecordIdState::NullRemoteId(mapped_key, state) => {
+            state.commit()?;
+            error!(kind = FailureKind::NullRecord; "Cannot delete null record data: {mapped_key}");
+            suggest!("Delete null records using `autobib util evict`.");
+            return Ok(None);
+        }
+        RecordIdState::Unknown(unknown) => {
+            let maybe_normalized = unknown.combine_and_commit()?;
+            error!("Cannot delete key not in database: {maybe_normalized}");
+            return Ok(None);
+        }
+        RecordIdState::UndefinedAlias(alias) => {
+            error!("Cannot delete undefined alias: {alias}");
+            return Ok(None);
+        }
+        RecordIdState::InvalidRemoteId(record_error) => {
+            reraise_as(FailureKind::InvalidIdentifier, &record_error);
+            return Ok(None);
+        }
+    };
+    Ok(Some(summary))
+}
+
+/// Append the current data of the records to the BibTeX file at the provided path, using the
+/// canonical identifier as the key, so that the records can be restored with `autobib import`.
+pub fn write_tombstone(path: &Path, summaries: &[HardDeleteSummary]) -> Result<(), anyhow::Error> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    for summary in summaries {
+        let Some(data) = &summary.data else {
+            warn!(
+                "Not writing '{}' to the tombstone file since the record has no current data",
+                summary.canonical
+            );
+            continue;
+        };
+        match EntryKey::try_new(summary.canonical.name().to_owned()) {
+            Ok(key) => {
+                writeln!(file, "% identifiers: {}", summary.keys.join(", "))?;
+                writeln!(file, "{}", Entry::new(key, data.clone()))?;
+            }
+            Err(err) => warn!(
+                "Not writing '{}' to the tombstone file: {err}",
+                summary.canonical
+            ),
+        }
+    }
+    Ok(())
+}
+
 /// Soft-delete the data associated with the provided identifier.
 ///
 /// If record data exists for the provided key, the data is replaced with a 'deletion' marker, but not
@@ -77,7 +213,7 @@ where
         RecordIdState::Void(original_name, _, state) => voided_callback(original_name, state)?,
         RecordIdState::NullRemoteId(mapped_key, state) => {
             state.commit()?;
-            error!("Cannot delete null record data: {mapped_key}");
+            error!(kind = FailureKind::NullRecord; "Cannot delete null record data: {mapped_key}");
             suggest!("Delete null records using `autobib util evict`.");
         }
         RecordIdState::Unknown(unknown) => {
@@ -88,7 +224,7 @@ where
             error!("Cannot delete undefined alias: {alias}");
         }
         RecordIdState::InvalidRemoteId(record_error) => {
-            reraise(&record_error);
+            reraise_as(FailureKind::InvalidIdentifier, &record_error);
         }
     };
     Ok(())
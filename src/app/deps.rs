@@ -0,0 +1,80 @@
+//! # Dependency lists for build systems
+//!
+//! The dependency list uses Makefile syntax, which is also understood by `latexmk`. The output
+//! file is the target, and every file which was read to generate the output file is a
+//! prerequisite. Every prerequisite is also written as a target without prerequisites, so that a
+//! build system does not fail if one of the files is removed.
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+/// Escape a path for use in a Makefile rule.
+fn escape_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    let mut escaped = String::with_capacity(path.len());
+    for ch in path.chars() {
+        match ch {
+            ' ' | '\t' | '#' | ':' => {
+                escaped.push('\\');
+                escaped.push(ch);
+            }
+            '$' => escaped.push_str("$$"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Write the dependency list for the target to the writer.
+fn write_dependencies<W: Write, P: AsRef<Path>>(
+    mut writer: W,
+    target: &Path,
+    prerequisites: &[P],
+) -> io::Result<()> {
+    write!(writer, "{}:", escape_path(target))?;
+    for prereq in prerequisites {
+        write!(writer, " {}", escape_path(prereq.as_ref()))?;
+    }
+    writeln!(writer)?;
+
+    for prereq in prerequisites {
+        writeln!(writer, "\n{}:", escape_path(prereq.as_ref()))?;
+    }
+    writer.flush()
+}
+
+/// Write the dependency list for the target to the file at the provided path.
+pub fn emit_dependencies<P: AsRef<Path>>(
+    path: &Path,
+    target: &Path,
+    prerequisites: &[P],
+) -> Result<(), anyhow::Error> {
+    match File::create(path)
+        .and_then(|file| write_dependencies(BufWriter::new(file), target, prerequisites))
+    {
+        Ok(()) => Ok(()),
+        Err(e) => anyhow::bail!("Failed to write dependency file '{}': {e}", path.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_dependencies() {
+        let mut buffer = Vec::new();
+        write_dependencies(
+            &mut buffer,
+            Path::new("main.bib"),
+            &["main.tex", "my chapter.tex", "c$#:.tex"],
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "main.bib: main.tex my\\ chapter.tex c$$\\#\\:.tex\n\nmain.tex:\n\nmy\\ chapter.tex:\n\nc$$\\#\\:.tex:\n"
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! # Provider diagnostics
+//!
+//! Every provider is checked by retrieving a record which is known to exist. The requests made
+//! while retrieving the record are recorded by a [`DiagnoseClient`], so that the latency and the
+//! status code of the endpoint can be reported along with whether or not the response could be
+//! parsed.
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use anyhow::bail;
+use ureq::http::{self, StatusCode, Uri};
+
+use crate::{
+    entry::EntryData,
+    error::ProviderError,
+    http::Client,
+    provider::{
+        INCOMPLETE_FIELD, REMOTE_PROVIDERS, RemoteResponse, get_remote_response, is_valid_provider,
+    },
+    record::RemoteId,
+};
+
+/// For each remote provider, an identifier which is known to have data, and the name of the
+/// endpoint which is used to retrieve it.
+const KNOWN_GOOD: [(&str, &str, &str); 10] = [
+    ("arxiv", "1212.1873", "arXiv API"),
+    ("doi", "10.4007/annals.2014.180.2.7", "Crossref API"),
+    ("gbooks", "zyTCAlFPjgYC", "Google Books API"),
+    ("isbn", "9781119942399", "Open Library API"),
+    ("jfm", "60.0017.02", "zbMATH Open API"),
+    ("mr", "3224722", "MathSciNet API"),
+    ("ol", "31159704M", "Open Library API"),
+    (
+        "url",
+        "https://annals.math.princeton.edu/2014/180-2/p07",
+        "Publisher landing page",
+    ),
+    ("zbmath", "06346461", "zbMATH Open API"),
+    ("zbl", "1337.28015", "zbMATH Open API"),
+];
+
+/// A request made while checking a provider.
+struct Request {
+    /// The status code of the response, or the error if no response was received.
+    status: Result<StatusCode, String>,
+    /// The time until the response was received.
+    elapsed: Duration,
+}
+
+/// A [`Client`] which records every request made by the underlying client.
+struct DiagnoseClient<'c, C> {
+    inner: &'c C,
+    requests: RefCell<Vec<Request>>,
+}
+
+impl<'c, C: Client> DiagnoseClient<'c, C> {
+    fn new(inner: &'c C) -> Self {
+        Self {
+            inner,
+            requests: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl<C: Client> Client for DiagnoseClient<'_, C> {
+    type Body = C::Body;
+
+    fn get<T>(&self, uri: T) -> Result<http::Response<Self::Body>, ureq::Error>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        let uri = Uri::try_from(uri).map_err(Into::<http::Error>::into)?;
+        let start = Instant::now();
+        let res = self.inner.get::<Uri>(uri);
+        let status = match &res {
+            Ok(response) => Ok(response.status()),
+            Err(err) => Err(err.to_string()),
+        };
+        self.requests.borrow_mut().push(Request {
+            status,
+            elapsed: start.elapsed(),
+        });
+        res
+    }
+
+    fn offline(&self) -> bool {
+        self.inner.offline()
+    }
+
+    fn batch_requests(&self) -> bool {
+        false
+    }
+}
+
+/// The outcome of checking a provider.
+enum Outcome {
+    /// The record was retrieved.
+    Ok,
+    /// The provider did not return data for the known-good identifier.
+    Null,
+    /// The endpoint could not be reached.
+    Unreachable(String),
+    /// The endpoint returned an unexpected status code.
+    BadStatus(String),
+    /// The response could not be parsed.
+    ParseFailure(String),
+    /// Network access is disabled.
+    Offline,
+}
+
+impl Outcome {
+    fn from_result(res: Result<RemoteResponse, ProviderError>) -> Self {
+        match res {
+            Ok(RemoteResponse::Data(data)) if data.contains_field(INCOMPLETE_FIELD) => {
+                Self::ParseFailure("only a partial record could be recovered".to_owned())
+            }
+            Ok(RemoteResponse::Data(_) | RemoteResponse::Reference(_)) => Self::Ok,
+            Ok(RemoteResponse::Null) => Self::Null,
+            Err(ProviderError::Offline(_)) => Self::Offline,
+            Err(err @ ProviderError::NetworkFailure(_)) => Self::Unreachable(err.to_string()),
+            Err(
+                err @ (ProviderError::UnexpectedStatusCode(_) | ProviderError::TemporaryFailure),
+            ) => Self::BadStatus(err.to_string()),
+            Err(ProviderError::UnexpectedResponseFormat(msg)) => Self::ParseFailure(msg),
+            Err(err) => Self::ParseFailure(err.to_string()),
+        }
+    }
+
+    /// A short description of the outcome.
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Null => "no data",
+            Self::Unreachable(_) => "unreachable",
+            Self::BadStatus(_) => "bad status",
+            Self::ParseFailure(_) => "parse failure",
+            Self::Offline => "offline",
+        }
+    }
+}
+
+/// The result of checking a single provider.
+pub struct Check {
+    provider: &'static str,
+    remote_id: RemoteId,
+    endpoint: &'static str,
+    outcome: Outcome,
+    requests: Vec<Request>,
+}
+
+impl Check {
+    /// Whether or not the provider is working as expected.
+    pub fn is_ok(&self) -> bool {
+        matches!(self.outcome, Outcome::Ok)
+    }
+
+    /// A message explaining how to resolve the failure, if the check failed.
+    fn message(&self) -> Option<String> {
+        let Self {
+            remote_id,
+            endpoint,
+            ..
+        } = self;
+        match &self.outcome {
+            Outcome::Ok => None,
+            Outcome::Null => Some(format!(
+                "{endpoint} returned no data for '{remote_id}', which is known to exist; the API may have changed, so please report this at https://github.com/autobib/autobib/issues"
+            )),
+            Outcome::Unreachable(err) => Some(format!(
+                "{endpoint} unreachable: {err}; check your network connection and the `[http]` section of the configuration"
+            )),
+            Outcome::BadStatus(err) => Some(format!(
+                "{endpoint} returned an error: {err}; the service may be down, so try again later"
+            )),
+            Outcome::ParseFailure(err) => Some(format!(
+                "{endpoint} returned a response which could not be parsed; this is a bug, so please report it at https://github.com/autobib/autobib/issues, including the error message:\n> {err}"
+            )),
+            Outcome::Offline => Some(format!(
+                "{endpoint} not checked since network access is disabled with `--offline`"
+            )),
+        }
+    }
+}
+
+/// Check the provided remote provider, or every remote provider if none is provided.
+pub fn diagnose<C: Client>(
+    client: &C,
+    provider: Option<&str>,
+) -> Result<Vec<Check>, anyhow::Error> {
+    if let Some(provider) = provider
+        && !REMOTE_PROVIDERS.contains(&provider)
+    {
+        if is_valid_provider(provider) {
+            bail!("Provider '{provider}' does not retrieve remote data");
+        } else {
+            bail!("Unknown provider '{provider}'");
+        }
+    }
+
+    Ok(KNOWN_GOOD
+        .iter()
+        .filter(|(name, _, _)| provider.is_none_or(|provider| provider == *name))
+        .map(|&(provider, sub_id, endpoint)| {
+            let remote_id = RemoteId::from_parts(provider, sub_id)
+                .expect("Known-good identifier should be valid");
+            let diagnose_client = DiagnoseClient::new(client);
+            let outcome = Outcome::from_result(get_remote_response(&diagnose_client, &remote_id));
+            Check {
+                provider,
+                remote_id,
+                endpoint,
+                outcome,
+                requests: diagnose_client.requests.into_inner(),
+            }
+        })
+        .collect())
+}
+
+/// Write the results of the checks, with a message for every check which failed.
+pub fn write_checks<W: Write>(writer: &mut W, checks: &[Check]) -> io::Result<()> {
+    let width = checks
+        .iter()
+        .map(|check| check.provider.len())
+        .max()
+        .unwrap_or(0);
+    for check in checks {
+        let latency: Duration = check.requests.iter().map(|request| request.elapsed).sum();
+        let status = match check.requests.last().map(|request| &request.status) {
+            Some(Ok(status)) => format!("HTTP {}", status.as_u16()),
+            Some(Err(_)) => "no response".to_owned(),
+            None => "no request".to_owned(),
+        };
+        writeln!(
+            writer,
+            "{:<width$}  {:<13}  {:>6} ms  {status}",
+            check.provider,
+            check.outcome.label(),
+            latency.as_millis(),
+        )?;
+        if let Some(message) = check.message() {
+            writeln!(writer, "  {message}")?;
+        }
+    }
+    Ok(())
+}
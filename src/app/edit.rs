@@ -1,24 +1,32 @@
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    collections::BTreeMap,
+    io::{self, IsTerminal, Write, stderr, stdout},
+    path::PathBuf,
+    str::FromStr,
+};
 
 use anyhow::Result;
+use itertools::{EitherOrBoth, Itertools};
 
-use super::OnConflict;
+use super::{OnConflict, conflicts::RecordConflict};
 
 use crate::{
     app::data_from_path,
+    config::{AutoAlias, FieldMergeRule},
     db::{
         Identifier,
         state::{IsEntry, RecordsInsert, State},
     },
     entry::{
-        ConflictResolved, Entry, EntryData, EntryEditCommand, EntryKey, MutableEntryData,
-        RawEntryData,
+        ConflictResolved, Entry, EntryData, EntryEditCommand, EntryKey, FieldValue,
+        MutableEntryData, RawEntryData,
     },
     error::MergeError,
     logger::{error, info, reraise, set_failed, suggest, warn},
     normalize::{Normalization, Normalize},
-    record::{Alias, RemoteId},
-    term::{Editor, EditorConfig, Input},
+    output::stdout_lock_wrap,
+    record::{Alias, RemoteId, add_auto_alias},
+    term::{Editor, EditorConfig, Input, WordDiff},
 };
 
 /// Given a candidate alias string, check if it is a valid alias, and if it is, try to add it as an
@@ -45,6 +53,9 @@ pub fn create_alias_if_valid(key: &str, row: &State<IsEntry>) -> Result<(), rusq
 ///
 /// If the row does not exist in the 'Records' table, this inserts a new row as the unique entry.
 /// If the row exists, this adds the new row as a child of the existing row.
+///
+/// If an [`AutoAlias`] is provided, an alias is also created automatically for the new row.
+#[allow(clippy::too_many_arguments)]
 pub fn insert<'conn, I>(
     missing: State<'conn, I>,
     from_bibtex: Option<PathBuf>,
@@ -53,6 +64,7 @@ pub fn insert<'conn, I>(
     normalization: &Normalization,
     edit: &EntryEditCommand,
     add_alias: Option<&Alias>,
+    auto_alias: Option<&AutoAlias>,
 ) -> anyhow::Result<()>
 where
     State<'conn, I>: RecordsInsert<'conn>,
@@ -82,6 +94,9 @@ where
             if key.as_ref() != remote_id.name() {
                 create_alias_if_valid(key.as_ref(), &row)?;
             }
+            if let Some(auto_alias) = auto_alias {
+                add_auto_alias(&row, auto_alias)?;
+            }
             row.commit()?;
         } else {
             missing.commit()?;
@@ -95,30 +110,198 @@ where
     {
         error!("Alias '{alias}' already exists and references a different record.");
     }
+    if let Some(auto_alias) = auto_alias {
+        add_auto_alias(&exists, auto_alias)?;
+    }
 
     exists.commit()?;
     Ok(())
 }
 
+/// Print the current and incoming values of a conflict, highlighting the words which differ.
+fn print_conflict(current: &str, incoming: &str) {
+    let styled = stderr().is_terminal();
+    let diff = WordDiff::new(current, incoming);
+    eprintln!("   Current value: {}", diff.current(styled));
+    eprintln!("  Incoming value: {}", diff.incoming(styled));
+}
+
+/// Print the changes between the current and the edited data of a record, without modifying the
+/// record.
+pub fn print_edit_report<D: EntryData, E: EntryData>(
+    canonical: &RemoteId,
+    current: &D,
+    edited: &E,
+) -> io::Result<()> {
+    let styled = stdout().is_terminal();
+    let mut lock = stdout_lock_wrap();
+
+    if current.entry_type() == edited.entry_type() && current.fields().eq(edited.fields()) {
+        return writeln!(lock, "{canonical}: unchanged");
+    }
+
+    writeln!(lock, "{canonical}:")?;
+    write_data_diff(&mut lock, current, edited, styled)
+}
+
+/// Write the entry type and the fields which differ between the current and the edited data,
+/// highlighting the words which differ if `styled` is set.
+pub fn write_data_diff<W: Write, D: EntryData, E: EntryData>(
+    writer: &mut W,
+    current: &D,
+    edited: &E,
+    styled: bool,
+) -> io::Result<()> {
+    if current.entry_type() != edited.entry_type() {
+        writeln!(writer, "  entry type")?;
+        write_changed(writer, current.entry_type(), edited.entry_type(), styled)?;
+    }
+
+    for pair in current
+        .fields()
+        .merge_join_by(edited.fields(), |(k1, _), (k2, _)| k1.cmp(k2))
+    {
+        match pair {
+            EitherOrBoth::Both((key, current_val), (_, edited_val)) => {
+                if current_val != edited_val {
+                    writeln!(writer, "  {key}")?;
+                    write_changed(writer, current_val, edited_val, styled)?;
+                }
+            }
+            EitherOrBoth::Left((key, val)) => {
+                writeln!(writer, "  {key} (deleted)")?;
+                writeln!(writer, "    - {val}")?;
+            }
+            EitherOrBoth::Right((key, val)) => {
+                writeln!(writer, "  {key} (added)")?;
+                writeln!(writer, "    + {val}")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write the current and edited value of a field, highlighting the words which differ.
+fn write_changed<W: Write>(
+    writer: &mut W,
+    current: &str,
+    edited: &str,
+    styled: bool,
+) -> io::Result<()> {
+    let diff = WordDiff::new(current, edited);
+    writeln!(writer, "    - {}", diff.current(styled))?;
+    writeln!(writer, "    + {}", diff.incoming(styled))
+}
+
+/// Resolve a conflict for the field `key` using the rule for the field in the `[merge]` section
+/// of the configuration, if there is one.
+fn resolve_by_rule(
+    rules: &BTreeMap<String, FieldMergeRule>,
+    key: &str,
+    current: &str,
+    incoming: &str,
+) -> Option<ConflictResolved> {
+    match rules.get(key)? {
+        FieldMergeRule::PreferCurrent => Some(ConflictResolved::Current),
+        FieldMergeRule::PreferIncoming => Some(ConflictResolved::Incoming),
+        FieldMergeRule::PreferLongest => {
+            if incoming.chars().count() > current.chars().count() {
+                Some(ConflictResolved::Incoming)
+            } else {
+                Some(ConflictResolved::Current)
+            }
+        }
+        FieldMergeRule::Concat => {
+            if current.contains(incoming) {
+                Some(ConflictResolved::Current)
+            } else {
+                match FieldValue::try_new(format!("{current}; {incoming}")) {
+                    Ok(value) => Some(ConflictResolved::New(value)),
+                    Err(err) => {
+                        warn!("Cannot concatenate the values of '{key}': {err}");
+                        None
+                    }
+                }
+            }
+        }
+    }
+}
+
 /// Merge an iterator of [`EntryData`] into existing data, using the merge rules as specified
 /// by the passed [`OnConflict`].
+///
+/// Conflicting values of a field which has a rule in `rules` are resolved using the rule, so that
+/// there is no prompt for the field. The other conflicts which are resolved without prompting are
+/// appended to `conflicts`.
 pub fn merge_record_data<'a, D: EntryData + 'a>(
     on_conflict: OnConflict,
+    rules: &BTreeMap<String, FieldMergeRule>,
     existing_record: &mut MutableEntryData,
     new_raw_data: impl IntoIterator<Item = &'a D>,
     id_display: impl std::fmt::Display,
+    conflicts: &mut Vec<RecordConflict>,
 ) -> Result<(), MergeError> {
     match on_conflict {
         OnConflict::PreferCurrent => {
             info!("Updating {id_display} with new data, skipping existing fields");
             for data in new_raw_data {
-                existing_record.merge_or_skip(data);
+                if existing_record.entry_type() != data.entry_type() {
+                    conflicts.push(RecordConflict::new(
+                        &id_display,
+                        None,
+                        existing_record.entry_type(),
+                        data.entry_type(),
+                        true,
+                    ));
+                }
+                existing_record.merge_with_callback(
+                    data,
+                    |_, _| ConflictResolved::Current,
+                    |key, current, incoming| {
+                        resolve_by_rule(rules, key.as_ref(), current.as_ref(), incoming.as_ref())
+                            .unwrap_or_else(|| {
+                                conflicts.push(RecordConflict::new(
+                                    &id_display,
+                                    Some(key.as_ref()),
+                                    current.as_ref(),
+                                    incoming.as_ref(),
+                                    true,
+                                ));
+                                ConflictResolved::Current
+                            })
+                    },
+                );
             }
         }
         OnConflict::PreferIncoming => {
             info!("Updating {id_display} with new data, overwriting existing fields");
             for data in new_raw_data {
-                existing_record.merge_or_overwrite(data);
+                if existing_record.entry_type() != data.entry_type() {
+                    conflicts.push(RecordConflict::new(
+                        &id_display,
+                        None,
+                        existing_record.entry_type(),
+                        data.entry_type(),
+                        false,
+                    ));
+                }
+                existing_record.merge_with_callback(
+                    data,
+                    |_, _| ConflictResolved::Incoming,
+                    |key, current, incoming| {
+                        resolve_by_rule(rules, key.as_ref(), current.as_ref(), incoming.as_ref())
+                            .unwrap_or_else(|| {
+                                conflicts.push(RecordConflict::new(
+                                    &id_display,
+                                    Some(key.as_ref()),
+                                    current.as_ref(),
+                                    incoming.as_ref(),
+                                    false,
+                                ));
+                                ConflictResolved::Incoming
+                            })
+                    },
+                );
             }
         }
         OnConflict::Prompt => {
@@ -130,8 +313,7 @@ pub fn merge_record_data<'a, D: EntryData + 'a>(
                     data,
                     |current, incoming| {
                         eprintln!("Conflict for the entry type:");
-                        eprintln!("   Current value: {current}");
-                        eprintln!("  Incoming value: {incoming}");
+                        print_conflict(current.as_ref(), incoming.as_ref());
                         let prompt = Input::new("Accept incoming value? [y]es / [N]o / [e]dit");
                         let choice = match prompt.input() {
                             Ok(r) => r,
@@ -168,9 +350,16 @@ pub fn merge_record_data<'a, D: EntryData + 'a>(
                         }
                     },
                     |key, current, incoming| {
+                        if let Some(resolved) = resolve_by_rule(
+                            rules,
+                            key.as_ref(),
+                            current.as_ref(),
+                            incoming.as_ref(),
+                        ) {
+                            return resolved;
+                        }
                         eprintln!("Conflict for the field '{key}':");
-                        eprintln!("   Current value: {current}");
-                        eprintln!("  Incoming value: {incoming}");
+                        print_conflict(current.as_ref(), incoming.as_ref());
                         let prompt = Input::new("Accept incoming value? [y]es / [N]o / [e]dit");
                         let choice = match prompt.input() {
                             Ok(r) => r,
@@ -0,0 +1,137 @@
+use std::{
+    fs::{create_dir_all, write},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::Result;
+
+use super::path::track_attachment;
+use crate::{
+    db::{
+        RecordDatabase,
+        state::{ExistsOrUnknown, RecordsInsert},
+    },
+    entry::{MutableEntryData, RawEntryData},
+    logger::{info, warn},
+    path_hash::PathHash,
+    record::{Alias, RemoteId},
+};
+
+/// Words used to construct synthetic titles.
+const TITLE_WORDS: [&str; 16] = [
+    "algebraic",
+    "analysis",
+    "bounded",
+    "categories",
+    "convex",
+    "dynamics",
+    "equations",
+    "finite",
+    "geometry",
+    "groups",
+    "invariants",
+    "manifolds",
+    "operators",
+    "random",
+    "spectral",
+    "theory",
+];
+
+/// Names used to construct synthetic authors.
+const NAMES: [&str; 8] = [
+    "Agarwal", "Brown", "Chen", "Dubois", "Eriksson", "Fischer", "García", "Hughes",
+];
+
+/// Entry types used for synthetic records.
+const ENTRY_TYPES: [&str; 4] = ["article", "book", "inproceedings", "misc"];
+
+/// The synthetic data for the record with index `n` at revision `rev`.
+fn synthetic_data(n: usize, rev: usize) -> Result<MutableEntryData> {
+    let mut data = MutableEntryData::try_new(ENTRY_TYPES[n % ENTRY_TYPES.len()].to_owned())?;
+
+    let title = (0..4)
+        .map(|i| TITLE_WORDS[(n / (i + 1) + 3 * i) % TITLE_WORDS.len()])
+        .collect::<Vec<_>>()
+        .join(" ");
+    data.check_and_insert("title".to_owned(), format!("On {title} {n}"))?;
+
+    let author = format!(
+        "{} and {}",
+        NAMES[n % NAMES.len()],
+        NAMES[(n / NAMES.len() + 1) % NAMES.len()]
+    );
+    data.check_and_insert("author".to_owned(), author)?;
+    data.check_and_insert("year".to_owned(), (1950 + n % 75).to_string())?;
+
+    if rev > 0 {
+        data.check_and_insert("note".to_owned(), format!("Revision {rev}"))?;
+    }
+
+    Ok(data)
+}
+
+/// Populate the database with `records` synthetic local records, each with `revisions` additional
+/// revisions and an alias of the same name. If `attachment_root` is provided, also create a small
+/// attachment for each record.
+///
+/// Records which already exist in the database are skipped. Returns the number of records which
+/// were inserted.
+pub fn generate(
+    record_db: &mut RecordDatabase,
+    records: usize,
+    revisions: usize,
+    attachment_root: Option<&Path>,
+) -> Result<usize> {
+    let mut inserted = 0;
+
+    for n in 0..records {
+        let alias = Alias::from_str(&format!("synthetic-{n}"))?;
+        let remote_id = RemoteId::local(&alias);
+
+        let mut row = match record_db.state_from_remote_id(&remote_id)?.delete_null()? {
+            ExistsOrUnknown::Entry(_, state) => {
+                state.commit()?;
+                continue;
+            }
+            ExistsOrUnknown::Deleted(_, state) => {
+                state.commit()?;
+                continue;
+            }
+            ExistsOrUnknown::Void(_, void) => void.insert(
+                &RawEntryData::from_entry_data(&synthetic_data(n, 0)?),
+                &remote_id,
+            )?,
+            ExistsOrUnknown::Unknown(missing) => missing.insert(
+                &RawEntryData::from_entry_data(&synthetic_data(n, 0)?),
+                &remote_id,
+            )?,
+        };
+
+        for rev in 1..=revisions {
+            row = row.modify(&RawEntryData::from_entry_data(&synthetic_data(n, rev)?))?;
+        }
+
+        if !row.add_alias(&alias)? {
+            warn!("Alias '{alias}' already exists and references a different record.");
+        }
+        row.commit()?;
+
+        if let Some(root) = attachment_root {
+            let mut dir = root.to_path_buf();
+            remote_id.extend_attachments_path(&mut dir);
+            create_dir_all(&dir)?;
+            let path = dir.join("synthetic.txt");
+            write(&path, format!("Attachment for '{remote_id}'\n"))?;
+            track_attachment(record_db, &remote_id, &dir, &path)?;
+        }
+
+        inserted += 1;
+    }
+
+    if inserted < records {
+        info!("Skipped {} records which already exist", records - inserted);
+    }
+
+    Ok(inserted)
+}
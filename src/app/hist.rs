@@ -1,39 +1,306 @@
+use std::{
+    convert::Infallible,
+    io::{self, Write},
+};
+
+use anyhow::bail;
+use chrono::{DateTime, Local};
+
+use super::{edit::write_data_diff, info::write_field_sources, source::glob_match};
 use crate::{
-    db::state::{InRecordsTable, IsArbitrary, RecordRowMoveResult, RedoError, UndoError},
-    logger::{LogDisplay, error, suggest},
+    config::AliasTransform,
+    db::{
+        FieldSource, Identifier, RecordDatabase, RunInfo, RunSummary, Tx, UndoRunResult,
+        state::{
+            ArbitraryData, InRecordsTable, IsArbitrary, RecordRow, RecordRowMoveResult, RedoError,
+            RemoteIdState, RevisionId, State, UndoError,
+        },
+    },
+    entry::RawEntryData,
+    error::FailureKind,
+    logger::{LogDisplay, error, info, suggest, warn},
+    record::{RecordId, RemoteId},
 };
 
-pub fn handle_undo_result<'conn, I: InRecordsTable, J>(
-    res: RecordRowMoveResult<'conn, I, J, UndoError>,
+/// Write the summary of a run as a table containing the canonical identifier, the action, and
+/// the revision of every change.
+pub fn write_run_summary<W: Write>(writer: &mut W, summary: &RunSummary) -> io::Result<()> {
+    let num_records = summary.num_records();
+    writeln!(
+        writer,
+        "Changed {num_records} {} with `autobib {}` on {} (session {}):",
+        if num_records == 1 {
+            "record"
+        } else {
+            "records"
+        },
+        summary.command,
+        summary.finished.format("%b %d %Y at %X%Z"),
+        summary.session,
+    )?;
+    let width = summary
+        .changes
+        .iter()
+        .map(|change| change.canonical.chars().count())
+        .max()
+        .unwrap_or(0);
+    for change in &summary.changes {
+        writeln!(
+            writer,
+            "  {:<width$}  {:<9}  {}",
+            change.canonical, change.action, change.revision
+        )?;
+    }
+    Ok(())
+}
+
+/// The entry data of a revision of the record, or an error if the revision does not belong to the
+/// record or does not contain entry data.
+fn revision_data(
+    canonical: &RemoteId,
+    rev: RevisionId,
+    row: Option<RecordRow<ArbitraryData>>,
+) -> anyhow::Result<RawEntryData> {
+    match row {
+        Some(row) if &row.canonical == canonical => match row.data {
+            ArbitraryData::Entry(data) => Ok(data),
+            ArbitraryData::Deleted(_) => {
+                bail!("Cannot compare {rev} of '{canonical}' since it is a deletion marker")
+            }
+            ArbitraryData::Void => bail!("Cannot compare {rev} of '{canonical}' since it is void"),
+        },
+        _ => bail!("Record '{canonical}' does not have {rev}"),
+    }
+}
+
+/// Write the changes to the entry type and the fields between two revisions of a record.
+///
+/// If no revisions are provided, the active revision is compared with its parent. If one revision
+/// is provided, it is compared with the active revision.
+pub fn write_revision_diff<W: Write>(
+    writer: &mut W,
+    state: State<'_, IsArbitrary>,
+    revisions: &[RevisionId],
+    styled: bool,
 ) -> anyhow::Result<()> {
-    match res {
-        RecordRowMoveResult::Updated(state) => {
-            state.log_opt()?;
-            state.commit()?;
+    let current = state.current()?;
+    let canonical = current.row.canonical.clone();
+    let (from, to) = match *revisions {
+        [] => match current.parent_rev_id() {
+            Some(parent) => (parent, current.rev_id()),
+            None => bail!("Record '{canonical}' does not have an earlier revision"),
+        },
+        [from] => (from, current.rev_id()),
+        [from, to] => (from, to),
+        _ => bail!("At most two revisions can be compared"),
+    };
+    drop(current);
+
+    let tx = state.into_tx();
+    let from_row = RecordRow::<ArbitraryData>::load(&tx, from)?;
+    let to_row = RecordRow::<ArbitraryData>::load(&tx, to)?;
+    let field_sources = FieldSource::load_revision(&tx, to)?;
+    tx.commit()?;
+
+    let from_data = revision_data(&canonical, from, from_row)?;
+    let to_data = revision_data(&canonical, to, to_row)?;
+    if from_data == to_data {
+        writeln!(writer, "{canonical}: {from} and {to} are identical")?;
+    } else {
+        writeln!(writer, "{canonical}: {from} to {to}")?;
+        write_data_diff(writer, &from_data, &to_data, styled)?;
+    }
+    if !field_sources.is_empty() {
+        writeln!(writer, "Field sources of {to}:")?;
+        write_field_sources(writer, &field_sources)?;
+    }
+    Ok(())
+}
+
+/// Write the sessions as a table containing the session id, the time, the number of changed
+/// records, and the command of every session.
+pub fn write_sessions<W: Write>(writer: &mut W, sessions: &[RunInfo]) -> io::Result<()> {
+    let width = sessions
+        .iter()
+        .map(|session| session.session.to_string().len())
+        .max()
+        .unwrap_or(0);
+    for session in sessions {
+        writeln!(
+            writer,
+            "{:>width$}  {}  {:>4} {:<7}  autobib {}",
+            session.session,
+            session.finished.format("%b %d %Y at %X%Z"),
+            session.records,
+            if session.records == 1 {
+                "record"
+            } else {
+                "records"
+            },
+            session.command,
+        )?;
+    }
+    Ok(())
+}
+
+/// Undo every change made during a session, reporting the outcome.
+pub fn undo_session(record_db: &mut RecordDatabase, session: i64) -> anyhow::Result<()> {
+    match record_db.undo_run(session)? {
+        UndoRunResult::Undone(num_records) => {
+            info!("Undid the changes to {num_records} record(s) made during session {session}");
         }
-        RecordRowMoveResult::Unchanged(state, err) => {
-            state.commit()?;
-            match err {
-                UndoError::ParentEntry => {
-                    error!("Parent is not deleted");
-                }
-                UndoError::ParentDeleted => {
-                    error!("Cannot undo into a deleted state with `autobib hist undo`",);
-                    suggest!("Undo into a deleted state with `autobib hist undo --delete`");
-                }
-                UndoError::ParentVoidExists => {
-                    error!("Cannot void record with `autobib hist undo`",);
-                    suggest!("Void records with `autobib hist void`");
-                }
-                UndoError::ParentVoidMissing => {
-                    error!("Cannot void record with `autobib hist undo`",);
-                    suggest!("Void records with `autobib hist void`");
+        UndoRunResult::Undefined => {
+            error!("No session with id {session}");
+            suggest!("List the sessions with `autobib hist show --sessions`");
+        }
+        UndoRunResult::ChangedAfterRun(remote_id) => {
+            error!(
+                "Record '{remote_id}' was changed after session {session}; no records were changed"
+            );
+        }
+        UndoRunResult::PreviousUnavailable(remote_id) => {
+            error!(
+                "The revision of '{remote_id}' before session {session} is not available; no records were changed"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Determine the canonical identifiers of the records selected by the identifiers.
+///
+/// An identifier which contains `*` or `?` is a glob pattern, which selects every record, including
+/// deleted records, whose canonical identifier matches the pattern. Returns the canonical
+/// identifiers, sorted and without duplicates, and whether every identifier could be resolved.
+pub fn select_records<A: AliasTransform>(
+    record_db: &mut RecordDatabase,
+    identifiers: Vec<RecordId>,
+    alias_transform: &A,
+) -> anyhow::Result<(Vec<RemoteId>, bool)> {
+    let mut selected = Vec::new();
+    let mut resolved = true;
+
+    for identifier in identifiers {
+        let pattern = identifier.name();
+        if pattern.contains(['*', '?']) {
+            let num_selected = selected.len();
+            let snapshot = record_db.snapshot()?;
+            for deleted in [false, true] {
+                snapshot.map_canonical_identifiers(deleted, |remote_id| {
+                    if glob_match(pattern, remote_id.name()) {
+                        selected.push(RemoteId::from_string_unchecked(remote_id.name().to_owned()));
+                    }
+                    Ok::<_, Infallible>(())
+                })?;
+            }
+            snapshot.commit()?;
+            if selected.len() == num_selected {
+                warn!("No records match the pattern '{pattern}'");
+            }
+        } else {
+            match record_db
+                .state_from_record_id(identifier, alias_transform)?
+                .require_record()?
+            {
+                Some((_, disambiguated)) => {
+                    let (row, state) = disambiguated.forget();
+                    state.commit()?;
+                    selected.push(row.canonical);
                 }
+                None => resolved = false,
             }
         }
     }
 
-    Ok(())
+    selected.sort();
+    selected.dedup();
+    Ok((selected, resolved))
+}
+
+/// Report the reason why the most recent change of a record could not be undone.
+fn report_undo_error(remote_id: &RemoteId, err: UndoError) {
+    match err {
+        UndoError::ParentEntry => {
+            error!("Parent of '{remote_id}' is not deleted");
+        }
+        UndoError::ParentDeleted => {
+            error!("Cannot undo '{remote_id}' into a deleted state with `autobib hist undo`");
+            suggest!("Undo into a deleted state with `autobib hist undo --delete`");
+        }
+        UndoError::ParentVoidExists | UndoError::ParentVoidMissing => {
+            error!("Cannot void record '{remote_id}' with `autobib hist undo`");
+            suggest!("Void records with `autobib hist void`");
+        }
+    }
+}
+
+/// Undo the most recent change of the record as part of the transaction, and return the
+/// transaction along with whether the change was undone.
+///
+/// If `since` is set and the active revision of the record was modified before `since`, the
+/// record is skipped, which counts as success.
+pub fn undo_in_tx<'conn>(
+    tx: Tx<'conn>,
+    remote_id: &RemoteId,
+    delete: bool,
+    since: Option<DateTime<Local>>,
+) -> anyhow::Result<(Tx<'conn>, bool)> {
+    fn finish<'conn, I: InRecordsTable, J>(
+        remote_id: &RemoteId,
+        res: RecordRowMoveResult<'conn, I, J, UndoError>,
+    ) -> anyhow::Result<(Tx<'conn>, bool)> {
+        match res {
+            RecordRowMoveResult::Updated(state) => {
+                state.log_opt()?;
+                Ok((state.into_tx(), true))
+            }
+            RecordRowMoveResult::Unchanged(state, err) => {
+                report_undo_error(remote_id, err);
+                Ok((state.into_tx(), false))
+            }
+        }
+    }
+
+    let is_skipped = |modified: DateTime<Local>| {
+        let skipped = since.is_some_and(|since| modified < since);
+        if skipped {
+            info!("Skipping '{remote_id}' since it was last modified before the provided time");
+        }
+        skipped
+    };
+
+    match RemoteIdState::determine(tx, remote_id)? {
+        RemoteIdState::Entry(row, state) if is_skipped(row.modified) => Ok((state.into_tx(), true)),
+        RemoteIdState::Deleted(row, state) if is_skipped(row.modified) => {
+            Ok((state.into_tx(), true))
+        }
+        RemoteIdState::Entry(_, state) => {
+            if delete {
+                finish(remote_id, state.undo_delete()?)
+            } else {
+                finish(remote_id, state.undo()?)
+            }
+        }
+        RemoteIdState::Deleted(_, state) => {
+            if delete {
+                finish(remote_id, state.undo_delete()?)
+            } else {
+                finish(remote_id, state.undo()?)
+            }
+        }
+        RemoteIdState::Void(_, state) => {
+            error!("Nothing to undo for '{remote_id}'!");
+            Ok((state.into_tx(), false))
+        }
+        RemoteIdState::Null(state) => {
+            error!(kind = FailureKind::NullRecord; "Null remote id: {remote_id}");
+            Ok((state.into_tx(), false))
+        }
+        RemoteIdState::Unknown(state) => {
+            error!("Record not in database: {remote_id}");
+            Ok((state.into_tx(), false))
+        }
+    }
 }
 
 pub fn handle_redo_result<'conn, I>(
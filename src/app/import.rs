@@ -1,28 +1,37 @@
 use std::{
+    collections::{BTreeMap, HashMap, HashSet},
     fs, io,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
 };
 
 use anyhow::anyhow;
+use walkdir::WalkDir;
 
+use super::path::{attachment_metadata, track_attachment};
 use crate::{
     Identifier, RawEntryData,
-    app::{cli::OnConflict, edit::merge_record_data},
-    config::Config,
+    app::{cli::OnConflict, conflicts::RecordConflict, edit::merge_record_data},
+    config::{AutoAlias, Config, FieldMergeRule},
     db::{
-        RecordDatabase,
-        state::{IsEntry, IsMissing, IsVoid, RemoteIdState, State},
+        AttachmentRow, RecordDatabase,
+        state::{IsEntry, IsMissing, IsVoid, RecordIdState, RemoteIdState, State},
+    },
+    entry::{
+        Entry, MutableEntryData, Relation, changed_fields, entries_from_bibtex, macro_definitions,
     },
-    entry::{Entry, MutableEntryData, entries_from_bibtex},
     error::{self, RecordError},
     http::Client,
+    interrupt::interrupted,
     logger::{error, info, set_failed, warn},
     normalize::{Normalization, Normalize},
     path_hash::PathHash,
+    pdf::identifiers_from_pdf,
+    progress::Progress,
     provider::{RemoteIdCandidate, determine_remote_id_candidates, is_canonical},
     record::{
         Alias, MappedAliasOrRemoteId, MappedKey, RecordId, RecursiveRemoteResponse, RemoteId,
-        get_remote_response_recursive,
+        add_auto_alias, get_record_row, get_remote_response_recursive,
     },
 };
 
@@ -35,9 +44,20 @@ pub struct ImportConfig {
     pub no_alias: bool,
     pub include_files: bool,
     pub file_sep: Option<String>,
+    pub keep_relations: bool,
+    /// The number of entries which are imported in a single transaction.
+    pub batch_size: NonZeroUsize,
 }
 
 /// Import records from the provided buffer.
+///
+/// The abbreviations defined by `@string` entries are expanded in the imported entries, and are
+/// also stored in the database so that they can be written as a preamble of generated
+/// bibliographies.
+///
+/// If the import was interrupted with `Ctrl-C`, the remaining entries are written to `failed`
+/// without importing them, so that they can be imported again. The conflicts which are resolved
+/// without prompting when updating existing records are appended to `conflicts`.
 #[allow(clippy::too_many_arguments)]
 #[inline]
 pub fn from_buffer<F, C, W>(
@@ -49,6 +69,7 @@ pub fn from_buffer<F, C, W>(
     attachment_root: &Path,
     bibfile: impl std::fmt::Display,
     failed: &mut W,
+    conflicts: &mut Vec<RecordConflict>,
 ) -> Result<(), anyhow::Error>
 where
     F: FnOnce() -> Vec<(regex::Regex, String)>,
@@ -60,42 +81,412 @@ where
     } else {
         None
     };
-    // let mut stdout = stdout_lock_wrap();
+    let macros = macro_definitions(scratch);
+    if !macros.is_empty() {
+        info!(
+            "Storing {} abbreviations defined in '{bibfile}'",
+            macros.len()
+        );
+        record_db.insert_macros(&macros)?;
+    }
+
+    // the entries are read in advance, since the parent of an entry can appear later in the file
+    let mut entries = Vec::new();
     for res in entries_from_bibtex(scratch) {
-        if let Some(p) = attachment_root_buf.as_mut() {
-            p.clear();
-            p.push(attachment_root);
-        };
         match res {
-            Ok(entry) => match import_entry(
+            Ok(entry) => entries.push(entry),
+            Err(err) => {
+                error!("Parse error for file '{bibfile}': {err}");
+            }
+        }
+    }
+    let parents = resolve_parents(&mut entries, record_db, config)?;
+
+    // the canonical identifier of each entry which was imported
+    let mut canonicals: Vec<Option<RemoteId>> = Vec::with_capacity(entries.len());
+    let num_entries = entries.len();
+
+    // the entries are imported in batches, each of which is committed as a single transaction;
+    // the current batch is also committed if the import stops with an error, so that the
+    // entries which were already imported are kept
+    let import_entries = || -> Result<(), anyhow::Error> {
+        for (idx, entry) in Progress::new("Importing", num_entries)
+            .iter(entries)
+            .enumerate()
+        {
+            if idx % import_config.batch_size.get() == 0 {
+                record_db.commit_batch()?;
+                record_db.begin_batch()?;
+            }
+
+            if interrupted() {
+                writeln!(failed, "% Not imported since the import was interrupted")?;
+                writeln!(failed, "{entry}")?;
+                set_failed();
+                canonicals.push(None);
+                continue;
+            }
+
+            if entry
+                .entry_type()
+                .eq_ignore_ascii_case(Relation::Xdata.as_str())
+            {
+                info!("Skipping '@xdata' entry '{}'", entry.key());
+                canonicals.push(None);
+                continue;
+            }
+
+            if let Some(p) = attachment_root_buf.as_mut() {
+                p.clear();
+                p.push(attachment_root);
+            };
+            // the entry is kept so that it can be written to `failed` if the import fails, in
+            // which case only the changes made while importing the entry are rolled back
+            let contents = entry.to_string();
+            let key = entry.key().to_string();
+            match import_entry(
                 entry,
                 import_config,
                 record_db,
                 client,
                 config,
                 attachment_root_buf.as_mut(),
-            )? {
-                ImportOutcome::Success => {}
-                ImportOutcome::Failure(error, entry) => {
+                conflicts,
+            ) {
+                Ok(ImportOutcome::Success(canonical)) => canonicals.push(Some(canonical)),
+                Ok(ImportOutcome::Failure(error, entry)) => {
                     writeln!(failed, "% {error}")?;
                     writeln!(failed, "{entry}")?;
                     set_failed();
+                    canonicals.push(None);
+                }
+                Err(error) => {
+                    error!("Failed to import '{key}': {error}");
+                    writeln!(failed, "% {error}")?;
+                    writeln!(failed, "{contents}")?;
+                    canonicals.push(None);
+                }
+            }
+        }
+        Ok(())
+    };
+    let res = import_entries();
+    record_db.commit_batch()?;
+    res?;
+
+    if import_config.keep_relations {
+        for (canonical, entry_parents) in canonicals.iter().zip(parents) {
+            let Some(canonical) = canonical else {
+                continue;
+            };
+            for (relation, parent) in entry_parents {
+                let parent = match parent {
+                    Parent::File(idx) => canonicals[idx].clone(),
+                    Parent::Database(parent) => Some(parent),
+                };
+                if let Some(parent) = parent {
+                    record_db.insert_relation(canonical, &parent, relation.as_str())?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Where the parent of an entry was found.
+enum Parent {
+    /// The entry with the provided index in the imported file.
+    File(usize),
+    /// The record with the provided canonical identifier in the database.
+    Database(RemoteId),
+}
+
+/// Look up the canonical identifier and the data of the record with the provided key.
+fn lookup_parent<F>(
+    record_db: &mut RecordDatabase,
+    key: &str,
+    config: &Config<F>,
+) -> Result<Option<(RemoteId, RawEntryData)>, rusqlite::Error>
+where
+    F: FnOnce() -> Vec<(regex::Regex, String)>,
+{
+    match record_db.state_from_record_id(RecordId::from(key), &config.alias_transform)? {
+        RecordIdState::Entry(_, row, state) => {
+            state.commit()?;
+            Ok(Some((row.canonical, row.data)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Copy the fields of the parents referenced by the `crossref` and `xdata` fields of the entries,
+/// and remove the references which could be resolved. The parent is the entry in the file with
+/// the same key, ignoring case, or otherwise the record in the database. Returns the parents of
+/// each entry.
+fn resolve_parents<F>(
+    entries: &mut [Entry<MutableEntryData>],
+    record_db: &mut RecordDatabase,
+    config: &Config<F>,
+) -> Result<Vec<Vec<(Relation, Parent)>>, rusqlite::Error>
+where
+    F: FnOnce() -> Vec<(regex::Regex, String)>,
+{
+    let index: HashMap<String, usize> = entries
+        .iter()
+        .enumerate()
+        .map(|(idx, entry)| (entry.key().as_ref().to_lowercase(), idx))
+        .collect();
+    let parent_in_file = |idx: usize, key: &str| {
+        index
+            .get(&key.to_lowercase())
+            .copied()
+            .filter(|parent| *parent != idx)
+    };
+
+    let mut parents: Vec<Vec<(Relation, Parent)>> = entries.iter().map(|_| Vec::new()).collect();
+    let mut pending: Vec<usize> = (0..entries.len())
+        .filter(|idx| !entries[*idx].record_data.parent_keys().is_empty())
+        .collect();
+
+    while !pending.is_empty() {
+        let mut remaining = Vec::new();
+        for &idx in &pending {
+            let keys = entries[idx].record_data.parent_keys();
+            // parents in the file are resolved first, so that fields are inherited transitively
+            if keys.iter().any(|(_, key)| {
+                parent_in_file(idx, key).is_some_and(|parent| pending.contains(&parent))
+            }) {
+                remaining.push(idx);
+                continue;
+            }
+
+            let mut missing = Vec::new();
+            for (relation, key) in keys {
+                let parent = match parent_in_file(idx, &key) {
+                    Some(parent) => {
+                        let data = MutableEntryData::from_entry_data(&entries[parent].record_data);
+                        entries[idx].record_data.inherit(&data, relation);
+                        Parent::File(parent)
+                    }
+                    None => match lookup_parent(record_db, &key, config)? {
+                        Some((canonical, data)) => {
+                            entries[idx].record_data.inherit(&data, relation);
+                            Parent::Database(canonical)
+                        }
+                        None => {
+                            warn!(
+                                "Could not find the parent '{key}' of entry '{}'",
+                                entries[idx].key()
+                            );
+                            missing.push(relation);
+                            continue;
+                        }
+                    },
+                };
+                parents[idx].push((relation, parent));
+            }
+
+            for relation in [Relation::Crossref, Relation::Xdata] {
+                if !missing.contains(&relation) {
+                    entries[idx].record_data.remove(relation.as_str());
                 }
-            },
+            }
+        }
+
+        if remaining.len() == pending.len() {
+            for idx in remaining {
+                warn!(
+                    "Could not resolve the parents of entry '{}' since they refer to each other",
+                    entries[idx].key()
+                );
+            }
+            break;
+        }
+        pending = remaining;
+    }
+
+    Ok(parents)
+}
+
+/// Import records from the PDF files in the provided directory, which is searched recursively.
+///
+/// For each file, the DOI or arXiv identifier of the article is determined from the contents of
+/// the file, the corresponding record is retrieved, and the file is attached to the record. Files
+/// which are already attached to the record are skipped, so that the import can be repeated as
+/// new files are added to the directory. The files for which no record could be determined are
+/// printed along with error messages.
+pub fn from_pdf_dir<F, C, W>(
+    dir: &Path,
+    record_db: &mut RecordDatabase,
+    client: &C,
+    config: &Config<F>,
+    attachment_root: &Path,
+    failed: &mut W,
+) -> Result<(), anyhow::Error>
+where
+    F: FnOnce() -> Vec<(regex::Regex, String)>,
+    C: Client,
+    W: io::Write + ?Sized,
+{
+    for res in WalkDir::new(dir).sort_by_file_name() {
+        let dir_entry = match res {
+            Ok(dir_entry) => dir_entry,
             Err(err) => {
-                error!("Parse error for file '{bibfile}': {err}");
+                error!("Failed to read directory entry: {err}");
+                continue;
+            }
+        };
+        let path = dir_entry.path();
+        if !dir_entry.file_type().is_file()
+            || !path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+        {
+            continue;
+        }
+
+        let contents = match fs::read(path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                error!(
+                    "Failed to read contents of file '{}': {err}",
+                    path.display()
+                );
+                continue;
+            }
+        };
+
+        match import_pdf(path, &contents, record_db, client, config, attachment_root) {
+            Ok(_) => {}
+            Err(err) => {
+                writeln!(failed, "% {err}")?;
+                writeln!(failed, "{}", path.display())?;
+                set_failed();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Import records from the provided PDF files.
+///
+/// For each file, the record is determined and the file is attached in the same way as
+/// [`from_pdf_dir`], and the canonical identifier of the record is written to `out`. Files for
+/// which no record could be determined are reported as errors.
+pub fn from_pdf_files<F, C, W>(
+    files: &[PathBuf],
+    record_db: &mut RecordDatabase,
+    client: &C,
+    config: &Config<F>,
+    attachment_root: &Path,
+    out: &mut W,
+) -> Result<(), anyhow::Error>
+where
+    F: FnOnce() -> Vec<(regex::Regex, String)>,
+    C: Client,
+    W: io::Write + ?Sized,
+{
+    for path in files {
+        let contents = match fs::read(path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                error!(
+                    "Failed to read contents of file '{}': {err}",
+                    path.display()
+                );
+                continue;
             }
+        };
+
+        match import_pdf(path, &contents, record_db, client, config, attachment_root) {
+            Ok(canonical) => writeln!(out, "{canonical}")?,
+            Err(err) => error!("Failed to import '{}': {err}", path.display()),
         }
     }
 
     Ok(())
 }
 
+/// Determine the record for a single PDF file, and attach the file to the record. Returns the
+/// canonical identifier of the record.
+fn import_pdf<F, C>(
+    path: &Path,
+    contents: &[u8],
+    record_db: &mut RecordDatabase,
+    client: &C,
+    config: &Config<F>,
+    attachment_root: &Path,
+) -> Result<RemoteId, anyhow::Error>
+where
+    F: FnOnce() -> Vec<(regex::Regex, String)>,
+    C: Client,
+{
+    // only keep the most frequent candidate for each provider, and then try the providers in
+    // order of preference
+    let mut candidates = identifiers_from_pdf(contents);
+    let mut providers = HashSet::new();
+    candidates.retain(|id| providers.insert(id.provider().to_owned()));
+    candidates.sort_by_key(|id| std::cmp::Reverse(config.score_id(id)));
+
+    if candidates.is_empty() {
+        anyhow::bail!("Could not determine an identifier from the file contents");
+    }
+
+    let mut canonical = None;
+    for candidate in candidates {
+        let candidate = candidate.forget();
+        info!("Retrieving record for candidate '{candidate}'");
+        match get_record_row(record_db, candidate.clone(), client, config)
+            .map_err(anyhow::Error::from)
+            .and_then(|response| response.exists_or_commit_null("Cannot attach file to"))
+        {
+            Ok((record, row)) => {
+                row.commit()?;
+                canonical = Some(record.canonical);
+                break;
+            }
+            Err(err) => warn!(
+                "Skipping candidate '{candidate}' for '{}': {err}",
+                path.display()
+            ),
+        }
+    }
+    let Some(canonical) = canonical else {
+        anyhow::bail!("Could not retrieve a record for any identifier in the file contents");
+    };
+
+    let Some(file_name) = path.file_name() else {
+        anyhow::bail!("Cannot import filename containing relative path");
+    };
+    let mut attachment_dir = attachment_root.to_path_buf();
+    canonical.extend_attachments_path(&mut attachment_dir);
+    fs::create_dir_all(&attachment_dir)?;
+    let target = attachment_dir.join(file_name);
+
+    match fs::File::create_new(&target) {
+        Ok(mut target_file) => {
+            info!("Attaching file '{}' to '{canonical}'", path.display());
+            io::Write::write_all(&mut target_file, contents)?;
+            track_attachment(record_db, &canonical, &attachment_dir, &target)?;
+        }
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            info!(
+                "Skipping file '{}': already attached to '{canonical}'",
+                path.display()
+            );
+        }
+        Err(err) => return Err(err.into()),
+    }
+    Ok(canonical)
+}
+
 /// The outcome of attempting to import the given entry.
 #[must_use]
 enum ImportOutcome {
-    /// The import was successful.
-    Success,
+    /// The import was successful, with the canonical identifier of the record.
+    Success(RemoteId),
     /// The import failed with an error and with the provided entry.
     Failure(anyhow::Error, Entry<MutableEntryData>),
 }
@@ -109,6 +500,7 @@ fn import_entry<F, C>(
     client: &C,
     config: &Config<F>,
     attachment_root: Option<&mut PathBuf>,
+    conflicts: &mut Vec<RecordConflict>,
 ) -> Result<ImportOutcome, anyhow::Error>
 where
     F: FnOnce() -> Vec<(regex::Regex, String)>,
@@ -119,14 +511,28 @@ where
         entry,
         import_config,
         &config.on_insert,
+        &config.merge,
+        config.auto_alias.as_ref(),
         attachment_root,
+        conflicts,
         |entry, record_db| {
             let determined = determine_key::<F>(entry, config);
 
             // it is more convenient to do this first since we want to perform
             // the database lookup using the canonical id if possible
             let determined = if import_config.resolve {
-                match determined.resolve_reference(client) {
+                // the batch is committed before accessing the network, so that other processes
+                // can write to the database while waiting for the response
+                let resolved =
+                    if matches!(determined, DeterminedKey::Reference(..)) && record_db.in_batch() {
+                        record_db.commit_batch()?;
+                        let resolved = determined.resolve_reference(client);
+                        record_db.begin_batch()?;
+                        resolved
+                    } else {
+                        determined.resolve_reference(client)
+                    };
+                match resolved {
                     Ok(d) => d,
                     Err(err) => return Ok(ImportAction::Fail(err)),
                 }
@@ -283,53 +689,69 @@ fn create_alias_and_commit(
     Ok(())
 }
 
+/// Copy the file into the attachment directory, returning the metadata of the attachment if the
+/// file was copied.
 fn import_file(
     source_path: &Path,
     target_path: &mut PathBuf,
     canonical: &RemoteId,
-) -> Result<(), anyhow::Error> {
+) -> Result<Option<AttachmentRow>, anyhow::Error> {
     canonical.extend_attachments_path(target_path);
     match source_path.file_name() {
         None => anyhow::bail!("Cannot import filename containing relative path"),
         Some(file_name) => {
             fs::create_dir_all(&target_path)?;
+            let attachment_dir = target_path.clone();
             target_path.push(file_name);
             // FIXME: this is a TOCTOU error
             if !target_path.exists() {
-                fs::copy(source_path, target_path)?;
+                fs::copy(source_path, &target_path)?;
+                return Ok(Some(attachment_metadata(
+                    canonical,
+                    &attachment_dir,
+                    target_path,
+                )?));
             }
         }
     }
-    Ok(())
+    Ok(None)
 }
 
+/// Normalize the entry data, and import the files in the `file` field if `include_files` is
+/// set. Returns the metadata of the imported attachments.
 fn normalize_data(
     entry: &mut Entry<MutableEntryData>,
     nl: &Normalization,
     include_files: Option<&mut PathBuf>,
     file_sep: &Option<String>,
     canonical: &RemoteId,
-) -> Result<(), anyhow::Error> {
+) -> Result<Vec<AttachmentRow>, anyhow::Error> {
     entry.record_data.normalize(nl);
+    let mut attachments = Vec::new();
     if let Some(target_path) = include_files
         && let Some(path) = entry.record_data.remove("file")
     {
         let path_str = path.as_ref();
         if let Some(sep) = file_sep {
             for component in path_str.split(sep) {
-                if let Err(err) = import_file(component.as_ref(), target_path, canonical) {
-                    anyhow::bail!("Failed to import file '{component}': {err}");
+                match import_file(component.as_ref(), target_path, canonical) {
+                    Ok(attachment) => attachments.extend(attachment),
+                    Err(err) => anyhow::bail!("Failed to import file '{component}': {err}"),
                 }
             }
-        } else if let Err(err) = import_file(path_str.as_ref(), target_path, canonical) {
-            anyhow::bail!("Failed to import file '{path}': {err}");
+        } else {
+            match import_file(path_str.as_ref(), target_path, canonical) {
+                Ok(attachment) => attachments.extend(attachment),
+                Err(err) => anyhow::bail!("Failed to import file '{path}': {err}"),
+            }
         }
     }
-    Ok(())
+    Ok(attachments)
 }
 
 /// The actual import implementation, which is generic over the `determine_action` closure which
 /// encodes the process of converting an entry into a relevant [`ImportAction`].
+#[allow(clippy::too_many_arguments)]
 #[inline]
 fn import_entry_impl<F>(
     record_db: &mut RecordDatabase,
@@ -337,7 +759,10 @@ fn import_entry_impl<F>(
     import_config: &ImportConfig,
     // no_alias: bool,
     nl: &Normalization,
+    merge_rules: &BTreeMap<String, FieldMergeRule>,
+    auto_alias: Option<&AutoAlias>,
     attachment_root: Option<&mut PathBuf>,
+    conflicts: &mut Vec<RecordConflict>,
     mut determine_action: F,
 ) -> Result<ImportOutcome, anyhow::Error>
 where
@@ -349,29 +774,40 @@ where
     match determine_action(&entry, record_db)? {
         ImportAction::Update(row, update_mode, remote_id, maybe_alias) => {
             if let Some(on_conflict) = update_mode {
-                if let Err(err) = normalize_data(
+                let attachments = match normalize_data(
                     &mut entry,
                     nl,
                     attachment_root,
                     &import_config.file_sep,
                     &remote_id,
                 ) {
-                    return Ok(ImportOutcome::Failure(err, entry));
-                }
+                    Ok(attachments) => attachments,
+                    Err(err) => return Ok(ImportOutcome::Failure(err, entry)),
+                };
 
                 let current_data = row.get_data()?.data;
                 let mut existing_record = MutableEntryData::from_entry_data(&current_data);
                 merge_record_data(
                     on_conflict,
+                    merge_rules,
                     &mut existing_record,
                     std::iter::once(entry.data()),
                     &remote_id,
+                    conflicts,
                 )?;
 
                 let new_data = RawEntryData::from_entry_data(&existing_record);
+                let fields = changed_fields(&current_data, &new_data);
 
                 info!("Updating data for record with identifier '{remote_id}'");
                 let new_row = row.modify(&new_data)?;
+                new_row.set_field_sources(
+                    fields.iter().map(String::as_str),
+                    &format!("imported entry {}", entry.key()),
+                )?;
+                for attachment in &attachments {
+                    new_row.insert_attachment(attachment)?;
+                }
 
                 create_alias_and_commit(
                     new_row,
@@ -382,39 +818,50 @@ where
             } else {
                 info!("Skipping identifier '{remote_id}': already present in database");
             }
-            Ok(ImportOutcome::Success)
+            Ok(ImportOutcome::Success(remote_id))
         }
         ImportAction::Insert(missing, canonical, maybe_alias) => {
-            if let Err(err) = normalize_data(
+            let attachments = match normalize_data(
                 &mut entry,
                 nl,
                 attachment_root,
                 &import_config.file_sep,
                 &canonical,
             ) {
-                return Ok(ImportOutcome::Failure(err, entry));
-            }
+                Ok(attachments) => attachments,
+                Err(err) => return Ok(ImportOutcome::Failure(err, entry)),
+            };
 
             info!("Inserting new record with identifier '{canonical}'");
             let row = missing.insert_entry_data(&entry.record_data, &canonical)?;
+            for attachment in &attachments {
+                row.insert_attachment(attachment)?;
+            }
+            if let Some(auto_alias) = auto_alias {
+                add_auto_alias(&row, auto_alias)?;
+            }
             create_alias_and_commit(row, canonical.name(), import_config.no_alias, maybe_alias)?;
-            Ok(ImportOutcome::Success)
+            Ok(ImportOutcome::Success(canonical))
         }
         ImportAction::Revive(void, remote_id, maybe_alias) => {
-            if let Err(err) = normalize_data(
+            let attachments = match normalize_data(
                 &mut entry,
                 nl,
                 attachment_root,
                 &import_config.file_sep,
                 &remote_id,
             ) {
-                return Ok(ImportOutcome::Failure(err, entry));
-            }
+                Ok(attachments) => attachments,
+                Err(err) => return Ok(ImportOutcome::Failure(err, entry)),
+            };
 
             info!("Re-inserting record with canonical id '{remote_id}'");
             let row = void.reinsert(&RawEntryData::from_entry_data(&entry.record_data))?;
+            for attachment in &attachments {
+                row.insert_attachment(attachment)?;
+            }
             create_alias_and_commit(row, remote_id.name(), import_config.no_alias, maybe_alias)?;
-            Ok(ImportOutcome::Success)
+            Ok(ImportOutcome::Success(remote_id))
         }
         ImportAction::Fail(prompt) => Ok(ImportOutcome::Failure(prompt, entry)),
     }
@@ -438,7 +885,7 @@ impl DeterminedKey {
         if let Self::Reference(mkr, _, maybe_alias) = self {
             let MappedKey { mapped, original } = mkr;
             match get_remote_response_recursive(mapped, client)? {
-                RecursiveRemoteResponse::Exists(_, remote_id) => Ok(Self::Canonical(
+                RecursiveRemoteResponse::Exists(_, remote_id, _) => Ok(Self::Canonical(
                     MappedKey {
                         mapped: remote_id,
                         original,
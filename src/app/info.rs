@@ -1,15 +1,98 @@
 use std::io::Write;
 
+use chrono::{DateTime, Local};
 use itertools::Itertools;
+use serde::Serialize;
 use serde_bibtex::token::is_entry_key;
 
 use crate::{
     app::cli::InfoReportType,
-    db::state::{InRecordsTable, RecordRow, State},
-    logger::error,
-    output::{StdoutWriter, owriteln, stdout_lock_wrap},
+    db::{
+        FieldSource, RelatedRecord, RevisionProvenance,
+        state::{InRecordsTable, RecordRow, RecordRowDisplay, State},
+    },
+    logger::{error, info},
+    output::{StdoutWriter, owrite_json, owriteln, stdout_lock_wrap},
+    provider::record_url,
 };
 
+/// An alias which refers to another alias.
+#[derive(Serialize)]
+struct LinkedAlias {
+    name: String,
+    target: String,
+}
+
+/// The JSON output of [`database_report_json`].
+#[derive(Serialize)]
+struct InfoReport<'a> {
+    identifier: &'a str,
+    #[serde(flatten)]
+    row: RecordRowDisplay<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    added: Option<DateTime<Local>>,
+    equivalent: Vec<String>,
+    linked_aliases: Vec<LinkedAlias>,
+    alias_chain: Vec<String>,
+    valid_bibtex: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    provenance: Vec<RevisionProvenance>,
+    field_sources: Vec<FieldSource>,
+    relations: Vec<RelatedRecord>,
+}
+
+/// Write the source of every field, aligning the sources.
+pub fn write_field_sources<W: Write>(
+    writer: &mut W,
+    field_sources: &[FieldSource],
+) -> std::io::Result<()> {
+    let width = field_sources
+        .iter()
+        .map(|field_source| field_source.field.chars().count())
+        .max()
+        .unwrap_or(0);
+    for FieldSource {
+        field,
+        source,
+        revision,
+    } in field_sources
+    {
+        writeln!(writer, "  {field:<width$}  {source} (revision {revision})")?;
+    }
+    Ok(())
+}
+
+/// Print a report for a row in the database as JSON.
+pub fn database_report_json<'conn, I: InRecordsTable>(
+    record_id: String,
+    state: State<'conn, I>,
+    alias_chain: Vec<String>,
+) -> anyhow::Result<()> {
+    let url = record_url(&state.canonical()?);
+    let version = state.current()?;
+    let report = InfoReport {
+        identifier: &record_id,
+        row: version.display(false),
+        added: state.added()?,
+        equivalent: state.referencing_keys()?,
+        linked_aliases: state
+            .linked_aliases()?
+            .into_iter()
+            .map(|(name, target)| LinkedAlias { name, target })
+            .collect(),
+        alias_chain,
+        valid_bibtex: is_entry_key(&record_id),
+        url,
+        provenance: state.provenance()?,
+        field_sources: state.field_sources()?,
+        relations: state.relations()?,
+    };
+    owrite_json(&report)?;
+    state.commit()?;
+    Ok(())
+}
+
 pub fn database_report<'conn, D, I: InRecordsTable>(
     record_id: String,
     data: RecordRow<D>,
@@ -22,12 +105,26 @@ pub fn database_report<'conn, D, I: InRecordsTable>(
             let mut lock = stdout_lock_wrap();
             header(data.data, &mut lock)?;
             writeln!(lock, "Canonical: {}", data.canonical)?;
+            if let Some(url) = record_url(&data.canonical) {
+                writeln!(lock, "URL: {url}")?;
+            }
             writeln!(lock, "Revision: {}", state.rev())?;
             writeln!(
                 lock,
                 "Equivalent references: {}",
                 state.referencing_keys()?.iter().join(", ")
             )?;
+            let linked = state.linked_aliases()?;
+            if !linked.is_empty() {
+                writeln!(
+                    lock,
+                    "Linked aliases: {}",
+                    linked
+                        .iter()
+                        .map(|(name, target)| format!("{name} -> {target}"))
+                        .join(", ")
+                )?;
+            }
             writeln!(
                 lock,
                 "Valid BibTeX? {}",
@@ -37,7 +134,17 @@ pub fn database_report<'conn, D, I: InRecordsTable>(
                     "no"
                 }
             )?;
+            if let Some(added) = state.added()? {
+                writeln!(lock, "Added: {added}")?;
+            }
             writeln!(lock, "Data last modified: {}", data.modified)?;
+            if let Some(latest) = state.provenance()?.first() {
+                writeln!(
+                    lock,
+                    "Retrieved from: {} ({}) at {}",
+                    latest.provenance.url, latest.provenance.provider, latest.provenance.retrieved
+                )?;
+            }
         }
         InfoReportType::Canonical => {
             owriteln!("{}", state.canonical()?)?;
@@ -56,10 +163,55 @@ pub fn database_report<'conn, D, I: InRecordsTable>(
             for re in state.referencing_keys()? {
                 writeln!(lock, "{re}")?;
             }
+            for (name, target) in state.linked_aliases()? {
+                writeln!(lock, "{name} -> {target}")?;
+            }
         }
         InfoReportType::Modified => {
             owriteln!("{}", state.last_modified()?)?;
         }
+        InfoReportType::Added => match state.added()? {
+            Some(added) => owriteln!("{added}")?,
+            None => info!("No added time recorded for '{record_id}'"),
+        },
+        InfoReportType::Provenance => {
+            let history = state.provenance()?;
+            let field_sources = state.field_sources()?;
+            if history.is_empty() && field_sources.is_empty() {
+                info!("No provenance recorded for '{record_id}'");
+            }
+            let mut lock = stdout_lock_wrap();
+            for RevisionProvenance {
+                revision,
+                provenance,
+            } in history
+            {
+                writeln!(lock, "Revision: {revision}")?;
+                writeln!(lock, "  Provider: {}", provenance.provider)?;
+                writeln!(lock, "  URL: {}", provenance.url)?;
+                writeln!(lock, "  Retrieved: {}", provenance.retrieved)?;
+                writeln!(lock, "  SHA-256: {}", provenance.sha256)?;
+            }
+            if !field_sources.is_empty() {
+                writeln!(lock, "Field sources:")?;
+                write_field_sources(&mut lock, &field_sources)?;
+            }
+        }
+        InfoReportType::Related => {
+            let relations = state.relations()?;
+            if relations.is_empty() {
+                info!("No relations recorded for '{record_id}'");
+            }
+            let mut lock = stdout_lock_wrap();
+            for RelatedRecord {
+                canonical,
+                kind,
+                target,
+            } in relations
+            {
+                writeln!(lock, "{canonical} {kind} {target}")?;
+            }
+        }
     };
     state.commit()?;
     Ok(())
@@ -1,11 +1,12 @@
 use ramify::{Config, TryRamify, writer::Style};
+use serde::Serialize;
 
 use crate::{
     db::{
-        state::{InRecordsTable, State},
+        state::{InRecordsTable, RecordRowDisplay, RevisionId, State, Version},
         tree::RamifierConfig,
     },
-    output::stdout_lock_wrap,
+    output::{owrite_json, stdout_lock_wrap},
 };
 
 fn write_branch_diagram<V, R, W>(
@@ -70,3 +71,59 @@ pub fn print_log<'conn, I: InRecordsTable>(
         write_branch_diagram(stdout, current, ramifier, reverse)
     }
 }
+
+/// A single revision in the JSON output of [`print_log_json`].
+#[derive(Serialize)]
+struct LogItem<'a> {
+    #[serde(flatten)]
+    row: RecordRowDisplay<'a>,
+    parent: Option<RevisionId>,
+    active: bool,
+}
+
+/// Print the revisions which would be displayed by [`print_log`] as a JSON array.
+///
+/// The revisions are ordered from newest to oldest, or oldest to newest if `reverse` is set.
+pub fn print_log_json<'conn, I: InRecordsTable>(
+    state: &State<'conn, I>,
+    tree: bool,
+    all: bool,
+    reverse: bool,
+) -> anyhow::Result<()> {
+    let current = state.current()?;
+    let active = current.rev_id();
+    let mut versions: Vec<Version<'_, 'conn>> = Vec::new();
+
+    // this follows the same traversal rules as the ramifiers used by `print_log`
+    if tree {
+        let mut stack = vec![current.root(all)?];
+        while let Some(version) = stack.pop() {
+            if version.is_entry() || all {
+                stack.extend(version.children()?);
+            }
+            versions.push(version);
+        }
+    } else {
+        let mut next = Some(current);
+        while let Some(version) = next {
+            next = version.parent()?.filter(|parent| parent.is_entry() || all);
+            versions.push(version);
+        }
+    }
+
+    versions.sort_by_key(|version| version.row.modified);
+    if !reverse {
+        versions.reverse();
+    }
+
+    let items: Vec<LogItem<'_>> = versions
+        .iter()
+        .map(|version| LogItem {
+            row: version.display(false),
+            parent: version.parent_rev_id(),
+            active: version.rev_id() == active,
+        })
+        .collect();
+    owrite_json(&items)?;
+    Ok(())
+}
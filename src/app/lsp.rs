@@ -0,0 +1,341 @@
+//! # A minimal language server
+//!
+//! The language server communicates using JSON-RPC over standard input and output, and provides
+//! completion and hover information for citation keys in TeX, Markdown, and Typst documents.
+//! Documents are synchronized in full on every change, and requests are answered sequentially.
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    io::{BufRead, Write},
+    ops::Range,
+    str::FromStr,
+};
+
+use anyhow::{Result, bail};
+use nucleo_picker::Render;
+use serde_json::{Value, json};
+
+use crate::{
+    cite_search::{SourceFileType, citekey_range_at},
+    config::AliasTransform,
+    db::{RecordDatabase, state::RecordIdState},
+    format::Template,
+    logger::{info, warn},
+    record::RecordId,
+};
+
+/// The JSON-RPC error code for an unknown method.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// The JSON-RPC error code for an internal error.
+const INTERNAL_ERROR: i64 = -32603;
+
+/// The LSP completion item kind for a reference.
+const COMPLETION_KIND_REFERENCE: u8 = 18;
+
+/// An open text document.
+struct Document {
+    file_type: SourceFileType,
+    text: String,
+}
+
+impl Document {
+    /// Convert an LSP position, which counts UTF-16 code units, to a byte offset.
+    fn offset_at(&self, position: &Value) -> usize {
+        let line = position["line"].as_u64().unwrap_or(0) as usize;
+        let character = position["character"].as_u64().unwrap_or(0) as usize;
+
+        let line_start = match line {
+            0 => 0,
+            n => self
+                .text
+                .match_indices('\n')
+                .nth(n - 1)
+                .map_or(self.text.len(), |(idx, _)| idx + 1),
+        };
+
+        let mut units = 0;
+        for (idx, ch) in self.text[line_start..].char_indices() {
+            if units >= character || ch == '\n' {
+                return line_start + idx;
+            }
+            units += ch.len_utf16();
+        }
+        self.text.len()
+    }
+
+    /// Convert a byte offset to an LSP position.
+    fn position_at(&self, offset: usize) -> Value {
+        let before = &self.text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        json!({
+            "line": before.matches('\n').count(),
+            "character": before[line_start..].encode_utf16().count(),
+        })
+    }
+
+    /// Convert a byte range to an LSP range.
+    fn range(&self, range: &Range<usize>) -> Value {
+        json!({
+            "start": self.position_at(range.start),
+            "end": self.position_at(range.end),
+        })
+    }
+
+    /// The byte offset and the range of the citation key at the provided LSP position.
+    fn citekey_at(&self, position: &Value) -> Option<(usize, Range<usize>)> {
+        let cursor = self.offset_at(position);
+        citekey_range_at(self.file_type, self.text.as_bytes(), cursor).map(|range| (cursor, range))
+    }
+}
+
+/// Determine the file type of a document from its language identifier, falling back to the
+/// extension of the document URI.
+fn file_type(language_id: &str, uri: &str) -> Option<SourceFileType> {
+    match language_id {
+        "latex" | "tex" | "plaintex" | "context" => Some(SourceFileType::Tex),
+        "markdown" | "pandoc" | "quarto" | "rmd" => Some(SourceFileType::Md),
+        "typst" => Some(SourceFileType::Typ),
+        _ => uri
+            .rsplit_once('.')
+            .and_then(|(_, ext)| SourceFileType::from_str(ext).ok()),
+    }
+}
+
+/// Read a single message, returning `None` at the end of the input.
+fn read_message<R: BufRead>(input: &mut R) -> Result<Option<Value>> {
+    let mut content_length = None;
+    let mut header = String::new();
+    loop {
+        header.clear();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = Some(value.trim().parse::<usize>()?);
+        }
+    }
+
+    let Some(len) = content_length else {
+        bail!("Message is missing the 'Content-Length' header");
+    };
+    let mut buffer = vec![0; len];
+    input.read_exact(&mut buffer)?;
+    Ok(Some(serde_json::from_slice(&buffer)?))
+}
+
+/// Write a single message.
+fn write_message<W: Write>(output: &mut W, message: &Value) -> Result<()> {
+    let body = serde_json::to_vec(message)?;
+    write!(output, "Content-Length: {}\r\n\r\n", body.len())?;
+    output.write_all(&body)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// The capabilities of the server, in response to the `initialize` request.
+fn capabilities() -> Value {
+    json!({
+        "capabilities": {
+            // full document synchronization
+            "textDocumentSync": 1,
+            "completionProvider": {
+                "triggerCharacters": ["{", ",", "@", "<"],
+            },
+            "hoverProvider": true,
+        },
+        "serverInfo": {
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+    })
+}
+
+/// Complete the citation key at the provided position using the identifiers in the database.
+fn completion(
+    record_db: &mut RecordDatabase,
+    document: &Document,
+    position: &Value,
+) -> Result<Value> {
+    let Some((cursor, range)) = document.citekey_at(position) else {
+        return Ok(Value::Null);
+    };
+    let prefix = &document.text[range.start..cursor];
+    let edit_range = document.range(&range);
+
+    let mut items = Vec::new();
+    let snapshot = record_db.snapshot()?;
+    snapshot.map_identifiers(false, |name| {
+        if name.starts_with(prefix) {
+            items.push(json!({
+                "label": name,
+                "kind": COMPLETION_KIND_REFERENCE,
+                "textEdit": { "range": edit_range, "newText": name },
+            }));
+        }
+        Ok::<_, Infallible>(())
+    })?;
+    snapshot.commit()?;
+
+    Ok(json!({ "isIncomplete": false, "items": items }))
+}
+
+/// Describe the record associated with the citation key at the provided position.
+fn hover<A: AliasTransform>(
+    record_db: &mut RecordDatabase,
+    template: &Template,
+    alias_transform: &A,
+    document: &Document,
+    position: &Value,
+) -> Result<Value> {
+    let Some((_, range)) = document.citekey_at(position) else {
+        return Ok(Value::Null);
+    };
+    let key = &document.text[range.clone()];
+    if key.is_empty() {
+        return Ok(Value::Null);
+    }
+
+    let contents = match record_db.state_from_record_id(RecordId::from(key), alias_transform)? {
+        RecordIdState::Entry(_, row, state) => {
+            state.commit()?;
+            format!(
+                "**{key}** (`{}`)\n\n{}",
+                row.canonical,
+                template.render(&row)
+            )
+        }
+        RecordIdState::Deleted(_, row, state) => {
+            state.commit()?;
+            match row.data {
+                Some(replacement) => {
+                    format!("**{key}**: deleted record, replaced by `{replacement}`")
+                }
+                None => format!("**{key}**: deleted record"),
+            }
+        }
+        RecordIdState::Void(_, _, state) => {
+            state.commit()?;
+            format!("**{key}**: voided record")
+        }
+        RecordIdState::NullRemoteId(_, state) => {
+            state.commit()?;
+            format!("**{key}**: null record")
+        }
+        RecordIdState::Unknown(unknown) => {
+            unknown.combine_and_commit()?;
+            format!("**{key}**: not in database")
+        }
+        RecordIdState::UndefinedAlias(_) => format!("**{key}**: undefined alias"),
+        RecordIdState::InvalidRemoteId(err) => format!("**{key}**: {err}"),
+    };
+
+    Ok(json!({
+        "contents": { "kind": "markdown", "value": contents },
+        "range": document.range(&range),
+    }))
+}
+
+/// Run the language server until the `exit` notification is received or the input is closed.
+pub fn run_language_server<R: BufRead, W: Write, A: AliasTransform>(
+    record_db: &mut RecordDatabase,
+    template: &Template,
+    alias_transform: &A,
+    mut input: R,
+    mut output: W,
+) -> Result<()> {
+    let mut documents: HashMap<String, Document> = HashMap::new();
+    let mut shutdown = false;
+
+    while let Some(message) = read_message(&mut input)? {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        info!("Received message: {method}");
+
+        let result = match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                shutdown = true;
+                Ok(Value::Null)
+            }
+            "exit" => break,
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                let language_id = document["languageId"].as_str().unwrap_or_default();
+                match file_type(language_id, uri) {
+                    Some(file_type) => {
+                        documents.insert(
+                            uri.to_owned(),
+                            Document {
+                                file_type,
+                                text: document["text"].as_str().unwrap_or_default().to_owned(),
+                            },
+                        );
+                    }
+                    None => warn!("Unsupported language '{language_id}' for document '{uri}'"),
+                }
+                continue;
+            }
+            "textDocument/didChange" => {
+                if let Some(document) = documents.get_mut(uri)
+                    && let Some(text) = params["contentChanges"]
+                        .as_array()
+                        .and_then(|changes| changes.last())
+                        .and_then(|change| change["text"].as_str())
+                {
+                    document.text = text.to_owned();
+                }
+                continue;
+            }
+            "textDocument/didClose" => {
+                documents.remove(uri);
+                continue;
+            }
+            "textDocument/completion" => match documents.get(uri) {
+                Some(document) => completion(record_db, document, &params["position"])
+                    .map_err(|err| (INTERNAL_ERROR, err.to_string())),
+                None => Ok(Value::Null),
+            },
+            "textDocument/hover" => match documents.get(uri) {
+                Some(document) => hover(
+                    record_db,
+                    template,
+                    alias_transform,
+                    document,
+                    &params["position"],
+                )
+                .map_err(|err| (INTERNAL_ERROR, err.to_string())),
+                None => Ok(Value::Null),
+            },
+            _ => Err((METHOD_NOT_FOUND, format!("Unsupported method '{method}'"))),
+        };
+
+        // notifications do not receive a response
+        let Some(id) = message.get("id") else {
+            continue;
+        };
+
+        let response = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+        write_message(&mut output, &response)?;
+    }
+
+    if !shutdown {
+        warn!("Language server exited without a shutdown request");
+    }
+
+    Ok(())
+}
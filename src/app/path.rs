@@ -1,20 +1,40 @@
 use std::{
-    fs::read_to_string,
-    path::{Path, PathBuf},
+    collections::{BTreeMap, HashSet, btree_map},
+    ffi::OsStr,
+    fs::{
+        File, copy, create_dir_all, exists, read, read_dir, read_link, read_to_string, remove_dir,
+        remove_file, rename, symlink_metadata,
+    },
+    io::{self, Read},
+    path::{Component, Path, PathBuf, absolute},
+    process::Command,
     str::FromStr,
 };
 
 use anyhow::bail;
+use chrono::Local;
+use data_encoding::HEXLOWER;
+use nucleo_picker::Render;
+use ring::digest::{Context, SHA256};
+use walkdir::WalkDir;
 
+use super::picker::choose_canonical_id_with_query;
 use crate::{
     Config,
     db::{
-        Tx,
-        state::{ArbitraryData, RecordIdState, RecordRow},
+        AttachmentRow, Identifier, RecordDatabase, Tx,
+        state::{ArbitraryData, DisambiguatedRecordRow, RecordIdState, RecordRow, RemoteIdState},
+    },
+    entry::{Entry, MutableEntryData, RawEntryData},
+    format::Template,
+    fulltext::sanitize_filename,
+    logger::{error, info, set_failed, suggest, warn},
+    output::owriteln,
+    path_hash::{
+        ATTACHMENTS_PATH_DEPTH, PathHash, remote_id_from_attachments_path,
+        remote_id_from_stale_attachments_path,
     },
-    entry::{Entry, MutableEntryData},
-    logger::info,
-    path_hash::PathHash,
+    pdf::identifiers_from_pdf,
     record::{RecordId, RemoteId},
 };
 
@@ -55,6 +75,893 @@ pub fn get_attachment_dir(
     Ok(attachments_root)
 }
 
+/// Determine the canonical identifier of the record associated with the identifier, which may
+/// be deleted or void. If there is no such record, an error is reported and this returns `None`.
+pub fn canonical_from_record_id<F: FnOnce() -> Vec<(regex::Regex, String)>>(
+    record_db: &mut RecordDatabase,
+    identifier: RecordId,
+    config: &Config<F>,
+) -> Result<Option<RemoteId>, rusqlite::Error> {
+    Ok(
+        match record_db
+            .state_from_record_id(identifier, &config.alias_transform)?
+            .require_record()?
+        {
+            Some((_, DisambiguatedRecordRow::Entry(record_row, state))) => {
+                state.commit()?;
+                Some(record_row.canonical)
+            }
+            Some((_, DisambiguatedRecordRow::Deleted(record_row, state))) => {
+                state.commit()?;
+                Some(record_row.canonical)
+            }
+            Some((_, DisambiguatedRecordRow::Void(record_row, state))) => {
+                state.commit()?;
+                Some(record_row.canonical)
+            }
+            None => None,
+        },
+    )
+}
+
+/// List the files in an attachment directory, as paths relative to the directory and sorted by
+/// name. If the directory does not exist, there are no attachments.
+pub fn list_attachments(attachment_dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+    if !attachment_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut attachments = Vec::new();
+    for dir_entry in WalkDir::new(attachment_dir)
+        .min_depth(1)
+        .sort_by_file_name()
+    {
+        let dir_entry = dir_entry?;
+        if dir_entry.file_type().is_file() {
+            attachments.push(
+                dir_entry
+                    .path()
+                    .strip_prefix(attachment_dir)
+                    .expect("DirEntry was created originally from this root path")
+                    .to_path_buf(),
+            );
+        }
+    }
+    Ok(attachments)
+}
+
+/// Compute the size and the lowercase hexadecimal SHA-256 hash of the file.
+fn size_and_sha256(path: &Path) -> Result<(u64, String), io::Error> {
+    let mut file = File::open(path)?;
+    let mut context = Context::new(&SHA256);
+    let mut buffer = [0; 8192];
+    let mut size = 0;
+    loop {
+        let len = file.read(&mut buffer)?;
+        if len == 0 {
+            break;
+        }
+        context.update(&buffer[..len]);
+        size += len as u64;
+    }
+    Ok((size, HEXLOWER.encode(context.finish().as_ref())))
+}
+
+/// Compute the metadata of the attachment at `path`, which must be contained in the attachment
+/// directory of `canonical`.
+pub fn attachment_metadata(
+    canonical: &RemoteId,
+    attachment_dir: &Path,
+    path: &Path,
+) -> Result<AttachmentRow, anyhow::Error> {
+    let Ok(name) = path.strip_prefix(attachment_dir) else {
+        bail!(
+            "Attachment '{}' is not contained in the attachment directory '{}'",
+            path.display(),
+            attachment_dir.display()
+        );
+    };
+    let (size, sha256) = size_and_sha256(path)?;
+    Ok(AttachmentRow {
+        canonical: canonical.clone(),
+        name: name.to_string_lossy().into_owned(),
+        size,
+        sha256,
+        added: Local::now(),
+    })
+}
+
+/// Record the metadata of the attachment at `path` in the database.
+pub fn track_attachment(
+    record_db: &mut RecordDatabase,
+    canonical: &RemoteId,
+    attachment_dir: &Path,
+    path: &Path,
+) -> Result<(), anyhow::Error> {
+    let attachment = attachment_metadata(canonical, attachment_dir, path)?;
+    record_db.insert_attachment(&attachment)?;
+    Ok(())
+}
+
+/// Determine the existing record for a file found by [`scan_attachments`], without making any
+/// remote requests.
+///
+/// The most frequent DOI or arXiv identifier in the contents of the file is used first. Otherwise,
+/// the file stem is read as an identifier or an alias, such as `hochman2014.pdf`, or as an
+/// identifier whose provider is separated by `_`, such as `arxiv_1212.1873.pdf`.
+fn match_existing_record<F: FnOnce() -> Vec<(regex::Regex, String)>>(
+    record_db: &mut RecordDatabase,
+    path: &Path,
+    config: &Config<F>,
+) -> Result<Option<RemoteId>, anyhow::Error> {
+    let mut candidates = identifiers_from_pdf(&read(path)?);
+    let mut providers = HashSet::new();
+    candidates.retain(|id| providers.insert(id.provider().to_owned()));
+    for candidate in candidates {
+        if let RemoteIdState::Entry(record_row, state) =
+            record_db.state_from_remote_id(&candidate)?
+        {
+            state.commit()?;
+            return Ok(Some(record_row.canonical));
+        }
+    }
+
+    let Some(stem) = path.file_stem().and_then(OsStr::to_str) else {
+        return Ok(None);
+    };
+    let mut keys = vec![stem.to_owned()];
+    if !stem.contains(':')
+        && let Some((provider, sub_id)) = stem.split_once('_')
+    {
+        keys.push(format!("{provider}:{sub_id}"));
+    }
+    for key in keys {
+        if let RecordIdState::Entry(_, record_row, state) =
+            record_db.state_from_record_id(RecordId::from(key.as_str()), &config.alias_transform)?
+        {
+            state.commit()?;
+            return Ok(Some(record_row.canonical));
+        }
+    }
+    Ok(None)
+}
+
+/// Copy or move a file found by [`scan_attachments`] to the attachment directory of the record,
+/// unless a file with the same name is already attached.
+fn attach_scanned_file(
+    record_db: &mut RecordDatabase,
+    path: &Path,
+    canonical: &RemoteId,
+    attachment_root: &Path,
+    move_file: bool,
+) -> Result<(), anyhow::Error> {
+    let Some(file_name) = path.file_name() else {
+        bail!("Cannot attach filename containing relative path");
+    };
+    let mut attachment_dir = attachment_root.to_path_buf();
+    canonical.extend_attachments_path(&mut attachment_dir);
+    create_dir_all(&attachment_dir)?;
+    let target = attachment_dir.join(file_name);
+
+    if exists(&target)? {
+        info!(
+            "Skipping file '{}': already attached to '{canonical}'",
+            path.display()
+        );
+        return Ok(());
+    }
+
+    info!("Attaching file '{}' to '{canonical}'", path.display());
+    if move_file {
+        // renaming fails if the attachment directory is on a different file system
+        if rename(path, &target).is_err() {
+            copy(path, &target)?;
+            remove_file(path)?;
+        }
+    } else {
+        copy(path, &target)?;
+    }
+    track_attachment(record_db, canonical, &attachment_dir, &target)
+}
+
+/// Attach the PDF files in the provided directory, which is searched recursively, to existing
+/// records.
+///
+/// Each file is matched to a record using its contents or its name, or, if a template is
+/// provided, by picking the record interactively. The file is then copied to the attachment
+/// directory of the record, or moved if `move_files` is true. Files which are already attached
+/// are skipped, and the files which could not be matched to a record are written to `unmatched`.
+pub fn scan_attachments<F, W>(
+    dir: &Path,
+    mut record_db: RecordDatabase,
+    config: &Config<F>,
+    attachment_root: &Path,
+    pick: Option<&Template>,
+    move_files: bool,
+    unmatched: &mut W,
+) -> Result<(), anyhow::Error>
+where
+    F: FnOnce() -> Vec<(regex::Regex, String)>,
+    W: io::Write + ?Sized,
+{
+    for res in WalkDir::new(dir).sort_by_file_name() {
+        let dir_entry = match res {
+            Ok(dir_entry) => dir_entry,
+            Err(err) => {
+                error!("Failed to read directory entry: {err}");
+                continue;
+            }
+        };
+        let path = dir_entry.path();
+        if !dir_entry.file_type().is_file()
+            || !path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+        {
+            continue;
+        }
+
+        let matched = match match_existing_record(&mut record_db, path, config) {
+            Ok(matched) => matched,
+            Err(err) => {
+                error!("Failed to read file '{}': {err}", path.display());
+                continue;
+            }
+        };
+
+        let canonical = match (matched, pick) {
+            (Some(canonical), _) => Some(canonical),
+            (None, Some(template)) => {
+                info!("Choose the record for file '{}'", path.display());
+                let query = path.file_stem().unwrap_or_default().to_string_lossy();
+                let (mut picker, handle) =
+                    choose_canonical_id_with_query(record_db, template.clone(), &query);
+                let picked = picker.pick()?.map(|row_data| row_data.canonical.clone());
+                record_db = handle.join().expect("Thread should not have panicked")?;
+                picked
+            }
+            (None, None) => None,
+        };
+
+        match canonical {
+            Some(canonical) => {
+                if let Err(err) = attach_scanned_file(
+                    &mut record_db,
+                    path,
+                    &canonical,
+                    attachment_root,
+                    move_files,
+                ) {
+                    error!("Failed to attach file '{}': {err}", path.display());
+                }
+            }
+            None => {
+                writeln!(unmatched, "{}", path.display())?;
+                set_failed();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove the attachment with the provided name, which is a path relative to the attachment
+/// directory. Any directories which are empty after removing the attachment are also removed, up
+/// to and including the attachment directory itself.
+pub fn remove_attachment(attachment_dir: &Path, name: &str) -> Result<(), anyhow::Error> {
+    let relative = Path::new(name);
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        bail!("Attachment name must be a relative path inside the attachment directory: '{name}'");
+    }
+
+    let target = attachment_dir.join(relative);
+    if !target.is_file() {
+        bail!("Attachment does not exist: '{name}'");
+    }
+    info!("Removing attachment '{}'", target.display());
+    remove_file(&target)?;
+
+    let mut parent = target.parent();
+    while let Some(dir) = parent
+        && dir.starts_with(attachment_dir)
+        && read_dir(dir)?.next().is_none()
+    {
+        remove_dir(dir)?;
+        parent = dir.parent();
+    }
+    Ok(())
+}
+
+/// Open the file with the default application of the platform.
+pub fn open_with_default_application(path: &Path) -> Result<(), anyhow::Error> {
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        // the first quoted argument to `start` is the window title
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+
+    info!("Opening '{}'", path.display());
+    match command.arg(path).status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => bail!(
+            "Failed to open '{}': opener exited with {status}",
+            path.display()
+        ),
+        Err(err) => bail!("Failed to open '{}': {err}", path.display()),
+    }
+}
+
+/// Move the attachment directory associated with `from` so that it is associated with `to`.
+///
+/// If the target directory already exists, the attachments are moved into the target directory
+/// one at a time, and attachments which would overwrite an existing file are left in place.
+/// The metadata of the migrated attachments is updated accordingly. Returns `true` if there was
+/// an attachment directory to migrate.
+pub fn migrate_attachments(
+    record_db: &mut RecordDatabase,
+    attachment_root: &Path,
+    from: &RemoteId,
+    to: &RemoteId,
+) -> Result<bool, anyhow::Error> {
+    if !move_attachments(attachment_root, from, to)? {
+        return Ok(false);
+    }
+    record_db.migrate_attachments(from, to)?;
+    Ok(true)
+}
+
+/// Move the attachment directory associated with `from` so that it is associated with `to`,
+/// without updating the metadata of the attachments. Returns `true` if there was an attachment
+/// directory to move.
+pub fn move_attachments(
+    attachment_root: &Path,
+    from: &RemoteId,
+    to: &RemoteId,
+) -> Result<bool, anyhow::Error> {
+    let mut source = attachment_root.to_path_buf();
+    from.extend_attachments_path(&mut source);
+    if !source.is_dir() {
+        return Ok(false);
+    }
+
+    let mut target = attachment_root.to_path_buf();
+    to.extend_attachments_path(&mut target);
+
+    move_attachment_dir(&source, &target)?;
+    Ok(true)
+}
+
+/// Move the attachment directory at `source` to `target`.
+///
+/// If the target directory already exists, the attachments are moved into the target directory
+/// one at a time, and attachments which would overwrite an existing file are left in place.
+fn move_attachment_dir(source: &Path, target: &Path) -> Result<(), anyhow::Error> {
+    info!(
+        "Migrating attachments from '{}' to '{}'",
+        source.display(),
+        target.display()
+    );
+
+    if exists(target)? {
+        for dir_entry in read_dir(source)? {
+            let dir_entry = dir_entry?;
+            let destination = target.join(dir_entry.file_name());
+            if exists(&destination)? {
+                warn!(
+                    "Not migrating attachment '{}' since the destination '{}' already exists",
+                    dir_entry.path().display(),
+                    destination.display()
+                );
+            } else {
+                rename(dir_entry.path(), destination)?;
+            }
+        }
+
+        // only remove the source directory if every attachment was migrated
+        if read_dir(source)?.next().is_none() {
+            remove_dir(source)?;
+        }
+    } else {
+        if let Some(parent) = target.parent() {
+            create_dir_all(parent)?;
+        }
+        rename(source, target)?;
+    }
+
+    Ok(())
+}
+
+/// Determine the canonical identifier of the record which currently exists for the identifier,
+/// following replacements of deleted records. Returns `None` if there is no such record.
+fn current_canonical(
+    record_db: &mut RecordDatabase,
+    remote_id: &RemoteId,
+) -> Result<Option<RemoteId>, rusqlite::Error> {
+    // the state borrows the database, so the replacement is resolved after it is committed
+    let deleted = match record_db.state_from_remote_id(remote_id)? {
+        RemoteIdState::Entry(row, state) => {
+            state.commit()?;
+            return Ok(Some(row.canonical));
+        }
+        RemoteIdState::Deleted(_, state) => {
+            state.commit()?;
+            true
+        }
+        RemoteIdState::Void(_, state) => {
+            state.commit()?;
+            false
+        }
+        RemoteIdState::Null(state) => {
+            state.commit()?;
+            false
+        }
+        RemoteIdState::Unknown(state) => {
+            state.commit()?;
+            false
+        }
+    };
+
+    if deleted {
+        resolve_replacement(record_db, remote_id)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Move every attachment directory in the attachment root to the directory of the canonical
+/// identifier of the record which currently exists for it, merging the directories if the target
+/// directory already exists.
+///
+/// This relocates directories of records which were replaced or whose canonical identifier
+/// changed, and directories whose path is out of date. If `dry_run` is set, the directories which
+/// would be moved are printed and nothing is changed. Returns the number of directories which
+/// were (or would be) moved.
+pub fn relocate_attachments(
+    record_db: &mut RecordDatabase,
+    attachment_root: &Path,
+    dry_run: bool,
+) -> Result<usize, anyhow::Error> {
+    if !attachment_root.is_dir() {
+        return Ok(0);
+    }
+
+    // collect the directories first, since the directory tree is modified while relocating
+    let mut dirs = Vec::new();
+    for dir_entry in WalkDir::new(attachment_root)
+        .min_depth(ATTACHMENTS_PATH_DEPTH)
+        .max_depth(ATTACHMENTS_PATH_DEPTH)
+        .sort_by_file_name()
+    {
+        let dir_entry = dir_entry?;
+        if dir_entry.file_type().is_dir() {
+            dirs.push(dir_entry.into_path());
+        }
+    }
+
+    let mut relocated = 0;
+    for source in dirs {
+        let relative = source
+            .strip_prefix(attachment_root)
+            .expect("DirEntry was created originally from this root path");
+
+        let Some(remote_id) = remote_id_from_stale_attachments_path(relative) else {
+            warn!(
+                "Attachment directory does not correspond to an identifier: '{}'",
+                source.display()
+            );
+            continue;
+        };
+
+        // directories of records which no longer exist are left in place, but the directory
+        // path is still brought up to date
+        let canonical = match current_canonical(record_db, &remote_id)? {
+            Some(canonical) => canonical,
+            None => {
+                warn!("No record exists for the attachment directory of '{remote_id}'");
+                remote_id.clone()
+            }
+        };
+
+        let mut target = attachment_root.to_path_buf();
+        canonical.extend_attachments_path(&mut target);
+        if target == source {
+            continue;
+        }
+
+        relocated += 1;
+        if dry_run {
+            owriteln!(
+                "{remote_id} -> {canonical}: '{}' -> '{}'",
+                relative.display(),
+                target
+                    .strip_prefix(attachment_root)
+                    .expect("Target was created from this root path")
+                    .display()
+            )?;
+        } else {
+            move_attachment_dir(&source, &target)?;
+            if remote_id != canonical {
+                record_db.migrate_attachments(&remote_id, &canonical)?;
+            }
+        }
+    }
+
+    Ok(relocated)
+}
+
+/// Compile the `attach.layout` configuration value.
+pub fn layout_template(layout: &str) -> Result<Template, anyhow::Error> {
+    match Template::compile(layout) {
+        Ok(template) => Ok(template),
+        Err(err) => bail!("Syntax error in `attach.layout` configuration value: {err}"),
+    }
+}
+
+/// The path of the record in the human-readable layout, relative to the root of the layout.
+///
+/// Every `/` in the rendered template separates directories, and the final component is the
+/// canonical identifier of the record. Each component is sanitized so that it is a valid file
+/// name, and empty components are replaced by `unknown`.
+pub fn layout_path(template: &Template, row: &RecordRow<RawEntryData>) -> PathBuf {
+    let mut path = PathBuf::new();
+    for component in template.render(row).split('/') {
+        let component = sanitize_filename(component);
+        if component.is_empty() {
+            path.push("unknown");
+        } else {
+            path.push(component);
+        }
+    }
+    path.push(sanitize_filename(row.canonical.name()));
+    path
+}
+
+/// Create a symbolic link at `link` which points to the directory `target`.
+#[cfg(unix)]
+fn symlink_dir(target: &Path, link: &Path) -> Result<(), io::Error> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Create a symbolic link at `link` which points to the directory `target`.
+#[cfg(windows)]
+fn symlink_dir(target: &Path, link: &Path) -> Result<(), io::Error> {
+    std::os::windows::fs::symlink_dir(target, link)
+}
+
+/// Create a symbolic link at `link` which points to the directory `target`.
+#[cfg(not(any(unix, windows)))]
+fn symlink_dir(_target: &Path, _link: &Path) -> Result<(), io::Error> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symbolic links are not supported on this platform",
+    ))
+}
+
+/// Remove a symbolic link to a directory, without modifying the directory itself.
+fn remove_symlink_dir(link: &Path) -> Result<(), io::Error> {
+    // directory links are removed like directories on Windows, and like files elsewhere
+    if cfg!(windows) {
+        remove_dir(link)
+    } else {
+        remove_file(link)
+    }
+}
+
+/// Update the tree of symbolic links in `layout_root` so that it contains a link to the
+/// attachment directory of every record with attachments, at the path determined by
+/// [`layout_path`].
+///
+/// Links into the attachment root which do not correspond to a record, or whose target changed,
+/// are removed, along with the directories which are empty afterwards. Other files are never
+/// modified. Returns the number of links which were created.
+pub fn relink_attachments(
+    record_db: &mut RecordDatabase,
+    attachment_root: &Path,
+    layout_root: &Path,
+    template: &Template,
+) -> Result<usize, anyhow::Error> {
+    // the targets are absolute so that the links do not depend on the location of the layout
+    let attachment_root = absolute(attachment_root)?;
+
+    let mut expected = BTreeMap::new();
+    record_db.map_active_records(|row| {
+        let mut target = attachment_root.clone();
+        row.canonical.extend_attachments_path(&mut target);
+        if !target.is_dir() {
+            return;
+        }
+
+        match expected.entry(layout_root.join(layout_path(template, &row))) {
+            btree_map::Entry::Vacant(vacant) => {
+                vacant.insert(target);
+            }
+            btree_map::Entry::Occupied(occupied) => {
+                warn!(
+                    "Not linking attachments of '{}' since the path '{}' is already used",
+                    row.canonical,
+                    occupied.key().display()
+                );
+            }
+        }
+    })?;
+
+    if layout_root.is_dir() {
+        // collect the paths first, since the directory tree is modified afterwards
+        let mut stale = Vec::new();
+        let mut dirs = Vec::new();
+        for dir_entry in WalkDir::new(layout_root).min_depth(1).contents_first(true) {
+            let dir_entry = dir_entry?;
+            if dir_entry.path_is_symlink() {
+                let target = read_link(dir_entry.path())?;
+                if target.starts_with(&attachment_root)
+                    && expected.get(dir_entry.path()) != Some(&target)
+                {
+                    stale.push(dir_entry.into_path());
+                }
+            } else if dir_entry.file_type().is_dir() {
+                dirs.push(dir_entry.into_path());
+            }
+        }
+
+        for link in stale {
+            info!("Removing stale link '{}'", link.display());
+            remove_symlink_dir(&link)?;
+        }
+
+        // the directories are in depth-first order, so nested empty directories are removed
+        for dir in dirs {
+            if read_dir(&dir)?.next().is_none() {
+                remove_dir(&dir)?;
+            }
+        }
+    }
+
+    let mut created = 0;
+    for (link, target) in expected {
+        match symlink_metadata(&link) {
+            Ok(metadata) if metadata.file_type().is_symlink() => continue,
+            Ok(_) => {
+                warn!(
+                    "Not linking attachments to '{}' since the file already exists",
+                    link.display()
+                );
+                continue;
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        if let Some(parent) = link.parent() {
+            create_dir_all(parent)?;
+        }
+        symlink_dir(&target, &link)?;
+        created += 1;
+    }
+
+    Ok(created)
+}
+
+/// Follow the chain of replacements starting at the provided canonical identifier, returning the
+/// canonical identifier of the record which currently exists, if any.
+fn resolve_replacement(
+    record_db: &mut RecordDatabase,
+    canonical: &RemoteId,
+) -> Result<Option<RemoteId>, rusqlite::Error> {
+    let mut seen = HashSet::new();
+    let mut current = canonical.clone();
+
+    loop {
+        match record_db.state_from_remote_id(&current)? {
+            RemoteIdState::Entry(_, state) => {
+                state.commit()?;
+                return Ok((&current != canonical).then_some(current));
+            }
+            RemoteIdState::Deleted(row, state) => {
+                state.commit()?;
+                let Some(replacement) = row.data else {
+                    return Ok(None);
+                };
+                // guard against cycles of replacements
+                if !seen.insert(current) {
+                    return Ok(None);
+                }
+                current = replacement;
+            }
+            RemoteIdState::Void(_, state) => {
+                state.commit()?;
+                return Ok(None);
+            }
+            RemoteIdState::Null(state) => {
+                state.commit()?;
+                return Ok(None);
+            }
+            RemoteIdState::Unknown(state) => {
+                state.commit()?;
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// Check the attachment directory for attachments which are associated with records which were
+/// replaced, or which do not correspond to any record in the database. If `fix` is set,
+/// attachments of replaced records are migrated to the replacement record.
+pub fn check_attachments(
+    record_db: &mut RecordDatabase,
+    attachment_root: &Path,
+    fix: bool,
+) -> Result<(), anyhow::Error> {
+    if !attachment_root.is_dir() {
+        return Ok(());
+    }
+
+    let mut orphaned = Vec::new();
+
+    for dir_entry in WalkDir::new(attachment_root)
+        .min_depth(ATTACHMENTS_PATH_DEPTH)
+        .max_depth(ATTACHMENTS_PATH_DEPTH)
+    {
+        let dir_entry = dir_entry?;
+        if !dir_entry.file_type().is_dir() {
+            continue;
+        }
+
+        let relative = dir_entry
+            .path()
+            .strip_prefix(attachment_root)
+            .expect("DirEntry was created originally from this root path");
+
+        let Some(canonical) = remote_id_from_attachments_path(relative) else {
+            warn!(
+                "Attachment directory does not correspond to an identifier: '{}'",
+                dir_entry.path().display()
+            );
+            continue;
+        };
+
+        match record_db.state_from_remote_id(&canonical)? {
+            RemoteIdState::Entry(_, state) => state.commit()?,
+            RemoteIdState::Deleted(_, state) => {
+                state.commit()?;
+                orphaned.push(canonical);
+            }
+            RemoteIdState::Void(_, state) => state.commit()?,
+            RemoteIdState::Null(state) => {
+                state.commit()?;
+                warn!("Attachment directory exists for null record '{canonical}'");
+            }
+            RemoteIdState::Unknown(state) => {
+                state.commit()?;
+                warn!("Attachment directory exists for record not in database: '{canonical}'");
+            }
+        }
+    }
+
+    for canonical in orphaned {
+        if let Some(replacement) = resolve_replacement(record_db, &canonical)? {
+            if fix {
+                migrate_attachments(record_db, attachment_root, &canonical, &replacement)?;
+            } else {
+                error!(
+                    "Attachments for '{canonical}' were not migrated to the replacement record '{replacement}'"
+                );
+                suggest!("Migrate the attachments with `autobib util check --fix`.");
+            }
+        } else {
+            warn!("Attachment directory exists for deleted record '{canonical}'");
+        }
+    }
+
+    Ok(())
+}
+
+/// Compare the attachments against the metadata stored in the database, reporting attachments
+/// which are missing or were modified, and attachments which have no metadata. If `fix` is set,
+/// the metadata is updated to match the attachments.
+pub fn check_attachment_metadata(
+    record_db: &mut RecordDatabase,
+    attachment_root: &Path,
+    fix: bool,
+) -> Result<(), anyhow::Error> {
+    let mut tracked = HashSet::new();
+
+    for attachment in record_db.attachments()? {
+        let mut attachment_dir = attachment_root.to_path_buf();
+        attachment
+            .canonical
+            .extend_attachments_path(&mut attachment_dir);
+        let path = attachment_dir.join(&attachment.name);
+        let canonical = &attachment.canonical;
+        let name = &attachment.name;
+
+        if !path.is_file() {
+            if fix {
+                info!("Removing metadata of missing attachment '{name}' of '{canonical}'");
+                record_db.delete_attachment(canonical, name)?;
+            } else {
+                error!("Attachment '{name}' of '{canonical}' is missing");
+                suggest!("Remove the metadata with `autobib util check --attachments --fix`.");
+            }
+            continue;
+        }
+
+        let (size, sha256) = size_and_sha256(&path)?;
+        if size != attachment.size || sha256 != attachment.sha256 {
+            if fix {
+                warn!("Updating metadata of modified attachment '{name}' of '{canonical}'");
+                record_db.insert_attachment(&AttachmentRow {
+                    size,
+                    sha256,
+                    ..attachment.clone()
+                })?;
+            } else {
+                error!(
+                    "Attachment '{name}' of '{canonical}' was modified after it was added on {}",
+                    attachment.added.format("%Y-%m-%d")
+                );
+            }
+        }
+
+        tracked.insert(path);
+    }
+
+    if !attachment_root.is_dir() {
+        return Ok(());
+    }
+
+    for dir_entry in WalkDir::new(attachment_root)
+        .min_depth(ATTACHMENTS_PATH_DEPTH)
+        .max_depth(ATTACHMENTS_PATH_DEPTH)
+    {
+        let dir_entry = dir_entry?;
+        if !dir_entry.file_type().is_dir() {
+            continue;
+        }
+
+        let relative = dir_entry
+            .path()
+            .strip_prefix(attachment_root)
+            .expect("DirEntry was created originally from this root path");
+
+        // directories which do not correspond to an identifier are reported by
+        // `check_attachments`
+        let Some(canonical) = remote_id_from_attachments_path(relative) else {
+            continue;
+        };
+
+        for name in list_attachments(dir_entry.path())? {
+            let path = dir_entry.path().join(&name);
+            if tracked.contains(&path) {
+                continue;
+            }
+
+            if fix {
+                info!(
+                    "Adding metadata of attachment '{}' of '{canonical}'",
+                    name.display()
+                );
+                track_attachment(record_db, &canonical, dir_entry.path(), &path)?;
+            } else {
+                warn!(
+                    "Attachment '{}' of '{canonical}' is not tracked in the database",
+                    name.display()
+                );
+                suggest!("Add the metadata with `autobib util check --attachments --fix`.");
+            }
+        }
+    }
+
+    Ok(())
+}
+
 pub fn data_from_key<'conn, F: FnOnce() -> Vec<(regex::Regex, String)>>(
     tx: Tx<'conn>,
     record_id: RecordId,
@@ -1,14 +1,15 @@
 use std::{
+    collections::HashSet,
     path::{Path, PathBuf},
     thread,
 };
 
 use nonempty::NonEmpty;
-use nucleo_picker::{Picker, PickerOptions, Render};
+use nucleo_picker::{Picker, PickerOptions, Render, render::PathRenderer};
 use walkdir::{DirEntry, WalkDir};
 
 use crate::{
-    db::{RecordDatabase, state::RecordRow},
+    db::{Identifier, RecordDatabase, state::RecordRow},
     entry::RawEntryData,
     format::Template,
     path_hash::PathHash,
@@ -42,11 +43,20 @@ pub fn choose_attachment(att_data: &AttachmentData) -> Picker<DirEntry, DirEntry
     picker
 }
 
+/// Returns a picker for the attachments of a single record, as paths relative to the attachment
+/// directory.
+pub fn choose_attachment_name(attachments: Vec<PathBuf>) -> Picker<PathBuf, PathRenderer> {
+    let mut picker = PickerOptions::new().match_paths().picker(PathRenderer);
+    picker.extend(attachments);
+    picker
+}
+
 /// Returns a picker which returns the record attachment data associated with the picked item.
 pub fn choose_attachment_path<F: FnMut(&Path) -> bool + Send + 'static>(
     mut record_db: RecordDatabase,
     template: Template,
     strict: bool,
+    tagged: Option<HashSet<String>>,
     attachment_root: PathBuf,
     ignore_hidden: bool,
     mut filter: F,
@@ -61,6 +71,12 @@ pub fn choose_attachment_path<F: FnMut(&Path) -> bool + Send + 'static>(
             if strict && !injector.renderer().has_keys_contained_in(&row_data) {
                 return None;
             }
+            if tagged
+                .as_ref()
+                .is_some_and(|tagged| !tagged.contains(row_data.canonical.name()))
+            {
+                return None;
+            }
 
             // fill the buffer with the attachment path
             let mut attachment_root = attachment_root.to_path_buf();
@@ -107,17 +123,43 @@ pub fn choose_attachment_path<F: FnMut(&Path) -> bool + Send + 'static>(
 /// Returns a picker which returns the record data associated with the picked item.
 #[allow(clippy::type_complexity)]
 pub fn choose_canonical_id(
-    mut record_db: RecordDatabase,
+    record_db: RecordDatabase,
     template: Template,
     strict: bool,
+    tagged: Option<HashSet<String>>,
 ) -> (
     Picker<RecordRow<RawEntryData>, Template>,
     thread::JoinHandle<Result<RecordDatabase, rusqlite::Error>>,
 ) {
-    // initialize picker
-    let picker = Picker::new(template);
+    populate_canonical_ids(Picker::new(template), record_db, strict, tagged)
+}
 
-    // populate the picker from a separate thread
+/// Returns a picker which returns the record data associated with the picked item, with the
+/// provided initial query.
+#[allow(clippy::type_complexity)]
+pub fn choose_canonical_id_with_query(
+    record_db: RecordDatabase,
+    template: Template,
+    query: &str,
+) -> (
+    Picker<RecordRow<RawEntryData>, Template>,
+    thread::JoinHandle<Result<RecordDatabase, rusqlite::Error>>,
+) {
+    let picker = PickerOptions::new().query(query).picker(template);
+    populate_canonical_ids(picker, record_db, false, None)
+}
+
+/// Populate the picker with the active records from a separate thread.
+#[allow(clippy::type_complexity)]
+fn populate_canonical_ids(
+    picker: Picker<RecordRow<RawEntryData>, Template>,
+    mut record_db: RecordDatabase,
+    strict: bool,
+    tagged: Option<HashSet<String>>,
+) -> (
+    Picker<RecordRow<RawEntryData>, Template>,
+    thread::JoinHandle<Result<RecordDatabase, rusqlite::Error>>,
+) {
     let injector = picker.injector();
     let handle = thread::spawn(move || {
         // TODO: to better support cancellation here, we could use an Arc<AtomicBool>
@@ -125,7 +167,11 @@ pub fn choose_canonical_id(
         // page size (maybe 10k? this should take <1ms per page), and then check for cancellation
         // between pages.
         record_db.inject_active_records(injector.clone(), |row_data| {
-            if strict && !injector.renderer().has_keys_contained_in(&row_data) {
+            if (strict && !injector.renderer().has_keys_contained_in(&row_data))
+                || tagged
+                    .as_ref()
+                    .is_some_and(|tagged| !tagged.contains(row_data.canonical.name()))
+            {
                 None
             } else {
                 Some(row_data)
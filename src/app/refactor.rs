@@ -0,0 +1,149 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use anyhow::bail;
+
+use crate::{
+    Alias, Config, RecordId,
+    cite_search::{SourceFileType, citekey_ranges},
+    db::RecordDatabase,
+    http::Client,
+    logger::{info, suggest},
+    record::{RecordRowResponse, get_record_row},
+};
+
+/// The result of renaming the citations of a key in a single file.
+pub struct FileEdit {
+    /// The path to the file.
+    pub path: PathBuf,
+    /// The original contents of the file.
+    original: Vec<u8>,
+    /// The contents of the file after renaming the citations.
+    renamed: Vec<u8>,
+    /// The number of citations which were renamed.
+    pub count: usize,
+}
+
+impl FileEdit {
+    /// Write the changes to the file as a unified diff.
+    ///
+    /// Since citation keys cannot contain line breaks, every line of the original file
+    /// corresponds to exactly one line of the renamed file.
+    pub fn write_diff<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let path = self.path.display();
+        writeln!(writer, "--- {path}")?;
+        writeln!(writer, "+++ {path}")?;
+        let original_lines = self.original.split(|b| *b == b'\n');
+        let renamed_lines = self.renamed.split(|b| *b == b'\n');
+        for (idx, (original, renamed)) in original_lines.zip(renamed_lines).enumerate() {
+            if original != renamed {
+                let line = idx + 1;
+                writeln!(writer, "@@ -{line} +{line} @@")?;
+                writeln!(writer, "-{}", String::from_utf8_lossy(original))?;
+                writeln!(writer, "+{}", String::from_utf8_lossy(renamed))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the renamed contents to the file.
+    pub fn apply(&self) -> io::Result<()> {
+        fs::write(&self.path, &self.renamed)
+    }
+}
+
+/// Rename the citations of `old` to `new` in the provided files, returning the edits for the
+/// files which contain at least one citation of `old`.
+///
+/// Files are only read; the changes are written with [`FileEdit::apply`].
+pub fn rename_citations(
+    paths: Vec<PathBuf>,
+    old: &str,
+    new: &str,
+) -> Result<Vec<FileEdit>, anyhow::Error> {
+    let mut edits = Vec::new();
+    for path in paths {
+        let file_type = match SourceFileType::detect(&path) {
+            Ok(ft @ (SourceFileType::Tex | SourceFileType::Md | SourceFileType::Typ)) => ft,
+            _ => {
+                info!(
+                    "Skipping '{}': citation keys can only be renamed in TeX, Markdown, and Typst files",
+                    path.display()
+                );
+                continue;
+            }
+        };
+
+        let original = match fs::read(&path) {
+            Ok(original) => original,
+            Err(err) => bail!("Failed to read file '{}': {err}", path.display()),
+        };
+
+        let ranges = citekey_ranges(file_type, &original, old);
+        if ranges.is_empty() {
+            continue;
+        }
+
+        let mut renamed = Vec::with_capacity(original.len());
+        let mut pos = 0;
+        for range in &ranges {
+            renamed.extend_from_slice(&original[pos..range.start]);
+            renamed.extend_from_slice(new.as_bytes());
+            pos = range.end;
+        }
+        renamed.extend_from_slice(&original[pos..]);
+
+        edits.push(FileEdit {
+            path,
+            original,
+            renamed,
+            count: ranges.len(),
+        });
+    }
+    Ok(edits)
+}
+
+/// Update the database so that the alias `new` refers to the record of `old`.
+///
+/// If `old` is an alias, it is renamed to `new`. Otherwise, if `new` is not already defined, it
+/// is created as an alias for the record with identifier `old`, retrieving the record if
+/// necessary.
+pub fn update_alias<F, C>(
+    record_db: &mut RecordDatabase,
+    old: RecordId,
+    new: &Alias,
+    client: &C,
+    config: &Config<F>,
+) -> Result<(), anyhow::Error>
+where
+    F: FnOnce() -> Vec<(regex::Regex, String)>,
+    C: Client,
+{
+    if let Ok(old_alias) = Alias::try_from(old.clone())
+        && record_db.contains_alias(&old_alias)?
+    {
+        info!("Renaming alias '{old_alias}' to '{new}'");
+        if record_db.contains_alias(new)? {
+            bail!("Alias already exists: '{new}'");
+        }
+        // the result is always `Renamed`, since we checked that the new alias does not exist
+        let _ = record_db.rename_alias(&old_alias, new)?;
+    } else if record_db.contains_alias(new)? {
+        info!("Alias '{new}' already exists");
+    } else {
+        info!("Creating alias '{new}' for '{old}'");
+        let (_, row) = match get_record_row(record_db, old, client, config)? {
+            RecordRowResponse::NullAlias(alias) => {
+                suggest!("Create the alias with `autobib alias add {new} <IDENTIFIER>` first.");
+                bail!("Cannot determine the record for the undefined alias '{alias}'");
+            }
+            response => response.exists_or_commit_null("Cannot create alias for")?,
+        };
+        row.add_alias(new)?;
+        row.commit()?;
+    }
+    Ok(())
+}
@@ -6,11 +6,15 @@ use crate::{
         Tx,
         state::{DisambiguatedRecordRow, IsEntry, RecordIdState, State, replace_hard_unchecked},
     },
-    entry::{MutableEntryData, RawEntryData},
+    entry::{MutableEntryData, RawEntryData, changed_fields},
     logger::warn,
-    record::{Record, RecordId},
+    record::{Record, RecordId, RemoteId},
 };
 
+/// Replace the record corresponding to `identifier` with the record returned by `data_cb`.
+///
+/// On success, returns the canonical identifiers of the original record and of the replacement
+/// record.
 pub fn replace<'conn, F, G>(
     identifier: RecordId,
     tx: Tx<'conn>,
@@ -19,7 +23,7 @@ pub fn replace<'conn, F, G>(
     hard: bool,
     update_aliases: bool,
     on_conflict: OnConflict,
-) -> Result<(), anyhow::Error>
+) -> Result<Option<(RemoteId, RemoteId)>, anyhow::Error>
 where
     F: FnOnce() -> Vec<(regex::Regex, String)>,
     G: FnOnce(
@@ -48,7 +52,7 @@ where
                 );
             }
             // `set_failed` was already called here
-            None => return Ok(()),
+            None => return Ok(None),
         };
 
     // next, get the target data. maybe it doesn't exist in the database yet, so it
@@ -67,12 +71,19 @@ where
     let mut incoming_record = MutableEntryData::from_entry_data(&replacement_record.data);
     crate::app::edit::merge_record_data(
         on_conflict,
+        &cfg.merge,
         &mut incoming_record,
         Some(&original_record.data),
         &original_record.canonical,
+        &mut Vec::new(),
     )?;
+    let fields = changed_fields(&replacement_record.data, &incoming_record);
     let replacement_row =
         replacement_row.modify(&RawEntryData::from_entry_data(&incoming_record))?;
+    replacement_row.set_field_sources(
+        fields.iter().map(String::as_str),
+        &format!("replaced record {}", original_record.canonical),
+    )?;
 
     let (tx, replacement_row_id) = replacement_row.into_parts();
 
@@ -96,5 +107,8 @@ where
             .commit()?;
     }
 
-    Ok(())
+    Ok(Some((
+        original_record.canonical,
+        replacement_record.canonical,
+    )))
 }
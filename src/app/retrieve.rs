@@ -1,44 +1,53 @@
-use std::collections::{
-    BTreeMap,
-    btree_map::Entry::{Occupied, Vacant},
+use std::{
+    collections::{
+        BTreeMap, BTreeSet, HashMap, HashSet,
+        btree_map::Entry::{Occupied, Vacant},
+    },
+    convert::Infallible,
 };
 
+use chrono::{Local, TimeDelta};
+use itertools::Itertools;
 use nonempty::NonEmpty;
 use serde_bibtex::token::is_entry_key;
 
 use crate::{
     config::Config,
     db::{
-        RecordDatabase,
-        state::{IsEntry, RecordIdState, RecordRow, State},
+        Identifier, RecordDatabase,
+        state::{
+            ArbitraryData, IsEntry, RecordIdState, RecordRow, RemoteIdState, RevisionId, State,
+        },
     },
     entry::{Entry, EntryKey, RawEntryData},
-    error::Error,
+    error::{Error, FailureKind},
     http::Client,
-    logger::{error, reraise, suggest},
+    interrupt::{CatchInterrupt, interrupted},
+    logger::{error, info, reraise, reraise_as, suggest},
+    progress::Progress,
+    provider::BatchClient,
     record::{Record, RecordId, RecordRowResponse, RemoteId, get_record_row},
 };
 
-/// Group valid entries by their canonical id in order to catch duplicate entries.
-fn group_valid_entries_by_canonical<T>(
-    valid_entries: T,
-) -> BTreeMap<RemoteId, NonEmpty<Entry<RawEntryData>>>
-where
-    T: IntoIterator<Item = (Entry<RawEntryData>, RemoteId)>,
-{
-    let mut grouped_entries: BTreeMap<RemoteId, NonEmpty<Entry<RawEntryData>>> = BTreeMap::new();
-    for (bibtex_entry, canonical) in valid_entries {
-        match grouped_entries.entry(canonical) {
-            Occupied(e) => e.into_mut().push(bibtex_entry),
-            Vacant(e) => {
-                e.insert(NonEmpty::singleton(bibtex_entry));
-            }
+/// Add a valid entry to the entries grouped by their canonical id, in order to catch duplicate
+/// entries.
+fn group_valid_entry(
+    grouped_entries: &mut BTreeMap<RemoteId, NonEmpty<Entry<RawEntryData>>>,
+    bibtex_entry: Entry<RawEntryData>,
+    canonical: RemoteId,
+) {
+    match grouped_entries.entry(canonical) {
+        Occupied(e) => e.into_mut().push(bibtex_entry),
+        Vacant(e) => {
+            e.insert(NonEmpty::singleton(bibtex_entry));
         }
     }
-    grouped_entries
 }
 
 /// Retrieve and validate BibTeX entries.
+///
+/// If `Ctrl-C` is pressed, the identifier which is currently being retrieved is finished, and the
+/// entries which were retrieved so far are returned. The remaining identifiers are reported.
 pub fn retrieve_and_validate_entries<
     T: IntoIterator<Item = RecordId>,
     F: FnOnce() -> Vec<(regex::Regex, String)>,
@@ -51,21 +60,207 @@ pub fn retrieve_and_validate_entries<
     ignore_null: bool,
     config: &Config<F>,
 ) -> BTreeMap<RemoteId, NonEmpty<Entry<RawEntryData>>> {
-    let valid_entries = ids.into_iter().filter_map(|id| {
-        retrieve_and_validate_single_entry(
-            record_db,
-            id,
-            client,
-            retrieve_only,
-            ignore_null,
-            config,
-        )
+    let mut grouped_entries = BTreeMap::new();
+    let Ok(()) = stream_valid_entries(
+        ids,
+        record_db,
+        client,
+        retrieve_only,
+        ignore_null,
+        config,
+        |_, bibtex_entry, canonical| {
+            group_valid_entry(&mut grouped_entries, bibtex_entry, canonical);
+            Ok::<_, Infallible>(())
+        },
+    );
+    grouped_entries
+}
+
+/// Retrieve and validate BibTeX entries, passing each valid entry to `sink` as soon as it is
+/// retrieved, instead of collecting the entries.
+///
+/// The entries are passed in the order of the identifiers, and retrieval stops at the first error
+/// returned by `sink`. If `Ctrl-C` is pressed, the identifier which is currently being retrieved
+/// is finished, and the remaining identifiers are reported.
+pub fn stream_valid_entries<
+    T: IntoIterator<Item = RecordId>,
+    F: FnOnce() -> Vec<(regex::Regex, String)>,
+    C: Client,
+    E,
+>(
+    ids: T,
+    record_db: &mut RecordDatabase,
+    client: &C,
+    retrieve_only: bool,
+    ignore_null: bool,
+    config: &Config<F>,
+    mut sink: impl FnMut(&mut RecordDatabase, Entry<RawEntryData>, RemoteId) -> Result<(), E>,
+) -> Result<(), E> {
+    let ids: Vec<RecordId> = ids.into_iter().collect();
+    let total = ids.len();
+    let _catch = CatchInterrupt::new();
+
+    // the identifiers which are already in the database are looked up at once, and only the
+    // remaining identifiers are looked up one at a time
+    let mut cached = record_db
+        .lookup_entries(ids.iter().map(RecordId::name))
         .unwrap_or_else(|error| {
             reraise(&error);
-            None
-        })
-    });
-    group_valid_entries_by_canonical(valid_entries)
+            HashMap::new()
+        });
+
+    let client = BatchClient::new(client);
+    if client.batch_requests() {
+        let uncached: Vec<RecordId> = ids
+            .iter()
+            .filter(|id| !cached.contains_key(id.name()))
+            .cloned()
+            .collect();
+        client.prefetch(&unknown_remote_ids(&uncached, record_db, config));
+    }
+
+    let mut ids = ids.into_iter();
+    let mut progress = Progress::new("Retrieving", total);
+    while !interrupted()
+        && let Some(id) = ids.next()
+    {
+        let valid_entry = match cached
+            .remove(id.name())
+            .and_then(|row| cached_entry(&id, row, retrieve_only))
+        {
+            Some(valid_entry) => valid_entry,
+            None => retrieve_and_validate_single_entry(
+                record_db,
+                id,
+                &client,
+                retrieve_only,
+                ignore_null,
+                config,
+            )
+            .unwrap_or_else(|error| {
+                reraise_as(error.failure_kind(), &error);
+                None
+            }),
+        };
+        if let Some((bibtex_entry, canonical)) = valid_entry {
+            sink(record_db, bibtex_entry, canonical)?;
+        }
+        progress.inc();
+    }
+    drop(progress);
+
+    let remaining: Vec<RecordId> = ids.collect();
+    if !remaining.is_empty() {
+        error!(
+            "Interrupted after retrieving {} of {total} identifiers",
+            total - remaining.len()
+        );
+        suggest!("Remaining identifiers: {}", remaining.iter().join(", "));
+    }
+    Ok(())
+}
+
+/// Convert a record found by [`RecordDatabase::lookup_entries`] into the result of
+/// [`retrieve_and_validate_single_entry`].
+///
+/// Returns `None` if the identifier is not a valid BibTeX key, in which case the identifier must
+/// be retrieved with [`retrieve_and_validate_single_entry`] so that the error can suggest a
+/// valid key.
+fn cached_entry(
+    id: &RecordId,
+    row: RecordRow<RawEntryData>,
+    retrieve_only: bool,
+) -> Option<Option<(Entry<RawEntryData>, RemoteId)>> {
+    let key = EntryKey::try_new(id.name().to_owned()).ok()?;
+    info!("Found existing data for key {}", id.name());
+    if retrieve_only {
+        Some(None)
+    } else {
+        Some(Some((Entry::new(key, row.data), row.canonical)))
+    }
+}
+
+/// Determine the remote identifiers which are not present in the database, and which therefore
+/// need to be retrieved.
+fn unknown_remote_ids<F: FnOnce() -> Vec<(regex::Regex, String)>>(
+    ids: &[RecordId],
+    record_db: &mut RecordDatabase,
+    config: &Config<F>,
+) -> Vec<RemoteId> {
+    let mut remote_ids = Vec::new();
+    for id in ids {
+        let state = match record_db.state_from_record_id(id.clone(), &config.alias_transform) {
+            Ok(state) => state,
+            // errors are reported when the identifier is retrieved
+            Err(_) => continue,
+        };
+        let committed = match state {
+            RecordIdState::Unknown(unknown) => unknown.combine_and_commit().map(|mapped| {
+                remote_ids.push(mapped.mapped);
+            }),
+            RecordIdState::Entry(_, _, state) => state.commit(),
+            RecordIdState::Deleted(_, _, state) => state.commit(),
+            RecordIdState::Void(_, _, state) => state.commit(),
+            RecordIdState::NullRemoteId(_, state) => state.commit(),
+            RecordIdState::UndefinedAlias(_) | RecordIdState::InvalidRemoteId(_) => Ok(()),
+        };
+        if let Err(err) = committed {
+            reraise(&err);
+        }
+    }
+    remote_ids
+}
+
+/// Delete the cached null records of the identifiers which were last attempted at least `null_ttl`
+/// seconds ago, so that they are retrieved again. At most `budget` null records are deleted,
+/// starting with the oldest attempts.
+///
+/// Returns the number of null records which were deleted.
+pub fn expire_null_records<'a, F: FnOnce() -> Vec<(regex::Regex, String)>>(
+    ids: impl IntoIterator<Item = &'a RecordId>,
+    record_db: &mut RecordDatabase,
+    budget: usize,
+    null_ttl: u32,
+    config: &Config<F>,
+) -> Result<usize, rusqlite::Error> {
+    if budget == 0 {
+        return Ok(0);
+    }
+
+    let threshold = Local::now() - TimeDelta::seconds(null_ttl.into());
+    let mut expired = Vec::new();
+    for id in ids {
+        match record_db.state_from_record_id(id.clone(), &config.alias_transform)? {
+            RecordIdState::NullRemoteId(mapped, state) => {
+                let attempted = state.get_null_attempted()?;
+                state.commit()?;
+                if attempted <= threshold {
+                    expired.push((attempted, mapped.mapped));
+                }
+            }
+            RecordIdState::Unknown(unknown) => {
+                unknown.combine_and_commit()?;
+            }
+            RecordIdState::Entry(_, _, state) => state.commit()?,
+            RecordIdState::Deleted(_, _, state) => state.commit()?,
+            RecordIdState::Void(_, _, state) => state.commit()?,
+            RecordIdState::UndefinedAlias(_) | RecordIdState::InvalidRemoteId(_) => {}
+        }
+    }
+
+    // several identifiers may correspond to the same null record
+    expired.sort();
+    expired.dedup_by(|(_, left), (_, right)| left == right);
+
+    let mut num_expired = 0;
+    for (_, remote_id) in expired.into_iter().take(budget) {
+        if let RemoteIdState::Null(state) = record_db.state_from_remote_id(&remote_id)? {
+            info!("Retrying null record '{remote_id}'");
+            state.delete()?.commit()?;
+            num_expired += 1;
+        }
+    }
+    Ok(num_expired)
 }
 
 pub fn retrieve_entries_read_only<
@@ -78,14 +273,52 @@ pub fn retrieve_entries_read_only<
     ignore_null: bool,
     config: &Config<F>,
 ) -> BTreeMap<RemoteId, NonEmpty<Entry<RawEntryData>>> {
-    let valid_entries = ids.into_iter().filter_map(|record_id| {
-        retrieve_single_entry_read_only(record_db, record_id, retrieve_only, ignore_null, config)
-            .unwrap_or_else(|error| {
-                error!("{error}");
-                None
-            })
-    });
-    group_valid_entries_by_canonical(valid_entries)
+    let mut grouped_entries = BTreeMap::new();
+    let Ok(()) = stream_entries_read_only(
+        ids,
+        record_db,
+        retrieve_only,
+        ignore_null,
+        config,
+        |_, bibtex_entry, canonical| {
+            group_valid_entry(&mut grouped_entries, bibtex_entry, canonical);
+            Ok::<_, Infallible>(())
+        },
+    );
+    grouped_entries
+}
+
+/// Retrieve BibTeX entries which exist in the database, passing each entry to `sink` as soon as
+/// it is retrieved. Retrieval stops at the first error returned by `sink`.
+pub fn stream_entries_read_only<
+    T: IntoIterator<Item = RecordId>,
+    F: FnOnce() -> Vec<(regex::Regex, String)>,
+    E,
+>(
+    ids: T,
+    record_db: &mut RecordDatabase,
+    retrieve_only: bool,
+    ignore_null: bool,
+    config: &Config<F>,
+    mut sink: impl FnMut(&mut RecordDatabase, Entry<RawEntryData>, RemoteId) -> Result<(), E>,
+) -> Result<(), E> {
+    for record_id in ids {
+        let valid_entry = retrieve_single_entry_read_only(
+            record_db,
+            record_id,
+            retrieve_only,
+            ignore_null,
+            config,
+        )
+        .unwrap_or_else(|error| {
+            error!("{error}");
+            None
+        });
+        if let Some((bibtex_entry, canonical)) = valid_entry {
+            sink(record_db, bibtex_entry, canonical)?;
+        }
+    }
+    Ok(())
 }
 
 /// Retrieve a single BibTeX entry if it exists in the database, returning if it does not `Ok(None)` otherwise.
@@ -131,7 +364,7 @@ fn retrieve_single_entry_read_only<F: FnOnce() -> Vec<(regex::Regex, String)>>(
         }
         RecordIdState::NullRemoteId(remote_id, missing) => {
             if !ignore_null {
-                error!("Null record: '{remote_id}'");
+                error!(kind = FailureKind::NullRecord; "Null record: '{remote_id}'");
             }
             missing.commit()?;
             Ok(None)
@@ -143,7 +376,7 @@ fn retrieve_single_entry_read_only<F: FnOnce() -> Vec<(regex::Regex, String)>>(
             Ok(None)
         }
         RecordIdState::InvalidRemoteId(err) => {
-            reraise(&err);
+            reraise_as(FailureKind::InvalidIdentifier, &err);
             Ok(None)
         }
         RecordIdState::Unknown(unknown) => {
@@ -154,6 +387,136 @@ fn retrieve_single_entry_read_only<F: FnOnce() -> Vec<(regex::Regex, String)>>(
     }
 }
 
+/// Split an identifier of the form `<identifier>@<revision>` into the identifier and the
+/// revision. The identifier is returned unchanged if it does not end with a revision.
+fn split_revision(id: RecordId) -> (RecordId, Option<RevisionId>) {
+    if let Some((name, rev)) = id.name().rsplit_once('@')
+        && !name.is_empty()
+        && !rev.is_empty()
+        && rev.bytes().all(|b| b.is_ascii_hexdigit())
+        && let Ok(rev) = rev.parse()
+    {
+        (RecordId::from(name), Some(rev))
+    } else {
+        (id, None)
+    }
+}
+
+/// Partition the identifiers into those which refer to the current version of a record, and
+/// those which refer to a specific revision. If `rev` is provided, it applies to every
+/// identifier which does not specify its own revision.
+pub fn partition_revisions<T: IntoIterator<Item = RecordId>>(
+    ids: T,
+    rev: Option<RevisionId>,
+) -> (Vec<RecordId>, Vec<(RecordId, RevisionId)>) {
+    let mut current = Vec::new();
+    let mut revisions = Vec::new();
+    for id in ids {
+        match split_revision(id) {
+            (id, Some(rev)) => revisions.push((id, rev)),
+            (id, None) => match rev {
+                Some(rev) => revisions.push((id, rev)),
+                None => current.push(id),
+            },
+        }
+    }
+    (current, revisions)
+}
+
+/// Retrieve BibTeX entries from specific revisions, without changing the active version of the
+/// records, and add them to the grouped entries.
+pub fn retrieve_revision_entries<
+    T: IntoIterator<Item = (RecordId, RevisionId)>,
+    F: FnOnce() -> Vec<(regex::Regex, String)>,
+>(
+    revisions: T,
+    record_db: &mut RecordDatabase,
+    config: &Config<F>,
+    grouped_entries: &mut BTreeMap<RemoteId, NonEmpty<Entry<RawEntryData>>>,
+) {
+    for (id, rev) in revisions {
+        match retrieve_single_revision(record_db, id, rev, config) {
+            Ok(Some((bibtex_entry, canonical))) => match grouped_entries.entry(canonical) {
+                Occupied(e) => e.into_mut().push(bibtex_entry),
+                Vacant(e) => {
+                    e.insert(NonEmpty::singleton(bibtex_entry));
+                }
+            },
+            Ok(None) => {}
+            Err(error) => reraise_as(error.failure_kind(), &error),
+        }
+    }
+}
+
+/// Retrieve the BibTeX entry of a single revision, checking that the revision belongs to the
+/// record associated with the identifier.
+fn retrieve_single_revision<F: FnOnce() -> Vec<(regex::Regex, String)>>(
+    record_db: &mut RecordDatabase,
+    id: RecordId,
+    rev: RevisionId,
+    config: &Config<F>,
+) -> Result<Option<(Entry<RawEntryData>, RemoteId)>, Error> {
+    let (key, canonical) = match record_db.state_from_record_id(id, &config.alias_transform)? {
+        RecordIdState::Entry(key, row, state) => {
+            state.commit()?;
+            (key, row.canonical)
+        }
+        RecordIdState::Deleted(key, row, state) => {
+            state.commit()?;
+            (key, row.canonical)
+        }
+        RecordIdState::Void(key, row, state) => {
+            state.commit()?;
+            (key, row.canonical)
+        }
+        RecordIdState::NullRemoteId(remote_id, missing) => {
+            missing.commit()?;
+            error!(kind = FailureKind::NullRecord; "Null record: '{remote_id}'");
+            return Ok(None);
+        }
+        RecordIdState::UndefinedAlias(alias) => {
+            error!("Undefined alias: '{alias}'");
+            return Ok(None);
+        }
+        RecordIdState::InvalidRemoteId(err) => {
+            reraise_as(FailureKind::InvalidIdentifier, &err);
+            return Ok(None);
+        }
+        RecordIdState::Unknown(unknown) => {
+            let mapped = unknown.combine_and_commit()?;
+            error!("Database does not contain key: {mapped}");
+            return Ok(None);
+        }
+    };
+
+    let tx = record_db.transaction()?;
+    let row = RecordRow::<ArbitraryData>::load(&tx, rev)?;
+    tx.commit()?;
+
+    let Some(row) = row.filter(|row| row.canonical == canonical) else {
+        error!("Record '{canonical}' does not have {rev}");
+        return Ok(None);
+    };
+
+    match row.data {
+        ArbitraryData::Entry(data) => match EntryKey::try_new(key) {
+            Ok(key) => Ok(Some((Entry::new(key, data), canonical))),
+            Err(parse_result) => {
+                reraise(&parse_result);
+                Ok(None)
+            }
+        },
+        ArbitraryData::Deleted(_) => {
+            error!("Cannot output deleted data from {rev} of record '{canonical}'");
+            Ok(None)
+        }
+        ArbitraryData::Void => {
+            error!("Cannot output voided data from {rev} of record '{canonical}'");
+            Ok(None)
+        }
+    }
+}
+
 /// Retrieve and validate a single BibTeX entry.
 fn retrieve_and_validate_single_entry<F, C>(
     record_db: &mut RecordDatabase,
@@ -196,7 +559,7 @@ where
         }
         RecordRowResponse::NullRemoteId(remote_id, missing) => {
             if !ignore_null {
-                error!("Null record: '{remote_id}'");
+                error!(kind = FailureKind::NullRecord; "Null record: '{remote_id}'");
             }
             missing.commit()?;
             Ok(None)
@@ -208,7 +571,7 @@ where
             Ok(None)
         }
         RecordRowResponse::InvalidRemoteId(err) => {
-            reraise(&err);
+            reraise_as(FailureKind::InvalidIdentifier, &err);
             Ok(None)
         }
     }
@@ -244,3 +607,47 @@ fn validate_bibtex_key(key: String, row: &State<IsEntry>) -> Option<EntryKey<Str
         }
     }
 }
+
+/// Add the entries of the records which are related to the records in `grouped_entries`,
+/// following chains of relations. Records whose canonical identifier is in `skipped` are not
+/// added.
+///
+/// If `all` is false, only the parents which were referenced by the `crossref` and `xdata` fields
+/// of imported entries are added.
+pub fn retrieve_related_entries<F: FnOnce() -> Vec<(regex::Regex, String)>>(
+    record_db: &mut RecordDatabase,
+    config: &Config<F>,
+    grouped_entries: &mut BTreeMap<RemoteId, NonEmpty<Entry<RawEntryData>>>,
+    skipped: &HashSet<RecordId>,
+    all: bool,
+) -> Result<(), rusqlite::Error> {
+    let mut seen: BTreeSet<RemoteId> = grouped_entries.keys().cloned().collect();
+    let mut queue: Vec<RemoteId> = seen.iter().cloned().collect();
+    let mut related = Vec::new();
+    while let Some(canonical) = queue.pop() {
+        let neighbours = if all {
+            record_db
+                .relations(&canonical)?
+                .into_iter()
+                .flat_map(|relation| [relation.canonical, relation.target])
+                .map(RemoteId::from_string_unchecked)
+                .collect()
+        } else {
+            record_db.parents(&canonical)?
+        };
+        for other in neighbours {
+            let record_id = RecordId::from(other.name());
+            if !skipped.contains(&record_id) && seen.insert(other.clone()) {
+                related.push(record_id);
+                queue.push(other);
+            }
+        }
+    }
+
+    for (canonical, entry_group) in
+        retrieve_entries_read_only(related, record_db, false, false, config)
+    {
+        grouped_entries.entry(canonical).or_insert(entry_group);
+    }
+    Ok(())
+}
@@ -0,0 +1,403 @@
+//! # A minimal JSON API over HTTP
+//!
+//! The server keeps the database connection open and answers requests sequentially, so that
+//! editor integrations can perform lookups without paying the startup cost of a new process for
+//! every query. Since a single connection blocks every other connection while it is being
+//! handled, reads and writes time out after [`CONNECTION_TIMEOUT`]. Only `GET` requests are
+//! supported, and every response other than the dump is a JSON document.
+//!
+//! The following endpoints are available:
+//!
+//! - `/get?id=<identifier>`: the data associated with an identifier.
+//! - `/search?q=<terms>&limit=<n>`: the records containing every search term.
+//! - `/list?canonical=<bool>&deleted=<bool>`: the identifiers in the database.
+//! - `/dump`: a dump of the database in the JSON lines format, as written by `autobib util export`.
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    ops::ControlFlow,
+    time::Duration,
+};
+
+use anyhow::Result;
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+    config::AliasTransform,
+    db::{
+        RecordDatabase,
+        state::{InRecordsTable, RecordIdState, RecordRowDisplay, State},
+    },
+    entry::EntryData,
+    logger::{info, warn},
+    record::RecordId,
+};
+
+/// The default maximum number of results returned by the `/search` endpoint.
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+
+/// The maximum amount of time to wait when reading a request or writing a response.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The maximum number of bytes read from the request line and the headers of a request.
+const MAX_REQUEST_HEAD_LEN: u64 = 16 * 1024;
+
+/// A connection to a client.
+pub trait Connection: io::Read + Write {
+    /// Set the timeout for both reads from and writes to the connection.
+    fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.set_read_timeout(timeout)?;
+        self.set_write_timeout(timeout)
+    }
+}
+
+#[cfg(unix)]
+impl Connection for std::os::unix::net::UnixStream {
+    fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.set_read_timeout(timeout)?;
+        self.set_write_timeout(timeout)
+    }
+}
+
+/// A response to a request.
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn ok<T: Serialize>(body: &T) -> Self {
+        match serde_json::to_vec(body) {
+            Ok(body) => Self {
+                status: "200 OK",
+                content_type: "application/json",
+                body,
+            },
+            Err(err) => Self::error("500 Internal Server Error", err),
+        }
+    }
+
+    fn error(status: &'static str, message: impl std::fmt::Display) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: json!({ "error": message.to_string() })
+                .to_string()
+                .into_bytes(),
+        }
+    }
+
+    fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(
+            writer,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            self.content_type,
+            self.body.len()
+        )?;
+        writer.write_all(&self.body)?;
+        writer.flush()
+    }
+}
+
+/// The response of the `/get` endpoint.
+#[derive(Serialize)]
+struct GetResponse<'a> {
+    identifier: &'a str,
+    #[serde(flatten)]
+    row: RecordRowDisplay<'a>,
+}
+
+/// A single result of the `/search` endpoint.
+#[derive(Serialize)]
+struct SearchResult {
+    canonical: String,
+    entry_type: String,
+    fields: BTreeMap<String, String>,
+}
+
+/// Decode a percent-encoded query string component, in which `+` encodes a space.
+fn decode_component(s: &str) -> String {
+    percent_decode_str(&s.replace('+', " "))
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+/// Parse the query string of a request target into key-value pairs.
+fn parse_query(query: &str) -> BTreeMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, val) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode_component(key), decode_component(val))
+        })
+        .collect()
+}
+
+/// Parse a boolean query parameter, which is `false` if missing.
+fn parse_flag(params: &BTreeMap<String, String>, name: &str) -> Result<bool, Response> {
+    match params.get(name).map(String::as_str) {
+        None | Some("false" | "0") => Ok(false),
+        Some("" | "true" | "1") => Ok(true),
+        Some(other) => Err(Response::error(
+            "400 Bad Request",
+            format!("Invalid value '{other}' for parameter '{name}'"),
+        )),
+    }
+}
+
+/// Serialize the current version of a row in the 'Records' table.
+fn row_response<I: InRecordsTable>(identifier: &str, state: State<'_, I>) -> Result<Response> {
+    let response = {
+        let version = state.current()?;
+        Response::ok(&GetResponse {
+            identifier,
+            row: version.display(false),
+        })
+    };
+    state.commit()?;
+    Ok(response)
+}
+
+fn get<A: AliasTransform>(
+    record_db: &mut RecordDatabase,
+    identifier: &str,
+    alias_transform: &A,
+) -> Result<Response> {
+    const NOT_FOUND: &str = "404 Not Found";
+
+    match record_db.state_from_record_id(RecordId::from(identifier), alias_transform)? {
+        RecordIdState::Entry(_, _, state) => row_response(identifier, state),
+        RecordIdState::Deleted(_, _, state) => row_response(identifier, state),
+        RecordIdState::Void(_, _, state) => row_response(identifier, state),
+        RecordIdState::NullRemoteId(remote_id, state) => {
+            state.commit()?;
+            Ok(Response::error(
+                NOT_FOUND,
+                format!("Null record: {remote_id}"),
+            ))
+        }
+        RecordIdState::Unknown(unknown) => {
+            let mapped = unknown.combine_and_commit()?;
+            Ok(Response::error(
+                NOT_FOUND,
+                format!("Record not in database: {mapped}"),
+            ))
+        }
+        RecordIdState::UndefinedAlias(alias) => Ok(Response::error(
+            NOT_FOUND,
+            format!("Undefined alias: '{alias}'"),
+        )),
+        RecordIdState::InvalidRemoteId(err) => Ok(Response::error("400 Bad Request", err)),
+    }
+}
+
+fn search(record_db: &mut RecordDatabase, query: &str, limit: usize) -> Result<Response> {
+    let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    let mut results: Vec<SearchResult> = Vec::new();
+
+    if limit == 0 {
+        return Ok(Response::ok(&results));
+    }
+
+    record_db.try_map_active_records(|row| {
+        let canonical = row.canonical.to_string();
+        let haystack: Vec<String> = std::iter::once(canonical.to_lowercase())
+            .chain(row.data.fields().map(|(_, val)| val.to_lowercase()))
+            .collect();
+
+        if terms
+            .iter()
+            .all(|term| haystack.iter().any(|s| s.contains(term.as_str())))
+        {
+            results.push(SearchResult {
+                canonical,
+                entry_type: row.data.entry_type().to_owned(),
+                fields: row
+                    .data
+                    .fields()
+                    .map(|(key, val)| (key.to_owned(), val.to_owned()))
+                    .collect(),
+            });
+            if results.len() >= limit {
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    })?;
+
+    Ok(Response::ok(&results))
+}
+
+fn list(record_db: &mut RecordDatabase, canonical: bool, deleted: bool) -> Result<Response> {
+    let snapshot = record_db.snapshot()?;
+    let mut identifiers: Vec<String> = Vec::new();
+    if canonical {
+        snapshot.map_canonical_identifiers(deleted, |remote_id| {
+            identifiers.push(remote_id.to_string());
+            Ok::<_, Infallible>(())
+        })?;
+    } else {
+        snapshot.map_identifiers(deleted, |key_str| {
+            identifiers.push(key_str.to_owned());
+            Ok::<_, Infallible>(())
+        })?;
+    }
+    snapshot.commit()?;
+    Ok(Response::ok(&identifiers))
+}
+
+fn dump(record_db: &mut RecordDatabase) -> Result<Response> {
+    let mut body = Vec::new();
+    record_db.export_dump(&mut body)?;
+    Ok(Response {
+        status: "200 OK",
+        content_type: "application/x-ndjson",
+        body,
+    })
+}
+
+/// Determine the response to a request from the request line.
+fn respond<A: AliasTransform>(
+    record_db: &mut RecordDatabase,
+    request_line: &str,
+    alias_transform: &A,
+) -> Result<Response> {
+    let mut parts = request_line.split_ascii_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Ok(Response::error("400 Bad Request", "Malformed request"));
+    };
+
+    if method != "GET" {
+        return Ok(Response::error(
+            "405 Method Not Allowed",
+            format!("Unsupported method '{method}'"),
+        ));
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let params = parse_query(query);
+
+    match path {
+        "/get" => match params.get("id") {
+            Some(identifier) => get(record_db, identifier, alias_transform),
+            None => Ok(Response::error("400 Bad Request", "Missing parameter 'id'")),
+        },
+        "/search" => {
+            let limit = match params.get("limit").map(|l| l.parse()) {
+                None => DEFAULT_SEARCH_LIMIT,
+                Some(Ok(limit)) => limit,
+                Some(Err(err)) => {
+                    return Ok(Response::error(
+                        "400 Bad Request",
+                        format!("Invalid value for parameter 'limit': {err}"),
+                    ));
+                }
+            };
+            search(record_db, params.get("q").map_or("", String::as_str), limit)
+        }
+        "/list" => {
+            let flags = parse_flag(&params, "canonical")
+                .and_then(|canonical| Ok((canonical, parse_flag(&params, "deleted")?)));
+            match flags {
+                Ok((canonical, deleted)) => list(record_db, canonical, deleted),
+                Err(response) => Ok(response),
+            }
+        }
+        "/dump" => dump(record_db),
+        _ => Ok(Response::error(
+            "404 Not Found",
+            format!("Unknown endpoint '{path}'"),
+        )),
+    }
+}
+
+/// Read a single request from the stream and write the response.
+fn handle_connection<S: Connection, A: AliasTransform>(
+    record_db: &mut RecordDatabase,
+    mut stream: S,
+    alias_transform: &A,
+) -> io::Result<()> {
+    stream.set_timeout(Some(CONNECTION_TIMEOUT))?;
+
+    let mut request_line = String::new();
+    let too_large = {
+        let mut reader = BufReader::new((&mut stream).take(MAX_REQUEST_HEAD_LEN));
+        reader.read_line(&mut request_line)?;
+
+        // the request body is never used, so we only need to consume the headers
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 0 && !header.trim_end().is_empty() {
+            header.clear();
+        }
+
+        // the limit was reached before the empty line which ends the headers
+        !header.ends_with('\n') && reader.get_ref().limit() == 0
+    };
+
+    let response = if too_large {
+        warn!("Rejecting request which exceeds {MAX_REQUEST_HEAD_LEN} bytes");
+        Response::error(
+            "431 Request Header Fields Too Large",
+            "Request line and headers are too large",
+        )
+    } else {
+        info!("Received request: {}", request_line.trim_end());
+        respond(record_db, &request_line, alias_transform)
+            .unwrap_or_else(|err| Response::error("500 Internal Server Error", err))
+    };
+    response.write_to(stream)
+}
+
+/// Bind a Unix domain socket at the provided path.
+///
+/// A socket file left behind by a server which is no longer running is removed first, but an
+/// error is returned if another server is still listening on the socket.
+#[cfg(unix)]
+pub fn bind_unix_socket(path: &std::path::Path) -> Result<std::os::unix::net::UnixListener> {
+    use std::os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    };
+
+    if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        if UnixStream::connect(path).is_ok() {
+            anyhow::bail!(
+                "Another server is already listening on '{}'",
+                path.display()
+            );
+        }
+        info!("Removing stale socket '{}'", path.display());
+        std::fs::remove_file(path)?;
+    }
+
+    Ok(UnixListener::bind(path)?)
+}
+
+/// Answer requests from incoming connections until the listener is closed.
+///
+/// Errors in individual connections are reported but do not stop the server.
+pub fn serve<S, I, A>(record_db: &mut RecordDatabase, incoming: I, alias_transform: &A)
+where
+    S: Connection,
+    I: IntoIterator<Item = io::Result<S>>,
+    A: AliasTransform,
+{
+    for stream in incoming {
+        if let Err(err) =
+            stream.and_then(|stream| handle_connection(record_db, stream, alias_transform))
+        {
+            warn!("Failed to handle connection: {err}");
+        }
+    }
+}
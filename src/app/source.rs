@@ -1,16 +1,117 @@
 use std::{
-    fs::File,
-    io::{Read, stdin},
+    collections::BTreeSet,
+    fs::{File, metadata},
+    io::{Read, Write, stdin},
+    num::NonZeroUsize,
+    panic::resume_unwind,
+    path::{Path, PathBuf},
+    thread::{self, available_parallelism, sleep},
+    time::{Duration, SystemTime},
 };
 
 use anyhow::bail;
+use walkdir::{DirEntry, WalkDir};
 
 use crate::{
-    RecordId,
+    Identifier, RecordId,
     cite_search::{SourceFileType, get_citekeys_filter},
+    config::RawSourceConfig,
     logger::{error, info},
+    output::stdout_lock_wrap,
 };
 
+/// Whether or not the name matches the glob pattern, in which `*` matches any sequence of
+/// characters and `?` matches any single character.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // the position of the last `*` in the pattern, and the position in the name at which it
+    // started matching
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, start)) => {
+                    p = star + 1;
+                    n = start + 1;
+                    backtrack = Some((star, start + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Whether or not a file found while searching a directory should be read, which is the case if
+/// the file type is detected (or matches the provided override) and the file type is not a
+/// BibTeX or text file, since these are not usually citation sources.
+fn is_source_file(path: &std::path::Path, file_type: Option<SourceFileType>) -> bool {
+    match SourceFileType::detect(path) {
+        Ok(SourceFileType::Bib | SourceFileType::Txt) | Err(_) => false,
+        Ok(detected) => file_type.is_none_or(|ft| ft == detected),
+    }
+}
+
+/// Replace any directories in the provided paths with the files of a supported type which they
+/// contain, searching recursively.
+///
+/// Files and directories whose names match one of the `ignore` patterns in the configuration are
+/// skipped, as are hidden files and directories unless `include_hidden` is set. Paths which are
+/// not directories are kept as-is.
+pub fn expand_directories(
+    paths: Vec<PathBuf>,
+    file_type: Option<SourceFileType>,
+    config: &RawSourceConfig,
+) -> Vec<PathBuf> {
+    let is_ignored = |entry: &DirEntry| {
+        // the root directory is never ignored
+        entry.depth() > 0
+            && entry.file_name().to_str().is_some_and(|name| {
+                (!config.include_hidden && name.starts_with('.'))
+                    || config
+                        .ignore
+                        .iter()
+                        .any(|pattern| glob_match(pattern, name))
+            })
+    };
+
+    let mut expanded = Vec::with_capacity(paths.len());
+    for path in paths {
+        if !path.is_dir() {
+            expanded.push(path);
+            continue;
+        }
+
+        info!("Searching directory '{}'", path.display());
+        for entry in WalkDir::new(&path)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| !is_ignored(entry))
+        {
+            match entry {
+                Ok(entry) => {
+                    if entry.file_type().is_file() && is_source_file(entry.path(), file_type) {
+                        expanded.push(entry.into_path());
+                    }
+                }
+                Err(err) => error!("Failed to search directory '{}': {err}", path.display()),
+            }
+        }
+    }
+    expanded
+}
+
 pub fn get_citekeys_from_file<T: Extend<RecordId>, P: AsRef<std::path::Path>>(
     read_from: P,
     file_type: Option<SourceFileType>,
@@ -80,3 +181,152 @@ pub fn get_citekeys_from_file_filter<
         ),
     }
 }
+
+/// The minimum number of files read by each thread in [`get_citekeys_from_files`], so that small
+/// projects are read without starting threads.
+const MIN_FILES_PER_THREAD: usize = 16;
+
+/// Read the citation keys from each of the files, excluding the keys for which `exclude` returns
+/// `false`, in the same way as [`get_citekeys_from_file_filter`].
+///
+/// The files are split into consecutive chunks which are read in parallel, each with its own
+/// buffer. The keys of each file, or the error which occurred while reading the file, are
+/// returned in the order of the paths, so that the result does not depend on the number of
+/// threads.
+pub fn get_citekeys_from_files<E: Fn(&RecordId) -> bool + Sync>(
+    paths: &[PathBuf],
+    file_type: Option<SourceFileType>,
+    exclude: E,
+) -> Vec<Result<Vec<RecordId>, anyhow::Error>> {
+    let read_chunk = |chunk: &[PathBuf]| {
+        let mut scratch = Vec::new();
+        chunk
+            .iter()
+            .map(|path| {
+                let mut keys = Vec::new();
+                get_citekeys_from_file_filter(
+                    path,
+                    file_type,
+                    &mut keys,
+                    &mut scratch,
+                    "--file-type",
+                    &exclude,
+                )
+                .map(|()| keys)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let num_threads = available_parallelism().map_or(1, NonZeroUsize::get);
+    let chunk_size = paths.len().div_ceil(num_threads).max(MIN_FILES_PER_THREAD);
+    if paths.len() <= chunk_size {
+        return read_chunk(paths);
+    }
+
+    let read_chunk = &read_chunk;
+    thread::scope(|scope| {
+        let handles: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || read_chunk(chunk)))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap_or_else(|err| resume_unwind(err)))
+            .collect()
+    })
+}
+
+/// Print the keys which are cited but missing from the bibliography file, followed by the keys
+/// in the bibliography file which are not cited.
+pub fn write_key_diff(
+    bib_path: &Path,
+    cited: &BTreeSet<RecordId>,
+    present: &BTreeSet<RecordId>,
+) -> std::io::Result<()> {
+    let missing: Vec<_> = cited.difference(present).collect();
+    let unused: Vec<_> = present.difference(cited).collect();
+    if missing.is_empty() && unused.is_empty() {
+        info!(
+            "The keys in '{}' are exactly the cited keys",
+            bib_path.display()
+        );
+        return Ok(());
+    }
+
+    let mut lock = stdout_lock_wrap();
+    if !missing.is_empty() {
+        writeln!(lock, "Cited but missing from '{}':", bib_path.display())?;
+        for key in missing {
+            writeln!(lock, "  {}", key.name())?;
+        }
+    }
+    if !unused.is_empty() {
+        writeln!(lock, "Present in '{}' but not cited:", bib_path.display())?;
+        for key in unused {
+            writeln!(lock, "  {}", key.name())?;
+        }
+    }
+    Ok(())
+}
+
+/// The interval at which watched files are checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Detect changes to a collection of files by polling their modification times.
+pub struct FileWatcher<'a> {
+    paths: &'a [PathBuf],
+    modified: Vec<Option<SystemTime>>,
+}
+
+impl<'a> FileWatcher<'a> {
+    /// Start watching the provided files.
+    pub fn new(paths: &'a [PathBuf]) -> Self {
+        Self {
+            paths,
+            modified: modified_times(paths),
+        }
+    }
+
+    /// Block until at least one of the files has changed, and then until the files have not
+    /// changed for one interval, so that a file which is still being written is not read.
+    pub fn wait(&mut self) {
+        let mut changed = false;
+        loop {
+            sleep(WATCH_INTERVAL);
+            let modified = modified_times(self.paths);
+            if modified != self.modified {
+                self.modified = modified;
+                changed = true;
+            } else if changed {
+                return;
+            }
+        }
+    }
+}
+
+/// The modification times of the files, or `None` if a file is missing.
+fn modified_times(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|path| metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("build", "build"));
+        assert!(!glob_match("build", "builds"));
+        assert!(glob_match("*.tmp", "notes.tmp"));
+        assert!(glob_match("*.tmp", ".tmp"));
+        assert!(!glob_match("*.tmp", "notes.tmp.tex"));
+        assert!(glob_match("draft-?.tex", "draft-1.tex"));
+        assert!(!glob_match("draft-?.tex", "draft-10.tex"));
+        assert!(glob_match("*draft*", "old-draft-2.tex"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("?", ""));
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::{self, Write},
+};
+
+use serde::Serialize;
+
+use crate::{
+    db::{Identifier, RecordDatabase},
+    entry::EntryData,
+};
+
+/// The year which is reported for records without a `year` or `date` field.
+const UNKNOWN_YEAR: &str = "unknown";
+
+/// Summary statistics of the records in the database.
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    /// The number of active records.
+    records: usize,
+    /// The number of active records with at least one tracked attachment.
+    with_attachments: usize,
+    /// The number of active records without tracked attachments.
+    without_attachments: usize,
+    /// The number of cached null records.
+    null_records: usize,
+    /// The number of revisions of all records, including deleted and void records.
+    revisions: usize,
+    /// The number of runs in the change log.
+    runs: usize,
+    /// The number of active records for each provider.
+    providers: BTreeMap<String, usize>,
+    /// The number of active records for each entry type.
+    entry_types: BTreeMap<String, usize>,
+    /// The number of active records for each year.
+    years: BTreeMap<String, usize>,
+}
+
+/// The year of the record data, from the `year` field or the start of the `date` field.
+fn year<D: EntryData>(data: &D) -> Option<&str> {
+    if let Some(year) = data.get_field("year") {
+        return Some(year.trim());
+    }
+    let date = data.get_field("date")?.trim();
+    date.get(..4)
+        .filter(|year| year.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Compute the statistics of the records in the database.
+pub fn collect_stats(record_db: &mut RecordDatabase) -> Result<Stats, rusqlite::Error> {
+    let attached: HashSet<String> = record_db
+        .attachments()?
+        .into_iter()
+        .map(|attachment| attachment.canonical.name().to_owned())
+        .collect();
+
+    let mut stats = Stats::default();
+    record_db.map_active_records(|row| {
+        stats.records += 1;
+        if attached.contains(row.canonical.name()) {
+            stats.with_attachments += 1;
+        } else {
+            stats.without_attachments += 1;
+        }
+        *stats
+            .providers
+            .entry(row.canonical.provider().to_owned())
+            .or_default() += 1;
+        *stats
+            .entry_types
+            .entry(row.data.entry_type().to_owned())
+            .or_default() += 1;
+        *stats
+            .years
+            .entry(year(&row.data).unwrap_or(UNKNOWN_YEAR).to_owned())
+            .or_default() += 1;
+    })?;
+
+    stats.null_records = record_db.num_null_records()?;
+    (stats.revisions, stats.runs) = record_db.history_size()?;
+    Ok(stats)
+}
+
+/// Write the counts of a category, aligning the counts.
+fn write_counts<W: Write>(
+    writer: &mut W,
+    heading: &str,
+    counts: &BTreeMap<String, usize>,
+) -> io::Result<()> {
+    writeln!(writer, "{heading}:")?;
+    let width = counts
+        .keys()
+        .map(|key| key.chars().count())
+        .max()
+        .unwrap_or(0);
+    for (key, count) in counts {
+        writeln!(writer, "  {key:<width$}  {count}")?;
+    }
+    Ok(())
+}
+
+/// Write the statistics in a human-readable format.
+pub fn write_stats<W: Write>(writer: &mut W, stats: &Stats) -> io::Result<()> {
+    writeln!(writer, "Records: {}", stats.records)?;
+    writeln!(writer, "  with attachments: {}", stats.with_attachments)?;
+    writeln!(
+        writer,
+        "  without attachments: {}",
+        stats.without_attachments
+    )?;
+    writeln!(writer, "Null records: {}", stats.null_records)?;
+    writeln!(writer, "Revisions: {}", stats.revisions)?;
+    writeln!(writer, "Runs: {}", stats.runs)?;
+    if stats.records > 0 {
+        write_counts(writer, "Providers", &stats.providers)?;
+        write_counts(writer, "Entry types", &stats.entry_types)?;
+        write_counts(writer, "Years", &stats.years)?;
+    }
+    Ok(())
+}
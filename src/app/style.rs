@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+
+use itertools::Itertools;
+use nonempty::NonEmpty;
+
+use crate::{
+    entry::{Entry, EntryData, RawEntryData},
+    logger::warn,
+    record::RemoteId,
+};
+
+const AUTHOR: &[&str] = &["author"];
+const AUTHOR_OR_EDITOR: &[&str] = &["author", "editor"];
+const TITLE: &[&str] = &["title"];
+const YEAR: &[&str] = &["year"];
+
+/// The entry types supported by the standard BibTeX styles, along with the fields which are
+/// required to format the entry. Each element is a list of alternatives, at least one of which
+/// must be present.
+///
+/// This table is also used by `autobib check`, which additionally accepts the biblatex
+/// equivalents of the fields.
+pub(super) const STANDARD_ENTRY_TYPES: &[(&str, &[&[&str]])] = &[
+    ("article", &[AUTHOR, TITLE, &["journal"], YEAR]),
+    ("book", &[AUTHOR_OR_EDITOR, TITLE, &["publisher"], YEAR]),
+    ("booklet", &[TITLE]),
+    (
+        "inbook",
+        &[
+            AUTHOR_OR_EDITOR,
+            TITLE,
+            &["chapter", "pages"],
+            &["publisher"],
+            YEAR,
+        ],
+    ),
+    (
+        "incollection",
+        &[AUTHOR, TITLE, &["booktitle"], &["publisher"], YEAR],
+    ),
+    ("inproceedings", &[AUTHOR, TITLE, &["booktitle"], YEAR]),
+    ("conference", &[AUTHOR, TITLE, &["booktitle"], YEAR]),
+    ("manual", &[TITLE]),
+    ("mastersthesis", &[AUTHOR, TITLE, &["school"], YEAR]),
+    ("misc", &[&["howpublished"]]),
+    ("phdthesis", &[AUTHOR, TITLE, &["school"], YEAR]),
+    ("proceedings", &[TITLE, YEAR]),
+    ("techreport", &[AUTHOR, TITLE, &["institution"], YEAR]),
+    ("unpublished", &[AUTHOR, TITLE, &["note"]]),
+];
+
+/// The names of the built-in styles, all of which use [`STANDARD_ENTRY_TYPES`].
+const BUILTIN_STYLES: &[&str] = &["abbrv", "acm", "alpha", "ieeetr", "plain", "siam", "unsrt"];
+
+/// The requirements imposed by a BibTeX style on the entries which it formats.
+#[derive(Debug)]
+pub struct StyleRequirements {
+    name: String,
+    /// The required fields for each supported entry type.
+    entry_types: BTreeMap<String, Vec<String>>,
+}
+
+impl StyleRequirements {
+    /// Look up the requirements for the style with the provided name, first in the styles
+    /// defined in the configuration and then in the built-in styles.
+    pub fn lookup(
+        name: &str,
+        configured: &BTreeMap<String, BTreeMap<String, Vec<String>>>,
+    ) -> Option<Self> {
+        if let Some(entry_types) = configured.get(name) {
+            return Some(Self {
+                name: name.to_owned(),
+                entry_types: entry_types
+                    .iter()
+                    .map(|(ty, fields)| (ty.to_ascii_lowercase(), fields.clone()))
+                    .collect(),
+            });
+        }
+
+        BUILTIN_STYLES.contains(&name).then(|| Self {
+            name: name.to_owned(),
+            entry_types: STANDARD_ENTRY_TYPES
+                .iter()
+                .map(|(ty, fields)| {
+                    (
+                        (*ty).to_owned(),
+                        fields
+                            .iter()
+                            .map(|alternatives| alternatives.join("|"))
+                            .collect(),
+                    )
+                })
+                .collect(),
+        })
+    }
+
+    /// Warn about an entry which would not be formatted correctly by this style.
+    pub fn check_entry<D: EntryData>(&self, entry: &Entry<D>) {
+        let entry_type = entry.entry_type().to_ascii_lowercase();
+        let Some(required) = self.entry_types.get(&entry_type) else {
+            warn!(
+                "Entry '{}' has entry type '{entry_type}' which is not supported by style '{}'",
+                entry.key(),
+                self.name
+            );
+            return;
+        };
+
+        for alternatives in required {
+            if !alternatives
+                .split('|')
+                .any(|field| entry.data().contains_field(field.trim()))
+            {
+                warn!(
+                    "Entry '{}' with entry type '{entry_type}' is missing field '{}' required by style '{}'",
+                    entry.key(),
+                    alternatives.split('|').map(str::trim).join("' or '"),
+                    self.name
+                );
+            }
+        }
+    }
+
+    /// Warn about every entry which would not be formatted correctly by this style.
+    pub fn check_entries(&self, entries: &BTreeMap<RemoteId, NonEmpty<Entry<RawEntryData>>>) {
+        for entry in entries.values().flatten() {
+            self.check_entry(entry);
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use std::{
+    convert::Infallible,
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::{self, Child, Command, Stdio},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::bail;
+
+use crate::{
+    db::{RecordDatabase, SyncReport, lock_database},
+    error::FailureKind,
+    http::{BodyBytes, Client},
+    logger::{error, info, suggest},
+};
+
+/// The location of the database with which to synchronize.
+#[derive(Debug, Clone)]
+pub enum SyncRemote {
+    /// A database file.
+    Path(PathBuf),
+    /// A database file on another machine, which is copied with `scp`.
+    Ssh { host: String, path: String },
+    /// A server started with `autobib serve`.
+    Http(String),
+}
+
+impl FromStr for SyncRemote {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("http://") || s.starts_with("https://") {
+            Ok(Self::Http(s.trim_end_matches('/').to_owned()))
+        } else if let Some(rest) = s.strip_prefix("ssh://")
+            && let Some((host, path)) = rest.split_once('/')
+        {
+            // paths relative to the home directory are written as `ssh://host/~/path`
+            let path = if path.starts_with('~') {
+                path.to_owned()
+            } else {
+                format!("/{path}")
+            };
+            Ok(Self::Ssh {
+                host: host.to_owned(),
+                path,
+            })
+        } else {
+            Ok(Self::Path(s.into()))
+        }
+    }
+}
+
+/// Run `scp` to copy a file from `source` to `target`.
+fn scp(source: &str, target: &str) -> Result<(), anyhow::Error> {
+    match Command::new("scp").args(["-q", source, target]).status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => bail!("Failed to copy '{source}' to '{target}': scp exited with {status}"),
+        Err(err) => bail!("Failed to run scp: {err}"),
+    }
+}
+
+/// Run a shell command on the remote host with `ssh`.
+fn ssh(host: &str, command: &str) -> Result<(), anyhow::Error> {
+    match Command::new("ssh").args([host, command]).status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => bail!("Failed to run '{command}' on '{host}': ssh exited with {status}"),
+        Err(err) => bail!("Failed to run ssh: {err}"),
+    }
+}
+
+/// Quote a string as a single argument for the remote shell.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Quote a path for the remote shell, keeping a leading `~` so that it is expanded.
+fn shell_path(path: &str) -> String {
+    if path == "~" {
+        path.to_owned()
+    } else if let Some(rest) = path.strip_prefix("~/") {
+        format!("~/{}", shell_quote(rest))
+    } else {
+        shell_quote(path)
+    }
+}
+
+/// The lock on a remote database, which is held by an `ssh` process running `flock` on the lock
+/// file of the database until it is dropped.
+struct RemoteLock {
+    child: Child,
+}
+
+impl RemoteLock {
+    /// Lock the database on the remote host, failing if it is already locked.
+    fn acquire(host: &str, path: &str) -> Result<Self, anyhow::Error> {
+        // `cat` keeps the lock until its standard input is closed
+        let command = format!(
+            "flock -n {}.lock -c 'echo locked && exec cat'",
+            shell_path(path)
+        );
+        let mut child = match Command::new("ssh")
+            .args([host, &command])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(err) => bail!("Failed to run ssh: {err}"),
+        };
+
+        let mut line = String::new();
+        if let Some(stdout) = child.stdout.take() {
+            BufReader::new(stdout).read_line(&mut line)?;
+        }
+        if line.trim_end() == "locked" {
+            return Ok(Self { child });
+        }
+
+        let status = child.wait()?;
+        if status.code() == Some(1) {
+            bail!("Remote database '{host}:{path}' is locked by another autobib process");
+        }
+        bail!("Failed to lock remote database '{host}:{path}': ssh exited with {status}");
+    }
+}
+
+impl Drop for RemoteLock {
+    fn drop(&mut self) {
+        drop(self.child.stdin.take());
+        let _ = self.child.wait();
+    }
+}
+
+/// A temporary database file in the data directory, which is removed when dropped.
+struct TemporaryFile {
+    path: PathBuf,
+}
+
+impl TemporaryFile {
+    /// Create a new empty file in the data directory, failing if the file already exists.
+    fn create(data_dir: &Path, name: &str) -> Result<Self, anyhow::Error> {
+        fs::create_dir_all(data_dir)?;
+        let path = data_dir.join(format!("{name}-{}.db", process::id()));
+        match File::create_new(&path) {
+            Ok(_) => Ok(Self { path }),
+            Err(err) => bail!(
+                "Failed to create temporary file '{}': {err}",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl Drop for TemporaryFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Synchronize the database with a database on another machine.
+///
+/// The remote database is locked for the duration of the synchronization. A consistent copy of
+/// the remote database is written with `VACUUM INTO` and copied with `scp`, and after
+/// synchronization the updated copy is written back into the remote database with the
+/// `.restore` command of `sqlite3`, so that other connections to the remote database are not
+/// affected.
+fn sync_ssh(
+    record_db: &mut RecordDatabase,
+    host: &str,
+    path: &str,
+    data_dir: &Path,
+) -> Result<SyncReport, anyhow::Error> {
+    let Some((dir, name)) = path.rsplit_once('/') else {
+        bail!("Invalid remote database path '{path}'");
+    };
+    if name.contains(['\'', '"']) {
+        bail!("Remote database file name '{name}' must not contain quotes");
+    }
+    let dir = if dir.is_empty() { "/" } else { dir };
+    let copy_name = format!("{name}.sync");
+    let remote_copy = format!("{host}:{path}.sync");
+
+    let _lock = RemoteLock::acquire(host, path)?;
+
+    info!("Copying remote database from '{host}:{path}'");
+    ssh(
+        host,
+        &format!(
+            "cd {} && rm -f {copy} && sqlite3 {} {}",
+            shell_path(dir),
+            shell_quote(name),
+            shell_quote(&format!("VACUUM INTO '{copy_name}'")),
+            copy = shell_quote(&copy_name),
+        ),
+    )?;
+    let downloaded = TemporaryFile::create(data_dir, "sync-remote")?;
+    scp(&remote_copy, &downloaded.path.to_string_lossy())?;
+
+    let updated = TemporaryFile::create(data_dir, "sync-updated")?;
+    let report = {
+        let mut remote_db = RecordDatabase::open(&downloaded.path, false)?;
+        let report = record_db.sync_with(&mut remote_db)?;
+        remote_db.write_backup(&updated.path)?;
+        report
+    };
+
+    info!("Copying updated database to '{host}:{path}'");
+    scp(&updated.path.to_string_lossy(), &remote_copy)?;
+    ssh(
+        host,
+        &format!(
+            "cd {} && sqlite3 {} {} && rm -f {copy}",
+            shell_path(dir),
+            shell_quote(name),
+            shell_quote(&format!(".restore '{copy_name}'")),
+            copy = shell_quote(&copy_name),
+        ),
+    )?;
+    Ok(report)
+}
+
+/// Synchronize the database with the remote database, and report the changes.
+pub fn sync<C: Client>(
+    record_db: &mut RecordDatabase,
+    remote: SyncRemote,
+    data_dir: &Path,
+    client: &C,
+) -> Result<(), anyhow::Error> {
+    let report = match remote {
+        SyncRemote::Path(path) => {
+            if !path.is_file() {
+                bail!("Remote database does not exist: '{}'", path.display());
+            }
+            let _lock = lock_database(&path, Some(Duration::ZERO))?;
+            let mut remote_db = RecordDatabase::open(&path, false)?;
+            record_db.sync_with(&mut remote_db)?
+        }
+        SyncRemote::Ssh { host, path } => sync_ssh(record_db, &host, &path, data_dir)?,
+        SyncRemote::Http(url) => {
+            info!("Downloading database dump from '{url}'");
+            let response = client.get(format!("{url}/dump"))?;
+            let body = match response.status() {
+                ureq::http::StatusCode::OK => response.into_body().bytes()?,
+                code => bail!("Failed to download database dump: {code}"),
+            };
+
+            // the dump is read into a temporary database, which is discarded afterwards
+            let temporary = TemporaryFile::create(data_dir, "sync-dump")?;
+            let mut remote_db = RecordDatabase::open(&temporary.path, false)?;
+            remote_db.import_dump(body.as_slice())?;
+            let mut report = record_db.sync_with(&mut remote_db)?;
+            if !report.pushed.is_empty() || !report.merged.is_empty() {
+                info!(
+                    "Local changes are not sent to the server: run `autobib sync` on the server to update its database"
+                );
+            }
+            report.pushed.clear();
+            report
+        }
+    };
+
+    for canonical in &report.pulled {
+        info!("Updated '{canonical}' from the remote database");
+    }
+    for canonical in &report.pushed {
+        info!("Updated '{canonical}' in the remote database");
+    }
+    for canonical in &report.merged {
+        info!("Merged changes to '{canonical}' from both databases");
+    }
+    if report.identifiers > 0 {
+        info!("Added {} identifiers", report.identifiers);
+    }
+    if report.tags > 0 {
+        info!("Added {} tags", report.tags);
+    }
+    if report.relations > 0 {
+        info!("Added {} relations", report.relations);
+    }
+    if report.collection_records > 0 {
+        info!("Added {} records to collections", report.collection_records);
+    }
+    if report.overrides > 0 {
+        info!("Added {} field overrides", report.overrides);
+    }
+    if !report.conflicts.is_empty() {
+        for conflict in &report.conflicts {
+            error!(kind = FailureKind::Conflict; "{conflict}");
+        }
+        suggest!(
+            "Edit the conflicting records or aliases so that they agree in both databases, and run `autobib sync` again."
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,306 @@
+//! # Interactive record browser
+//!
+//! The browser repeatedly opens a picker over the active records in the database. The keybindings
+//! for the actions select the highlighted record and remember the action, which is performed once
+//! the picker exits. The picker is then opened again with the same query, so that the changes
+//! made by the action are visible.
+use std::{
+    cell::Cell,
+    fs::{File, OpenOptions, create_dir_all},
+    io::{self, Write, copy},
+    path::{Path, PathBuf},
+};
+
+use anyhow::bail;
+use crossterm::{
+    cursor::MoveTo,
+    event::{Event as TermEvent, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, read},
+    execute,
+    style::Stylize,
+    terminal::{
+        Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode,
+        enable_raw_mode, size,
+    },
+};
+use nucleo_picker::{
+    error::PickError,
+    event::{Event, keybind_default},
+};
+
+use super::{
+    delete::soft_delete,
+    edit::create_alias_if_valid,
+    path::{list_attachments, open_with_default_application, track_attachment},
+    picker::{choose_attachment_name, choose_canonical_id},
+};
+use crate::{
+    Config,
+    db::{
+        Identifier, RecordDatabase,
+        state::{RecordRow, RemoteIdState},
+    },
+    entry::{Entry, EntryKey, MutableEntryData, RawEntryData},
+    format::Template,
+    logger::{info, reraise, warn},
+    path_hash::PathHash,
+    record::{RecordId, RemoteId},
+    term::{Confirm, Editor, Input},
+};
+
+/// An action performed on the record highlighted in the picker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    /// Edit the record with the editor.
+    Edit,
+    /// Soft-delete the record.
+    Delete,
+    /// Copy a file into the attachment directory of the record.
+    Attach,
+    /// Open an attachment of the record with the default application.
+    Open,
+}
+
+impl Action {
+    /// The action corresponding to the key, if any.
+    fn from_key(code: KeyCode) -> Option<Self> {
+        match code {
+            KeyCode::Char('e') => Some(Self::Edit),
+            KeyCode::Char('d') => Some(Self::Delete),
+            KeyCode::Char('a') => Some(Self::Attach),
+            KeyCode::Char('o') => Some(Self::Open),
+            _ => None,
+        }
+    }
+}
+
+/// The keybindings of the picker. The actions are bound to `Alt` and the key of the action, since
+/// most `Control` keys are already used to edit the prompt.
+fn keybind(action: &Cell<Option<Action>>, key_event: KeyEvent) -> Option<Event> {
+    match key_event {
+        KeyEvent {
+            kind: KeyEventKind::Press,
+            modifiers: KeyModifiers::ALT,
+            code,
+            ..
+        } if Action::from_key(code).is_some() => {
+            action.set(Action::from_key(code));
+            Some(Event::Select)
+        }
+        e => keybind_default(e),
+    }
+}
+
+/// The canonical identifier of the record picked by the user, and the action to perform, which
+/// is `None` if the record should be previewed.
+type Picked = Option<(RemoteId, Option<Action>)>;
+
+/// Open the picker with the provided query, returning the database, the query when the picker
+/// exited, and the picked record.
+fn pick(
+    record_db: RecordDatabase,
+    template: Template,
+    query: String,
+) -> Result<(RecordDatabase, String, Picked), anyhow::Error> {
+    let (mut picker, handle) = choose_canonical_id(record_db, template, false, None);
+    picker.update_query(query);
+
+    let action = Cell::new(None);
+    let picked = match picker.pick_with_keybind(|key_event| keybind(&action, key_event)) {
+        Ok(row) => row.map(|row| (row.canonical.clone(), action.get())),
+        Err(PickError::UserInterrupted) => None,
+        Err(err) => return Err(err.into()),
+    };
+    let query = picker.query().to_owned();
+    drop(picker);
+
+    let record_db = handle.join().expect("Thread should not have panicked")?;
+    Ok((record_db, query, picked))
+}
+
+/// Write the BibTeX entry and the keybindings, and return the action corresponding to the next
+/// key which is pressed.
+fn show_preview<W: Write>(writer: &mut W, entry: &str) -> io::Result<Option<Action>> {
+    // leave space for the blank line and the keybindings
+    let (_, rows) = size()?;
+    for line in entry.lines().take(usize::from(rows.saturating_sub(2))) {
+        write!(writer, "{line}\r\n")?;
+    }
+    write!(
+        writer,
+        "\r\n{}",
+        "e: edit  d: delete  a: attach  o: open  any other key: back".dim()
+    )?;
+    writer.flush()?;
+
+    loop {
+        if let TermEvent::Key(KeyEvent {
+            kind: KeyEventKind::Press,
+            code,
+            ..
+        }) = read()?
+        {
+            return Ok(Action::from_key(code));
+        }
+    }
+}
+
+/// Show the BibTeX entry of the record on the full screen, and return the action corresponding to
+/// the next key which is pressed.
+fn preview(entry: &str) -> io::Result<Option<Action>> {
+    let mut stderr = io::stderr().lock();
+    enable_raw_mode()?;
+    execute!(
+        stderr,
+        EnterAlternateScreen,
+        Clear(ClearType::All),
+        MoveTo(0, 0)
+    )?;
+
+    // restore the terminal even if the preview failed
+    let res = show_preview(&mut stderr, entry);
+    execute!(stderr, LeaveAlternateScreen)?;
+    disable_raw_mode()?;
+    res
+}
+
+/// Edit the record with the editor.
+fn edit(record_db: &mut RecordDatabase, canonical: &RemoteId) -> Result<(), anyhow::Error> {
+    let RemoteIdState::Entry(RecordRow { data, .. }, row) =
+        record_db.state_from_remote_id(canonical)?
+    else {
+        bail!("Record '{canonical}' was changed while the browser was running");
+    };
+
+    let entry = Entry {
+        key: EntryKey::try_new(canonical.name().to_owned())
+            .unwrap_or_else(|_| EntryKey::placeholder()),
+        record_data: MutableEntryData::from_entry_data(&data),
+    };
+    if let Some(Entry { key, record_data }) = Editor::new_bibtex().edit(&entry)? {
+        let new_row = row.modify(&RawEntryData::from_entry_data(&record_data))?;
+        if key.as_ref() != entry.key.as_ref() {
+            create_alias_if_valid(key.as_ref(), &new_row)?;
+        }
+        new_row.commit()?;
+    } else {
+        row.commit()?;
+        info!("Record data unchanged");
+    }
+    Ok(())
+}
+
+/// Copy a file, whose path is read from the terminal, into the attachment directory of the
+/// record.
+fn attach(
+    record_db: &mut RecordDatabase,
+    canonical: &RemoteId,
+    attachment_root: &Path,
+) -> Result<(), anyhow::Error> {
+    let answer = Input::new("File to attach (leave empty to cancel)").input()?;
+    let file = PathBuf::from(answer.trim());
+    if file.as_os_str().is_empty() {
+        return Ok(());
+    }
+    let Some(name) = file.file_name() else {
+        bail!("Source file must not be a directory");
+    };
+
+    let mut attachment_dir = attachment_root.to_path_buf();
+    canonical.extend_attachments_path(&mut attachment_dir);
+    create_dir_all(&attachment_dir)?;
+    let target = attachment_dir.join(name);
+
+    let mut source_file = File::open(&file)?;
+    info!("Copying file from: {}", file.display());
+    let mut target_file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&target)?;
+    copy(&mut source_file, &mut target_file)?;
+
+    track_attachment(record_db, canonical, &attachment_dir, &target)
+}
+
+/// Open an attachment of the record with the default application, choosing the attachment with a
+/// picker if there is more than one.
+fn open(canonical: &RemoteId, attachment_root: &Path) -> Result<(), anyhow::Error> {
+    let mut attachment_dir = attachment_root.to_path_buf();
+    canonical.extend_attachments_path(&mut attachment_dir);
+
+    let mut attachments = list_attachments(&attachment_dir)?;
+    let attachment = match attachments.len() {
+        0 => {
+            warn!("No attachments for '{canonical}'");
+            return Ok(());
+        }
+        1 => attachments.swap_remove(0),
+        _ => match choose_attachment_name(attachments).pick()? {
+            Some(attachment) => attachment.clone(),
+            None => return Ok(()),
+        },
+    };
+    open_with_default_application(&attachment_dir.join(attachment))
+}
+
+/// Run the interactive browser until the user quits the picker, returning the database.
+pub fn browse<F: FnOnce() -> Vec<(regex::Regex, String)>>(
+    mut record_db: RecordDatabase,
+    template: Template,
+    config: &Config<F>,
+    attachment_root: &Path,
+) -> Result<RecordDatabase, anyhow::Error> {
+    let mut query = String::new();
+    loop {
+        let picked;
+        (record_db, query, picked) = pick(record_db, template.clone(), query)?;
+        let Some((canonical, action)) = picked else {
+            return Ok(record_db);
+        };
+
+        let action = match action {
+            Some(action) => action,
+            None => {
+                let entry = match record_db.state_from_remote_id(&canonical)? {
+                    RemoteIdState::Entry(RecordRow { data, .. }, state) => {
+                        state.commit()?;
+                        let key = EntryKey::try_new(canonical.name().to_owned())
+                            .unwrap_or_else(|_| EntryKey::placeholder());
+                        Entry::new(key, data).to_string()
+                    }
+                    _ => {
+                        warn!("Record '{canonical}' was changed while the browser was running");
+                        continue;
+                    }
+                };
+                match preview(&entry)? {
+                    Some(action) => action,
+                    None => continue,
+                }
+            }
+        };
+
+        // errors only cancel the action, so that the session can continue
+        let res = match action {
+            Action::Edit => edit(&mut record_db, &canonical),
+            Action::Delete => {
+                if Confirm::new(format!("Delete record '{canonical}'?"), false).confirm()? {
+                    soft_delete(
+                        RecordId::from(canonical.name()),
+                        &None,
+                        &mut record_db,
+                        config,
+                        false,
+                    )
+                    .map_err(Into::into)
+                } else {
+                    Ok(())
+                }
+            }
+            Action::Attach => attach(&mut record_db, &canonical, attachment_root),
+            Action::Open => open(&canonical, attachment_root),
+        };
+        if let Err(err) = res {
+            reraise(&err);
+        }
+    }
+}
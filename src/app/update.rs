@@ -1,35 +1,245 @@
-use std::iter::once;
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    iter::once,
+    thread::sleep,
+    time::{Duration, Instant},
+};
 
 use anyhow::bail;
+use chrono::{Local, TimeDelta};
 
 use crate::{
     RemoteId,
-    app::{cli::OnConflict, merge_record_data},
-    db::state::{RecordIdState, RecordRow},
-    entry::{MutableEntryData, RawEntryData},
+    app::{cli::OnConflict, conflicts::RecordConflict, merge_record_data},
+    config::FieldMergeRule,
+    db::{
+        Provenance, RecordDatabase,
+        state::{RecordIdState, RecordRow, RemoteIdState},
+    },
+    entry::{MutableEntryData, RawEntryData, changed_fields},
+    error::FailureKind,
     http::Client,
-    logger::{error, suggest},
+    interrupt::{CatchInterrupt, interrupted},
+    logger::{error, info, reraise_as, suggest},
     normalize::{Normalization, Normalize},
+    progress::Progress,
+    provider::{BatchClient, custom},
     record::{RecursiveRemoteResponse, get_remote_response_recursive},
 };
 
+/// The minimum time between two individual requests made by [`update_stale`], so that providers
+/// are not overwhelmed by a large number of requests.
+const REQUEST_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A record which was changed by [`update_stale`].
+pub struct UpdatedRecord {
+    /// The canonical identifier of the record.
+    pub canonical: RemoteId,
+    /// The names of the fields whose values were added or changed. The entry type is reported as
+    /// `entry type`.
+    pub fields: Vec<String>,
+}
+
+/// The outcome of [`update_stale`].
+#[derive(Default)]
+pub struct StaleUpdate {
+    /// The number of records which were retrieved again.
+    pub num_retrieved: usize,
+    /// The records which were changed.
+    pub updated: Vec<UpdatedRecord>,
+    /// The records which were not retrieved since `Ctrl-C` was pressed.
+    pub remaining: Vec<RemoteId>,
+}
+
+/// Data which is merged into a record, along with where it came from.
+pub struct IncomingData {
+    /// The data.
+    pub data: MutableEntryData,
+    /// A description of the source of the data, which is recorded for the fields it supplies.
+    pub source: String,
+    /// The provenance of the data, if it was retrieved from a provider.
+    pub provenance: Option<Provenance>,
+}
+
+/// Retrieve every active record which is not a local record and which was last modified longer
+/// ago than `older_than` again from its provider, and merge the new data into the record.
+///
+/// Records which are served by providers supporting batch requests are retrieved in batches, and
+/// individual requests are separated by [`REQUEST_INTERVAL`]. Errors for individual records are
+/// reported and do not stop the update. If `Ctrl-C` is pressed, the record which is currently
+/// being updated is finished and the remaining records are returned. The conflicts which are
+/// resolved without prompting are appended to `conflicts`.
+pub fn update_stale<C: Client>(
+    record_db: &mut RecordDatabase,
+    older_than: Option<TimeDelta>,
+    on_conflict: OnConflict,
+    merge_rules: &BTreeMap<String, FieldMergeRule>,
+    normalization: &Normalization,
+    client: &C,
+    conflicts: &mut Vec<RecordConflict>,
+) -> Result<StaleUpdate, rusqlite::Error> {
+    let threshold = older_than.map(|age| Local::now() - age);
+    let mut stale = Vec::new();
+    record_db.map_active_records(|row| {
+        if !row.canonical.is_local()
+            && custom::lookup(row.canonical.provider()).is_none()
+            && threshold.is_none_or(|threshold| row.modified <= threshold)
+        {
+            stale.push(row.canonical);
+        }
+    })?;
+    info!("Found {} records to update", stale.len());
+
+    let _catch = CatchInterrupt::new();
+    let client = BatchClient::new(client);
+    client.prefetch(&stale);
+
+    let mut outcome = StaleUpdate::default();
+    let mut last_request: Option<Instant> = None;
+    let mut progress = Progress::new("Updating", stale.len());
+    let mut stale = stale.into_iter();
+    for canonical in stale.by_ref() {
+        if !client.is_prefetched(&canonical) {
+            if let Some(last_request) = last_request {
+                sleep(REQUEST_INTERVAL.saturating_sub(last_request.elapsed()));
+            }
+            last_request = Some(Instant::now());
+        }
+
+        match update_stale_record(
+            record_db,
+            canonical,
+            on_conflict,
+            merge_rules,
+            normalization,
+            &client,
+            conflicts,
+        ) {
+            Ok(Some(updated)) => outcome.updated.push(updated),
+            Ok(None) => {}
+            Err(err) => reraise_as(FailureKind::of(&err), &err),
+        }
+        outcome.num_retrieved += 1;
+        progress.inc();
+
+        if interrupted() {
+            break;
+        }
+    }
+    outcome.remaining = stale.collect();
+    Ok(outcome)
+}
+
+/// Retrieve a single record again, returning the changed fields if the record was changed.
+fn update_stale_record<C: Client>(
+    record_db: &mut RecordDatabase,
+    canonical: RemoteId,
+    on_conflict: OnConflict,
+    merge_rules: &BTreeMap<String, FieldMergeRule>,
+    normalization: &Normalization,
+    client: &C,
+    conflicts: &mut Vec<RecordConflict>,
+) -> Result<Option<UpdatedRecord>, anyhow::Error> {
+    let RemoteIdState::Entry(RecordRow { data, .. }, state) =
+        record_db.state_from_remote_id(&canonical)?
+    else {
+        bail!("Record '{canonical}' was changed during the update");
+    };
+
+    let (mut new_raw_data, provenance) = match data_from_remote(canonical.clone(), client) {
+        Ok((data, _, provenance)) => (data, provenance),
+        Err(err) => {
+            state.commit()?;
+            return Err(err);
+        }
+    };
+    new_raw_data.normalize(normalization);
+
+    let mut merged = MutableEntryData::from_entry_data(&data);
+    if let Err(err) = merge_record_data(
+        on_conflict,
+        merge_rules,
+        &mut merged,
+        once(&new_raw_data),
+        &canonical,
+        conflicts,
+    ) {
+        state.commit()?;
+        return Err(err.into());
+    }
+
+    let fields = changed_fields(&data, &merged);
+    if fields.is_empty() {
+        state.commit()?;
+        Ok(None)
+    } else {
+        let state = state.modify(&RawEntryData::from_entry_data(&merged))?;
+        if let Some(provenance) = &provenance {
+            state.set_provenance(provenance)?;
+        }
+        state.set_field_sources(
+            fields.iter().map(String::as_str),
+            &format!("retrieved {canonical}"),
+        )?;
+        state.commit()?;
+        Ok(Some(UpdatedRecord { canonical, fields }))
+    }
+}
+
+/// Write the records which were changed by [`update_stale`], along with the fields which changed.
+pub fn write_stale_update<W: Write>(writer: &mut W, outcome: &StaleUpdate) -> io::Result<()> {
+    writeln!(
+        writer,
+        "Retrieved {} {} and changed {}{}",
+        outcome.num_retrieved,
+        if outcome.num_retrieved == 1 {
+            "record"
+        } else {
+            "records"
+        },
+        outcome.updated.len(),
+        if outcome.updated.is_empty() { "" } else { ":" }
+    )?;
+    let width = outcome
+        .updated
+        .iter()
+        .map(|updated| updated.canonical.to_string().chars().count())
+        .max()
+        .unwrap_or(0);
+    for updated in &outcome.updated {
+        writeln!(
+            writer,
+            "  {:<width$}  {}",
+            updated.canonical.to_string(),
+            updated.fields.join(", ")
+        )?;
+    }
+    Ok(())
+}
+
 /// Update the record id corresponding to the [`RecordIdState`] using data returned by
-/// `data_callback`.
+/// `produce_data`.
 ///
-/// If the record exists, update it either with the provided data, or remote data if none.
+/// If the record exists, update it either with the provided data, or remote data if none. The
+/// source of the incoming data is recorded for every field which is changed by the merge, and the
+/// conflicts which are resolved without prompting are appended to `conflicts`.
 ///
 /// If the record is null, it cannot be updated by provided data, and will only update if there is
 /// new data to retrieve from remote.
+#[allow(clippy::too_many_arguments)]
 pub fn update<F>(
     on_conflict: OnConflict,
+    merge_rules: &BTreeMap<String, FieldMergeRule>,
     record_id_state: RecordIdState,
-    provided_data: Option<MutableEntryData>,
+    provided_data: Option<IncomingData>,
     normalization: &Normalization,
     revive: bool,
+    conflicts: &mut Vec<RecordConflict>,
     produce_data: F,
 ) -> Result<(), anyhow::Error>
 where
-    F: FnOnce(RemoteId) -> Result<MutableEntryData, anyhow::Error>,
+    F: FnOnce(RemoteId) -> Result<IncomingData, anyhow::Error>,
 {
     match record_id_state {
         RecordIdState::Entry(
@@ -43,11 +253,15 @@ where
                 state.commit()?;
                 bail!("Record already exists");
             } else {
-                let mut new_raw_data = if let Some(data) = provided_data {
-                    data
+                let IncomingData {
+                    data: mut new_raw_data,
+                    source,
+                    provenance,
+                } = if let Some(incoming) = provided_data {
+                    incoming
                 } else {
                     match produce_data(canonical) {
-                        Ok(data) => data,
+                        Ok(incoming) => incoming,
                         Err(e) => {
                             state.commit()?;
                             return Err(e);
@@ -58,20 +272,35 @@ where
                 new_raw_data.normalize(normalization);
 
                 let mut existing_record = MutableEntryData::from_entry_data(&data);
-                merge_record_data(on_conflict, &mut existing_record, once(&new_raw_data), &id)?;
+                merge_record_data(
+                    on_conflict,
+                    merge_rules,
+                    &mut existing_record,
+                    once(&new_raw_data),
+                    &id,
+                    conflicts,
+                )?;
 
-                state
-                    .modify(&RawEntryData::from_entry_data(&existing_record))?
-                    .commit()?;
+                let fields = changed_fields(&data, &existing_record);
+                let state = state.modify(&RawEntryData::from_entry_data(&existing_record))?;
+                if let Some(provenance) = &provenance {
+                    state.set_provenance(provenance)?;
+                }
+                state.set_field_sources(fields.iter().map(String::as_str), &source)?;
+                state.commit()?;
             }
         }
         RecordIdState::Deleted(id, data, state) => {
             if revive {
-                let mut raw_data = if let Some(data) = provided_data {
-                    data
+                let IncomingData {
+                    data: mut raw_data,
+                    provenance,
+                    ..
+                } = if let Some(incoming) = provided_data {
+                    incoming
                 } else {
                     match produce_data(data.canonical) {
-                        Ok(data) => data,
+                        Ok(incoming) => incoming,
                         Err(e) => {
                             state.commit()?;
                             return Err(e);
@@ -80,9 +309,11 @@ where
                 };
 
                 raw_data.normalize(normalization);
-                state
-                    .reinsert(&RawEntryData::from_entry_data(&raw_data))?
-                    .commit()?;
+                let state = state.reinsert(&RawEntryData::from_entry_data(&raw_data))?;
+                if let Some(provenance) = &provenance {
+                    state.set_provenance(provenance)?;
+                }
+                state.commit()?;
             } else {
                 state.commit()?;
                 error!("Cannot update soft-deleted row '{id}'.");
@@ -117,7 +348,7 @@ where
         RecordIdState::UndefinedAlias(alias) => {
             bail!("Undefined alias: '{alias}'");
         }
-        RecordIdState::InvalidRemoteId(err) => bail!("{err}"),
+        RecordIdState::InvalidRemoteId(err) => bail!(err),
     };
     Ok(())
 }
@@ -125,9 +356,11 @@ where
 pub fn data_from_remote<C: Client>(
     remote_id: RemoteId,
     client: &C,
-) -> Result<(MutableEntryData, RemoteId), anyhow::Error> {
+) -> Result<(MutableEntryData, RemoteId, Option<Provenance>), anyhow::Error> {
     match get_remote_response_recursive(remote_id, client)? {
-        RecursiveRemoteResponse::Exists(record_data, canonical) => Ok((record_data, canonical)),
+        RecursiveRemoteResponse::Exists(record_data, canonical, provenance) => {
+            Ok((record_data, canonical, provenance))
+        }
         RecursiveRemoteResponse::Null(null_remote_id) => {
             bail!("Remote data for canonical id '{null_remote_id}' is null");
         }
@@ -1,23 +1,99 @@
 use std::{
-    collections::BTreeMap,
-    fs::OpenOptions,
+    collections::{
+        BTreeMap,
+        btree_map::Entry::{Occupied, Vacant},
+    },
+    fs::{File, OpenOptions},
     io::{self, IsTerminal, Write},
+    iter::once,
     path::Path,
 };
 
+use chrono::{DateTime, Local};
 use itertools::Itertools;
 use nonempty::NonEmpty;
-use serde::Serializer as _;
+use serde::{Serialize, Serializer as _};
 use serde_bibtex::ser::Serializer;
 
 use crate::{
     Identifier,
-    entry::{Entry, EntryData},
-    logger::warn,
+    config::{DuplicatePolicy, SortOrder},
+    db::{FieldOverride, RecordDatabase},
+    entry::{
+        Entry, EntryData, EntryStyle, Flavor, MacroDefinition, MutableEntryData, RawEntryData,
+    },
+    logger::{error, warn},
+    normalize::first_last_name,
     output::stdout_lock_wrap,
+    progress,
     record::RemoteId,
 };
 
+/// Information about a record which is written in a comment above its entries.
+pub struct Annotation {
+    /// Other identifiers which refer to the record.
+    equivalent: Vec<RemoteId>,
+    /// When the record data was last modified.
+    modified: DateTime<Local>,
+}
+
+/// Look up the [`Annotation`] for each record in the database.
+pub fn lookup_annotations<D>(
+    record_db: &mut RecordDatabase,
+    grouped_entries: &BTreeMap<RemoteId, NonEmpty<Entry<D>>>,
+) -> Result<BTreeMap<RemoteId, Annotation>, rusqlite::Error> {
+    lookup_annotations_of(record_db, grouped_entries.keys())
+}
+
+/// Look up the [`Annotation`] for a single record.
+fn lookup_annotation(
+    record_db: &mut RecordDatabase,
+    canonical: &RemoteId,
+) -> Result<Option<Annotation>, rusqlite::Error> {
+    Ok(lookup_annotations_of(record_db, [canonical])?.remove(canonical))
+}
+
+/// Look up the [`Annotation`] for each of the records in a single transaction.
+fn lookup_annotations_of<'a>(
+    record_db: &mut RecordDatabase,
+    canonicals: impl IntoIterator<Item = &'a RemoteId>,
+) -> Result<BTreeMap<RemoteId, Annotation>, rusqlite::Error> {
+    // the entries were just retrieved from the database, so every record is present unless the
+    // database was modified concurrently
+    Ok(record_db
+        .referencing_remote_ids(canonicals)?
+        .into_iter()
+        .map(|(canonical, (modified, mut equivalent))| {
+            equivalent.retain(|remote_id| remote_id != &canonical);
+            equivalent.sort();
+            (
+                canonical,
+                Annotation {
+                    equivalent,
+                    modified,
+                },
+            )
+        })
+        .collect())
+}
+
+/// Write the comment line describing a record.
+fn write_annotation<W: io::Write>(
+    mut writer: W,
+    canonical: &RemoteId,
+    annotation: &Annotation,
+) -> io::Result<()> {
+    write!(writer, "% autobib: {canonical}")?;
+    for remote_id in &annotation.equivalent {
+        write!(writer, ", {remote_id}")?;
+    }
+    writeln!(
+        writer,
+        " (modified {})",
+        annotation.modified.format("%Y-%m-%d")
+    )
+}
+
 pub fn init_outfile<P: AsRef<Path>>(
     out: Option<P>,
     append: bool,
@@ -54,31 +130,202 @@ pub fn output_keys<'a>(keys: impl Iterator<Item = &'a crate::RecordId>) -> Resul
     Ok(())
 }
 
+/// Get the overrides which apply to the output file, grouped by record.
+///
+/// An override applies if the path of the output file ends with the scope of the override, so
+/// that the scope `project.bib` applies to `papers/project.bib`. No overrides apply when writing
+/// to stdout.
+pub fn lookup_overrides(
+    record_db: &mut RecordDatabase,
+    out: Option<&Path>,
+) -> Result<BTreeMap<RemoteId, Vec<FieldOverride>>, rusqlite::Error> {
+    let mut overrides: BTreeMap<RemoteId, Vec<FieldOverride>> = BTreeMap::new();
+    if let Some(out) = out {
+        for field_override in record_db.overrides(None)? {
+            if out.ends_with(&field_override.scope) {
+                overrides
+                    .entry(RemoteId::from_string_unchecked(
+                        field_override.canonical.clone(),
+                    ))
+                    .or_default()
+                    .push(field_override);
+            }
+        }
+    }
+    Ok(overrides)
+}
+
+/// The last name of the first author of the entry, or of the first editor if there is no
+/// `author` field, in lowercase.
+fn author_sort_key<D: EntryData>(data: &D) -> Option<String> {
+    data.get_field("author")
+        .or_else(|| data.get_field("editor"))
+        .and_then(first_last_name)
+        .map(|name| name.to_lowercase())
+}
+
+/// The year of the entry, from the `year` field or otherwise from the `date` field.
+fn year_sort_key<D: EntryData>(data: &D) -> Option<String> {
+    data.get_field("year")
+        .or_else(|| {
+            data.get_field("date")
+                .and_then(|date| date.split(['-', '/']).next())
+        })
+        .map(|year| year.trim().to_owned())
+}
+
+/// Sort the records in the provided order. Records which do not have the field used for sorting
+/// are written last, and records with the same sort key are sorted by their first key.
+#[allow(clippy::type_complexity)]
+pub fn sort_entries<D: EntryData>(
+    record_db: &mut RecordDatabase,
+    grouped_entries: BTreeMap<RemoteId, NonEmpty<Entry<D>>>,
+    order: SortOrder,
+) -> Result<Vec<(RemoteId, NonEmpty<Entry<D>>)>, rusqlite::Error> {
+    let mut sorted: Vec<_> = grouped_entries.into_iter().collect();
+
+    let first_key =
+        |entry_group: &NonEmpty<Entry<D>>| entry_group.first().key().as_ref().to_owned();
+    match order {
+        SortOrder::Unsorted => {}
+        SortOrder::Key => sorted.sort_by_cached_key(|(_, entry_group)| first_key(entry_group)),
+        SortOrder::Author => sorted.sort_by_cached_key(|(_, entry_group)| {
+            let data = entry_group.first().data();
+            let author = author_sort_key(data);
+            let year = year_sort_key(data);
+            (
+                author.is_none(),
+                author,
+                year.is_none(),
+                year,
+                first_key(entry_group),
+            )
+        }),
+        SortOrder::Year => sorted.sort_by_cached_key(|(_, entry_group)| {
+            let data = entry_group.first().data();
+            let year = year_sort_key(data);
+            let author = author_sort_key(data);
+            (
+                year.is_none(),
+                year,
+                author.is_none(),
+                author,
+                first_key(entry_group),
+            )
+        }),
+        SortOrder::Added => {
+            let added = record_db.added_times()?;
+            sorted.sort_by_cached_key(|(canonical, entry_group)| {
+                let added = added.get(canonical).copied();
+                (added.is_none(), added, first_key(entry_group))
+            });
+        }
+    }
+
+    Ok(sorted)
+}
+
+/// Convert the data of every entry to the provided flavor, if any, and then replace the fields
+/// which are overridden.
+fn convert_entries<D: EntryData>(
+    sorted_entries: Vec<(RemoteId, NonEmpty<Entry<D>>)>,
+    flavor: Option<Flavor>,
+    overrides: &BTreeMap<RemoteId, Vec<FieldOverride>>,
+) -> Vec<(RemoteId, NonEmpty<Entry<MutableEntryData>>)> {
+    sorted_entries
+        .into_iter()
+        .map(|(canonical, entry_group)| {
+            let entry_group =
+                entry_group.map(|entry| convert_entry(entry, &canonical, flavor, overrides));
+            (canonical, entry_group)
+        })
+        .collect()
+}
+
+/// Convert the data of a single entry of the record to the provided flavor, if any, and then
+/// replace the fields which are overridden.
+fn convert_entry<D: EntryData>(
+    Entry { key, record_data }: Entry<D>,
+    canonical: &RemoteId,
+    flavor: Option<Flavor>,
+    overrides: &BTreeMap<RemoteId, Vec<FieldOverride>>,
+) -> Entry<MutableEntryData> {
+    let mut data = MutableEntryData::from_entry_data(&record_data);
+    if let Some(flavor) = flavor {
+        data.convert_flavor(flavor);
+    }
+    for FieldOverride { field, value, .. } in overrides.get(canonical).into_iter().flatten() {
+        if let Err(err) = data.check_and_insert(field.clone(), value.clone()) {
+            warn!("Skipping invalid override of '{field}' for '{canonical}': {err}");
+        }
+    }
+    Entry::new(key, data)
+}
+
 /// Either write records to stdout, or to a provided file.
+///
+/// If a flavor is provided, the entries are converted to the flavor before they are written, and
+/// the overridden fields replace the fields of the entries. The abbreviations in the preamble are
+/// written before the entries, unless appending to a file. The entries are written in the order
+/// in which they are provided, and formatted according to the style.
+#[allow(clippy::too_many_arguments)]
 pub fn output_entries<D: EntryData>(
     out: Option<std::fs::File>,
     append: bool,
-    grouped_entries: BTreeMap<RemoteId, NonEmpty<Entry<D>>>,
+    sorted_entries: Vec<(RemoteId, NonEmpty<Entry<D>>)>,
+    annotations: Option<BTreeMap<RemoteId, Annotation>>,
+    flavor: Option<Flavor>,
+    overrides: BTreeMap<RemoteId, Vec<FieldOverride>>,
+    preamble: Vec<MacroDefinition>,
+    style: EntryStyle,
 ) -> Result<(), serde_bibtex::Error> {
+    if flavor.is_none() && overrides.is_empty() {
+        write_output(out, append, sorted_entries, annotations, preamble, style)
+    } else {
+        write_output(
+            out,
+            append,
+            convert_entries(sorted_entries, flavor, &overrides),
+            annotations,
+            preamble,
+            style,
+        )
+    }
+}
+
+fn write_output<D: EntryData>(
+    out: Option<std::fs::File>,
+    append: bool,
+    sorted_entries: Vec<(RemoteId, NonEmpty<Entry<D>>)>,
+    annotations: Option<BTreeMap<RemoteId, Annotation>>,
+    preamble: Vec<MacroDefinition>,
+    style: EntryStyle,
+) -> Result<(), serde_bibtex::Error> {
+    let annotations = annotations.as_ref();
     match out {
         Some(file) => {
             let mut writer = io::BufWriter::new(file);
-            if append && !grouped_entries.is_empty() {
-                writer.write_all(b"\n")?;
+            if append {
+                // the abbreviations are already defined at the start of the file
+                if !sorted_entries.is_empty() {
+                    writer.write_all(b"\n")?;
+                }
+                write_entries(writer, sorted_entries, annotations, &[], style)?;
+            } else {
+                write_entries(writer, sorted_entries, annotations, &preamble, style)?;
             }
-            write_entries(writer, grouped_entries)?;
         }
         _ => {
             let stdout = io::stdout();
             if stdout.is_terminal() {
                 // do not write an extra newline if interactive and there is nothing to write
-                if !grouped_entries.is_empty() {
+                if !sorted_entries.is_empty() {
                     // no need to use `stdout_lock_wrap` as broken pipe error cannot occur
-                    write_entries(stdout.lock(), grouped_entries)?;
+                    write_entries(stdout.lock(), sorted_entries, annotations, &preamble, style)?;
                 }
             } else {
                 let writer = io::BufWriter::new(stdout_lock_wrap());
-                write_entries(writer, grouped_entries)?;
+                write_entries(writer, sorted_entries, annotations, &preamble, style)?;
             }
         }
     };
@@ -86,20 +333,330 @@ pub fn output_entries<D: EntryData>(
     Ok(())
 }
 
-/// Iterate over records, writing the entries and warning about duplicates.
+/// Write entries to stdout, or to a provided file, as soon as they are retrieved.
+///
+/// This is the streaming counterpart of [`output_entries`]: the entries are written in the order
+/// in which they are provided, so the entries of a record with multiple keys are not necessarily
+/// adjacent, and only the keys of the entries which were written are kept in memory so that
+/// duplicates can be reported by [`EntryStream::finish`].
+pub struct EntryStream {
+    writer: Box<dyn io::Write>,
+    /// Whether or not the entries are written to an interactive terminal.
+    terminal: bool,
+    /// Whether or not the entries are appended to an existing file.
+    append: bool,
+    /// Whether or not each record is preceded by an annotation.
+    annotate: bool,
+    flavor: Option<Flavor>,
+    overrides: BTreeMap<RemoteId, Vec<FieldOverride>>,
+    /// The abbreviations written before the first entry, unless appending.
+    preamble: Vec<MacroDefinition>,
+    style: EntryStyle,
+    /// The keys of the entries which were written, grouped by record.
+    written: BTreeMap<RemoteId, NonEmpty<String>>,
+}
+
+impl EntryStream {
+    /// Start writing entries to the provided file, or to stdout if there is no file.
+    ///
+    /// The entries are converted and formatted in the same way as by [`output_entries`]. If
+    /// `annotate` is set, the first entry of each record is preceded by its [`Annotation`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        out: Option<File>,
+        append: bool,
+        annotate: bool,
+        flavor: Option<Flavor>,
+        overrides: BTreeMap<RemoteId, Vec<FieldOverride>>,
+        preamble: Vec<MacroDefinition>,
+        style: EntryStyle,
+    ) -> Self {
+        let (writer, terminal): (Box<dyn io::Write>, bool) = match out {
+            Some(file) => (Box::new(io::BufWriter::new(file)), false),
+            None => {
+                let stdout = io::stdout();
+                if stdout.is_terminal() {
+                    // no need to use `stdout_lock_wrap` as broken pipe error cannot occur
+                    (Box::new(stdout.lock()), true)
+                } else {
+                    (Box::new(io::BufWriter::new(stdout_lock_wrap())), false)
+                }
+            }
+        };
+
+        Self {
+            writer,
+            terminal,
+            append,
+            annotate,
+            flavor,
+            overrides,
+            preamble,
+            style,
+            written: BTreeMap::new(),
+        }
+    }
+
+    /// Write the abbreviations, or an empty line if appending, before the first entry.
+    fn write_header(&mut self) -> io::Result<()> {
+        if self.append {
+            // the abbreviations are already defined at the start of the file
+            self.writer.write_all(b"\n")
+        } else if !self.preamble.is_empty() {
+            write_preamble(&mut self.writer, &self.preamble)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Write a single entry of the record.
+    pub fn write(
+        &mut self,
+        record_db: &mut RecordDatabase,
+        entry: Entry<RawEntryData>,
+        canonical: RemoteId,
+    ) -> Result<(), anyhow::Error> {
+        let annotation = if self.annotate && !self.written.contains_key(&canonical) {
+            lookup_annotation(record_db, &canonical)?
+        } else {
+            None
+        };
+
+        if self.terminal {
+            progress::clear();
+        }
+        if self.written.is_empty() {
+            self.write_header()?;
+        } else {
+            self.writer.write_all(b"\n")?;
+        }
+        if let Some(annotation) = annotation {
+            write_annotation(&mut self.writer, &canonical, &annotation)?;
+        }
+
+        let key = entry.key().as_ref().to_owned();
+        if self.flavor.is_none() && !self.overrides.contains_key(&canonical) {
+            write_single_entry(&mut self.writer, &entry, self.style)?;
+        } else {
+            let entry = convert_entry(entry, &canonical, self.flavor, &self.overrides);
+            write_single_entry(&mut self.writer, &entry, self.style)?;
+        }
+        if self.terminal {
+            self.writer.flush()?;
+        }
+
+        match self.written.entry(canonical) {
+            Occupied(e) => e.into_mut().push(key),
+            Vacant(e) => {
+                e.insert(NonEmpty::singleton(key));
+            }
+        }
+        Ok(())
+    }
+
+    /// Finish writing the entries, and then report the records for which there are multiple
+    /// entries according to the policy, and to the file at the provided path, if any.
+    pub fn finish<P: AsRef<Path>>(
+        mut self,
+        policy: DuplicatePolicy,
+        report_duplicates: Option<P>,
+    ) -> Result<(), anyhow::Error> {
+        // do not write anything if interactive and there was nothing to write
+        if self.written.is_empty() && !self.terminal && !self.append {
+            self.write_header()?;
+        }
+        self.writer.flush()?;
+
+        let written_keys = || {
+            self.written
+                .iter()
+                .map(|(canonical, keys)| (canonical, keys.as_ref().map(String::as_str)))
+        };
+        report_duplicate_keys(written_keys(), policy);
+        if let Some(path) = report_duplicates {
+            write_duplicate_keys_report(path, written_keys())?;
+        }
+        Ok(())
+    }
+}
+
+/// Write a `\nocite` command containing the first key of each record, to the file at the
+/// provided path or to STDOUT.
+pub fn write_nocite<D: EntryData, P: AsRef<Path>>(
+    out: Option<P>,
+    grouped_entries: &BTreeMap<RemoteId, NonEmpty<Entry<D>>>,
+) -> io::Result<()> {
+    let keys = grouped_entries
+        .values()
+        .map(|entry_group| entry_group.first().key().as_ref())
+        .join(",");
+    match out {
+        Some(path) => {
+            let mut writer = io::BufWriter::new(File::create(path)?);
+            writeln!(writer, "\\nocite{{{keys}}}")?;
+            writer.flush()
+        }
+        None => writeln!(stdout_lock_wrap(), "\\nocite{{{keys}}}"),
+    }
+}
+
+/// The keys of the entries of each record, grouped by record.
+fn grouped_keys<D: EntryData>(
+    grouped_entries: &BTreeMap<RemoteId, NonEmpty<Entry<D>>>,
+) -> impl Iterator<Item = (&RemoteId, NonEmpty<&str>)> {
+    grouped_entries.iter().map(|(canonical, entry_group)| {
+        let keys = entry_group.as_ref().map(|e| e.key().as_ref());
+        (canonical, keys)
+    })
+}
+
+/// Report the records for which there are multiple entries, according to the policy.
+pub fn check_duplicates<D: EntryData>(
+    grouped_entries: &BTreeMap<RemoteId, NonEmpty<Entry<D>>>,
+    policy: DuplicatePolicy,
+) {
+    report_duplicate_keys(grouped_keys(grouped_entries), policy);
+}
+
+/// Report the records for which there are multiple keys, according to the policy.
+fn report_duplicate_keys<'a>(
+    grouped_keys: impl Iterator<Item = (&'a RemoteId, NonEmpty<&'a str>)>,
+    policy: DuplicatePolicy,
+) {
+    for (canonical, keys) in grouped_keys {
+        if keys.len() > 1 {
+            let keys = keys.iter().join(", ");
+            match policy {
+                DuplicatePolicy::Ignore => {}
+                DuplicatePolicy::Warn => warn!("Multiple keys for '{canonical}': {keys}"),
+                DuplicatePolicy::Error => error!("Multiple keys for '{canonical}': {keys}"),
+            }
+        }
+    }
+}
+
+/// A group of keys which refer to the same record.
+#[derive(Serialize)]
+struct DuplicateGroup<'a> {
+    canonical: &'a str,
+    keys: Vec<&'a str>,
+}
+
+/// Write the records for which there are multiple entries to the file at the provided path, as
+/// a JSON array of objects with fields `canonical` and `keys`.
+pub fn write_duplicates_report<D: EntryData, P: AsRef<Path>>(
+    path: P,
+    grouped_entries: &BTreeMap<RemoteId, NonEmpty<Entry<D>>>,
+) -> io::Result<()> {
+    write_duplicate_keys_report(path, grouped_keys(grouped_entries))
+}
+
+/// Write the records for which there are multiple keys to the file at the provided path, in the
+/// format of [`write_duplicates_report`].
+fn write_duplicate_keys_report<'a, P: AsRef<Path>>(
+    path: P,
+    grouped_keys: impl Iterator<Item = (&'a RemoteId, NonEmpty<&'a str>)>,
+) -> io::Result<()> {
+    let groups: Vec<DuplicateGroup> = grouped_keys
+        .filter(|(_, keys)| keys.len() > 1)
+        .map(|(canonical, keys)| DuplicateGroup {
+            canonical: canonical.name(),
+            keys: keys.into(),
+        })
+        .collect();
+
+    let mut writer = io::BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, &groups)?;
+    writeln!(writer)?;
+    writer.flush()
+}
+
+/// Write an `@string` entry for each abbreviation, followed by an empty line.
+fn write_preamble<W: io::Write>(mut writer: W, preamble: &[MacroDefinition]) -> io::Result<()> {
+    for def in preamble {
+        writeln!(writer, "@string{{{} = {{{}}}}}", def.name, def.value)?;
+    }
+    writeln!(writer)
+}
+
+/// Iterate over records, writing the entries formatted according to a style which is not the
+/// default, with an empty line between consecutive entries.
+fn write_styled_entries<W: io::Write, D: EntryData>(
+    mut writer: W,
+    sorted_entries: Vec<(RemoteId, NonEmpty<Entry<D>>)>,
+    annotations: Option<&BTreeMap<RemoteId, Annotation>>,
+    style: EntryStyle,
+) -> Result<(), serde_bibtex::Error> {
+    for (idx, (canonical, entry_group)) in sorted_entries.iter().enumerate() {
+        if idx > 0 {
+            writer.write_all(b"\n")?;
+        }
+        if let Some(annotation) = annotations.and_then(|annotations| annotations.get(canonical)) {
+            write_annotation(&mut writer, canonical, annotation)?;
+        }
+        for (entry_idx, entry) in entry_group.iter().enumerate() {
+            if entry_idx > 0 {
+                writer.write_all(b"\n")?;
+            }
+            style.write_entry(&mut writer, entry)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Iterate over records, writing the entries.
+///
+/// If annotations are provided, the entries for each record are preceded by a comment line
+/// describing the record. If the preamble is not empty, the entries are preceded by the
+/// abbreviations.
 fn write_entries<W: io::Write, D: EntryData>(
-    writer: W,
-    grouped_entries: BTreeMap<RemoteId, NonEmpty<Entry<D>>>,
+    mut writer: W,
+    sorted_entries: Vec<(RemoteId, NonEmpty<Entry<D>>)>,
+    annotations: Option<&BTreeMap<RemoteId, Annotation>>,
+    preamble: &[MacroDefinition],
+    style: EntryStyle,
 ) -> Result<(), serde_bibtex::Error> {
-    let mut serializer = Serializer::unchecked(writer);
-
-    serializer.collect_seq(grouped_entries.iter().flat_map(|(canonical, entry_group)| {
-        if entry_group.len() > 1 {
-            warn!(
-                "Multiple keys for '{canonical}': {}",
-                entry_group.iter().map(|e| e.key().as_ref()).join(", ")
-            );
-        };
-        entry_group
-    }))
+    if !preamble.is_empty() {
+        write_preamble(&mut writer, preamble)?;
+    }
+
+    if !style.is_default() {
+        return write_styled_entries(writer, sorted_entries, annotations, style);
+    }
+
+    let Some(annotations) = annotations else {
+        let mut serializer = Serializer::unchecked(writer);
+
+        return serializer.collect_seq(
+            sorted_entries
+                .iter()
+                .flat_map(|(_, entry_group)| entry_group),
+        );
+    };
+
+    for (idx, (canonical, entry_group)) in sorted_entries.iter().enumerate() {
+        if idx > 0 {
+            writer.write_all(b"\n")?;
+        }
+        if let Some(annotation) = annotations.get(canonical) {
+            write_annotation(&mut writer, canonical, annotation)?;
+        }
+        let mut serializer = Serializer::unchecked(&mut writer);
+        serializer.collect_seq(entry_group)?;
+    }
+    Ok(())
+}
+
+/// Write a single entry formatted according to the style.
+fn write_single_entry<W: io::Write, D: EntryData>(
+    mut writer: W,
+    entry: &Entry<D>,
+    style: EntryStyle,
+) -> Result<(), serde_bibtex::Error> {
+    if style.is_default() {
+        Serializer::unchecked(writer).collect_seq(once(entry))
+    } else {
+        Ok(style.write_entry(&mut writer, entry)?)
+    }
 }
@@ -22,17 +22,24 @@
 //!     assert_eq!(exp, rec);
 //! }
 //! ```
+pub mod bcf;
 pub mod bib;
+pub mod md;
+pub mod org;
+pub mod rst;
 pub mod tex;
 pub mod tex_auxfile;
 pub mod txt;
+pub mod typ;
 
-use std::{ffi::OsStr, path::Path, str::FromStr};
+use std::{ffi::OsStr, ops::Range, path::Path, str::FromStr};
+
+use memchr::memmem;
 
 use crate::{RecordId, error::Error};
 
 /// The file type of a source from which citation keys can be read.
-#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SourceFileType {
     /// TeX-style contents, such as `.tex` or `.sty` files.
     Tex,
@@ -40,8 +47,19 @@ pub enum SourceFileType {
     Txt,
     /// TeX-based AUX file contents, mainly `.aux` files.
     Aux,
+    /// Biblatex control file contents, mainly `.bcf` files.
+    Bcf,
     /// Read citation keys from a BibTeX file.
     Bib,
+    /// Markdown contents with Pandoc-style citations, such as `[@key]`, including Quarto and R
+    /// Markdown files.
+    Md,
+    /// Typst contents, with references such as `@key` and calls such as `#cite(<key>)`.
+    Typ,
+    /// Org-mode contents, with org-cite citations such as `[cite:@key]`.
+    Org,
+    /// reStructuredText contents, with `sphinxcontrib-bibtex` roles such as ``:cite:`key` ``.
+    Rst,
 }
 
 impl FromStr for SourceFileType {
@@ -52,7 +70,12 @@ impl FromStr for SourceFileType {
             "tex" | "sty" | "cls" => Ok(Self::Tex),
             "txt" => Ok(Self::Txt),
             "aux" => Ok(Self::Aux),
+            "bcf" => Ok(Self::Bcf),
             "bib" => Ok(Self::Bib),
+            "md" | "markdown" | "qmd" | "rmd" | "Rmd" => Ok(Self::Md),
+            "typ" => Ok(Self::Typ),
+            "org" => Ok(Self::Org),
+            "rst" => Ok(Self::Rst),
             ext => Err(Error::UnsupportedFileType(ext.into())),
         }
     }
@@ -91,8 +114,13 @@ pub fn get_citekeys_filter<T: Extend<RecordId>, F: FnMut(&RecordId) -> bool>(
     let get_citekey_impl = match ft {
         SourceFileType::Tex => tex::get_citekeys,
         SourceFileType::Aux => tex_auxfile::get_citekeys,
+        SourceFileType::Bcf => bcf::get_citekeys,
         SourceFileType::Bib => bib::get_citekeys,
         SourceFileType::Txt => txt::get_citekeys,
+        SourceFileType::Md => md::get_citekeys,
+        SourceFileType::Typ => typ::get_citekeys,
+        SourceFileType::Org => org::get_citekeys,
+        SourceFileType::Rst => rst::get_citekeys,
     };
     get_citekey_impl(buffer, &mut FilterExtend { container, f });
 }
@@ -105,3 +133,73 @@ pub fn get_citekeys_filter<T: Extend<RecordId>, F: FnMut(&RecordId) -> bool>(
 pub fn get_citekeys<T: Extend<RecordId>>(ft: SourceFileType, buffer: &[u8], container: &mut T) {
     get_citekeys_filter(ft, buffer, container, |_| true);
 }
+
+/// Get the byte range of the citation key at the position `cursor` in the buffer, if the position
+/// is inside a citation.
+///
+/// The buffer is assumed to have file type specified by `ft`. Only TeX, Markdown, and Typst
+/// contents have citations in which a position can be located.
+pub fn citekey_range_at(ft: SourceFileType, buffer: &[u8], cursor: usize) -> Option<Range<usize>> {
+    match ft {
+        SourceFileType::Tex => tex::citekey_range_at(buffer, cursor),
+        SourceFileType::Md => md::citekey_range_at(buffer, cursor),
+        SourceFileType::Typ => typ::citekey_range_at(buffer, cursor),
+        SourceFileType::Txt
+        | SourceFileType::Aux
+        | SourceFileType::Bcf
+        | SourceFileType::Bib
+        | SourceFileType::Org
+        | SourceFileType::Rst => None,
+    }
+}
+
+/// Get the byte ranges of every citation of `key` in the buffer, in order.
+///
+/// The buffer is assumed to have file type specified by `ft`. As with [`citekey_range_at`], only
+/// TeX, Markdown, and Typst contents are supported; for other file types, no ranges are returned.
+pub fn citekey_ranges(ft: SourceFileType, buffer: &[u8], key: &str) -> Vec<Range<usize>> {
+    if key.is_empty() {
+        return Vec::new();
+    }
+
+    // Pandoc does not parse citations inside code
+    let code = if ft == SourceFileType::Md {
+        md::code_ranges(buffer)
+    } else {
+        Vec::new()
+    };
+
+    memmem::find_iter(buffer, key.as_bytes())
+        .map(|start| start..start + key.len())
+        .filter(|range| !code.iter().any(|code| code.contains(&range.start)))
+        .filter(|range| citekey_range_at(ft, buffer, range.end).as_ref() == Some(range))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_citekey_ranges() {
+        let contents = br"See \cite{ref1, ref10} and \textcite[p. 2]{ref1}, but not ref1.";
+        assert_eq!(
+            citekey_ranges(SourceFileType::Tex, contents, "ref1"),
+            vec![10..14, 43..47]
+        );
+
+        let contents = b"As in [@ref1; @ref10] and @ref1. Also `@ref1` and user@ref1.";
+        assert_eq!(
+            citekey_ranges(SourceFileType::Md, contents, "ref1"),
+            vec![8..12, 27..31]
+        );
+
+        let contents = b"See @ref1[p. 3] and #cite(<ref1>), but not <ref1>.";
+        assert_eq!(
+            citekey_ranges(SourceFileType::Typ, contents, "ref1"),
+            vec![5..9, 27..31]
+        );
+
+        assert!(citekey_ranges(SourceFileType::Bib, b"@article{ref1,}", "ref1").is_empty());
+    }
+}
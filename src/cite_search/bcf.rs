@@ -0,0 +1,68 @@
+use std::{borrow::Cow, str::from_utf8, sync::LazyLock};
+
+use regex::bytes::Regex;
+use serde_bibtex::token::is_entry_key;
+
+use crate::RecordId;
+
+static BCF_CITEKEY_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<bcf:citekey\b[^>]*>([^<]+)</bcf:citekey>").unwrap());
+
+/// Replace the predefined XML entities in the text.
+fn unescape_xml(s: &str) -> Cow<'_, str> {
+    if s.contains('&') {
+        Cow::Owned(
+            s.replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&"),
+        )
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+/// Get all citation keys in the buffer.
+///
+/// The buffer is a biblatex control file, in which citekeys appear in the form
+/// `<bcf:citekey order="1" intorder="1">key</bcf:citekey>`.
+pub fn get_citekeys<T: Extend<RecordId>>(buffer: &[u8], container: &mut T) {
+    container.extend(
+        BCF_CITEKEY_RE
+            .captures_iter(buffer)
+            // SAFETY: the regex has a non-optional capture group
+            .filter_map(|c| from_utf8(c.get(1).unwrap().as_bytes()).ok())
+            .map(|s| unescape_xml(s.trim()))
+            .filter(|s| is_entry_key(s) && s != "*")
+            .map(|s| RecordId::from(s.as_ref())),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_bcf_citekeys() {
+        let input = br#"<?xml version="1.0" encoding="UTF-8"?>
+<bcf:controlfile version="3.10" bltxversion="3.19" xmlns:bcf="https://sourceforge.net/projects/biblatex">
+  <bcf:bibdata section="0">
+    <bcf:datasource type="file" datatype="bibtex" glob="false">refs.bib</bcf:datasource>
+  </bcf:bibdata>
+  <bcf:section number="0">
+    <bcf:citekey order="1" intorder="1">c1</bcf:citekey>
+    <bcf:citekey order="2" intorder="1">zbl:1337.28015</bcf:citekey>
+    <bcf:citekey order="3" intorder="1">c&amp;2</bcf:citekey>
+    <bcf:citekey order="4" intorder="1">*</bcf:citekey>
+  </bcf:section>
+</bcf:controlfile>
+"#;
+        let mut vec: Vec<RecordId> = Vec::new();
+        get_citekeys(input, &mut vec);
+        assert_eq!(
+            vec,
+            ["c1", "zbl:1337.28015", "c&2"].map(RecordId::from).to_vec()
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::{ops::Range, str::from_utf8};
+
+use memchr::memchr;
+use serde_bibtex::token::is_entry_key;
+
+use crate::RecordId;
+
+/// Whether or not the byte can appear in a Pandoc-style citation key.
+fn is_key_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"_:.#$%&-+?<>~/".contains(&b) || !b.is_ascii()
+}
+
+/// Whether or not the `@` at position `at` begins a citation, which is the case unless it is
+/// preceded by an alphanumeric character (such as in an email address).
+fn is_citation_start(buffer: &[u8], at: usize) -> bool {
+    at == 0 || !buffer[at - 1].is_ascii_alphanumeric()
+}
+
+/// Move forward while the bytes can appear in a citation key, and then backtrack over any
+/// trailing punctuation, which is not part of the key.
+fn key_end(buffer: &[u8], start: usize, min: usize) -> usize {
+    let mut end = buffer[start..]
+        .iter()
+        .position(|b| !is_key_byte(*b))
+        .map_or(buffer.len(), |offset| start + offset);
+    while end > min && buffer[end - 1].is_ascii_punctuation() && buffer[end - 1] != b'_' {
+        end -= 1;
+    }
+    end
+}
+
+/// The length of the fence if the line opens or closes a fenced code block, which is a line
+/// beginning with at least three backticks or tildes, indented by at most three spaces.
+fn fence(line: &[u8]) -> Option<(u8, usize)> {
+    let indent = line.iter().take(4).take_while(|b| **b == b' ').count();
+    let rest = &line[indent..];
+    let ch = *rest.first().filter(|b| matches!(b, b'`' | b'~'))?;
+    let len = rest.iter().take_while(|b| **b == ch).count();
+    (indent < 4 && len >= 3).then_some((ch, len))
+}
+
+/// Append the byte ranges of the inline code spans in the line, which begins at `offset`.
+fn inline_code_ranges(line: &[u8], offset: usize, ranges: &mut Vec<Range<usize>>) {
+    let mut pos = 0;
+    while let Some(next) = memchr(b'`', &line[pos..]) {
+        let start = pos + next;
+        let len = line[start..].iter().take_while(|b| **b == b'`').count();
+        pos = start + len;
+
+        // find a closing run of backticks of the same length
+        let mut search = pos;
+        while let Some(next) = memchr(b'`', &line[search..]) {
+            let close = search + next;
+            let close_len = line[close..].iter().take_while(|b| **b == b'`').count();
+            if close_len == len {
+                ranges.push(offset + start..offset + close + close_len);
+                pos = close + close_len;
+                break;
+            }
+            search = close + close_len;
+        }
+    }
+}
+
+/// The byte ranges of fenced code blocks and inline code spans, in which Pandoc does not parse
+/// citations. The ranges are sorted and do not overlap.
+pub(super) fn code_ranges(buffer: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut open_fence: Option<(u8, usize, usize)> = None;
+    let mut offset = 0;
+
+    for line in buffer.split_inclusive(|b| *b == b'\n') {
+        let end = offset + line.len();
+        match (open_fence, fence(line)) {
+            (Some((ch, len, start)), Some((close_ch, close_len)))
+                if ch == close_ch && close_len >= len =>
+            {
+                ranges.push(start..end);
+                open_fence = None;
+            }
+            (Some(_), _) => {}
+            (None, Some((ch, len))) => open_fence = Some((ch, len, offset)),
+            (None, None) => inline_code_ranges(line, offset, &mut ranges),
+        }
+        offset = end;
+    }
+
+    // an unclosed code block extends to the end of the buffer
+    if let Some((_, _, start)) = open_fence {
+        ranges.push(start..buffer.len());
+    }
+    ranges
+}
+
+/// Get all citation keys in the buffer.
+///
+/// Citation keys appear in the buffer in the form `[@key1; @key2]`, `[-@key]`, `@key`, or
+/// `@{key}`, following the syntax used by Pandoc and Quarto. Citations are also read from the
+/// YAML metadata block, such as in the `nocite` field. Citations inside code blocks and inline
+/// code are ignored.
+pub fn get_citekeys<T: Extend<RecordId>>(buffer: &[u8], container: &mut T) {
+    let code = code_ranges(buffer);
+    let mut code_iter = code.iter().peekable();
+    let mut pos: usize = 0;
+
+    while let Some(next) = memchr(b'@', &buffer[pos..]) {
+        pos += next;
+
+        // skip over any code which contains the current position
+        while code_iter.next_if(|range| range.end <= pos).is_some() {}
+        if let Some(range) = code_iter.peek()
+            && range.contains(&pos)
+        {
+            pos = range.end;
+            continue;
+        }
+
+        if !is_citation_start(buffer, pos) {
+            pos += 1;
+            continue;
+        }
+
+        // the key is either delimited by braces, or consists of the bytes which can appear in a
+        // citation key
+        let (key, end) = if buffer.get(pos + 1) == Some(&b'{') {
+            match memchr(b'}', &buffer[pos + 2..]) {
+                Some(len) => (&buffer[pos + 2..pos + 2 + len], pos + 3 + len),
+                None => (&buffer[pos + 2..pos + 2], pos + 2),
+            }
+        } else {
+            let end = key_end(buffer, pos + 1, pos + 1);
+            (&buffer[pos + 1..end], end)
+        };
+
+        if let Ok(key) = from_utf8(key)
+            && is_entry_key(key)
+        {
+            container.extend(Some(RecordId::from(key)));
+        }
+        pos = end.max(pos + 1);
+    }
+}
+
+/// Get the byte range of the citation key containing the position `cursor`, if any. The range
+/// is empty if only the `@` has been typed.
+pub fn citekey_range_at(buffer: &[u8], cursor: usize) -> Option<Range<usize>> {
+    let start = buffer[..cursor]
+        .iter()
+        .rposition(|b| !is_key_byte(*b))
+        .map_or(0, |idx| idx + 1);
+
+    if start == 0 || buffer[start - 1] != b'@' || !is_citation_start(buffer, start - 1) {
+        return None;
+    }
+
+    Some(start..key_end(buffer, cursor, cursor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_citekeys_md() {
+        let contents = b"As shown in [@ref1; @ref2, p. 3], and by @ref3.
+Send email to user@example.com, or see [-@ref4].";
+        let mut vec: Vec<RecordId> = Vec::new();
+        get_citekeys(contents, &mut vec);
+        assert_eq!(
+            vec,
+            ["ref1", "ref2", "ref3", "ref4"]
+                .map(RecordId::from)
+                .to_vec()
+        );
+    }
+
+    #[test]
+    fn test_get_citekeys_md_metadata_and_code() {
+        let contents = b"---
+title: Example
+nocite: |
+  @ref1, @ref2
+---
+
+Some `inline @code` and a citation @{zbl:1337.28015}.
+
+```python
+print(\"@decorator\")
+```
+
+~~~~
+@ignored
+```
+~~~~
+
+Finally [see @ref3, pp. 33-35; also @ref4, chap. 1].";
+        let mut vec: Vec<RecordId> = Vec::new();
+        get_citekeys(contents, &mut vec);
+        assert_eq!(
+            vec,
+            ["ref1", "ref2", "zbl:1337.28015", "ref3", "ref4"]
+                .map(RecordId::from)
+                .to_vec()
+        );
+    }
+
+    #[test]
+    fn test_citekey_range_at_md() {
+        let contents = b"See [@ref1; @zbl:12] and @ab";
+        assert_eq!(citekey_range_at(contents, 7), Some(6..10));
+        assert_eq!(citekey_range_at(contents, 6), Some(6..10));
+        assert_eq!(citekey_range_at(contents, 15), Some(13..19));
+        assert_eq!(citekey_range_at(contents, 28), Some(26..28));
+        assert_eq!(citekey_range_at(contents, 2), None);
+        assert_eq!(citekey_range_at(contents, 11), None);
+    }
+}
@@ -0,0 +1,75 @@
+use std::str::from_utf8;
+
+use memchr::memmem;
+use serde_bibtex::token::is_entry_key;
+
+use crate::RecordId;
+
+/// Whether or not the byte can appear in an org-cite citation key.
+fn is_key_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"-.:?!`'/*@+|(){}<>&_^$#%~".contains(&b) || !b.is_ascii()
+}
+
+/// Get all citation keys in the buffer.
+///
+/// Citation keys appear in the buffer in org-cite citations, such as `[cite:@key]`,
+/// `[cite/t:@key1;@key2]`, or `[cite:see @key p. 7]`. Each key is prefixed by `@`, and keys are
+/// separated by `;`.
+pub fn get_citekeys<T: Extend<RecordId>>(buffer: &[u8], container: &mut T) {
+    let mut pos: usize = 0;
+
+    while let Some(next) = memmem::find(&buffer[pos..], b"[cite") {
+        pos += next + 5;
+
+        // the optional style, such as `/t` or `/author/caps`, is followed by the colon
+        let Some(colon) = buffer[pos..]
+            .iter()
+            .position(|b| !(b.is_ascii_alphanumeric() || b"/_-".contains(b)))
+            .filter(|offset| buffer[pos + offset] == b':' && (*offset == 0 || buffer[pos] == b'/'))
+        else {
+            continue;
+        };
+        let start = pos + colon + 1;
+        let Some(len) = buffer[start..].iter().position(|b| *b == b']') else {
+            break;
+        };
+        let end = start + len;
+
+        for reference in buffer[start..end].split(|b| *b == b';') {
+            if let Some(at) = reference.iter().position(|b| *b == b'@') {
+                let key = &reference[at + 1..];
+                let key_len = key
+                    .iter()
+                    .position(|b| !is_key_byte(*b))
+                    .unwrap_or(key.len());
+                if let Ok(key) = from_utf8(&key[..key_len])
+                    && is_entry_key(key)
+                {
+                    container.extend(Some(RecordId::from(key)));
+                }
+            }
+        }
+        pos = end + 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_citekeys_org() {
+        let contents =
+            b"As shown in [cite:@ref1; see @ref2 p. 3], and by [cite/t/c:@zbl:1337.28015].
+Send email to user@example.com, or see [cite:common prefix; @ref3; @ref4 suffix].
+This is not a citation: [citep:@ref5] or [cite @ref6].";
+        let mut vec: Vec<RecordId> = Vec::new();
+        get_citekeys(contents, &mut vec);
+        assert_eq!(
+            vec,
+            ["ref1", "ref2", "zbl:1337.28015", "ref3", "ref4"]
+                .map(RecordId::from)
+                .to_vec()
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use std::str::from_utf8;
+
+use memchr::memmem;
+use serde_bibtex::token::is_entry_key;
+
+use crate::RecordId;
+
+/// The start of the role name containing the `cite` at position `cite`, if the role name is
+/// `cite` or `footcite`.
+fn role_start(buffer: &[u8], cite: usize) -> Option<usize> {
+    let before = &buffer[..cite];
+    if before.ends_with(b":foot") {
+        Some(cite - 5)
+    } else if before.ends_with(b":") {
+        Some(cite - 1)
+    } else {
+        None
+    }
+}
+
+/// Get all citation keys in the buffer.
+///
+/// Citation keys appear in the buffer as the contents of the citation roles provided by
+/// `sphinxcontrib-bibtex`, such as ``:cite:`key` ``, ``:cite:p:`key1,key2` ``, or
+/// ``:footcite:t:`key` ``. Keys are separated by commas.
+pub fn get_citekeys<T: Extend<RecordId>>(buffer: &[u8], container: &mut T) {
+    let mut pos: usize = 0;
+
+    while let Some(next) = memmem::find(&buffer[pos..], b"cite") {
+        let cite = pos + next;
+        pos = cite + 4;
+
+        // the role name must begin at the start of a word
+        let Some(start) = role_start(buffer, cite) else {
+            continue;
+        };
+        if start > 0 && buffer[start - 1].is_ascii_alphanumeric() {
+            continue;
+        }
+
+        // the role name may contain a variant, such as `:p` or `:ts`, and is followed by the
+        // interpreted text in backticks
+        let variant_len = match buffer[pos..].strip_prefix(b":") {
+            Some(rest) => rest.iter().take_while(|b| b.is_ascii_lowercase()).count(),
+            None => continue,
+        };
+        let open = pos + 1 + variant_len;
+        let content_start = if variant_len == 0 {
+            open
+        } else if buffer[open..].starts_with(b":") {
+            open + 1
+        } else {
+            continue;
+        };
+        if buffer.get(content_start) != Some(&b'`') {
+            continue;
+        }
+
+        let Some(len) = buffer[content_start + 1..].iter().position(|b| *b == b'`') else {
+            break;
+        };
+        let end = content_start + 1 + len;
+
+        for key in buffer[content_start + 1..end].split(|b| *b == b',') {
+            if let Ok(key) = from_utf8(key.trim_ascii())
+                && is_entry_key(key)
+            {
+                container.extend(Some(RecordId::from(key)));
+            }
+        }
+        pos = end + 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_citekeys_rst() {
+        let contents = b"As shown in :cite:`ref1`, and by :cite:t:`ref2, zbl:1337.28015`.
+See also :footcite:p:`ref3` and :footcite:`ref4`.
+
+The word citation, the role :excite:`ref5`, and ``:cite:`` are not citations.";
+        let mut vec: Vec<RecordId> = Vec::new();
+        get_citekeys(contents, &mut vec);
+        assert_eq!(
+            vec,
+            ["ref1", "ref2", "zbl:1337.28015", "ref3", "ref4"]
+                .map(RecordId::from)
+                .to_vec()
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use std::sync::LazyLock;
+use std::{ops::Range, sync::LazyLock};
 
 use memchr::{memchr, memchr2, memchr3};
 use regex::Regex;
@@ -160,6 +160,60 @@ pub fn get_citekeys<T: Extend<RecordId>>(buffer: &[u8], container: &mut T) {
     }
 }
 
+/// Get the byte range of the citation key containing the position `cursor`, if the position is
+/// inside the argument of a citation macro. The range is empty if the key has not been typed yet.
+///
+/// The argument need not be closed, so that this can be used while the citation is being typed.
+pub fn citekey_range_at(buffer: &[u8], cursor: usize) -> Option<Range<usize>> {
+    let mut pos: usize = 0;
+
+    while let Some(next) = memchr2(b'%', b'\\', &buffer[pos..cursor]) {
+        pos += next;
+        match buffer[pos] {
+            b'\\' => {
+                let (opt_cmd, next) = ascii_macro(buffer, pos);
+                pos = next;
+                if let Some(cmd) = opt_cmd
+                    && is_citation_macro_name(cmd)
+                {
+                    pos = comment_and_ws(buffer, pos);
+                    pos = macro_opt_argument(buffer, pos);
+                    pos = comment_and_ws(buffer, pos);
+                    if let Some(b'{') = buffer.get(pos) {
+                        let start = pos + 1;
+                        let end = memchr2(b'{', b'}', &buffer[start..])
+                            .map_or(buffer.len(), |offset| start + offset);
+                        if (start..=end).contains(&cursor) {
+                            let is_sep =
+                                |b: &u8| *b == b',' || *b == b'%' || b.is_ascii_whitespace();
+                            let key_start = buffer[start..cursor]
+                                .iter()
+                                .rposition(is_sep)
+                                .map_or(start, |idx| start + idx + 1);
+                            let key_end = buffer[cursor..end]
+                                .iter()
+                                .position(is_sep)
+                                .map_or(end, |idx| cursor + idx);
+                            return Some(key_start..key_end);
+                        }
+                        pos = end;
+                    }
+                }
+            }
+            _ => match memchr(b'\n', &buffer[pos..]) {
+                Some(skip) => {
+                    pos += skip + 1;
+                }
+                None => break,
+            },
+        }
+        if pos >= cursor {
+            break;
+        }
+    }
+    None
+}
+
 #[cfg(test)]
 mod test {
     use std::collections::BTreeSet;
@@ -203,4 +257,16 @@ mod test {
             assert_eq!(*exp, rec.name());
         }
     }
+
+    #[test]
+    fn test_citekey_range_at_tex() {
+        let contents = br"See \cite[p. 2]{ref1, ref2} and \textcite{ab";
+        assert_eq!(citekey_range_at(contents, 19), Some(16..20));
+        assert_eq!(citekey_range_at(contents, 23), Some(22..26));
+        assert_eq!(citekey_range_at(contents, 26), Some(22..26));
+        assert_eq!(citekey_range_at(contents, 27), None);
+        assert_eq!(citekey_range_at(contents, 44), Some(42..44));
+        assert_eq!(citekey_range_at(contents, 30), None);
+        assert_eq!(citekey_range_at(contents, 2), None);
+    }
 }
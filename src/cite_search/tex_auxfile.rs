@@ -8,10 +8,14 @@ use crate::RecordId;
 static AUX_CITE_RE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"\\abx@aux@cite\{[0-9]*\}\{([^\}]+)\}").unwrap());
 
+static AUX_CITATION_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\\citation\{([^\}]+)\}").unwrap());
+
 /// Get all citation keys in the buffer.
 ///
 /// Citekeys essentially appear in the buffer as in the form `\abx@aux@cite{...}{key}` where `...`
-/// is a sequence of digits (possibly empty).
+/// is a sequence of digits (possibly empty), as written by biblatex, or in the form
+/// `\citation{key1,key2}`, as written by BibTeX.
 pub fn get_citekeys<T: Extend<RecordId>>(buffer: &[u8], container: &mut T) {
     container.extend(
         AUX_CITE_RE
@@ -21,6 +25,15 @@ pub fn get_citekeys<T: Extend<RecordId>>(buffer: &[u8], container: &mut T) {
             .filter(|s| is_entry_key(s) && s != &"*")
             .map(RecordId::from),
     );
+    container.extend(
+        AUX_CITATION_RE
+            .captures_iter(buffer)
+            // SAFETY: the regex has a non-optional capture group
+            .filter_map(|c| from_utf8(c.get(1).unwrap().as_bytes()).ok())
+            .flat_map(|s| s.split(','))
+            .filter(|s| is_entry_key(s) && s != &"*")
+            .map(RecordId::from),
+    );
 }
 
 #[cfg(test)]
@@ -46,4 +59,22 @@ mod test {
             assert!(!vec.contains(&RecordId::from(s)));
         }
     }
+
+    #[test]
+    fn test_find_bibtex_aux_citekeys() {
+        let input = br#"
+\relax
+\citation{b1}
+\citation{b2,zbl:1337.28015}
+\citation{*}
+\bibstyle{plain}
+\bibdata{refs}
+        "#;
+        let mut vec: Vec<RecordId> = Vec::new();
+        get_citekeys(input, &mut vec);
+        assert_eq!(
+            vec,
+            ["b1", "b2", "zbl:1337.28015"].map(RecordId::from).to_vec()
+        );
+    }
 }
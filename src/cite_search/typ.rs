@@ -0,0 +1,141 @@
+use std::{ops::Range, str::from_utf8};
+
+use memchr::memchr2;
+use serde_bibtex::token::is_entry_key;
+
+use crate::RecordId;
+
+/// Whether or not the byte can appear in a Typst label.
+fn is_label_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"_-:.".contains(&b) || !b.is_ascii()
+}
+
+/// Whether or not the `@` at position `at` begins a reference, which is the case unless it is
+/// preceded by an alphanumeric character (such as in an email address).
+fn is_reference_start(buffer: &[u8], at: usize) -> bool {
+    at == 0 || !buffer[at - 1].is_ascii_alphanumeric()
+}
+
+/// Move forward while the bytes can appear in a label, and then backtrack over any trailing `.`
+/// or `:`, which Typst does not include in a reference.
+fn label_end(buffer: &[u8], start: usize, min: usize) -> usize {
+    let mut end = buffer[start..]
+        .iter()
+        .position(|b| !is_label_byte(*b))
+        .map_or(buffer.len(), |offset| start + offset);
+    while end > min && matches!(buffer[end - 1], b'.' | b':') {
+        end -= 1;
+    }
+    end
+}
+
+/// Insert the key into the container if it is a valid citation key.
+fn insert_key<T: Extend<RecordId>>(key: &[u8], container: &mut T) {
+    if let Ok(key) = from_utf8(key)
+        && is_entry_key(key)
+    {
+        container.extend(Some(RecordId::from(key)));
+    }
+}
+
+/// Parse the first argument of a `#cite(...)` call, where `start` is the position after the
+/// opening parenthesis. Returns the range of the key and the position after the argument.
+fn cite_argument(buffer: &[u8], start: usize) -> Option<(Range<usize>, usize)> {
+    let start = start
+        + buffer[start..]
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())?;
+    let rest = &buffer[start..];
+
+    let (open, close) = if rest.starts_with(b"<") {
+        (1, b'>')
+    } else if rest.starts_with(b"label(\"") {
+        (7, b'"')
+    } else {
+        return None;
+    };
+
+    let key_start = start + open;
+    let key_end = key_start + buffer[key_start..].iter().position(|b| *b == close)?;
+    Some((key_start..key_end, key_end + 1))
+}
+
+/// Get all citation keys in the buffer.
+///
+/// Citation keys appear in the buffer either as references of the form `@key` or `@key[p. 7]`,
+/// or as the first argument of a `#cite(<key>)` or `#cite(label("key"))` call.
+pub fn get_citekeys<T: Extend<RecordId>>(buffer: &[u8], container: &mut T) {
+    let mut pos: usize = 0;
+
+    while let Some(next) = memchr2(b'@', b'#', &buffer[pos..]) {
+        pos += next;
+        if buffer[pos] == b'@' {
+            let end = label_end(buffer, pos + 1, pos + 1);
+            if is_reference_start(buffer, pos) {
+                insert_key(&buffer[pos + 1..end], container);
+            }
+            pos = end.max(pos + 1);
+        } else if buffer[pos + 1..].starts_with(b"cite(")
+            && let Some((range, end)) = cite_argument(buffer, pos + 6)
+        {
+            insert_key(&buffer[range], container);
+            pos = end;
+        } else {
+            pos += 1;
+        }
+    }
+}
+
+/// Get the byte range of the citation key containing the position `cursor`, if any. The range
+/// is empty if only the `@` or the opening `<` has been typed.
+pub fn citekey_range_at(buffer: &[u8], cursor: usize) -> Option<Range<usize>> {
+    let start = buffer[..cursor]
+        .iter()
+        .rposition(|b| !is_label_byte(*b))
+        .map_or(0, |idx| idx + 1);
+
+    match start.checked_sub(1).map(|idx| buffer[idx]) {
+        Some(b'@') if is_reference_start(buffer, start - 1) => {
+            Some(start..label_end(buffer, cursor, cursor))
+        }
+        Some(b'<') if buffer[..start - 1].trim_ascii_end().ends_with(b"#cite(") => {
+            let end = buffer[cursor..]
+                .iter()
+                .position(|b| *b == b'>' || b.is_ascii_whitespace())
+                .map_or(buffer.len(), |offset| cursor + offset);
+            Some(start..end)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_citekeys_typ() {
+        let contents = br#"As shown in @ref1[p. 3] and @zbl:1337.28015.
+Send email to user@example.com, or see #cite(<ref2>, form: "prose")
+and #cite( label("ref3") ). See also <not-a-citation> and #emph[@ref4]."#;
+        let mut vec: Vec<RecordId> = Vec::new();
+        get_citekeys(contents, &mut vec);
+        assert_eq!(
+            vec,
+            ["ref1", "zbl:1337.28015", "ref2", "ref3", "ref4"]
+                .map(RecordId::from)
+                .to_vec()
+        );
+    }
+
+    #[test]
+    fn test_citekey_range_at_typ() {
+        let contents = b"See @ref1 and #cite(<zbl:12>) and @ab.";
+        assert_eq!(citekey_range_at(contents, 6), Some(5..9));
+        assert_eq!(citekey_range_at(contents, 5), Some(5..9));
+        assert_eq!(citekey_range_at(contents, 23), Some(21..27));
+        assert_eq!(citekey_range_at(contents, 37), Some(35..37));
+        assert_eq!(citekey_range_at(contents, 2), None);
+        assert_eq!(citekey_range_at(contents, 12), None);
+    }
+}
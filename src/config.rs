@@ -1,20 +1,47 @@
+mod edit;
 mod validate;
 
-use std::{fs::read_to_string, io, path::Path, sync::LazyLock};
+use std::{
+    collections::BTreeMap,
+    fs::read_to_string,
+    io,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
 
-use anyhow::{Error, anyhow};
+use anyhow::{Error, anyhow, bail};
+use chrono::TimeDelta;
 use regex::Regex;
 use serde::Deserialize;
 use toml::from_str;
 
 use crate::{
     Alias, Identifier,
-    format::DEFAULT_FIND_TEMPLATE,
+    entry::{EntryStyle, Flavor},
+    format::{DEFAULT_ATTACH_FILENAME, DEFAULT_ATTACH_LAYOUT, DEFAULT_FIND_TEMPLATE, Template},
     logger::{debug, info, warn},
-    normalize::Normalization,
+    normalize::{NameFormat, Normalization},
+    provider::{
+        DEFAULT_ISBN_SOURCES, IsbnSource,
+        custom::{self, CustomProvider},
+        register_isbn_sources,
+    },
 };
+pub use edit::{get_value, set_value};
 pub use validate::report_config_errors as validate;
 
+/// The default configuration file.
+const DEFAULT_CONFIG: &str = include_str!("config/default_config.toml");
+
+/// The default number of seconds after which a cached null record is retried, which is one week.
+const DEFAULT_NULL_TTL: u32 = 604_800;
+
+/// The default number of automatic backups which are kept.
+const DEFAULT_BACKUP_KEEP: usize = 10;
+
+/// The name of the project configuration file.
+pub const PROJECT_CONFIG_FILE: &str = ".autobib.toml";
+
 /// A direct representation of the default configuration used by library, for easy deserialization
 /// from configuration files.
 #[derive(Debug, Default, Deserialize, PartialEq)]
@@ -27,7 +54,35 @@ struct RawConfig {
     #[serde(default)]
     pub alias_transform: RawAutoAlias,
     #[serde(default)]
+    pub auto_alias: RawAutoAliasConfig,
+    #[serde(default)]
     pub on_insert: Normalization,
+    #[serde(default)]
+    pub normalize: RawNormalizeConfig,
+    #[serde(default)]
+    pub styles: BTreeMap<String, BTreeMap<String, Vec<String>>>,
+    #[serde(default)]
+    pub get: RawGetConfig,
+    #[serde(default)]
+    pub output: RawOutputConfig,
+    #[serde(default)]
+    pub source: RawSourceConfig,
+    #[serde(default)]
+    pub attach: RawAttachConfig,
+    #[serde(default)]
+    pub backup: RawBackupConfig,
+    #[serde(default)]
+    pub cache: RawCacheConfig,
+    #[serde(default)]
+    pub http: RawHttpConfig,
+    #[serde(default)]
+    pub isbn: RawIsbnConfig,
+    #[serde(default)]
+    pub merge: BTreeMap<String, FieldMergeRule>,
+    #[serde(default)]
+    pub providers: BTreeMap<String, RawProviderConfig>,
+    #[serde(default)]
+    pub profile: BTreeMap<String, RawProfileConfig>,
 }
 
 fn find_default_template() -> String {
@@ -53,7 +108,313 @@ impl Default for RawFindConfig {
     }
 }
 
-/// A direct representation of the `[auto_alias]` section of the configuration.
+/// A direct representation of the `[normalize]` section of the configuration.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RawNormalizeConfig {
+    #[serde(default)]
+    pub protected_words: Vec<String>,
+    #[serde(default)]
+    pub name_format: NameFormat,
+}
+
+/// A direct representation of the `[get]` section of the configuration.
+#[derive(Debug, Default, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RawGetConfig {
+    #[serde(default)]
+    pub default_out: Option<PathBuf>,
+    #[serde(default)]
+    pub default_append: bool,
+}
+
+fn output_default_indent() -> usize {
+    EntryStyle::default().indent
+}
+
+fn output_default_trailing_comma() -> bool {
+    EntryStyle::default().trailing_comma
+}
+
+/// A direct representation of the `[output]` section of the configuration.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RawOutputConfig {
+    #[serde(default)]
+    pub align: bool,
+    #[serde(default)]
+    pub annotate: bool,
+    #[serde(default)]
+    pub duplicates: DuplicatePolicy,
+    #[serde(default)]
+    pub flavor: Option<Flavor>,
+    #[serde(default = "output_default_indent")]
+    pub indent: usize,
+    #[serde(default)]
+    pub sort: SortOrder,
+    #[serde(default)]
+    pub string_preamble: bool,
+    #[serde(default = "output_default_trailing_comma")]
+    pub trailing_comma: bool,
+    #[serde(default)]
+    pub wrap: usize,
+}
+
+impl Default for RawOutputConfig {
+    fn default() -> Self {
+        Self {
+            align: false,
+            annotate: false,
+            duplicates: DuplicatePolicy::default(),
+            flavor: None,
+            indent: output_default_indent(),
+            sort: SortOrder::default(),
+            string_preamble: false,
+            trailing_comma: output_default_trailing_comma(),
+            wrap: 0,
+        }
+    }
+}
+
+impl RawOutputConfig {
+    /// The formatting of the written entries.
+    pub fn style(&self) -> EntryStyle {
+        EntryStyle {
+            indent: self.indent,
+            align: self.align,
+            wrap: self.wrap,
+            trailing_comma: self.trailing_comma,
+        }
+    }
+}
+
+fn source_default_null_ttl() -> u32 {
+    DEFAULT_NULL_TTL
+}
+
+/// A direct representation of the `[source]` section of the configuration.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RawSourceConfig {
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    #[serde(default)]
+    pub include_hidden: bool,
+    #[serde(default)]
+    pub retry_null: usize,
+    #[serde(default = "source_default_null_ttl")]
+    pub null_ttl: u32,
+}
+
+impl Default for RawSourceConfig {
+    fn default() -> Self {
+        Self {
+            ignore: Vec::new(),
+            include_hidden: false,
+            retry_null: 0,
+            null_ttl: DEFAULT_NULL_TTL,
+        }
+    }
+}
+
+fn attach_default_filename() -> String {
+    DEFAULT_ATTACH_FILENAME.into()
+}
+
+fn attach_default_layout() -> String {
+    DEFAULT_ATTACH_LAYOUT.into()
+}
+
+/// A direct representation of the `[attach]` section of the configuration.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RawAttachConfig {
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default = "attach_default_filename")]
+    pub filename: String,
+    #[serde(default = "attach_default_layout")]
+    pub layout: String,
+}
+
+impl Default for RawAttachConfig {
+    fn default() -> Self {
+        Self {
+            email: None,
+            filename: attach_default_filename(),
+            layout: attach_default_layout(),
+        }
+    }
+}
+
+fn backup_default_enabled() -> bool {
+    true
+}
+
+fn backup_default_keep() -> usize {
+    DEFAULT_BACKUP_KEEP
+}
+
+/// A direct representation of the `[backup]` section of the configuration.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RawBackupConfig {
+    #[serde(default = "backup_default_enabled")]
+    pub enabled: bool,
+    #[serde(default = "backup_default_keep")]
+    pub keep: usize,
+}
+
+impl Default for RawBackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: backup_default_enabled(),
+            keep: backup_default_keep(),
+        }
+    }
+}
+
+/// A direct representation of the `[cache]` section of the configuration.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RawCacheConfig {
+    #[serde(default)]
+    pub null_ttl: Option<String>,
+}
+
+/// A direct representation of the `[http]` section of the configuration.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RawHttpConfig {
+    #[serde(default)]
+    pub proxy: Option<String>,
+    #[serde(default)]
+    pub ca_bundle: Option<PathBuf>,
+}
+
+fn isbn_default_sources() -> Vec<IsbnSource> {
+    DEFAULT_ISBN_SOURCES.into()
+}
+
+/// A direct representation of the `[isbn]` section of the configuration.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RawIsbnConfig {
+    #[serde(default = "isbn_default_sources")]
+    pub sources: Vec<IsbnSource>,
+}
+
+impl Default for RawIsbnConfig {
+    fn default() -> Self {
+        Self {
+            sources: isbn_default_sources(),
+        }
+    }
+}
+
+/// Parse an age, such as `180d`, which is a number followed by one of the units `h` (hours), `d`
+/// (days), `w` (weeks), or `y` (years of 365 days).
+pub fn parse_age(input: &str) -> Result<TimeDelta, &'static str> {
+    const INVALID: &str = "expected a number followed by 'h', 'd', 'w', or 'y', such as '180d'";
+
+    let Some(unit) = input.chars().last() else {
+        return Err(INVALID);
+    };
+    let count: i64 = input[..input.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| INVALID)?;
+    let age = match unit {
+        'h' => TimeDelta::try_hours(count),
+        'd' => TimeDelta::try_days(count),
+        'w' => TimeDelta::try_weeks(count),
+        'y' => count.checked_mul(365).and_then(TimeDelta::try_days),
+        _ => None,
+    };
+    age.filter(|age| *age >= TimeDelta::zero()).ok_or(INVALID)
+}
+
+fn provider_default_canonical() -> bool {
+    true
+}
+
+/// A direct representation of a user-defined provider in the `[providers]` section of the
+/// configuration.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RawProviderConfig {
+    pub id: String,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default = "provider_default_canonical")]
+    pub canonical: bool,
+}
+
+/// A direct representation of a profile in the `[profile]` section of the configuration, or of a
+/// project configuration file.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RawProfileConfig {
+    #[serde(default)]
+    pub database: Option<PathBuf>,
+    #[serde(default)]
+    pub attachments_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub preferred_providers: Option<Vec<String>>,
+    #[serde(default)]
+    pub get: Option<RawGetConfig>,
+    #[serde(default)]
+    pub output: Option<RawOutputConfig>,
+}
+
+/// How to report multiple keys which refer to the same record in the output.
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicatePolicy {
+    /// Do not report duplicate keys.
+    Ignore,
+    /// Report duplicate keys as warnings.
+    #[default]
+    Warn,
+    /// Report duplicate keys as errors, which results in a non-zero exit code.
+    Error,
+}
+
+/// The order in which the records are written in the output.
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+    /// Sort by the first key of each record.
+    Key,
+    /// Sort by the last name of the first author or editor, and then by year.
+    Author,
+    /// Sort by year, and then by the last name of the first author or editor.
+    Year,
+    /// Sort by when the record was added to the database, oldest first.
+    Added,
+    /// Do not sort the records, which are written in the order of their canonical identifiers.
+    #[default]
+    #[serde(rename = "none")]
+    #[value(name = "none")]
+    Unsorted,
+}
+
+/// How conflicting values of a field are resolved, as set in the `[merge]` section of the
+/// configuration.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FieldMergeRule {
+    /// Keep the current value.
+    PreferCurrent,
+    /// Use the incoming value.
+    PreferIncoming,
+    /// Use the longer value, keeping the current value if the values have the same length.
+    PreferLongest,
+    /// Append the incoming value to the current value, unless the current value already contains
+    /// the incoming value.
+    Concat,
+}
+
+/// A direct representation of the `[alias_transform]` section of the configuration.
 #[derive(Debug, Default, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 struct RawAutoAlias {
@@ -63,6 +424,25 @@ struct RawAutoAlias {
     create_alias: bool,
 }
 
+/// A direct representation of the `[auto_alias]` section of the configuration.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RawAutoAliasConfig {
+    #[serde(default)]
+    pub template: Option<String>,
+    #[serde(default)]
+    pub lowercase: bool,
+}
+
+/// The rule for the aliases which are created automatically for new records.
+#[derive(Debug)]
+pub struct AutoAlias {
+    /// The template which is rendered with the data of the new record.
+    pub template: Template,
+    /// Whether or not to convert the rendered alias to lowercase.
+    pub lowercase: bool,
+}
+
 impl RawConfig {
     /// Load configuration by deserializing a toml file at the provided path, returning the default
     /// of `missing_ok` is true.
@@ -97,7 +477,30 @@ pub struct Config<F> {
     pub find: RawFindConfig,
     pub preferred_providers: Vec<String>,
     pub alias_transform: LazyAliasTransform<F>,
+    /// The rule for the aliases created automatically for new records, if any.
+    pub auto_alias: Option<AutoAlias>,
     pub on_insert: Normalization,
+    pub normalize: RawNormalizeConfig,
+    pub styles: BTreeMap<String, BTreeMap<String, Vec<String>>>,
+    /// The default output of `autobib get`. A relative path to the output file is relative to
+    /// the directory containing the file in which it is set.
+    pub get: RawGetConfig,
+    pub output: RawOutputConfig,
+    pub source: RawSourceConfig,
+    pub attach: RawAttachConfig,
+    pub backup: RawBackupConfig,
+    /// The age after which a cached null record is retrieved again when it is looked up, if any.
+    pub null_ttl: Option<TimeDelta>,
+    /// The HTTP configuration. A relative path to the CA bundle is relative to the directory
+    /// containing the configuration file.
+    pub http: RawHttpConfig,
+    /// The rules for resolving conflicting values of specific fields, keyed by the lowercase
+    /// field name.
+    pub merge: BTreeMap<String, FieldMergeRule>,
+    /// The database file set by the project configuration or the profile, if any.
+    pub database: Option<PathBuf>,
+    /// The attachment directory set by the project configuration or the profile, if any.
+    pub attachments_dir: Option<PathBuf>,
 }
 
 impl<F> Config<F> {
@@ -119,29 +522,163 @@ pub struct LazyAliasTransform<F> {
     create_alias: bool,
 }
 
+/// The project configuration file in `dir` or the closest of its parent directories, if any.
+pub fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|ancestor| ancestor.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Load a project configuration file.
+fn load_project(path: &Path) -> Result<RawProfileConfig, Error> {
+    let st = match read_to_string(path) {
+        Ok(st) => st,
+        Err(err) => bail!(
+            "Failed to load project configuration file '{}': {err}",
+            path.display()
+        ),
+    };
+    info!("Loading project configuration at path '{}'", path.display());
+    let project = from_str(&st)?;
+    debug!("Using project configuration:\n{project:?}");
+    Ok(project)
+}
+
+/// Replace the values of the configuration with the values set in a profile, where relative paths
+/// are relative to `dir`.
+fn apply_profile(
+    profile: RawProfileConfig,
+    dir: &Path,
+    database: &mut Option<PathBuf>,
+    attachments_dir: &mut Option<PathBuf>,
+    preferred_providers: &mut Vec<String>,
+    get: &mut RawGetConfig,
+    output: &mut RawOutputConfig,
+) {
+    if let Some(db_path) = profile.database {
+        *database = Some(dir.join(db_path));
+    }
+    if let Some(dir_path) = profile.attachments_dir {
+        *attachments_dir = Some(dir.join(dir_path));
+    }
+    if let Some(providers) = profile.preferred_providers {
+        *preferred_providers = providers;
+    }
+    if let Some(profile_get) = profile.get {
+        *get = RawGetConfig {
+            default_out: profile_get.default_out.map(|out| dir.join(out)),
+            ..profile_get
+        };
+    }
+    if let Some(profile_output) = profile.output {
+        *output = profile_output;
+    }
+}
+
 #[cold]
 pub fn write_default<W: io::Write>(mut writer: W) -> Result<(), io::Error> {
-    writer
-        .write(include_str!("config/default_config.toml").as_bytes())
-        .map(|_| ())
+    writer.write(DEFAULT_CONFIG.as_bytes()).map(|_| ())
 }
 
 /// Attempt to load the configuration file from the provided path.
 ///
 /// If `missing_ok` is true and the file is not found, this returns the default configuration.
+/// If a project configuration file is provided, the values set in the file replace the values in
+/// the configuration; relative paths in the file are relative to the directory containing the
+/// file. If a profile is provided, the values set in the corresponding `[profile.<name>]` section
+/// then replace the values in the rest of the configuration; relative paths in the profile are
+/// relative to the directory containing the configuration file.
 pub fn load<P: AsRef<Path>>(
     path: P,
     missing_ok: bool,
+    project: Option<&Path>,
+    profile: Option<&str>,
 ) -> Result<Config<impl FnOnce() -> Vec<(Regex, String)>>, Error> {
     let RawConfig {
         find,
-        preferred_providers,
+        mut preferred_providers,
         alias_transform: RawAutoAlias {
             rules,
             create_alias,
         },
-        on_insert,
-    } = RawConfig::load(path, missing_ok)?;
+        auto_alias,
+        mut on_insert,
+        normalize,
+        styles,
+        mut get,
+        mut output,
+        source,
+        attach,
+        backup,
+        cache,
+        mut http,
+        isbn,
+        merge,
+        providers,
+        profile: mut profiles,
+    } = RawConfig::load(&path, missing_ok)?;
+
+    let config_dir = path.as_ref().parent().unwrap_or(Path::new(""));
+    http.ca_bundle = http.ca_bundle.map(|ca_bundle| config_dir.join(ca_bundle));
+    get.default_out = get.default_out.map(|out| config_dir.join(out));
+
+    let mut database = None;
+    let mut attachments_dir = None;
+    if let Some(project_path) = project {
+        apply_profile(
+            load_project(project_path)?,
+            project_path.parent().unwrap_or(Path::new("")),
+            &mut database,
+            &mut attachments_dir,
+            &mut preferred_providers,
+            &mut get,
+            &mut output,
+        );
+    }
+    if let Some(name) = profile {
+        let Some(profile) = profiles.remove(name) else {
+            bail!("Profile '{name}' is not defined in the configuration");
+        };
+        info!("Using profile '{name}'");
+
+        apply_profile(
+            profile,
+            config_dir,
+            &mut database,
+            &mut attachments_dir,
+            &mut preferred_providers,
+            &mut get,
+            &mut output,
+        );
+    }
+
+    if on_insert.protect_case {
+        on_insert
+            .protected_words
+            .clone_from(&normalize.protected_words);
+    }
+    on_insert.name_format = normalize.name_format;
+
+    custom::register(
+        providers
+            .into_iter()
+            .filter_map(|(name, RawProviderConfig { id, url, canonical })| {
+                if let Err(err) = custom::validate_name(&name) {
+                    warn!("Invalid config: skipping provider '{name}': {err}");
+                    return None;
+                }
+                CustomProvider::new(name.clone(), &id, url, canonical)
+                    .inspect_err(|err| {
+                        warn!(
+                            "Invalid config: failed to compile 'providers.{name}.id' pattern: {err}"
+                        );
+                    })
+                    .ok()
+            })
+            .collect(),
+    );
+
+    register_isbn_sources(isbn.sources);
 
     let rules = LazyLock::new(move || {
         rules
@@ -160,11 +697,45 @@ pub fn load<P: AsRef<Path>>(
         create_alias,
     };
 
+    let auto_alias = auto_alias.template.and_then(|template| {
+        Template::compile(&template)
+            .inspect_err(|err| warn!("Invalid config: ignoring 'auto_alias.template': {err}"))
+            .ok()
+            .map(|template| AutoAlias {
+                template,
+                lowercase: auto_alias.lowercase,
+            })
+    });
+
+    let null_ttl = cache.null_ttl.and_then(|null_ttl| {
+        parse_age(&null_ttl)
+            .inspect_err(|err| warn!("Invalid config: ignoring 'cache.null_ttl': {err}"))
+            .ok()
+    });
+
+    let merge = merge
+        .into_iter()
+        .map(|(field, rule)| (field.to_lowercase(), rule))
+        .collect();
+
     Ok(Config {
         find,
         preferred_providers,
         alias_transform,
+        auto_alias,
         on_insert,
+        normalize,
+        styles,
+        get,
+        output,
+        source,
+        attach,
+        backup,
+        null_ttl,
+        http,
+        merge,
+        database,
+        attachments_dir,
     })
 }
 
@@ -0,0 +1,242 @@
+//! Read and modify individual configuration values.
+//!
+//! Values are modified by editing the text of the configuration file in place, so that comments
+//! and formatting elsewhere in the file are preserved.
+use std::{fs, io, path::Path};
+
+use anyhow::{Error, anyhow, bail};
+use toml::{Table, Value, from_str};
+
+use super::{DEFAULT_CONFIG, RawConfig};
+
+/// Recursively merge the values in `overrides` into `base`.
+fn merge(base: &mut Table, overrides: Table) {
+    for (key, value) in overrides {
+        match value {
+            Value::Table(table) if matches!(base.get(&key), Some(Value::Table(_))) => {
+                if let Some(Value::Table(base_table)) = base.get_mut(&key) {
+                    merge(base_table, table);
+                }
+            }
+            value => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Parse a value provided on the command line. Input which is not a valid TOML value is
+/// interpreted as a string.
+fn parse_value(input: &str) -> Value {
+    from_str::<Table>(&format!("value = {input}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(input.to_owned()))
+}
+
+/// The name of the table defined by a header line such as `[normalize]`, if any.
+fn header_name(line: &str) -> Option<String> {
+    let line = line.trim_start();
+    if line.starts_with("[[") {
+        return None;
+    }
+    let (name, _) = line.strip_prefix('[')?.split_once(']')?;
+    Some(name.split('.').map(str::trim).collect::<Vec<_>>().join("."))
+}
+
+/// Whether or not the line assigns a value to the key `name`.
+fn assigns_key(line: &str, name: &str) -> bool {
+    line.trim_start()
+        .strip_prefix(name)
+        .is_some_and(|rest| rest.trim_start().starts_with('='))
+}
+
+/// Set the value of the key `name` in the table `table` in the document, where an empty table
+/// name refers to the top level of the document.
+fn set_in_document(
+    contents: &str,
+    table: &str,
+    name: &str,
+    value: &Value,
+) -> Result<String, Error> {
+    let mut lines: Vec<&str> = contents.lines().collect();
+    let assignment = format!("{name} = {value}");
+
+    // the range of lines which belong to the table, excluding the header
+    let start = if table.is_empty() {
+        0
+    } else {
+        match lines
+            .iter()
+            .position(|line| header_name(line).is_some_and(|header| header == table))
+        {
+            Some(idx) => idx + 1,
+            None => {
+                if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                    lines.push("");
+                }
+                let header = format!("[{table}]");
+                lines.push(&header);
+                lines.push(&assignment);
+                return Ok(lines.join("\n") + "\n");
+            }
+        }
+    };
+    let end = lines[start..]
+        .iter()
+        .position(|line| header_name(line).is_some())
+        .map_or(lines.len(), |offset| start + offset);
+
+    match (start..end).find(|idx| assigns_key(lines[*idx], name)) {
+        Some(key_idx) => {
+            // the existing value may span multiple lines
+            let value_end = (key_idx..end)
+                .find(|last| from_str::<Table>(&lines[key_idx..=*last].join("\n")).is_ok())
+                .ok_or_else(|| anyhow!("Failed to parse the existing value of '{name}'"))?;
+            lines.splice(key_idx..=value_end, [assignment.as_str()]);
+        }
+        None => {
+            let insert_idx = (start..end)
+                .rev()
+                .find(|idx| !lines[*idx].trim().is_empty())
+                .map_or(start, |idx| idx + 1);
+            lines.insert(insert_idx, &assignment);
+        }
+    }
+
+    Ok(lines.join("\n") + "\n")
+}
+
+/// Get the value of a configuration key, which is a sequence of table names and a field name
+/// separated by `.`, such as `normalize.name_format`.
+///
+/// Values which are not set in the configuration file are taken from the default configuration.
+pub fn get_value<P: AsRef<Path>>(path: P, missing_ok: bool, key: &str) -> Result<Value, Error> {
+    let mut config: Table = from_str(DEFAULT_CONFIG)?;
+    match fs::read_to_string(&path) {
+        Ok(st) => merge(&mut config, from_str(&st)?),
+        Err(err) if missing_ok && err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => bail!("Failed to load configuration file: {err}"),
+    }
+
+    let mut value = Value::Table(config);
+    for segment in key.split('.') {
+        value = match value {
+            Value::Table(mut table) => table.remove(segment),
+            _ => None,
+        }
+        .ok_or_else(|| anyhow!("Unknown configuration key '{key}'"))?;
+    }
+    Ok(value)
+}
+
+/// Set the value of a configuration key, which is a sequence of table names and a field name
+/// separated by `.`, such as `normalize.name_format`.
+///
+/// If the configuration file does not exist, it is created from the default configuration. The
+/// file is only written if the resulting configuration is valid.
+pub fn set_value<P: AsRef<Path>>(path: P, key: &str, input: &str) -> Result<(), Error> {
+    let path = path.as_ref();
+    let contents = match fs::read_to_string(path) {
+        Ok(st) => st,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => DEFAULT_CONFIG.to_owned(),
+        Err(err) => bail!("Failed to load configuration file: {err}"),
+    };
+
+    let (table, name) = key.rsplit_once('.').unwrap_or(("", key));
+    let updated = set_in_document(&contents, table, name, &parse_value(input))?;
+
+    if let Err(err) = from_str::<RawConfig>(&updated) {
+        bail!("Invalid value for configuration key '{key}': {err}");
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, updated)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value("true"), Value::Boolean(true));
+        assert_eq!(parse_value("\"doi\""), Value::String("doi".into()));
+        assert_eq!(
+            parse_value("first-last"),
+            Value::String("first-last".into())
+        );
+        assert_eq!(
+            parse_value("[\"doi\", \"zbl\"]"),
+            Value::Array(vec!["doi".into(), "zbl".into()])
+        );
+    }
+
+    #[test]
+    fn test_set_in_document() {
+        let contents = "# providers
+preferred_providers = []
+
+[on_insert]
+# a comment
+normalize_whitespace = false
+set_eprint = [
+    \"doi\",
+]
+
+[normalize]
+";
+        let updated = set_in_document(
+            contents,
+            "on_insert",
+            "normalize_whitespace",
+            &Value::Boolean(true),
+        )
+        .unwrap();
+        assert_eq!(
+            updated,
+            contents.replace(
+                "normalize_whitespace = false",
+                "normalize_whitespace = true"
+            )
+        );
+
+        let updated = set_in_document(
+            contents,
+            "on_insert",
+            "set_eprint",
+            &Value::Array(vec!["zbl".into()]),
+        )
+        .unwrap();
+        assert_eq!(
+            updated,
+            contents.replace("set_eprint = [\n    \"doi\",\n]", "set_eprint = [\"zbl\"]")
+        );
+
+        let updated =
+            set_in_document(contents, "normalize", "name_format", &"first-last".into()).unwrap();
+        assert_eq!(updated, format!("{contents}name_format = \"first-last\"\n"));
+
+        let updated = set_in_document(
+            contents,
+            "",
+            "preferred_providers",
+            &Value::Array(vec!["mr".into()]),
+        )
+        .unwrap();
+        assert_eq!(
+            updated,
+            contents.replace("preferred_providers = []", "preferred_providers = [\"mr\"]")
+        );
+
+        let updated =
+            set_in_document(contents, "find", "ignore_hidden", &Value::Boolean(true)).unwrap();
+        assert_eq!(
+            updated,
+            format!("{contents}\n[find]\nignore_hidden = true\n")
+        );
+    }
+}
@@ -1,10 +1,14 @@
-use std::{fmt, path::Path, str::FromStr};
+use std::{collections::BTreeMap, fmt, path::Path, str::FromStr};
 
 use anyhow::Error;
+use regex::Regex;
 use regex_syntax::ast::{Ast, GroupKind, Span, parse::Parser};
 
-use super::RawConfig;
-use crate::{logger::error, provider::is_valid_provider};
+use super::{RawConfig, RawProviderConfig, parse_age};
+use crate::{
+    logger::error,
+    provider::{custom, is_valid_provider},
+};
 
 /// Validate the configuration file loaded at the provided path.
 ///
@@ -15,7 +19,24 @@ pub fn report_config_errors<P: AsRef<Path>>(path: P) -> Result<(), Error> {
     let raw_config = RawConfig::load(path, true)?;
 
     validate_find_default_template(&raw_config.find.default_template);
-    validate_alias_transform_rules(raw_config.alias_transform.rules);
+    validate_attach_filename(&raw_config.attach.filename);
+    validate_attach_layout(&raw_config.attach.layout);
+    if let Some(template) = &raw_config.auto_alias.template {
+        validate_auto_alias_template(template);
+    }
+    validate_providers(&raw_config.providers);
+    if let Some(null_ttl) = &raw_config.cache.null_ttl {
+        validate_null_ttl(null_ttl);
+    }
+    if let Some(proxy) = &raw_config.http.proxy {
+        validate_http_proxy(proxy);
+    }
+    if raw_config.isbn.sources.is_empty() {
+        error!("Config 'isbn.sources' is empty, so no ISBN can be resolved");
+    }
+    validate_alias_transform_rules(raw_config.alias_transform.rules, |provider| {
+        is_valid_provider(provider) || raw_config.providers.contains_key(provider)
+    });
 
     Ok(())
 }
@@ -166,10 +187,58 @@ fn validate_find_default_template(s: &str) {
     }
 }
 
+fn validate_attach_filename(s: &str) {
+    if let Err(e) = crate::format::Template::from_str(s) {
+        error!("Config 'attach.filename' has invalid syntax: {e}");
+    }
+}
+
+fn validate_attach_layout(s: &str) {
+    if let Err(e) = crate::format::Template::from_str(s) {
+        error!("Config 'attach.layout' has invalid syntax: {e}");
+    }
+}
+
+fn validate_auto_alias_template(s: &str) {
+    if let Err(e) = crate::format::Template::from_str(s) {
+        error!("Config 'auto_alias.template' has invalid syntax: {e}");
+    }
+}
+
+fn validate_null_ttl(s: &str) {
+    if let Err(e) = parse_age(s) {
+        error!("Config 'cache.null_ttl' is invalid: {e}");
+    }
+}
+
+fn validate_http_proxy(s: &str) {
+    if let Err(e) = ureq::Proxy::new(s) {
+        error!("Config 'http.proxy' is invalid: {e}");
+    }
+}
+
+/// Validate user-defined providers; namely the names are valid and the patterns compile.
+fn validate_providers(providers: &BTreeMap<String, RawProviderConfig>) {
+    for (name, RawProviderConfig { id, url, .. }) in providers {
+        if let Err(err) = custom::validate_name(name) {
+            error!("Config 'providers.{name}': {err}");
+        }
+        if let Err(err) = Regex::new(id) {
+            error!("Config 'providers.{name}.id': {err}");
+        }
+        if let Some(url) = url
+            && !url.contains("{sub_id}")
+        {
+            error!("Config 'providers.{name}.url': template does not contain '{{sub_id}}'");
+        }
+    }
+}
+
 /// Validate alias transform rules for correctness; namely regexes compile, providers are valid,
 /// and the regex rules satisfy the 'every alternative contains exactly one capture group' rule
 fn validate_alias_transform_rules<S: AsRef<str>, T: AsRef<str>>(
     rules: impl IntoIterator<Item = (S, T)>,
+    is_valid_provider: impl Fn(&str) -> bool,
 ) {
     for (re, provider) in rules {
         let provider = provider.as_ref();
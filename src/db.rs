@@ -11,21 +11,35 @@
 //!   present in the database at all
 //! - The [`Snapshot`] struct represents a global representation of database state.
 
+mod added;
+mod backup;
+mod collections;
+mod dry_run;
+mod dump;
 mod functions;
+mod lock;
 mod migrate;
+mod mirror;
+mod overlay;
+mod overrides;
+mod provenance;
+mod relations;
 mod schema;
 mod snapshot;
 pub mod state;
+mod sync;
+mod tags;
+mod transcript;
 pub mod tree;
 mod validate;
 
-use std::path::Path;
+use std::{collections::HashMap, ops::ControlFlow, path::Path};
 
-use chrono::{Local, TimeDelta};
+use chrono::{DateTime, Local, TimeDelta};
 use delegate::delegate;
 use functions::{AppFunction, register_application_function};
 use nucleo_picker::{Injector, Render};
-use rusqlite::{Connection, DropBehavior, OpenFlags, OptionalExtension};
+use rusqlite::{Connection, DropBehavior, OpenFlags, OptionalExtension, params_from_iter};
 
 use self::{
     state::{RecordIdState, RecordRow, RemoteIdState},
@@ -34,15 +48,21 @@ use self::{
 use crate::{
     Alias, RecordId, RemoteId,
     config::AliasTransform,
-    entry::RawEntryData,
+    entry::{MacroDefinition, RawEntryData},
     error::DatabaseError,
     logger::{debug, error, info, warn},
 };
+pub use lock::{DatabaseLock, lock_database};
+pub use overrides::FieldOverride;
+pub use provenance::{FieldSource, Provenance, RevisionProvenance};
+pub use relations::RelatedRecord;
 pub use snapshot::Snapshot;
+pub use sync::{SyncConflict, SyncReport};
+pub use transcript::{RunInfo, RunSummary, UndoRunResult};
 
 /// The current database version expected by the application.
 pub const fn user_version() -> i32 {
-    2
+    14
 }
 
 /// The unique application id used to determine if the opened database matches one used by this
@@ -52,6 +72,13 @@ pub const fn application_id() -> i32 {
     0x16611f2f
 }
 
+/// The number of prepared statements which are cached by each connection.
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+
+/// The maximum number of names which are looked up in a single query by
+/// [`RecordDatabase::lookup_entries`].
+const LOOKUP_CHUNK_SIZE: usize = 500;
+
 /// An alias for the internal row ID used by SQLite for the `Records` and the `NullRecords` table. This is
 /// the `key` column in the table schema defined in [`schema::records`], and the
 /// implicit `rowid` column in the table schema defined in [`schema::null_records`]
@@ -64,6 +91,50 @@ fn get_row_id<K: Identifier>(tx: &Tx, record_id: &K) -> Result<Option<RowId>, ru
         .optional()
 }
 
+/// Determine the [`RowId`] in the `Records` table corresponding to an [`Identifier`] which is an
+/// alias in the `AliasLinks` table, following the chain of links transitively.
+///
+/// Since the recursive query only produces each name once, this terminates even if the links
+/// contain a cycle, in which case there is no corresponding row.
+fn get_linked_row_id<K: Identifier>(
+    tx: &Tx,
+    record_id: &K,
+) -> Result<Option<RowId>, rusqlite::Error> {
+    tx.prepare_cached(
+        "WITH RECURSIVE chain(name) AS (
+            SELECT target FROM AliasLinks WHERE name = ?1
+            UNION
+            SELECT AliasLinks.target FROM AliasLinks JOIN chain ON AliasLinks.name = chain.name
+        )
+        SELECT record_key FROM Identifiers WHERE name IN (SELECT name FROM chain) LIMIT 1",
+    )?
+    .query_row([record_id.name()], |row| row.get("record_key"))
+    .optional()
+}
+
+/// Determine the target of an alias in the `AliasLinks` table.
+fn get_link_target<K: Identifier>(tx: &Tx, alias: &K) -> Result<Option<String>, rusqlite::Error> {
+    tx.prepare_cached("SELECT target FROM AliasLinks WHERE name = ?1")?
+        .query_row([alias.name()], |row| row.get("target"))
+        .optional()
+}
+
+/// Insert the metadata of an attachment into the `Attachments` table, replacing any existing
+/// metadata for the attachment with the same name.
+fn insert_attachment(tx: &Tx, attachment: &AttachmentRow) -> Result<(), rusqlite::Error> {
+    tx.prepare_cached(
+        "INSERT OR REPLACE INTO Attachments (record_id, name, size, sha256, added) VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?
+    .execute((
+        attachment.canonical.name(),
+        &attachment.name,
+        attachment.size,
+        &attachment.sha256,
+        attachment.added,
+    ))?;
+    Ok(())
+}
+
 /// Determine the [`RowId`] in the `NullRecords` table corresponding to a [`Identifier`].
 pub fn get_null_row_id(tx: &Tx, remote_id: &RemoteId) -> Result<Option<RowId>, rusqlite::Error> {
     tx.prepare_cached("SELECT rowid FROM NullRecords WHERE record_id = ?1")?
@@ -100,6 +171,32 @@ mod private {
 ///    documented in [`schema::identifiers`].
 /// 3. `NullRecords`. This is a cache table used to keep track of records which are known to
 ///    not exist. The table schema is documented in [`schema::null_records`].
+/// 4. `AliasLinks`. This is the table used to store aliases which refer to other aliases. The
+///    table schema is documented in [`schema::alias_links`].
+/// 5. `Attachments`. This is the table used to store the metadata of the files attached to each
+///    record. The table schema is documented in [`schema::attachments`].
+/// 6. `Provenance`. This is the table used to store the provider, the URL, and the time from which
+///    the data of a row in the `Records` table was retrieved. The table schema is documented in
+///    [`schema::provenance`].
+/// 7. `FieldSources`. This is the table used to store the source of each field which was supplied
+///    when data from another source was merged into a row in the `Records` table. The table
+///    schema is documented in [`schema::field_sources`].
+/// 8. `Macros`. This is the table used to store the abbreviations defined by `@string` entries in
+///    imported files. The table schema is documented in [`schema::macros`].
+/// 9. `Relations`. This is the table used to store the relations between records, such as the
+///    parents which were referenced by the `crossref` field of imported entries. The table
+///    schema is documented in [`schema::relations`].
+/// 10. `Tags`. This is the table used to store the tags of each record. The table schema is
+///     documented in [`schema::tags`].
+/// 11. `Collections`. This is the table used to store the names of the collections of records.
+///     The table schema is documented in [`schema::collections`].
+/// 12. `CollectionRecords`. This is the table used to store the records in each collection. The
+///     table schema is documented in [`schema::collection_records`].
+/// 13. `Overrides`. This is the table used to store the values of fields which replace the record
+///     data when writing to specific output files. The table schema is documented in
+///     [`schema::overrides`].
+/// 14. `Added`. This is the table used to store the time at which each record was first added to
+///     the database. The table schema is documented in [`schema::added`].
 ///
 /// For a [`RemoteId`], there are two variants depending on the value returned by [`get_remote_response`](crate::provider::get_remote_response):
 ///
@@ -120,6 +217,8 @@ mod private {
 /// |Alias       |        NO         |          NO           |          YES           |
 pub struct RecordDatabase {
     conn: Connection,
+    /// Whether or not transactions are rolled back instead of being committed.
+    dry_run: bool,
 }
 
 impl RecordDatabase {
@@ -151,6 +250,11 @@ impl RecordDatabase {
         #[cfg(feature = "in_memory_database")]
         let mut conn = Connection::open_in_memory_with_flags(flags)?;
 
+        // wait for transactions of other connections instead of failing immediately
+        conn.busy_timeout(lock::BUSY_TIMEOUT)?;
+        // bulk operations use more distinct statements than fit in the default cache
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+
         #[cfg(not(feature = "bundled-sqlite"))]
         {
             if !read_only {
@@ -167,7 +271,10 @@ impl RecordDatabase {
 
         Self::initialize(&mut conn, read_only)?;
 
-        Ok(Self { conn })
+        Ok(Self {
+            conn,
+            dry_run: false,
+        })
     }
 
     /// Enable an application function for use in subsequent SQL queries.
@@ -231,6 +338,19 @@ impl RecordDatabase {
                 tx.execute(schema::records(), ())?;
                 tx.execute(schema::identifiers(), ())?;
                 tx.execute(schema::null_records(), ())?;
+                tx.execute(schema::alias_links(), ())?;
+                tx.execute(schema::attachments(), ())?;
+                tx.execute(schema::runs(), ())?;
+                tx.execute(schema::run_changes(), ())?;
+                tx.execute(schema::provenance(), ())?;
+                tx.execute(schema::field_sources(), ())?;
+                tx.execute(schema::macros(), ())?;
+                tx.execute(schema::relations(), ())?;
+                tx.execute(schema::tags(), ())?;
+                tx.execute(schema::collections(), ())?;
+                tx.execute(schema::collection_records(), ())?;
+                tx.execute(schema::overrides(), ())?;
+                tx.execute(schema::added(), ())?;
 
                 debug!("Initializing indices");
                 tx.execute_batch(schema::create_indices())?;
@@ -290,8 +410,49 @@ impl RecordDatabase {
         self.conn.execute("VACUUM", ()).map(|_| ())
     }
 
+    /// Start a transaction, or a savepoint if a batch was started with
+    /// [`RecordDatabase::begin_batch`].
     pub fn transaction(&mut self) -> rusqlite::Result<Tx<'_>> {
-        self.conn.transaction().map(Into::into)
+        let tx = if self.conn.is_autocommit() {
+            TxInner::Transaction(self.conn.transaction()?)
+        } else {
+            TxInner::Savepoint(self.conn.savepoint()?)
+        };
+        Ok(Tx {
+            tx,
+            dry_run: self.dry_run,
+        })
+    }
+
+    /// Start a batch, in which every subsequent transaction is a savepoint of a single
+    /// transaction, until the batch is finished with [`RecordDatabase::commit_batch`].
+    ///
+    /// A transaction which is rolled back only rolls back its own changes. The changes of the
+    /// transactions which are committed are only written to the database file once the batch is
+    /// committed, which is much faster than committing each transaction separately.
+    pub fn begin_batch(&mut self) -> rusqlite::Result<()> {
+        debug!("Beginning batch transaction");
+        self.conn.execute_batch("BEGIN")
+    }
+
+    /// Check if a batch was started with [`RecordDatabase::begin_batch`] and not yet committed.
+    pub fn in_batch(&self) -> bool {
+        !self.conn.is_autocommit()
+    }
+
+    /// Commit the transaction of a batch started with [`RecordDatabase::begin_batch`].
+    ///
+    /// If the mirror is enabled, the files of the records which were changed in the batch are
+    /// updated after committing.
+    pub fn commit_batch(&mut self) -> rusqlite::Result<()> {
+        if self.conn.is_autocommit() {
+            return Ok(());
+        }
+        debug!("Committing batch transaction");
+        let changes = mirror::take_changes(&self.conn)?;
+        self.conn.execute_batch("COMMIT")?;
+        mirror::write_changes(changes);
+        Ok(())
     }
 
     /// Get the [`RecordIdState`] associated with a [`RecordId`].
@@ -304,6 +465,77 @@ impl RecordDatabase {
         RecordIdState::determine(self.transaction()?, record_id, alias_transform)
     }
 
+    /// Look up the records of many identifiers at once, keyed by the identifier.
+    ///
+    /// Only identifiers which are present in the database as provided, and whose record has
+    /// data, are included. In particular, identifiers which are only present after normalization
+    /// or after applying an alias transform, and identifiers whose record was deleted or voided,
+    /// must be looked up with [`RecordDatabase::state_from_record_id`] instead.
+    pub fn lookup_entries<'a, T: IntoIterator<Item = &'a str>>(
+        &mut self,
+        names: T,
+    ) -> Result<HashMap<String, RecordRow<RawEntryData>>, rusqlite::Error> {
+        let names: Vec<&str> = names.into_iter().collect();
+        let mut found = HashMap::with_capacity(names.len());
+
+        let tx = self.transaction()?;
+        for chunk in names.chunks(LOOKUP_CHUNK_SIZE) {
+            let placeholders = vec!["?"; chunk.len()].join(", ");
+            let mut stmt = tx.prepare(&format!(
+                "SELECT i.name, r.record_id, r.modified, r.data, r.variant
+                FROM Identifiers i JOIN Records r ON r.key = i.record_key
+                WHERE r.variant = 0 AND i.name IN ({placeholders})"
+            ))?;
+            let mut rows = stmt.query(params_from_iter(chunk))?;
+            while let Some(row) = rows.next()? {
+                found.insert(row.get("name")?, RecordRow::from_row_unchecked(row));
+            }
+        }
+        tx.commit()?;
+        debug!(
+            "Found {} of {} identifiers in a single lookup",
+            found.len(),
+            names.len()
+        );
+        Ok(found)
+    }
+
+    /// Get the remote identifiers which refer to each record with data, along with the time at
+    /// which the record was last modified, keyed by the canonical identifier.
+    ///
+    /// The records are looked up in a single transaction. Records which are not in the database
+    /// or which do not have data are omitted.
+    #[allow(clippy::type_complexity)]
+    pub fn referencing_remote_ids<'a, T: IntoIterator<Item = &'a RemoteId>>(
+        &mut self,
+        canonicals: T,
+    ) -> Result<HashMap<RemoteId, (DateTime<Local>, Vec<RemoteId>)>, rusqlite::Error> {
+        let mut found = HashMap::new();
+
+        let tx = self.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "SELECT r.modified, i.name FROM Records r JOIN Identifiers i ON i.record_key = r.key
+                WHERE r.record_id = ?1 AND r.variant = 0",
+            )?;
+            for canonical in canonicals {
+                let mut rows = stmt.query((canonical.name(),))?;
+                while let Some(row) = rows.next()? {
+                    let (_, referencing) = found
+                        .entry(canonical.clone())
+                        .or_insert_with(|| (row.get_unwrap("modified"), Vec::new()));
+                    if let Some(remote_id) =
+                        RemoteId::from_alias_or_remote_id_unchecked(row.get::<_, String>("name")?)
+                    {
+                        referencing.push(remote_id);
+                    }
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(found)
+    }
+
     /// Get the [`RemoteIdState`] associated with a [`RemoteId`].
     #[inline]
     pub fn state_from_remote_id(
@@ -328,10 +560,16 @@ impl RecordDatabase {
 
     /// Validate the internal consistency of the database.
     ///
-    /// If `fix` is true, then potentially destructive database changes will take place.
-    pub fn recover(&mut self, fix: bool) -> Result<Vec<DatabaseFault>, rusqlite::Error> {
+    /// If `fix` is true, then potentially destructive database changes will take place. Faults
+    /// which cannot be fixed unambiguously are only fixed if the `confirm` callback returns
+    /// `true` when called with a description of the change.
+    pub fn recover<F: FnMut(&str) -> bool>(
+        &mut self,
+        fix: bool,
+        mut confirm: F,
+    ) -> Result<Vec<DatabaseFault>, rusqlite::Error> {
         let validator = DatabaseValidator {
-            tx: self.conn.transaction()?.into(),
+            tx: self.transaction()?,
         };
         let mut faults = Vec::new();
 
@@ -344,11 +582,14 @@ impl RecordDatabase {
         validator.monotonic_timestamps(&mut faults)?;
         validator.void_correct_formatting(&mut faults)?;
         validator.check_active_row_counts(&mut faults)?;
+        validator.alias_links(&mut faults)?;
+        validator.ambiguous_aliases(&mut faults)?;
+        validator.orphaned_rows(&mut faults)?;
 
         let tx = validator.into_tx();
 
         if fix {
-            faults.retain(|fault| match Self::fix_fault_tx(&tx, fault) {
+            faults.retain(|fault| match Self::fix_fault_tx(&tx, fault, &mut confirm) {
                 Ok(b) => !b,
                 Err(err) => {
                     error!("While fixing the error {fault}, another error occurred:\n  {err}");
@@ -365,9 +606,22 @@ impl RecordDatabase {
     /// Attempt to fix a database fault inside a transaction.
     ///
     /// If the fault is fixed, return `true`, and return `false` otherwise.
-    fn fix_fault_tx(tx: &Tx, fault: &DatabaseFault) -> Result<bool, rusqlite::Error> {
+    fn fix_fault_tx<F: FnMut(&str) -> bool>(
+        tx: &Tx,
+        fault: &DatabaseFault,
+        confirm: &mut F,
+    ) -> Result<bool, rusqlite::Error> {
         match fault {
             DatabaseFault::RowHasInvalidCanonicalId(_, _) => Ok(false),
+            DatabaseFault::RowHasNonNormalizedCanonicalId(_, name, expected) => {
+                validate::normalize_record_id(tx, name, expected, confirm)
+            }
+            DatabaseFault::NonNormalizedIdentifier(name, expected) => {
+                validate::normalize_identifier(tx, name, expected, confirm)
+            }
+            DatabaseFault::InvalidIdentifier(name) => {
+                validate::remove_invalid_identifier(tx, name, confirm)
+            }
             DatabaseFault::NullIdentifiers(_) => {
                 let mut invalid_keys: Vec<String> = Vec::new();
                 {
@@ -390,13 +644,37 @@ impl RecordDatabase {
                 .execute(())?;
                 Ok(true)
             }
+            DatabaseFault::DanglingAliasLink(name, target) => {
+                warn!("Deleting alias '{name}' which refers to undefined alias '{target}'");
+                tx.prepare("DELETE FROM AliasLinks WHERE name = ?1")?
+                    .execute((name,))?;
+                Ok(true)
+            }
+            DatabaseFault::AmbiguousAlias(name) => {
+                warn!("Deleting alias link '{name}' which is also an identifier of a record");
+                tx.prepare("DELETE FROM AliasLinks WHERE name = ?1")?
+                    .execute((name,))?;
+                Ok(true)
+            }
+            DatabaseFault::OrphanedRows(table_name, record_id) => {
+                warn!(
+                    "Deleting rows of '{table_name}' for record id '{record_id}' which does not exist"
+                );
+                for (table, column) in validate::ORPHAN_COLUMNS {
+                    if table == table_name {
+                        tx.prepare(&format!("DELETE FROM {table} WHERE {column} = ?1"))?
+                            .execute((record_id,))?;
+                    }
+                }
+                Ok(true)
+            }
             _ => Ok(false),
         }
     }
 
     pub fn snapshot(&mut self) -> rusqlite::Result<Snapshot<'_>> {
         Ok(Snapshot {
-            tx: self.conn.transaction()?.into(),
+            tx: self.transaction()?,
         })
     }
 
@@ -428,13 +706,38 @@ impl RecordDatabase {
         R: Render<T>,
     {
         debug!("Sending all database records to an injector.");
+        self.map_active_records(|row| {
+            if let Some(data) = filter_map(row) {
+                injector.push(data);
+            }
+        })
+    }
+
+    /// Call the provided closure on every active row in the `Records` table which contains
+    /// entry data.
+    pub fn map_active_records<F>(&mut self, mut f: F) -> Result<(), rusqlite::Error>
+    where
+        F: FnMut(RecordRow<RawEntryData>),
+    {
+        self.try_map_active_records(|row| {
+            f(row);
+            ControlFlow::Continue(())
+        })
+    }
+
+    /// Call the provided closure on every active row in the `Records` table which contains
+    /// entry data, stopping as soon as the closure returns [`ControlFlow::Break`].
+    pub fn try_map_active_records<F>(&mut self, mut f: F) -> Result<(), rusqlite::Error>
+    where
+        F: FnMut(RecordRow<RawEntryData>) -> ControlFlow<()>,
+    {
         let mut retriever = self
             .conn
-            .prepare("SELECT record_id, modified, data, variant FROM Records WHERE key IN (SELECT record_key FROM Identifiers) AND variant = 0")?;
+            .prepare_cached("SELECT record_id, modified, data, variant FROM Records WHERE key IN (SELECT record_key FROM Identifiers) AND variant = 0")?;
 
         for res in retriever.query_map([], |row| Ok(RecordRow::from_row_unchecked(row)))? {
-            if let Some(data) = filter_map(res?) {
-                injector.push(data);
+            if f(res?).is_break() {
+                break;
             }
         }
 
@@ -442,32 +745,236 @@ impl RecordDatabase {
     }
 
     /// Rename an alias, returning the status of the renaming.
+    ///
+    /// Any aliases which refer to the renamed alias are updated to refer to the new name.
     pub fn rename_alias(
         &mut self,
         old: &Alias,
         new: &Alias,
     ) -> Result<RenameAliasResult, rusqlite::Error> {
-        let mut updater = self
-            .conn
-            .prepare("UPDATE Identifiers SET name = ?1 WHERE name = ?2")?;
-        match flatten_constraint_violation(updater.execute((new.name(), old.name())))? {
-            Constraint::Satisfied(_) => Ok(RenameAliasResult::Renamed),
-            Constraint::Violated => Ok(RenameAliasResult::TargetExists),
+        let tx = self.transaction()?;
+        if get_link_target(&tx, new)?.is_some() || get_row_id(&tx, new)?.is_some() {
+            return Ok(RenameAliasResult::TargetExists);
         }
+
+        let renamed = tx
+            .prepare("UPDATE Identifiers SET name = ?1 WHERE name = ?2")?
+            .execute((new.name(), old.name()))?
+            + tx.prepare("UPDATE AliasLinks SET name = ?1 WHERE name = ?2")?
+                .execute((new.name(), old.name()))?;
+
+        if renamed > 0 {
+            tx.prepare("UPDATE AliasLinks SET target = ?1 WHERE target = ?2")?
+                .execute((new.name(), old.name()))?;
+        }
+
+        tx.commit()?;
+        Ok(RenameAliasResult::Renamed)
     }
 
-    /// Delete an alias, returning the status of the deletion.
+    /// Delete an alias, returning the status of the deletion. An alias which is the target of an
+    /// alias link is not deleted.
     pub fn delete_alias(&mut self, alias: &Alias) -> Result<DeleteAliasResult, rusqlite::Error> {
-        let mut deleter = self
-            .conn
-            .prepare("DELETE FROM Identifiers WHERE name = ?1")?;
-        if deleter.execute((alias.name(),))? == 0 {
+        let tx = self.transaction()?;
+        let links = tx
+            .prepare("SELECT name FROM AliasLinks WHERE target = ?1 ORDER BY name")?
+            .query_map((alias.name(),), |row| row.get("name"))?
+            .collect::<Result<Vec<String>, _>>()?;
+        if !links.is_empty() {
+            return Ok(DeleteAliasResult::Linked(links));
+        }
+
+        let deleted = tx
+            .prepare("DELETE FROM Identifiers WHERE name = ?1")?
+            .execute((alias.name(),))?
+            + tx.prepare("DELETE FROM AliasLinks WHERE name = ?1")?
+                .execute((alias.name(),))?;
+        tx.commit()?;
+
+        if deleted == 0 {
             Ok(DeleteAliasResult::Missing)
         } else {
             Ok(DeleteAliasResult::Deleted)
         }
     }
 
+    /// Create an alias which refers to another alias, returning the status of the link.
+    pub fn link_alias(
+        &mut self,
+        alias: &Alias,
+        target: &Alias,
+    ) -> Result<LinkAliasResult, rusqlite::Error> {
+        let tx = self.transaction()?;
+        if get_link_target(&tx, alias)?.is_some() || get_row_id(&tx, alias)?.is_some() {
+            return Ok(LinkAliasResult::AliasExists);
+        }
+
+        if get_link_target(&tx, target)?.is_none() && get_row_id(&tx, target)?.is_none() {
+            return Ok(LinkAliasResult::TargetUndefined);
+        }
+
+        tx.prepare("INSERT INTO AliasLinks (name, target) VALUES (?1, ?2)")?
+            .execute((alias.name(), target.name()))?;
+        tx.commit()?;
+        Ok(LinkAliasResult::Linked)
+    }
+
+    /// Check if the alias is defined, either as an identifier of a record or as a link to another
+    /// alias.
+    pub fn contains_alias(&mut self, alias: &Alias) -> Result<bool, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let contains = get_row_id(&tx, alias)?.is_some() || get_link_target(&tx, alias)?.is_some();
+        tx.commit()?;
+        Ok(contains)
+    }
+
+    /// Follow the chain of links beginning at the provided alias, returning the sequence of
+    /// aliases which were visited, not including the original alias.
+    ///
+    /// The chain stops at the first alias which is not a link, or if a cycle is detected.
+    pub fn alias_chain(&mut self, alias: &Alias) -> Result<Vec<String>, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let mut chain: Vec<String> = Vec::new();
+        let mut current = alias.name().to_owned();
+        while let Some(target) = get_link_target(&tx, &RecordId::from(current.as_str()))? {
+            if target == alias.name() || chain.contains(&target) {
+                warn!("Alias '{alias}' is part of a cycle of links");
+                break;
+            }
+            chain.push(target.clone());
+            current = target;
+        }
+        tx.commit()?;
+        Ok(chain)
+    }
+
+    /// Get every alias along with the canonical identifier of the record that it refers to,
+    /// ordered by alias. Aliases which refer to other aliases are resolved by following the chain
+    /// of links, and are omitted if the chain does not end at a record.
+    pub fn aliases(&mut self) -> Result<Vec<(String, RemoteId)>, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let aliases = {
+            let mut stmt = tx.prepare(
+                "WITH RECURSIVE chain(name, target) AS (
+                    SELECT name, target FROM AliasLinks
+                    UNION
+                    SELECT chain.name, AliasLinks.target FROM chain
+                        JOIN AliasLinks ON AliasLinks.name = chain.target
+                )
+                SELECT Identifiers.name AS name, Records.record_id AS canonical FROM Identifiers
+                    JOIN Records ON Identifiers.record_key = Records.key
+                    WHERE instr(Identifiers.name, ':') = 0
+                UNION
+                SELECT chain.name AS name, Records.record_id AS canonical FROM chain
+                    JOIN Identifiers ON Identifiers.name = chain.target
+                    JOIN Records ON Identifiers.record_key = Records.key
+                ORDER BY name",
+            )?;
+            stmt.query_map([], |row| {
+                Ok((
+                    row.get("name")?,
+                    RemoteId::from_string_unchecked(row.get("canonical")?),
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?
+        };
+        tx.commit()?;
+        Ok(aliases)
+    }
+
+    /// Replace aliases which refer to other aliases with aliases which refer directly to the
+    /// corresponding record. If `aliases` is empty, every alias link is flattened.
+    ///
+    /// Returns the number of aliases which were flattened.
+    pub fn flatten_alias_links(&mut self, aliases: &[Alias]) -> Result<usize, rusqlite::Error> {
+        let tx = self.transaction()?;
+
+        let names: Vec<String> = if aliases.is_empty() {
+            let mut stmt = tx.prepare("SELECT name FROM AliasLinks")?;
+            stmt.query_map([], |row| row.get("name"))?
+                .collect::<Result<_, _>>()?
+        } else {
+            let mut names = Vec::with_capacity(aliases.len());
+            for alias in aliases {
+                if get_link_target(&tx, alias)?.is_some() {
+                    names.push(alias.name().to_owned());
+                } else {
+                    warn!("Alias '{alias}' does not refer to another alias");
+                }
+            }
+            names
+        };
+
+        let mut flattened = 0;
+        for name in names {
+            let record_id = RecordId::from(name.as_str());
+            match get_linked_row_id(&tx, &record_id)? {
+                Some(row_id) => {
+                    debug!("Flattening alias '{name}' to refer to row '{row_id}'");
+                    tx.prepare("DELETE FROM AliasLinks WHERE name = ?1")?
+                        .execute((&name,))?;
+                    tx.prepare("INSERT INTO Identifiers (name, record_key) VALUES (?1, ?2)")?
+                        .execute((&name, row_id))?;
+                    flattened += 1;
+                }
+                None => {
+                    error!("Cannot flatten alias '{name}' which does not refer to a record");
+                }
+            }
+        }
+
+        tx.commit()?;
+        Ok(flattened)
+    }
+
+    /// Get the number of rows in `NullRecords`.
+    pub fn num_null_records(&mut self) -> Result<usize, rusqlite::Error> {
+        self.conn
+            .query_row("SELECT count(*) FROM NullRecords", [], |row| row.get(0))
+    }
+
+    /// Get the number of rows in the `Records` table, which includes every revision of every
+    /// record, and the number of rows in the `Runs` table.
+    pub fn history_size(&mut self) -> Result<(usize, usize), rusqlite::Error> {
+        let num_revisions = self
+            .conn
+            .query_row("SELECT count(*) FROM Records", [], |row| row.get(0))?;
+        let num_runs = self
+            .conn
+            .query_row("SELECT count(*) FROM Runs", [], |row| row.get(0))?;
+        Ok((num_revisions, num_runs))
+    }
+
+    /// Get the time at which the record was first added to the database, if known.
+    pub fn added(
+        &mut self,
+        canonical: &RemoteId,
+    ) -> Result<Option<DateTime<Local>>, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let added = added::load_added(&tx, canonical.name())?;
+        tx.commit()?;
+        Ok(added)
+    }
+
+    /// Get the time at which every record was first added to the database, keyed by the canonical
+    /// identifier.
+    pub fn added_times(&mut self) -> Result<HashMap<RemoteId, DateTime<Local>>, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let added = added::load_all_added(&tx)?;
+        tx.commit()?;
+        Ok(added
+            .into_iter()
+            .map(|(canonical, added)| (RemoteId::from_string_unchecked(canonical), added))
+            .collect())
+    }
+
+    /// Replace `from` with `to` in the added times, after the record `from` was replaced by `to`.
+    pub fn migrate_added(&mut self, from: &RemoteId, to: &RemoteId) -> Result<(), rusqlite::Error> {
+        let tx = self.transaction()?;
+        added::migrate_added(&tx, from.name(), to.name())?;
+        tx.commit()
+    }
+
     /// Delete all rows from `NullRecords`.
     pub fn evict_cache(&mut self) -> Result<(), rusqlite::Error> {
         let num_deleted = self.conn.prepare("DELETE FROM NullRecords")?.execute(())?;
@@ -485,6 +992,411 @@ impl RecordDatabase {
         info!("Removed {num_deleted} cached null records.");
         Ok(())
     }
+
+    /// Insert the metadata of an attachment into the `Attachments` table, replacing any existing
+    /// metadata for the attachment with the same name.
+    pub fn insert_attachment(&mut self, attachment: &AttachmentRow) -> Result<(), rusqlite::Error> {
+        let tx = self.transaction()?;
+        insert_attachment(&tx, attachment)?;
+        tx.commit()
+    }
+
+    /// Delete the metadata of the attachment with the provided name. Returns `true` if there was
+    /// metadata for the attachment.
+    pub fn delete_attachment(
+        &mut self,
+        canonical: &RemoteId,
+        name: &str,
+    ) -> Result<bool, rusqlite::Error> {
+        let num_deleted = self
+            .conn
+            .prepare_cached("DELETE FROM Attachments WHERE record_id = ?1 AND name = ?2")?
+            .execute((canonical.name(), name))?;
+        Ok(num_deleted > 0)
+    }
+
+    /// Get the metadata of every attachment in the `Attachments` table, ordered by identifier and
+    /// name.
+    pub fn attachments(&mut self) -> Result<Vec<AttachmentRow>, rusqlite::Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT * FROM Attachments ORDER BY record_id, name")?;
+        stmt.query_map([], |row| {
+            Ok(AttachmentRow {
+                canonical: RemoteId::from_string_unchecked(row.get("record_id")?),
+                name: row.get("name")?,
+                size: row.get("size")?,
+                sha256: row.get("sha256")?,
+                added: row.get("added")?,
+            })
+        })?
+        .collect()
+    }
+
+    /// Insert the provided abbreviations into the `Macros` table, replacing any existing
+    /// abbreviations with the same name.
+    pub fn insert_macros(&mut self, macros: &[MacroDefinition]) -> Result<(), rusqlite::Error> {
+        let tx = self.transaction()?;
+        {
+            let mut stmt =
+                tx.prepare_cached("INSERT OR REPLACE INTO Macros (name, value) VALUES (?1, ?2)")?;
+            for def in macros {
+                debug!("Setting abbreviation '{}' to '{}'", def.name, def.value);
+                stmt.execute((&def.name, &def.value))?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// Get every abbreviation in the `Macros` table, ordered by name.
+    pub fn macros(&mut self) -> Result<Vec<MacroDefinition>, rusqlite::Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, value FROM Macros ORDER BY name")?;
+        stmt.query_map([], |row| {
+            Ok(MacroDefinition {
+                name: row.get("name")?,
+                value: row.get("value")?,
+            })
+        })?
+        .collect()
+    }
+
+    /// Record a relation of the provided kind from `canonical` to `target`, if it does not
+    /// exist.
+    pub fn insert_relation(
+        &mut self,
+        canonical: &RemoteId,
+        target: &RemoteId,
+        kind: &str,
+    ) -> Result<(), rusqlite::Error> {
+        let tx = self.transaction()?;
+        relations::insert_relation(&tx, canonical.name(), target.name(), kind)?;
+        tx.commit()
+    }
+
+    /// Delete the relation of the provided kind from `canonical` to `target`. Returns `true` if
+    /// the relation existed.
+    pub fn delete_relation(
+        &mut self,
+        canonical: &RemoteId,
+        target: &RemoteId,
+        kind: &str,
+    ) -> Result<bool, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let deleted = relations::delete_relation(&tx, canonical.name(), target.name(), kind)?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// Get the canonical identifiers of the parents of the record, ordered by identifier.
+    pub fn parents(&mut self, canonical: &RemoteId) -> Result<Vec<RemoteId>, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let parents = relations::load_parents(&tx, canonical.name())?;
+        tx.commit()?;
+        Ok(parents
+            .into_iter()
+            .map(RemoteId::from_string_unchecked)
+            .collect())
+    }
+
+    /// Replace `from` with `to` in every relation, after the record `from` was replaced by `to`.
+    pub fn migrate_relations(
+        &mut self,
+        from: &RemoteId,
+        to: &RemoteId,
+    ) -> Result<(), rusqlite::Error> {
+        let tx = self.transaction()?;
+        relations::migrate_relations(&tx, from.name(), to.name())?;
+        tx.commit()
+    }
+
+    /// Get the relations from and to the record.
+    pub fn relations(
+        &mut self,
+        canonical: &RemoteId,
+    ) -> Result<Vec<RelatedRecord>, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let relations = relations::load_relations(&tx, canonical.name())?;
+        tx.commit()?;
+        Ok(relations)
+    }
+
+    /// Tag the record, returning `true` if the record did not already have the tag.
+    pub fn insert_tag(&mut self, canonical: &RemoteId, tag: &str) -> Result<bool, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let inserted = tags::insert_tag(&tx, canonical.name(), tag)?;
+        tx.commit()?;
+        Ok(inserted)
+    }
+
+    /// Remove the tag from the record, returning `true` if the record had the tag.
+    pub fn delete_tag(&mut self, canonical: &RemoteId, tag: &str) -> Result<bool, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let deleted = tags::delete_tag(&tx, canonical.name(), tag)?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// Replace `from` with `to` in every tag, after the record `from` was replaced by `to`.
+    pub fn migrate_tags(&mut self, from: &RemoteId, to: &RemoteId) -> Result<(), rusqlite::Error> {
+        let tx = self.transaction()?;
+        tags::migrate_tags(&tx, from.name(), to.name())?;
+        tx.commit()
+    }
+
+    /// Get the tags of the record, in alphabetical order.
+    pub fn tags(&mut self, canonical: &RemoteId) -> Result<Vec<String>, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let tags = tags::load_tags(&tx, canonical.name())?;
+        tx.commit()?;
+        Ok(tags)
+    }
+
+    /// Get every tag along with the number of records with the tag, in alphabetical order.
+    pub fn tag_counts(&mut self) -> Result<Vec<(String, usize)>, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let counts = tags::load_tag_counts(&tx)?;
+        tx.commit()?;
+        Ok(counts)
+    }
+
+    /// Get the canonical identifiers of the records with the tag, ordered by identifier.
+    pub fn tagged(&mut self, tag: &str) -> Result<Vec<RemoteId>, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let tagged = tags::load_tagged(&tx, tag)?;
+        tx.commit()?;
+        Ok(tagged
+            .into_iter()
+            .map(RemoteId::from_string_unchecked)
+            .collect())
+    }
+
+    /// Create an empty collection, returning `true` if the collection did not exist.
+    pub fn create_collection(&mut self, name: &str) -> Result<bool, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let created = collections::insert_collection(&tx, name)?;
+        tx.commit()?;
+        Ok(created)
+    }
+
+    /// Delete a collection, returning `true` if the collection existed.
+    pub fn delete_collection(&mut self, name: &str) -> Result<bool, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let deleted = collections::delete_collection(&tx, name)?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// Check if the collection exists.
+    pub fn collection_exists(&mut self, name: &str) -> Result<bool, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let exists = collections::collection_exists(&tx, name)?;
+        tx.commit()?;
+        Ok(exists)
+    }
+
+    /// Add the record to the collection, returning `true` if the collection did not already
+    /// contain the record. The collection must exist.
+    pub fn insert_collection_record(
+        &mut self,
+        collection: &str,
+        canonical: &RemoteId,
+    ) -> Result<bool, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let inserted = collections::insert_member(&tx, collection, canonical.name())?;
+        tx.commit()?;
+        Ok(inserted)
+    }
+
+    /// Remove the record from the collection, returning `true` if the collection contained the
+    /// record.
+    pub fn delete_collection_record(
+        &mut self,
+        collection: &str,
+        canonical: &RemoteId,
+    ) -> Result<bool, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let deleted = collections::delete_member(&tx, collection, canonical.name())?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// Replace `from` with `to` in every collection, after the record `from` was replaced by
+    /// `to`.
+    pub fn migrate_collections(
+        &mut self,
+        from: &RemoteId,
+        to: &RemoteId,
+    ) -> Result<(), rusqlite::Error> {
+        let tx = self.transaction()?;
+        collections::migrate_collections(&tx, from.name(), to.name())?;
+        tx.commit()
+    }
+
+    /// Get every collection along with the number of records in the collection, in alphabetical
+    /// order.
+    pub fn collection_sizes(&mut self) -> Result<Vec<(String, usize)>, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let sizes = collections::load_collection_sizes(&tx)?;
+        tx.commit()?;
+        Ok(sizes)
+    }
+
+    /// Get the canonical identifiers of the records in the collection, ordered by identifier.
+    pub fn collection_records(
+        &mut self,
+        collection: &str,
+    ) -> Result<Vec<RemoteId>, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let members = collections::load_members(&tx, collection)?;
+        tx.commit()?;
+        Ok(members
+            .into_iter()
+            .map(RemoteId::from_string_unchecked)
+            .collect())
+    }
+
+    /// Set the value of an override, replacing any existing value.
+    pub fn insert_override(
+        &mut self,
+        field_override: &FieldOverride,
+    ) -> Result<(), rusqlite::Error> {
+        let tx = self.transaction()?;
+        overrides::insert_override(&tx, field_override)?;
+        tx.commit()
+    }
+
+    /// Delete the override of the field of the record in the scope. Returns `true` if the
+    /// override existed.
+    pub fn delete_override(
+        &mut self,
+        canonical: &RemoteId,
+        scope: &str,
+        field: &str,
+    ) -> Result<bool, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let deleted = overrides::delete_override(&tx, canonical.name(), scope, field)?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// Replace `from` with `to` in every override, after the record `from` was replaced by `to`.
+    pub fn migrate_overrides(
+        &mut self,
+        from: &RemoteId,
+        to: &RemoteId,
+    ) -> Result<(), rusqlite::Error> {
+        let tx = self.transaction()?;
+        overrides::migrate_overrides(&tx, from.name(), to.name())?;
+        tx.commit()
+    }
+
+    /// Get the overrides, ordered by record, scope, and field. If a record is provided, only the
+    /// overrides of the record are returned.
+    pub fn overrides(
+        &mut self,
+        canonical: Option<&RemoteId>,
+    ) -> Result<Vec<FieldOverride>, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let overrides = overrides::load_overrides(&tx, canonical.map(Identifier::name))?;
+        tx.commit()?;
+        Ok(overrides)
+    }
+
+    /// Associate the attachment metadata of `from` with `to`, after the attachment directory was
+    /// migrated. Metadata for attachments which were not migrated since an attachment with the
+    /// same name already exists is left in place.
+    pub fn migrate_attachments(
+        &mut self,
+        from: &RemoteId,
+        to: &RemoteId,
+    ) -> Result<(), rusqlite::Error> {
+        let num_migrated = self
+            .conn
+            .prepare("UPDATE OR IGNORE Attachments SET record_id = ?2 WHERE record_id = ?1")?
+            .execute((from.name(), to.name()))?;
+        debug!("Migrated metadata of {num_migrated} attachments from '{from}' to '{to}'");
+        Ok(())
+    }
+
+    /// Change the canonical identifier of the record with canonical identifier `from` to `to`,
+    /// returning the status of the change.
+    ///
+    /// Every revision of the record is changed, so that the history of the record is preserved.
+    /// The identifier `from` is kept as a reference to the record, so that it can still be used
+    /// to look up the record. The relations, tags, collections, overrides, attachment metadata,
+    /// and the time at which the record was added are migrated in the same transaction, but the
+    /// attachment directory must be moved separately.
+    pub fn remap_canonical(
+        &mut self,
+        from: &RemoteId,
+        to: &RemoteId,
+    ) -> Result<RemapCanonicalResult, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let Some((row_id, canonical)) = tx
+            .prepare(
+                "SELECT r.key, r.record_id FROM Identifiers i INNER JOIN Records r ON i.record_key = r.key WHERE i.name = ?1",
+            )?
+            .query_row((from.name(),), |row| {
+                Ok((row.get::<_, RowId>("key")?, row.get::<_, String>("record_id")?))
+            })
+            .optional()?
+        else {
+            return Ok(RemapCanonicalResult::Missing);
+        };
+        if canonical != from.name() {
+            return Ok(RemapCanonicalResult::NotCanonical(
+                RemoteId::from_string_unchecked(canonical),
+            ));
+        }
+
+        match get_row_id(&tx, to)? {
+            Some(other_row_id) if other_row_id != row_id => {
+                return Ok(RemapCanonicalResult::TargetExists);
+            }
+            Some(_) => {}
+            None => {
+                tx.prepare("INSERT INTO Identifiers (name, record_key) VALUES (?1, ?2)")?
+                    .execute((to.name(), row_id))?;
+            }
+        }
+
+        let num_revisions = tx
+            .prepare("UPDATE Records SET record_id = ?2 WHERE record_id = ?1")?
+            .execute((from.name(), to.name()))?;
+        tx.prepare("DELETE FROM NullRecords WHERE record_id = ?1")?
+            .execute((to.name(),))?;
+        debug!("Changed canonical identifier of {num_revisions} revisions from '{from}' to '{to}'");
+
+        let num_attachments = tx
+            .prepare("UPDATE OR IGNORE Attachments SET record_id = ?2 WHERE record_id = ?1")?
+            .execute((from.name(), to.name()))?;
+        debug!("Migrated metadata of {num_attachments} attachments from '{from}' to '{to}'");
+        relations::migrate_relations(&tx, from.name(), to.name())?;
+        tags::migrate_tags(&tx, from.name(), to.name())?;
+        collections::migrate_collections(&tx, from.name(), to.name())?;
+        overrides::migrate_overrides(&tx, from.name(), to.name())?;
+        added::migrate_added(&tx, from.name(), to.name())?;
+
+        tx.commit()?;
+        Ok(RemapCanonicalResult::Remapped)
+    }
+}
+
+/// The metadata of an attachment, as stored in the `Attachments` table.
+#[derive(Debug, Clone)]
+pub struct AttachmentRow {
+    /// The canonical identifier which determines the attachment directory.
+    pub canonical: RemoteId,
+    /// The path of the attachment, relative to the attachment directory.
+    pub name: String,
+    /// The size of the attachment in bytes.
+    pub size: u64,
+    /// The lowercase hexadecimal SHA-256 hash of the attachment.
+    pub sha256: String,
+    /// The time at which the attachment was added.
+    pub added: DateTime<Local>,
 }
 
 impl Drop for RecordDatabase {
@@ -523,35 +1435,124 @@ pub enum RenameAliasResult {
     TargetExists,
 }
 
-/// The result of renaming an alias.
+/// The result of linking an alias to another alias.
+#[must_use]
+pub enum LinkAliasResult {
+    /// The alias was successfully linked.
+    Linked,
+    /// The alias already exists.
+    AliasExists,
+    /// The target alias does not exist.
+    TargetUndefined,
+}
+
+/// The result of deleting an alias.
 #[must_use]
 pub enum DeleteAliasResult {
-    /// The alias was successfully renamed.
+    /// The alias was successfully deleted.
     Deleted,
     /// The alias did not exist.
     Missing,
+    /// The alias was not deleted, since it is the target of the provided alias links.
+    Linked(Vec<String>),
+}
+
+/// The result of changing the canonical identifier of a record.
+#[must_use]
+pub enum RemapCanonicalResult {
+    /// The canonical identifier was successfully changed.
+    Remapped,
+    /// The identifier does not refer to a record.
+    Missing,
+    /// The identifier refers to a record with a different canonical identifier.
+    NotCanonical(RemoteId),
+    /// The new canonical identifier already refers to a different record.
+    TargetExists,
 }
 
 /// A wrapper around a [`rusqlite::Transaction`] which provides additional logging and exposes
 /// fewer public methods.
+///
+/// Inside a batch started with [`RecordDatabase::begin_batch`], the transaction is a
+/// [`rusqlite::Savepoint`] of the transaction of the batch instead.
 #[derive(Debug)]
 pub struct Tx<'conn> {
-    tx: rusqlite::Transaction<'conn>,
+    tx: TxInner<'conn>,
+    /// Whether or not the transaction is rolled back instead of being committed.
+    dry_run: bool,
+}
+
+#[derive(Debug)]
+enum TxInner<'conn> {
+    Transaction(rusqlite::Transaction<'conn>),
+    Savepoint(rusqlite::Savepoint<'conn>),
 }
 
 impl<'conn> From<rusqlite::Transaction<'conn>> for Tx<'conn> {
     fn from(tx: rusqlite::Transaction<'conn>) -> Self {
-        Self { tx }
+        Self {
+            tx: TxInner::Transaction(tx),
+            dry_run: false,
+        }
+    }
+}
+
+impl<'conn> From<rusqlite::Savepoint<'conn>> for Tx<'conn> {
+    fn from(sp: rusqlite::Savepoint<'conn>) -> Self {
+        Self {
+            tx: TxInner::Savepoint(sp),
+            dry_run: false,
+        }
     }
 }
 
 impl Tx<'_> {
+    /// The connection on which the transaction is running.
+    fn conn(&self) -> &Connection {
+        match &self.tx {
+            TxInner::Transaction(tx) => tx,
+            TxInner::Savepoint(sp) => sp,
+        }
+    }
+
+    fn drop_behavior(&self) -> DropBehavior {
+        match &self.tx {
+            TxInner::Transaction(tx) => tx.drop_behavior(),
+            TxInner::Savepoint(sp) => sp.drop_behavior(),
+        }
+    }
+
+    fn set_drop_behavior(&mut self, drop_behavior: DropBehavior) {
+        match &mut self.tx {
+            TxInner::Transaction(tx) => tx.set_drop_behavior(drop_behavior),
+            TxInner::Savepoint(sp) => sp.set_drop_behavior(drop_behavior),
+        }
+    }
+
     /// Commit the transaction.
     ///
     /// This method sets the transaction's drop behaviour to [`rusqlite::DropBehavior::Commit`] and then drops it.
+    ///
+    /// If the mirror is enabled, the files of the records which were changed in the transaction
+    /// are updated after committing, or after committing the batch if the transaction is a
+    /// savepoint of a batch. If a dry run was enabled with
+    /// [`RecordDatabase::enable_dry_run`], the changes are reported and the transaction is rolled
+    /// back instead.
     pub fn commit(mut self) -> rusqlite::Result<()> {
-        self.tx.set_drop_behavior(DropBehavior::Commit);
+        if self.dry_run {
+            dry_run::report(&self)?;
+            return self.rollback();
+        }
+
+        // the changes of a savepoint may still be rolled back with the batch, so they are
+        // written to the mirror by `RecordDatabase::commit_batch`
+        let changes = match self.tx {
+            TxInner::Transaction(_) => mirror::take_changes(self.conn())?,
+            TxInner::Savepoint(_) => Vec::new(),
+        };
+        self.set_drop_behavior(DropBehavior::Commit);
         drop(self);
+        mirror::write_changes(changes);
         Ok(())
     }
 
@@ -559,14 +1560,14 @@ impl Tx<'_> {
     ///
     /// This method sets the transaction's drop behaviour to [`rusqlite::DropBehavior::Rollback`] and then drops it.
     pub fn rollback(mut self) -> rusqlite::Result<()> {
-        self.tx.set_drop_behavior(DropBehavior::Rollback);
+        self.set_drop_behavior(DropBehavior::Rollback);
         drop(self);
         Ok(())
     }
 
     // only expose internal methods privately
     delegate! {
-        to self.tx {
+        to self.conn() {
             fn pragma_query<F>(&self, schema_name: Option<&str>, pragma_name: &str, f: F) -> rusqlite::Result<()>
             where
                 F: FnMut(&rusqlite::Row<'_>) -> rusqlite::Result<()>;
@@ -581,7 +1582,7 @@ impl Tx<'_> {
 impl Drop for Tx<'_> {
     #[inline]
     fn drop(&mut self) {
-        match self.tx.drop_behavior() {
+        match self.drop_behavior() {
             DropBehavior::Rollback => debug!("Rolling back transaction"),
             DropBehavior::Commit => debug!("Committing transaction"),
             DropBehavior::Ignore => debug!("Ignoring transaction"),
@@ -0,0 +1,75 @@
+//! # Times at which records were added
+//!
+//! The time at which a record was first added to the database is stored in the `Added` table
+//! along with the canonical identifier of the record. In contrast to the `modified` column of the
+//! `Records` table, which is set for each revision, the time is preserved when the data of the
+//! record changes, for instance by `autobib edit`, `autobib update`, or `autobib hist undo`.
+use std::collections::HashMap;
+
+use chrono::{DateTime, Local};
+use rusqlite::OptionalExtension;
+
+use super::Tx;
+use crate::logger::debug;
+
+/// Record that the record was added at the provided time, replacing any existing time.
+pub(super) fn set_added(
+    tx: &Tx,
+    canonical: &str,
+    added: &DateTime<Local>,
+) -> Result<(), rusqlite::Error> {
+    debug!("Setting the time at which '{canonical}' was added to '{added}'");
+    tx.prepare_cached("INSERT OR REPLACE INTO Added (record_id, added) VALUES (?1, ?2)")?
+        .execute((canonical, added))?;
+    Ok(())
+}
+
+/// Record that the record was added at the provided time, unless the record is already known to
+/// have been added earlier.
+pub(super) fn merge_added(
+    tx: &Tx,
+    canonical: &str,
+    added: &DateTime<Local>,
+) -> Result<(), rusqlite::Error> {
+    if load_added(tx, canonical)?.is_none_or(|existing| *added < existing) {
+        set_added(tx, canonical, added)?;
+    }
+    Ok(())
+}
+
+/// Forget the time at which the record was added.
+pub(super) fn delete_added(tx: &Tx, canonical: &str) -> Result<(), rusqlite::Error> {
+    debug!("Deleting the time at which '{canonical}' was added");
+    tx.prepare_cached("DELETE FROM Added WHERE record_id = ?1")?
+        .execute((canonical,))?;
+    Ok(())
+}
+
+/// Replace `from` with `to` in the added times. If `to` already has an added time, the earlier of
+/// the two times is kept.
+pub(super) fn migrate_added(tx: &Tx, from: &str, to: &str) -> Result<(), rusqlite::Error> {
+    if let Some(added) = load_added(tx, from)? {
+        merge_added(tx, to, &added)?;
+        tx.prepare("DELETE FROM Added WHERE record_id = ?1")?
+            .execute((from,))?;
+    }
+    debug!("Migrated the time at which '{from}' was added to '{to}'");
+    Ok(())
+}
+
+/// The time at which the record was added, if known.
+pub(super) fn load_added(
+    tx: &Tx,
+    canonical: &str,
+) -> Result<Option<DateTime<Local>>, rusqlite::Error> {
+    tx.prepare_cached("SELECT added FROM Added WHERE record_id = ?1")?
+        .query_row((canonical,), |row| row.get("added"))
+        .optional()
+}
+
+/// The time at which every record was added, keyed by the canonical identifier.
+pub(super) fn load_all_added(tx: &Tx) -> Result<HashMap<String, DateTime<Local>>, rusqlite::Error> {
+    let mut stmt = tx.prepare_cached("SELECT record_id, added FROM Added")?;
+    stmt.query_map([], |row| Ok((row.get("record_id")?, row.get("added")?)))?
+        .collect()
+}
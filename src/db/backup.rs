@@ -0,0 +1,93 @@
+//! # Database backups
+//!
+//! A backup is a copy of the database file, written with `VACUUM INTO` so that the copy is
+//! consistent even though the database is open. A backup is restored by attaching it with
+//! `ATTACH DATABASE` using the schema name `backup`, and replacing the rows of every table with
+//! the rows of the corresponding table of the backup in a single transaction.
+use std::path::Path;
+
+use super::{RecordDatabase, application_id, transcript::clear_transcript, user_version};
+use crate::{error::DatabaseError, logger::info};
+
+/// The tables of the database, in an order in which the rows can be inserted without violating
+/// the foreign key constraints.
+const TABLES: [&str; 16] = [
+    "Records",
+    "Identifiers",
+    "NullRecords",
+    "AliasLinks",
+    "Attachments",
+    "Runs",
+    "RunChanges",
+    "Provenance",
+    "FieldSources",
+    "Macros",
+    "Relations",
+    "Tags",
+    "Collections",
+    "CollectionRecords",
+    "Overrides",
+    "Added",
+];
+
+impl RecordDatabase {
+    /// Write a copy of the database to the provided path, which must not exist.
+    pub fn write_backup<P: AsRef<Path>>(&self, path: P) -> Result<(), rusqlite::Error> {
+        let path = path.as_ref();
+        info!("Writing backup to '{}'", path.display());
+        self.conn
+            .execute("VACUUM main INTO ?1", [path.to_string_lossy().as_ref()])?;
+        Ok(())
+    }
+
+    /// Replace the contents of the database with the contents of the backup at the provided
+    /// path. Either the entire backup is restored, or the database is not changed.
+    ///
+    /// The backup must have the same version as the current binary.
+    pub fn restore_backup<P: AsRef<Path>>(&mut self, path: P) -> Result<(), DatabaseError> {
+        let path = path.as_ref();
+        if !path.is_file() {
+            return Err(DatabaseError::MissingBackup(path.display().to_string()));
+        }
+
+        info!("Restoring backup from '{}'", path.display());
+        self.conn.execute(
+            "ATTACH DATABASE ?1 AS backup",
+            [path.to_string_lossy().as_ref()],
+        )?;
+        let res = self.restore_attached_backup(path);
+        self.conn.execute("DETACH DATABASE backup", [])?;
+        res
+    }
+
+    /// Replace the contents of the database with the contents of the attached backup.
+    fn restore_attached_backup(&mut self, path: &Path) -> Result<(), DatabaseError> {
+        let db_user_version: i32 =
+            self.conn
+                .query_row("PRAGMA backup.user_version", [], |row| row.get(0))?;
+        let db_application_id: i32 =
+            self.conn
+                .query_row("PRAGMA backup.application_id", [], |row| row.get(0))?;
+        if db_user_version != user_version() || db_application_id != application_id() {
+            return Err(DatabaseError::IncompatibleBackup(
+                path.display().to_string(),
+            ));
+        }
+
+        let tx = self.transaction()?;
+        for table in TABLES.iter().rev() {
+            tx.prepare(&format!("DELETE FROM main.{table}"))?
+                .execute([])?;
+        }
+        for table in TABLES {
+            tx.prepare(&format!(
+                "INSERT INTO main.{table} SELECT * FROM backup.{table}"
+            ))?
+            .execute([])?;
+        }
+        // the restored rows are not changes made by the current run
+        clear_transcript(&tx)?;
+        tx.commit()?;
+        Ok(())
+    }
+}
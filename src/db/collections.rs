@@ -0,0 +1,105 @@
+//! # Collections of records
+//!
+//! A collection is a named set of records, such as the references of a thesis, which can be
+//! written as a unit with `autobib get --collection`. The names of the collections are stored in
+//! the `Collections` table, and the records in each collection are stored in the
+//! `CollectionRecords` table by canonical identifier. When a record is replaced, the canonical
+//! identifier is updated in every collection which contains the record.
+use super::Tx;
+use crate::logger::debug;
+
+/// Create an empty collection, returning `true` if the collection did not exist.
+pub(super) fn insert_collection(tx: &Tx, name: &str) -> Result<bool, rusqlite::Error> {
+    debug!("Creating collection '{name}'");
+    let num_inserted = tx
+        .prepare_cached("INSERT OR IGNORE INTO Collections (name) VALUES (?1)")?
+        .execute((name,))?;
+    Ok(num_inserted > 0)
+}
+
+/// Delete a collection, returning `true` if the collection existed. The records themselves are
+/// not deleted.
+pub(super) fn delete_collection(tx: &Tx, name: &str) -> Result<bool, rusqlite::Error> {
+    debug!("Deleting collection '{name}'");
+    let num_deleted = tx
+        .prepare_cached("DELETE FROM Collections WHERE name = ?1")?
+        .execute((name,))?;
+    Ok(num_deleted > 0)
+}
+
+/// Check if the collection exists.
+pub(super) fn collection_exists(tx: &Tx, name: &str) -> Result<bool, rusqlite::Error> {
+    tx.prepare_cached("SELECT EXISTS (SELECT 1 FROM Collections WHERE name = ?1)")?
+        .query_row((name,), |row| row.get(0))
+}
+
+/// Add the record to the collection, returning `true` if the collection did not already contain
+/// the record.
+pub(super) fn insert_member(
+    tx: &Tx,
+    collection: &str,
+    canonical: &str,
+) -> Result<bool, rusqlite::Error> {
+    debug!("Adding '{canonical}' to collection '{collection}'");
+    let num_inserted = tx
+        .prepare_cached(
+            "INSERT OR IGNORE INTO CollectionRecords (collection, record_id) VALUES (?1, ?2)",
+        )?
+        .execute((collection, canonical))?;
+    Ok(num_inserted > 0)
+}
+
+/// Remove the record from the collection, returning `true` if the collection contained the
+/// record.
+pub(super) fn delete_member(
+    tx: &Tx,
+    collection: &str,
+    canonical: &str,
+) -> Result<bool, rusqlite::Error> {
+    debug!("Removing '{canonical}' from collection '{collection}'");
+    let num_deleted = tx
+        .prepare_cached("DELETE FROM CollectionRecords WHERE collection = ?1 AND record_id = ?2")?
+        .execute((collection, canonical))?;
+    Ok(num_deleted > 0)
+}
+
+/// Delete every collection membership of the record, returning the number of rows which were
+/// deleted.
+pub(super) fn delete_record_collections(
+    tx: &Tx,
+    canonical: &str,
+) -> Result<usize, rusqlite::Error> {
+    debug!("Deleting the collection memberships of '{canonical}'");
+    tx.prepare_cached("DELETE FROM CollectionRecords WHERE record_id = ?1")?
+        .execute((canonical,))
+}
+
+/// Replace `from` with `to` in every collection. Collections which already contain `to` keep a
+/// single copy.
+pub(super) fn migrate_collections(tx: &Tx, from: &str, to: &str) -> Result<(), rusqlite::Error> {
+    tx.prepare("UPDATE OR IGNORE CollectionRecords SET record_id = ?2 WHERE record_id = ?1")?
+        .execute((from, to))?;
+    let num_deleted = tx
+        .prepare("DELETE FROM CollectionRecords WHERE record_id = ?1")?
+        .execute((from,))?;
+    debug!("Migrated collections from '{from}' to '{to}', deleting {num_deleted} duplicates");
+    Ok(())
+}
+
+/// Every collection along with the number of records in the collection, in alphabetical order.
+pub(super) fn load_collection_sizes(tx: &Tx) -> Result<Vec<(String, usize)>, rusqlite::Error> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT c.name, COUNT(m.record_id) AS num FROM Collections AS c LEFT JOIN CollectionRecords AS m ON m.collection = c.name GROUP BY c.name ORDER BY c.name",
+    )?;
+    stmt.query_map([], |row| Ok((row.get("name")?, row.get("num")?)))?
+        .collect()
+}
+
+/// The canonical identifiers of the records in the collection, ordered by identifier.
+pub(super) fn load_members(tx: &Tx, collection: &str) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT record_id FROM CollectionRecords WHERE collection = ?1 ORDER BY record_id",
+    )?;
+    stmt.query_map((collection,), |row| row.get("record_id"))?
+        .collect()
+}
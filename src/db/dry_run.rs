@@ -0,0 +1,147 @@
+//! # Dry runs
+//!
+//! With `--dry-run`, every transaction is rolled back instead of being committed. The records
+//! which change in a transaction are tracked with temporary triggers, in the same way as for the
+//! [mirror](super::mirror), and the changes are logged before the transaction is rolled back. Since no transaction is committed, the changes of each transaction are
+//! reported relative to the database on disk, so a command which changes a record in several
+//! transactions may report the record more than once.
+use rusqlite::{Connection, OptionalExtension};
+
+use super::{
+    RecordDatabase, RowId, Tx,
+    state::{ArbitraryData, FromBytesAndVariant},
+};
+use crate::{entry::changed_fields, logger::info};
+
+/// The temporary tables and triggers which track the records changed in a transaction, along
+/// with the revisions which were inserted in the transaction.
+const DRY_RUN_TRIGGERS: &str = "
+CREATE TEMP TABLE IF NOT EXISTS DryRunQueue (record_id TEXT PRIMARY KEY);
+CREATE TEMP TABLE IF NOT EXISTS DryRunInserted (key INTEGER PRIMARY KEY);
+CREATE TEMP TRIGGER IF NOT EXISTS dry_run_records_insert AFTER INSERT ON main.Records BEGIN
+    INSERT OR IGNORE INTO DryRunQueue (record_id) VALUES (NEW.record_id);
+    INSERT OR IGNORE INTO DryRunInserted (key) VALUES (NEW.key);
+END;
+CREATE TEMP TRIGGER IF NOT EXISTS dry_run_records_update AFTER UPDATE ON main.Records BEGIN
+    INSERT OR IGNORE INTO DryRunQueue (record_id) VALUES (OLD.record_id), (NEW.record_id);
+END;
+CREATE TEMP TRIGGER IF NOT EXISTS dry_run_records_delete AFTER DELETE ON main.Records BEGIN
+    INSERT OR IGNORE INTO DryRunQueue (record_id) VALUES (OLD.record_id);
+END;
+CREATE TEMP TRIGGER IF NOT EXISTS dry_run_identifiers_insert AFTER INSERT ON main.Identifiers BEGIN
+    INSERT OR IGNORE INTO DryRunQueue (record_id)
+        SELECT record_id FROM main.Records WHERE key = NEW.record_key;
+END;
+CREATE TEMP TRIGGER IF NOT EXISTS dry_run_identifiers_update AFTER UPDATE ON main.Identifiers BEGIN
+    INSERT OR IGNORE INTO DryRunQueue (record_id)
+        SELECT record_id FROM main.Records WHERE key IN (OLD.record_key, NEW.record_key);
+END;
+CREATE TEMP TRIGGER IF NOT EXISTS dry_run_identifiers_delete AFTER DELETE ON main.Identifiers BEGIN
+    INSERT OR IGNORE INTO DryRunQueue (record_id)
+        SELECT record_id FROM main.Records WHERE key = OLD.record_key;
+END;
+";
+
+/// Whether or not the revision was inserted in the transaction.
+fn inserted(tx: &Tx, key: RowId) -> Result<bool, rusqlite::Error> {
+    Ok(tx
+        .prepare_cached("SELECT 1 FROM DryRunInserted WHERE key = ?1")?
+        .query_row((key,), |_| Ok(()))
+        .optional()?
+        .is_some())
+}
+
+/// The parent of the revision.
+fn parent(tx: &Tx, key: RowId) -> Result<Option<RowId>, rusqlite::Error> {
+    Ok(tx
+        .prepare_cached("SELECT parent_key FROM main.Records WHERE key = ?1")?
+        .query_row((key,), |row| row.get("parent_key"))
+        .optional()?
+        .flatten())
+}
+
+/// The data of the revision.
+fn data(tx: &Tx, key: RowId) -> Result<Option<ArbitraryData>, rusqlite::Error> {
+    tx.prepare_cached("SELECT data, variant FROM main.Records WHERE key = ?1")?
+        .query_row((key,), |row| {
+            Ok(ArbitraryData::from_bytes_and_variant(
+                row.get("data")?,
+                row.get("variant")?,
+            ))
+        })
+        .optional()
+}
+
+/// Describe the change to the record with the canonical identifier in the transaction.
+fn describe(tx: &Tx, canonical: &str) -> Result<String, rusqlite::Error> {
+    let active: Option<(RowId, Option<RowId>)> = tx
+        .prepare_cached(
+            "SELECT key, parent_key FROM main.Records
+                WHERE record_id = ?1 AND key IN (SELECT record_key FROM main.Identifiers)",
+        )?
+        .query_row((canonical,), |row| {
+            Ok((row.get("key")?, row.get("parent_key")?))
+        })
+        .optional()?;
+
+    let Some((key, mut parent_key)) = active else {
+        return Ok(format!("Would permanently delete '{canonical}'"));
+    };
+    if !inserted(tx, key)? {
+        return Ok(format!("Would change the identifiers of '{canonical}'"));
+    }
+
+    // the revision which was active before the transaction is the closest ancestor which was not
+    // inserted in the transaction
+    while let Some(ancestor) = parent_key
+        && inserted(tx, ancestor)?
+    {
+        parent_key = parent(tx, ancestor)?;
+    }
+    let before = match parent_key {
+        Some(ancestor) => data(tx, ancestor)?,
+        None => None,
+    };
+
+    Ok(match (before, data(tx, key)?) {
+        (_, Some(ArbitraryData::Deleted(_))) => format!("Would delete '{canonical}'"),
+        (_, Some(ArbitraryData::Void)) => format!("Would void '{canonical}'"),
+        (Some(ArbitraryData::Entry(before)), Some(ArbitraryData::Entry(after))) => {
+            let fields = changed_fields(&before, &after);
+            if fields.is_empty() {
+                format!("Would add a revision of '{canonical}' without changing its data")
+            } else {
+                format!("Would change {} of '{canonical}'", fields.join(", "))
+            }
+        }
+        (Some(_), _) => format!("Would restore '{canonical}'"),
+        (None, _) => format!("Would insert '{canonical}'"),
+    })
+}
+
+/// Log the changes made in the transaction.
+pub(super) fn report(tx: &Tx) -> Result<(), rusqlite::Error> {
+    let changed: Vec<String> = tx
+        .prepare_cached("SELECT record_id FROM DryRunQueue ORDER BY record_id")?
+        .query_map([], |row| row.get("record_id"))?
+        .collect::<Result<_, _>>()?;
+    for canonical in changed {
+        info!("{}", describe(tx, &canonical)?);
+    }
+    Ok(())
+}
+
+/// Create the temporary tables and triggers which track the changed records.
+fn create_triggers(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute_batch(DRY_RUN_TRIGGERS)
+}
+
+impl RecordDatabase {
+    /// Roll back every subsequent transaction instead of committing it, and report the changes
+    /// which would have been made.
+    pub fn enable_dry_run(&mut self) -> Result<(), rusqlite::Error> {
+        create_triggers(&self.conn)?;
+        self.dry_run = true;
+        Ok(())
+    }
+}
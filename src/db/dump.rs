@@ -0,0 +1,594 @@
+//! # Database dumps
+//!
+//! A dump is a text representation of the entire database, which is suitable for backups, for
+//! moving a database between machines, and for tracking the database with version control.
+//!
+//! The dump is in the [JSON lines](https://jsonlines.org/) format. The first line is a header
+//! which contains the version of the dump format, and every subsequent line is a single row of
+//! one of the database tables, tagged by the `type` key. The rows are written in a deterministic
+//! order, so that dumps of the same database are identical.
+//!
+//! The `key` of a record is the revision number, which is used to refer to the record from the
+//! `parent` of other records and from the `key` of the identifiers, of the provenance, and of the
+//! field sources.
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, Write},
+};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    Identifier, RecordDatabase, Tx,
+    state::{ArbitraryData, AsRecordRowData, FromBytesAndVariant},
+    user_version,
+};
+use crate::{
+    RemoteId,
+    entry::{EntryData, MutableEntryData, RawEntryData},
+    error::DumpError,
+};
+
+/// The name of the dump format, which is written in the header.
+const DUMP_FORMAT: &str = "autobib-dump";
+
+/// The version of the dump format.
+const DUMP_VERSION: u32 = 1;
+
+/// The data of a row in the `Records` table.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum DumpData {
+    Entry {
+        entry_type: String,
+        fields: BTreeMap<String, String>,
+    },
+    Deleted {
+        replacement: Option<String>,
+    },
+    Void,
+}
+
+/// A single line in a dump.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum DumpLine {
+    Header {
+        format: String,
+        version: u32,
+        database_version: i32,
+    },
+    Record {
+        key: i64,
+        canonical: String,
+        modified: DateTime<Local>,
+        parent: Option<i64>,
+        data: DumpData,
+    },
+    Identifier {
+        name: String,
+        key: i64,
+    },
+    AliasLink {
+        name: String,
+        target: String,
+    },
+    NullRecord {
+        canonical: String,
+        attempted: DateTime<Local>,
+    },
+    Attachment {
+        canonical: String,
+        name: String,
+        size: u64,
+        sha256: String,
+        added: DateTime<Local>,
+    },
+    Provenance {
+        key: i64,
+        provider: String,
+        url: String,
+        retrieved: DateTime<Local>,
+        sha256: String,
+    },
+    FieldSource {
+        key: i64,
+        field: String,
+        source: String,
+    },
+    Macro {
+        name: String,
+        value: String,
+    },
+    Relation {
+        canonical: String,
+        target: String,
+        kind: String,
+    },
+    Tag {
+        canonical: String,
+        tag: String,
+    },
+    Collection {
+        name: String,
+    },
+    CollectionRecord {
+        collection: String,
+        canonical: String,
+    },
+    Override {
+        canonical: String,
+        scope: String,
+        field: String,
+        value: String,
+    },
+    Added {
+        canonical: String,
+        added: DateTime<Local>,
+    },
+}
+
+impl DumpData {
+    fn from_arbitrary_data(data: ArbitraryData) -> Self {
+        match data {
+            ArbitraryData::Entry(raw_entry_data) => Self::Entry {
+                entry_type: raw_entry_data.entry_type().to_owned(),
+                fields: raw_entry_data
+                    .fields()
+                    .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                    .collect(),
+            },
+            ArbitraryData::Deleted(replacement) => Self::Deleted {
+                replacement: replacement.map(|remote_id| remote_id.name().to_owned()),
+            },
+            ArbitraryData::Void => Self::Void,
+        }
+    }
+
+    fn into_arbitrary_data(self) -> Result<ArbitraryData, DumpError> {
+        Ok(match self {
+            Self::Entry { entry_type, fields } => {
+                let mut data = MutableEntryData::try_new(entry_type)?;
+                for (key, value) in fields {
+                    data.check_and_insert(key, value)?;
+                }
+                ArbitraryData::Entry(RawEntryData::from_entry_data(&data))
+            }
+            Self::Deleted { replacement } => {
+                ArbitraryData::Deleted(replacement.map(parse_remote_id).transpose()?)
+            }
+            Self::Void => ArbitraryData::Void,
+        })
+    }
+}
+
+/// Check that the identifier has the form of a remote identifier.
+///
+/// The identifier is not validated by the provider, since the dump could contain identifiers of
+/// user-defined providers.
+fn parse_remote_id(id: String) -> Result<RemoteId, DumpError> {
+    RemoteId::from_alias_or_remote_id_unchecked(id.clone()).ok_or(DumpError::InvalidIdentifier(id))
+}
+
+/// Write a single line of the dump.
+fn write_line<W: Write>(writer: &mut W, line: &DumpLine) -> Result<(), DumpError> {
+    serde_json::to_writer(&mut *writer, line)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Write every row of the database as a line of the dump.
+fn write_rows<W: Write>(tx: &Tx, writer: &mut W) -> Result<(), DumpError> {
+    let mut stmt = tx.prepare(
+        "SELECT key, record_id, data, variant, modified, parent_key FROM Records ORDER BY key",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let data = ArbitraryData::from_bytes_and_variant(row.get("data")?, row.get("variant")?);
+        write_line(
+            writer,
+            &DumpLine::Record {
+                key: row.get("key")?,
+                canonical: row.get("record_id")?,
+                modified: row.get("modified")?,
+                parent: row.get("parent_key")?,
+                data: DumpData::from_arbitrary_data(data),
+            },
+        )?;
+    }
+
+    let mut stmt = tx.prepare("SELECT name, record_key FROM Identifiers ORDER BY name")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        write_line(
+            writer,
+            &DumpLine::Identifier {
+                name: row.get("name")?,
+                key: row.get("record_key")?,
+            },
+        )?;
+    }
+
+    let mut stmt = tx.prepare("SELECT name, target FROM AliasLinks ORDER BY name")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        write_line(
+            writer,
+            &DumpLine::AliasLink {
+                name: row.get("name")?,
+                target: row.get("target")?,
+            },
+        )?;
+    }
+
+    let mut stmt = tx.prepare("SELECT record_id, attempted FROM NullRecords ORDER BY record_id")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        write_line(
+            writer,
+            &DumpLine::NullRecord {
+                canonical: row.get("record_id")?,
+                attempted: row.get("attempted")?,
+            },
+        )?;
+    }
+
+    let mut stmt = tx.prepare(
+        "SELECT record_id, name, size, sha256, added FROM Attachments ORDER BY record_id, name",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        write_line(
+            writer,
+            &DumpLine::Attachment {
+                canonical: row.get("record_id")?,
+                name: row.get("name")?,
+                size: row.get("size")?,
+                sha256: row.get("sha256")?,
+                added: row.get("added")?,
+            },
+        )?;
+    }
+
+    let mut stmt = tx.prepare(
+        "SELECT record_key, provider, url, retrieved, sha256 FROM Provenance ORDER BY record_key",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        write_line(
+            writer,
+            &DumpLine::Provenance {
+                key: row.get("record_key")?,
+                provider: row.get("provider")?,
+                url: row.get("url")?,
+                retrieved: row.get("retrieved")?,
+                sha256: row.get("sha256")?,
+            },
+        )?;
+    }
+
+    let mut stmt = tx
+        .prepare("SELECT record_key, field, source FROM FieldSources ORDER BY record_key, field")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        write_line(
+            writer,
+            &DumpLine::FieldSource {
+                key: row.get("record_key")?,
+                field: row.get("field")?,
+                source: row.get("source")?,
+            },
+        )?;
+    }
+
+    let mut stmt = tx.prepare("SELECT name, value FROM Macros ORDER BY name")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        write_line(
+            writer,
+            &DumpLine::Macro {
+                name: row.get("name")?,
+                value: row.get("value")?,
+            },
+        )?;
+    }
+
+    let mut stmt = tx.prepare(
+        "SELECT record_id, target, kind FROM Relations ORDER BY record_id, target, kind",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        write_line(
+            writer,
+            &DumpLine::Relation {
+                canonical: row.get("record_id")?,
+                target: row.get("target")?,
+                kind: row.get("kind")?,
+            },
+        )?;
+    }
+
+    let mut stmt = tx.prepare("SELECT record_id, tag FROM Tags ORDER BY record_id, tag")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        write_line(
+            writer,
+            &DumpLine::Tag {
+                canonical: row.get("record_id")?,
+                tag: row.get("tag")?,
+            },
+        )?;
+    }
+
+    let mut stmt = tx.prepare("SELECT name FROM Collections ORDER BY name")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        write_line(
+            writer,
+            &DumpLine::Collection {
+                name: row.get("name")?,
+            },
+        )?;
+    }
+
+    let mut stmt = tx.prepare(
+        "SELECT collection, record_id FROM CollectionRecords ORDER BY collection, record_id",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        write_line(
+            writer,
+            &DumpLine::CollectionRecord {
+                collection: row.get("collection")?,
+                canonical: row.get("record_id")?,
+            },
+        )?;
+    }
+
+    let mut stmt = tx.prepare(
+        "SELECT record_id, scope, field, value FROM Overrides ORDER BY record_id, scope, field",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        write_line(
+            writer,
+            &DumpLine::Override {
+                canonical: row.get("record_id")?,
+                scope: row.get("scope")?,
+                field: row.get("field")?,
+                value: row.get("value")?,
+            },
+        )?;
+    }
+
+    let mut stmt = tx.prepare("SELECT record_id, added FROM Added ORDER BY record_id")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        write_line(
+            writer,
+            &DumpLine::Added {
+                canonical: row.get("record_id")?,
+                added: row.get("added")?,
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Insert a single line of the dump into the database. The parents of records are only recorded
+/// in `parents`, since the parent could appear later in the dump.
+fn insert_line(tx: &Tx, line: DumpLine, parents: &mut Vec<(i64, i64)>) -> Result<(), DumpError> {
+    match line {
+        DumpLine::Header { .. } => return Err(DumpError::UnexpectedHeader),
+        DumpLine::Record {
+            key,
+            canonical,
+            modified,
+            parent,
+            data,
+        } => {
+            let canonical = parse_remote_id(canonical)?;
+            let data = data.into_arbitrary_data()?;
+            tx.prepare_cached(
+                "INSERT INTO Records (key, record_id, data, modified, variant) VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?
+            .execute((
+                key,
+                canonical.name(),
+                data.data_blob(),
+                modified,
+                data.variant(),
+            ))?;
+            if let Some(parent) = parent {
+                parents.push((key, parent));
+            }
+        }
+        DumpLine::Identifier { name, key } => {
+            tx.prepare_cached("INSERT INTO Identifiers (name, record_key) VALUES (?1, ?2)")?
+                .execute((name, key))?;
+        }
+        DumpLine::AliasLink { name, target } => {
+            tx.prepare_cached("INSERT INTO AliasLinks (name, target) VALUES (?1, ?2)")?
+                .execute((name, target))?;
+        }
+        DumpLine::NullRecord {
+            canonical,
+            attempted,
+        } => {
+            let canonical = parse_remote_id(canonical)?;
+            tx.prepare_cached("INSERT INTO NullRecords (record_id, attempted) VALUES (?1, ?2)")?
+                .execute((canonical.name(), attempted))?;
+        }
+        DumpLine::Attachment {
+            canonical,
+            name,
+            size,
+            sha256,
+            added,
+        } => {
+            let canonical = parse_remote_id(canonical)?;
+            tx.prepare_cached(
+                "INSERT INTO Attachments (record_id, name, size, sha256, added) VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?
+            .execute((canonical.name(), name, size, sha256, added))?;
+        }
+        DumpLine::Provenance {
+            key,
+            provider,
+            url,
+            retrieved,
+            sha256,
+        } => {
+            tx.prepare_cached(
+                "INSERT INTO Provenance (record_key, provider, url, retrieved, sha256) VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?
+            .execute((key, provider, url, retrieved, sha256))?;
+        }
+        DumpLine::FieldSource { key, field, source } => {
+            tx.prepare_cached(
+                "INSERT INTO FieldSources (record_key, field, source) VALUES (?1, ?2, ?3)",
+            )?
+            .execute((key, field, source))?;
+        }
+        DumpLine::Macro { name, value } => {
+            tx.prepare_cached("INSERT INTO Macros (name, value) VALUES (?1, ?2)")?
+                .execute((name, value))?;
+        }
+        DumpLine::Relation {
+            canonical,
+            target,
+            kind,
+        } => {
+            let canonical = parse_remote_id(canonical)?;
+            let target = parse_remote_id(target)?;
+            tx.prepare_cached(
+                "INSERT INTO Relations (record_id, target, kind) VALUES (?1, ?2, ?3)",
+            )?
+            .execute((canonical.name(), target.name(), kind))?;
+        }
+        DumpLine::Tag { canonical, tag } => {
+            let canonical = parse_remote_id(canonical)?;
+            tx.prepare_cached("INSERT INTO Tags (record_id, tag) VALUES (?1, ?2)")?
+                .execute((canonical.name(), tag))?;
+        }
+        DumpLine::Collection { name } => {
+            tx.prepare_cached("INSERT INTO Collections (name) VALUES (?1)")?
+                .execute((name,))?;
+        }
+        DumpLine::CollectionRecord {
+            collection,
+            canonical,
+        } => {
+            let canonical = parse_remote_id(canonical)?;
+            tx.prepare_cached(
+                "INSERT INTO CollectionRecords (collection, record_id) VALUES (?1, ?2)",
+            )?
+            .execute((collection, canonical.name()))?;
+        }
+        DumpLine::Override {
+            canonical,
+            scope,
+            field,
+            value,
+        } => {
+            let canonical = parse_remote_id(canonical)?;
+            tx.prepare_cached(
+                "INSERT INTO Overrides (record_id, scope, field, value) VALUES (?1, ?2, ?3, ?4)",
+            )?
+            .execute((canonical.name(), scope, field, value))?;
+        }
+        DumpLine::Added { canonical, added } => {
+            let canonical = parse_remote_id(canonical)?;
+            tx.prepare_cached("INSERT OR REPLACE INTO Added (record_id, added) VALUES (?1, ?2)")?
+                .execute((canonical.name(), added))?;
+        }
+    }
+    Ok(())
+}
+
+impl RecordDatabase {
+    /// Write a dump of the entire database.
+    pub fn export_dump<W: Write>(&mut self, mut writer: W) -> Result<(), DumpError> {
+        let tx = self.transaction()?;
+        write_line(
+            &mut writer,
+            &DumpLine::Header {
+                format: DUMP_FORMAT.to_owned(),
+                version: DUMP_VERSION,
+                database_version: user_version(),
+            },
+        )?;
+        write_rows(&tx, &mut writer)?;
+        writer.flush()?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Read a dump into the database, which must not contain any records. Either the entire dump
+    /// is read, or the database is not changed.
+    ///
+    /// Returns the number of rows which were inserted.
+    pub fn import_dump<R: BufRead>(&mut self, reader: R) -> Result<usize, DumpError> {
+        let tx = self.transaction()?;
+
+        let is_empty: bool = tx
+            .prepare(
+                "SELECT NOT (EXISTS (SELECT 1 FROM Records) OR EXISTS (SELECT 1 FROM NullRecords) OR EXISTS (SELECT 1 FROM AliasLinks) OR EXISTS (SELECT 1 FROM Macros))",
+            )?
+            .query_row([], |row| row.get(0))?;
+        if !is_empty {
+            return Err(DumpError::NotEmpty);
+        }
+
+        let mut lines = reader.lines();
+        let mut parents = Vec::new();
+        let mut num_rows = 0;
+
+        let database_version = match lines.next().transpose()? {
+            Some(header) => match serde_json::from_str(&header) {
+                Ok(DumpLine::Header {
+                    format,
+                    version,
+                    database_version,
+                }) if format == DUMP_FORMAT => {
+                    if version != DUMP_VERSION {
+                        return Err(DumpError::UnsupportedVersion(version));
+                    }
+                    database_version
+                }
+                _ => return Err(DumpError::MissingHeader),
+            },
+            None => return Err(DumpError::MissingHeader),
+        };
+
+        for (idx, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let parsed = serde_json::from_str(&line)
+                .map_err(|err| DumpError::Line(idx + 2, err.to_string()))?;
+            insert_line(&tx, parsed, &mut parents)
+                .map_err(|err| DumpError::Line(idx + 2, err.to_string()))?;
+            num_rows += 1;
+        }
+
+        for (key, parent) in parents {
+            tx.prepare_cached("UPDATE Records SET parent_key = ?2 WHERE key = ?1")?
+                .execute((key, parent))?;
+        }
+
+        // dumps of databases which predate the `Added` table use the first revision instead
+        if database_version < 14 {
+            tx.prepare_cached(
+                "INSERT OR IGNORE INTO Added (record_id, added) SELECT record_id, modified FROM Records WHERE key IN (SELECT min(key) FROM Records GROUP BY record_id)",
+            )?
+            .execute(())?;
+        }
+
+        tx.commit()?;
+        Ok(num_rows)
+    }
+}
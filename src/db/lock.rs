@@ -0,0 +1,95 @@
+//! # Locking the database for concurrent invocations
+//!
+//! SQLite only locks the database for the duration of a single transaction, so two invocations
+//! which write to the same database would otherwise interleave their transactions, and a
+//! transaction which cannot acquire the lock fails immediately with `database is locked`.
+//!
+//! Invocations which write to the database therefore first acquire an exclusive advisory lock on
+//! a lock file next to the database file, which is held until the invocation finishes. An
+//! invocation which finds the lock held waits for the other invocation to finish, up to a
+//! timeout. In addition, every connection waits for a short time when a transaction cannot
+//! acquire the SQLite lock, which covers invocations which do not take the advisory lock, such
+//! as invocations with `--read-only`.
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    path::{Path, PathBuf},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::DatabaseError,
+    logger::{debug, info, warn},
+};
+
+/// The time for which a connection waits for the SQLite lock before a transaction fails.
+pub(super) const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The time between two attempts to acquire the lock.
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// An exclusive lock on a database, which is released when dropped.
+#[derive(Debug)]
+pub struct DatabaseLock {
+    _file: File,
+}
+
+/// The path of the lock file of the database at the provided path.
+fn lock_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push(".lock");
+    PathBuf::from(path)
+}
+
+/// Acquire an exclusive lock on the database at the provided path.
+///
+/// If the database is locked by another invocation, wait until it is released, or until the
+/// timeout elapses. If `timeout` is `None`, wait until the lock is released.
+pub fn lock_database(
+    db_path: &Path,
+    timeout: Option<Duration>,
+) -> Result<DatabaseLock, DatabaseError> {
+    let path = lock_path(db_path);
+    debug!("Acquiring lock file '{}'", path.display());
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .map_err(|err| DatabaseError::LockFailed(db_path.display().to_string(), err))?;
+
+    let start = Instant::now();
+    let mut waiting = false;
+    loop {
+        match file.try_lock() {
+            Ok(()) => break,
+            Err(TryLockError::WouldBlock) => {
+                if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+                    return Err(DatabaseError::Locked(db_path.display().to_string()));
+                }
+                if !waiting {
+                    warn!(
+                        "Database '{}' is locked by another autobib process; waiting for it to finish",
+                        db_path.display()
+                    );
+                    waiting = true;
+                }
+                sleep(RETRY_INTERVAL);
+            }
+            Err(TryLockError::Error(err)) => {
+                return Err(DatabaseError::LockFailed(
+                    db_path.display().to_string(),
+                    err,
+                ));
+            }
+        }
+    }
+
+    if waiting {
+        info!(
+            "Acquired the database lock after {:.1}s",
+            start.elapsed().as_secs_f64()
+        );
+    }
+    Ok(DatabaseLock { _file: file })
+}
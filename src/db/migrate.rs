@@ -136,6 +136,123 @@ pub fn migrate(conn: &mut Connection, v: i32) -> Result<(), DatabaseError> {
 
             tx.commit()?;
         }
+        2 => {
+            let tx = conn.transaction()?;
+
+            debug!("Creating new table 'AliasLinks'");
+            tx.execute(include_str!("migrate/v2/create_table_alias_links.sql"), ())?;
+
+            tx.commit()?;
+        }
+        3 => {
+            let tx = conn.transaction()?;
+
+            debug!("Creating new table 'Attachments'");
+            tx.execute(include_str!("migrate/v3/create_table_attachments.sql"), ())?;
+
+            tx.commit()?;
+        }
+        4 => {
+            let tx = conn.transaction()?;
+
+            debug!("Creating new tables 'Runs' and 'RunChanges'");
+            tx.execute(include_str!("migrate/v4/create_table_runs.sql"), ())?;
+            tx.execute(include_str!("migrate/v4/create_table_run_changes.sql"), ())?;
+
+            tx.commit()?;
+        }
+        5 => {
+            let tx = conn.transaction()?;
+
+            debug!("Creating new temporary table `tmp_RunChanges`");
+            tx.execute(include_str!("migrate/v5/make_tmp_table.sql"), ())?;
+
+            debug!("Recreating the `RunChanges` table with the new column `previous`");
+            tx.execute(include_str!("migrate/v5/drop_original.sql"), ())?;
+            tx.execute(include_str!("migrate/v5/create_table_run_changes.sql"), ())?;
+
+            debug!("Copying changes into the new `RunChanges` table");
+            tx.execute(include_str!("migrate/v5/copy_changes.sql"), ())?;
+            tx.execute(include_str!("migrate/v5/drop_tmp_table.sql"), ())?;
+
+            tx.commit()?;
+        }
+        6 => {
+            let tx = conn.transaction()?;
+
+            debug!("Creating new table 'Provenance'");
+            tx.execute(include_str!("migrate/v6/create_table_provenance.sql"), ())?;
+
+            tx.commit()?;
+        }
+        7 => {
+            let tx = conn.transaction()?;
+
+            debug!("Creating new table 'FieldSources'");
+            tx.execute(
+                include_str!("migrate/v7/create_table_field_sources.sql"),
+                (),
+            )?;
+
+            tx.commit()?;
+        }
+        8 => {
+            let tx = conn.transaction()?;
+
+            debug!("Creating new table 'Macros'");
+            tx.execute(include_str!("migrate/v8/create_table_macros.sql"), ())?;
+
+            tx.commit()?;
+        }
+        9 => {
+            let tx = conn.transaction()?;
+
+            debug!("Creating new table 'Relations'");
+            tx.execute(include_str!("migrate/v9/create_table_relations.sql"), ())?;
+
+            tx.commit()?;
+        }
+        10 => {
+            let tx = conn.transaction()?;
+
+            debug!("Creating new table 'Tags'");
+            tx.execute(include_str!("migrate/v10/create_table_tags.sql"), ())?;
+
+            tx.commit()?;
+        }
+        11 => {
+            let tx = conn.transaction()?;
+
+            debug!("Creating new table 'Collections'");
+            tx.execute(include_str!("migrate/v11/create_table_collections.sql"), ())?;
+
+            debug!("Creating new table 'CollectionRecords'");
+            tx.execute(
+                include_str!("migrate/v11/create_table_collection_records.sql"),
+                (),
+            )?;
+
+            tx.commit()?;
+        }
+        12 => {
+            let tx = conn.transaction()?;
+
+            debug!("Creating new table 'Overrides'");
+            tx.execute(include_str!("migrate/v12/create_table_overrides.sql"), ())?;
+
+            tx.commit()?;
+        }
+        13 => {
+            let tx = conn.transaction()?;
+
+            debug!("Creating new table 'Added'");
+            tx.execute(include_str!("migrate/v13/create_table_added.sql"), ())?;
+
+            debug!("Copying the time of the first revision of each record into 'Added'");
+            tx.execute(include_str!("migrate/v13/copy_added.sql"), ())?;
+
+            tx.commit()?;
+        }
         // this is only reachable if the user_version was set by a different program
         _ => return Err(DatabaseError::InvalidDatabase),
     }
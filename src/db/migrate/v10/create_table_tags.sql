@@ -0,0 +1,5 @@
+CREATE TABLE Tags (
+    record_id TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (record_id, tag)
+) STRICT, WITHOUT ROWID
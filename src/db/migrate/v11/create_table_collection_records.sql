@@ -0,0 +1,5 @@
+CREATE TABLE CollectionRecords (
+    collection TEXT NOT NULL REFERENCES Collections(name) ON DELETE CASCADE,
+    record_id TEXT NOT NULL,
+    PRIMARY KEY (collection, record_id)
+) STRICT, WITHOUT ROWID
@@ -0,0 +1,3 @@
+INSERT INTO Added (record_id, added)
+    SELECT record_id, modified FROM Records
+    WHERE key IN (SELECT min(key) FROM Records GROUP BY record_id)
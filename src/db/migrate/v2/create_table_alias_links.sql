@@ -0,0 +1,4 @@
+CREATE TABLE AliasLinks (
+    name TEXT NOT NULL PRIMARY KEY,
+    target TEXT NOT NULL
+) STRICT, WITHOUT ROWID
@@ -0,0 +1,8 @@
+CREATE TABLE Attachments (
+    record_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    sha256 TEXT NOT NULL,
+    added TEXT NOT NULL,
+    PRIMARY KEY (record_id, name)
+) STRICT, WITHOUT ROWID
@@ -0,0 +1,8 @@
+CREATE TABLE RunChanges (
+    run_key INTEGER NOT NULL REFERENCES Runs(key) ON DELETE CASCADE,
+    seq INTEGER NOT NULL,
+    record_id TEXT NOT NULL,
+    action TEXT NOT NULL,
+    revision INTEGER NOT NULL,
+    PRIMARY KEY (run_key, seq)
+) STRICT, WITHOUT ROWID
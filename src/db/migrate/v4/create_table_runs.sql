@@ -0,0 +1,5 @@
+CREATE TABLE Runs (
+    key INTEGER PRIMARY KEY,
+    command TEXT NOT NULL,
+    finished TEXT NOT NULL
+) STRICT
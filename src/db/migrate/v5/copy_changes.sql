@@ -0,0 +1,7 @@
+INSERT INTO RunChanges (run_key, seq, record_id, action, revision, previous)
+    SELECT run_key, seq, record_id, action, revision,
+        CASE WHEN action IN ('updated', 'deleted')
+            THEN (SELECT parent_key FROM Records WHERE key = revision)
+            ELSE NULL
+        END
+    FROM tmp_RunChanges
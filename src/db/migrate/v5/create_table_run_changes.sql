@@ -0,0 +1,9 @@
+CREATE TABLE RunChanges (
+    run_key INTEGER NOT NULL REFERENCES Runs(key) ON DELETE CASCADE,
+    seq INTEGER NOT NULL,
+    record_id TEXT NOT NULL,
+    action TEXT NOT NULL,
+    revision INTEGER NOT NULL,
+    previous INTEGER,
+    PRIMARY KEY (run_key, seq)
+) STRICT, WITHOUT ROWID
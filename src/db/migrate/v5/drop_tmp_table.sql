@@ -0,0 +1 @@
+DROP TABLE tmp_RunChanges
@@ -0,0 +1 @@
+CREATE TEMP TABLE tmp_RunChanges AS SELECT * FROM RunChanges
@@ -0,0 +1,7 @@
+CREATE TABLE Provenance (
+    record_key INTEGER NOT NULL PRIMARY KEY REFERENCES Records(key) ON DELETE CASCADE,
+    provider TEXT NOT NULL,
+    url TEXT NOT NULL,
+    retrieved TEXT NOT NULL,
+    sha256 TEXT NOT NULL
+) STRICT
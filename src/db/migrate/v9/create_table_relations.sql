@@ -0,0 +1,6 @@
+CREATE TABLE Relations (
+    record_id TEXT NOT NULL,
+    target TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    PRIMARY KEY (record_id, target, kind)
+) STRICT, WITHOUT ROWID
@@ -0,0 +1,263 @@
+//! # Database mirror
+//!
+//! The mirror is a directory which contains a BibTeX file for every active record in the
+//! database, so that the records can be tracked with version control. The file of a record is
+//! `<provider>/<sub_id>.bib`, where characters of the `sub_id` which cannot appear in file names
+//! are percent-encoded.
+//!
+//! The records which change in a transaction are tracked with temporary triggers, which insert
+//! the canonical identifier of every modified row of the `Records` and `Identifiers` tables into
+//! the temporary `MirrorQueue` table. When the transaction is committed, the queue is cleared and
+//! the files of the queued records are updated. Inside a batch, this only happens once the batch
+//! is committed, since the queue is rolled back along with the changes. Since the triggers only
+//! exist for the connection on which the mirror was enabled, changes made by other connections
+//! are only written by `autobib util mirror`.
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use rusqlite::{Connection, OptionalExtension};
+
+use super::{
+    Identifier, RecordDatabase,
+    state::{ArbitraryData, FromBytesAndVariant},
+};
+use crate::{
+    RemoteId,
+    entry::{Entry, EntryKey, RawEntryData},
+    error::MirrorError,
+    logger::{info, warn},
+};
+
+/// The directory of the mirror, if it was enabled.
+static MIRROR_DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+
+/// The temporary table and triggers which track the records changed in a transaction.
+const MIRROR_TRIGGERS: &str = "
+CREATE TEMP TABLE IF NOT EXISTS MirrorQueue (record_id TEXT PRIMARY KEY);
+CREATE TEMP TRIGGER IF NOT EXISTS mirror_records_insert AFTER INSERT ON main.Records BEGIN
+    INSERT OR IGNORE INTO MirrorQueue (record_id) VALUES (NEW.record_id);
+END;
+CREATE TEMP TRIGGER IF NOT EXISTS mirror_records_update AFTER UPDATE ON main.Records BEGIN
+    INSERT OR IGNORE INTO MirrorQueue (record_id) VALUES (OLD.record_id), (NEW.record_id);
+END;
+CREATE TEMP TRIGGER IF NOT EXISTS mirror_records_delete AFTER DELETE ON main.Records BEGIN
+    INSERT OR IGNORE INTO MirrorQueue (record_id) VALUES (OLD.record_id);
+END;
+CREATE TEMP TRIGGER IF NOT EXISTS mirror_identifiers_insert AFTER INSERT ON main.Identifiers BEGIN
+    INSERT OR IGNORE INTO MirrorQueue (record_id)
+        SELECT record_id FROM main.Records WHERE key = NEW.record_key;
+END;
+CREATE TEMP TRIGGER IF NOT EXISTS mirror_identifiers_update AFTER UPDATE ON main.Identifiers BEGIN
+    INSERT OR IGNORE INTO MirrorQueue (record_id)
+        SELECT record_id FROM main.Records WHERE key IN (OLD.record_key, NEW.record_key);
+END;
+CREATE TEMP TRIGGER IF NOT EXISTS mirror_identifiers_delete AFTER DELETE ON main.Identifiers BEGIN
+    INSERT OR IGNORE INTO MirrorQueue (record_id)
+        SELECT record_id FROM main.Records WHERE key = OLD.record_key;
+END;
+";
+
+/// A pending change to a file in the mirror.
+pub(super) struct MirrorChange {
+    /// The canonical identifier of the record.
+    canonical: RemoteId,
+    /// The new contents of the file, or `None` if the file should be removed.
+    contents: Option<String>,
+}
+
+/// Render the data of a record as the contents of its file in the mirror.
+fn render(canonical: &RemoteId, data: RawEntryData) -> String {
+    let key =
+        EntryKey::try_new(canonical.name().to_owned()).unwrap_or_else(|_| EntryKey::placeholder());
+    Entry::new(key, data).to_string()
+}
+
+/// Encode the `sub_id` of a canonical identifier as a file name.
+///
+/// The characters which are not permitted in file names on common platforms, control
+/// characters, a leading `.`, and `%` itself are percent-encoded, so that distinct identifiers
+/// correspond to distinct files.
+fn file_name(sub_id: &str) -> String {
+    let mut name = String::with_capacity(sub_id.len() + 4);
+    for (idx, ch) in sub_id.chars().enumerate() {
+        match ch {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '%' => {
+                let _ = write!(name, "%{:02X}", ch as u32);
+            }
+            '.' if idx == 0 => name.push_str("%2E"),
+            ch if ch.is_control() => {
+                let _ = write!(name, "%{:02X}", ch as u32);
+            }
+            ch => name.push(ch),
+        }
+    }
+    name.push_str(".bib");
+    name
+}
+
+/// The path of the file of a record in the mirror.
+fn mirror_path(dir: &Path, canonical: &RemoteId) -> PathBuf {
+    dir.join(canonical.provider())
+        .join(file_name(canonical.sub_id()))
+}
+
+/// Write the file, unless it already has the provided contents.
+fn write_if_changed(path: &Path, contents: &str) -> io::Result<()> {
+    if fs::read_to_string(path).is_ok_and(|current| current == contents) {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)
+}
+
+/// Remove the file, if it exists.
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+/// Apply a change to the mirror in the directory, warning if the file could not be updated.
+fn apply_change(dir: &Path, change: &MirrorChange) {
+    let path = mirror_path(dir, &change.canonical);
+    let res = match &change.contents {
+        Some(contents) => write_if_changed(&path, contents),
+        None => remove_if_exists(&path),
+    };
+    if let Err(err) = res {
+        warn!("Failed to update mirror file '{}': {err}", path.display());
+    }
+}
+
+/// Take the changes to the mirror which were queued in the current transaction of the
+/// connection, clearing the queue.
+///
+/// Returns no changes if the mirror is not enabled on the connection.
+pub(super) fn take_changes(conn: &Connection) -> Result<Vec<MirrorChange>, rusqlite::Error> {
+    if MIRROR_DIRECTORY.get().is_none() {
+        return Ok(Vec::new());
+    }
+
+    let enabled = conn
+        .prepare_cached(
+            "SELECT 1 FROM sqlite_temp_master WHERE type = 'table' AND name = 'MirrorQueue'",
+        )?
+        .query_row([], |_| Ok(()))
+        .optional()?
+        .is_some();
+    if !enabled {
+        return Ok(Vec::new());
+    }
+
+    let mut changes = Vec::new();
+    let mut stmt = conn.prepare_cached(
+        "SELECT q.record_id, r.data, r.variant FROM MirrorQueue q LEFT JOIN main.Records r
+            ON r.record_id = q.record_id AND r.key IN (SELECT record_key FROM main.Identifiers)
+        ORDER BY q.record_id",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let canonical = RemoteId::from_string_unchecked(row.get("record_id")?);
+        let data: Option<Vec<u8>> = row.get("data")?;
+        let contents = match data {
+            Some(bytes) => {
+                match ArbitraryData::from_bytes_and_variant(bytes, row.get("variant")?) {
+                    ArbitraryData::Entry(data) => Some(render(&canonical, data)),
+                    ArbitraryData::Deleted(_) | ArbitraryData::Void => None,
+                }
+            }
+            None => None,
+        };
+        changes.push(MirrorChange {
+            canonical,
+            contents,
+        });
+    }
+    drop(rows);
+    drop(stmt);
+
+    conn.prepare_cached("DELETE FROM MirrorQueue")?
+        .execute([])?;
+    Ok(changes)
+}
+
+/// Write the changes taken from a committed transaction to the mirror.
+pub(super) fn write_changes(changes: Vec<MirrorChange>) {
+    if let Some(dir) = MIRROR_DIRECTORY.get() {
+        for change in &changes {
+            apply_change(dir, change);
+        }
+    }
+}
+
+/// Create the temporary table and triggers which track the changed records.
+fn create_triggers(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute_batch(MIRROR_TRIGGERS)
+}
+
+impl RecordDatabase {
+    /// Keep the mirror in the provided directory up to date with every subsequent change made
+    /// through this connection. The mirror can only be enabled for a single directory; subsequent
+    /// calls only enable the mirror on the connection.
+    pub fn enable_mirror(&self, dir: PathBuf) -> Result<(), rusqlite::Error> {
+        create_triggers(&self.conn)?;
+        let _ = MIRROR_DIRECTORY.set(dir);
+        Ok(())
+    }
+
+    /// Write the file of every active record to the mirror in the provided directory, and remove
+    /// the files which do not correspond to an active record.
+    pub fn write_mirror(&mut self, dir: &Path) -> Result<(), MirrorError> {
+        let mut expected = HashSet::new();
+        self.map_active_records(|row| {
+            let change = MirrorChange {
+                contents: Some(render(&row.canonical, row.data)),
+                canonical: row.canonical,
+            };
+            apply_change(dir, &change);
+            expected.insert(mirror_path(dir, &change.canonical));
+        })?;
+
+        let providers = match fs::read_dir(dir) {
+            Ok(providers) => providers,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        for provider in providers {
+            let provider = provider?.path();
+            if !provider.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(&provider)? {
+                let path = file?.path();
+                if path.extension().is_some_and(|ext| ext == "bib") && !expected.contains(&path) {
+                    info!("Removing stale mirror file '{}'", path.display());
+                    fs::remove_file(&path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("first"), "first.bib");
+        assert_eq!(file_name("10.1000/182"), "10.1000%2F182.bib");
+        assert_eq!(file_name("50%"), "50%25.bib");
+        assert_eq!(file_name(".hidden"), "%2Ehidden.bib");
+        assert_eq!(file_name("a:b"), "a%3Ab.bib");
+    }
+}
@@ -0,0 +1,97 @@
+//! # Overlay databases
+//!
+//! Overlay databases are additional databases which are attached to the connection of the
+//! writable database with `ATTACH DATABASE`, using the schema names `overlay_1`, `overlay_2`,
+//! and so on, in the order in which they were provided.
+//!
+//! The overlays are only read when an identifier is not present in the writable database: the
+//! record is then copied from the first overlay which contains the identifier, instead of
+//! retrieving it from the remote provider. Since every statement which modifies the database
+//! refers to the tables without a schema name, which resolve to the tables of the writable
+//! database, the overlays are never modified.
+use std::path::Path;
+
+use rusqlite::OptionalExtension;
+
+use super::{Identifier, RecordDatabase, Tx, application_id, state::RecordRow, user_version};
+use crate::{entry::RawEntryData, error::DatabaseError, logger::info};
+
+/// The schema names of the attached overlay databases, in the order in which they are searched.
+fn overlay_schemas(tx: &Tx) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT name FROM pragma_database_list WHERE name GLOB 'overlay_*' ORDER BY seq",
+    )?;
+    stmt.query_map([], |row| row.get("name"))?.collect()
+}
+
+/// Look up the identifier in the overlay databases, returning the active record of the first
+/// overlay which contains the identifier.
+///
+/// Deleted and void records are skipped, so that the next overlay is searched instead.
+pub(in crate::db) fn lookup<K: Identifier>(
+    tx: &Tx,
+    record_id: &K,
+) -> Result<Option<RecordRow<RawEntryData>>, rusqlite::Error> {
+    for schema in overlay_schemas(tx)? {
+        let row = tx
+            .prepare_cached(&format!(
+                "SELECT r.record_id, r.modified, r.data, r.variant
+                FROM {schema}.Identifiers i JOIN {schema}.Records r ON r.key = i.record_key
+                WHERE i.name = ?1 AND r.variant = 0"
+            ))?
+            .query_row([record_id.name()], |row| {
+                Ok(RecordRow::from_row_unchecked(row))
+            })
+            .optional()?;
+        if row.is_some() {
+            return Ok(row);
+        }
+    }
+    Ok(None)
+}
+
+impl RecordDatabase {
+    /// Attach the database at the provided path as a read-only overlay, which is searched after
+    /// the overlays which were already attached.
+    ///
+    /// The overlay must have the same version as the current binary.
+    pub fn attach_overlay<P: AsRef<Path>>(&self, path: P) -> Result<(), DatabaseError> {
+        let path = path.as_ref();
+        if !path.is_file() {
+            return Err(DatabaseError::MissingOverlay(path.display().to_string()));
+        }
+
+        let num_overlays: usize = self.conn.query_row(
+            "SELECT count(*) FROM pragma_database_list WHERE name GLOB 'overlay_*'",
+            [],
+            |row| row.get(0),
+        )?;
+        let schema = format!("overlay_{}", num_overlays + 1);
+        info!(
+            "Using overlay database file '{}' as '{schema}'",
+            path.display()
+        );
+        self.conn.execute(
+            &format!("ATTACH DATABASE ?1 AS {schema}"),
+            [path.to_string_lossy().as_ref()],
+        )?;
+
+        let db_user_version: i32 =
+            self.conn
+                .query_row(&format!("PRAGMA {schema}.user_version"), [], |row| {
+                    row.get(0)
+                })?;
+        let db_application_id: i32 =
+            self.conn
+                .query_row(&format!("PRAGMA {schema}.application_id"), [], |row| {
+                    row.get(0)
+                })?;
+        if db_user_version != user_version() || db_application_id != application_id() {
+            return Err(DatabaseError::IncompatibleOverlay(
+                path.display().to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
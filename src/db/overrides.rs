@@ -0,0 +1,92 @@
+//! # Overrides of output fields
+//!
+//! An override replaces the value of a field of a record when the record is written to a specific
+//! output file, without changing the data of the record. Overrides are stored in the `Overrides`
+//! table, keyed by the canonical identifier of the record, the scope, and the name of the field.
+//! The scope is a path which is compared with the path of the output file when writing entries.
+use super::Tx;
+use crate::logger::debug;
+
+/// A value of a field which replaces the value in the record data when writing to an output file
+/// which matches the scope.
+#[derive(Debug, Clone)]
+pub struct FieldOverride {
+    /// The canonical identifier of the record.
+    pub canonical: String,
+    /// The path of the output files to which the override applies.
+    pub scope: String,
+    /// The name of the field.
+    pub field: String,
+    /// The value of the field.
+    pub value: String,
+}
+
+/// Set the value of the override, replacing any existing value.
+pub(super) fn insert_override(
+    tx: &Tx,
+    field_override: &FieldOverride,
+) -> Result<(), rusqlite::Error> {
+    let FieldOverride {
+        canonical,
+        scope,
+        field,
+        value,
+    } = field_override;
+    debug!("Overriding '{field}' of '{canonical}' in '{scope}'");
+    tx.prepare_cached(
+        "INSERT OR REPLACE INTO Overrides (record_id, scope, field, value) VALUES (?1, ?2, ?3, ?4)",
+    )?
+    .execute((canonical, scope, field, value))?;
+    Ok(())
+}
+
+/// Delete the override, returning `true` if the override existed.
+pub(super) fn delete_override(
+    tx: &Tx,
+    canonical: &str,
+    scope: &str,
+    field: &str,
+) -> Result<bool, rusqlite::Error> {
+    let num_deleted = tx
+        .prepare_cached("DELETE FROM Overrides WHERE record_id = ?1 AND scope = ?2 AND field = ?3")?
+        .execute((canonical, scope, field))?;
+    Ok(num_deleted > 0)
+}
+
+/// Delete every override of the record, returning the number of overrides which were deleted.
+pub(super) fn delete_record_overrides(tx: &Tx, canonical: &str) -> Result<usize, rusqlite::Error> {
+    debug!("Deleting the overrides of '{canonical}'");
+    tx.prepare_cached("DELETE FROM Overrides WHERE record_id = ?1")?
+        .execute((canonical,))
+}
+
+/// Replace `from` with `to` in every override. Overrides which already exist for `to` are kept.
+pub(super) fn migrate_overrides(tx: &Tx, from: &str, to: &str) -> Result<(), rusqlite::Error> {
+    tx.prepare("UPDATE OR IGNORE Overrides SET record_id = ?2 WHERE record_id = ?1")?
+        .execute((from, to))?;
+    let num_deleted = tx
+        .prepare("DELETE FROM Overrides WHERE record_id = ?1")?
+        .execute((from,))?;
+    debug!("Migrated overrides from '{from}' to '{to}', deleting {num_deleted} duplicates");
+    Ok(())
+}
+
+/// The overrides, ordered by record, scope, and field. If a record is provided, only the
+/// overrides of the record are returned.
+pub(super) fn load_overrides(
+    tx: &Tx,
+    canonical: Option<&str>,
+) -> Result<Vec<FieldOverride>, rusqlite::Error> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT record_id, scope, field, value FROM Overrides WHERE ?1 IS NULL OR record_id = ?1 ORDER BY record_id, scope, field",
+    )?;
+    stmt.query_map((canonical,), |row| {
+        Ok(FieldOverride {
+            canonical: row.get("record_id")?,
+            scope: row.get("scope")?,
+            field: row.get("field")?,
+            value: row.get("value")?,
+        })
+    })?
+    .collect()
+}
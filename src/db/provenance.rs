@@ -0,0 +1,190 @@
+//! # Provenance of record data
+//!
+//! When the data of a revision is retrieved from a provider, the provider, the URL of the
+//! request, the time of retrieval, and the hash of the raw response are stored in the
+//! `Provenance` table, keyed by the revision. Revisions which are created by editing or merging
+//! data do not have provenance, so the provenance of a revision is determined by its ancestors.
+//!
+//! When data from another source is merged into a record, the fields which were supplied by the
+//! merge are stored in the `FieldSources` table along with a description of the source, keyed by
+//! the revision created by the merge and the name of the field.
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use super::{Tx, state::RevisionId};
+use crate::logger::debug;
+
+/// Where the data of a revision was retrieved from.
+#[derive(Debug, Clone, Serialize)]
+pub struct Provenance {
+    /// The provider which returned the data.
+    pub provider: String,
+    /// The URL of the request whose response contained the data.
+    pub url: String,
+    /// When the data was retrieved.
+    pub retrieved: DateTime<Local>,
+    /// The lowercase hexadecimal SHA-256 hash of the raw response.
+    pub sha256: String,
+}
+
+/// The provenance of a revision, along with the revision.
+#[derive(Debug, Serialize)]
+pub struct RevisionProvenance {
+    /// The hexadecimal revision.
+    pub revision: String,
+    #[serde(flatten)]
+    pub provenance: Provenance,
+}
+
+/// The source of a field, along with the revision in which the field was supplied.
+#[derive(Debug, Serialize)]
+pub struct FieldSource {
+    /// The name of the field.
+    pub field: String,
+    /// A description of the source which supplied the field.
+    pub source: String,
+    /// The hexadecimal revision which was created by the merge.
+    pub revision: String,
+}
+
+impl FieldSource {
+    /// The sources of the fields which were supplied in the provided revision, sorted by field.
+    pub fn load_revision(tx: &Tx, rev: RevisionId) -> Result<Vec<Self>, rusqlite::Error> {
+        load_revision_field_sources(tx, rev.0)
+    }
+}
+
+/// Record where the data of the revision with the provided key was retrieved from.
+pub(super) fn insert_provenance(
+    tx: &Tx,
+    row_id: i64,
+    provenance: &Provenance,
+) -> Result<(), rusqlite::Error> {
+    debug!(
+        "Setting provenance of row '{row_id}' to '{}'",
+        provenance.url
+    );
+    tx.prepare_cached(
+        "INSERT OR REPLACE INTO Provenance (record_key, provider, url, retrieved, sha256) VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?
+    .execute((
+        row_id,
+        &provenance.provider,
+        &provenance.url,
+        provenance.retrieved,
+        &provenance.sha256,
+    ))?;
+    Ok(())
+}
+
+/// The provenance of the revision with the provided key and all of its ancestors, starting with
+/// the most recent revision.
+pub(super) fn load_provenance(
+    tx: &Tx,
+    row_id: i64,
+) -> Result<Vec<RevisionProvenance>, rusqlite::Error> {
+    let mut stmt = tx.prepare_cached(
+        "
+WITH RECURSIVE ancestors AS (
+    SELECT key, parent_key, 0 as level
+    FROM Records
+    WHERE key = ?1
+
+    UNION ALL
+
+    SELECT r.key, r.parent_key, a.level + 1
+    FROM ancestors a
+    INNER JOIN Records AS r ON a.parent_key = r.key
+)
+SELECT p.record_key, p.provider, p.url, p.retrieved, p.sha256
+FROM Provenance AS p
+INNER JOIN ancestors a ON p.record_key = a.key
+ORDER BY a.level",
+    )?;
+    stmt.query_map((row_id,), |row| {
+        Ok(RevisionProvenance {
+            revision: format!("{:0>4x}", row.get::<_, i64>("record_key")?),
+            provenance: Provenance {
+                provider: row.get("provider")?,
+                url: row.get("url")?,
+                retrieved: row.get("retrieved")?,
+                sha256: row.get("sha256")?,
+            },
+        })
+    })?
+    .collect()
+}
+
+/// Record that the provided fields of the revision with the provided key were supplied by
+/// `source`.
+pub(super) fn insert_field_sources<'a>(
+    tx: &Tx,
+    row_id: i64,
+    fields: impl IntoIterator<Item = &'a str>,
+    source: &str,
+) -> Result<(), rusqlite::Error> {
+    debug!("Setting source of fields of row '{row_id}' to '{source}'");
+    let mut stmt = tx.prepare_cached(
+        "INSERT OR REPLACE INTO FieldSources (record_key, field, source) VALUES (?1, ?2, ?3)",
+    )?;
+    for field in fields {
+        stmt.execute((row_id, field, source))?;
+    }
+    Ok(())
+}
+
+/// The sources of the fields supplied in the revision with the provided key, sorted by field.
+fn load_revision_field_sources(tx: &Tx, row_id: i64) -> Result<Vec<FieldSource>, rusqlite::Error> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT field, source FROM FieldSources WHERE record_key = ?1 ORDER BY field",
+    )?;
+    stmt.query_map((row_id,), |row| {
+        Ok(FieldSource {
+            field: row.get("field")?,
+            source: row.get("source")?,
+            revision: format!("{row_id:0>4x}"),
+        })
+    })?
+    .collect()
+}
+
+/// The most recent source of every field of the revision with the provided key, considering the
+/// revision and all of its ancestors, sorted by field.
+pub(super) fn load_field_sources(
+    tx: &Tx,
+    row_id: i64,
+) -> Result<Vec<FieldSource>, rusqlite::Error> {
+    let mut stmt = tx.prepare_cached(
+        "
+WITH RECURSIVE ancestors AS (
+    SELECT key, parent_key, 0 as level
+    FROM Records
+    WHERE key = ?1
+
+    UNION ALL
+
+    SELECT r.key, r.parent_key, a.level + 1
+    FROM ancestors a
+    INNER JOIN Records AS r ON a.parent_key = r.key
+)
+SELECT f.record_key, f.field, f.source
+FROM FieldSources AS f
+INNER JOIN ancestors a ON f.record_key = a.key
+ORDER BY f.field, a.level",
+    )?;
+    let mut sources: Vec<FieldSource> = Vec::new();
+    for source in stmt.query_map((row_id,), |row| {
+        Ok(FieldSource {
+            field: row.get("field")?,
+            source: row.get("source")?,
+            revision: format!("{:0>4x}", row.get::<_, i64>("record_key")?),
+        })
+    })? {
+        let source = source?;
+        // the rows for each field are ordered from the most recent revision
+        if sources.last().is_none_or(|last| last.field != source.field) {
+            sources.push(source);
+        }
+    }
+    Ok(sources)
+}
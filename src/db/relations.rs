@@ -0,0 +1,98 @@
+//! # Relations between records
+//!
+//! A relation is stored in the `Relations` table as the canonical identifier of a record, the
+//! canonical identifier of the target of the relation, and the kind of the relation. Relations of
+//! the kinds `crossref` and `xdata` are recorded when importing entries whose fields are inherited
+//! from a parent, in which case the target is the parent. Other relations, such as `cites` or
+//! `translation-of`, are created with `autobib relate`.
+use serde::Serialize;
+
+use super::Tx;
+use crate::logger::debug;
+
+/// A relation between two records.
+#[derive(Debug, Clone, Serialize)]
+pub struct RelatedRecord {
+    /// The canonical identifier of the record.
+    pub canonical: String,
+    /// The kind of the relation.
+    pub kind: String,
+    /// The canonical identifier of the target of the relation.
+    pub target: String,
+}
+
+/// Record a relation, if it does not exist.
+pub(super) fn insert_relation(
+    tx: &Tx,
+    canonical: &str,
+    target: &str,
+    kind: &str,
+) -> Result<(), rusqlite::Error> {
+    debug!("Recording relation '{canonical}' {kind} '{target}'");
+    tx.prepare_cached(
+        "INSERT OR IGNORE INTO Relations (record_id, target, kind) VALUES (?1, ?2, ?3)",
+    )?
+    .execute((canonical, target, kind))?;
+    Ok(())
+}
+
+/// Delete a relation, returning `true` if the relation existed.
+pub(super) fn delete_relation(
+    tx: &Tx,
+    canonical: &str,
+    target: &str,
+    kind: &str,
+) -> Result<bool, rusqlite::Error> {
+    let num_deleted = tx
+        .prepare_cached("DELETE FROM Relations WHERE record_id = ?1 AND target = ?2 AND kind = ?3")?
+        .execute((canonical, target, kind))?;
+    Ok(num_deleted > 0)
+}
+
+/// Delete every relation from or to the record, returning the number of rows which were deleted.
+pub(super) fn delete_record_relations(tx: &Tx, canonical: &str) -> Result<usize, rusqlite::Error> {
+    debug!("Deleting the relations of '{canonical}'");
+    tx.prepare_cached("DELETE FROM Relations WHERE record_id = ?1 OR target = ?1")?
+        .execute((canonical,))
+}
+
+/// Replace `from` with `to` in every relation. Relations which already exist for `to`, and
+/// relations from `to` to itself, are deleted.
+pub(super) fn migrate_relations(tx: &Tx, from: &str, to: &str) -> Result<(), rusqlite::Error> {
+    tx.prepare("UPDATE OR IGNORE Relations SET record_id = ?2 WHERE record_id = ?1")?
+        .execute((from, to))?;
+    tx.prepare("UPDATE OR IGNORE Relations SET target = ?2 WHERE target = ?1")?
+        .execute((from, to))?;
+    let num_deleted = tx
+        .prepare("DELETE FROM Relations WHERE record_id = ?1 OR target = ?1 OR record_id = target")?
+        .execute((from,))?;
+    debug!("Migrated relations from '{from}' to '{to}', deleting {num_deleted} duplicates");
+    Ok(())
+}
+
+/// The relations from and to the record, ordered by record, target, and kind.
+pub(super) fn load_relations(
+    tx: &Tx,
+    canonical: &str,
+) -> Result<Vec<RelatedRecord>, rusqlite::Error> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT record_id, target, kind FROM Relations WHERE record_id = ?1 OR target = ?1 ORDER BY record_id, target, kind",
+    )?;
+    stmt.query_map((canonical,), |row| {
+        Ok(RelatedRecord {
+            canonical: row.get("record_id")?,
+            kind: row.get("kind")?,
+            target: row.get("target")?,
+        })
+    })?
+    .collect()
+}
+
+/// The parents of the record, ordered by identifier.
+pub(super) fn load_parents(tx: &Tx, canonical: &str) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT DISTINCT target FROM Relations WHERE record_id = ?1 AND kind IN ('crossref', 'xdata') ORDER BY target",
+    )?;
+    stmt.query_map((canonical,), |row| row.get("target"))?
+        .collect()
+}
@@ -21,3 +21,62 @@ schema!(records, "The table which stores record data.");
 schema!(null_records, "The table which caches null records.");
 
 schema!(create_indices, "Create indices for the tables.");
+
+schema!(
+    alias_links,
+    "The lookup table for aliases which refer to other aliases."
+);
+
+schema!(attachments, "The table which stores attachment metadata");
+
+schema!(
+    runs,
+    "The table which stores the commands which changed records"
+);
+
+schema!(
+    run_changes,
+    "The table which stores the changes to records made by each command"
+);
+
+schema!(
+    provenance,
+    "The table which stores where the data of each revision was retrieved from"
+);
+
+schema!(
+    field_sources,
+    "The table which stores the source of the fields supplied by each merge"
+);
+
+schema!(
+    macros,
+    "The table which stores the abbreviations defined by `@string` entries"
+);
+
+schema!(
+    relations,
+    "The table which stores the relations between records"
+);
+
+schema!(tags, "The table which stores the tags of each record");
+
+schema!(
+    collections,
+    "The table which stores the names of the collections"
+);
+
+schema!(
+    collection_records,
+    "The table which stores the records in each collection"
+);
+
+schema!(
+    overrides,
+    "The table which stores the overridden fields of records in output files"
+);
+
+schema!(
+    added,
+    "The table which stores the time at which each record was added"
+);
@@ -0,0 +1,4 @@
+CREATE TABLE Added (
+    record_id TEXT NOT NULL PRIMARY KEY,
+    added TEXT NOT NULL
+) STRICT, WITHOUT ROWID
@@ -0,0 +1,3 @@
+CREATE TABLE Collections (
+    name TEXT NOT NULL PRIMARY KEY
+) STRICT
@@ -0,0 +1,6 @@
+CREATE TABLE FieldSources (
+    record_key INTEGER NOT NULL REFERENCES Records(key) ON DELETE CASCADE,
+    field TEXT NOT NULL,
+    source TEXT NOT NULL,
+    PRIMARY KEY (record_key, field)
+) STRICT
@@ -0,0 +1,4 @@
+CREATE TABLE Macros (
+    name TEXT NOT NULL PRIMARY KEY,
+    value TEXT NOT NULL
+) STRICT
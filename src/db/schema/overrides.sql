@@ -0,0 +1,7 @@
+CREATE TABLE Overrides (
+    record_id TEXT NOT NULL,
+    scope TEXT NOT NULL,
+    field TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (record_id, scope, field)
+) STRICT, WITHOUT ROWID
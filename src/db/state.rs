@@ -36,13 +36,16 @@ mod version;
 use rusqlite::{CachedStatement, Error, Statement};
 
 pub use self::{borrow::ArbitraryDataRef, disp::*, missing::*, null::*, record::*, version::*};
-use super::{RowId, Tx, get_null_row_id, get_row_id};
+use super::{
+    AttachmentRow, RowId, Tx, get_linked_row_id, get_null_row_id, get_row_id, insert_attachment,
+    overlay,
+};
 use crate::{
     Alias, AliasOrRemoteId, MappedKey, RecordId, RemoteId,
     config::AliasTransform,
     entry::RawEntryData,
-    error::RecordError,
-    logger::{debug, error, reraise},
+    error::{FailureKind, RecordError},
+    logger::{debug, error, reraise_as},
 };
 
 /// A representation of the current database state corresponding to a [`RecordId`].
@@ -79,6 +82,12 @@ impl<'conn, I> State<'conn, I> {
         Self::init(tx, id)
     }
 
+    /// Insert the metadata of an attachment into the `Attachments` table, as part of the
+    /// transaction.
+    pub fn insert_attachment(&self, attachment: &AttachmentRow) -> Result<(), Error> {
+        insert_attachment(&self.tx, attachment)
+    }
+
     /// Prepare the SQL statement for execution.
     fn prepare(&self, sql: &'static str) -> Result<Statement<'_>, Error> {
         self.tx.prepare(sql)
@@ -240,6 +249,17 @@ impl<'conn> RecordIdState<'conn> {
                 )
             }
             Ok(AliasOrRemoteId::Alias(alias, maybe_mapped)) => {
+                // check if the alias refers to another alias
+                if let Some(row_id) = get_linked_row_id(&tx, &alias)? {
+                    return Self::existent(tx, row_id, alias);
+                }
+
+                // an alias defined in an overlay database takes precedence over the mapped value
+                let maybe_mapped = match overlay::lookup(&tx, &alias)? {
+                    Some(row) => Some(row.canonical),
+                    None => maybe_mapped,
+                };
+
                 // check the mapped value, if mapped
                 match maybe_mapped {
                     Some(remote_id) => {
@@ -292,7 +312,7 @@ impl<'conn> RecordIdState<'conn> {
             Self::Void(s, data, state) => Some((s, DisambiguatedRecordRow::Void(data, state))),
             Self::NullRemoteId(mapped_key, state) => {
                 state.commit()?;
-                error!("Null remote id: {mapped_key}");
+                error!(kind = FailureKind::NullRecord; "Null remote id: {mapped_key}");
                 None
             }
             Self::Unknown(unknown) => {
@@ -305,7 +325,7 @@ impl<'conn> RecordIdState<'conn> {
                 None
             }
             Self::InvalidRemoteId(record_error) => {
-                reraise(&record_error);
+                reraise_as(FailureKind::InvalidIdentifier, &record_error);
                 None
             }
         })
@@ -1,11 +1,12 @@
 //! # Display adapter for a record row
 //!
 //! The only struct here is [`RecordRowDisplay`], which is used to display the data present in a
-//! row in the 'Records' table.
+//! row in the 'Records' table, either as text or serialized as JSON.
 use std::fmt;
 
 use chrono::{DateTime, Local};
 use crossterm::style::{ContentStyle, StyledContent, Stylize};
+use serde::ser::{Serialize, SerializeMap, Serializer};
 
 use super::{ArbitraryDataRef, InRecordsTable, RecordRow, RevisionId, State, Version};
 use crate::{entry::EntryData, logger::LogDisplay, record::RemoteId};
@@ -125,3 +126,39 @@ impl<'a> fmt::Display for RecordRowDisplay<'a> {
         }
     }
 }
+
+/// Serialize the fields of entry data as a map.
+struct FieldsMap<'a, D>(&'a D);
+
+impl<D: EntryData> Serialize for FieldsMap<'_, D> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.fields())
+    }
+}
+
+impl<'a> Serialize for RecordRowDisplay<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("revision", &self.rev_id)?;
+        map.serialize_entry("canonical", &self.canonical.to_string())?;
+        map.serialize_entry("modified", &self.modified)?;
+        match &self.data {
+            ArbitraryDataRef::Entry(raw_entry_data) => {
+                map.serialize_entry("status", "entry")?;
+                map.serialize_entry("entry_type", raw_entry_data.entry_type())?;
+                map.serialize_entry("fields", &FieldsMap(raw_entry_data))?;
+            }
+            ArbitraryDataRef::Deleted(replacement) => {
+                map.serialize_entry("status", "deleted")?;
+                map.serialize_entry(
+                    "replacement",
+                    &replacement.as_ref().map(ToString::to_string),
+                )?;
+            }
+            ArbitraryDataRef::Void => {
+                map.serialize_entry("status", "void")?;
+            }
+        }
+        map.end()
+    }
+}
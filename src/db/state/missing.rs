@@ -1,7 +1,12 @@
 use chrono::Local;
 
-use super::{IsEntry, IsNull, NotEntry, State};
-use crate::{RawEntryData, RemoteId, db::Identifier, entry::EntryData, logger::debug};
+use super::{IsEntry, IsNull, NotEntry, RecordRow, State};
+use crate::{
+    RawEntryData, RemoteId,
+    db::{Identifier, added, overlay},
+    entry::EntryData,
+    logger::debug,
+};
 
 /// Types which know how to insert new data.
 ///
@@ -47,6 +52,15 @@ impl<'conn> RecordsInsert<'conn> for State<'conn, IsEntry> {
 pub struct IsMissing;
 
 impl<'conn> State<'conn, IsMissing> {
+    /// Look up the remote identifier in the overlay databases, returning the active record of the
+    /// first overlay which contains it.
+    pub fn overlay_record(
+        &self,
+        remote_id: &RemoteId,
+    ) -> Result<Option<RecordRow<RawEntryData>>, rusqlite::Error> {
+        overlay::lookup(&self.tx, remote_id)
+    }
+
     /// Set a null row, converting into the [`IsNull`] state.
     pub fn set_null(self, remote_id: &RemoteId) -> Result<State<'conn, IsNull>, rusqlite::Error> {
         let row_id: i64 = {
@@ -69,10 +83,12 @@ impl<'conn> State<'conn, IsMissing> {
         refs: R,
     ) -> Result<State<'conn, IsEntry>, rusqlite::Error> {
         debug!("Inserting data for canonical id '{canonical}'");
+        let now = Local::now();
         let row_id: i64 = self.prepare_cached("INSERT OR ABORT INTO Records (record_id, data, modified) values (?1, ?2, ?3) RETURNING key")?.query_row(
-            (canonical.name(), data.to_byte_repr(), &Local::now()),
+            (canonical.name(), data.to_byte_repr(), &now),
             |row| row.get(0),
         )?;
+        added::set_added(&self.tx, canonical.name(), &now)?;
         let row = State::init(self.tx, IsEntry(row_id));
         row.add_refs(refs)?;
         Ok(row)
@@ -5,7 +5,18 @@ use rusqlite::{OptionalExtension, Row};
 
 use crate::{
     Alias, RawEntryData, RemoteId,
-    db::{Constraint, Identifier, flatten_constraint_violation, get_row_id},
+    db::{
+        Constraint, FieldSource, Identifier, Provenance, RelatedRecord, RevisionProvenance,
+        added::{delete_added, load_added},
+        collections::delete_record_collections,
+        flatten_constraint_violation, get_link_target, get_linked_row_id, get_row_id,
+        overrides::delete_record_overrides,
+        provenance::{
+            insert_field_sources, insert_provenance, load_field_sources, load_provenance,
+        },
+        relations::{delete_record_relations, load_relations},
+        tags::delete_record_tags,
+    },
     logger::{debug, info},
 };
 
@@ -355,6 +366,12 @@ fn get_canonical(tx: &Tx, row_id: i64) -> rusqlite::Result<RemoteId> {
         })
 }
 
+/// Get the key of the parent row, if any.
+fn get_parent_key(tx: &Tx, row_id: i64) -> rusqlite::Result<Option<i64>> {
+    tx.prepare_cached("SELECT parent_key FROM Records WHERE key = ?1")?
+        .query_row([row_id], |row| row.get("parent_key"))
+}
+
 /// Get the last modified time.
 fn get_last_modified(tx: &Tx, row_id: i64) -> rusqlite::Result<DateTime<Local>> {
     tx.prepare_cached("SELECT modified FROM Records WHERE key = ?1")?
@@ -389,26 +406,53 @@ pub enum SetActiveError {
     DifferentCanonical(RemoteId),
 }
 
+/// A description of the state which prevented a squash operation from completing.
+pub enum SquashError {
+    /// The revision does not exist, or corresponds to a different record.
+    RevisionUndefined(RevisionId),
+    /// The first revision is not an ancestor of the second revision.
+    NotAncestor,
+    /// A revision which would be removed is active.
+    Active(RevisionId),
+    /// A revision which would be removed has changes which are not part of the chain.
+    Divergent(RevisionId),
+}
+
 impl<'conn, I: InRecordsTable> State<'conn, I> {
     pub(in crate::db) fn row_id(&self) -> i64 {
         self.id.row_id()
     }
 
     /// Hard delete the row. This deletes every entry in the 'Records' with the same canonical
-    /// identifier as the current row.
+    /// identifier as the current row, along with the data which is keyed by the canonical
+    /// identifier, so that it does not reappear if the record is retrieved again.
     pub fn delete_hard(self) -> Result<State<'conn, IsMissing>, rusqlite::Error> {
         debug!(
             "Permanently deleting all rows in the edit-tree associated with the id '{}'",
             self.row_id()
         );
+        let canonical = self.canonical()?;
         self.prepare(
             "DELETE FROM Records WHERE record_id IN (SELECT record_id FROM Records WHERE key = ?1);",
         )?
         .execute((self.row_id(),))?;
+        delete_record_overrides(&self.tx, canonical.name())?;
+        delete_record_relations(&self.tx, canonical.name())?;
+        delete_record_collections(&self.tx, canonical.name())?;
+        delete_record_tags(&self.tx, canonical.name())?;
+        delete_added(&self.tx, canonical.name())?;
 
         Ok(State::init(self.tx, IsMissing))
     }
 
+    /// Get the number of rows in the edit-tree associated with the current row.
+    pub fn num_revisions(&self) -> Result<usize, rusqlite::Error> {
+        self.prepare(
+            "SELECT COUNT(*) FROM Records WHERE record_id IN (SELECT record_id FROM Records WHERE key = ?1);",
+        )?
+        .query_row((self.row_id(),), |row| row.get(0))
+    }
+
     /// Unchecked conversion with a new row id of any type, updating the rows in the Identifiers table.
     fn transmute<N: FromRowId>(self, new_row_id: i64) -> rusqlite::Result<State<'conn, N>> {
         self.update_identifier_lookup(new_row_id)?;
@@ -443,6 +487,32 @@ impl<'conn, I: InRecordsTable> State<'conn, I> {
         get_last_modified(&self.tx, self.row_id())
     }
 
+    /// Get the time at which the record was first added to the database, if known.
+    pub fn added(&self) -> Result<Option<DateTime<Local>>, rusqlite::Error> {
+        debug!("Getting added time for row '{}'.", self.row_id());
+        load_added(&self.tx, self.canonical()?.name())
+    }
+
+    /// Get the provenance of this row and all of its ancestors, starting with the most recent
+    /// revision.
+    pub fn provenance(&self) -> Result<Vec<RevisionProvenance>, rusqlite::Error> {
+        debug!("Getting provenance for row '{}'.", self.row_id());
+        load_provenance(&self.tx, self.row_id())
+    }
+
+    /// Get the most recent source of every field which was supplied by merging data, considering
+    /// this row and all of its ancestors.
+    pub fn field_sources(&self) -> Result<Vec<FieldSource>, rusqlite::Error> {
+        debug!("Getting field sources for row '{}'.", self.row_id());
+        load_field_sources(&self.tx, self.row_id())
+    }
+
+    /// Get the relations from and to the record.
+    pub fn relations(&self) -> Result<Vec<RelatedRecord>, rusqlite::Error> {
+        debug!("Getting relations for row '{}'.", self.row_id());
+        load_relations(&self.tx, self.canonical()?.name())
+    }
+
     /// Obtain the complete data for this row.
     pub fn get_complete_data(&self) -> rusqlite::Result<CompleteRecordRow<I::Data>> {
         debug!(
@@ -496,6 +566,68 @@ impl<'conn, I: InRecordsTable> State<'conn, I> {
         self.transmute(new_id)
     }
 
+    /// Collapse the chain of revisions beginning at `from` and ending at `to` into a single
+    /// revision, which has the data of `to` and the parent of `from`.
+    ///
+    /// The revisions in the chain other than `to` are deleted, so they must not be active and
+    /// they must not have any children outside the chain. On success, returns the number of
+    /// revisions which were deleted.
+    pub fn squash(
+        &self,
+        from: RevisionId,
+        to: RevisionId,
+    ) -> rusqlite::Result<Result<usize, SquashError>> {
+        debug!(
+            "Squashing revisions from '{}' to '{}' for '{}'.",
+            from.0,
+            to.0,
+            self.row_id()
+        );
+        let self_canonical = self.canonical()?;
+        for rev in [from, to] {
+            if get_canonical(&self.tx, rev.0).optional()?.as_ref() != Some(&self_canonical) {
+                return Ok(Err(SquashError::RevisionUndefined(rev)));
+            }
+        }
+
+        // walk from `to` towards the root until we find `from`
+        let mut chain = Vec::new();
+        let mut current = to.0;
+        while current != from.0 {
+            match get_parent_key(&self.tx, current)? {
+                Some(parent) => {
+                    chain.push(parent);
+                    current = parent;
+                }
+                None => return Ok(Err(SquashError::NotAncestor)),
+            }
+        }
+
+        let mut is_active =
+            self.prepare("SELECT EXISTS (SELECT 1 FROM Identifiers WHERE record_key = ?1)")?;
+        let mut num_children =
+            self.prepare("SELECT count(*) FROM Records WHERE parent_key = ?1")?;
+        for row_id in &chain {
+            if is_active.query_row([row_id], |row| row.get(0))? {
+                return Ok(Err(SquashError::Active(RevisionId(*row_id))));
+            }
+            if num_children.query_row([row_id], |row| row.get::<_, i64>(0))? != 1 {
+                return Ok(Err(SquashError::Divergent(RevisionId(*row_id))));
+            }
+        }
+
+        let new_parent = get_parent_key(&self.tx, from.0)?;
+        self.prepare("UPDATE Records SET parent_key = ?1 WHERE key = ?2")?
+            .execute((new_parent, to.0))?;
+
+        let mut deleter = self.prepare("DELETE FROM Records WHERE key = ?1")?;
+        for row_id in &chain {
+            deleter.execute([row_id])?;
+        }
+
+        Ok(Ok(chain.len()))
+    }
+
     /// Update the 'Identifiers' table by setting any rows which reference the current row to
     /// reference a new row id instead.
     fn update_identifier_lookup(&self, new_key: i64) -> Result<usize, rusqlite::Error> {
@@ -508,6 +640,27 @@ impl<'conn, I: InRecordsTable> State<'conn, I> {
         self.referencing_keys_impl(Some)
     }
 
+    /// Get every alias in the `AliasLinks` table which transitively refers to this row, along
+    /// with the alias that it refers to.
+    pub fn linked_aliases(&self) -> Result<Vec<(String, String)>, rusqlite::Error> {
+        debug!("Getting linked aliases for '{}'.", self.row_id());
+        let mut selector = self.prepare(
+            "WITH RECURSIVE chain(name, target) AS (
+                SELECT AliasLinks.name, AliasLinks.target FROM AliasLinks
+                    JOIN Identifiers ON AliasLinks.target = Identifiers.name
+                    WHERE Identifiers.record_key = ?1
+                UNION
+                SELECT AliasLinks.name, AliasLinks.target FROM AliasLinks
+                    JOIN chain ON AliasLinks.target = chain.name
+            )
+            SELECT name, target FROM chain",
+        )?;
+        let rows = selector.query_map((self.row_id(),), |row| {
+            Ok((row.get("name")?, row.get("target")?))
+        })?;
+        rows.collect()
+    }
+
     /// Get every remote id in the `Identifiers` table which references this row.
     pub fn referencing_remote_ids(&self) -> Result<Vec<RemoteId>, rusqlite::Error> {
         self.referencing_keys_impl(RemoteId::from_alias_or_remote_id_unchecked)
@@ -864,6 +1017,20 @@ macro_rules! impl_row_from {
 impl_row_from!(RawEntryData, Option<RemoteId>, ());
 
 impl<'conn> State<'conn, IsEntry> {
+    /// Record where the data of this row was retrieved from.
+    pub fn set_provenance(&self, provenance: &Provenance) -> Result<(), rusqlite::Error> {
+        insert_provenance(&self.tx, self.row_id(), provenance)
+    }
+
+    /// Record that the provided fields of this row were supplied by `source`.
+    pub fn set_field_sources<'a>(
+        &self,
+        fields: impl IntoIterator<Item = &'a str>,
+        source: &str,
+    ) -> Result<(), rusqlite::Error> {
+        insert_field_sources(&self.tx, self.row_id(), fields, source)
+    }
+
     /// Insert new data, preserving the old row as the parent row.
     pub fn modify(self, data: &RawEntryData) -> Result<Self, rusqlite::Error> {
         let new_key = self.replace_impl(data)?;
@@ -934,9 +1101,13 @@ RETURNING key",
 
     /// Add a new alias for this row.
     ///
-    /// The return value is `false` if the alias already exists, and otherwise `true`.
+    /// The return value is `false` if the alias already exists, either as an identifier or as a
+    /// link to another alias, and otherwise `true`.
     #[inline]
     pub fn add_alias(&self, alias: &Alias) -> Result<bool, rusqlite::Error> {
+        if get_link_target(&self.tx, alias)?.is_some() {
+            return Ok(false);
+        }
         self.add_refs_impl(std::iter::once(alias), IdentifierInsertMode::FailIfExists)
     }
 
@@ -954,18 +1125,29 @@ RETURNING key",
     /// Ensure that the given alias exists for this row.
     ///
     /// If the alias already exists and points to a different row, the canonical id of the other row is returned.
+    /// If the alias is a link to another alias which does not refer to this row, the target of the
+    /// link is returned.
     #[inline]
-    pub fn ensure_alias(&self, alias: &Alias) -> Result<Option<RemoteId>, rusqlite::Error> {
+    pub fn ensure_alias(&self, alias: &Alias) -> Result<Option<String>, rusqlite::Error> {
         debug!(
             "Ensuring alias '{alias}' refers to row_id '{}'",
             self.row_id()
         );
+        if let Some(target) = get_link_target(&self.tx, alias)? {
+            return if get_linked_row_id(&self.tx, alias)? == Some(self.row_id()) {
+                Ok(None)
+            } else {
+                Ok(Some(target))
+            };
+        }
         match get_row_id(&self.tx, alias)? {
             Some(existing_row_id) => {
                 if existing_row_id == self.row_id() {
                     Ok(None)
                 } else {
-                    Ok(Some(self.canonical()?))
+                    self.prepare("SELECT record_id FROM Records WHERE key = ?1")?
+                        .query_row((existing_row_id,), |row| row.get("record_id"))
+                        .map(Some)
                 }
             }
             None => {
@@ -1,6 +1,7 @@
 use std::{fmt, str::FromStr};
 
 use rusqlite::types::{FromSql, FromSqlError, ValueRef};
+use serde::{Serialize, Serializer};
 
 use super::{
     ArbitraryData, CompleteRecordRow, InRecordsTable, RecordRow, RecordRowDisplay, State, Tx,
@@ -17,7 +18,7 @@ pub struct Version<'tx, 'conn> {
     parent_row_id: Option<i64>,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct RevisionId(pub(in crate::db) i64);
 
 impl FromSql for RevisionId {
@@ -36,6 +37,12 @@ impl fmt::Display for RevisionId {
     }
 }
 
+impl Serialize for RevisionId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{:0>4x}", self.0))
+    }
+}
+
 impl FromStr for RevisionId {
     type Err = std::num::ParseIntError;
 
@@ -109,6 +116,11 @@ impl<'tx, 'conn> Version<'tx, 'conn> {
         RevisionId(self.row_id)
     }
 
+    /// Get the revision of the parent row, if any.
+    pub fn parent_rev_id(&self) -> Option<RevisionId> {
+        self.parent_row_id.map(RevisionId)
+    }
+
     /// The number of children.
     pub fn num_children(&self) -> rusqlite::Result<usize> {
         self.tx
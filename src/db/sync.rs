@@ -0,0 +1,743 @@
+//! # Synchronization between databases
+//!
+//! Two databases are synchronized by copying the revisions of every record which are missing
+//! from one database into the other, so that afterwards both databases contain the same edit
+//! tree for each record. Since the `key` of a revision is only meaningful within a single
+//! database, revisions are matched by their content, that is, by their data and the time at
+//! which they were modified. Revisions copied by a previous synchronization have the same content
+//! in both databases, and therefore record the common history of the two databases.
+//!
+//! The active revision of a record is then determined as follows.
+//!
+//! 1. If the active revision in one database is an ancestor of the active revision in the other
+//!    database, the newer revision is made active in both databases.
+//! 2. Otherwise, the changes made in each database relative to the most recent common revision
+//!    are merged field by field, and the merged data is inserted as a new revision in both
+//!    databases. If a field (or the entry type) was changed in both databases to different
+//!    values, or if the record was deleted in one database and modified in the other, the record
+//!    is left unchanged and the conflict is reported.
+//!
+//! Identifiers and alias links which are only present in one database are added to the other
+//! database, as are tags, relations, collections along with their records, and field overrides.
+//! An override which has a different value in the two databases is reported as a conflict. Since removals are not recorded, identifiers and records which were removed from only
+//! one database are restored from the other database.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+};
+
+use chrono::Local;
+
+use super::{
+    RecordDatabase, Tx, added,
+    state::{AsRecordRowData, FromBytesAndVariant},
+};
+use crate::entry::{EntryData, MutableEntryData, RawEntryData};
+
+/// The content of a revision, which identifies the revision across databases.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Content {
+    modified: String,
+    variant: i64,
+    data: Vec<u8>,
+}
+
+impl Content {
+    /// Decode the entry data, if this is an entry revision.
+    fn entry_data(&self) -> Option<RawEntryData> {
+        (self.variant == 0)
+            .then(|| RawEntryData::from_bytes_and_variant(self.data.clone(), self.variant))
+    }
+
+    /// Whether or not the revisions have the same data, ignoring the modification time.
+    fn same_data(&self, other: &Self) -> bool {
+        self.variant == other.variant && self.data == other.data
+    }
+}
+
+/// A single revision in the edit tree of a record.
+#[derive(Debug)]
+struct Revision {
+    parent: Option<i64>,
+    content: Content,
+}
+
+/// The edit tree of a record in a single database.
+#[derive(Debug, Default)]
+struct Tree {
+    /// The revisions, indexed by their key.
+    revisions: BTreeMap<i64, Revision>,
+    /// The key of the revision with the given content.
+    keys: HashMap<Content, i64>,
+}
+
+impl Tree {
+    /// Load every revision of the record with the provided canonical identifier.
+    fn load(tx: &Tx, canonical: &str) -> rusqlite::Result<Self> {
+        let mut tree = Self::default();
+        let mut stmt = tx.prepare_cached(
+            "SELECT key, parent_key, modified, variant, data FROM Records WHERE record_id = ?1",
+        )?;
+        let mut rows = stmt.query([canonical])?;
+        while let Some(row) = rows.next()? {
+            let key = row.get("key")?;
+            let content = Content {
+                modified: row.get("modified")?,
+                variant: row.get("variant")?,
+                data: row.get("data")?,
+            };
+            tree.keys.entry(content.clone()).or_insert(key);
+            tree.revisions.insert(
+                key,
+                Revision {
+                    parent: row.get("parent_key")?,
+                    content,
+                },
+            );
+        }
+        Ok(tree)
+    }
+
+    /// Insert a revision into the database, returning its key.
+    fn insert(
+        &mut self,
+        tx: &Tx,
+        canonical: &str,
+        content: Content,
+        parent: Option<i64>,
+    ) -> rusqlite::Result<i64> {
+        let key = tx
+            .prepare_cached(
+                "INSERT INTO Records (record_id, data, modified, variant, parent_key) VALUES (?1, ?2, ?3, ?4, ?5) RETURNING key",
+            )?
+            .query_row(
+                (
+                    canonical,
+                    &content.data,
+                    &content.modified,
+                    content.variant,
+                    parent,
+                ),
+                |row| row.get(0),
+            )?;
+        self.keys.insert(content.clone(), key);
+        self.revisions.insert(key, Revision { parent, content });
+        Ok(key)
+    }
+
+    /// Insert new data into the database as a child of the revision with key `parent`, returning
+    /// the content of the new revision.
+    fn insert_now(
+        &mut self,
+        tx: &Tx,
+        canonical: &str,
+        data: &RawEntryData,
+        parent: i64,
+    ) -> rusqlite::Result<Content> {
+        let (key, modified) = tx
+            .prepare_cached(
+                "INSERT INTO Records (record_id, data, modified, variant, parent_key) VALUES (?1, ?2, ?3, ?4, ?5) RETURNING key, modified",
+            )?
+            .query_row(
+                (
+                    canonical,
+                    data.data_blob(),
+                    Local::now(),
+                    data.variant(),
+                    parent,
+                ),
+                |row| Ok((row.get("key")?, row.get("modified")?)),
+            )?;
+        let content = Content {
+            modified,
+            variant: data.variant(),
+            data: data.data_blob().to_vec(),
+        };
+        self.keys.insert(content.clone(), key);
+        self.revisions.insert(
+            key,
+            Revision {
+                parent: Some(parent),
+                content: content.clone(),
+            },
+        );
+        Ok(content)
+    }
+
+    /// Copy the revisions of `other` which are missing from this tree into the database.
+    fn copy_missing(&mut self, tx: &Tx, canonical: &str, other: &Self) -> rusqlite::Result<()> {
+        // a revision always has a larger key than its parent, so the parent is copied first
+        for revision in other.revisions.values() {
+            if self.keys.contains_key(&revision.content) {
+                continue;
+            }
+            let parent = revision
+                .parent
+                .and_then(|key| other.revisions.get(&key))
+                .and_then(|parent| self.keys.get(&parent.content))
+                .copied();
+            self.insert(tx, canonical, revision.content.clone(), parent)?;
+        }
+        Ok(())
+    }
+
+    /// The contents of the revision with the provided key and all of its ancestors, starting
+    /// with the revision itself.
+    fn ancestors(&self, key: i64) -> Vec<&Content> {
+        let mut ancestors = Vec::new();
+        let mut next = Some(key);
+        while let Some(revision) = next.and_then(|key| self.revisions.get(&key)) {
+            ancestors.push(&revision.content);
+            next = revision.parent;
+        }
+        ancestors
+    }
+}
+
+/// The result of merging the changes to a record from both databases.
+enum Merged {
+    /// The local revision contains the changes from both databases.
+    Local,
+    /// The remote revision contains the changes from both databases.
+    Remote,
+    /// The changes from both databases were combined into new data.
+    New(RawEntryData),
+}
+
+/// Merge a single value which was changed relative to `base` in either database.
+fn merge_value<'a>(
+    base: Option<&'a str>,
+    local: Option<&'a str>,
+    remote: Option<&'a str>,
+) -> Result<Option<&'a str>, ()> {
+    if local == remote || remote == base {
+        Ok(local)
+    } else if local == base {
+        Ok(remote)
+    } else {
+        Err(())
+    }
+}
+
+/// Merge the changes to a record relative to the most recent common revision `base`, returning
+/// the names of the conflicting fields on failure.
+fn merge(base: Option<&Content>, local: &Content, remote: &Content) -> Result<Merged, Vec<String>> {
+    if local.same_data(remote) || base.is_some_and(|base| base.same_data(remote)) {
+        return Ok(Merged::Local);
+    } else if base.is_some_and(|base| base.same_data(local)) {
+        return Ok(Merged::Remote);
+    }
+
+    let (Some(local_data), Some(remote_data)) = (local.entry_data(), remote.entry_data()) else {
+        // a deleted or voided record cannot be merged with changes from the other database
+        return Err(Vec::new());
+    };
+    let base_data = base.and_then(Content::entry_data);
+
+    let mut conflicts = Vec::new();
+    let entry_type = match merge_value(
+        base_data.as_ref().map(EntryData::entry_type),
+        Some(local_data.entry_type()),
+        Some(remote_data.entry_type()),
+    ) {
+        Ok(entry_type) => entry_type.unwrap_or_else(|| local_data.entry_type()),
+        Err(()) => {
+            conflicts.push("entry type".to_owned());
+            local_data.entry_type()
+        }
+    };
+
+    // the entry type was validated when the data was inserted, so this cannot fail
+    let mut merged = MutableEntryData::try_new(entry_type.to_owned()).unwrap();
+    let keys: BTreeSet<&str> = local_data
+        .fields()
+        .chain(remote_data.fields())
+        .map(|(key, _)| key)
+        .collect();
+    for key in keys {
+        match merge_value(
+            base_data.as_ref().and_then(|data| data.get_field(key)),
+            local_data.get_field(key),
+            remote_data.get_field(key),
+        ) {
+            Ok(Some(value)) => {
+                // the field was validated when the data was inserted, so this cannot fail
+                merged
+                    .check_and_insert(key.to_owned(), value.to_owned())
+                    .unwrap();
+            }
+            Ok(None) => {}
+            Err(()) => conflicts.push(key.to_owned()),
+        }
+    }
+
+    if !conflicts.is_empty() {
+        return Err(conflicts);
+    }
+
+    let merged = RawEntryData::from_entry_data(&merged);
+    if merged.data_blob() == local.data {
+        Ok(Merged::Local)
+    } else if merged.data_blob() == remote.data {
+        Ok(Merged::Remote)
+    } else {
+        Ok(Merged::New(merged))
+    }
+}
+
+/// A change made in both databases which could not be merged automatically.
+#[derive(Debug)]
+pub enum SyncConflict {
+    /// A record was changed in both databases.
+    Record {
+        /// The canonical identifier of the record.
+        canonical: String,
+        /// The conflicting fields. This is empty if the record was deleted in one of the
+        /// databases.
+        fields: Vec<String>,
+    },
+    /// A field override has different values in the two databases.
+    Override {
+        /// The canonical identifier of the record.
+        canonical: String,
+        /// The scope of the override.
+        scope: String,
+        /// The overridden field.
+        field: String,
+    },
+    /// An identifier refers to different records in the two databases.
+    Identifier {
+        /// The identifier.
+        name: String,
+        /// The target of the identifier in the local database.
+        local: String,
+        /// The target of the identifier in the remote database.
+        remote: String,
+    },
+}
+
+impl fmt::Display for SyncConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Record { canonical, fields } if fields.is_empty() => write!(
+                f,
+                "Record '{canonical}' was deleted in one database and modified in the other"
+            ),
+            Self::Record { canonical, fields } => write!(
+                f,
+                "Record '{canonical}' has conflicting changes to: {}",
+                fields.join(", ")
+            ),
+            Self::Override {
+                canonical,
+                scope,
+                field,
+            } => write!(
+                f,
+                "Override of field '{field}' of record '{canonical}' in scope '{scope}' has different values in the two databases"
+            ),
+            Self::Identifier {
+                name,
+                local,
+                remote,
+            } => write!(
+                f,
+                "Identifier '{name}' refers to '{local}' locally and to '{remote}' in the remote database"
+            ),
+        }
+    }
+}
+
+/// A summary of the changes made by a synchronization.
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    /// The canonical identifiers of the records which were updated in the local database.
+    pub pulled: Vec<String>,
+    /// The canonical identifiers of the records which were updated in the remote database.
+    pub pushed: Vec<String>,
+    /// The canonical identifiers of the records whose changes were merged.
+    pub merged: Vec<String>,
+    /// The number of identifiers and alias links which were added to either database.
+    pub identifiers: usize,
+    /// The number of tags which were added to either database.
+    pub tags: usize,
+    /// The number of relations which were added to either database.
+    pub relations: usize,
+    /// The number of records which were added to collections in either database.
+    pub collection_records: usize,
+    /// The number of field overrides which were added to either database.
+    pub overrides: usize,
+    /// The changes which could not be merged.
+    pub conflicts: Vec<SyncConflict>,
+}
+
+/// The target of a name in the `Identifiers` or the `AliasLinks` table.
+#[derive(Debug, PartialEq, Eq)]
+enum Target {
+    /// The canonical identifier of the record in the `Identifiers` table.
+    Record(String),
+    /// The target of the alias in the `AliasLinks` table.
+    Link(String),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Record(name) | Self::Link(name) => f.write_str(name),
+        }
+    }
+}
+
+/// Get the key of the active revision of every record, indexed by the canonical identifier.
+fn active_keys(tx: &Tx) -> rusqlite::Result<BTreeMap<String, i64>> {
+    let mut stmt = tx.prepare(
+        "SELECT record_id, key FROM Records WHERE key IN (SELECT record_key FROM Identifiers)",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut active = BTreeMap::new();
+    while let Some(row) = rows.next()? {
+        active.insert(row.get("record_id")?, row.get("key")?);
+    }
+    Ok(active)
+}
+
+/// Get the target of every name in the `Identifiers` and the `AliasLinks` tables.
+fn targets(tx: &Tx) -> rusqlite::Result<BTreeMap<String, Target>> {
+    let mut targets = BTreeMap::new();
+
+    let mut stmt = tx.prepare(
+        "SELECT i.name, r.record_id FROM Identifiers AS i INNER JOIN Records AS r ON i.record_key = r.key",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        targets.insert(row.get(0)?, Target::Record(row.get(1)?));
+    }
+
+    let mut stmt = tx.prepare("SELECT name, target FROM AliasLinks")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        targets.insert(row.get(0)?, Target::Link(row.get(1)?));
+    }
+
+    Ok(targets)
+}
+
+/// Make the revision with key `new` active in place of the revision with key `old`.
+fn set_active(tx: &Tx, old: i64, new: i64) -> rusqlite::Result<()> {
+    tx.prepare_cached("UPDATE Identifiers SET record_key = ?1 WHERE record_key = ?2")?
+        .execute((new, old))?;
+    Ok(())
+}
+
+/// Add the names which are only present in `source` to the database, given the active
+/// revision of each record in the database.
+fn add_missing_targets(
+    tx: &Tx,
+    source: &BTreeMap<String, Target>,
+    existing: &BTreeMap<String, Target>,
+    active: &BTreeMap<String, i64>,
+) -> rusqlite::Result<usize> {
+    let mut added = 0;
+    for (name, target) in source {
+        if existing.contains_key(name) {
+            continue;
+        }
+        match target {
+            Target::Record(canonical) => {
+                if let Some(key) = active.get(canonical) {
+                    tx.prepare_cached(
+                        "INSERT INTO Identifiers (name, record_key) VALUES (?1, ?2)",
+                    )?
+                    .execute((name, key))?;
+                    added += 1;
+                }
+            }
+            Target::Link(alias) => {
+                tx.prepare_cached("INSERT INTO AliasLinks (name, target) VALUES (?1, ?2)")?
+                    .execute((name, alias))?;
+                added += 1;
+            }
+        }
+    }
+    Ok(added)
+}
+
+/// Copy the rows of the table which are only present in the `source` database into the `target`
+/// database, returning the number of rows which were copied. Rows whose primary key is already
+/// present in the `target` database are not copied.
+fn copy_missing_rows(
+    source: &Tx,
+    target: &Tx,
+    table: &str,
+    columns: &[&str],
+) -> rusqlite::Result<usize> {
+    let column_list = columns.join(", ");
+    let placeholders: Vec<String> = (1..=columns.len()).map(|idx| format!("?{idx}")).collect();
+    let mut select = source.prepare(&format!("SELECT {column_list} FROM {table}"))?;
+    let mut insert = target.prepare(&format!(
+        "INSERT OR IGNORE INTO {table} ({column_list}) VALUES ({})",
+        placeholders.join(", ")
+    ))?;
+
+    let mut copied = 0;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        let values = (0..columns.len())
+            .map(|idx| row.get::<_, String>(idx))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        copied += insert.execute(rusqlite::params_from_iter(values))?;
+    }
+    Ok(copied)
+}
+
+/// Get the value of every field override, keyed by the canonical identifier, the scope, and the
+/// field.
+fn overrides(tx: &Tx) -> rusqlite::Result<BTreeMap<(String, String, String), String>> {
+    let mut stmt = tx.prepare("SELECT record_id, scope, field, value FROM Overrides")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            (row.get("record_id")?, row.get("scope")?, row.get("field")?),
+            row.get("value")?,
+        ))
+    })?;
+    rows.collect()
+}
+
+impl RecordDatabase {
+    /// Synchronize this database with the `remote` database, so that both databases contain the
+    /// changes made in either database.
+    ///
+    /// The changes to each database are made in a single transaction, and the remote database is
+    /// committed first.
+    pub fn sync_with(&mut self, remote: &mut Self) -> Result<SyncReport, rusqlite::Error> {
+        let local_tx = self.transaction()?;
+        let remote_tx = remote.transaction()?;
+        let mut report = SyncReport::default();
+
+        let mut local_active = active_keys(&local_tx)?;
+        let mut remote_active = active_keys(&remote_tx)?;
+        let canonicals: BTreeSet<String> = local_active
+            .keys()
+            .chain(remote_active.keys())
+            .cloned()
+            .collect();
+
+        for canonical in canonicals {
+            let mut local_tree = Tree::load(&local_tx, &canonical)?;
+            let mut remote_tree = Tree::load(&remote_tx, &canonical)?;
+            local_tree.copy_missing(&local_tx, &canonical, &remote_tree)?;
+            remote_tree.copy_missing(&remote_tx, &canonical, &local_tree)?;
+
+            // the record was added when it was first added to either database
+            let local_added = added::load_added(&local_tx, &canonical)?;
+            let remote_added = added::load_added(&remote_tx, &canonical)?;
+            if let Some(first_added) = local_added.into_iter().chain(remote_added).min() {
+                added::merge_added(&local_tx, &canonical, &first_added)?;
+                added::merge_added(&remote_tx, &canonical, &first_added)?;
+            }
+
+            match (
+                local_active.get(&canonical).copied(),
+                remote_active.get(&canonical).copied(),
+            ) {
+                (Some(local_key), None) => {
+                    let content = &local_tree.revisions[&local_key].content;
+                    remote_active.insert(canonical.clone(), remote_tree.keys[content]);
+                    report.pushed.push(canonical);
+                }
+                (None, Some(remote_key)) => {
+                    let content = &remote_tree.revisions[&remote_key].content;
+                    local_active.insert(canonical.clone(), local_tree.keys[content]);
+                    report.pulled.push(canonical);
+                }
+                (Some(local_key), Some(remote_key)) => {
+                    let local_ancestors = local_tree.ancestors(local_key);
+                    let remote_ancestors = remote_tree.ancestors(remote_key);
+                    let (local_content, remote_content) = (local_ancestors[0], remote_ancestors[0]);
+                    if local_content == remote_content {
+                        continue;
+                    }
+
+                    let merged = if local_ancestors.contains(&remote_content) {
+                        Ok(Merged::Local)
+                    } else if remote_ancestors.contains(&local_content) {
+                        Ok(Merged::Remote)
+                    } else {
+                        let base = local_ancestors
+                            .iter()
+                            .copied()
+                            .find(|content| remote_ancestors.contains(content));
+                        merge(base, local_content, remote_content)
+                    };
+
+                    match merged {
+                        Ok(Merged::Local) => {
+                            let new_key = remote_tree.keys[local_content];
+                            set_active(&remote_tx, remote_key, new_key)?;
+                            remote_active.insert(canonical.clone(), new_key);
+                            report.pushed.push(canonical);
+                        }
+                        Ok(Merged::Remote) => {
+                            let new_key = local_tree.keys[remote_content];
+                            set_active(&local_tx, local_key, new_key)?;
+                            local_active.insert(canonical.clone(), new_key);
+                            report.pulled.push(canonical);
+                        }
+                        Ok(Merged::New(data)) => {
+                            // the merged revision is a child of the local revision in both databases
+                            let parent = remote_tree.keys[local_content];
+                            let content =
+                                local_tree.insert_now(&local_tx, &canonical, &data, local_key)?;
+                            let new_local_key = local_tree.keys[&content];
+                            let new_remote_key = remote_tree.insert(
+                                &remote_tx,
+                                &canonical,
+                                content,
+                                Some(parent),
+                            )?;
+                            set_active(&local_tx, local_key, new_local_key)?;
+                            set_active(&remote_tx, remote_key, new_remote_key)?;
+                            local_active.insert(canonical.clone(), new_local_key);
+                            remote_active.insert(canonical.clone(), new_remote_key);
+                            report.merged.push(canonical);
+                        }
+                        Err(fields) => {
+                            report
+                                .conflicts
+                                .push(SyncConflict::Record { canonical, fields });
+                        }
+                    }
+                }
+                (None, None) => unreachable!(),
+            }
+        }
+
+        let local_targets = targets(&local_tx)?;
+        let remote_targets = targets(&remote_tx)?;
+        for (name, local) in &local_targets {
+            if let Some(remote) = remote_targets.get(name)
+                && local != remote
+            {
+                report.conflicts.push(SyncConflict::Identifier {
+                    name: name.clone(),
+                    local: local.to_string(),
+                    remote: remote.to_string(),
+                });
+            }
+        }
+        report.identifiers +=
+            add_missing_targets(&local_tx, &remote_targets, &local_targets, &local_active)?;
+        report.identifiers +=
+            add_missing_targets(&remote_tx, &local_targets, &remote_targets, &remote_active)?;
+
+        const TAG_COLUMNS: &[&str] = &["record_id", "tag"];
+        report.tags += copy_missing_rows(&remote_tx, &local_tx, "Tags", TAG_COLUMNS)?;
+        report.tags += copy_missing_rows(&local_tx, &remote_tx, "Tags", TAG_COLUMNS)?;
+
+        const RELATION_COLUMNS: &[&str] = &["record_id", "target", "kind"];
+        report.relations +=
+            copy_missing_rows(&remote_tx, &local_tx, "Relations", RELATION_COLUMNS)?;
+        report.relations +=
+            copy_missing_rows(&local_tx, &remote_tx, "Relations", RELATION_COLUMNS)?;
+
+        // the collections are copied first, since the records of a collection refer to it
+        copy_missing_rows(&remote_tx, &local_tx, "Collections", &["name"])?;
+        copy_missing_rows(&local_tx, &remote_tx, "Collections", &["name"])?;
+        const COLLECTION_RECORD_COLUMNS: &[&str] = &["collection", "record_id"];
+        report.collection_records += copy_missing_rows(
+            &remote_tx,
+            &local_tx,
+            "CollectionRecords",
+            COLLECTION_RECORD_COLUMNS,
+        )?;
+        report.collection_records += copy_missing_rows(
+            &local_tx,
+            &remote_tx,
+            "CollectionRecords",
+            COLLECTION_RECORD_COLUMNS,
+        )?;
+
+        let local_overrides = overrides(&local_tx)?;
+        let remote_overrides = overrides(&remote_tx)?;
+        for (key, local) in &local_overrides {
+            if let Some(remote) = remote_overrides.get(key)
+                && local != remote
+            {
+                let (canonical, scope, field) = key.clone();
+                report.conflicts.push(SyncConflict::Override {
+                    canonical,
+                    scope,
+                    field,
+                });
+            }
+        }
+        const OVERRIDE_COLUMNS: &[&str] = &["record_id", "scope", "field", "value"];
+        report.overrides +=
+            copy_missing_rows(&remote_tx, &local_tx, "Overrides", OVERRIDE_COLUMNS)?;
+        report.overrides +=
+            copy_missing_rows(&local_tx, &remote_tx, "Overrides", OVERRIDE_COLUMNS)?;
+
+        remote_tx.commit()?;
+        local_tx.commit()?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content(fields: &[(&str, &str)]) -> Content {
+        let mut data = MutableEntryData::try_new("article".to_owned()).unwrap();
+        for (key, value) in fields {
+            data.check_and_insert((*key).to_owned(), (*value).to_owned())
+                .unwrap();
+        }
+        Content {
+            modified: String::new(),
+            variant: 0,
+            data: RawEntryData::from_entry_data(&data).data_blob().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let base = content(&[("title", "A"), ("year", "2000")]);
+        let local = content(&[("title", "B"), ("year", "2000")]);
+        let remote = content(&[("title", "A"), ("year", "2001"), ("doi", "10.1000/x")]);
+
+        match merge(Some(&base), &local, &remote) {
+            Ok(Merged::New(data)) => {
+                assert_eq!(data.get_field("title"), Some("B"));
+                assert_eq!(data.get_field("year"), Some("2001"));
+                assert_eq!(data.get_field("doi"), Some("10.1000/x"));
+            }
+            _ => panic!("expected merged data"),
+        }
+
+        assert!(matches!(
+            merge(Some(&base), &local, &base),
+            Ok(Merged::Local)
+        ));
+        assert!(matches!(
+            merge(Some(&base), &base, &remote),
+            Ok(Merged::Remote)
+        ));
+
+        let conflicting = content(&[("title", "C"), ("year", "2000")]);
+        match merge(Some(&base), &local, &conflicting) {
+            Err(fields) => assert_eq!(fields, vec!["title".to_owned()]),
+            _ => panic!("expected conflict"),
+        }
+
+        // without a common revision, only fields present on one side can be merged
+        assert!(matches!(
+            merge(
+                None,
+                &content(&[("title", "A")]),
+                &content(&[("year", "2000")])
+            ),
+            Ok(Merged::New(_))
+        ));
+    }
+}
@@ -0,0 +1,68 @@
+//! # Tags of records
+//!
+//! A tag is a short label, such as `reading-list`, which is stored in the `Tags` table along with
+//! the canonical identifier of the record. Since the tags are keyed by the canonical identifier
+//! rather than by a revision, they are preserved when the data of the record changes, for instance
+//! by `autobib edit`, `autobib update`, or `autobib hist undo`.
+use super::Tx;
+use crate::logger::debug;
+
+/// Tag the record, returning `true` if the record did not already have the tag.
+pub(super) fn insert_tag(tx: &Tx, canonical: &str, tag: &str) -> Result<bool, rusqlite::Error> {
+    debug!("Tagging '{canonical}' with '{tag}'");
+    let num_inserted = tx
+        .prepare_cached("INSERT OR IGNORE INTO Tags (record_id, tag) VALUES (?1, ?2)")?
+        .execute((canonical, tag))?;
+    Ok(num_inserted > 0)
+}
+
+/// Remove the tag from the record, returning `true` if the record had the tag.
+pub(super) fn delete_tag(tx: &Tx, canonical: &str, tag: &str) -> Result<bool, rusqlite::Error> {
+    debug!("Removing tag '{tag}' from '{canonical}'");
+    let num_deleted = tx
+        .prepare_cached("DELETE FROM Tags WHERE record_id = ?1 AND tag = ?2")?
+        .execute((canonical, tag))?;
+    Ok(num_deleted > 0)
+}
+
+/// Delete every tag of the record, returning the number of rows which were deleted.
+pub(super) fn delete_record_tags(tx: &Tx, canonical: &str) -> Result<usize, rusqlite::Error> {
+    debug!("Deleting the tags of '{canonical}'");
+    tx.prepare_cached("DELETE FROM Tags WHERE record_id = ?1")?
+        .execute((canonical,))
+}
+
+/// Replace `from` with `to` in every tag. Tags which `to` already has are deleted.
+pub(super) fn migrate_tags(tx: &Tx, from: &str, to: &str) -> Result<(), rusqlite::Error> {
+    tx.prepare("UPDATE OR IGNORE Tags SET record_id = ?2 WHERE record_id = ?1")?
+        .execute((from, to))?;
+    let num_deleted = tx
+        .prepare("DELETE FROM Tags WHERE record_id = ?1")?
+        .execute((from,))?;
+    debug!("Migrated tags from '{from}' to '{to}', deleting {num_deleted} duplicates");
+    Ok(())
+}
+
+/// The tags of the record, in alphabetical order.
+pub(super) fn load_tags(tx: &Tx, canonical: &str) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = tx.prepare_cached("SELECT tag FROM Tags WHERE record_id = ?1 ORDER BY tag")?;
+    stmt.query_map((canonical,), |row| row.get("tag"))?
+        .collect()
+}
+
+/// Every tag along with the number of records with the tag, in alphabetical order.
+pub(super) fn load_tag_counts(tx: &Tx) -> Result<Vec<(String, usize)>, rusqlite::Error> {
+    let mut stmt =
+        tx.prepare_cached("SELECT tag, COUNT(*) AS num FROM Tags GROUP BY tag ORDER BY tag")?;
+    stmt.query_map([], |row| Ok((row.get("tag")?, row.get("num")?)))?
+        .collect()
+}
+
+/// The canonical identifiers of the records in the database with the tag, ordered by identifier.
+pub(super) fn load_tagged(tx: &Tx, tag: &str) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT record_id FROM Tags WHERE tag = ?1 AND record_id IN (SELECT name FROM Identifiers) ORDER BY record_id",
+    )?;
+    stmt.query_map((tag,), |row| row.get("record_id"))?
+        .collect()
+}
@@ -0,0 +1,435 @@
+//! # Run transcripts
+//!
+//! The changes made to records during a single run are tracked with temporary triggers, which
+//! insert a row into the temporary `RunTranscript` table for every new row of the `Records`
+//! table, and for every change of the active row of a record. When the run is finished, the
+//! transcript is stored in the `Runs` and `RunChanges` tables, so that the changes made by a run
+//! can be displayed again, and undone as a single unit.
+//!
+//! Every change also stores the row which was active before the change, so that a run can be
+//! undone by making the rows which were active before the run active again.
+use std::{
+    collections::{BTreeMap, HashSet, btree_map::Entry},
+    fmt,
+};
+
+use chrono::{DateTime, Local};
+use rusqlite::{
+    OptionalExtension,
+    types::{FromSql, FromSqlError, FromSqlResult, ValueRef},
+};
+use serde::Serialize;
+
+use super::{
+    RecordDatabase, Tx,
+    state::{DisambiguatedRecordRow, RecordRowMoveResult, RemoteIdState, RevisionId},
+};
+use crate::record::RemoteId;
+
+/// The temporary table and triggers which track the changes made during the run.
+///
+/// A change of the active row is only recorded if the row was not inserted during the run, since
+/// inserting a new revision also updates the identifiers of the record. The identifiers are
+/// updated after the new row is inserted, so the row which was active before the change can be
+/// read from the `Identifiers` table.
+const TRANSCRIPT_TRIGGERS: &str = "
+CREATE TEMP TABLE IF NOT EXISTS RunTranscript (
+    seq INTEGER PRIMARY KEY,
+    record_id TEXT NOT NULL,
+    action TEXT NOT NULL,
+    revision INTEGER NOT NULL,
+    previous INTEGER
+);
+CREATE TEMP TRIGGER IF NOT EXISTS transcript_records_insert AFTER INSERT ON main.Records BEGIN
+    INSERT INTO RunTranscript (record_id, action, revision, previous) VALUES (
+        NEW.record_id,
+        CASE
+            WHEN NEW.variant = 1 THEN 'deleted'
+            WHEN NEW.variant = 2 THEN 'voided'
+            WHEN NEW.parent_key IS NULL THEN 'created'
+            ELSE 'updated'
+        END,
+        NEW.key,
+        (SELECT record_key FROM main.Identifiers WHERE name = NEW.record_id)
+    );
+END;
+CREATE TEMP TRIGGER IF NOT EXISTS transcript_identifiers_update
+AFTER UPDATE OF record_key ON main.Identifiers
+WHEN NEW.record_key != OLD.record_key
+    AND NOT EXISTS (SELECT 1 FROM RunTranscript WHERE revision = NEW.record_key)
+BEGIN
+    INSERT INTO RunTranscript (record_id, action, revision, previous)
+        SELECT record_id, 'activated', key, OLD.record_key FROM main.Records
+            WHERE key = NEW.record_key;
+END;
+";
+
+/// Forget the changes tracked during the run, if the changes are being tracked.
+pub(super) fn clear_transcript(tx: &Tx) -> Result<(), rusqlite::Error> {
+    let tracked = tx
+        .prepare_cached("SELECT 1 FROM temp.sqlite_master WHERE name = 'RunTranscript'")?
+        .exists([])?;
+    if tracked {
+        tx.prepare_cached("DELETE FROM temp.RunTranscript")?
+            .execute([])?;
+    }
+    Ok(())
+}
+
+/// The change made to a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunAction {
+    /// A new record was created.
+    Created,
+    /// A new revision was added to the record.
+    Updated,
+    /// The record was deleted.
+    Deleted,
+    /// The record was voided.
+    Voided,
+    /// An existing revision was made active, for instance by `autobib hist undo`.
+    Activated,
+}
+
+impl RunAction {
+    /// The name of the action, which is stored in the `action` column of the `RunChanges` table.
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Updated => "updated",
+            Self::Deleted => "deleted",
+            Self::Voided => "voided",
+            Self::Activated => "activated",
+        }
+    }
+}
+
+impl fmt::Display for RunAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl FromSql for RunAction {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "created" => Ok(Self::Created),
+            "updated" => Ok(Self::Updated),
+            "deleted" => Ok(Self::Deleted),
+            "voided" => Ok(Self::Voided),
+            "activated" => Ok(Self::Activated),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+/// A single change made during a run.
+#[derive(Debug, Serialize)]
+pub struct RunChange {
+    /// The canonical identifier of the record.
+    pub canonical: String,
+    /// The change made to the record.
+    pub action: RunAction,
+    /// The revision which was added or made active.
+    pub revision: RevisionId,
+}
+
+/// The changes made to records during a single run.
+#[derive(Debug, Serialize)]
+pub struct RunSummary {
+    /// The key of the run, which identifies the session.
+    pub session: i64,
+    /// The command-line arguments of the run.
+    pub command: String,
+    /// When the run finished.
+    pub finished: DateTime<Local>,
+    /// The changes, in the order in which they were made.
+    pub changes: Vec<RunChange>,
+}
+
+impl RunSummary {
+    /// The number of distinct records which were changed.
+    pub fn num_records(&self) -> usize {
+        self.changes
+            .iter()
+            .map(|change| change.canonical.as_str())
+            .collect::<HashSet<_>>()
+            .len()
+    }
+}
+
+/// Read the changes returned by a prepared query, which must contain the `record_id`, `action`,
+/// and `revision` columns.
+fn read_changes(
+    mut stmt: rusqlite::CachedStatement<'_>,
+    params: impl rusqlite::Params,
+) -> Result<Vec<RunChange>, rusqlite::Error> {
+    stmt.query_map(params, |row| {
+        Ok(RunChange {
+            canonical: row.get("record_id")?,
+            action: row.get("action")?,
+            revision: row.get("revision")?,
+        })
+    })?
+    .collect()
+}
+
+/// Load the run with the provided key.
+fn load_run(
+    tx: &Tx,
+    key: i64,
+    command: String,
+    finished: DateTime<Local>,
+) -> Result<RunSummary, rusqlite::Error> {
+    let changes = read_changes(
+        tx.prepare_cached(
+            "SELECT record_id, action, revision FROM RunChanges WHERE run_key = ?1 ORDER BY seq",
+        )?,
+        [key],
+    )?;
+    Ok(RunSummary {
+        session: key,
+        command,
+        finished,
+        changes,
+    })
+}
+
+/// A run which changed at least one record.
+#[derive(Debug, Serialize)]
+pub struct RunInfo {
+    /// The key of the run, which identifies the session.
+    pub session: i64,
+    /// The command-line arguments of the run.
+    pub command: String,
+    /// When the run finished.
+    pub finished: DateTime<Local>,
+    /// The number of distinct records which were changed.
+    pub records: usize,
+}
+
+/// The outcome of [`RecordDatabase::undo_run`].
+pub enum UndoRunResult {
+    /// The changes were undone, and this many records were changed.
+    Undone(usize),
+    /// There is no run with the provided key.
+    Undefined,
+    /// The record was changed again after the run.
+    ChangedAfterRun(RemoteId),
+    /// The row which was active before the run is not known, either since the run was stored by
+    /// an older version or since the row was later removed.
+    PreviousUnavailable(RemoteId),
+}
+
+/// The changes made to a single record during a run.
+struct RunRecord {
+    /// The first change made to the record.
+    action: RunAction,
+    /// The row which was active before the first change.
+    previous: Option<RevisionId>,
+    /// The rows which were added or made active by the run.
+    revisions: Vec<RevisionId>,
+}
+
+/// Undo the changes made to a single record during a run, returning the transaction, or the
+/// reason why the changes cannot be undone.
+fn undo_run_record<'conn>(
+    tx: Tx<'conn>,
+    remote_id: RemoteId,
+    record: RunRecord,
+) -> Result<Result<Tx<'conn>, (Tx<'conn>, UndoRunResult)>, rusqlite::Error> {
+    let row = match RemoteIdState::determine(tx, &remote_id)? {
+        RemoteIdState::Entry(data, state) => DisambiguatedRecordRow::Entry(data, state),
+        RemoteIdState::Deleted(data, state) => DisambiguatedRecordRow::Deleted(data, state),
+        RemoteIdState::Void(data, state) => DisambiguatedRecordRow::Void(data, state),
+        RemoteIdState::Null(state) => {
+            return Ok(Err((
+                state.into_tx(),
+                UndoRunResult::ChangedAfterRun(remote_id),
+            )));
+        }
+        RemoteIdState::Unknown(state) => {
+            return Ok(Err((
+                state.into_tx(),
+                UndoRunResult::ChangedAfterRun(remote_id),
+            )));
+        }
+    };
+
+    let current = match &row {
+        DisambiguatedRecordRow::Entry(_, state) => RevisionId(state.row_id()),
+        DisambiguatedRecordRow::Deleted(_, state) => RevisionId(state.row_id()),
+        DisambiguatedRecordRow::Void(_, state) => RevisionId(state.row_id()),
+    };
+    if !record.revisions.contains(&current) {
+        let (_, state) = row.forget();
+        return Ok(Err((
+            state.into_tx(),
+            UndoRunResult::ChangedAfterRun(remote_id),
+        )));
+    }
+
+    Ok(match (record.previous, record.action, row) {
+        (Some(previous), _, row) => match row.forget().1.set_active(previous)? {
+            RecordRowMoveResult::Updated(state) => Ok(state.into_tx()),
+            RecordRowMoveResult::Unchanged(state, _) => Err((
+                state.into_tx(),
+                UndoRunResult::PreviousUnavailable(remote_id),
+            )),
+        },
+        // the record did not exist before the run
+        (None, RunAction::Created, DisambiguatedRecordRow::Entry(_, state)) => {
+            Ok(state.void()?.into_tx())
+        }
+        (None, RunAction::Created, DisambiguatedRecordRow::Deleted(_, state)) => {
+            Ok(state.void()?.into_tx())
+        }
+        (None, RunAction::Created, DisambiguatedRecordRow::Void(_, state)) => Ok(state.into_tx()),
+        (None, _, row) => Err((
+            row.forget().1.into_tx(),
+            UndoRunResult::PreviousUnavailable(remote_id),
+        )),
+    })
+}
+
+impl RecordDatabase {
+    /// Start tracking the changes made to records through this connection.
+    pub fn enable_transcript(&self) -> Result<(), rusqlite::Error> {
+        self.conn.execute_batch(TRANSCRIPT_TRIGGERS)
+    }
+
+    /// Store the changes tracked since [`RecordDatabase::enable_transcript`] as a run with the
+    /// provided command-line arguments, and return the summary of the run.
+    ///
+    /// Returns `None`, and does not store a run, if no records were changed.
+    pub fn save_run(&mut self, command: String) -> Result<Option<RunSummary>, rusqlite::Error> {
+        let tx = self.transaction()?;
+
+        let changes = read_changes(
+            tx.prepare_cached(
+                "SELECT record_id, action, revision FROM RunTranscript ORDER BY seq",
+            )?,
+            [],
+        )?;
+        if changes.is_empty() {
+            tx.commit()?;
+            return Ok(None);
+        }
+
+        let finished = Local::now();
+        let run_key: i64 = tx
+            .prepare_cached("INSERT INTO Runs (command, finished) VALUES (?1, ?2) RETURNING key")?
+            .query_row((&command, finished), |row| row.get("key"))?;
+        tx.prepare_cached(
+            "INSERT INTO RunChanges (run_key, seq, record_id, action, revision, previous)
+                SELECT ?1, seq, record_id, action, revision, previous FROM RunTranscript",
+        )?
+        .execute([run_key])?;
+        tx.prepare_cached("DELETE FROM RunTranscript")?
+            .execute([])?;
+        tx.commit()?;
+
+        Ok(Some(RunSummary {
+            session: run_key,
+            command,
+            finished,
+            changes,
+        }))
+    }
+
+    /// Get the summary of the most recent run which changed at least one record.
+    pub fn last_run(&mut self) -> Result<Option<RunSummary>, rusqlite::Error> {
+        let tx = self.transaction()?;
+        let last = tx
+            .prepare_cached("SELECT key, command, finished FROM Runs ORDER BY key DESC LIMIT 1")?
+            .query_row([], |row| {
+                Ok((row.get("key")?, row.get("command")?, row.get("finished")?))
+            })
+            .optional()?;
+        let summary = match last {
+            Some((key, command, finished)) => Some(load_run(&tx, key, command, finished)?),
+            None => None,
+        };
+        tx.commit()?;
+        Ok(summary)
+    }
+
+    /// Get the runs which changed at least one record, starting with the most recent run, and
+    /// returning at most `limit` runs.
+    pub fn runs(&mut self, limit: Option<u32>) -> Result<Vec<RunInfo>, rusqlite::Error> {
+        // SQLite uses `-1` to indicate no limit
+        let limit: i64 = limit.map(Into::into).unwrap_or(-1);
+        let tx = self.transaction()?;
+        let runs = tx
+            .prepare_cached(
+                "SELECT key, command, finished,
+                    (SELECT count(DISTINCT record_id) FROM RunChanges WHERE run_key = Runs.key)
+                        AS records
+                FROM Runs ORDER BY key DESC LIMIT ?1",
+            )?
+            .query_map([limit], |row| {
+                Ok(RunInfo {
+                    session: row.get("key")?,
+                    command: row.get("command")?,
+                    finished: row.get("finished")?,
+                    records: row.get("records")?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        tx.commit()?;
+        Ok(runs)
+    }
+
+    /// Undo every change made during the run with the provided key, in a single transaction.
+    ///
+    /// The row which was active before the run is made active again for every record changed
+    /// during the run, and records which were created during the run are voided. If any record
+    /// was changed after the run, no changes are made.
+    pub fn undo_run(&mut self, key: i64) -> Result<UndoRunResult, rusqlite::Error> {
+        let mut tx = self.transaction()?;
+        if !tx
+            .prepare_cached("SELECT 1 FROM Runs WHERE key = ?1")?
+            .exists([key])?
+        {
+            tx.commit()?;
+            return Ok(UndoRunResult::Undefined);
+        }
+
+        let mut records: BTreeMap<String, RunRecord> = BTreeMap::new();
+        {
+            let mut stmt = tx.prepare_cached(
+                "SELECT record_id, action, revision, previous FROM RunChanges
+                    WHERE run_key = ?1 ORDER BY seq",
+            )?;
+            let mut rows = stmt.query([key])?;
+            while let Some(row) = rows.next()? {
+                let revision = row.get("revision")?;
+                match records.entry(row.get("record_id")?) {
+                    Entry::Occupied(mut entry) => entry.get_mut().revisions.push(revision),
+                    Entry::Vacant(entry) => {
+                        entry.insert(RunRecord {
+                            action: row.get("action")?,
+                            previous: row.get("previous")?,
+                            revisions: vec![revision],
+                        });
+                    }
+                }
+            }
+        }
+
+        let num_records = records.len();
+        for (canonical, record) in records {
+            let remote_id = RemoteId::from_string_unchecked(canonical);
+            tx = match undo_run_record(tx, remote_id, record)? {
+                Ok(tx) => tx,
+                Err((tx, err)) => {
+                    tx.rollback()?;
+                    return Ok(err);
+                }
+            };
+        }
+        tx.commit()?;
+        Ok(UndoRunResult::Undone(num_records))
+    }
+}
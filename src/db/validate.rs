@@ -1,4 +1,5 @@
 mod find_cycles;
+mod repair;
 
 use std::{
     collections::{HashMap, HashSet},
@@ -10,6 +11,9 @@ use std::{
 use chrono::{DateTime, Local};
 use rusqlite::types::ValueRef;
 
+pub(super) use self::repair::{
+    normalize_identifier, normalize_record_id, remove_invalid_identifier,
+};
 use super::{Tx, schema};
 use crate::{
     Identifier, RawEntryData, RecordId, RemoteId, error::InvalidBytesError, logger::debug,
@@ -50,6 +54,14 @@ pub enum DatabaseFault {
     MissingTable(String),
     /// A table has the incorrect schema.
     InvalidTableSchema(String, String),
+    /// An alias in the `AliasLinks` table has a target which is not defined.
+    DanglingAliasLink(String, String),
+    /// A name is both an identifier in the `Identifiers` table and an alias in the `AliasLinks`
+    /// table.
+    AmbiguousAlias(String),
+    /// A table which is keyed by the canonical id contains rows for a record id which is not in
+    /// the `Records` table.
+    OrphanedRows(&'static str, String),
 }
 
 impl fmt::Display for DatabaseFault {
@@ -145,6 +157,18 @@ impl fmt::Display for DatabaseFault {
                 f,
                 "Table '{table_name}' has invalid schema:\n{table_schema}",
             ),
+            Self::DanglingAliasLink(name, target) => write!(
+                f,
+                "Alias '{name}' refers to alias '{target}' which does not exist"
+            ),
+            Self::AmbiguousAlias(name) => write!(
+                f,
+                "Alias '{name}' is both an identifier of a record and a link to another alias"
+            ),
+            Self::OrphanedRows(table_name, record_id) => write!(
+                f,
+                "Table '{table_name}' contains rows for record id '{record_id}' which is not in the Records table"
+            ),
         }
     }
 }
@@ -173,6 +197,17 @@ pub fn check_table_schema(
     }
 }
 
+/// The tables which are keyed by the canonical id, along with the columns which contain
+/// canonical ids.
+pub(super) const ORPHAN_COLUMNS: &[(&str, &str)] = &[
+    ("Overrides", "record_id"),
+    ("Tags", "record_id"),
+    ("CollectionRecords", "record_id"),
+    ("Relations", "record_id"),
+    ("Relations", "target"),
+    ("Added", "record_id"),
+];
+
 pub struct DatabaseValidator<'conn> {
     pub tx: Tx<'conn>,
 }
@@ -188,6 +223,19 @@ impl<'conn> DatabaseValidator<'conn> {
             ("Records", schema::records()),
             ("Identifiers", schema::identifiers()),
             ("NullRecords", schema::null_records()),
+            ("AliasLinks", schema::alias_links()),
+            ("Attachments", schema::attachments()),
+            ("Runs", schema::runs()),
+            ("RunChanges", schema::run_changes()),
+            ("Provenance", schema::provenance()),
+            ("FieldSources", schema::field_sources()),
+            ("Macros", schema::macros()),
+            ("Relations", schema::relations()),
+            ("Tags", schema::tags()),
+            ("Collections", schema::collections()),
+            ("CollectionRecords", schema::collection_records()),
+            ("Overrides", schema::overrides()),
+            ("Added", schema::added()),
         ] {
             debug!("Checking schema for table '{tbl_name}'.");
             if let Some(fault) = check_table_schema(&self.tx, tbl_name, schema)? {
@@ -230,6 +278,57 @@ impl<'conn> DatabaseValidator<'conn> {
         Ok(())
     }
 
+    /// Check that every alias in the `AliasLinks` table has a target which is defined.
+    pub fn alias_links(&self, faults: &mut Vec<DatabaseFault>) -> rusqlite::Result<()> {
+        debug!("Checking that alias links refer to defined aliases");
+        let mut stmt = self.tx.prepare(
+            "
+SELECT name, target
+FROM AliasLinks
+WHERE target NOT IN (SELECT name FROM Identifiers)
+    AND target NOT IN (SELECT name FROM AliasLinks)
+",
+        )?;
+
+        for row in stmt.query_map([], |row| Ok((row.get("name")?, row.get("target")?)))? {
+            let (name, target) = row?;
+            faults.push(DatabaseFault::DanglingAliasLink(name, target));
+        }
+
+        Ok(())
+    }
+
+    /// Check that no alias in the `AliasLinks` table is also an identifier in the `Identifiers`
+    /// table.
+    pub fn ambiguous_aliases(&self, faults: &mut Vec<DatabaseFault>) -> rusqlite::Result<()> {
+        debug!("Checking that alias links are not also identifiers");
+        let mut stmt = self.tx.prepare(
+            "SELECT name FROM AliasLinks WHERE name IN (SELECT name FROM Identifiers) ORDER BY name",
+        )?;
+
+        for name in stmt.query_map([], |row| row.get("name"))? {
+            faults.push(DatabaseFault::AmbiguousAlias(name?));
+        }
+
+        Ok(())
+    }
+
+    /// Check that the tables which are keyed by the canonical id only contain rows for records
+    /// in the `Records` table.
+    pub fn orphaned_rows(&self, faults: &mut Vec<DatabaseFault>) -> rusqlite::Result<()> {
+        for &(table_name, column) in ORPHAN_COLUMNS {
+            debug!("Checking that '{table_name}' only refers to records which exist");
+            let mut stmt = self.tx.prepare(&format!(
+                "SELECT DISTINCT {column} FROM {table_name} WHERE {column} NOT IN (SELECT record_id FROM Records) ORDER BY {column}"
+            ))?;
+            for row in stmt.query_map([], |row| row.get(0))? {
+                faults.push(DatabaseFault::OrphanedRows(table_name, row?));
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn unique_tree_per_record_id(
         &self,
         faults: &mut Vec<DatabaseFault>,
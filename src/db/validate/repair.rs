@@ -0,0 +1,212 @@
+//! Repairs of faults in the identifiers of the database.
+//!
+//! The repairs which are not ambiguous are applied automatically. Otherwise, the `confirm`
+//! callback is asked whether to proceed, and the fault is left in place if it returns `false`.
+use chrono::{DateTime, Local};
+use rusqlite::OptionalExtension;
+
+use super::ORPHAN_COLUMNS;
+use crate::{
+    db::{RowId, Tx},
+    logger::{info, warn},
+};
+
+/// The revision tree of a record, when it is consistent.
+#[derive(Debug, Clone, Copy)]
+struct RevisionTree {
+    /// The root row of the tree.
+    root: RowId,
+    /// The modification time of the root row.
+    modified: DateTime<Local>,
+    /// The variant of the root row.
+    variant: i64,
+    /// The active row of the tree.
+    active: RowId,
+}
+
+/// Load the revision tree of the record, returning `None` if the record does not have a unique
+/// root row and a unique active row.
+fn revision_tree(tx: &Tx, record_id: &str) -> Result<Option<RevisionTree>, rusqlite::Error> {
+    let roots = tx
+        .prepare_cached(
+            "SELECT key, modified, variant FROM Records WHERE record_id = ?1 AND parent_key IS NULL",
+        )?
+        .query_map([record_id], |row| {
+            Ok((row.get("key")?, row.get("modified")?, row.get("variant")?))
+        })?
+        .collect::<Result<Vec<(RowId, DateTime<Local>, i64)>, _>>()?;
+    let active = tx
+        .prepare_cached(
+            "SELECT key FROM Records WHERE record_id = ?1 AND key IN (SELECT record_key FROM Identifiers)",
+        )?
+        .query_map([record_id], |row| row.get("key"))?
+        .collect::<Result<Vec<RowId>, _>>()?;
+
+    Ok(match (roots.as_slice(), active.as_slice()) {
+        ([(root, modified, variant)], [active]) => Some(RevisionTree {
+            root: *root,
+            modified: *modified,
+            variant: *variant,
+            active: *active,
+        }),
+        _ => None,
+    })
+}
+
+/// Check if there is a row in the `Records` table with the record id.
+fn record_exists(tx: &Tx, record_id: &str) -> Result<bool, rusqlite::Error> {
+    Ok(tx
+        .prepare_cached("SELECT 1 FROM Records WHERE record_id = ?1 LIMIT 1")?
+        .query_row([record_id], |_| Ok(()))
+        .optional()?
+        .is_some())
+}
+
+/// Get the row referenced by the identifier in the `Identifiers` table.
+fn identifier_key(tx: &Tx, name: &str) -> Result<Option<RowId>, rusqlite::Error> {
+    tx.prepare_cached("SELECT record_key FROM Identifiers WHERE name = ?1")?
+        .query_row([name], |row| row.get("record_key"))
+        .optional()
+}
+
+/// Merge the revisions of the record `name` into the record `expected`.
+///
+/// The root row which was modified earlier becomes the parent of the other root row, so that
+/// the revisions form a single tree with monotonic timestamps. The active row of `expected`
+/// remains active, unless the `confirm` callback chooses the active row of `name`.
+fn merge_records<F: FnMut(&str) -> bool>(
+    tx: &Tx,
+    name: &str,
+    expected: &str,
+    confirm: &mut F,
+) -> Result<bool, rusqlite::Error> {
+    let (Some(source), Some(target)) = (revision_tree(tx, name)?, revision_tree(tx, expected)?)
+    else {
+        warn!("Cannot merge '{name}' into '{expected}' since the revisions are inconsistent");
+        return Ok(false);
+    };
+
+    let (parent, child) = if source.modified < target.modified {
+        (source, target)
+    } else {
+        (target, source)
+    };
+    // void rows must be roots
+    if child.variant == 2 {
+        warn!("Cannot merge '{name}' into '{expected}' since both records are void");
+        return Ok(false);
+    }
+
+    let active = if confirm(&format!(
+        "Record '{name}' will be merged into '{expected}'. Keep the current data of '{name}'?"
+    )) {
+        source.active
+    } else {
+        target.active
+    };
+
+    warn!("Merging record '{name}' into '{expected}'");
+    tx.prepare_cached("UPDATE Records SET record_id = ?2 WHERE record_id = ?1")?
+        .execute((name, expected))?;
+    tx.prepare_cached("UPDATE Records SET parent_key = ?1 WHERE key = ?2")?
+        .execute((parent.root, child.root))?;
+    tx.prepare_cached("UPDATE Identifiers SET record_key = ?1 WHERE record_key IN (?2, ?3)")?
+        .execute((active, source.active, target.active))?;
+    Ok(true)
+}
+
+/// Replace the non-normalized canonical identifier `name` of a record with the normalized
+/// identifier `expected`, merging the records if a record with the identifier `expected`
+/// already exists.
+pub fn normalize_record_id<F: FnMut(&str) -> bool>(
+    tx: &Tx,
+    name: &str,
+    expected: &str,
+    confirm: &mut F,
+) -> Result<bool, rusqlite::Error> {
+    // every row of the record is renamed at once, so the fault may already be fixed
+    if !record_exists(tx, name)? {
+        return Ok(true);
+    }
+
+    if record_exists(tx, expected)? {
+        if !merge_records(tx, name, expected, confirm)? {
+            return Ok(false);
+        }
+    } else {
+        info!("Renaming record '{name}' to '{expected}'");
+        tx.prepare_cached("UPDATE Records SET record_id = ?2 WHERE record_id = ?1")?
+            .execute((name, expected))?;
+    }
+
+    tx.prepare_cached("UPDATE OR IGNORE Attachments SET record_id = ?2 WHERE record_id = ?1")?
+        .execute((name, expected))?;
+
+    // the rows which are keyed by the canonical id follow the record, and the rows of `expected`
+    // take precedence
+    for (table, column) in ORPHAN_COLUMNS {
+        tx.prepare(&format!(
+            "UPDATE OR IGNORE {table} SET {column} = ?2 WHERE {column} = ?1"
+        ))?
+        .execute((name, expected))?;
+        tx.prepare(&format!("DELETE FROM {table} WHERE {column} = ?1"))?
+            .execute([name])?;
+    }
+    Ok(true)
+}
+
+/// Replace the non-normalized identifier `name` with the normalized identifier `expected`.
+///
+/// If `expected` already refers to a different record, the `confirm` callback is asked whether
+/// to delete `name`.
+pub fn normalize_identifier<F: FnMut(&str) -> bool>(
+    tx: &Tx,
+    name: &str,
+    expected: &str,
+    confirm: &mut F,
+) -> Result<bool, rusqlite::Error> {
+    let Some(key) = identifier_key(tx, name)? else {
+        return Ok(true);
+    };
+
+    match identifier_key(tx, expected)? {
+        None => {
+            info!("Renaming identifier '{name}' to '{expected}'");
+            tx.prepare_cached("UPDATE Identifiers SET name = ?2 WHERE name = ?1")?
+                .execute((name, expected))?;
+        }
+        Some(expected_key) if expected_key == key => {
+            info!("Deleting identifier '{name}' since '{expected}' refers to the same record");
+            tx.prepare_cached("DELETE FROM Identifiers WHERE name = ?1")?
+                .execute([name])?;
+        }
+        Some(_) => {
+            if !confirm(&format!(
+                "Identifier '{name}' refers to a different record than '{expected}'. Delete '{name}'?"
+            )) {
+                return Ok(false);
+            }
+            warn!("Deleting identifier '{name}'");
+            tx.prepare_cached("DELETE FROM Identifiers WHERE name = ?1")?
+                .execute([name])?;
+        }
+    }
+    Ok(true)
+}
+
+/// Delete the invalid identifier `name`, if it is not the canonical identifier of a record and
+/// the `confirm` callback agrees.
+pub fn remove_invalid_identifier<F: FnMut(&str) -> bool>(
+    tx: &Tx,
+    name: &str,
+    confirm: &mut F,
+) -> Result<bool, rusqlite::Error> {
+    if record_exists(tx, name)? || !confirm(&format!("Delete invalid identifier '{name}'?")) {
+        return Ok(false);
+    }
+
+    warn!("Deleting invalid identifier '{name}'");
+    tx.prepare_cached("DELETE FROM Identifiers WHERE name = ?1")?
+        .execute([name])?;
+    Ok(true)
+}
@@ -1,5 +1,9 @@
 mod data;
 mod deserialize;
+mod flavor;
+mod inherit;
+mod macros;
+mod style;
 
 use std::{fmt, str::FromStr};
 
@@ -10,8 +14,13 @@ use serde_bibtex::{MacroDictionary, de::Deserializer, to_string_unchecked};
 pub use self::data::{
     BorrowedEntryData, ConflictResolved, EntryData, EntryEditCommand, EntryKey, EntryType,
     FieldKey, FieldValue, MutableEntryData, RawEntryData, RawRecordFieldsIter, SetFieldCommand,
+    changed_fields,
 };
 pub(crate) use self::data::{EntryTypeHeader, KeyHeader, ValueHeader};
+pub use self::flavor::Flavor;
+pub use self::inherit::Relation;
+pub use self::macros::{MacroDefinition, macro_definitions};
+pub use self::style::EntryStyle;
 
 use crate::error::BibtexDataError;
 
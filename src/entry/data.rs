@@ -23,7 +23,9 @@ pub use identifier::{EntryKey, EntryType, FieldKey, FieldValue, validate_ascii_i
 pub(crate) use raw::{EntryTypeHeader, KeyHeader, ValueHeader};
 pub use raw::{RawEntryData, RawRecordFieldsIter};
 
-use crate::normalize::{Normalize, normalize_whitespace_str};
+use crate::normalize::{
+    NameFormat, Normalize, normalize_names_str, normalize_whitespace_str, protect_case_str,
+};
 
 /// This trait represents types which encapsulate the data content of a single BibTeX entry.
 ///
@@ -101,6 +103,25 @@ pub unsafe trait EntryData: PartialEq {
     }
 }
 
+/// The names of the fields whose values differ between the current and the new data.
+pub fn changed_fields<D: EntryData, E: EntryData>(current: &D, new: &E) -> Vec<String> {
+    let mut fields = Vec::new();
+    if current.entry_type() != new.entry_type() {
+        fields.push("entry type".to_owned());
+    }
+    for (key, value) in new.fields() {
+        if current.get_field(key) != Some(value) {
+            fields.push(key.to_owned());
+        }
+    }
+    for (key, _) in current.fields() {
+        if !new.contains_field(key) {
+            fields.push(key.to_owned());
+        }
+    }
+    fields
+}
+
 /// An in-memory [`EntryData`] implementation which supports addition and deletion of fields.
 #[derive(Debug, PartialEq, Eq)]
 pub struct MutableEntryData<S = String> {
@@ -264,9 +285,9 @@ impl MutableEntryData {
         }
     }
 
-    /// This method is very similar to `merge_or_overwrite`, but also updates the entry type and is
-    /// slightly more optimized since it blindly overwrites existing entries, instead of checking
-    /// that they are different.
+    /// This method is very similar to `merge_with_callback` resolving every conflict with the
+    /// incoming value, but also updates the entry type and is slightly more optimized since it
+    /// blindly overwrites existing entries, instead of checking that they are different.
     pub fn update_from<D: EntryData>(&mut self, data: &D) {
         self.entry_type.0.clear();
         self.entry_type.0.push_str(data.entry_type());
@@ -342,26 +363,6 @@ impl MutableEntryData {
         }
     }
 
-    /// Merge data from `other`, ignoring fields that already exist in `self`.
-    #[inline]
-    pub fn merge_or_skip<D: EntryData>(&mut self, other: &D) {
-        self.merge_with_callback(
-            other,
-            |_, _| ConflictResolved::Current,
-            |_, _, _| ConflictResolved::Current,
-        );
-    }
-
-    /// Merge data from `other`, overwriting fields that already exist in `self`.
-    #[inline]
-    pub fn merge_or_overwrite<D: EntryData>(&mut self, other: &D) {
-        self.merge_with_callback(
-            other,
-            |_, _| ConflictResolved::Incoming,
-            |_, _, _| ConflictResolved::Incoming,
-        );
-    }
-
     pub fn try_new(e: String) -> Result<Self, crate::error::RecordDataError> {
         Ok(Self::new(EntryType::try_new(e)?))
     }
@@ -442,6 +443,12 @@ unsafe impl<S: AsRef<str> + Ord> EntryData for MutableEntryData<S> {
     }
 }
 
+/// The fields in which capitalization is protected by [`Normalize::protect_case`].
+const PROTECT_CASE_FIELDS: [&str; 3] = ["booktitle", "subtitle", "title"];
+
+/// The fields containing name lists which are rewritten by [`Normalize::normalize_names`].
+const NAME_LIST_FIELDS: [&str; 2] = ["author", "editor"];
+
 static TRAILING_JOURNAL_SERIES_RE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"\s*\([1-9][0-9]*\)$").unwrap());
 
@@ -498,4 +505,51 @@ impl Normalize for MutableEntryData {
         }
         false
     }
+
+    fn protect_case<Q: AsRef<str>>(&mut self, words: &[Q]) -> bool {
+        let mut updated = false;
+
+        for key in PROTECT_CASE_FIELDS {
+            if let Some(val) = self.fields.get_mut(key)
+                && let Some(new_val) = protect_case_str(val.0.as_ref(), words)
+                && new_val.len() <= ValueHeader::MAX as usize
+            {
+                updated = true;
+                // SAFETY: the length was checked above, and a balanced pair of braces is added
+                // around each protected word, which preserves the balanced `{}` rule.
+                *val = FieldValue(new_val);
+            }
+        }
+
+        updated
+    }
+
+    fn normalize_names(&mut self, format: NameFormat) -> bool {
+        let mut updated = false;
+
+        for key in NAME_LIST_FIELDS {
+            if let Some(val) = self.fields.get_mut(key)
+                && let Some(new_val) = normalize_names_str(val.0.as_ref(), format)
+                && new_val.len() <= ValueHeader::MAX as usize
+            {
+                updated = true;
+                // SAFETY: the length was checked above, and the words in the new value are
+                // balanced since they are split from the original value at brace depth 0.
+                *val = FieldValue(new_val);
+            }
+        }
+
+        updated
+    }
+
+    fn article_number_to_pages(&mut self) -> bool {
+        if !self.contains_key("pages")
+            && let Some(eid) = self.remove("eid")
+        {
+            // SAFETY: 'pages' satisfies the key requirements
+            self.insert(FieldKey("pages".into()), eid);
+            return true;
+        }
+        false
+    }
 }
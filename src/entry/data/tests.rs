@@ -16,6 +16,45 @@ fn test_normalize_whitespace() {
     assert!(!record_data.normalize_whitespace());
 }
 
+#[test]
+fn test_protect_case() {
+    let mut record_data = MutableEntryData::try_new("article".into()).unwrap();
+    for (k, v) in [
+        ("author", "BibTeX"),
+        ("title", "On BibTeX"),
+        ("booktitle", "Proceedings"),
+    ] {
+        record_data.check_and_insert(k.into(), v.into()).unwrap();
+    }
+    let changed = record_data.protect_case(&["BibTeX", "Proceedings"]);
+    assert!(changed);
+    assert_eq!(record_data.get_str("author"), Some("BibTeX"));
+    assert_eq!(record_data.get_str("title"), Some("On {BibTeX}"));
+    assert_eq!(record_data.get_str("booktitle"), Some("{Proceedings}"));
+    assert!(!record_data.protect_case(&["BibTeX", "Proceedings"]));
+}
+
+#[test]
+fn test_normalize_names() {
+    let mut record_data = MutableEntryData::try_new("article".into()).unwrap();
+    for (k, v) in [
+        ("author", "Donald E. Knuth and Leslie Lamport"),
+        ("editor", "van Beethoven, Ludwig"),
+        ("title", "Donald E. Knuth"),
+    ] {
+        record_data.check_and_insert(k.into(), v.into()).unwrap();
+    }
+    let changed = record_data.normalize_names(NameFormat::LastFirst);
+    assert!(changed);
+    assert_eq!(
+        record_data.get_str("author"),
+        Some("Knuth, Donald E. and Lamport, Leslie")
+    );
+    assert_eq!(record_data.get_str("editor"), Some("van Beethoven, Ludwig"));
+    assert_eq!(record_data.get_str("title"), Some("Donald E. Knuth"));
+    assert!(!record_data.normalize_names(NameFormat::LastFirst));
+}
+
 #[test]
 fn test_normalize_eprint() {
     // standard normalize
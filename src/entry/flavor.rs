@@ -0,0 +1,362 @@
+//! # BibTeX and biblatex flavors
+//!
+//! Records are stored as they are returned by the providers, which mostly use the entry types and
+//! the fields of BibTeX, but the data can also contain biblatex entry types such as `@online` and
+//! fields such as `date`. When writing entries, the data can be converted to a [`Flavor`], in which
+//! case the entry types and the fields of the other flavor are replaced by their closest
+//! equivalent. Entry types and fields which do not exist in either flavor are not changed.
+use serde::Deserialize;
+
+use super::{EntryType, FieldKey, FieldValue, MutableEntryData};
+
+/// The flavor of the entry types and the fields of written entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Flavor {
+    /// Use the entry types and the fields of BibTeX, such as `year` and `journal`.
+    Bibtex,
+    /// Use the entry types and the fields of biblatex, such as `date` and `journaltitle`.
+    Biblatex,
+}
+
+/// Fields which have a different name in biblatex, as pairs of the BibTeX name and the biblatex
+/// name.
+const RENAMED_FIELDS: [(&str, &str); 3] = [
+    ("journal", "journaltitle"),
+    ("address", "location"),
+    ("primaryclass", "eprintclass"),
+];
+
+/// Entry types which are aliases in biblatex, and the biblatex entry type, along with the value of
+/// the `type` field which distinguishes the aliases.
+const BIBTEX_ENTRY_TYPES: [(&str, &str, Option<&str>); 6] = [
+    ("conference", "inproceedings", None),
+    ("electronic", "online", None),
+    ("www", "online", None),
+    ("mastersthesis", "thesis", Some("mathesis")),
+    ("phdthesis", "thesis", Some("phdthesis")),
+    ("techreport", "report", Some("techreport")),
+];
+
+/// Entry types which only exist in biblatex, and the closest BibTeX entry type.
+const BIBLATEX_ENTRY_TYPES: [(&str, &str); 28] = [
+    ("artwork", "misc"),
+    ("audio", "misc"),
+    ("bookinbook", "inbook"),
+    ("collection", "book"),
+    ("dataset", "misc"),
+    ("image", "misc"),
+    ("inreference", "incollection"),
+    ("jurisdiction", "misc"),
+    ("legislation", "misc"),
+    ("letter", "misc"),
+    ("movie", "misc"),
+    ("music", "misc"),
+    ("mvbook", "book"),
+    ("mvcollection", "book"),
+    ("mvproceedings", "proceedings"),
+    ("mvreference", "book"),
+    ("online", "misc"),
+    ("patent", "misc"),
+    ("performance", "misc"),
+    ("periodical", "misc"),
+    ("reference", "book"),
+    ("report", "techreport"),
+    ("review", "article"),
+    ("software", "misc"),
+    ("standard", "misc"),
+    ("suppbook", "inbook"),
+    ("suppcollection", "incollection"),
+    ("thesis", "phdthesis"),
+];
+
+/// The English names of the months.
+pub(super) const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Parse the value of a `month` field, which is either a number or a name, possibly abbreviated to
+/// three letters.
+pub(super) fn parse_month(value: &str) -> Option<usize> {
+    let value = value.trim();
+    if let Ok(month) = value.parse::<usize>() {
+        return (1..=12).contains(&month).then_some(month);
+    }
+    let lower = value.to_ascii_lowercase();
+    MONTHS
+        .iter()
+        .position(|name| {
+            let name = name.to_ascii_lowercase();
+            lower == name || (lower.len() == 3 && name.starts_with(&lower))
+        })
+        .map(|idx| idx + 1)
+}
+
+/// Parse a `date` field of the form `YYYY`, `YYYY-MM`, or `YYYY-MM-DD` into the year and the
+/// month, if any.
+fn parse_date(value: &str) -> Option<(&str, Option<usize>)> {
+    let mut parts = value.trim().split('-');
+    let year = parts
+        .next()
+        .filter(|year| year.len() == 4 && year.bytes().all(|b| b.is_ascii_digit()))?;
+    let month = match parts.next() {
+        Some(month) if month.len() == 2 => Some(parse_month(month)?),
+        Some(_) => return None,
+        None => None,
+    };
+    match parts.next() {
+        Some(day) if day.len() != 2 || !day.bytes().all(|b| b.is_ascii_digit()) => return None,
+        _ => {}
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((year, month))
+}
+
+impl MutableEntryData {
+    /// Rename the field `from` to `to`, unless the field `to` already exists.
+    fn rename_field(&mut self, from: &str, to: &str) {
+        if !self.fields.contains_key(to)
+            && let Some(value) = self.fields.remove(from)
+        {
+            self.fields.insert(FieldKey(to.to_owned()), value);
+        }
+    }
+
+    /// Set the field to the provided value, if the value is valid.
+    fn set_field_value(&mut self, key: &str, value: String) {
+        if let Ok(value) = FieldValue::try_new(value) {
+            self.fields.insert(FieldKey(key.to_owned()), value);
+        }
+    }
+
+    /// Convert the entry type and the fields to the provided flavor.
+    pub fn convert_flavor(&mut self, flavor: Flavor) {
+        match flavor {
+            Flavor::Bibtex => self.convert_to_bibtex(),
+            Flavor::Biblatex => self.convert_to_biblatex(),
+        }
+    }
+
+    fn convert_to_biblatex(&mut self) {
+        let entry_type = self.entry_type.0.to_ascii_lowercase();
+        if let Some((_, biblatex, thesis_type)) = BIBTEX_ENTRY_TYPES
+            .iter()
+            .find(|(bibtex, _, _)| *bibtex == entry_type)
+        {
+            self.entry_type = EntryType((*biblatex).to_owned());
+            if let Some(thesis_type) = thesis_type
+                && !self.fields.contains_key("type")
+            {
+                self.set_field_value("type", (*thesis_type).to_owned());
+            }
+        }
+        if matches!(self.entry_type.0.as_str(), "thesis" | "report") {
+            self.rename_field("school", "institution");
+        }
+
+        for (bibtex, biblatex) in RENAMED_FIELDS {
+            self.rename_field(bibtex, biblatex);
+        }
+
+        if !self.fields.contains_key("date")
+            && let Some(year) = self.get_str("year")
+            && year.len() == 4
+            && year.bytes().all(|b| b.is_ascii_digit())
+        {
+            let year = year.to_owned();
+            // a month which cannot be parsed is kept as a separate field
+            match self.get_str("month").and_then(parse_month) {
+                Some(month) => {
+                    self.set_field_value("date", format!("{year}-{month:02}"));
+                    self.fields.remove("month");
+                }
+                None => self.set_field_value("date", year),
+            }
+            self.fields.remove("year");
+        }
+    }
+
+    fn convert_to_bibtex(&mut self) {
+        let entry_type = self.entry_type.0.to_ascii_lowercase();
+        let type_field = self.get_str("type").map(str::to_ascii_lowercase);
+        match entry_type.as_str() {
+            "thesis" => {
+                let bibtex = match type_field.as_deref() {
+                    Some("mathesis" | "masters" | "mastersthesis") => "mastersthesis",
+                    _ => "phdthesis",
+                };
+                if type_field.as_deref().is_some_and(|ty| {
+                    matches!(ty, "mathesis" | "masters" | "mastersthesis" | "phdthesis")
+                }) {
+                    self.fields.remove("type");
+                }
+                self.entry_type = EntryType(bibtex.to_owned());
+                self.rename_field("institution", "school");
+            }
+            "report" => {
+                if type_field.as_deref() == Some("techreport") {
+                    self.fields.remove("type");
+                }
+                self.entry_type = EntryType("techreport".to_owned());
+            }
+            "online" => {
+                if !self.fields.contains_key("howpublished")
+                    && let Some(url) = self.get_str("url")
+                {
+                    let howpublished = format!("\\url{{{url}}}");
+                    self.set_field_value("howpublished", howpublished);
+                }
+                self.entry_type = EntryType("misc".to_owned());
+            }
+            _ => {
+                if let Some((_, bibtex)) = BIBLATEX_ENTRY_TYPES
+                    .iter()
+                    .find(|(biblatex, _)| *biblatex == entry_type)
+                {
+                    self.entry_type = EntryType((*bibtex).to_owned());
+                }
+            }
+        }
+
+        for (bibtex, biblatex) in RENAMED_FIELDS {
+            self.rename_field(biblatex, bibtex);
+        }
+
+        if !self.fields.contains_key("year")
+            && let Some((year, month)) = self.get_str("date").and_then(parse_date)
+        {
+            let year = year.to_owned();
+            if let Some(month) = month
+                && !self.fields.contains_key("month")
+            {
+                self.set_field_value("month", MONTHS[month - 1].to_owned());
+            }
+            self.set_field_value("year", year);
+            self.fields.remove("date");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(entry_type: &str, fields: &[(&str, &str)]) -> MutableEntryData {
+        let mut data = MutableEntryData::try_new(entry_type.to_owned()).unwrap();
+        for (key, value) in fields {
+            data.check_and_insert((*key).to_owned(), (*value).to_owned())
+                .unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn to_biblatex() {
+        let mut article = data(
+            "article",
+            &[
+                ("journal", "Ann. of Math."),
+                ("year", "2014"),
+                ("month", "mar"),
+            ],
+        );
+        article.convert_flavor(Flavor::Biblatex);
+        assert_eq!(
+            article,
+            data(
+                "article",
+                &[("journaltitle", "Ann. of Math."), ("date", "2014-03")]
+            )
+        );
+
+        let mut thesis = data("mastersthesis", &[("school", "MIT"), ("year", "to appear")]);
+        thesis.convert_flavor(Flavor::Biblatex);
+        assert_eq!(
+            thesis,
+            data(
+                "thesis",
+                &[
+                    ("institution", "MIT"),
+                    ("type", "mathesis"),
+                    ("year", "to appear")
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn to_bibtex() {
+        let mut online = data(
+            "online",
+            &[("url", "https://example.com"), ("date", "2020-11-05")],
+        );
+        online.convert_flavor(Flavor::Bibtex);
+        assert_eq!(
+            online,
+            data(
+                "misc",
+                &[
+                    ("howpublished", "\\url{https://example.com}"),
+                    ("month", "November"),
+                    ("url", "https://example.com"),
+                    ("year", "2020"),
+                ]
+            )
+        );
+
+        let mut thesis = data(
+            "thesis",
+            &[
+                ("institution", "MIT"),
+                ("type", "phdthesis"),
+                ("date", "2001/2002"),
+            ],
+        );
+        thesis.convert_flavor(Flavor::Bibtex);
+        assert_eq!(
+            thesis,
+            data("phdthesis", &[("school", "MIT"), ("date", "2001/2002")])
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let original = data(
+            "techreport",
+            &[
+                ("institution", "MPI"),
+                ("address", "Bonn"),
+                ("year", "1999"),
+                ("month", "7"),
+            ],
+        );
+        let mut converted = MutableEntryData::from_entry_data(&original);
+        converted.convert_flavor(Flavor::Biblatex);
+        converted.convert_flavor(Flavor::Bibtex);
+        assert_eq!(
+            converted,
+            data(
+                "techreport",
+                &[
+                    ("institution", "MPI"),
+                    ("address", "Bonn"),
+                    ("year", "1999"),
+                    ("month", "July"),
+                ]
+            )
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! # Inheritance of fields from parent entries
+//!
+//! An entry can inherit fields from other entries with the BibTeX `crossref` field, which refers
+//! to a single parent such as the proceedings containing an article, or with the biblatex `xdata`
+//! field, which is a comma-separated list of `@xdata` entries containing shared fields. When
+//! resolving the inheritance, the fields of the parent are copied to the entry unless the entry
+//! already contains the field.
+use super::{EntryData, MutableEntryData};
+
+/// How an entry refers to a parent entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    /// The parent is referenced by the `crossref` field.
+    Crossref,
+    /// The parent is referenced by the `xdata` field.
+    Xdata,
+}
+
+impl Relation {
+    /// The name of the field which refers to the parent, which is also used to store the
+    /// relation.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Crossref => "crossref",
+            Self::Xdata => "xdata",
+        }
+    }
+}
+
+/// Fields which are never inherited from a parent.
+const NOT_INHERITED: [&str; 3] = ["crossref", "xdata", "ids"];
+
+/// Entry types whose `booktitle` is the `title` of the parent referenced by `crossref`.
+const BOOKTITLE_FROM_TITLE: [&str; 7] = [
+    "bookinbook",
+    "inbook",
+    "incollection",
+    "inproceedings",
+    "inreference",
+    "suppbook",
+    "suppcollection",
+];
+
+impl MutableEntryData {
+    /// The keys of the parents of the entry, in the order in which they are inherited.
+    pub fn parent_keys(&self) -> Vec<(Relation, String)> {
+        let mut keys = Vec::new();
+        if let Some(key) = self.get_str(Relation::Crossref.as_str()) {
+            keys.push((Relation::Crossref, key.trim().to_owned()));
+        }
+        if let Some(xdata) = self.get_str(Relation::Xdata.as_str()) {
+            keys.extend(
+                xdata
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|key| !key.is_empty())
+                    .map(|key| (Relation::Xdata, key.to_owned())),
+            );
+        }
+        keys
+    }
+
+    /// Copy the fields of the parent which are not present in the entry.
+    ///
+    /// If the parent is referenced by `crossref` and the entry is part of a larger work, such as
+    /// an `@inproceedings`, the `title` of the parent is copied to the `booktitle` of the entry.
+    pub fn inherit<D: EntryData>(&mut self, parent: &D, relation: Relation) {
+        let title_as_booktitle = relation == Relation::Crossref
+            && BOOKTITLE_FROM_TITLE.contains(&self.entry_type.0.to_ascii_lowercase().as_str());
+
+        for (key, value) in parent.fields() {
+            let key = match key {
+                "title" if title_as_booktitle => "booktitle",
+                key if NOT_INHERITED.contains(&key) => continue,
+                key => key,
+            };
+            if !self.fields.contains_key(key) {
+                // the fields of the parent are already valid
+                let _ = self.check_and_insert(key.to_owned(), value.to_owned());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(entry_type: &str, fields: &[(&str, &str)]) -> MutableEntryData {
+        let mut data = MutableEntryData::try_new(entry_type.to_owned()).unwrap();
+        for (key, value) in fields {
+            data.check_and_insert((*key).to_owned(), (*value).to_owned())
+                .unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn parent_keys() {
+        let entry = data("inproceedings", &[("crossref", "proc"), ("xdata", "a, b,")]);
+        assert_eq!(
+            entry.parent_keys(),
+            [
+                (Relation::Crossref, "proc".to_owned()),
+                (Relation::Xdata, "a".to_owned()),
+                (Relation::Xdata, "b".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn inherit_crossref() {
+        let mut entry = data(
+            "inproceedings",
+            &[("title", "A talk"), ("year", "2001"), ("crossref", "proc")],
+        );
+        let parent = data(
+            "proceedings",
+            &[
+                ("title", "Proceedings"),
+                ("year", "2000"),
+                ("publisher", "AMS"),
+                ("ids", "other"),
+            ],
+        );
+        entry.inherit(&parent, Relation::Crossref);
+        assert_eq!(
+            entry,
+            data(
+                "inproceedings",
+                &[
+                    ("title", "A talk"),
+                    ("booktitle", "Proceedings"),
+                    ("year", "2001"),
+                    ("publisher", "AMS"),
+                    ("crossref", "proc"),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn inherit_xdata() {
+        let mut entry = data("article", &[("title", "A title"), ("xdata", "ams")]);
+        let parent = data("xdata", &[("title", "Ignored"), ("publisher", "AMS")]);
+        entry.inherit(&parent, Relation::Xdata);
+        assert_eq!(
+            entry,
+            data(
+                "article",
+                &[("title", "A title"), ("publisher", "AMS"), ("xdata", "ams")]
+            )
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! # Macro definitions
+//!
+//! BibTeX files can define abbreviations with `@string` entries, such as
+//! `@string{jams = {J. Amer. Math. Soc.}}`. The abbreviations are expanded when the entries of a
+//! file are read, so the definitions themselves are read separately in order to store them in the
+//! database and to write them as a preamble of generated bibliographies.
+use super::flavor::{MONTHS, parse_month};
+
+/// An abbreviation defined by an `@string` entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroDefinition {
+    /// The name of the abbreviation.
+    pub name: String,
+    /// The expanded value of the abbreviation, without delimiters.
+    pub value: String,
+}
+
+/// The contents of a value delimited by `{}` or by `""`, and the remaining input after the
+/// closing delimiter.
+fn delimited(s: &str) -> Option<(&str, &str)> {
+    let mut chars = s.char_indices();
+    let (_, open) = chars.next()?;
+    let mut depth = 0_usize;
+    for (idx, ch) in chars {
+        match ch {
+            '{' => depth += 1,
+            '}' if depth == 0 => return (open == '{').then(|| (&s[1..idx], &s[idx + 1..])),
+            '}' => depth -= 1,
+            '"' if open == '"' && depth == 0 => return Some((&s[1..idx], &s[idx + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Whether or not the character can appear in the name of an abbreviation.
+fn is_name_char(ch: char) -> bool {
+    !ch.is_whitespace() && !"{}(),=#%\"'\\".contains(ch)
+}
+
+/// Parse a single part of a value, which is either delimited, a number, or an abbreviation
+/// which was defined earlier. Returns the expanded part and the remaining input.
+fn value_part<'a>(s: &'a str, defined: &[MacroDefinition]) -> Option<(String, &'a str)> {
+    match s.chars().next()? {
+        '{' | '"' => delimited(s).map(|(value, rest)| (value.to_owned(), rest)),
+        ch if ch.is_ascii_digit() => {
+            let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+            Some((s[..end].to_owned(), &s[end..]))
+        }
+        _ => {
+            let end = s.find(|c| !is_name_char(c)).unwrap_or(s.len());
+            let name = &s[..end];
+            let value = match defined
+                .iter()
+                .find(|def| def.name.eq_ignore_ascii_case(name))
+            {
+                Some(def) => def.value.clone(),
+                None if name.len() == 3 => MONTHS[parse_month(name)? - 1].to_owned(),
+                None => return None,
+            };
+            Some((value, &s[end..]))
+        }
+    }
+}
+
+/// Parse the body of an `@string` entry following the opening delimiter. Returns the definition
+/// and the remaining input after the closing delimiter.
+fn definition<'a>(
+    s: &'a str,
+    close: char,
+    defined: &[MacroDefinition],
+) -> Option<(MacroDefinition, &'a str)> {
+    let s = s.trim_start();
+    let end = s.find(|c| !is_name_char(c)).unwrap_or(s.len());
+    let name = &s[..end];
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let mut rest = s[end..].trim_start().strip_prefix('=')?;
+    let mut value = String::new();
+    loop {
+        let (part, remaining) = value_part(rest.trim_start(), defined)?;
+        value.push_str(&part);
+        rest = remaining.trim_start();
+        match rest.strip_prefix('#') {
+            Some(remaining) => rest = remaining,
+            None => break,
+        }
+    }
+
+    let rest = rest.strip_prefix(close)?;
+    Some((
+        MacroDefinition {
+            name: name.to_owned(),
+            value,
+        },
+        rest,
+    ))
+}
+
+/// Read the `@string` definitions from a BibTeX file. Definitions which cannot be parsed, or
+/// which refer to undefined abbreviations, are skipped. If an abbreviation is defined more than
+/// once, the last definition is used.
+pub fn macro_definitions(bibtex: &[u8]) -> Vec<MacroDefinition> {
+    let text = String::from_utf8_lossy(bibtex);
+    let mut definitions: Vec<MacroDefinition> = Vec::new();
+    let mut rest: &str = &text;
+    while let Some(idx) = rest.find('@') {
+        rest = &rest[idx + 1..];
+        let Some(body) = rest
+            .trim_start()
+            .get(..6)
+            .filter(|ty| ty.eq_ignore_ascii_case("string"))
+            .map(|_| rest.trim_start()[6..].trim_start())
+        else {
+            continue;
+        };
+        let close = match body.chars().next() {
+            Some('{') => '}',
+            Some('(') => ')',
+            _ => continue,
+        };
+        if let Some((def, remaining)) = definition(&body[1..], close, &definitions) {
+            definitions.retain(|existing| !existing.name.eq_ignore_ascii_case(&def.name));
+            definitions.push(def);
+            rest = remaining;
+        }
+    }
+    definitions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn def(name: &str, value: &str) -> MacroDefinition {
+        MacroDefinition {
+            name: name.to_owned(),
+            value: value.to_owned(),
+        }
+    }
+
+    #[test]
+    fn read_definitions() {
+        let bibtex = br#"
+@string{jams = {J. Amer. Math. Soc.}}
+@STRING ( publ = "Springer" # { } # "Verlag" )
+@comment{not a definition}
+@string{date = mar # " " # 2014}
+@article{key, journal = jams}
+@string{jams = {Journal of the AMS}}
+"#;
+        assert_eq!(
+            macro_definitions(bibtex),
+            [
+                def("publ", "Springer Verlag"),
+                def("date", "March 2014"),
+                def("jams", "Journal of the AMS"),
+            ]
+        );
+    }
+
+    #[test]
+    fn skip_invalid_definitions() {
+        let bibtex = br#"
+@string{undefined = missing # {x}}
+@string{unclosed = {value}
+@string{1abc = {value}}
+@string{nested = {A {B} "C"}}
+"#;
+        assert_eq!(macro_definitions(bibtex), [def("nested", "A {B} \"C\"")]);
+    }
+}
@@ -0,0 +1,181 @@
+//! # Formatting of written entries
+//!
+//! Entries are written with the fields indented by two spaces, a single space around each `=`,
+//! and a comma after every field. This matches the serializer of `serde_bibtex`, which is used
+//! unless an [`EntryStyle`] other than the default is requested, in which case the entries are
+//! written by [`EntryStyle::write_entry`].
+use std::io;
+
+use super::{Entry, EntryData};
+
+/// The formatting of written entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryStyle {
+    /// The number of spaces used to indent the fields.
+    pub indent: usize,
+    /// Whether or not to pad the field keys so that the values of the fields are aligned.
+    pub align: bool,
+    /// The column at which values are wrapped, or `0` to not wrap values.
+    pub wrap: usize,
+    /// Whether or not to write a comma after the last field.
+    pub trailing_comma: bool,
+}
+
+impl Default for EntryStyle {
+    fn default() -> Self {
+        Self {
+            indent: 2,
+            align: false,
+            wrap: 0,
+            trailing_comma: true,
+        }
+    }
+}
+
+impl EntryStyle {
+    /// Whether or not this is the default style.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Write the value of a field, where `start` is the column at which the value starts.
+    ///
+    /// If the value does not fit before the wrap column, the value is broken at whitespace and
+    /// the following lines are indented to `start`. Words which are longer than the available
+    /// space are never broken.
+    fn write_value<W: io::Write>(
+        &self,
+        mut writer: W,
+        value: &str,
+        start: usize,
+    ) -> io::Result<()> {
+        // the closing `}` and the comma follow the value
+        if self.wrap == 0 || start + value.chars().count() + 2 <= self.wrap {
+            return writer.write_all(value.as_bytes());
+        }
+
+        let mut column = start;
+        for (idx, word) in value.split_whitespace().enumerate() {
+            let len = word.chars().count();
+            if idx > 0 {
+                if column + 1 + len > self.wrap {
+                    write!(writer, "\n{:start$}", "")?;
+                    column = start;
+                } else {
+                    writer.write_all(b" ")?;
+                    column += 1;
+                }
+            }
+            writer.write_all(word.as_bytes())?;
+            column += len;
+        }
+        Ok(())
+    }
+
+    /// Write a single entry, followed by a newline.
+    pub fn write_entry<W: io::Write, D: EntryData, S: AsRef<str>>(
+        &self,
+        mut writer: W,
+        entry: &Entry<D, S>,
+    ) -> io::Result<()> {
+        write!(writer, "@{}{{{}", entry.entry_type(), entry.key().as_ref())?;
+
+        let fields: Vec<(&str, &str)> = entry.fields().collect();
+        if fields.is_empty() {
+            return writeln!(writer, "{}}}", if self.trailing_comma { "," } else { "" });
+        }
+        writeln!(writer, ",")?;
+
+        let width = if self.align {
+            fields.iter().map(|(key, _)| key.len()).max().unwrap_or(0)
+        } else {
+            0
+        };
+
+        for (idx, (key, value)) in fields.iter().enumerate() {
+            write!(
+                writer,
+                "{:indent$}{key:<width$} = {{",
+                "",
+                indent = self.indent
+            )?;
+            self.write_value(&mut writer, value, self.indent + width.max(key.len()) + 4)?;
+            if idx + 1 < fields.len() || self.trailing_comma {
+                writeln!(writer, "}},")?;
+            } else {
+                writeln!(writer, "}}")?;
+            }
+        }
+        writeln!(writer, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::entry::{EntryKey, MutableEntryData};
+
+    fn entry(fields: &[(&str, &str)]) -> Entry<MutableEntryData> {
+        let mut data = MutableEntryData::try_new("article".to_owned()).unwrap();
+        for (key, value) in fields {
+            data.check_and_insert((*key).to_owned(), (*value).to_owned())
+                .unwrap();
+        }
+        Entry::new(EntryKey::try_new("key".to_owned()).unwrap(), data)
+    }
+
+    fn written(style: EntryStyle, entry: &Entry<MutableEntryData>) -> String {
+        let mut buffer = Vec::new();
+        style.write_entry(&mut buffer, entry).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn default_style() {
+        assert_eq!(
+            written(
+                EntryStyle::default(),
+                &entry(&[("title", "A title"), ("year", "2014")])
+            ),
+            "@article{key,\n  title = {A title},\n  year = {2014},\n}\n"
+        );
+        assert_eq!(
+            written(EntryStyle::default(), &entry(&[])),
+            "@article{key,}\n"
+        );
+    }
+
+    #[test]
+    fn indent_align_comma() {
+        let style = EntryStyle {
+            indent: 4,
+            align: true,
+            wrap: 0,
+            trailing_comma: false,
+        };
+        assert_eq!(
+            written(style, &entry(&[("journal", "J"), ("year", "2014")])),
+            "@article{key,\n    journal = {J},\n    year    = {2014}\n}\n"
+        );
+        assert_eq!(written(style, &entry(&[])), "@article{key}\n");
+    }
+
+    #[test]
+    fn wrap() {
+        let style = EntryStyle {
+            wrap: 30,
+            ..EntryStyle::default()
+        };
+        assert_eq!(
+            written(
+                style,
+                &entry(&[
+                    ("title", "On self-similar sets with overlaps"),
+                    ("year", "2014")
+                ])
+            ),
+            "@article{key,\n  title = {On self-similar\n           sets with overlaps},\n  year = {2014},\n}\n"
+        );
+    }
+}
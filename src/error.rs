@@ -4,6 +4,7 @@ mod bibtex;
 mod database;
 mod format;
 mod provider;
+mod query;
 mod record;
 mod record_data;
 
@@ -15,9 +16,10 @@ use thiserror::Error;
 
 pub use self::{
     bibtex::BibtexDataError,
-    database::DatabaseError,
+    database::{DatabaseError, DumpError, MirrorError},
     format::{KeyParseError, KeyParseErrorKind},
     provider::ProviderError,
+    query::QueryError,
     record::{
         AliasConversionError, AliasErrorKind, RecordError, RecordErrorKind,
         RemoteIdConversionError, RemoteIdErrorKind,
@@ -58,6 +60,62 @@ impl From<rusqlite::Error> for Error {
     }
 }
 
+impl Error {
+    /// The kind of failure which this error represents.
+    pub fn failure_kind(&self) -> FailureKind {
+        match self {
+            Self::ProviderError(_) => FailureKind::Provider,
+            _ => FailureKind::Other,
+        }
+    }
+}
+
+/// The kind of a failure, which determines the exit code of the program.
+///
+/// The exit code `2` is not used, since it is the exit code of invalid command line arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    /// A failure which does not have a more specific kind.
+    Other,
+    /// A provider could not be reached or returned an unexpected response.
+    Provider,
+    /// A remote identifier does not correspond to any data.
+    NullRecord,
+    /// An identifier is not a valid remote identifier or alias.
+    InvalidIdentifier,
+    /// Conflicting data could not be resolved.
+    Conflict,
+}
+
+impl FailureKind {
+    /// The exit code of the program for this kind of failure.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::Other => 1,
+            Self::Provider => 3,
+            Self::NullRecord => 4,
+            Self::InvalidIdentifier => 5,
+            Self::Conflict => 6,
+        }
+    }
+
+    /// The kind of failure represented by an error which stopped the program.
+    pub fn of(err: &anyhow::Error) -> Self {
+        if let Some(err) = err.downcast_ref::<Error>() {
+            err.failure_kind()
+        } else if err.is::<ProviderError>() {
+            Self::Provider
+        } else if err.is::<RecordError>()
+            || err.is::<RemoteIdConversionError>()
+            || err.is::<AliasConversionError>()
+        {
+            Self::InvalidIdentifier
+        } else {
+            Self::Other
+        }
+    }
+}
+
 /// A special error wrapper type which has displays a template syntax error nicely for clap.
 #[derive(Debug)]
 pub struct ClapTemplateError(pub SyntaxError<KeyParseError>);
@@ -22,4 +22,54 @@ pub enum DatabaseError {
     CannotMigrate(i32),
     #[error("Cannot open empty database in read-only mode")]
     EmptyReadOnly,
+    #[error("Overlay database file does not exist: '{0}'")]
+    MissingOverlay(String),
+    #[error(
+        "Overlay database '{0}' does not have the same version as the current binary. Open the overlay database once as the writable database to migrate it."
+    )]
+    IncompatibleOverlay(String),
+    #[error("Backup file does not exist: '{0}'")]
+    MissingBackup(String),
+    #[error(
+        "Backup '{0}' does not have the same version as the current binary. Open the backup once as the writable database to migrate it."
+    )]
+    IncompatibleBackup(String),
+    #[error(
+        "Database '{0}' is locked by another autobib process. Wait for the other process to finish, or increase the time to wait with `--lock-timeout`."
+    )]
+    Locked(String),
+    #[error("Failed to lock database '{0}': {1}")]
+    LockFailed(String, std::io::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum DumpError {
+    #[error("SQLite error: {0}")]
+    SQLiteError(#[from] rusqlite::Error),
+    #[error("IO error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid record data: {0}")]
+    RecordData(#[from] crate::error::RecordDataError),
+    #[error("Invalid identifier '{0}'")]
+    InvalidIdentifier(String),
+    #[error("File is not a database dump: the first line must be a dump header")]
+    MissingHeader,
+    #[error("Unexpected dump header after the first line")]
+    UnexpectedHeader,
+    #[error("Unsupported dump format version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Cannot import a dump into a database which already contains records")]
+    NotEmpty,
+    #[error("Error on line {0}: {1}")]
+    Line(usize, String),
+}
+
+#[derive(Error, Debug)]
+pub enum MirrorError {
+    #[error("SQLite error: {0}")]
+    SQLiteError(#[from] rusqlite::Error),
+    #[error("IO error: {0}")]
+    IO(#[from] std::io::Error),
 }
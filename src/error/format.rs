@@ -46,4 +46,8 @@ pub enum KeyParseErrorKind {
     Empty,
     #[error("{0}")]
     Custom(&'static str),
+    #[error(
+        "A conditional segment contains an {0}.\n      Nested expressions require extended delimiters, like {{#?key: {{key}}#}}"
+    )]
+    InvalidSegment(&'static str),
 }
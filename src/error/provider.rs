@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use thiserror::Error;
 use ureq::http::StatusCode;
 
@@ -15,6 +17,14 @@ pub enum ProviderError {
     NetworkFailure(#[from] ureq::Error),
     #[error("Cannot retrieve remote data for key with local provenance: '{0}'")]
     UnexpectedLocal(String),
+    #[error(
+        "Cannot retrieve remote data for key with user-defined provider: '{0}'. Add the record manually, for instance with `autobib import`."
+    )]
+    UnexpectedCustom(String),
+    #[error(
+        "Cannot retrieve remote data for '{0}' since network access is disabled with `--offline`"
+    )]
+    Offline(String),
     #[error(
         "API server is temporarily inaccessible; try again later. If this is a recurring problem, please report it at https://github.com/autobib/autobib/issues"
     )]
@@ -25,6 +35,14 @@ pub enum ProviderError {
         "API returned response in unexpected format. This is a bug, please report it at 'https://github.com/autobib/autobib/issues', including the below error message:\n> {0}"
     )]
     UnexpectedResponseFormat(String),
+    #[error(
+        "API returned response in unexpected format, and no data could be recovered. This is a bug, please report it at 'https://github.com/autobib/autobib/issues', attaching the response saved to '{}' and including the below error message:\n> {message}",
+        response_file.display()
+    )]
+    UnrecoverableResponse {
+        message: String,
+        response_file: PathBuf,
+    },
     #[error("Unexpected failure: {0}")]
     Unexpected(String),
     #[error("Incompatible data format: {0}")]
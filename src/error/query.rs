@@ -0,0 +1,17 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum QueryError {
+    #[error("Query must be non-empty")]
+    Empty,
+    #[error("Expected {0}, but reached the end of the query")]
+    UnexpectedEof(&'static str),
+    #[error("Expected {0}, found '{1}'")]
+    Unexpected(&'static str, String),
+    #[error("String started with '\"' is unclosed")]
+    UnclosedString,
+    #[error("Invalid regular expression: {0}")]
+    InvalidRegex(#[from] regex::Error),
+    #[error("Invalid date '{0}': expected a date in the format YYYY-MM-DD")]
+    InvalidDate(String),
+}
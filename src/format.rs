@@ -2,7 +2,7 @@ mod parse;
 
 use std::{convert::Infallible, fmt, iter::Peekable, str::FromStr};
 
-use mufmt::{Ast, Manifest, ManifestMut, Span, SyntaxError};
+use mufmt::{Ast, Manifest, ManifestMut, Span, SyntaxError, SyntaxErrorKind};
 use nucleo_picker::Render;
 
 use self::parse::{Kind, Lexer, Token};
@@ -11,6 +11,8 @@ use crate::{
     db::{Identifier, state::RecordRow},
     entry::{EntryData, FieldKey, MutableEntryData, RawEntryData, RawRecordFieldsIter},
     error::{ClapTemplateError, KeyParseError, KeyParseErrorKind},
+    normalize::first_last_name,
+    score::completeness_score,
 };
 
 /// A `{%meta}` token.
@@ -24,6 +26,10 @@ pub enum Meta {
     SubId,
     /// `{%full_id}`
     FullId,
+    /// `{%score}`
+    Score,
+    /// `{%author1_last}`
+    FirstAuthorLast,
 }
 
 impl FromStr for Meta {
@@ -35,6 +41,8 @@ impl FromStr for Meta {
             "provider" => Ok(Self::Provider),
             "sub_id" => Ok(Self::SubId),
             "full_id" => Ok(Self::FullId),
+            "score" => Ok(Self::Score),
+            "author1_last" => Ok(Self::FirstAuthorLast),
             _ => Err(KeyParseErrorKind::InvalidMeta(s.into())),
         }
     }
@@ -112,16 +120,61 @@ impl Atom {
 
 /// An abstract representation of the contents of a `{ ... }` expression in the template.
 ///
-/// This is either a bare token, or a conditional token which only renders if the key is present
-/// or not present in the field keys.
+/// This is either a bare token, a list of alternatives of which the first available one is
+/// rendered, a conditional token which only renders if the key is present or not present in the
+/// field keys, or a conditional segment which renders a nested template if the key is present.
 #[derive(Debug, Clone)]
 pub enum Expression {
     /// `{atom}`: render `atom`
     Bare(Atom),
+    /// `{atom1|atom2|...}`: render the first atom which is available
+    Fallback(Vec<Atom>),
     /// `{=key atom}`: render `atom` if `key` is present
     IfDefined(FieldKey, Atom),
     /// `{!key atom}`: render `atom` if `key` is not present
     IfUndefined(FieldKey, Atom),
+    /// `{#?key: template#}`: render `template` if `key` is present
+    Segment(FieldKey, Template),
+}
+
+impl Expression {
+    /// Parse the remainder of a `{?key: template}` expression after the `?` has been consumed.
+    fn segment_from_lexer(lexer: &mut Lexer<'_>) -> Result<Self, KeyParseError> {
+        static MSG: &str = "a field key";
+        let token = lexer.expect_token(MSG)?;
+        let field_key = match token.kind {
+            Kind::Ident(s) => FieldKey::try_new_normalize(s).spanned(token.span)?,
+            _ => return Err(unexp(MSG, token)),
+        };
+
+        let (offset, body) = lexer.expect_segment_body()?;
+        match Template::compile(body) {
+            Ok(template) => Ok(Self::Segment(field_key, template)),
+            Err(err) => {
+                let span = err.locate();
+                Err(match err.kind {
+                    SyntaxErrorKind::InvalidExpr(KeyParseError { kind, span: inner }) => {
+                        // the span of the inner error is relative to the nested expression
+                        let inner = inner.unwrap_or(0..span.end - span.start);
+                        KeyParseError {
+                            kind,
+                            span: Some(
+                                offset + span.start + inner.start..offset + span.start + inner.end,
+                            ),
+                        }
+                    }
+                    SyntaxErrorKind::ExtraBrace => KeyParseError {
+                        kind: KeyParseErrorKind::InvalidSegment("unopened expression"),
+                        span: Some(offset + span.start..offset + span.end),
+                    },
+                    SyntaxErrorKind::UnclosedExpr => KeyParseError {
+                        kind: KeyParseErrorKind::InvalidSegment("unclosed expression"),
+                        span: Some(offset + span.start..offset + span.end),
+                    },
+                })
+            }
+        }
+    }
 }
 
 impl Ast<'_> for Expression {
@@ -129,6 +182,10 @@ impl Ast<'_> for Expression {
 
     fn from_expr(expr: &str) -> Result<Self, Self::Error> {
         let mut lexer = Lexer::new(expr);
+        if lexer.skip_if_opt().is_some() {
+            return Self::segment_from_lexer(&mut lexer);
+        }
+
         let res = match lexer.skip_if_cond() {
             Some(c) => {
                 // {=key} but now the = has been consumed
@@ -156,7 +213,18 @@ impl Ast<'_> for Expression {
             }
             None => {
                 let atom = Atom::from_lexer(&mut lexer)?;
-                Self::Bare(atom)
+                if lexer.skip_if_alt().is_some() {
+                    let mut atoms = vec![atom];
+                    loop {
+                        atoms.push(Atom::from_lexer(&mut lexer)?);
+                        if lexer.skip_if_alt().is_none() {
+                            break;
+                        }
+                    }
+                    Self::Fallback(atoms)
+                } else {
+                    Self::Bare(atom)
+                }
             }
         };
 
@@ -182,9 +250,12 @@ enum Strategy {
 }
 
 /// An iterator over the field keys in a template, in order of appearance.
+///
+/// The field keys inside a conditional segment are not included, since the nested template is
+/// rendered separately.
 struct TemplateFieldKeys<'a, T> {
     spans: std::slice::Iter<'a, Span<T, Expression>>,
-    buffered: Option<&'a FieldKey>,
+    atoms: std::slice::Iter<'a, Atom>,
 }
 
 impl<'a, T> TemplateFieldKeys<'a, T> {
@@ -192,7 +263,7 @@ impl<'a, T> TemplateFieldKeys<'a, T> {
     pub fn new(template: &'a mufmt::Template<T, Expression>) -> Self {
         Self {
             spans: template.spans().iter(),
-            buffered: None,
+            atoms: [].iter(),
         }
     }
 }
@@ -201,22 +272,29 @@ impl<'a, T> Iterator for TemplateFieldKeys<'a, T> {
     type Item = &'a FieldKey;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(b) = self.buffered.take() {
-            return Some(b);
-        }
-
         loop {
-            match self.spans.next()? {
-                Span::Expr(Expression::Bare(Atom::FieldKey(f) | Atom::FieldKeyOpt(f))) => {
+            if let Some(atom) = self.atoms.next() {
+                if let Atom::FieldKey(f) | Atom::FieldKeyOpt(f) = atom {
                     return Some(f);
                 }
+                continue;
+            }
+
+            match self.spans.next()? {
+                Span::Expr(Expression::Bare(atom)) => {
+                    self.atoms = std::slice::from_ref(atom).iter();
+                }
+                Span::Expr(Expression::Fallback(atoms)) => {
+                    self.atoms = atoms.iter();
+                }
                 Span::Expr(Expression::IfDefined(f, raw) | Expression::IfUndefined(f, raw)) => {
-                    if let Atom::FieldKeyOpt(field_key) | Atom::FieldKey(field_key) = raw {
-                        self.buffered = Some(field_key);
-                    }
+                    self.atoms = std::slice::from_ref(raw).iter();
                     return Some(f);
                 }
-                _ => {}
+                Span::Expr(Expression::Segment(f, _)) => {
+                    return Some(f);
+                }
+                Span::Text(_) => {}
             }
         }
     }
@@ -250,6 +328,7 @@ impl Template {
 
     fn contained_impl<T>(
         &self,
+        row: &RecordRow<RawEntryData>,
         init: impl FnOnce() -> T,
         mut contains: impl FnMut(&str, &mut T) -> bool,
     ) -> bool {
@@ -261,6 +340,14 @@ impl Template {
                 {
                     return false;
                 }
+                Span::Expr(Expression::Fallback(atoms))
+                    if !atoms.iter().any(|atom| match atom {
+                        Atom::FieldKey(k) => contains(k.as_ref(), &mut ctx),
+                        _ => true,
+                    }) =>
+                {
+                    return false;
+                }
                 Span::Expr(Expression::IfDefined(k1, Atom::FieldKey(k2)))
                     if contains(k1.as_ref(), &mut ctx) && !contains(k2.as_ref(), &mut ctx) =>
                 {
@@ -271,6 +358,11 @@ impl Template {
                 {
                     return false;
                 }
+                Span::Expr(Expression::Segment(k, template))
+                    if contains(k.as_ref(), &mut ctx) && !template.has_keys_contained_in(row) =>
+                {
+                    return false;
+                }
                 _ => {}
             }
         }
@@ -282,11 +374,13 @@ impl Template {
     pub fn has_keys_contained_in(&self, row: &RecordRow<RawEntryData>) -> bool {
         match self.strategy {
             Strategy::Sorted => self.contained_impl(
+                row,
                 || BibtexFields::new(row),
                 |k, fields| fields.get_field_ordered(k).is_some(),
             ),
-            Strategy::Small => self.contained_impl(|| (), |k, ()| row.data.contains_field(k)),
+            Strategy::Small => self.contained_impl(row, || (), |k, ()| row.data.contains_field(k)),
             Strategy::Large => self.contained_impl(
+                row,
                 || MutableEntryData::borrow_entry_data(&row.data),
                 |k, data| data.contains_field(k),
             ),
@@ -297,6 +391,12 @@ impl Template {
 /// The default template used by `autobib find`.
 pub const DEFAULT_FIND_TEMPLATE: &str = r#"{author} ~ {title}{=subtitle ". "}{subtitle?}"#;
 
+/// The default template for the names of files downloaded by `autobib attach --auto`.
+pub const DEFAULT_ATTACH_FILENAME: &str = r#"{author?}{=author " - "}{title}.pdf"#;
+
+/// The default template for the layout used by `autobib util relink-attachments`.
+pub const DEFAULT_ATTACH_LAYOUT: &str = "{year}/{%author1_last}";
+
 impl FromStr for Template {
     type Err = ClapTemplateError;
 
@@ -342,6 +442,8 @@ enum DisplayedRow<'row, 'ast, 'state> {
     Row(&'row str),
     Ast(&'ast str),
     State(&'state str),
+    Score(f64),
+    Owned(String),
     Skip,
 }
 
@@ -351,6 +453,8 @@ impl<'r, 'ast, 'state> fmt::Display for DisplayedRow<'r, 'ast, 'state> {
             Self::Row(s) => f.write_str(s),
             Self::Ast(s) => f.write_str(s),
             Self::State(s) => f.write_str(s),
+            Self::Score(score) => write!(f, "{score:.2}"),
+            Self::Owned(s) => f.write_str(s),
             Self::Skip => Ok(()),
         }
     }
@@ -380,9 +484,30 @@ impl<'row, 'ast, 'state> DisplayedRow<'row, 'ast, 'state> {
                     return Self::Skip;
                 }
             }
+            Expression::Segment(field_key, template) => {
+                return if f(field_key.as_ref()).is_some() {
+                    Self::Owned(template.render(row_data))
+                } else {
+                    Self::Skip
+                };
+            }
+            Expression::Fallback(atoms) => {
+                return atoms
+                    .iter()
+                    .map(|atom| Self::from_atom(row_data, atom, &mut f))
+                    .find(|displayed| !matches!(displayed, Self::Skip))
+                    .unwrap_or(Self::Skip);
+            }
             Expression::Bare(token) => token,
         };
 
+        Self::from_atom(row_data, token, f)
+    }
+
+    fn from_atom<F>(row_data: &'row RecordRow<RawEntryData>, token: &'ast Atom, mut f: F) -> Self
+    where
+        F: FnMut(&str) -> Option<&'state str>,
+    {
         match token {
             Atom::FieldKey(key) | Atom::FieldKeyOpt(key) => match f(key.as_ref()) {
                 Some(val) => DisplayedRow::State(val),
@@ -394,6 +519,16 @@ impl<'row, 'ast, 'state> DisplayedRow<'row, 'ast, 'state> {
                 Meta::Provider => DisplayedRow::Row(row_data.canonical.provider()),
                 Meta::SubId => DisplayedRow::Row(row_data.canonical.sub_id()),
                 Meta::FullId => DisplayedRow::Row(row_data.canonical.name()),
+                Meta::Score => DisplayedRow::Score(completeness_score(&row_data.data)),
+                Meta::FirstAuthorLast => match row_data
+                    .data
+                    .get_field("author")
+                    .or_else(|| row_data.data.get_field("editor"))
+                    .and_then(first_last_name)
+                {
+                    Some(name) => DisplayedRow::Owned(name),
+                    None => DisplayedRow::Skip,
+                },
             },
         }
     }
@@ -516,6 +651,13 @@ mod tests {
         check("{a} {=b b}", [("a", "A"), ("b", "B")], true);
         check("{=b a}", [("a", "A"), ("b", "B")], true);
         check("{=c \". \"}", [("a", "A"), ("b", "B")], true);
+        check("{c|a}", [("a", "A")], true);
+        check("{c|d}", [("a", "A")], false);
+        check("{c|d?}", [("a", "A")], true);
+        check(r#"{c|"none"}"#, [("a", "A")], true);
+        check("{#?c: {d}#}", [("a", "A")], true);
+        check("{#?a: {d}#}", [("a", "A")], false);
+        check("{#?a: {d|a}#}", [("a", "A")], true);
     }
 
     #[test]
@@ -538,10 +680,37 @@ mod tests {
         check(r#"{!CH D?}"#, ["ch", "d"]);
         check(r#"{E?}"#, ["e"]);
         check(r#"{(E?)}"#, ["e?"]);
+        check(r#"{c|%entry_type|A?|"b"} {d}"#, ["c", "a", "d"]);
+        check(r#"{#?b: {z} {a}#}{c}"#, ["b", "c"]);
         check(r#""#, []);
         check(r#"Nothing"#, []);
     }
 
+    #[test]
+    fn test_compile_errors() {
+        fn check(s: &str, span: std::ops::Range<usize>) {
+            println!("Testing template: {s}");
+            let err = Template::compile(s).unwrap_err();
+            let outer = err.locate();
+            match err.kind {
+                SyntaxErrorKind::InvalidExpr(KeyParseError { span: inner, .. }) => {
+                    let located = inner.map_or(outer.clone(), |inner| {
+                        outer.start + inner.start..outer.start + inner.end
+                    });
+                    assert_eq!(located, span);
+                }
+                kind => panic!("Unexpected error: {kind:?}"),
+            }
+        }
+
+        check("{a|}", 1..3);
+        check("{a|=b}", 3..4);
+        check("{#?doi doi#}", 6..10);
+        check("{#?doi: {%none}#}", 10..14);
+        check("{#?doi: {doi#}", 8..12);
+        check("{?doi: {doi}}", 7..11);
+    }
+
     #[test]
     fn test_render_row_data() {
         fn check<const N: usize>(
@@ -626,5 +795,77 @@ mod tests {
             Strategy::Sorted,
             "AAAA",
         );
+
+        check(
+            "{title}: {%score}",
+            [("author", "Knuth, Donald"), ("title", "T")],
+            "local",
+            "12345",
+            Strategy::Sorted,
+            "T: 0.20",
+        );
+
+        check(
+            "{year}/{%author1_last}",
+            [("author", "Vincent van Gogh"), ("year", "1888")],
+            "local",
+            "12345",
+            Strategy::Sorted,
+            "1888/Gogh",
+        );
+
+        check(
+            "{%author1_last}",
+            [("editor", "Doe, Jane and Roe, Richard")],
+            "local",
+            "12345",
+            Strategy::Sorted,
+            "Doe",
+        );
+
+        check(
+            r#"{journal|booktitle|"unpublished"}"#,
+            [("booktitle", "Proc."), ("eprint", "1234")],
+            "local",
+            "12345",
+            Strategy::Small,
+            "Proc.",
+        );
+
+        check(
+            r#"{journal | booktitle | "unpublished"}"#,
+            [("eprint", "1234")],
+            "local",
+            "12345",
+            Strategy::Small,
+            "unpublished",
+        );
+
+        check(
+            "{%author1_last|title}",
+            [("title", "T")],
+            "local",
+            "12345",
+            Strategy::Sorted,
+            "T",
+        );
+
+        check(
+            "{title}{#?doi: doi={doi}#}",
+            [("doi", "10.1/x"), ("title", "T")],
+            "local",
+            "12345",
+            Strategy::Small,
+            "Tdoi=10.1/x",
+        );
+
+        check(
+            "{#?doi: doi={doi}#}{title}",
+            [("title", "T")],
+            "local",
+            "12345",
+            Strategy::Sorted,
+            "T",
+        );
     }
 }
@@ -32,6 +32,8 @@ pub enum Kind<'a> {
     Neg,
     /// The '%' character
     Meta,
+    /// The '|' character
+    Alt,
     /// Either a bare identifier in the range `[a-zA-Z0-9_]` or a bracketed identifier `(ident)`
     /// where `ident` does not contain closing brackets.
     Ident(&'a str),
@@ -48,6 +50,7 @@ impl<'a> Kind<'a> {
             Self::Cond => "a conditional marker (=)",
             Self::Neg => "a negation marker (!)",
             Self::Meta => "a meta marker (%)",
+            Self::Alt => "an alternative separator (|)",
             Self::Ident(_) => "an identifier",
             Self::String(_) => "a string",
         }
@@ -103,6 +106,38 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Consume the next token if it is a `|` token, along with any whitespace surrounding it.
+    ///
+    /// Returns `Some` if a token was consumed, and `None` otherwise.
+    pub fn skip_if_alt(&mut self) -> Option<()> {
+        let rem = self.remainder();
+        let trimmed = rem.trim_start().strip_prefix('|')?;
+        self.offset += rem.len() - trimmed.trim_start().len();
+        Some(())
+    }
+
+    /// Consume a `:` character and any whitespace following it, along with the remainder of the
+    /// expression.
+    ///
+    /// Returns the offset and the contents of the remainder.
+    pub fn expect_segment_body(&mut self) -> Result<(usize, &'a str), KeyParseError> {
+        match self.remainder().strip_prefix(':') {
+            Some(body) => {
+                let body = body.trim_start();
+                let start = self.inner.len() - body.len();
+                self.offset = self.inner.len();
+                Ok((start, body))
+            }
+            None if self.remainder().is_empty() => Err(KeyParseError {
+                kind: KeyParseErrorKind::UnexpectedEof("a colon (:)"),
+                span: None,
+            }),
+            None => Err(self.step_err_final(KeyParseErrorKind::Custom(
+                "Expected a colon (:) after the field key",
+            ))),
+        }
+    }
+
     /// A helper function to yield a token and increment the inner offset.
     fn step_ok(&mut self, increment: usize, kind: Kind<'a>) -> Token<'a> {
         let new_offset = self.offset + increment;
@@ -194,6 +229,7 @@ impl<'a> Lexer<'a> {
             '!' => Ok(Some(self.step_ok(1, Kind::Neg))),
             '%' => Ok(Some(self.step_ok(1, Kind::Meta))),
             '?' => Ok(Some(self.step_ok(1, Kind::Opt))),
+            '|' => Ok(Some(self.step_ok(1, Kind::Alt))),
             '(' => {
                 let tail = chars.as_str().as_bytes();
                 match memchr::memchr(b')', tail) {
@@ -255,4 +291,36 @@ mod tests {
         check(&mut lexer, 25..31, Kind::Ident("more_c"));
         assert!(lexer.next_token().unwrap().is_none());
     }
+
+    #[test]
+    fn lexer_alt() {
+        let mut lexer = Lexer::new(r#"a |b| "c""#);
+        assert_eq!(lexer.next_token().unwrap().unwrap().kind, Kind::Ident("a"));
+        assert!(lexer.skip_if_alt().is_some());
+        assert_eq!(lexer.next_token().unwrap().unwrap().kind, Kind::Ident("b"));
+        assert!(lexer.skip_if_alt().is_some());
+        assert!(lexer.skip_if_alt().is_none());
+        assert_eq!(
+            lexer.next_token().unwrap().unwrap(),
+            Token {
+                span: 6..9,
+                kind: Kind::String("c".into())
+            }
+        );
+    }
+
+    #[test]
+    fn lexer_segment_body() {
+        let mut lexer = Lexer::new("doi:  doi={doi}");
+        assert_eq!(
+            lexer.next_token().unwrap().unwrap().kind,
+            Kind::Ident("doi")
+        );
+        assert_eq!(lexer.expect_segment_body().unwrap(), (6, "doi={doi}"));
+        assert!(lexer.expect_eof().is_ok());
+
+        let mut lexer = Lexer::new("doi doi");
+        lexer.next_token().unwrap();
+        assert!(lexer.expect_segment_body().is_err());
+    }
 }
@@ -0,0 +1,139 @@
+//! # Open-access full text
+//!
+//! This module determines the location of an open-access PDF for a record. Records from arXiv, or
+//! with an arXiv eprint, are downloaded directly from arXiv. Otherwise, the DOI of the record is
+//! looked up in the [Unpaywall](https://unpaywall.org/products/api) database, which requires an
+//! email address to be provided with each request.
+use serde::Deserialize;
+use ureq::http::StatusCode;
+
+use crate::{
+    RemoteId,
+    entry::EntryData,
+    error::ProviderError,
+    http::{BodyBytes, Client},
+    logger::{info, warn},
+};
+
+/// The location of an open-access copy of a work.
+#[derive(Deserialize)]
+struct OaLocation {
+    url_for_pdf: Option<String>,
+}
+
+/// The relevant part of an Unpaywall response.
+#[derive(Deserialize)]
+struct UnpaywallResponse {
+    best_oa_location: Option<OaLocation>,
+    #[serde(default)]
+    oa_locations: Vec<OaLocation>,
+}
+
+/// Look up the DOI in the Unpaywall database, returning the URL of the best open-access PDF.
+fn unpaywall_pdf_url<C: Client>(
+    doi: &str,
+    email: &str,
+    client: &C,
+) -> Result<Option<String>, ProviderError> {
+    info!("Making remote request for Unpaywall data of {doi}");
+    let response = client.get(format!("https://api.unpaywall.org/v2/{doi}?email={email}"))?;
+
+    let mut body = match response.status() {
+        StatusCode::OK => response.into_body().bytes()?,
+        StatusCode::NOT_FOUND => {
+            return Ok(None);
+        }
+        code => return Err(ProviderError::UnexpectedStatusCode(code)),
+    };
+
+    match body.read_json() {
+        Ok(UnpaywallResponse {
+            best_oa_location,
+            oa_locations,
+        }) => Ok(best_oa_location
+            .into_iter()
+            .chain(oa_locations)
+            .find_map(|location| location.url_for_pdf)),
+        Err(err) => Err(ProviderError::UnexpectedResponseFormat(format!(
+            "Unexpected Unpaywall data: {err}"
+        ))),
+    }
+}
+
+/// Get the arXiv identifier of the record, either from the canonical identifier or from the
+/// `eprint` field.
+fn arxiv_id<'a, D: EntryData>(canonical: &'a RemoteId, data: &'a D) -> Option<&'a str> {
+    if canonical.provider() == "arxiv" {
+        return Some(canonical.sub_id());
+    }
+
+    let eprint_type = data
+        .get_field("eprinttype")
+        .or_else(|| data.get_field("archiveprefix"))?;
+    if eprint_type.eq_ignore_ascii_case("arxiv") {
+        data.get_field("eprint")
+    } else {
+        None
+    }
+}
+
+/// Get the DOI of the record, either from the canonical identifier or from the `doi` field.
+fn doi<'a, D: EntryData>(canonical: &'a RemoteId, data: &'a D) -> Option<&'a str> {
+    if canonical.provider() == "doi" {
+        Some(canonical.sub_id())
+    } else {
+        data.get_field("doi")
+    }
+}
+
+/// Determine the URL of an open-access PDF for the record, if one exists.
+///
+/// The `email` is required to look up DOIs in the Unpaywall database; if it is not set, only
+/// arXiv is used.
+pub fn open_access_pdf_url<C: Client, D: EntryData>(
+    canonical: &RemoteId,
+    data: &D,
+    email: Option<&str>,
+    client: &C,
+) -> Result<Option<String>, ProviderError> {
+    if let Some(id) = arxiv_id(canonical, data) {
+        return Ok(Some(format!("https://arxiv.org/pdf/{id}")));
+    }
+
+    match (doi(canonical, data), email) {
+        (Some(doi), Some(email)) => unpaywall_pdf_url(doi, email, client),
+        (Some(_), None) => {
+            warn!("Set `attach.email` in your configuration to look up open-access PDFs by DOI");
+            Ok(None)
+        }
+        (None, _) => Ok(None),
+    }
+}
+
+/// Convert the rendered filename template into a valid filename, by replacing the characters
+/// which are not permitted in filenames on common platforms.
+pub fn sanitize_filename(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|ch| match ch {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            ch if ch.is_control() => '_',
+            ch => ch,
+        })
+        .collect();
+    sanitized.trim().trim_start_matches('.').to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(
+            sanitize_filename("Doe, J. - A/B: testing?.pdf"),
+            "Doe, J. - A_B_ testing_.pdf"
+        );
+        assert_eq!(sanitize_filename(" ../x.pdf "), "_x.pdf");
+    }
+}
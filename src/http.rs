@@ -3,17 +3,25 @@
 //! This module provides the [`Client`] trait, which is an abstraction over an HTTP client which
 //! can convert URIs into HTTP Response data.
 
-#[cfg(any(feature = "read_response_cache", feature = "write_response_cache"))]
 pub mod cache;
 
-use std::io;
+use std::{fs::read, io, path::Path, sync::OnceLock};
 
+use anyhow::{anyhow, bail};
 use ureq::{
-    Body,
+    Body, Proxy,
     http::{self, Uri},
+    tls::{PemItem, RootCerts, TlsConfig, parse_pem},
 };
 
-use crate::error::ProviderError;
+use crate::{
+    config::RawHttpConfig,
+    db::Provenance,
+    error::ProviderError,
+    logger::{info, warn},
+    provider::RemoteResponse,
+    record::RemoteId,
+};
 
 /// Abstraction over a HTTP client.
 pub trait Client {
@@ -25,6 +33,31 @@ pub trait Client {
     where
         Uri: TryFrom<T>,
         <Uri as TryFrom<T>>::Error: Into<http::Error>;
+
+    /// Take the response for the provided identifier, along with its provenance, if it was
+    /// obtained ahead of time, such as by a batch request.
+    fn take_prefetched(
+        &self,
+        _remote_id: &RemoteId,
+    ) -> Option<(RemoteResponse, Option<Provenance>)> {
+        None
+    }
+
+    /// Whether or not network access is disabled, in which case every request fails.
+    fn offline(&self) -> bool {
+        false
+    }
+
+    /// Whether or not requests for many identifiers may be combined into a single batch request.
+    fn batch_requests(&self) -> bool {
+        true
+    }
+
+    /// Apply the `[http]` section of the configuration, such as the proxy, to subsequent
+    /// requests. This is called once the configuration is loaded.
+    fn configure(&self, _http_config: &RawHttpConfig) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
 }
 
 pub trait BodyBytes {
@@ -67,38 +100,87 @@ impl BodyBytes for Vec<u8> {
     }
 }
 
+/// Read the root certificates from a PEM file.
+fn read_root_certs(path: &Path) -> Result<RootCerts, anyhow::Error> {
+    let pem = read(path)
+        .map_err(|err| anyhow!("Failed to read CA bundle '{}': {err}", path.display()))?;
+    let mut certs = Vec::new();
+    for item in parse_pem(&pem) {
+        match item {
+            Ok(PemItem::Certificate(cert)) => certs.push(cert),
+            Ok(_) => {}
+            Err(err) => bail!("Invalid CA bundle '{}': {err}", path.display()),
+        }
+    }
+    if certs.is_empty() {
+        bail!(
+            "CA bundle '{}' does not contain certificates",
+            path.display()
+        );
+    }
+    Ok(RootCerts::from(certs))
+}
+
+/// Construct an agent with the correct user agent and the provided configuration.
+///
+/// Unless a proxy is configured, the proxy is read from the `ALL_PROXY`, `HTTPS_PROXY`, or
+/// `HTTP_PROXY` environment variables.
+fn build_agent(http_config: &RawHttpConfig) -> Result<ureq::Agent, anyhow::Error> {
+    static APP_USER_AGENT: &str = concat!(
+        env!("CARGO_PKG_NAME"),
+        "/",
+        env!("CARGO_PKG_VERSION"),
+        " (",
+        env!("CARGO_PKG_HOMEPAGE"),
+        "; ",
+        env!("CARGO_PKG_AUTHORS"),
+        ")",
+    );
+
+    let mut builder = ureq::Agent::config_builder()
+        .user_agent(APP_USER_AGENT)
+        .http_status_as_error(false);
+    if let Some(proxy) = &http_config.proxy {
+        info!("Using proxy '{proxy}'");
+        let proxy = Proxy::new(proxy).map_err(|err| anyhow!("Invalid proxy '{proxy}': {err}"))?;
+        builder = builder.proxy(Some(proxy));
+    }
+    if let Some(path) = &http_config.ca_bundle {
+        info!("Using CA bundle '{}'", path.display());
+        let tls_config = TlsConfig::builder()
+            .root_certs(read_root_certs(path)?)
+            .build();
+        builder = builder.tls_config(tls_config);
+    }
+    Ok(ureq::Agent::new_with_config(builder.build()))
+}
+
 /// The standard HTTP client, which makes genuine HTTP/1.1 requests using an internal
 /// [`ureq::Agent`].
-#[cfg(not(feature = "read_response_cache"))]
+///
+/// The agent is constructed on the first request, using the configuration provided to
+/// [`Client::configure`] if any.
 pub struct UreqClient {
-    inner: ureq::Agent,
+    inner: OnceLock<ureq::Agent>,
 }
 
-#[cfg(not(feature = "read_response_cache"))]
 impl UreqClient {
     /// Construct a new HTTP client with default configuration and correct user agent.
     pub fn new() -> Self {
-        static APP_USER_AGENT: &str = concat!(
-            env!("CARGO_PKG_NAME"),
-            "/",
-            env!("CARGO_PKG_VERSION"),
-            " (",
-            env!("CARGO_PKG_HOMEPAGE"),
-            "; ",
-            env!("CARGO_PKG_AUTHORS"),
-            ")",
-        );
+        Self {
+            inner: OnceLock::new(),
+        }
+    }
 
-        let config = ureq::Agent::config_builder()
-            .user_agent(APP_USER_AGENT)
-            .http_status_as_error(false)
-            .build();
-        let inner = ureq::Agent::new_with_config(config);
-        Self { inner }
+    /// The agent used to make requests.
+    fn agent(&self) -> &ureq::Agent {
+        self.inner.get_or_init(|| {
+            build_agent(&RawHttpConfig::default())
+                .expect("The default configuration should be valid")
+        })
     }
 }
 
-#[cfg(not(feature = "read_response_cache"))]
 impl Client for UreqClient {
     type Body = Body;
 
@@ -107,6 +189,41 @@ impl Client for UreqClient {
         Uri: TryFrom<T>,
         <Uri as TryFrom<T>>::Error: Into<http::Error>,
     {
-        self.inner.get(uri).call()
+        self.agent().get(uri).call()
+    }
+
+    fn configure(&self, http_config: &RawHttpConfig) -> Result<(), anyhow::Error> {
+        let agent = build_agent(http_config)?;
+        if self.inner.set(agent).is_err() {
+            warn!("HTTP configuration was loaded after the first request, and is ignored");
+        }
+        Ok(())
+    }
+}
+
+/// A client which never makes requests, used when network access is disabled with `--offline`.
+/// Every request fails immediately.
+pub struct OfflineClient;
+
+impl Client for OfflineClient {
+    type Body = Vec<u8>;
+
+    fn get<T>(&self, _uri: T) -> Result<http::Response<Vec<u8>>, ureq::Error>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        Err(ureq::Error::Io(io::Error::new(
+            io::ErrorKind::NotConnected,
+            "network access is disabled with `--offline`",
+        )))
+    }
+
+    fn offline(&self) -> bool {
+        true
+    }
+
+    fn batch_requests(&self) -> bool {
+        false
     }
 }
@@ -1,11 +1,24 @@
+//! # HTTP response cache
+//!
+//! The responses to HTTP requests can be recorded to a cache with `--record-http-cache`, and
+//! replayed from the cache with `--replay-http-cache` instead of accessing the network. The cache
+//! is a directory containing a single file `responses.dat`, which maps each requested URI to the
+//! response, encoded with [`bincode`].
+//!
+//! Since the URI of a batch request depends on every identifier in the batch, batch requests are
+//! not used while recording or replaying responses.
 mod format;
-#[cfg(feature = "read_response_cache")]
-pub mod read;
-#[cfg(all(feature = "write_response_cache", not(feature = "read_response_cache")))]
-pub mod write;
+mod read;
+mod write;
 
-use std::{borrow::Cow, collections::HashMap, env::VarError, path::Path};
+use std::{
+    collections::HashMap,
+    fs::{File, create_dir_all},
+    path::{Path, PathBuf},
+};
 
+use anyhow::anyhow;
+use bincode::config;
 use ureq::{
     Body,
     http::{
@@ -14,27 +27,35 @@ use ureq::{
     },
 };
 
-use crate::logger::warn;
 pub use format::ResponseBytes;
-#[cfg(feature = "read_response_cache")]
 pub use read::LocalReadClient;
-
-#[cfg(all(feature = "write_response_cache", not(feature = "read_response_cache")))]
 pub use write::LocalWriteClient;
 
-static DEFAULT_RESPONSE_CACHE_PATH: &str = "response.dat";
+/// The name of the file in the cache directory which contains the responses.
+static RESPONSE_CACHE_FILE: &str = "responses.dat";
 
-fn response_cache_file() -> Cow<'static, Path> {
-    match std::env::var("AUTOBIB_RESPONSE_CACHE_PATH") {
-        Ok(s) => return Cow::Owned(s.into()),
-        Err(VarError::NotPresent) => {}
-        Err(VarError::NotUnicode(_)) => {
-            warn!(
-                "Variable 'AUTOBIB_RESPONSE_CACHE_PATH' is not Unicode. Falling back to default value: '{DEFAULT_RESPONSE_CACHE_PATH}'"
-            );
-        }
-    }
-    Cow::Borrowed(Path::new(DEFAULT_RESPONSE_CACHE_PATH))
+/// The path of the file containing the responses in the provided cache directory.
+fn response_cache_file(dir: &Path) -> PathBuf {
+    dir.join(RESPONSE_CACHE_FILE)
+}
+
+/// Write the responses, keyed by the requested URI, to the `responses.dat` file in the cache
+/// directory, creating the directory if it does not exist.
+pub fn write_response_cache(
+    dir: &Path,
+    responses: &HashMap<String, ResponseBytes>,
+) -> Result<(), anyhow::Error> {
+    let data_file = response_cache_file(dir);
+    create_dir_all(dir)?;
+    let mut lookup_file = File::create(&data_file).map_err(|err| {
+        anyhow!(
+            "Failed to create HTTP response cache '{}': {err}",
+            data_file.display()
+        )
+    })?;
+
+    bincode::encode_into_std_write(responses, &mut lookup_file, config::standard())?;
+    Ok(())
 }
 
 impl TryFrom<&ResponseBytes> for Response<Body> {
@@ -1,32 +1,39 @@
-use std::{collections::HashMap, fs::File};
+use std::{collections::HashMap, fs::File, path::Path};
 
+use anyhow::anyhow;
 use bincode::config;
 use ureq::{Body, http::Uri};
 
 use super::{ResponseBytes, response_cache_file};
 use crate::http::Client;
 
-/// A client which intercepts a request and replaces it with a deserialized response from a dictionary
-/// read from the `responses.dat` file. This file should have been generated by
-/// `LocalWriteClient`.
+/// A client which intercepts a request and replaces it with a deserialized response from a
+/// dictionary read from the `responses.dat` file in the cache directory. This file should have
+/// been generated by `LocalWriteClient`.
 pub struct LocalReadClient {
     lookup: HashMap<String, ResponseBytes>,
 }
 
 impl LocalReadClient {
-    pub fn new() -> Self {
-        let data_file = response_cache_file();
-        let mut lookup_file = File::open(&data_file).unwrap_or_else(|_| {
-            panic!(
-                "Failed to open response data file '{}'!",
+    /// Read the responses from the cache directory.
+    pub fn new(dir: &Path) -> Result<Self, anyhow::Error> {
+        let data_file = response_cache_file(dir);
+        let mut lookup_file = File::open(&data_file).map_err(|err| {
+            anyhow!(
+                "Failed to open HTTP response cache '{}': {err}",
                 data_file.display()
             )
-        });
+        })?;
 
-        let lookup = bincode::decode_from_std_read(&mut lookup_file, config::standard())
-            .expect("Failed to decide binary contents of data file");
+        let lookup =
+            bincode::decode_from_std_read(&mut lookup_file, config::standard()).map_err(|err| {
+                anyhow!(
+                    "Failed to decode HTTP response cache '{}': {err}",
+                    data_file.display()
+                )
+            })?;
 
-        Self { lookup }
+        Ok(Self { lookup })
     }
 }
 
@@ -44,4 +51,8 @@ impl Client for LocalReadClient {
         ))?;
         response_bytes.try_into()
     }
+
+    fn batch_requests(&self) -> bool {
+        false
+    }
 }
@@ -1,14 +1,16 @@
 use std::{
     collections::HashMap,
-    fs::File,
+    path::Path,
     sync::{Arc, Mutex},
 };
 
-use bincode::config;
 use ureq::http::Uri;
 
-use super::{ResponseBytes, response_cache_file};
-use crate::http::{Client, UreqClient};
+use super::{ResponseBytes, write_response_cache};
+use crate::{
+    config::RawHttpConfig,
+    http::{Client, UreqClient},
+};
 
 /// A client which intercepts HTTP responses and writes them to a response cache file for
 /// subsequent use by `LocalReadClient`.
@@ -27,17 +29,10 @@ impl LocalWriteClient {
 }
 
 impl LocalWriteClient {
-    pub fn serialize(self) {
-        let data_file = response_cache_file();
-        let mut lookup_file = File::create(&data_file).unwrap_or_else(|_| {
-            panic!(
-                "Failed to create response cache file '{}'",
-                data_file.display()
-            )
-        });
-
-        bincode::encode_into_std_write(&self.lookup, &mut lookup_file, config::standard())
-            .expect("Failed to encode lookup table");
+    /// Write the recorded responses to the `responses.dat` file in the cache directory, creating
+    /// the directory if it does not exist.
+    pub fn serialize(self, dir: &Path) -> Result<(), anyhow::Error> {
+        write_response_cache(dir, &self.lookup.lock().unwrap())
     }
 }
 
@@ -51,14 +46,20 @@ impl Client for LocalWriteClient {
     {
         let uri = Uri::try_from(uri).map_err(Into::into)?;
         let key = uri.to_string();
-        let res = self
-            .inner
-            .get::<Uri>(uri)?
-            .map(|mut body| body.read_to_vec().expect("Failed to read request body!"));
+        let (parts, mut body) = self.inner.get::<Uri>(uri)?.into_parts();
+        let res = ureq::http::Response::from_parts(parts, body.read_to_vec()?);
 
         let mut lookup = self.lookup.lock().unwrap();
         lookup.insert(key, ResponseBytes::from(&res));
 
         Ok(res)
     }
+
+    fn batch_requests(&self) -> bool {
+        false
+    }
+
+    fn configure(&self, http_config: &RawHttpConfig) -> Result<(), anyhow::Error> {
+        self.inner.configure(http_config)
+    }
 }
@@ -0,0 +1,64 @@
+//! # Interrupt handling
+//!
+//! Long-running loops which retrieve records catch `Ctrl-C` with a [`CatchInterrupt`] guard. The
+//! signal only sets a flag, which the loop checks before it processes the next item, so that the
+//! record which is currently being processed is finished and its transaction is committed. A
+//! second `Ctrl-C` terminates the process immediately.
+//!
+//! On non-Unix systems, `Ctrl-C` is not caught and [`interrupted`] always returns `false`.
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether `Ctrl-C` was pressed since the last [`CatchInterrupt`] guard was created.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn handle_sigint(_: libc::c_int) {
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+        // SAFETY: `signal` and `raise` are async-signal-safe, and the default handler terminates
+        // the process.
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            libc::raise(libc::SIGINT);
+        }
+    }
+}
+
+/// A guard which catches `Ctrl-C` until it is dropped, at which point the previous signal handler
+/// is restored.
+pub struct CatchInterrupt {
+    #[cfg(unix)]
+    previous: libc::sighandler_t,
+}
+
+impl CatchInterrupt {
+    /// Start catching `Ctrl-C`, and reset the flag returned by [`interrupted`].
+    pub fn new() -> Self {
+        INTERRUPTED.store(false, Ordering::SeqCst);
+
+        #[cfg(unix)]
+        {
+            let handler = handle_sigint as extern "C" fn(libc::c_int);
+            // SAFETY: the handler only performs async-signal-safe operations.
+            let previous = unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) };
+            Self { previous }
+        }
+
+        #[cfg(not(unix))]
+        Self {}
+    }
+}
+
+impl Drop for CatchInterrupt {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        // SAFETY: the previous handler was returned by `signal`, so it is valid.
+        unsafe {
+            libc::signal(libc::SIGINT, self.previous);
+        }
+    }
+}
+
+/// Check if `Ctrl-C` was pressed while a [`CatchInterrupt`] guard was active.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
@@ -1,26 +1,58 @@
+use chrono::Local;
 use crossterm::style::{StyledContent, Stylize};
 pub use log::{Level, max_level};
 use log::{Log, Metadata, Record};
 pub use log::{debug, info, trace, warn};
 use std::{
     fmt,
-    io::{self, IsTerminal},
-    sync::atomic::{AtomicBool, Ordering},
+    fs::{File, OpenOptions},
+    io::{self, IsTerminal, Write},
+    path::Path,
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicBool, AtomicU8, Ordering},
+    },
 };
 
-static HAS_ERROR: AtomicBool = AtomicBool::new(false);
+use crate::error::FailureKind;
+
+/// The exit code of the program, or `0` if no error occurred.
+static EXIT_CODE: AtomicU8 = AtomicU8::new(0);
+
+/// Whether or not messages are written to standard error as JSON.
+static JSON_FORMAT: AtomicBool = AtomicBool::new(false);
 
 pub fn reraise<E: fmt::Debug + fmt::Display>(err: &E) {
     error!("{err}");
     trace!("{err:?}");
 }
 
+/// Log the error as in [`reraise`], and record the kind of failure.
+pub fn reraise_as<E: fmt::Debug + fmt::Display>(kind: FailureKind, err: &E) {
+    set_failure(kind);
+    reraise(err);
+}
+
+/// Write a message to standard error as a single line of JSON, where the level is the header
+/// without the trailing `:`.
+fn write_json(header: &'static str, args: &std::fmt::Arguments) {
+    let message = serde_json::json!({
+        "time": Local::now().to_rfc3339(),
+        "level": header.trim_end_matches(':'),
+        "message": args.to_string(),
+    });
+    eprintln!("{message}");
+}
+
 pub(crate) fn log_with_style<Y: FnOnce(&'static str) -> StyledContent<&'static str>>(
     style: Y,
     header: &'static str,
     args: &std::fmt::Arguments,
 ) {
-    if io::stderr().is_terminal() {
+    if is_json_format() {
+        write_json(header, args);
+    } else if io::stderr().is_terminal() {
+        crate::progress::clear();
         eprintln!("{} {args}", style(header));
     } else {
         eprintln!("{header} {args}");
@@ -42,7 +74,16 @@ macro_rules! suggest {
 
 /// A convenience macro to combine the simultaneously error log with [`error`](log::error)  and
 /// also call [`set_failed`].
+///
+/// The kind of failure can be provided as `error!(kind = FailureKind::NullRecord; ...)`, in
+/// which case [`set_failure`] is called instead.
 macro_rules! error {
+    (kind = $kind:expr; $($arg:tt)+) => {
+        {
+            crate::logger::set_failure($kind);
+            ::log::error!($($arg)+)
+        }
+    };
     ($($arg:tt)+) => {
         // macro must return a block since `error!` can be called in expression position
         {
@@ -54,10 +95,37 @@ macro_rules! error {
 
 pub(crate) use {error, suggest};
 
-pub struct Logger {}
+/// The logger, which writes messages to standard error, and optionally also to a log file and to
+/// the system log.
+pub struct Logger {
+    /// The log file, if enabled.
+    file: OnceLock<Mutex<File>>,
+    /// Whether or not to also write messages to the system log.
+    syslog: AtomicBool,
+}
 
 pub fn set_failed() {
-    HAS_ERROR.store(true, Ordering::Release);
+    set_failure(FailureKind::Other);
+}
+
+/// Record that a failure of the provided kind occurred. The exit code is determined by the first
+/// failure which has a specific kind, and is otherwise the exit code of [`FailureKind::Other`].
+pub fn set_failure(kind: FailureKind) {
+    let code = kind.exit_code();
+    let other = FailureKind::Other.exit_code();
+    let _ = EXIT_CODE.fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+        (current == 0 || (current == other && code != other)).then_some(code)
+    });
+}
+
+/// Write messages to standard error as JSON instead of human-readable text.
+pub fn set_json_format() {
+    JSON_FORMAT.store(true, Ordering::Release);
+}
+
+/// Whether or not messages are written to standard error as JSON.
+pub fn is_json_format() -> bool {
+    JSON_FORMAT.load(Ordering::Acquire)
 }
 
 #[inline]
@@ -82,9 +150,66 @@ fn level_formatter(level: Level) -> fn(&'static str) -> StyledContent<&'static s
     }
 }
 
+#[cfg(unix)]
+#[inline]
+fn level_as_syslog_priority(level: Level) -> libc::c_int {
+    match level {
+        Level::Error => libc::LOG_ERR,
+        Level::Warn => libc::LOG_WARNING,
+        Level::Info => libc::LOG_INFO,
+        Level::Debug | Level::Trace => libc::LOG_DEBUG,
+    }
+}
+
 impl Logger {
+    pub const fn new() -> Self {
+        Self {
+            file: OnceLock::new(),
+            syslog: AtomicBool::new(false),
+        }
+    }
+
     pub fn has_error() -> bool {
-        HAS_ERROR.load(Ordering::Acquire)
+        EXIT_CODE.load(Ordering::Acquire) != 0
+    }
+
+    /// The exit code of the program, which is `0` if no error occurred.
+    pub fn exit_code() -> i32 {
+        EXIT_CODE.load(Ordering::Acquire).into()
+    }
+
+    /// Also append messages to the file at the provided path, with a timestamp.
+    pub fn log_to_file(&self, path: &Path) -> Result<(), io::Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let _ = self.file.set(Mutex::new(file));
+        Ok(())
+    }
+
+    /// Also write messages to the system log, which is forwarded to the journal on systems
+    /// running systemd.
+    #[cfg(unix)]
+    pub fn log_to_syslog(&self) -> Result<(), io::Error> {
+        // SAFETY: the identifier is a static nul-terminated string, as required by `openlog`
+        unsafe {
+            libc::openlog(c"autobib".as_ptr(), libc::LOG_PID, libc::LOG_USER);
+        }
+        self.syslog.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// The system log is only supported on Unix platforms.
+    #[cfg(not(unix))]
+    pub fn log_to_syslog(&self) -> Result<(), io::Error> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the system log is only supported on Unix platforms",
+        ))
+    }
+}
+
+impl Default for Logger {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -98,10 +223,44 @@ impl Log for Logger {
     fn log(&self, record: &Record) {
         let level = record.level();
         log_with_style(level_formatter(level), level_as_str(level), record.args());
+
+        if let Some(file) = self.file.get()
+            && let Ok(mut file) = file.lock()
+        {
+            // failing to write to the log file should not interrupt the program
+            let _ = writeln!(
+                file,
+                "{} {} {}",
+                Local::now().format("%Y-%m-%dT%H:%M:%S%.3f%:z"),
+                level_as_str(level),
+                record.args()
+            );
+        }
+
+        #[cfg(unix)]
+        if self.syslog.load(Ordering::Acquire)
+            && let Ok(message) = std::ffi::CString::new(record.args().to_string())
+        {
+            // SAFETY: the format string and the message are nul-terminated strings, and the
+            // format string consumes exactly one string argument
+            unsafe {
+                libc::syslog(
+                    level_as_syslog_priority(level),
+                    c"%s".as_ptr(),
+                    message.as_ptr(),
+                );
+            }
+        }
     }
 
     #[inline]
-    fn flush(&self) {}
+    fn flush(&self) {
+        if let Some(file) = self.file.get()
+            && let Ok(mut file) = file.lock()
+        {
+            let _ = file.flush();
+        }
+    }
 }
 
 pub trait LogDisplay {
@@ -5,27 +5,33 @@ pub mod db;
 mod entry;
 pub mod error;
 pub mod format;
+mod fulltext;
 mod http;
+mod interrupt;
 mod logger;
 mod normalize;
 mod output;
 mod path_hash;
+mod pdf;
+mod progress;
 pub mod provider;
+mod query;
 mod record;
+mod score;
 pub mod term;
 
 use std::process::exit;
 
-use clap::{CommandFactory, Parser};
-use clap_complete::aot::generate;
+use clap::Parser;
 
 use crate::output::stdout_lock_wrap;
 
 use self::{
-    app::{Cli, Command, run_cli},
+    app::{Cli, Command, LogFormat, run_cli, write_completions},
     db::Identifier,
     entry::RawEntryData,
-    logger::{Logger, reraise},
+    error::FailureKind,
+    logger::{Logger, error, info, reraise_as, set_json_format},
 };
 
 pub use self::{
@@ -36,7 +42,7 @@ pub use self::{
     term::{Confirm, Editor, EditorConfig},
 };
 
-static LOGGER: Logger = Logger {};
+static LOGGER: Logger = Logger::new();
 
 fn main() {
     #[cfg(not(debug_assertions))]
@@ -60,9 +66,10 @@ The following is a description of the error which occured:
 
     // generate completions upon request and exit
     if let Command::Completions { shell } = cli.command {
-        let mut clap_command = Cli::command();
-        let bin_name = clap_command.get_name().to_owned();
-        generate(shell, &mut clap_command, bin_name, &mut stdout_lock_wrap());
+        if let Err(err) = write_completions(shell, &mut stdout_lock_wrap()) {
+            eprintln!("Failed to write completions script: {err}");
+            exit(1)
+        }
         return;
     }
 
@@ -74,25 +81,49 @@ The following is a description of the error which occured:
         .map(|()| log::set_max_level(cli.verbose.log_level_filter()))
         .unwrap();
 
-    #[cfg(not(any(feature = "write_response_cache", feature = "read_response_cache")))]
-    let client = http::UreqClient::new();
+    if cli.log_format == LogFormat::Json {
+        set_json_format();
+    }
 
-    #[cfg(all(feature = "write_response_cache", not(feature = "read_response_cache")))]
-    let client = http::cache::LocalWriteClient::new();
+    if let Some(path) = &cli.log_file
+        && let Err(err) = LOGGER.log_to_file(path)
+    {
+        error!("Failed to open log file '{}': {err}", path.display());
+    }
 
-    #[cfg(feature = "read_response_cache")]
-    let client = http::cache::LocalReadClient::new();
+    if cli.syslog
+        && let Err(err) = LOGGER.log_to_syslog()
+    {
+        error!("Failed to open system log: {err}");
+    }
 
     // run the cli
-    if let Err(err) = run_cli(cli, &client) {
-        reraise(&err);
+    if let Err(err) = run_with_client(cli) {
+        reraise_as(FailureKind::of(&err), &err);
     }
 
     // check if there was a non-fatal error during execution
     if Logger::has_error() {
-        exit(1)
+        exit(Logger::exit_code())
     }
+}
 
-    #[cfg(all(feature = "write_response_cache", not(feature = "read_response_cache")))]
-    client.serialize()
+/// Run the cli with the HTTP client determined by the global options.
+fn run_with_client(cli: Cli) -> anyhow::Result<()> {
+    if cli.offline {
+        run_cli(cli, &http::OfflineClient)
+    } else if let Some(dir) = cli.replay_http_cache.clone() {
+        info!("Replaying HTTP responses from '{}'", dir.display());
+        run_cli(cli, &http::cache::LocalReadClient::new(&dir)?)
+    } else if let Some(dir) = cli.record_http_cache.clone() {
+        let client = http::cache::LocalWriteClient::new();
+        let res = run_cli(cli, &client);
+        // the responses are written even if the command failed, since they are useful to
+        // reproduce the failure
+        info!("Recording HTTP responses to '{}'", dir.display());
+        client.serialize(&dir)?;
+        res
+    } else {
+        run_cli(cli, &http::UreqClient::new())
+    }
 }
@@ -1,8 +1,12 @@
 //! Utilities for normalizing BibTeX data
+mod names;
+
 use std::{slice::Iter, str::CharIndices};
 
 use serde::Deserialize;
 
+pub use self::names::{NameFormat, first_last_name, is_normalized_names, normalize_names_str};
+
 /// A normalization which can be applied to bibliographic record data.
 #[derive(Debug, Default, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields)]
@@ -13,13 +17,44 @@ pub struct Normalization {
     pub set_eprint: Vec<String>,
     #[serde(default)]
     pub strip_journal_series: bool,
+    #[serde(default)]
+    pub protect_case: bool,
+    /// The words protected by `protect_case`. This is not read from the `on_insert` section
+    /// directly, but is instead set from the `normalize.protected_words` configuration value.
+    #[serde(skip)]
+    pub protected_words: Vec<String>,
+    #[serde(default)]
+    pub normalize_names: bool,
+    /// The convention used by `normalize_names`. This is set from the `normalize.name_format`
+    /// configuration value.
+    #[serde(skip)]
+    pub name_format: NameFormat,
+    #[serde(default)]
+    pub article_number: ArticleNumberField,
+}
+
+/// The field in which the article number of an article in an electronic-only journal is stored.
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArticleNumberField {
+    /// Keep the article number in the `eid` field, which is supported by biblatex.
+    #[default]
+    Eid,
+    /// Move the article number into the `pages` field, for BibTeX styles which do not support
+    /// the `eid` field.
+    Pages,
 }
 
 impl Normalization {
     /// Returns `true` if the normalization is guaranteed to do nothing. Note that the
     /// normalization, when applied to a record, may still not result in any changes.
     pub fn is_identity(&self) -> bool {
-        !self.normalize_whitespace && !self.strip_journal_series && self.set_eprint.is_empty()
+        !self.normalize_whitespace
+            && !self.strip_journal_series
+            && self.set_eprint.is_empty()
+            && (!self.protect_case || self.protected_words.is_empty())
+            && !self.normalize_names
+            && self.article_number == ArticleNumberField::Eid
     }
 }
 
@@ -43,6 +78,18 @@ pub trait Normalize {
     /// Strip trailing numbered series indicators, such as the (2) in `Ann. Math. (2)`
     fn strip_journal_series(&mut self) -> bool;
 
+    /// Protect the capitalization of the provided words in title fields by wrapping them in
+    /// braces, such as `{BibTeX}`.
+    fn protect_case<S: AsRef<str>>(&mut self, words: &[S]) -> bool;
+
+    /// Rewrite the names in name list fields, such as `author` and `editor`, using the
+    /// provided convention.
+    fn normalize_names(&mut self, format: NameFormat) -> bool;
+
+    /// Move the article number in the `eid` field into the `pages` field, if the `pages` field
+    /// is not set.
+    fn article_number_to_pages(&mut self) -> bool;
+
     /// Apply the given normalizations.
     #[inline]
     fn normalize(&mut self, nl: &Normalization) -> bool {
@@ -57,6 +104,18 @@ pub trait Normalize {
             changed |= self.strip_journal_series();
         }
 
+        if nl.protect_case {
+            changed |= self.protect_case(&nl.protected_words);
+        }
+
+        if nl.normalize_names {
+            changed |= self.normalize_names(nl.name_format);
+        }
+
+        if nl.article_number == ArticleNumberField::Pages {
+            changed |= self.article_number_to_pages();
+        }
+
         changed
     }
 }
@@ -166,6 +225,80 @@ pub fn normalize_whitespace_str(input: &str) -> Option<String> {
     }
 }
 
+/// Wrap every occurrence of a word in `words` with braces, as long as the occurrence is not
+/// already contained in braces and is not part of a larger word or a TeX command. If multiple
+/// words match at the same position, the longest word is protected.
+///
+/// If the input requires modification, return the new string. Otherwise, the original input
+/// is already protected.
+pub fn protect_case_str<S: AsRef<str>>(input: &str, words: &[S]) -> Option<String> {
+    /// Find the longest word which starts at the beginning of `tail` and ends at a word boundary.
+    fn longest_match<'a, S: AsRef<str>>(tail: &str, words: &'a [S]) -> Option<&'a str> {
+        words
+            .iter()
+            .map(AsRef::as_ref)
+            .filter(|word| {
+                !word.is_empty()
+                    && tail.starts_with(word)
+                    && tail[word.len()..]
+                        .chars()
+                        .next()
+                        .is_none_or(|ch| !ch.is_alphanumeric())
+            })
+            .max_by_key(|word| word.len())
+    }
+
+    let mut output = String::new();
+    // the offset up to which the input has been copied into the output
+    let mut copied = 0;
+    let mut depth: usize = 0;
+    // whether or not the previous character ends a word
+    let mut at_boundary = true;
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((offset, ch)) = chars.next() {
+        match ch {
+            '\\' => {
+                // skip the control sequence, which is either a single character, or a sequence
+                // of alphabetic characters
+                if let Some((_, next)) = chars.next()
+                    && next.is_alphabetic()
+                {
+                    while chars.next_if(|(_, ch)| ch.is_alphabetic()).is_some() {}
+                }
+                at_boundary = false;
+            }
+            '{' => {
+                depth += 1;
+                at_boundary = true;
+            }
+            '}' => {
+                depth = depth.saturating_sub(1);
+                at_boundary = true;
+            }
+            ch if depth == 0 && at_boundary && ch.is_alphanumeric() => {
+                if let Some(word) = longest_match(&input[offset..], words) {
+                    output.push_str(&input[copied..offset]);
+                    output.push('{');
+                    output.push_str(word);
+                    output.push('}');
+                    copied = offset + word.len();
+                    while chars.next_if(|(offset, _)| *offset < copied).is_some() {}
+                }
+                at_boundary = false;
+            }
+            ch => at_boundary = !ch.is_alphanumeric(),
+        }
+    }
+
+    if copied == 0 {
+        None
+    } else {
+        output.push_str(&input[copied..]);
+        Some(output)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -290,4 +423,43 @@ mod tests {
             Some("a 🍄 c".to_owned())
         );
     }
+
+    #[test]
+    fn test_protect_case() {
+        let words = ["BibTeX", "Navier–Stokes", "Navier", "MR"];
+
+        assert_eq!(
+            protect_case_str("Using BibTeX", &words),
+            Some("Using {BibTeX}".to_owned())
+        );
+        assert_eq!(
+            protect_case_str("The Navier–Stokes equations", &words),
+            Some("The {Navier–Stokes} equations".to_owned())
+        );
+        assert_eq!(
+            protect_case_str("Navier and MR: MR", &words),
+            Some("{Navier} and {MR}: {MR}".to_owned())
+        );
+
+        // already protected, or not a whole word
+        assert_eq!(protect_case_str("Using {BibTeX}", &words), None);
+        assert_eq!(protect_case_str("{Using BibTeX}", &words), None);
+        assert_eq!(protect_case_str("BibTeXing MRI", &words), None);
+        assert_eq!(protect_case_str("SMR", &words), None);
+
+        // TeX commands
+        assert_eq!(
+            protect_case_str("\\MR MR", &words),
+            Some("\\MR {MR}".to_owned())
+        );
+        assert_eq!(
+            protect_case_str("\\'{e} BibTeX", &words),
+            Some("\\'{e} {BibTeX}".to_owned())
+        );
+
+        // edge cases
+        assert_eq!(protect_case_str("", &words), None);
+        assert_eq!(protect_case_str("MR", &words), Some("{MR}".to_owned()));
+        assert_eq!(protect_case_str("MR", &[""]), None);
+    }
 }
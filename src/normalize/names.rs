@@ -0,0 +1,363 @@
+//! A parser for BibTeX name lists, such as the values of the `author` and `editor` fields.
+//!
+//! A name list is a list of names separated by the word `and`. Each name is in one of the forms
+//! ```text
+//! First von Last
+//! von Last, First
+//! von Last, Jr, First
+//! ```
+//! where the `von` part is the sequence of words which begin with a lowercase letter.
+use serde::Deserialize;
+
+/// The convention used to write names in a name list.
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NameFormat {
+    /// Write names as `von Last, First`, or `von Last, Jr, First` if there is a `Jr` part.
+    #[default]
+    LastFirst,
+    /// Write names as `First von Last`, falling back to `von Last, Jr, First` if there is a `Jr`
+    /// part or if the `First von Last` form would be ambiguous.
+    FirstLast,
+}
+
+/// A token in a name list.
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Word(&'a str),
+    Comma,
+}
+
+/// Split the input into words and commas at brace depth 0.
+fn tokenize(input: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut depth: usize = 0;
+    let mut word_start: Option<usize> = None;
+    let mut chars = input.char_indices();
+
+    while let Some((offset, ch)) = chars.next() {
+        if depth == 0 && (ch.is_whitespace() || ch == ',') {
+            if let Some(start) = word_start.take() {
+                tokens.push(Token::Word(&input[start..offset]));
+            }
+            if ch == ',' {
+                tokens.push(Token::Comma);
+            }
+            continue;
+        }
+
+        word_start.get_or_insert(offset);
+        match ch {
+            '\\' => {
+                // the escaped character is part of the word
+                chars.next();
+            }
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    if let Some(start) = word_start {
+        tokens.push(Token::Word(&input[start..]));
+    }
+
+    tokens
+}
+
+/// Determine if a word is lowercase, in the sense of BibTeX: the case of a word is the case of
+/// the first letter at brace depth 0, or the first letter of a special character `{\...}`.
+/// Words without any such letter are not lowercase.
+fn is_lowercase_word(word: &str) -> bool {
+    let mut chars = word.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' => {
+                if chars.next_if_eq(&'\\').is_some() {
+                    // a special character: use the first letter inside
+                    return chars
+                        .find(|ch| ch.is_alphabetic() || *ch == '}')
+                        .is_some_and(char::is_lowercase);
+                }
+
+                // skip the braced group, which is treated as caseless
+                let mut depth: usize = 1;
+                for ch in chars.by_ref() {
+                    match ch {
+                        '{' => depth += 1,
+                        '}' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            '\\' => {
+                // a command outside of braces: skip the command name
+                while chars.next_if(|ch| ch.is_alphabetic()).is_some() {}
+            }
+            ch if ch.is_alphabetic() => return ch.is_lowercase(),
+            _ => {}
+        }
+    }
+    false
+}
+
+/// A single parsed name.
+#[derive(Debug, PartialEq)]
+struct Name<'a> {
+    first: Vec<&'a str>,
+    /// The `von` and `Last` parts.
+    last: Vec<&'a str>,
+    jr: Vec<&'a str>,
+}
+
+impl<'a> Name<'a> {
+    /// Parse a name from the tokens, returning `None` if the name is empty or has too many
+    /// commas.
+    fn parse(tokens: &[Token<'a>]) -> Option<Self> {
+        let mut parts: Vec<Vec<&'a str>> = vec![Vec::new()];
+        for token in tokens {
+            match token {
+                Token::Word(word) => parts.last_mut()?.push(*word),
+                Token::Comma => parts.push(Vec::new()),
+            }
+        }
+
+        let mut parts = parts.into_iter();
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(words), None, None, None) => {
+                let (&final_word, rest) = words.split_last()?;
+                match rest.iter().position(|word| is_lowercase_word(word)) {
+                    Some(von_start) => Some(Self {
+                        first: words[..von_start].to_vec(),
+                        last: words[von_start..].to_vec(),
+                        jr: Vec::new(),
+                    }),
+                    None => Some(Self {
+                        first: rest.to_vec(),
+                        last: vec![final_word],
+                        jr: Vec::new(),
+                    }),
+                }
+            }
+            (Some(last), Some(first), None, None) if !last.is_empty() => Some(Self {
+                first,
+                last,
+                jr: Vec::new(),
+            }),
+            (Some(last), Some(jr), Some(first), None) if !last.is_empty() => {
+                Some(Self { first, last, jr })
+            }
+            _ => None,
+        }
+    }
+
+    /// Write the name in the `von Last, Jr, First` form.
+    fn write_last_first(&self, output: &mut String) {
+        output.push_str(&self.last.join(" "));
+        if !self.jr.is_empty() {
+            output.push_str(", ");
+            output.push_str(&self.jr.join(" "));
+            output.push(',');
+            if !self.first.is_empty() {
+                output.push(' ');
+            }
+        } else if !self.first.is_empty() {
+            output.push_str(", ");
+        }
+        output.push_str(&self.first.join(" "));
+    }
+
+    /// Write the name in the `First von Last` form, if this can be done without changing the
+    /// meaning of the name, and otherwise in the `von Last, Jr, First` form.
+    fn write_first_last(&self, output: &mut String) {
+        if self.jr.is_empty() {
+            let candidate = self
+                .first
+                .iter()
+                .chain(self.last.iter())
+                .copied()
+                .collect::<Vec<_>>()
+                .join(" ");
+            if Name::parse(&tokenize(&candidate)).as_ref() == Some(self) {
+                output.push_str(&candidate);
+                return;
+            }
+        }
+        self.write_last_first(output);
+    }
+}
+
+/// Write a BibTeX name list using the provided [`NameFormat`], returning `None` if the input
+/// cannot be parsed as a name list.
+fn write_names(input: &str, format: NameFormat) -> Option<String> {
+    let tokens = tokenize(input);
+    let mut output = String::with_capacity(input.len());
+
+    for (idx, name_tokens) in tokens
+        .split(|token| matches!(token, Token::Word(word) if word.eq_ignore_ascii_case("and")))
+        .enumerate()
+    {
+        if idx > 0 {
+            output.push_str(" and ");
+        }
+
+        // the special name 'others' is not a real name
+        if let [Token::Word("others")] = name_tokens {
+            output.push_str("others");
+            continue;
+        }
+
+        let name = Name::parse(name_tokens)?;
+        match format {
+            NameFormat::LastFirst => name.write_last_first(&mut output),
+            NameFormat::FirstLast => name.write_first_last(&mut output),
+        }
+    }
+
+    Some(output)
+}
+
+/// Rewrite a BibTeX name list using the provided [`NameFormat`].
+///
+/// If the input requires modification, return the new string. Otherwise, the original input is
+/// already normalized. If the input cannot be parsed as a name list, `None` is returned.
+pub fn normalize_names_str(input: &str, format: NameFormat) -> Option<String> {
+    write_names(input, format).filter(|output| output != input)
+}
+
+/// Determine the last name of the first name in a BibTeX name list, without the `von` part.
+///
+/// Returns `None` if the first name cannot be parsed.
+pub fn first_last_name(input: &str) -> Option<String> {
+    let tokens = tokenize(input);
+    let first = tokens
+        .split(|token| matches!(token, Token::Word(word) if word.eq_ignore_ascii_case("and")))
+        .next()?;
+    let name = Name::parse(first)?;
+
+    let last = name
+        .last
+        .iter()
+        .copied()
+        .skip_while(|word| is_lowercase_word(word))
+        .collect::<Vec<_>>();
+    if last.is_empty() {
+        // the name only has a `von` part
+        Some(name.last.join(" "))
+    } else {
+        Some(last.join(" "))
+    }
+}
+
+/// Check if a BibTeX name list is already written in one of the [`NameFormat`] conventions.
+pub fn is_normalized_names(input: &str) -> bool {
+    [NameFormat::LastFirst, NameFormat::FirstLast]
+        .into_iter()
+        .any(|format| write_names(input, format).as_deref() == Some(input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_lowercase_word() {
+        assert!(is_lowercase_word("von"));
+        assert!(is_lowercase_word("{\\'e}tienne"));
+        assert!(is_lowercase_word("{Von}der"));
+        assert!(!is_lowercase_word("Knuth"));
+        assert!(!is_lowercase_word("{von}"));
+        assert!(!is_lowercase_word("{\\'E}mile"));
+        assert!(!is_lowercase_word("D.~E."));
+        assert!(!is_lowercase_word("123"));
+    }
+
+    #[test]
+    fn test_normalize_names_last_first() {
+        fn check(input: &str, expected: Option<&str>) {
+            assert_eq!(
+                normalize_names_str(input, NameFormat::LastFirst).as_deref(),
+                expected
+            );
+        }
+
+        check("Donald E. Knuth", Some("Knuth, Donald E."));
+        check("Knuth, Donald E.", None);
+        check("Knuth,   Donald E.", Some("Knuth, Donald E."));
+        check(
+            "Charles Louis Xavier Joseph de la Vallée Poussin",
+            Some("de la Vallée Poussin, Charles Louis Xavier Joseph"),
+        );
+        check("Ludwig van Beethoven", Some("van Beethoven, Ludwig"));
+        check("King, Jr, Martin Luther", None);
+        check(
+            "D.~E. Knuth and Leslie Lamport and others",
+            Some("Knuth, D.~E. and Lamport, Leslie and others"),
+        );
+        check(
+            "Knuth, Donald AND Lamport, Leslie",
+            Some("Knuth, Donald and Lamport, Leslie"),
+        );
+        check("{Barnes and Noble, Inc.}", None);
+        check("Aristotle", None);
+        check("{\\'E}mile Borel", Some("Borel, {\\'E}mile"));
+
+        // cannot be parsed
+        check("A, B, C, D", None);
+        check("Knuth and and Lamport", None);
+        check(", Donald", None);
+    }
+
+    #[test]
+    fn test_normalize_names_first_last() {
+        fn check(input: &str, expected: Option<&str>) {
+            assert_eq!(
+                normalize_names_str(input, NameFormat::FirstLast).as_deref(),
+                expected
+            );
+        }
+
+        check("Knuth, Donald E.", Some("Donald E. Knuth"));
+        check("van Beethoven, Ludwig", Some("Ludwig van Beethoven"));
+        check("Donald E. Knuth", None);
+        // ambiguous, so it is not rewritten
+        check("Brinch Hansen, Per", None);
+        check("King, Jr, Martin Luther", None);
+    }
+
+    #[test]
+    fn test_first_last_name() {
+        assert_eq!(
+            first_last_name("Hochman, Michael").as_deref(),
+            Some("Hochman")
+        );
+        assert_eq!(
+            first_last_name("Vincent van Gogh and Doe, Jane").as_deref(),
+            Some("Gogh")
+        );
+        assert_eq!(
+            first_last_name("de la Fontaine, Jean").as_deref(),
+            Some("Fontaine")
+        );
+        assert_eq!(
+            first_last_name("{Barnes and Noble} and Doe, Jane").as_deref(),
+            Some("{Barnes and Noble}")
+        );
+        assert_eq!(first_last_name(""), None);
+    }
+
+    #[test]
+    fn test_is_normalized_names() {
+        assert!(is_normalized_names("Knuth, Donald E. and Lamport, Leslie"));
+        assert!(is_normalized_names("Donald E. Knuth and Leslie Lamport"));
+        assert!(is_normalized_names("{Barnes and Noble, Inc.}"));
+        assert!(!is_normalized_names("Knuth, Donald E. and Leslie Lamport"));
+        assert!(!is_normalized_names("Knuth,   Donald E."));
+        assert!(!is_normalized_names("A, B, C, D"));
+    }
+}
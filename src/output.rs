@@ -10,6 +10,13 @@ macro_rules! owriteln {
 
 pub(crate) use owriteln;
 
+/// Write a value to standard output as pretty-printed JSON, followed by a newline.
+pub(crate) fn owrite_json<T: serde::Serialize + ?Sized>(value: &T) -> io::Result<()> {
+    let mut lock = stdout_lock_wrap();
+    serde_json::to_writer_pretty(&mut lock, value)?;
+    writeln!(lock)
+}
+
 // The following section is copied with modification from the `pipecheck` crate by Alex Hamlin
 // under the MIT License (included below).
 
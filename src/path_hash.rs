@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use data_encoding::BASE32;
 use rapidhash::v1::rapidhash_v1;
@@ -41,3 +41,73 @@ impl PathHash for RemoteId {
         ]);
     }
 }
+
+/// The number of path components appended by [`PathHash::extend_attachments_path`].
+pub const ATTACHMENTS_PATH_DEPTH: usize = 5;
+
+/// Recover the [`RemoteId`] from a path relative to the attachment root. This is the inverse of
+/// [`PathHash::extend_attachments_path`], and returns `None` if the path was not produced by a
+/// valid identifier.
+pub fn remote_id_from_attachments_path(path: &Path) -> Option<RemoteId> {
+    let remote_id = remote_id_from_stale_attachments_path(path)?;
+
+    // the header must also match, and the identifier must not have been normalized
+    let mut expected = PathBuf::new();
+    remote_id.extend_attachments_path(&mut expected);
+    (expected == path).then_some(remote_id)
+}
+
+/// Recover the [`RemoteId`] from a path relative to the attachment root, without checking that
+/// the path is the one which is currently produced by the identifier.
+///
+/// This is used to relocate attachment directories whose path is out of date, for instance since
+/// the header does not match or since the identifier is normalized differently.
+pub fn remote_id_from_stale_attachments_path(path: &Path) -> Option<RemoteId> {
+    let mut components = path.components().map(|c| c.as_os_str().to_str());
+
+    let provider = components.next()??;
+    for _ in 0..3 {
+        components.next()??;
+    }
+    let sub_id_encoded = components.next()??;
+    if components.next().is_some() {
+        return None;
+    }
+
+    let sub_id = String::from_utf8(BASE32.decode(sub_id_encoded.as_bytes()).ok()?).ok()?;
+    RemoteId::from_parts(provider, &sub_id).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attachments_path_roundtrip() {
+        for id in [
+            "zbmath:06346461",
+            "doi:10.1016/0021-8693(89)90256-1",
+            "local:a b",
+        ] {
+            let remote_id: RemoteId = id.parse().unwrap();
+            let mut path = PathBuf::new();
+            remote_id.extend_attachments_path(&mut path);
+            assert_eq!(remote_id_from_attachments_path(&path), Some(remote_id));
+        }
+
+        assert_eq!(
+            remote_id_from_attachments_path(Path::new("zbmath/AA/AA/AA/GA3DGNBWGQ3DC===")),
+            None
+        );
+        assert_eq!(
+            remote_id_from_attachments_path(Path::new("zbmath/JX/TT/CT")),
+            None
+        );
+
+        let stale = Path::new("zbmath/AA/AA/AA/GA3DGNBWGQ3DC===");
+        assert_eq!(
+            remote_id_from_stale_attachments_path(stale),
+            Some("zbmath:06346461".parse().unwrap())
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! # Identifiers from PDF files
+//!
+//! A minimal extractor for the DOI and arXiv identifiers of the article contained in a PDF file.
+//! This does not attempt to parse the PDF structure: the identifiers are searched for in the raw
+//! file, which contains the document information dictionary, the XMP metadata, and the link
+//! annotations, and in the contents of the streams compressed with `/FlateDecode`, which contain
+//! the page text.
+//!
+//! Since a PDF file also typically contains the identifiers of the works which it cites, the
+//! candidates are ordered by the number of times that they appear in the file.
+use std::{collections::HashMap, io::Read, sync::LazyLock};
+
+use flate2::read::ZlibDecoder;
+use memchr::memmem;
+use regex::bytes::Regex;
+
+use crate::RemoteId;
+
+/// The maximum number of bytes read from a single decompressed stream.
+const MAX_STREAM_LEN: u64 = 1 << 24;
+
+static DOI_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b10\.[0-9]{4,9}/[^\s\x00\x28\x29<>\[\]{}\x22\\]+").unwrap());
+
+static ARXIV_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(?:arxiv:\s?|arxiv\.org/(?:abs|pdf)/)([0-9]{4}\.[0-9]{4,5}|[a-z-]+(?:\.[a-z]{2})?/[0-9]{7})(?:v[0-9]+)?\b").unwrap()
+});
+
+/// Iterate over the decompressed contents of the streams with the `/FlateDecode` filter.
+fn flate_streams(buffer: &[u8]) -> impl Iterator<Item = Vec<u8>> {
+    let mut pos = 0;
+    std::iter::from_fn(move || {
+        loop {
+            let start = pos + memmem::find(&buffer[pos..], b"stream")?;
+            pos = start + 6;
+
+            // the keyword `stream` is followed by an end-of-line marker, and preceded by the
+            // stream dictionary
+            let data_start = match &buffer[pos..] {
+                [b'\r', b'\n', ..] => pos + 2,
+                [b'\n', ..] => pos + 1,
+                _ => continue,
+            };
+            let dict_start = memmem::rfind(&buffer[..start], b"obj").unwrap_or(0);
+            let is_flate = memmem::find(&buffer[dict_start..start], b"/FlateDecode").is_some();
+
+            let data_end = memmem::find(&buffer[data_start..], b"endstream")
+                .map_or(buffer.len(), |len| data_start + len);
+            pos = (data_end + 9).min(buffer.len());
+
+            if is_flate {
+                let mut decoded = Vec::new();
+                // a stream which fails to decompress can still contain useful data
+                let _ = ZlibDecoder::new(&buffer[data_start..data_end])
+                    .take(MAX_STREAM_LEN)
+                    .read_to_end(&mut decoded);
+                return Some(decoded);
+            }
+        }
+    })
+}
+
+/// Record the occurrences of the identifiers in the text.
+fn count_identifiers(text: &[u8], counts: &mut HashMap<(&'static str, String), (usize, usize)>) {
+    let mut record = |provider: &'static str, sub_id: &[u8]| {
+        if let Ok(sub_id) = std::str::from_utf8(sub_id) {
+            let order = counts.len();
+            counts
+                .entry((provider, sub_id.to_owned()))
+                .or_insert((0, order))
+                .0 += 1;
+        }
+    };
+
+    for m in DOI_RE.find_iter(text) {
+        // trailing punctuation is not part of the DOI
+        let doi = m.as_bytes();
+        let len = doi.len()
+            - doi
+                .iter()
+                .rev()
+                .take_while(|b| b".,;:'".contains(b))
+                .count();
+        record("doi", &doi[..len]);
+    }
+
+    for c in ARXIV_RE.captures_iter(text) {
+        // SAFETY: the regex has a non-optional capture group
+        record("arxiv", c.get(1).unwrap().as_bytes());
+    }
+}
+
+/// Get the candidate identifiers of the article contained in the PDF file, ordered from most
+/// likely to least likely.
+pub fn identifiers_from_pdf(buffer: &[u8]) -> Vec<RemoteId> {
+    let mut counts = HashMap::new();
+    count_identifiers(buffer, &mut counts);
+    for stream in flate_streams(buffer) {
+        count_identifiers(&stream, &mut counts);
+    }
+
+    let mut candidates: Vec<_> = counts.into_iter().collect();
+    candidates.sort_by_key(|(_, (count, order))| (std::cmp::Reverse(*count), *order));
+    candidates
+        .into_iter()
+        .filter_map(|((provider, sub_id), _)| RemoteId::from_parts(provider, &sub_id).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{Compression, write::ZlibEncoder};
+
+    use super::*;
+
+    #[test]
+    fn test_identifiers_from_pdf() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        // the text is repeated so that it is not written to the stream uncompressed
+        for _ in 0..3 {
+            encoder
+                .write_all(
+                    b"BT (DOI: 10.1000/xyz123. See also arXiv:2101.00001v2 [math.CA]) Tj ET\n",
+                )
+                .unwrap();
+        }
+        let compressed = encoder.finish().unwrap();
+
+        let mut pdf = b"%PDF-1.7
+1 0 obj << /Type /Annot /A << /URI (https://doi.org/10.1000/xyz123) >> >> endobj
+2 0 obj << /Length 10 /Filter /FlateDecode >>
+stream
+"
+        .to_vec();
+        pdf.extend(compressed);
+        pdf.extend(
+            b"
+endstream
+endobj
+3 0 obj << /URI (https://doi.org/10.1000/other) >> endobj
+%%EOF",
+        );
+
+        assert_eq!(
+            identifiers_from_pdf(&pdf),
+            vec![
+                RemoteId::from_parts("doi", "10.1000/xyz123").unwrap(),
+                RemoteId::from_parts("arxiv", "2101.00001").unwrap(),
+                RemoteId::from_parts("doi", "10.1000/other").unwrap(),
+            ]
+        );
+    }
+}
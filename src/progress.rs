@@ -0,0 +1,183 @@
+//! # Progress bars
+//!
+//! Long-running loops, such as retrieving many records or importing a large file, show a
+//! [`Progress`] bar on standard error with the number of items which were processed and an
+//! estimate of the remaining time. The bar is only shown if standard error is a terminal, warnings
+//! are not silenced with `--quiet`, and the log messages are not written as JSON. It is first
+//! drawn once the loop has been running for a short time, so that fast loops, such as loops over
+//! records which are already in the database, are not cluttered.
+//!
+//! Log messages clear the bar before they are written, and the bar is drawn again once the next
+//! item is processed.
+use std::{
+    io::{self, IsTerminal, Write},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use log::LevelFilter;
+
+use crate::logger::{Level, is_json_format, max_level};
+
+/// The time after which a bar is first drawn.
+const DELAY: Duration = Duration::from_millis(500);
+
+/// The minimum time between two draws of a bar.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The number of characters in a full bar.
+const WIDTH: usize = 30;
+
+/// Whether or not a bar is currently drawn on the last line of standard error.
+static DRAWN: AtomicBool = AtomicBool::new(false);
+
+/// Clear the bar, if one is drawn, so that a message can be written to standard error.
+pub fn clear() {
+    if DRAWN.swap(false, Ordering::AcqRel) {
+        let _ = write!(io::stderr(), "\r\x1b[2K");
+    }
+}
+
+/// Whether or not bars are shown, given whether standard error is a terminal, the maximum log
+/// level, and whether the log messages are written as JSON.
+fn show_bars(is_terminal: bool, level: LevelFilter, json: bool) -> bool {
+    is_terminal && level >= Level::Warn && !json
+}
+
+/// A progress bar for a loop over a known number of items, which is cleared when dropped.
+pub struct Progress {
+    /// The description of the loop, such as `Retrieving`.
+    label: &'static str,
+    /// The total number of items.
+    total: usize,
+    /// The number of items which were processed.
+    done: usize,
+    /// The time at which the loop started.
+    start: Instant,
+    /// The time at which the bar was last drawn.
+    last_draw: Option<Instant>,
+    /// Whether or not the bar is shown at all.
+    enabled: bool,
+}
+
+impl Progress {
+    /// Start a bar for a loop over `total` items.
+    pub fn new(label: &'static str, total: usize) -> Self {
+        Self {
+            label,
+            total,
+            done: 0,
+            start: Instant::now(),
+            last_draw: None,
+            enabled: show_bars(io::stderr().is_terminal(), max_level(), is_json_format()),
+        }
+    }
+
+    /// Record that an item was processed, drawing the bar if required.
+    pub fn inc(&mut self) {
+        self.done += 1;
+        if !self.enabled {
+            return;
+        }
+
+        let now = Instant::now();
+        if now.duration_since(self.start) < DELAY
+            || self
+                .last_draw
+                .is_some_and(|last_draw| now.duration_since(last_draw) < REDRAW_INTERVAL)
+        {
+            return;
+        }
+        self.last_draw = Some(now);
+        self.draw(now);
+    }
+
+    /// Advance the bar for each item of the iterator once the following item is requested, which
+    /// is when the loop has finished processing the item.
+    pub fn iter<I: IntoIterator>(self, iter: I) -> ProgressIter<I::IntoIter> {
+        ProgressIter {
+            iter: iter.into_iter(),
+            progress: self,
+            started: false,
+        }
+    }
+
+    fn draw(&self, now: Instant) {
+        let done = self.done.min(self.total);
+        let filled = (done * WIDTH).checked_div(self.total).unwrap_or(WIDTH);
+
+        let mut stderr = io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "\r\x1b[2K{} [{}{}] {done}/{}",
+            self.label,
+            "=".repeat(filled),
+            " ".repeat(WIDTH - filled),
+            self.total,
+        );
+
+        // the remaining time is estimated from the average time per item so far
+        if done > 0 {
+            let elapsed = now.duration_since(self.start).as_secs_f64();
+            let remaining = (elapsed * (self.total - done) as f64 / done as f64) as u64;
+            let _ = write!(stderr, " ETA {}:{:02}", remaining / 60, remaining % 60);
+        }
+        let _ = stderr.flush();
+        DRAWN.store(true, Ordering::Release);
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        clear();
+    }
+}
+
+/// An iterator which advances a [`Progress`] bar, returned by [`Progress::iter`].
+pub struct ProgressIter<I> {
+    iter: I,
+    progress: Progress,
+    started: bool,
+}
+
+impl<I: Iterator> Iterator for ProgressIter<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.started {
+            self.progress.inc();
+        }
+        self.started = true;
+        self.iter.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_show_bars() {
+        assert!(show_bars(true, LevelFilter::Warn, false));
+        assert!(show_bars(true, LevelFilter::Trace, false));
+
+        // standard error is not a terminal
+        assert!(!show_bars(false, LevelFilter::Warn, false));
+        // warnings are silenced with `--quiet`
+        assert!(!show_bars(true, LevelFilter::Error, false));
+        assert!(!show_bars(true, LevelFilter::Off, false));
+        // log messages are written as JSON
+        assert!(!show_bars(true, LevelFilter::Warn, true));
+    }
+
+    #[test]
+    fn test_disabled_bar_is_not_drawn() {
+        let mut progress = Progress::new("Testing", 2);
+        progress.enabled = false;
+        progress.start -= 2 * DELAY;
+
+        progress.inc();
+        assert!(progress.last_draw.is_none());
+        assert!(!DRAWN.load(Ordering::Acquire));
+    }
+}
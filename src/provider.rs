@@ -4,26 +4,45 @@
 //! The fundamental types are [`Resolver`], [`Referrer`], and [`Validator`], which abstract over
 //! resource acquisition and resolution from a provider.
 mod arxiv;
+pub mod custom;
 mod doi;
+mod gbooks;
 mod isbn;
 mod jfm;
+mod lenient;
 mod local;
 mod mr;
 mod ol;
+mod url;
 mod zbl;
 mod zbmath;
 
+use std::{cell::RefCell, collections::HashMap, io::Read};
+
+pub use self::{
+    isbn::{DEFAULT_ISBN_SOURCES, IsbnSource, register_sources as register_isbn_sources},
+    lenient::{INCOMPLETE_FIELD, set_response_dir},
+    url::map_url,
+};
+
+use chrono::Local;
+use data_encoding::HEXLOWER;
+use ring::digest::{SHA256, digest};
 use serde::Deserialize;
-use ureq::http::StatusCode;
+use ureq::http::{self, StatusCode, Uri};
 
 // re-imports exposed to provider implementations
 use crate::{
     MappedKey, RemoteId,
+    db::Provenance,
     entry::{EntryData, EntryType, MutableEntryData},
     error::{ProviderError, RecordDataError},
     http::{BodyBytes, Client},
+    logger::{info, warn},
 };
 
+use self::lenient::{ResponseFormat, recover};
+
 /// A resolver, which converts a `sub_id` into [`MutableEntryData`].
 type Resolver<C> = fn(&str, &C) -> Result<Option<MutableEntryData>, ProviderError>;
 
@@ -33,14 +52,25 @@ type Referrer<C> = fn(&str, &C) -> Result<Option<RemoteId>, ProviderError>;
 /// A validator, which checks that a `sub_id` is valid.
 type Validator = fn(&str) -> ValidationOutcome;
 
+/// A batch resolver, which converts multiple [`RemoteId`]s into [`RemoteResponse`]s using a
+/// single request. The returned responses need not be in the same order as the provided
+/// identifiers, and identifiers without a response are resolved individually.
+type BatchResolver<C> =
+    fn(&[&RemoteId], &C) -> Result<Vec<(RemoteId, RemoteResponse)>, ProviderError>;
+
+/// A batch of identifiers for a single provider, along with its [`BatchResolver`] and the
+/// maximum number of identifiers per request.
+type Batch<'r, C> = (BatchResolver<C>, usize, Vec<&'r RemoteId>);
+
 /// A provider, which is either a [`Resolver`] or a [`Referrer`].
 enum Provider<C: Client> {
     Resolver(Resolver<C>),
     Referrer(Referrer<C>),
 }
 
-pub const REMOTE_PROVIDERS: [&str; 8] =
-    ["arxiv", "doi", "isbn", "jfm", "mr", "ol", "zbmath", "zbl"];
+pub const REMOTE_PROVIDERS: [&str; 10] = [
+    "arxiv", "doi", "gbooks", "isbn", "jfm", "mr", "ol", "url", "zbmath", "zbl",
+];
 
 /// Map the `provider` part of a [`RemoteId`] to a [`Resolver`] or [`Referrer`].
 #[inline]
@@ -48,11 +78,13 @@ fn lookup_provider<C: Client>(provider: &str) -> Provider<C> {
     match provider {
         "arxiv" => Provider::Resolver(arxiv::get_record),
         "doi" => Provider::Resolver(doi::get_record),
+        "gbooks" => Provider::Resolver(gbooks::get_record),
         "isbn" => Provider::Referrer(isbn::get_canonical),
         "jfm" => Provider::Referrer(jfm::get_canonical),
         "local" => Provider::Resolver(local::get_record),
         "mr" => Provider::Resolver(mr::get_record),
         "ol" => Provider::Resolver(ol::get_record),
+        "url" => Provider::Referrer(url::get_canonical),
         "zbmath" => Provider::Resolver(zbmath::get_record),
         "zbl" => Provider::Referrer(zbl::get_canonical),
         _ => unreachable!(
@@ -61,17 +93,32 @@ fn lookup_provider<C: Client>(provider: &str) -> Provider<C> {
     }
 }
 
+/// Map the `provider` part of a [`RemoteId`] to a [`BatchResolver`], if the provider supports batch
+/// requests. Also returns the name of the batch, since providers which share an API can be
+/// resolved in the same batch, and the maximum number of identifiers in a single batch.
+#[inline]
+fn lookup_batch_resolver<C: Client>(
+    provider: &str,
+) -> Option<(&'static str, BatchResolver<C>, usize)> {
+    match provider {
+        "zbmath" | "zbl" => Some(("zbmath", zbmath::get_records, zbmath::MAX_BATCH_SIZE)),
+        _ => None,
+    }
+}
+
 /// Validate a [`RemoteId`].
 #[inline]
 fn lookup_validator(provider: &str) -> Option<Validator> {
     match provider {
         "arxiv" => Some(arxiv::is_valid_id),
         "doi" => Some(doi::is_valid_id),
+        "gbooks" => Some(gbooks::is_valid_id),
         "isbn" => Some(isbn::is_valid_id),
         "jfm" => Some(jfm::is_valid_id),
         "local" => Some(local::is_valid_id),
         "mr" => Some(mr::is_valid_id),
         "ol" => Some(ol::is_valid_id),
+        "url" => Some(url::is_valid_id),
         "zbmath" => Some(zbmath::is_valid_id),
         "zbl" => Some(zbl::is_valid_id),
         _ => None,
@@ -180,11 +227,11 @@ pub fn determine_remote_id_candidates<K: Ord, D: EntryData, F: FnMut(&RemoteId)
         }
     }
 
-    let mut br = candidate_canonical.map(|c| {
+    let mut bc = candidate_canonical.map(|c| {
         let s = score(&c.mapped);
         (c, s)
     });
-    let mut bc = candidate_reference.map(|c| {
+    let mut br = candidate_reference.map(|c| {
         let s = score(&c.mapped);
         (c, s)
     });
@@ -234,7 +281,13 @@ pub fn validate_provider_sub_id(provider: &str, sub_id: &str) -> ValidationOutco
             ValidationOutcome::Normalize(s) => ValidationOutcomeExtended::Normalize(s),
             ValidationOutcome::Invalid => ValidationOutcomeExtended::InvalidSubId,
         },
-        None => ValidationOutcomeExtended::InvalidProvider,
+        None => match custom::lookup(provider) {
+            Some(custom) => match custom.is_valid_id(sub_id) {
+                ValidationOutcome::Valid => ValidationOutcomeExtended::Valid,
+                _ => ValidationOutcomeExtended::InvalidSubId,
+            },
+            None => ValidationOutcomeExtended::InvalidProvider,
+        },
     }
 }
 
@@ -243,7 +296,7 @@ pub fn suggest_valid_remote_identifiers<E, F>(sub_id: &str, mut cb: F) -> Result
 where
     F: FnMut(RemoteId) -> Result<(), E>,
 {
-    for provider in REMOTE_PROVIDERS {
+    for provider in REMOTE_PROVIDERS.into_iter().chain(custom::names()) {
         if let Ok(new) = RemoteId::from_parts(provider, sub_id) {
             cb(new)?;
         }
@@ -254,7 +307,13 @@ where
 /// Check if the given string corresponds to a valid provider.
 #[inline]
 pub fn is_valid_provider(provider: &str) -> bool {
-    lookup_validator(provider).is_some()
+    lookup_validator(provider).is_some() || custom::lookup(provider).is_some()
+}
+
+/// The URL of the record with the provided identifier, which is only known for user-defined
+/// providers with a URL template.
+pub fn record_url(remote_id: &RemoteId) -> Option<String> {
+    custom::lookup(remote_id.provider())?.url(remote_id.sub_id())
 }
 
 #[inline]
@@ -263,11 +322,14 @@ pub fn is_canonical(provider: &str) -> bool {
     // lookup_validator(provider).is_some()
     //     && matches!(lookup_provider::<C>(provider), Provider::Resolver(_))
     match provider {
-        "arxiv" | "doi" | "local" | "mr" | "ol" | "zbmath" => true,
-        "isbn" | "jfm" | "zbl" => false,
-        _ => unreachable!(
-            "Invalid provider '{provider}: an invalid provider should have been caught by a call to `lookup_validator`'!"
-        ),
+        "arxiv" | "doi" | "gbooks" | "local" | "mr" | "ol" | "zbmath" => true,
+        "isbn" | "jfm" | "url" | "zbl" => false,
+        _ => match custom::lookup(provider) {
+            Some(custom) => custom.canonical,
+            None => unreachable!(
+                "Invalid provider '{provider}: an invalid provider should have been caught by a call to `lookup_validator`'!"
+            ),
+        },
     }
 }
 
@@ -277,11 +339,14 @@ pub fn is_reference(provider: &str) -> bool {
     // lookup_validator(provider).is_some()
     //     && matches!(lookup_provider::<C>(provider), Provider::Referrer(_))
     match provider {
-        "arxiv" | "doi" | "local" | "mr" | "ol" | "zbmath" => false,
-        "isbn" | "jfm" | "zbl" => true,
-        _ => unreachable!(
-            "Invalid provider '{provider}: an invalid provider should have been caught by a call to `lookup_validator`'!"
-        ),
+        "arxiv" | "doi" | "gbooks" | "local" | "mr" | "ol" | "zbmath" => false,
+        "isbn" | "jfm" | "url" | "zbl" => true,
+        _ => match custom::lookup(provider) {
+            Some(custom) => !custom.canonical,
+            None => unreachable!(
+                "Invalid provider '{provider}: an invalid provider should have been caught by a call to `lookup_validator`'!"
+            ),
+        },
     }
 }
 
@@ -295,21 +360,210 @@ pub enum RemoteResponse {
     Null,
 }
 
+/// A [`Client`] which records the URL and the hash of the response of the first successful
+/// request, which is the request whose response contains the data.
+struct ProvenanceClient<'c, C> {
+    inner: &'c C,
+    first: RefCell<Option<(String, String)>>,
+}
+
+impl<'c, C: Client> ProvenanceClient<'c, C> {
+    fn new(inner: &'c C) -> Self {
+        Self {
+            inner,
+            first: RefCell::new(None),
+        }
+    }
+
+    /// The provenance of the data returned by the provider, if a request was successful.
+    fn into_provenance(self, provider: &str) -> Option<Provenance> {
+        self.first.into_inner().map(|(url, sha256)| Provenance {
+            provider: provider.to_owned(),
+            url,
+            retrieved: Local::now(),
+            sha256,
+        })
+    }
+}
+
+impl<C: Client> Client for ProvenanceClient<'_, C> {
+    type Body = Vec<u8>;
+
+    fn get<T>(&self, uri: T) -> Result<http::Response<Self::Body>, ureq::Error>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        let uri = Uri::try_from(uri).map_err(Into::<http::Error>::into)?;
+        let url = uri.to_string();
+        let (parts, mut body) = self.inner.get::<Uri>(uri)?.into_parts();
+
+        // the body is read here so that it can be hashed
+        let mut bytes = Vec::new();
+        body.as_reader()
+            .read_to_end(&mut bytes)
+            .map_err(ureq::Error::Io)?;
+
+        let mut first = self.first.borrow_mut();
+        if first.is_none() && parts.status.is_success() {
+            *first = Some((url, HEXLOWER.encode(digest(&SHA256, &bytes).as_ref())));
+        }
+        Ok(http::Response::from_parts(parts, bytes))
+    }
+
+    fn offline(&self) -> bool {
+        self.inner.offline()
+    }
+
+    fn batch_requests(&self) -> bool {
+        self.inner.batch_requests()
+    }
+}
+
 /// Obtain the [`RemoteResponse`] by looking up the [`RemoteId`] using the provided `client`.
 #[inline]
 pub fn get_remote_response<C: Client>(
     client: &C,
     remote_id: &RemoteId,
 ) -> Result<RemoteResponse, ProviderError> {
-    match lookup_provider(remote_id.provider()) {
-        Provider::Resolver(resolver) => match resolver(remote_id.sub_id(), client)? {
-            Some(data) => Ok(RemoteResponse::Data(data)),
-            None => Ok(RemoteResponse::Null),
+    get_remote_response_with_provenance(client, remote_id).map(|(response, _)| response)
+}
+
+/// Obtain the [`RemoteResponse`] by looking up the [`RemoteId`] using the provided `client`,
+/// along with the [`Provenance`] of the response if it was retrieved from the provider.
+pub fn get_remote_response_with_provenance<C: Client>(
+    client: &C,
+    remote_id: &RemoteId,
+) -> Result<(RemoteResponse, Option<Provenance>), ProviderError> {
+    if let Some(prefetched) = client.take_prefetched(remote_id) {
+        info!("Using response for '{remote_id}' from batch request");
+        return Ok(prefetched);
+    }
+
+    // the data for a user-defined provider must be added manually
+    if custom::lookup(remote_id.provider()).is_some() {
+        return Err(ProviderError::UnexpectedCustom(remote_id.to_string()));
+    }
+
+    if client.offline() {
+        return Err(ProviderError::Offline(remote_id.to_string()));
+    }
+
+    let client = ProvenanceClient::new(client);
+    let response = match lookup_provider(remote_id.provider()) {
+        Provider::Resolver(resolver) => match resolver(remote_id.sub_id(), &client)? {
+            Some(data) => RemoteResponse::Data(data),
+            None => RemoteResponse::Null,
         },
-        Provider::Referrer(referrer) => match referrer(remote_id.sub_id(), client)? {
-            Some(new_remote_id) => Ok(RemoteResponse::Reference(new_remote_id)),
-            None => Ok(RemoteResponse::Null),
+        Provider::Referrer(referrer) => match referrer(remote_id.sub_id(), &client)? {
+            Some(new_remote_id) => RemoteResponse::Reference(new_remote_id),
+            None => RemoteResponse::Null,
         },
+    };
+    Ok((response, client.into_provenance(remote_id.provider())))
+}
+
+/// A [`Client`] which also serves responses obtained ahead of time using batch requests.
+///
+/// Use [`BatchClient::prefetch`] to retrieve the responses for many identifiers at once, for the
+/// providers which support batch requests. Every other request is passed to the underlying
+/// client.
+pub struct BatchClient<'c, C> {
+    inner: &'c C,
+    prefetched: RefCell<HashMap<RemoteId, (RemoteResponse, Option<Provenance>)>>,
+}
+
+impl<'c, C: Client> BatchClient<'c, C> {
+    /// Wrap a client, initially without any prefetched responses.
+    pub fn new(inner: &'c C) -> Self {
+        Self {
+            inner,
+            prefetched: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Retrieve the responses for the provided identifiers using batch requests, grouping the
+    /// identifiers by provider. Providers which do not support batch requests are skipped, and
+    /// failed batch requests fall back to individual requests. Nothing is retrieved if the
+    /// underlying client does not allow batch requests.
+    pub fn prefetch<'a>(&self, remote_ids: impl IntoIterator<Item = &'a RemoteId>) {
+        if !self.inner.batch_requests() {
+            return;
+        }
+
+        let mut batches: HashMap<&'static str, Batch<ProvenanceClient<'c, C>>> = HashMap::new();
+        for remote_id in remote_ids {
+            if let Some((name, resolver, max_size)) = lookup_batch_resolver(remote_id.provider()) {
+                batches
+                    .entry(name)
+                    .or_insert_with(|| (resolver, max_size, Vec::new()))
+                    .2
+                    .push(remote_id);
+            }
+        }
+
+        for (name, (resolver, max_size, remote_ids)) in batches {
+            // a single identifier is resolved more efficiently with an individual request
+            if remote_ids.len() < 2 {
+                continue;
+            }
+
+            for chunk in remote_ids.chunks(max_size) {
+                info!("Resolving {} records from '{name}' in a batch", chunk.len());
+                let client = ProvenanceClient::new(self.inner);
+                match resolver(chunk, &client) {
+                    Ok(responses) => {
+                        // every response in the batch has the same provenance
+                        let provenance = client.into_provenance(name);
+                        self.prefetched
+                            .borrow_mut()
+                            .extend(responses.into_iter().map(|(remote_id, response)| {
+                                (remote_id, (response, provenance.clone()))
+                            }));
+                    }
+                    Err(err) => {
+                        warn!(
+                            "Batch request to '{name}' failed, falling back to individual requests: {err}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<C> BatchClient<'_, C> {
+    /// Check if the response for the identifier was obtained by a batch request, so that no
+    /// individual request is required.
+    pub fn is_prefetched(&self, remote_id: &RemoteId) -> bool {
+        self.prefetched.borrow().contains_key(remote_id)
+    }
+}
+
+impl<C: Client> Client for BatchClient<'_, C> {
+    type Body = C::Body;
+
+    fn get<T>(&self, uri: T) -> Result<http::Response<Self::Body>, ureq::Error>
+    where
+        Uri: TryFrom<T>,
+        <Uri as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        self.inner.get(uri)
+    }
+
+    fn take_prefetched(
+        &self,
+        remote_id: &RemoteId,
+    ) -> Option<(RemoteResponse, Option<Provenance>)> {
+        self.prefetched.borrow_mut().remove(remote_id)
+    }
+
+    fn offline(&self) -> bool {
+        self.inner.offline()
+    }
+
+    fn batch_requests(&self) -> bool {
+        self.inner.batch_requests()
     }
 }
 
@@ -410,3 +664,187 @@ impl TryFrom<ProviderBibtex> for MutableEntryData {
         Ok(record_data)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::TempDir;
+
+    use super::*;
+    use crate::http::cache::{LocalReadClient, ResponseBytes, write_response_cache};
+
+    /// A client which replays a recorded HTTP response cache, but which, unlike
+    /// [`LocalReadClient`], allows batch requests.
+    struct ReplayClient(LocalReadClient);
+
+    impl ReplayClient {
+        /// Record the responses, as pairs of the URL and the status and body of the response, to
+        /// a response cache in the directory and replay them.
+        fn new(dir: &TempDir, responses: &[(&str, u16, &str)]) -> Self {
+            let responses = responses
+                .iter()
+                .map(|(url, status, body)| {
+                    (
+                        (*url).to_owned(),
+                        ResponseBytes {
+                            status: *status,
+                            headers: HashMap::new(),
+                            body: body.as_bytes().to_vec(),
+                        },
+                    )
+                })
+                .collect();
+            write_response_cache(dir.path(), &responses).unwrap();
+            Self(LocalReadClient::new(dir.path()).unwrap())
+        }
+    }
+
+    impl Client for ReplayClient {
+        type Body = ureq::Body;
+
+        fn get<T>(&self, uri: T) -> Result<http::Response<Self::Body>, ureq::Error>
+        where
+            Uri: TryFrom<T>,
+            <Uri as TryFrom<T>>::Error: Into<http::Error>,
+        {
+            self.0.get(uri)
+        }
+    }
+
+    /// The URL of the zbMATH search request for the search terms.
+    fn search_url(terms: &[String]) -> String {
+        format!(
+            "https://api.zbmath.org/v1/document/_search?search_string={}&page=0&results_per_page={}",
+            terms.join("%20%7C%20"),
+            terms.len()
+        )
+    }
+
+    /// A zbMATH search response containing a journal article for each pair of an internal
+    /// identifier and a Zbl identifier.
+    fn search_response(documents: &[(u32, &str)]) -> String {
+        let documents: Vec<String> = documents
+            .iter()
+            .map(|(id, zbl)| {
+                format!(
+                    r#"{{"contributors": {{"authors": [{{"name": "Doe, J."}}], "editors": []}},
+                    "database": "Zbl", "document_type": {{"code": "j"}}, "id": {id},
+                    "identifier": "{zbl}", "language": {{"languages": []}}, "links": [],
+                    "source": {{"book": [], "pages": null, "series": []}},
+                    "title": {{"title": "Document {id}"}}, "year": "2020"}}"#
+                )
+            })
+            .collect();
+        format!(r#"{{"result": [{}]}}"#, documents.join(", "))
+    }
+
+    fn remote_id(s: &str) -> RemoteId {
+        RemoteId::from_string_unchecked(s.to_owned())
+    }
+
+    #[test]
+    fn zbmath_get_records_order() {
+        let dir = TempDir::new().unwrap();
+        let url = search_url(&["an%3A6000002".into(), "an%3A1337.00001".into()]);
+        let body = search_response(&[(6000001, "1337.00001"), (6000002, "1337.00002")]);
+        let client = ReplayClient::new(&dir, &[(&url, 200, &body)]);
+
+        let responses = zbmath::get_records(
+            &[&remote_id("zbmath:06000002"), &remote_id("zbl:1337.00001")],
+            &client,
+        )
+        .unwrap();
+
+        // the responses are in the order of the search results, with the reference for each
+        // document directly before its data
+        let identifiers: Vec<String> = responses.iter().map(|(r, _)| r.to_string()).collect();
+        assert_eq!(
+            identifiers,
+            [
+                "zbl:1337.00001",
+                "zbmath:06000001",
+                "zbl:1337.00002",
+                "zbmath:06000002"
+            ]
+        );
+        assert!(
+            matches!(&responses[0].1, RemoteResponse::Reference(canonical) if canonical.to_string() == "zbmath:06000001")
+        );
+        assert!(
+            matches!(&responses[1].1, RemoteResponse::Data(data) if data.get_field("title") == Some("Document 6000001"))
+        );
+    }
+
+    #[test]
+    fn zbmath_get_records_status() {
+        let dir = TempDir::new().unwrap();
+        let forbidden = search_url(&["an%3A6000001".into(), "an%3A6000002".into()]);
+        let not_found = search_url(&["an%3A6000003".into(), "an%3A6000004".into()]);
+        let client = ReplayClient::new(&dir, &[(&forbidden, 403, ""), (&not_found, 404, "")]);
+
+        let result = zbmath::get_records(
+            &[&remote_id("zbmath:06000001"), &remote_id("zbmath:06000002")],
+            &client,
+        );
+        assert!(matches!(result, Err(ProviderError::TemporaryFailure)));
+
+        let result = zbmath::get_records(
+            &[&remote_id("zbmath:06000003"), &remote_id("zbmath:06000004")],
+            &client,
+        );
+        assert!(result.unwrap().is_empty());
+    }
+
+    #[test]
+    fn batch_client_prefetch() {
+        let remote_ids: Vec<RemoteId> = (1..=zbmath::MAX_BATCH_SIZE as u32 + 2)
+            .map(|n| remote_id(&format!("zbmath:{:08}", 6000000 + n)))
+            .chain([remote_id("doi:10.1000/1")])
+            .collect();
+
+        // only the first batch is in the cache, so the second batch fails
+        let (first, second) = remote_ids.split_at(zbmath::MAX_BATCH_SIZE);
+        let url = search_url(
+            &first
+                .iter()
+                .map(|r| format!("an%3A{}", r.sub_id().trim_start_matches('0')))
+                .collect::<Vec<_>>(),
+        );
+        let documents: Vec<(u32, String)> = (1..=zbmath::MAX_BATCH_SIZE as u32)
+            .map(|n| (6000000 + n, format!("1337.{n:05}")))
+            .collect();
+        let body = search_response(
+            &documents
+                .iter()
+                .map(|(id, zbl)| (*id, zbl.as_str()))
+                .collect::<Vec<_>>(),
+        );
+
+        let dir = TempDir::new().unwrap();
+        let inner = ReplayClient::new(&dir, &[(&url, 200, &body)]);
+        let client = BatchClient::new(&inner);
+        client.prefetch(&remote_ids);
+
+        assert!(first.iter().all(|r| client.is_prefetched(r)));
+        assert!(client.is_prefetched(&remote_id("zbl:1337.00001")));
+        assert!(second.iter().all(|r| !client.is_prefetched(r)));
+
+        let (response, provenance) = client
+            .take_prefetched(&remote_id("zbmath:06000001"))
+            .unwrap();
+        assert!(matches!(response, RemoteResponse::Data(_)));
+        let provenance = provenance.unwrap();
+        assert_eq!(provenance.provider, "zbmath");
+        assert_eq!(provenance.url, url);
+        assert!(!client.is_prefetched(&remote_id("zbmath:06000001")));
+
+        // a single identifier is not resolved with a batch request
+        let client = BatchClient::new(&inner);
+        client.prefetch(&first[..1]);
+        assert!(!client.is_prefetched(&first[0]));
+
+        // batch requests are not used while replaying the response cache
+        let client = BatchClient::new(&inner.0);
+        client.prefetch(&remote_ids);
+        assert!(!client.is_prefetched(&first[0]));
+    }
+}
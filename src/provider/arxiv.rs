@@ -6,8 +6,8 @@ use rsxiv::{
 use serde::Deserialize;
 
 use super::{
-    BodyBytes, Client, EntryType, MutableEntryData, ProviderError, RecordDataError, StatusCode,
-    ValidationOutcome,
+    BodyBytes, Client, EntryType, MutableEntryData, ProviderError, RecordDataError, ResponseFormat,
+    StatusCode, ValidationOutcome, recover,
 };
 
 pub fn is_valid_id(id: &str) -> ValidationOutcome {
@@ -111,9 +111,14 @@ pub fn get_record<C: Client>(
             Some(entry) => Ok(Some(entry.try_into()?)),
             None => Ok(None),
         },
-        Err(err) => Err(ProviderError::Unexpected(format!(
-            "arXiv XML response had an unexpected format! Response body:\n{}\nError message:\n{err}",
-            String::from_utf8_lossy(&body)
-        ))),
+        Err(err) => recover(
+            "arxiv",
+            id,
+            &body,
+            ResponseFormat::Xml,
+            EntryType::preprint(),
+            format!("arXiv XML response had an unexpected format: {err}"),
+        )
+        .map(Some),
     }
 }
@@ -0,0 +1,134 @@
+//! # User-defined providers
+//!
+//! Providers declared in the `[providers]` section of the configuration. Identifiers with a
+//! user-defined provider are validated using a regular expression, but the corresponding data
+//! cannot be retrieved, and must instead be added to the database manually, for instance with
+//! `autobib import`.
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use super::{ValidationOutcome, lookup_validator};
+
+/// A user-defined provider.
+#[derive(Debug)]
+pub struct CustomProvider {
+    /// The name of the provider.
+    pub name: String,
+    /// The pattern which every `sub_id` must match in its entirety.
+    pattern: Regex,
+    /// A template for the URL of a record, in which `{sub_id}` is replaced by the `sub_id`.
+    url_template: Option<String>,
+    /// Whether or not the provider is canonical.
+    pub canonical: bool,
+}
+
+impl CustomProvider {
+    /// Construct a new provider, compiling the pattern so that it must match the entire `sub_id`.
+    pub fn new(
+        name: String,
+        pattern: &str,
+        url_template: Option<String>,
+        canonical: bool,
+    ) -> Result<Self, regex::Error> {
+        Ok(Self {
+            name,
+            pattern: Regex::new(&format!("^(?:{pattern})$"))?,
+            url_template,
+            canonical,
+        })
+    }
+
+    /// Check that the `sub_id` is valid.
+    pub fn is_valid_id(&self, id: &str) -> ValidationOutcome {
+        self.pattern.is_match(id).into()
+    }
+
+    /// The URL of the record with the given `sub_id`, if the provider has a URL template.
+    pub fn url(&self, id: &str) -> Option<String> {
+        self.url_template
+            .as_ref()
+            .map(|template| template.replace("{sub_id}", id))
+    }
+}
+
+/// Check that the name can be used for a user-defined provider, returning a description of the
+/// problem otherwise.
+pub fn validate_name(name: &str) -> Result<(), &'static str> {
+    if name.is_empty()
+        || !name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    {
+        Err("name must be non-empty and only contain ASCII letters, digits, '-', and '_'")
+    } else if lookup_validator(name).is_some() {
+        Err("name is already used by a built-in provider")
+    } else {
+        Ok(())
+    }
+}
+
+static CUSTOM_PROVIDERS: OnceLock<Vec<CustomProvider>> = OnceLock::new();
+
+/// Register the user-defined providers. The providers can only be registered once; subsequent
+/// calls are ignored.
+pub fn register(providers: Vec<CustomProvider>) {
+    let _ = CUSTOM_PROVIDERS.set(providers);
+}
+
+/// Get the user-defined provider with the given name, if it was registered.
+pub fn lookup(provider: &str) -> Option<&'static CustomProvider> {
+    CUSTOM_PROVIDERS
+        .get()?
+        .iter()
+        .find(|custom| custom.name == provider)
+}
+
+/// Iterate over the names of the registered user-defined providers.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    CUSTOM_PROVIDERS
+        .get()
+        .into_iter()
+        .flatten()
+        .map(|custom| custom.name.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("mylab").is_ok());
+        assert!(validate_name("my-lab_2").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("my:lab").is_err());
+        assert!(validate_name("doi").is_err());
+        assert!(validate_name("local").is_err());
+    }
+
+    #[test]
+    fn test_custom_provider() {
+        let provider = CustomProvider::new(
+            "mylab".into(),
+            "TR-[0-9]{4}-[0-9]{2}|TR-[0-9]{4}",
+            Some("https://example.org/reports/{sub_id}.pdf".into()),
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(provider.is_valid_id("TR-2024-07"), ValidationOutcome::Valid);
+        assert_eq!(
+            provider.is_valid_id("TR-2024-7"),
+            ValidationOutcome::Invalid
+        );
+        assert_eq!(
+            provider.is_valid_id("TR-2024-07-x"),
+            ValidationOutcome::Invalid
+        );
+        assert_eq!(
+            provider.url("TR-2024-07").as_deref(),
+            Some("https://example.org/reports/TR-2024-07.pdf")
+        );
+    }
+}
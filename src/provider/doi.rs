@@ -1,11 +1,14 @@
 use std::sync::LazyLock;
 
 use regex::Regex;
+use serde::Deserialize;
 use serde_bibtex::de::Deserializer;
 
+use crate::logger::info;
+
 use super::{
-    BodyBytes, Client, MutableEntryData, ProviderBibtex, ProviderError, StatusCode,
-    ValidationOutcome,
+    BodyBytes, Client, EntryData, EntryType, MutableEntryData, ProviderBibtex, ProviderError,
+    ResponseFormat, StatusCode, ValidationOutcome, recover,
 };
 
 static DOI_IDENTIFIER_RE: LazyLock<Regex> =
@@ -15,6 +18,41 @@ pub fn is_valid_id(id: &str) -> ValidationOutcome {
     DOI_IDENTIFIER_RE.is_match(id).into()
 }
 
+#[derive(Deserialize)]
+struct CrossrefWork {
+    #[serde(rename = "article-number")]
+    article_number: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CrossrefResponse {
+    message: CrossrefWork,
+}
+
+/// Retrieve the article number of a work, which is used by electronic-only journals in place of
+/// a page range. The article number is not included in the BibTeX data returned by Crossref.
+fn get_article_number<C: Client>(id: &str, client: &C) -> Result<Option<String>, ProviderError> {
+    info!("Making remote request for Crossref metadata of {id}");
+    let response = client.get(format!("https://api.crossref.org/works/{id}"))?;
+
+    let mut body = match response.status() {
+        StatusCode::OK => response.into_body().bytes()?,
+        StatusCode::NOT_FOUND => {
+            return Ok(None);
+        }
+        code => return Err(ProviderError::UnexpectedStatusCode(code)),
+    };
+
+    match body.read_json() {
+        Ok(CrossrefResponse {
+            message: CrossrefWork { article_number },
+        }) => Ok(article_number.filter(|number| !number.trim().is_empty())),
+        Err(err) => Err(ProviderError::UnexpectedResponseFormat(format!(
+            "Unexpected Crossref metadata: {err}"
+        ))),
+    }
+}
+
 pub fn get_record<C: Client>(
     id: &str,
     client: &C,
@@ -34,10 +72,39 @@ pub fn get_record<C: Client>(
     let mut entry_iter =
         Deserializer::from_slice(&body).into_iter_regular_entry::<ProviderBibtex>();
 
-    match entry_iter.next() {
-        Some(Ok(entry)) => Ok(Some(entry.try_into()?)),
-        _ => Err(ProviderError::Unexpected(
-            "CrossRef BibTeX record is invalid!".into(),
-        )),
+    let mut record_data: MutableEntryData = match entry_iter.next() {
+        Some(Ok(entry)) => entry.try_into()?,
+        Some(Err(err)) => {
+            return recover(
+                "doi",
+                id,
+                &body,
+                ResponseFormat::Bibtex,
+                EntryType::article(),
+                format!("Crossref BibTeX record is invalid: {err}"),
+            )
+            .map(Some);
+        }
+        None => {
+            return recover(
+                "doi",
+                id,
+                &body,
+                ResponseFormat::Bibtex,
+                EntryType::article(),
+                "Crossref response does not contain a BibTeX record".into(),
+            )
+            .map(Some);
+        }
+    };
+
+    // electronic-only journals provide an article number instead of a page range
+    if !record_data.contains_field("pages")
+        && !record_data.contains_field("eid")
+        && let Some(article_number) = get_article_number(id, client)?
+    {
+        record_data.check_and_insert("eid".into(), article_number)?;
     }
+
+    Ok(Some(record_data))
 }
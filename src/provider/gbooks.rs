@@ -0,0 +1,165 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use super::{
+    BodyBytes, Client, EntryType, MutableEntryData, ProviderError, RemoteId, ResponseFormat,
+    StatusCode, ValidationOutcome, recover,
+};
+
+static GBOOKS_IDENTIFIER_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z0-9_-]{12}$").unwrap());
+
+pub fn is_valid_id(id: &str) -> ValidationOutcome {
+    GBOOKS_IDENTIFIER_RE.is_match(id).into()
+}
+
+#[derive(Deserialize)]
+struct IndustryIdentifier {
+    #[serde(rename = "type")]
+    kind: String,
+    identifier: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VolumeInfo {
+    title: Option<String>,
+    subtitle: Option<String>,
+    #[serde(default)]
+    authors: Vec<String>,
+    publisher: Option<String>,
+    published_date: Option<String>,
+    #[serde(default)]
+    industry_identifiers: Vec<IndustryIdentifier>,
+    page_count: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Volume {
+    volume_info: VolumeInfo,
+}
+
+#[derive(Deserialize)]
+struct VolumeId {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    #[serde(default)]
+    items: Vec<VolumeId>,
+}
+
+pub fn get_record<C: Client>(
+    id: &str,
+    client: &C,
+) -> Result<Option<MutableEntryData>, ProviderError> {
+    let response = client.get(format!("https://www.googleapis.com/books/v1/volumes/{id}"))?;
+
+    let mut body = match response.status() {
+        StatusCode::OK => response.into_body().bytes()?,
+        StatusCode::NOT_FOUND => {
+            return Ok(None);
+        }
+        code => return Err(ProviderError::UnexpectedStatusCode(code)),
+    };
+
+    match body.read_json() {
+        Ok(Volume {
+            volume_info:
+                VolumeInfo {
+                    title,
+                    subtitle,
+                    authors,
+                    publisher,
+                    published_date,
+                    industry_identifiers,
+                    page_count,
+                },
+        }) => {
+            let mut record_data = MutableEntryData::new(EntryType::book());
+
+            if !authors.is_empty() {
+                record_data.check_and_insert("author".into(), authors.join(" and "))?;
+            }
+
+            if let Some(date) = published_date {
+                record_data.check_and_insert("date".into(), date)?;
+            }
+
+            record_data.check_and_insert("googlebooks".into(), id.into())?;
+
+            if let Some(IndustryIdentifier { identifier, .. }) = industry_identifiers
+                .into_iter()
+                .find(|ident| ident.kind == "ISBN_13")
+            {
+                record_data.check_and_insert("isbn".into(), identifier)?;
+            }
+
+            if let Some(page_count) = page_count {
+                record_data.check_and_insert("pagetotal".into(), page_count.to_string())?;
+            }
+
+            if let Some(publisher) = publisher {
+                record_data.check_and_insert("publisher".into(), publisher)?;
+            }
+
+            if let Some(subtitle) = subtitle {
+                record_data.check_and_insert("subtitle".into(), subtitle)?;
+            }
+
+            if let Some(title) = title {
+                record_data.check_and_insert("title".into(), title)?;
+            }
+            Ok(Some(record_data))
+        }
+        Err(err) => recover(
+            "gbooks",
+            id,
+            &body,
+            ResponseFormat::Json,
+            EntryType::book(),
+            format!("Google Books JSON response is invalid: {err}"),
+        )
+        .map(Some),
+    }
+}
+
+/// Search for the volume with the provided ISBN, returning the identifier of the first volume
+/// found.
+pub fn search_isbn<C: Client>(isbn: &str, client: &C) -> Result<Option<RemoteId>, ProviderError> {
+    let response = client.get(format!(
+        "https://www.googleapis.com/books/v1/volumes?q=isbn:{isbn}"
+    ))?;
+
+    let mut body = match response.status() {
+        StatusCode::OK => response.into_body(),
+        code => return Err(ProviderError::UnexpectedStatusCode(code)),
+    };
+
+    let SearchResponse { items } = match body.read_json() {
+        Ok(response) => response,
+        Err(err) => return Err(ProviderError::UnexpectedResponseFormat(err.to_string())),
+    };
+
+    match items.into_iter().next() {
+        Some(VolumeId { id }) => Ok(Some(RemoteId::from_parts("gbooks", &id)?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_valid() {
+        assert_eq!(is_valid_id("zyTCAlFPjgYC"), ValidationOutcome::Valid);
+        assert_eq!(is_valid_id("_-0aZ9bYc8dX"), ValidationOutcome::Valid);
+        assert_eq!(is_valid_id("zyTCAlFPjgY"), ValidationOutcome::Invalid);
+        assert_eq!(is_valid_id("zyTCAlFPjgY!"), ValidationOutcome::Invalid);
+    }
+}
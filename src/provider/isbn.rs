@@ -1,6 +1,41 @@
+use std::{fmt, sync::OnceLock};
+
 use serde::Deserialize;
 
-use super::{BodyBytes, Client, ProviderError, RemoteId, StatusCode, ValidationOutcome};
+use crate::logger::{info, warn};
+
+use super::{BodyBytes, Client, ProviderError, RemoteId, StatusCode, ValidationOutcome, gbooks};
+
+/// A catalog which is searched for the book with an ISBN.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IsbnSource {
+    /// The Open Library API, which returns an `ol:` identifier.
+    OpenLibrary,
+    /// The Google Books API, which returns a `gbooks:` identifier.
+    GoogleBooks,
+}
+
+impl fmt::Display for IsbnSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::OpenLibrary => "Open Library",
+            Self::GoogleBooks => "Google Books",
+        })
+    }
+}
+
+/// The catalogs which are searched if no catalogs were registered.
+pub const DEFAULT_ISBN_SOURCES: [IsbnSource; 2] =
+    [IsbnSource::OpenLibrary, IsbnSource::GoogleBooks];
+
+static ISBN_SOURCES: OnceLock<Vec<IsbnSource>> = OnceLock::new();
+
+/// Register the catalogs which are searched for an ISBN, in order of preference. The catalogs can
+/// only be registered once; subsequent calls are ignored.
+pub fn register_sources(sources: Vec<IsbnSource>) {
+    let _ = ISBN_SOURCES.set(sources);
+}
 
 /// Convert an ascii digit into the actual numerical value of the digit
 fn ascii_digit_to_u8(b: u8) -> Option<u8> {
@@ -145,7 +180,40 @@ struct OLKeyExtractor {
     key: String,
 }
 
+/// Search the registered catalogs in order, returning the identifier from the first catalog which
+/// contains the ISBN.
+///
+/// If a catalog cannot be searched, the next catalog is searched instead, and the error is only
+/// returned if no catalog contains the ISBN, so that the ISBN is not cached as null.
 pub fn get_canonical<C: Client>(id: &str, client: &C) -> Result<Option<RemoteId>, ProviderError> {
+    let sources = ISBN_SOURCES
+        .get()
+        .map_or(&DEFAULT_ISBN_SOURCES[..], Vec::as_slice);
+
+    let mut first_err = None;
+    for source in sources {
+        let found = match source {
+            IsbnSource::OpenLibrary => get_open_library(id, client),
+            IsbnSource::GoogleBooks => gbooks::search_isbn(id, client),
+        };
+        match found {
+            Ok(Some(remote_id)) => return Ok(Some(remote_id)),
+            Ok(None) => info!("ISBN '{id}' was not found in {source}"),
+            Err(err) => {
+                warn!("Failed to search {source} for ISBN '{id}': {err}");
+                first_err.get_or_insert(err);
+            }
+        }
+    }
+
+    match first_err {
+        Some(err) => Err(err),
+        None => Ok(None),
+    }
+}
+
+/// Search the Open Library API for the edition with the ISBN.
+fn get_open_library<C: Client>(id: &str, client: &C) -> Result<Option<RemoteId>, ProviderError> {
     let response = client.get(format!("https://openlibrary.org/isbn/{id}.json"))?;
 
     let mut body = match response.status() {
@@ -0,0 +1,345 @@
+//! # Lenient extraction of partial records
+//!
+//! When the response of a provider cannot be parsed, for instance because the provider changed
+//! the format of its responses, the title and the authors are extracted from the response on a
+//! best-effort basis. The resulting record is marked with the field `incomplete = {true}`, and the
+//! response is saved to a file so that it can be attached to a bug report.
+use std::{
+    fs::{File, create_dir_all},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{LazyLock, OnceLock},
+};
+
+use chrono::Utc;
+use regex::Regex;
+use serde_json::Value;
+
+use crate::logger::warn;
+
+use super::{EntryType, MutableEntryData, ProviderError};
+
+/// The directory in which responses which could not be parsed are saved, if it was set.
+static RESPONSE_DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+
+/// Save the responses which could not be parsed to files in the provided directory.
+pub fn set_response_dir(dir: PathBuf) {
+    let _ = RESPONSE_DIRECTORY.set(dir);
+}
+
+/// The field which marks records which were extracted leniently.
+pub const INCOMPLETE_FIELD: &str = "incomplete";
+
+/// The format of a response.
+#[derive(Debug, Clone, Copy)]
+pub enum ResponseFormat {
+    Xml,
+    Json,
+    Bibtex,
+}
+
+/// The fields which could be extracted from a response.
+#[derive(Default)]
+struct Extracted {
+    title: Option<String>,
+    authors: Vec<String>,
+}
+
+/// Replace every sequence of whitespace with a single space.
+fn collapse_whitespace(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The contents of every element with the provided tag, ignoring attributes.
+fn xml_elements<'a>(text: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{tag}");
+    let close = format!("</{tag}>");
+
+    let mut elements = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        // skip tags which only start with the provided tag, such as `<titles>`
+        if !rest.starts_with(|c: char| c == '>' || c.is_whitespace()) {
+            continue;
+        }
+        let Some(content_start) = rest.find('>') else {
+            break;
+        };
+        rest = &rest[content_start + 1..];
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+        elements.push(&rest[..end]);
+        rest = &rest[end + close.len()..];
+    }
+    elements
+}
+
+/// Replace the predefined XML entities.
+fn xml_unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Extract the title and the authors of the first entry of an Atom feed.
+fn extract_xml(text: &str) -> Extracted {
+    let entry = xml_elements(text, "entry")
+        .into_iter()
+        .next()
+        .unwrap_or(text);
+    Extracted {
+        title: xml_elements(entry, "title")
+            .first()
+            .map(|title| collapse_whitespace(&xml_unescape(title))),
+        authors: xml_elements(entry, "author")
+            .into_iter()
+            .filter_map(|author| xml_elements(author, "name").first().copied())
+            .map(|name| collapse_whitespace(&xml_unescape(name)))
+            .collect(),
+    }
+}
+
+/// Find the first value with the provided key, preferring values which are closer to the root.
+fn find_key<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    match value {
+        Value::Object(map) => map
+            .get(key)
+            .or_else(|| map.values().find_map(|value| find_key(value, key))),
+        Value::Array(values) => values.iter().find_map(|value| find_key(value, key)),
+        _ => None,
+    }
+}
+
+/// Extract the title and the authors from a JSON response. The title is either a string, or an
+/// object which has the title as a string; the authors are an array of strings, or of objects
+/// with a `name`.
+fn extract_json(text: &str) -> Extracted {
+    let Ok(value) = serde_json::from_str::<Value>(text) else {
+        return Extracted::default();
+    };
+
+    let title = match find_key(&value, "title") {
+        Some(Value::String(title)) => Some(title.as_str()),
+        Some(Value::Object(map)) => map.get("title").and_then(Value::as_str),
+        _ => None,
+    };
+    let authors = match find_key(&value, "authors") {
+        Some(Value::Array(authors)) => authors
+            .iter()
+            .filter_map(|author| match author {
+                Value::String(name) => Some(name.as_str()),
+                Value::Object(map) => map.get("name").and_then(Value::as_str),
+                _ => None,
+            })
+            .map(collapse_whitespace)
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    Extracted {
+        title: title.map(collapse_whitespace),
+        authors,
+    }
+}
+
+/// The value of the first BibTeX field with the provided name, delimited by braces or quotes.
+fn bibtex_field(text: &str, re: &Regex) -> Option<String> {
+    let value = &text[re.find(text)?.end()..];
+    let (close, mut depth) = match value.chars().next()? {
+        '{' => ('}', 1),
+        '"' => ('"', 0),
+        _ => return None,
+    };
+
+    for (idx, ch) in value.char_indices().skip(1) {
+        match ch {
+            '{' => depth += 1,
+            ch if ch == close && depth == usize::from(close == '}') => {
+                return Some(collapse_whitespace(&value[1..idx]));
+            }
+            '}' => depth = depth.checked_sub(1)?,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Extract the title and the authors from a BibTeX entry.
+fn extract_bibtex(text: &str) -> Extracted {
+    static TITLE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\btitle\s*=\s*").unwrap());
+    static AUTHOR_RE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?i)\bauthor\s*=\s*").unwrap());
+
+    Extracted {
+        title: bibtex_field(text, &TITLE_RE),
+        authors: bibtex_field(text, &AUTHOR_RE).into_iter().collect(),
+    }
+}
+
+/// Save the response to a new file in the response directory.
+fn save_response(provider: &str, sub_id: &str, body: &[u8]) -> io::Result<PathBuf> {
+    match RESPONSE_DIRECTORY.get() {
+        Some(dir) => save_response_in(dir, provider, sub_id, body),
+        None => Err(io::Error::other("no directory for saved responses")),
+    }
+}
+
+/// Save the response to a new file in the directory, which is created if it does not exist.
+/// Existing files are never overwritten.
+fn save_response_in(dir: &Path, provider: &str, sub_id: &str, body: &[u8]) -> io::Result<PathBuf> {
+    create_dir_all(dir)?;
+
+    let sanitized: String = sub_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let timestamp = Utc::now().format("%Y%m%dT%H%M%S");
+    let mut idx = 0;
+    loop {
+        let path = dir.join(format!("{provider}-{sanitized}-{timestamp}-{idx}.txt"));
+        match File::create_new(&path) {
+            Ok(mut file) => {
+                file.write_all(body)?;
+                return Ok(path);
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => idx += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Recover a partial record from a response which could not be parsed.
+///
+/// The response is saved to a file. If at least the title could be extracted, the record
+/// contains the title, the authors, and the identifier as the `eprint` field, and is marked with
+/// the [`INCOMPLETE_FIELD`]. Otherwise, an error which refers to the saved response is returned.
+pub fn recover(
+    provider: &str,
+    sub_id: &str,
+    body: &[u8],
+    format: ResponseFormat,
+    entry_type: EntryType,
+    message: String,
+) -> Result<MutableEntryData, ProviderError> {
+    let response_file = match save_response(provider, sub_id, body) {
+        Ok(path) => Some(path),
+        Err(err) => {
+            warn!("Failed to save the response for '{provider}:{sub_id}': {err}");
+            None
+        }
+    };
+    let unrecoverable = |message| match &response_file {
+        Some(response_file) => ProviderError::UnrecoverableResponse {
+            message,
+            response_file: response_file.clone(),
+        },
+        None => ProviderError::UnexpectedResponseFormat(message),
+    };
+
+    let text = String::from_utf8_lossy(body);
+    let Extracted { title, authors } = match format {
+        ResponseFormat::Xml => extract_xml(&text),
+        ResponseFormat::Json => extract_json(&text),
+        ResponseFormat::Bibtex => extract_bibtex(&text),
+    };
+
+    let mut record_data = MutableEntryData::new(entry_type);
+    match title {
+        Some(title) if !title.is_empty() => {
+            if record_data.check_and_insert("title".into(), title).is_err() {
+                return Err(unrecoverable(message));
+            }
+        }
+        _ => return Err(unrecoverable(message)),
+    }
+    // the authors are optional, so invalid values are skipped
+    if !authors.is_empty() {
+        let _ = record_data.check_and_insert("author".into(), authors.join(" and "));
+    }
+    record_data.check_and_insert("eprint".into(), sub_id.to_owned())?;
+    record_data.check_and_insert("eprinttype".into(), provider.to_owned())?;
+    record_data.check_and_insert(INCOMPLETE_FIELD.into(), "true".to_owned())?;
+
+    match &response_file {
+        Some(response_file) => warn!(
+            "Response for '{provider}:{sub_id}' had an unexpected format, so only the title and authors were recovered. Please report this at https://github.com/autobib/autobib/issues, attaching the response saved to '{}'. Error message: {message}",
+            response_file.display()
+        ),
+        None => warn!(
+            "Response for '{provider}:{sub_id}' had an unexpected format, so only the title and authors were recovered. Error message: {message}"
+        ),
+    }
+    Ok(record_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_from_xml() {
+        let text = r#"<feed><title type="html">arXiv Query</title>
+            <entry><id>http://arxiv.org/abs/1212.1873v2</id>
+            <title>An   example &amp; a
+              title</title>
+            <author><name>First Author</name></author>
+            <author><name>Second Author</name><arxiv:affiliation>X</arxiv:affiliation></author>
+            </entry></feed>"#;
+        let Extracted { title, authors } = extract_xml(text);
+        assert_eq!(title.as_deref(), Some("An example & a title"));
+        assert_eq!(authors, ["First Author", "Second Author"]);
+    }
+
+    #[test]
+    fn extract_from_json() {
+        let text = r#"{"result": {"contributors": {"authors": [{"name": "Doe, J."}]},
+            "title": {"title": "A title", "subtitle": null}}}"#;
+        let Extracted { title, authors } = extract_json(text);
+        assert_eq!(title.as_deref(), Some("A title"));
+        assert_eq!(authors, ["Doe, J."]);
+
+        let Extracted { title, authors } = extract_json("not json");
+        assert!(title.is_none());
+        assert!(authors.is_empty());
+    }
+
+    #[test]
+    fn extract_from_bibtex() {
+        let text = "@article{key, booktitle = {Wrong}, title = {A {B}ibTeX\n  title}, author = \"Doe, J. and {R}oe, R.\"}";
+        let Extracted { title, authors } = extract_bibtex(text);
+        assert_eq!(title.as_deref(), Some("A {B}ibTeX title"));
+        assert_eq!(authors, ["Doe, J. and {R}oe, R."]);
+
+        let Extracted { title, .. } = extract_bibtex("@article{key, title = {unclosed");
+        assert!(title.is_none());
+    }
+
+    #[test]
+    fn save_response_without_overwriting() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let responses = dir.path().join("responses");
+
+        let first = save_response_in(&responses, "arxiv", "1234/5", b"first").unwrap();
+        let second = save_response_in(&responses, "arxiv", "1234/5", b"second").unwrap();
+        assert_ne!(first, second);
+        assert!(first.starts_with(&responses));
+        assert!(
+            first
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("arxiv-1234_5-"))
+        );
+        assert_eq!(std::fs::read(&first).unwrap(), b"first");
+        assert_eq!(std::fs::read(&second).unwrap(), b"second");
+    }
+}
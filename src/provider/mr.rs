@@ -2,8 +2,8 @@ use serde::Deserialize;
 use serde_bibtex::de::Deserializer;
 
 use super::{
-    BodyBytes, Client, MutableEntryData, ProviderBibtex, ProviderError, StatusCode,
-    ValidationOutcome,
+    BodyBytes, Client, EntryType, MutableEntryData, ProviderBibtex, ProviderError, ResponseFormat,
+    StatusCode, ValidationOutcome, recover,
 };
 
 #[allow(dead_code)]
@@ -36,16 +36,28 @@ pub fn get_record<C: Client>(
     ))?;
 
     let mut body = match response.status() {
-        StatusCode::OK => response.into_body(),
+        StatusCode::OK => response.into_body().bytes()?,
         StatusCode::NOT_FOUND => {
             return Ok(None);
         }
         code => return Err(ProviderError::UnexpectedStatusCode(code)),
     };
 
+    // the BibTeX record is extracted from the raw response if the JSON cannot be parsed, since
+    // the fields can still be found in the JSON string
     let (msc_record,): (MathscinetRecord,) = match body.read_json() {
         Ok(record) => record,
-        Err(err) => return Err(ProviderError::Unexpected(err.to_string())),
+        Err(err) => {
+            return recover(
+                "mr",
+                id,
+                &body,
+                ResponseFormat::Bibtex,
+                EntryType::article(),
+                format!("MathSciNet JSON response is invalid: {err}"),
+            )
+            .map(Some);
+        }
     };
 
     let mut entry_iter =
@@ -53,8 +65,14 @@ pub fn get_record<C: Client>(
 
     match entry_iter.next() {
         Some(Ok(entry)) => Ok(Some(entry.try_into()?)),
-        _ => Err(ProviderError::Unexpected(
-            "MathSciNet BibTeX record is invalid!".into(),
-        )),
+        _ => recover(
+            "mr",
+            id,
+            msc_record.bib.as_bytes(),
+            ResponseFormat::Bibtex,
+            EntryType::article(),
+            "MathSciNet BibTeX record is invalid".into(),
+        )
+        .map(Some),
     }
 }
@@ -6,7 +6,8 @@ use serde::Deserialize;
 use crate::logger::info;
 
 use super::{
-    BodyBytes, Client, EntryType, MutableEntryData, ProviderError, StatusCode, ValidationOutcome,
+    BodyBytes, Client, EntryType, MutableEntryData, ProviderError, ResponseFormat, StatusCode,
+    ValidationOutcome, recover,
 };
 
 static OL_IDENTIFIER_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[0-9]{7,8}M$").unwrap());
@@ -142,6 +143,14 @@ pub fn get_record<C: Client>(
             }
             Ok(Some(record_data))
         }
-        Err(err) => Err(ProviderError::Unexpected(err.to_string())),
+        Err(err) => recover(
+            "ol",
+            id,
+            &body,
+            ResponseFormat::Json,
+            EntryType::book(),
+            format!("Open Library JSON response is invalid: {err}"),
+        )
+        .map(Some),
     }
 }
@@ -0,0 +1,243 @@
+use percent_encoding::percent_decode_str;
+
+use super::{
+    BodyBytes, Client, ProviderError, RemoteId, StatusCode, Uri, ValidationOutcome,
+    ValidationOutcomeExtended, validate_provider_sub_id,
+};
+
+pub fn is_valid_id(id: &str) -> ValidationOutcome {
+    (id.strip_prefix("https://")
+        .or_else(|| id.strip_prefix("http://"))
+        .is_some_and(|rest| !rest.is_empty() && !rest.starts_with('/'))
+        && Uri::try_from(id).is_ok())
+    .into()
+}
+
+/// The value of the parameter `key` in the query string of a URL.
+fn query_value<'a>(query: &'a str, key: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .find_map(|pair| pair.split_once('=').filter(|(k, _)| *k == key))
+        .map(|(_, value)| value)
+}
+
+/// The provider and sub_id of a landing page whose URL has a known shape, where `rest` is the URL
+/// following the scheme.
+fn known_landing_page(rest: &str) -> Option<(&'static str, String)> {
+    let (host, path) = rest.split_at(rest.find(['/', '?', '#']).unwrap_or(rest.len()));
+    let host = host.to_ascii_lowercase();
+    let host = host.split(':').next().unwrap_or_default();
+    let host = host.strip_prefix("www.").unwrap_or(host);
+
+    let path = path.split('#').next().unwrap_or_default();
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+
+    match host {
+        "arxiv.org" | "export.arxiv.org" => {
+            let id = path
+                .strip_prefix("/abs/")
+                .or_else(|| path.strip_prefix("/pdf/"))?;
+            let id = id.strip_suffix(".pdf").unwrap_or(id);
+            Some(("arxiv", id.trim_end_matches('/').to_owned()))
+        }
+        "doi.org" | "dx.doi.org" => {
+            let doi = percent_decode_str(path.strip_prefix('/')?).decode_utf8_lossy();
+            Some(("doi", doi.into_owned()))
+        }
+        "zbmath.org" => {
+            let id = match query_value(query, "q") {
+                Some(q) => percent_decode_str(q)
+                    .decode_utf8_lossy()
+                    .strip_prefix("an:")?
+                    .to_owned(),
+                None => path.trim_matches('/').to_owned(),
+            };
+            Some(("zbl", id))
+        }
+        "mathscinet.ams.org" => {
+            let id = query_value(query, "mr")?;
+            Some(("mr", id.trim_start_matches("MR").to_owned()))
+        }
+        _ => None,
+    }
+}
+
+/// Map an identifier of the form `url:<url>`, or a plain `http://` or `https://` URL, to the
+/// provider and sub_id which are used to retrieve the data.
+///
+/// If the URL is a landing page of a known shape, such as `https://arxiv.org/abs/2112.04570`, the
+/// corresponding identifier of the provider is returned. Otherwise, the URL is kept with the
+/// `url` provider, whose data is determined from the `<meta>` tags of the page. Returns `None`
+/// if the identifier is not a URL.
+pub fn map_url(full_id: &str) -> Option<(&'static str, String)> {
+    let url = full_id.strip_prefix("url:").unwrap_or(full_id);
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+
+    let known = known_landing_page(rest).filter(|(provider, sub_id)| {
+        matches!(
+            validate_provider_sub_id(provider, sub_id),
+            ValidationOutcomeExtended::Valid | ValidationOutcomeExtended::Normalize(_)
+        )
+    });
+    Some(known.unwrap_or_else(|| ("url", url.to_owned())))
+}
+
+/// The value of the attribute `key` of an HTML tag, where `tag` is the contents of the tag
+/// following the tag name.
+fn attribute<'a>(tag: &'a str, key: &str) -> Option<&'a str> {
+    // lowercasing ASCII characters does not change the byte offsets
+    let lower = tag.to_ascii_lowercase();
+    let mut start = 0;
+    while let Some(idx) = lower[start..].find(key) {
+        let idx = start + idx;
+        start = idx + key.len();
+        if !lower[..idx].ends_with(char::is_whitespace) {
+            continue;
+        }
+        let Some(rest) = tag[start..].trim_start().strip_prefix('=') else {
+            continue;
+        };
+        let rest = rest.trim_start();
+        return match rest.chars().next()? {
+            quote @ ('"' | '\'') => rest[1..].split(quote).next(),
+            _ => rest.split(char::is_whitespace).next(),
+        };
+    }
+    None
+}
+
+/// The `name` (or `property`) and the `content` of the `<meta>` tags of an HTML page.
+fn meta_tags(html: &str) -> impl Iterator<Item = (&str, &str)> {
+    html.split('<').filter_map(|tag| {
+        let tag = tag
+            .get(..4)
+            .filter(|name| name.eq_ignore_ascii_case("meta"))
+            .map(|_| &tag[4..])?;
+        let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+        let name = attribute(tag, "name").or_else(|| attribute(tag, "property"))?;
+        Some((name, attribute(tag, "content")?))
+    })
+}
+
+/// Remove the prefixes which are commonly used for DOIs in `<meta>` tags.
+fn strip_doi_prefix(doi: &str) -> &str {
+    let doi = doi.trim();
+    [
+        "https://doi.org/",
+        "http://doi.org/",
+        "https://dx.doi.org/",
+        "http://dx.doi.org/",
+        "doi:",
+    ]
+    .into_iter()
+    .find_map(|prefix| doi.strip_prefix(prefix))
+    .unwrap_or(doi)
+}
+
+/// The first identifier in the `<meta>` tags of an HTML page which is valid.
+fn identifier_from_meta_tags(html: &str) -> Option<RemoteId> {
+    meta_tags(html).find_map(|(name, content)| {
+        let (provider, sub_id) = match name.to_ascii_lowercase().as_str() {
+            "citation_doi" | "dc.identifier" | "prism.doi" | "bepress_citation_doi" => {
+                ("doi", strip_doi_prefix(content))
+            }
+            "citation_arxiv_id" => ("arxiv", content.trim()),
+            _ => return None,
+        };
+        RemoteId::from_parts(provider, sub_id).ok()
+    })
+}
+
+pub fn get_canonical<C: Client>(id: &str, client: &C) -> Result<Option<RemoteId>, ProviderError> {
+    let response = client.get(id)?;
+
+    let body = match response.status() {
+        StatusCode::OK => response.into_body().bytes()?,
+        StatusCode::NOT_FOUND | StatusCode::GONE => {
+            return Ok(None);
+        }
+        code => return Err(ProviderError::UnexpectedStatusCode(code)),
+    };
+
+    Ok(identifier_from_meta_tags(&String::from_utf8_lossy(&body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_urls() {
+        assert_eq!(
+            map_url("url:https://arxiv.org/abs/2112.04570"),
+            Some(("arxiv", "2112.04570".into()))
+        );
+        assert_eq!(
+            map_url("https://arxiv.org/pdf/math/9201254.pdf"),
+            Some(("arxiv", "math/9201254".into()))
+        );
+        assert_eq!(
+            map_url("https://doi.org/10.4007/annals.2014.180.2.7"),
+            Some(("doi", "10.4007/annals.2014.180.2.7".into()))
+        );
+        assert_eq!(
+            map_url("http://dx.doi.org/10.1002%2Fcpa.3160140303"),
+            Some(("doi", "10.1002/cpa.3160140303".into()))
+        );
+        assert_eq!(
+            map_url("https://zbmath.org/?q=an%3A1337.28015"),
+            Some(("zbl", "1337.28015".into()))
+        );
+        assert_eq!(
+            map_url("https://mathscinet.ams.org/mathscinet-getitem?mr=MR3224722"),
+            Some(("mr", "3224722".into()))
+        );
+    }
+
+    #[test]
+    fn other_urls() {
+        assert_eq!(
+            map_url("https://arxiv.org/list/math.DS/recent"),
+            Some(("url", "https://arxiv.org/list/math.DS/recent".into()))
+        );
+        assert_eq!(
+            map_url("url:https://example.com/paper"),
+            Some(("url", "https://example.com/paper".into()))
+        );
+        assert_eq!(map_url("doi:10.4007/annals.2014.180.2.7"), None);
+        assert_eq!(map_url("url:ftp://example.com"), None);
+    }
+
+    #[test]
+    fn is_valid() {
+        assert_eq!(
+            is_valid_id("https://example.com/paper?id=1"),
+            ValidationOutcome::Valid
+        );
+        assert_eq!(is_valid_id("https://"), ValidationOutcome::Invalid);
+        assert_eq!(is_valid_id("example.com"), ValidationOutcome::Invalid);
+        assert_eq!(
+            is_valid_id("https://example.com/a b"),
+            ValidationOutcome::Invalid
+        );
+    }
+
+    #[test]
+    fn meta_tag_identifier() {
+        let html = r#"<html><head>
+<META name="citation_title" content="A title">
+<meta content='doi:10.4007/annals.2014.180.2.7' name='citation_doi' />
+<meta name="citation_arxiv_id" content="1212.1873">
+</head></html>"#;
+        assert_eq!(
+            identifier_from_meta_tags(html),
+            Some(RemoteId::from_parts("doi", "10.4007/annals.2014.180.2.7").unwrap())
+        );
+        assert_eq!(
+            identifier_from_meta_tags(r#"<meta name="dc.identifier" content="ISBN 1234">"#),
+            None
+        );
+    }
+}
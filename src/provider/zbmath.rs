@@ -1,8 +1,16 @@
 mod response;
 
-use super::{BodyBytes, Client, MutableEntryData, ProviderError, StatusCode, ValidationOutcome};
+use itertools::Itertools;
 
-use self::response::Response;
+use super::{
+    BodyBytes, Client, EntryType, MutableEntryData, ProviderError, RemoteId, RemoteResponse,
+    ResponseFormat, StatusCode, ValidationOutcome, recover,
+};
+
+use self::response::{Response, SearchResponse};
+
+/// The maximum number of identifiers in a single batch request.
+pub const MAX_BATCH_SIZE: usize = 50;
 
 pub fn is_valid_id(id: &str) -> ValidationOutcome {
     if id.len() == 8 && id.as_bytes().iter().all(u8::is_ascii_digit) {
@@ -22,7 +30,7 @@ pub fn get_record<C: Client>(
     let response = client.get(format!("https://api.zbmath.org/v1/document/{id}"))?;
 
     let mut body = match response.status() {
-        StatusCode::OK => response.into_body(),
+        StatusCode::OK => response.into_body().bytes()?,
         StatusCode::FORBIDDEN => {
             return Err(ProviderError::TemporaryFailure);
         }
@@ -34,6 +42,67 @@ pub fn get_record<C: Client>(
 
     match body.read_json::<Response>() {
         Ok(response) => Ok(Some(response.result.try_into()?)),
-        Err(err) => Err(ProviderError::UnexpectedResponseFormat(err.to_string())),
+        Err(err) => recover(
+            "zbmath",
+            id,
+            &body,
+            ResponseFormat::Json,
+            EntryType::misc(),
+            err.to_string(),
+        )
+        .map(Some),
     }
 }
+
+/// Resolve multiple `zbmath` and `zbl` identifiers using a single search request.
+///
+/// Every document in the search results is returned as data for its `zbmath` identifier. If the
+/// document also has a Zbl identifier, it is returned as a reference to the `zbmath` identifier.
+pub fn get_records<C: Client>(
+    remote_ids: &[&RemoteId],
+    client: &C,
+) -> Result<Vec<(RemoteId, RemoteResponse)>, ProviderError> {
+    // the `an:` search field accepts both Zbl identifiers and internal zbMATH identifiers; the
+    // internal identifiers are searched without the zero padding
+    let search_string = remote_ids
+        .iter()
+        .map(|remote_id| match remote_id.provider() {
+            "zbmath" => format!("an%3A{}", remote_id.sub_id().trim_start_matches('0')),
+            _ => format!("an%3A{}", remote_id.sub_id()),
+        })
+        .join("%20%7C%20");
+
+    let response = client.get(format!(
+        "https://api.zbmath.org/v1/document/_search?search_string={search_string}&page=0&results_per_page={}",
+        remote_ids.len()
+    ))?;
+
+    let mut body = match response.status() {
+        StatusCode::OK => response.into_body(),
+        StatusCode::FORBIDDEN => {
+            return Err(ProviderError::TemporaryFailure);
+        }
+        StatusCode::NOT_FOUND => {
+            return Ok(Vec::new());
+        }
+        code => return Err(ProviderError::UnexpectedStatusCode(code)),
+    };
+
+    let entries = match body.read_json::<SearchResponse>() {
+        Ok(response) => response.result,
+        Err(err) => return Err(ProviderError::UnexpectedResponseFormat(err.to_string())),
+    };
+
+    let mut responses = Vec::with_capacity(2 * entries.len());
+    for entry in entries {
+        let canonical = RemoteId::from_parts("zbmath", &format!("{:0>8}", entry.id()))?;
+        if let Some(zbl) = entry.zbl()
+            && let Ok(reference) = RemoteId::from_parts("zbl", zbl)
+        {
+            responses.push((reference, RemoteResponse::Reference(canonical.clone())));
+        }
+        responses.push((canonical, RemoteResponse::Data(entry.try_into()?)));
+    }
+
+    Ok(responses)
+}
@@ -125,6 +125,12 @@ pub struct Response {
     // pub status: Status,
 }
 
+/// The response to a search request, which contains any number of entries.
+#[derive(Deserialize)]
+pub struct SearchResponse {
+    pub result: Vec<Entry>,
+}
+
 // #[derive(Deserialize)]
 // pub struct Status {
 //     execution: String,
@@ -151,6 +157,21 @@ pub struct Entry {
     year: Option<String>,
 }
 
+impl Entry {
+    /// The internal zbMATH identifier.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// The Zbl identifier, if the entry is in the Zbl database and the identifier is set.
+    pub fn zbl(&self) -> Option<&str> {
+        match self.database {
+            Database::Zbl => self.identifier.as_deref(),
+            Database::Jfm => None,
+        }
+    }
+}
+
 #[derive(Deserialize, Clone)]
 struct Language {
     languages: Vec<String>,
@@ -0,0 +1,400 @@
+//! # Record queries
+//!
+//! A query is a boolean expression which is evaluated against the data of a record. The basic
+//! expressions are comparisons of the form `FIELD OP VALUE`, where `OP` is one of the following:
+//!
+//! - `=`, `!=`: the field is equal, or not equal, to the value,
+//! - `~`, `!~`: the field matches, or does not match, the value as a regular expression, and
+//! - `<`, `<=`, `>`, `>=`: the field is ordered relative to the value. The field and the value are
+//!   compared as integers if both are integers, and as strings otherwise.
+//!
+//! The value is either a word which does not contain whitespace, parentheses, or operator
+//! characters, or a string delimited by `"`, in which `\"` and `\\` are escapes.
+//!
+//! Besides the fields of the record, the following names can be compared:
+//!
+//! - `entrytype`: the entry type of the record,
+//! - `provider`: the provider of the canonical identifier of the record, and
+//! - `modified`: the date on which the record was last modified, in the format `YYYY-MM-DD`.
+//!
+//! If the field is not present in the record, only the comparisons `!=` and `!~` hold.
+//! Comparisons are combined with `and`, `or`, and `not`, and grouped with parentheses. As usual,
+//! `not` binds more strongly than `and`, which binds more strongly than `or`.
+use std::{borrow::Cow, cmp::Ordering, iter::Peekable, str::FromStr, vec::IntoIter};
+
+use chrono::NaiveDate;
+use regex::Regex;
+
+use crate::{db::state::RecordRow, entry::EntryData, error::QueryError};
+
+/// The format of the `modified` date of a record.
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// A token of a query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    Op(&'static str),
+    Word(String),
+    Quoted(String),
+}
+
+impl Token {
+    /// The token as written in the query, for error messages.
+    fn describe(&self) -> String {
+        match self {
+            Self::Open => "(".to_owned(),
+            Self::Close => ")".to_owned(),
+            Self::Op(op) => (*op).to_owned(),
+            Self::Word(word) => word.clone(),
+            Self::Quoted(s) => format!("\"{s}\""),
+        }
+    }
+
+    /// Check if the token is the keyword, ignoring case.
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Self::Word(word) if word.eq_ignore_ascii_case(keyword))
+    }
+}
+
+/// The operators, ordered so that an operator comes before its prefixes.
+const OPERATORS: &[&str] = &["!=", "!~", "<=", ">=", "=", "~", "<", ">"];
+
+/// Split the query into tokens.
+fn tokenize(input: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        if c == '(' || c == ')' {
+            tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            rest = &rest[1..];
+        } else if let Some(op) = OPERATORS.iter().copied().find(|op| rest.starts_with(op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else if c == '"' {
+            let mut value = String::new();
+            let mut chars = rest[1..].char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((idx, '"')) => break idx + 2,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, escaped @ ('"' | '\\'))) => value.push(escaped),
+                        Some((_, other)) => {
+                            value.push('\\');
+                            value.push(other);
+                        }
+                        None => return Err(QueryError::UnclosedString),
+                    },
+                    Some((_, other)) => value.push(other),
+                    None => return Err(QueryError::UnclosedString),
+                }
+            };
+            tokens.push(Token::Quoted(value));
+            rest = &rest[end..];
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || "()\"=!~<>".contains(c))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return Err(QueryError::Unexpected("a field or value", c.to_string()));
+            }
+            tokens.push(Token::Word(rest[..end].to_owned()));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+/// A comparison operator which orders the field relative to the value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Order {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Order {
+    /// Check if the ordering of the field relative to the value satisfies the operator.
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Self::Eq => ordering.is_eq(),
+            Self::Ne => ordering.is_ne(),
+            Self::Lt => ordering.is_lt(),
+            Self::Le => ordering.is_le(),
+            Self::Gt => ordering.is_gt(),
+            Self::Ge => ordering.is_ge(),
+        }
+    }
+}
+
+/// The condition which is checked for the value of a field.
+#[derive(Debug, Clone)]
+enum Predicate {
+    /// Compare the field with the value.
+    Compare(Order, String),
+    /// Match the field against the regular expression, or check that it does not match if the
+    /// flag is set.
+    Match(Regex, bool),
+}
+
+impl Predicate {
+    /// Check if the value of the field satisfies the predicate.
+    fn holds(&self, field: Option<&str>) -> bool {
+        match (self, field) {
+            (Self::Compare(order, value), Some(field)) => {
+                let ordering = match (field.trim().parse::<i64>(), value.parse::<i64>()) {
+                    (Ok(lhs), Ok(rhs)) => lhs.cmp(&rhs),
+                    _ => field.cmp(value.as_str()),
+                };
+                order.holds(ordering)
+            }
+            (Self::Match(regex, negated), Some(field)) => regex.is_match(field) != *negated,
+            (Self::Compare(order, _), None) => *order == Order::Ne,
+            (Self::Match(_, negated), None) => *negated,
+        }
+    }
+}
+
+/// A boolean expression of predicates.
+#[derive(Debug, Clone)]
+enum Expr {
+    /// Both expressions hold.
+    And(Box<Self>, Box<Self>),
+    /// At least one of the expressions holds.
+    Or(Box<Self>, Box<Self>),
+    /// The expression does not hold.
+    Not(Box<Self>),
+    /// The field satisfies the predicate.
+    Field(String, Predicate),
+}
+
+impl Expr {
+    /// Evaluate the expression, looking up the values of the fields with the provided closure.
+    fn eval<'a, L: Fn(&str) -> Option<Cow<'a, str>>>(&self, lookup: &L) -> bool {
+        match self {
+            Self::And(lhs, rhs) => lhs.eval(lookup) && rhs.eval(lookup),
+            Self::Or(lhs, rhs) => lhs.eval(lookup) || rhs.eval(lookup),
+            Self::Not(expr) => !expr.eval(lookup),
+            Self::Field(field, predicate) => predicate.holds(lookup(field).as_deref()),
+        }
+    }
+}
+
+/// A query which is evaluated against the data of a record.
+#[derive(Debug, Clone)]
+pub struct Query(Expr);
+
+/// The parser for the grammar
+/// ```text
+/// or         := and ("or" and)*
+/// and        := unary ("and" unary)*
+/// unary      := "not" unary | "(" or ")" | comparison
+/// comparison := FIELD OP VALUE
+/// ```
+struct Parser {
+    tokens: Peekable<IntoIter<Token>>,
+}
+
+impl Parser {
+    fn next(&mut self, expected: &'static str) -> Result<Token, QueryError> {
+        self.tokens
+            .next()
+            .ok_or(QueryError::UnexpectedEof(expected))
+    }
+
+    fn next_if_keyword(&mut self, keyword: &str) -> bool {
+        self.tokens.next_if(|t| t.is_keyword(keyword)).is_some()
+    }
+
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.and()?;
+        while self.next_if_keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.unary()?;
+        while self.next_if_keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, QueryError> {
+        if self.next_if_keyword("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+
+        match self.next("a field or '('")? {
+            Token::Open => {
+                let expr = self.or()?;
+                match self.next("')'")? {
+                    Token::Close => Ok(expr),
+                    token => Err(QueryError::Unexpected("')'", token.describe())),
+                }
+            }
+            Token::Word(field) => self.comparison(field.to_lowercase()),
+            token => Err(QueryError::Unexpected("a field or '('", token.describe())),
+        }
+    }
+
+    fn comparison(&mut self, field: String) -> Result<Expr, QueryError> {
+        let op = match self.next("an operator")? {
+            Token::Op(op) => op,
+            token => return Err(QueryError::Unexpected("an operator", token.describe())),
+        };
+        let value = match self.next("a value")? {
+            Token::Word(value) | Token::Quoted(value) => value,
+            token => return Err(QueryError::Unexpected("a value", token.describe())),
+        };
+
+        let predicate = match op {
+            "~" => Predicate::Match(Regex::new(&value)?, false),
+            "!~" => Predicate::Match(Regex::new(&value)?, true),
+            _ => {
+                let order = match op {
+                    "=" => Order::Eq,
+                    "!=" => Order::Ne,
+                    "<" => Order::Lt,
+                    "<=" => Order::Le,
+                    ">" => Order::Gt,
+                    _ => Order::Ge,
+                };
+                // dates are compared as strings, so they must be written in the same format
+                if field == "modified" && NaiveDate::parse_from_str(&value, DATE_FORMAT).is_err() {
+                    return Err(QueryError::InvalidDate(value));
+                }
+                Predicate::Compare(order, value)
+            }
+        };
+        Ok(Expr::Field(field, predicate))
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        if tokens.is_empty() {
+            return Err(QueryError::Empty);
+        }
+
+        let mut parser = Parser {
+            tokens: tokens.into_iter().peekable(),
+        };
+        let expr = parser.or()?;
+        match parser.tokens.next() {
+            None => Ok(Self(expr)),
+            Some(token) => Err(QueryError::Unexpected("'and' or 'or'", token.describe())),
+        }
+    }
+}
+
+impl Query {
+    /// Check if the record satisfies the query.
+    pub fn matches<D: EntryData>(&self, row: &RecordRow<D>) -> bool {
+        self.0.eval(&|field: &str| match field {
+            "entrytype" => Some(Cow::Borrowed(row.data.entry_type())),
+            "provider" => Some(Cow::Borrowed(row.canonical.provider())),
+            "modified" => Some(Cow::Owned(row.modified.format(DATE_FORMAT).to_string())),
+            _ => row.data.get_field(field).map(Cow::Borrowed),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(query: &str, fields: &[(&str, &str)]) -> bool {
+        query.parse::<Query>().unwrap().0.eval(&|field: &str| {
+            fields
+                .iter()
+                .find(|(key, _)| *key == field)
+                .map(|(_, value)| Cow::Borrowed(*value))
+        })
+    }
+
+    #[test]
+    fn test_query_eval() {
+        let fields = &[
+            ("journal", "arXiv preprint"),
+            ("year", "2021"),
+            ("title", "A \"quoted\" title"),
+            ("modified", "2024-03-15"),
+        ];
+
+        assert!(eval("journal ~ arXiv", fields));
+        assert!(eval(r#"journal~"^arXiv""#, fields));
+        assert!(!eval("journal !~ arXiv", fields));
+        assert!(eval("year = 2021", fields));
+        assert!(eval("year > 999", fields));
+        assert!(eval("year >= 2021 and year < 2022", fields));
+        assert!(!eval("year < 2021 or year > 2021", fields));
+        assert!(eval(r#"title = "A \"quoted\" title""#, fields));
+        assert!(eval("not (year = 2020)", fields));
+        assert!(eval("NOT year = 2020 AND journal ~ arXiv", fields));
+        assert!(eval(
+            "year = 2020 or year = 2021 and journal ~ arXiv",
+            fields
+        ));
+        assert!(eval(
+            "modified >= 2024-01-01 and modified < 2024-04-01",
+            fields
+        ));
+
+        // missing fields
+        assert!(eval("doi != 10.1000/1", fields));
+        assert!(eval("doi !~ .", fields));
+        assert!(!eval("doi = 10.1000/1", fields));
+        assert!(!eval("doi ~ .", fields));
+        assert!(!eval("doi < 1", fields));
+    }
+
+    #[test]
+    fn test_query_parse_error() {
+        assert!(matches!("".parse::<Query>(), Err(QueryError::Empty)));
+        assert!(matches!(
+            "year =".parse::<Query>(),
+            Err(QueryError::UnexpectedEof(_))
+        ));
+        assert!(matches!(
+            "year 2021".parse::<Query>(),
+            Err(QueryError::Unexpected(..))
+        ));
+        assert!(matches!(
+            "(year = 2021".parse::<Query>(),
+            Err(QueryError::UnexpectedEof(_))
+        ));
+        assert!(matches!(
+            "year = 2021 journal = x".parse::<Query>(),
+            Err(QueryError::Unexpected(..))
+        ));
+        assert!(matches!(
+            r#"title = "unclosed"#.parse::<Query>(),
+            Err(QueryError::UnclosedString)
+        ));
+        assert!(matches!(
+            "title ~ (".parse::<Query>(),
+            Err(QueryError::Unexpected(..))
+        ));
+        assert!(matches!(
+            r#"title ~ "(""#.parse::<Query>(),
+            Err(QueryError::InvalidRegex(_))
+        ));
+        assert!(matches!(
+            "modified > 2024".parse::<Query>(),
+            Err(QueryError::InvalidDate(_))
+        ));
+    }
+}
@@ -1,15 +1,19 @@
 mod key;
 mod mapped;
 
+use std::str::FromStr;
+
 use anyhow::bail;
+use chrono::Local;
 use nonempty::NonEmpty;
+use nucleo_picker::Render;
 
 pub use self::key::{Alias, AliasOrRemoteId, MappedAliasOrRemoteId, MappedKey, RecordId, RemoteId};
 use crate::{
     Config,
-    config::AliasTransform,
+    config::{AliasTransform, AutoAlias},
     db::{
-        RecordDatabase, Tx,
+        Provenance, RecordDatabase, Tx,
         state::{
             IsDeleted, IsEntry, IsMissing, IsNull, IsVoid, RecordIdState, RecordRow, RemoteIdState,
             State, Unknown,
@@ -18,9 +22,9 @@ use crate::{
     entry::{MutableEntryData, RawEntryData},
     error::{Error, ProviderError, RecordError},
     http::Client,
-    logger::info,
+    logger::{info, warn},
     normalize::{Normalization, Normalize},
-    provider::{RemoteResponse, get_remote_response},
+    provider::{RemoteResponse, get_remote_response_with_provenance},
 };
 
 /// The fundamental record type for a record in the 'Records' table, with data depending on the
@@ -162,7 +166,24 @@ where
             Ok(RecordRowResponse::Deleted(Record::new(key, data), row))
         }
         RecordIdState::NullRemoteId(remote_id, null_row) => {
-            Ok(RecordRowResponse::NullRemoteId(remote_id.mapped, null_row))
+            // retrieve the record again if the null record has expired
+            if let Some(null_ttl) = config.null_ttl
+                && null_row.get_null_attempted()? <= Local::now() - null_ttl
+            {
+                info!("Retrying expired null record '{}'", remote_id.mapped);
+                get_record_row_recursive(
+                    null_row.delete()?,
+                    remote_id.mapped,
+                    client,
+                    &config.on_insert,
+                    config.auto_alias.as_ref(),
+                    |_, t| Ok(t),
+                    |_, t| Ok(t),
+                    remote_id.original,
+                )
+            } else {
+                Ok(RecordRowResponse::NullRemoteId(remote_id.mapped, null_row))
+            }
         }
         RecordIdState::UndefinedAlias(alias) => Ok(RecordRowResponse::NullAlias(alias)),
         RecordIdState::InvalidRemoteId(err) => Ok(RecordRowResponse::InvalidRemoteId(err)),
@@ -184,6 +205,7 @@ where
                 mapped,
                 client,
                 &config.on_insert,
+                config.auto_alias.as_ref(),
                 |row, alias| {
                     // create the new alias
                     if config.alias_transform.create() {
@@ -201,6 +223,7 @@ where
                 maybe_normalized.mapped,
                 client,
                 &config.on_insert,
+                config.auto_alias.as_ref(),
                 |_, t| Ok(t),
                 |_, t| Ok(t),
                 maybe_normalized.original,
@@ -226,12 +249,89 @@ where
     get_record_row_tx(db.transaction()?, record_id, client, config)
 }
 
+/// Create an alias for a new record by rendering the template of the [`AutoAlias`] with the
+/// data of the record. If the alias already refers to a different record, the first of the
+/// suffixes `a` through `z` for which the alias does not refer to a different record is appended.
+pub fn add_auto_alias(
+    row: &State<'_, IsEntry>,
+    auto_alias: &AutoAlias,
+) -> Result<(), rusqlite::Error> {
+    let data = row.get_data()?;
+    if !auto_alias.template.has_keys_contained_in(&data) {
+        info!(
+            "Not creating an automatic alias for '{}': the record is missing fields required by the template",
+            data.canonical
+        );
+        return Ok(());
+    }
+
+    let mut base: String = auto_alias
+        .template
+        .render(&data)
+        .chars()
+        .filter(|c| c.is_alphanumeric() || "-_./".contains(*c))
+        .collect();
+    if auto_alias.lowercase {
+        base = base.to_lowercase();
+    }
+
+    for suffix in std::iter::once(None).chain(('a'..='z').map(Some)) {
+        let name = match suffix {
+            Some(suffix) => format!("{base}{suffix}"),
+            None => base.clone(),
+        };
+        // the alias is only invalid if it is empty, since `:` is removed above
+        let Ok(alias) = Alias::from_str(&name) else {
+            info!(
+                "Not creating an automatic alias for '{}': the rendered alias is empty",
+                data.canonical
+            );
+            return Ok(());
+        };
+        if row.ensure_alias(&alias)?.is_none() {
+            info!("Creating alias '{alias}' for '{}'", data.canonical);
+            return Ok(());
+        }
+    }
+
+    warn!(
+        "Not creating an automatic alias for '{}': the alias '{base}' exists with every suffix",
+        data.canonical
+    );
+    Ok(())
+}
+
 /// Destructure a [`NonEmpty`] and return the last element.
 #[inline]
 fn into_last<T>(NonEmpty { head, mut tail }: NonEmpty<T>) -> T {
     tail.pop().unwrap_or(head)
 }
 
+/// Obtain the [`RemoteResponse`] from the first overlay database which contains the
+/// [`RemoteId`], and otherwise by looking up the [`RemoteId`] using the provided `client`, along
+/// with the [`Provenance`] of the response if it was retrieved from the provider.
+///
+/// If the identifier is not the canonical identifier of the record in the overlay, the response
+/// is a reference to the canonical identifier, so that it is resolved in the same way as a
+/// reference returned by a provider.
+fn get_overlay_or_remote_response<C: Client>(
+    missing: &State<'_, IsMissing>,
+    remote_id: &RemoteId,
+    client: &C,
+) -> Result<(RemoteResponse, Option<Provenance>), Error> {
+    match missing.overlay_record(remote_id)? {
+        Some(row) if &row.canonical == remote_id => {
+            info!("Found data for '{remote_id}' in an overlay database");
+            Ok((
+                RemoteResponse::Data(MutableEntryData::from_entry_data(&row.data)),
+                None,
+            ))
+        }
+        Some(row) => Ok((RemoteResponse::Reference(row.canonical), None)),
+        None => Ok(get_remote_response_with_provenance(client, remote_id)?),
+    }
+}
+
 /// Resolve remote records inside a loop within a transaction.
 ///
 /// The `exists_callback` is called if the remote record exists, and is passed a reference to the
@@ -244,11 +344,13 @@ fn into_last<T>(NonEmpty { head, mut tail }: NonEmpty<T>) -> T {
 /// At each intermediate stage, attempt to read any data possible from the database
 /// inside the transaction implicit in the [`State<Missing>`], and write any new data to the
 /// database.
+#[allow(clippy::too_many_arguments)]
 fn get_record_row_recursive<'conn, O, C: Client>(
     mut missing: State<'conn, IsMissing>,
     remote_id: RemoteId,
     client: &C,
     normalization: &Normalization,
+    auto_alias: Option<&AutoAlias>,
     exists_callback: impl FnOnce(&State<'conn, IsEntry>, O) -> Result<Option<String>, rusqlite::Error>,
     deleted_callback: impl FnOnce(
         &State<'conn, IsDeleted>,
@@ -259,7 +361,9 @@ fn get_record_row_recursive<'conn, O, C: Client>(
     info!("Resolving remote record for {remote_id}");
     let mut history = NonEmpty::singleton(remote_id);
     loop {
-        missing = match get_remote_response(client, history.last())? {
+        let (response, provenance) =
+            get_overlay_or_remote_response(&missing, history.last(), client)?;
+        missing = match response {
             RemoteResponse::Data(mut data) => {
                 data.normalize(normalization);
                 let raw_record_data = RawEntryData::from_entry_data(&data);
@@ -267,6 +371,12 @@ fn get_record_row_recursive<'conn, O, C: Client>(
                 // SAFETY: the provided canonical identifier is present in the provided references
                 let row =
                     missing.insert_with_refs(&raw_record_data, history.last(), history.iter())?;
+                if let Some(provenance) = &provenance {
+                    row.set_provenance(provenance)?;
+                }
+                if let Some(auto_alias) = auto_alias {
+                    add_auto_alias(&row, auto_alias)?;
+                }
                 let maybe_key = exists_callback(&row, original)?;
 
                 let NonEmpty { head, mut tail } = history;
@@ -373,8 +483,9 @@ fn get_record_row_recursive<'conn, O, C: Client>(
 
 /// The result of obtaining a remote record, with no reference to a database.
 pub enum RecursiveRemoteResponse {
-    /// The remote record exists, and has the provided data and canonical identifier.
-    Exists(MutableEntryData, RemoteId),
+    /// The remote record exists, and has the provided data, canonical identifier, and
+    /// provenance.
+    Exists(MutableEntryData, RemoteId, Option<Provenance>),
     /// The remote record does not exist.
     Null(RemoteId),
 }
@@ -386,19 +497,22 @@ pub fn revive_void<'conn, C: Client>(
     client: &C,
     normalization: &Normalization,
 ) -> Result<(RawEntryData, State<'conn, IsEntry>), Error> {
-    match get_remote_response(client, canonical)? {
-        RemoteResponse::Data(mut mutable_entry_data) => {
+    match get_remote_response_with_provenance(client, canonical)? {
+        (RemoteResponse::Data(mut mutable_entry_data), provenance) => {
             mutable_entry_data.normalize(normalization);
             let data = RawEntryData::from_entry_data(&mutable_entry_data);
             let entry = void.reinsert(&data)?;
+            if let Some(provenance) = &provenance {
+                entry.set_provenance(provenance)?;
+            }
             Ok((data, entry))
         }
-        RemoteResponse::Reference(remote_id) => {
+        (RemoteResponse::Reference(remote_id), _) => {
             panic!(
                 "Database error: 'Records' table contains identifier {remote_id} which is not canonical"
             );
         }
-        RemoteResponse::Null => {
+        (RemoteResponse::Null, _) => {
             Err(ProviderError::UnexpectedNullFromPreviousData(canonical.to_string()).into())
         }
     }
@@ -417,14 +531,18 @@ pub fn get_remote_response_recursive<C: Client>(
     loop {
         let last = history.last();
 
-        match get_remote_response(client, last)? {
-            RemoteResponse::Data(data) => {
-                break Ok(RecursiveRemoteResponse::Exists(data, into_last(history)));
+        match get_remote_response_with_provenance(client, last)? {
+            (RemoteResponse::Data(data), provenance) => {
+                break Ok(RecursiveRemoteResponse::Exists(
+                    data,
+                    into_last(history),
+                    provenance,
+                ));
             }
-            RemoteResponse::Reference(new_remote_id) => {
+            (RemoteResponse::Reference(new_remote_id), _) => {
                 history.push(new_remote_id);
             }
-            RemoteResponse::Null => {
+            (RemoteResponse::Null, _) => {
                 break Ok(RecursiveRemoteResponse::Null(history.head));
             }
         }
@@ -11,7 +11,7 @@ use crate::{
         AliasConversionError, AliasErrorKind, RecordError, RecordErrorKind,
         RemoteIdConversionError, RemoteIdErrorKind,
     },
-    provider::{ValidationOutcomeExtended, validate_provider_sub_id},
+    provider::{ValidationOutcomeExtended, map_url, validate_provider_sub_id},
 };
 pub use mapped::{MappedAliasOrRemoteId, MappedKey};
 
@@ -62,6 +62,25 @@ fn resolve_provider_sub_id(
     }
 }
 
+/// Resolve a URL, which was mapped to the provided `provider` and `sub_id`, keeping the original
+/// `full_id` if it was changed.
+fn resolve_url(
+    full_id: String,
+    provider: &str,
+    sub_id: &str,
+) -> Result<MappedKey, RemoteIdConversionError> {
+    let mut mapped_id = String::with_capacity(provider.len() + sub_id.len() + 1);
+    mapped_id.push_str(provider);
+    mapped_id.push(':');
+    mapped_id.push_str(sub_id);
+    let resolved = resolve_provider_sub_id(mapped_id, provider.len())?;
+    if resolved.mapped.name() == full_id {
+        Ok(resolved)
+    } else {
+        Ok(MappedKey::mapped(resolved.mapped, full_id))
+    }
+}
+
 /// An unvalidated wrapper for user input representing either a `provider:sub_id` or an `alias`.
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize, PartialOrd, Ord)]
 pub struct RecordId {
@@ -74,16 +93,21 @@ impl RecordId {
     ///
     /// The [`Alias`] conversion is infallible (validation only requires checking that the
     /// colon is not present) whereas the [`RemoteId`] conversion can fail if `provider` is
-    /// invalid or if `sub_id` is invalid given the provider.
+    /// invalid or if `sub_id` is invalid given the provider. A plain `http://` or `https://` URL
+    /// is converted to a [`RemoteId`] with the `url` provider, and a URL of a known landing
+    /// page is converted to the identifier of the corresponding provider.
     #[inline]
     pub fn resolve<A: AliasTransform>(
         self,
         alias_transform: &A,
     ) -> Result<AliasOrRemoteId, RecordError> {
         match self.provider_len {
-            Some(provider_len) => resolve_provider_sub_id(self.full_id, provider_len)
-                .map(AliasOrRemoteId::RemoteId)
-                .map_err(Into::into),
+            Some(provider_len) => match map_url(&self.full_id) {
+                Some((provider, sub_id)) => resolve_url(self.full_id, provider, &sub_id),
+                None => resolve_provider_sub_id(self.full_id, provider_len),
+            }
+            .map(AliasOrRemoteId::RemoteId)
+            .map_err(Into::into),
             None => {
                 if self.full_id.is_empty() {
                     Err(RecordError {
@@ -0,0 +1,91 @@
+//! # Record quality scores
+//!
+//! The completeness score of a record is a number between `0` and `1` which measures how much of
+//! the information expected in a bibliographic record is present in its data. The score is
+//! computed from the current data of the record, so it is updated whenever the record is
+//! modified.
+//!
+//! The score is a weighted sum of the following components:
+//!
+//! - `0.5`: the fraction of the required fields of the entry type which are present, using the
+//!   same required fields as `autobib check`,
+//! - `0.2`: the `doi` field is present,
+//! - `0.1`: the `abstract` field is present, and
+//! - `0.2`: the `author` field, or the `editor` field if there is no `author` field, is present
+//!   and written in one of the conventions of `normalize.name_format`.
+use crate::{app::required_fields, entry::EntryData, normalize::is_normalized_names};
+
+/// Compute the completeness score of the record data.
+pub fn completeness_score<D: EntryData>(data: &D) -> f64 {
+    let required = required_fields(&data.entry_type().to_ascii_lowercase());
+    let present = required
+        .iter()
+        .filter(|alternatives| alternatives.iter().any(|key| data.contains_field(key)))
+        .count();
+
+    // an entry type without required fields is not missing any fields
+    let mut score = if required.is_empty() {
+        0.5
+    } else {
+        0.5 * present as f64 / required.len() as f64
+    };
+    if data.contains_field("doi") {
+        score += 0.2;
+    }
+    if data.contains_field("abstract") {
+        score += 0.1;
+    }
+    if data
+        .get_field("author")
+        .or_else(|| data.get_field("editor"))
+        .is_some_and(is_normalized_names)
+    {
+        score += 0.2;
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::MutableEntryData;
+
+    fn data(entry_type: &str, fields: &[(&str, &str)]) -> MutableEntryData {
+        let mut data = MutableEntryData::try_new(entry_type.to_owned()).unwrap();
+        for (key, value) in fields {
+            data.check_and_insert((*key).to_owned(), (*value).to_owned())
+                .unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn test_completeness_score() {
+        let complete = data(
+            "article",
+            &[
+                ("author", "Knuth, Donald E."),
+                ("title", "Literate Programming"),
+                ("journal", "The Computer Journal"),
+                ("year", "1984"),
+                ("doi", "10.1093/comjnl/27.2.97"),
+                ("abstract", "The author and his associates..."),
+            ],
+        );
+        assert!((completeness_score(&complete) - 1.0).abs() < 1e-9);
+
+        let partial = data(
+            "article",
+            &[
+                ("author", "Knuth, Donald E. and Leslie Lamport"),
+                ("title", "Literate Programming"),
+            ],
+        );
+        assert!((completeness_score(&partial) - 0.25).abs() < 1e-9);
+
+        let book = data("book", &[("editor", "Lamport, Leslie"), ("date", "1986")]);
+        assert!((completeness_score(&book) - (0.5 * 2.0 / 4.0 + 0.2)).abs() < 1e-9);
+
+        assert_eq!(completeness_score(&data("misc", &[])), 0.0);
+    }
+}
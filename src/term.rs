@@ -1,7 +1,9 @@
 mod confirm;
+mod diff;
 mod editor;
 mod input;
 
 pub use confirm::Confirm;
-pub use editor::{Editor, EditorConfig};
+pub use diff::WordDiff;
+pub use editor::{Editor, EditorConfig, set_recovery_dir};
 pub use input::Input;
@@ -0,0 +1,211 @@
+use std::fmt;
+
+use crossterm::style::Stylize;
+
+/// The maximum size of the table used to compute the longest common subsequence. Larger inputs
+/// are treated as a single change.
+const MAX_TABLE_SIZE: usize = 1 << 20;
+
+/// A contiguous part of a word-level diff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Chunk<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+/// Split a string into alternating runs of whitespace and non-whitespace characters.
+fn tokenize(s: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut prev_ws = None;
+    for (idx, ch) in s.char_indices() {
+        let ws = ch.is_whitespace();
+        if prev_ws.is_some_and(|prev| prev != ws) {
+            tokens.push(&s[start..idx]);
+            start = idx;
+        }
+        prev_ws = Some(ws);
+    }
+    if start < s.len() {
+        tokens.push(&s[start..]);
+    }
+    tokens
+}
+
+/// A word-level diff between a current and an incoming value.
+///
+/// Use [`WordDiff::current`] and [`WordDiff::incoming`] to display either side of the diff, with
+/// the words which differ highlighted.
+#[derive(Debug)]
+pub struct WordDiff<'a> {
+    chunks: Vec<Chunk<'a>>,
+}
+
+impl<'a> WordDiff<'a> {
+    /// Compute the diff between the two values.
+    pub fn new(current: &'a str, incoming: &'a str) -> Self {
+        let old = tokenize(current);
+        let new = tokenize(incoming);
+
+        let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let mut chunks: Vec<Chunk<'a>> = old[..prefix].iter().copied().map(Chunk::Equal).collect();
+        diff_tokens(
+            &old[prefix..old.len() - suffix],
+            &new[prefix..new.len() - suffix],
+            &mut chunks,
+        );
+        chunks.extend(old[old.len() - suffix..].iter().copied().map(Chunk::Equal));
+
+        Self { chunks }
+    }
+
+    /// A display adapter for the current value, with removed words highlighted.
+    pub fn current(&self, styled: bool) -> DiffSide<'_, 'a> {
+        DiffSide {
+            diff: self,
+            incoming: false,
+            styled,
+        }
+    }
+
+    /// A display adapter for the incoming value, with added words highlighted.
+    pub fn incoming(&self, styled: bool) -> DiffSide<'_, 'a> {
+        DiffSide {
+            diff: self,
+            incoming: true,
+            styled,
+        }
+    }
+}
+
+/// Compute the diff between two token sequences using the longest common subsequence, and append
+/// the resulting chunks.
+fn diff_tokens<'a>(old: &[&'a str], new: &[&'a str], chunks: &mut Vec<Chunk<'a>>) {
+    let (n, m) = (old.len(), new.len());
+
+    if (n + 1).saturating_mul(m + 1) > MAX_TABLE_SIZE {
+        chunks.extend(old.iter().copied().map(Chunk::Delete));
+        chunks.extend(new.iter().copied().map(Chunk::Insert));
+        return;
+    }
+
+    // lcs[i * (m + 1) + j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i * (m + 1) + j] = if old[i] == new[j] {
+                lcs[(i + 1) * (m + 1) + j + 1] + 1
+            } else {
+                lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old[i] == new[j] {
+            chunks.push(Chunk::Equal(old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[(i + 1) * (m + 1) + j] >= lcs[i * (m + 1) + j + 1] {
+            chunks.push(Chunk::Delete(old[i]));
+            i += 1;
+        } else {
+            chunks.push(Chunk::Insert(new[j]));
+            j += 1;
+        }
+    }
+    chunks.extend(old[i..].iter().copied().map(Chunk::Delete));
+    chunks.extend(new[j..].iter().copied().map(Chunk::Insert));
+}
+
+/// A display adapter for one side of a [`WordDiff`].
+#[derive(Debug)]
+pub struct DiffSide<'d, 'a> {
+    diff: &'d WordDiff<'a>,
+    incoming: bool,
+    styled: bool,
+}
+
+impl fmt::Display for DiffSide<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in &self.diff.chunks {
+            match (*chunk, self.incoming) {
+                (Chunk::Equal(s), _) => f.write_str(s)?,
+                (Chunk::Delete(s), false) => {
+                    if self.styled {
+                        write!(f, "{}", s.red().bold().underlined())?;
+                    } else {
+                        f.write_str(s)?;
+                    }
+                }
+                (Chunk::Insert(s), true) => {
+                    if self.styled {
+                        write!(f, "{}", s.green().bold().underlined())?;
+                    } else {
+                        f.write_str(s)?;
+                    }
+                }
+                (Chunk::Delete(_), true) | (Chunk::Insert(_), false) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(tokenize(""), Vec::<&str>::new());
+        assert_eq!(tokenize("a  b\tc"), vec!["a", "  ", "b", "\t", "c"]);
+        assert_eq!(
+            tokenize(" On {BibTeX} "),
+            vec![" ", "On", " ", "{BibTeX}", " "]
+        );
+    }
+
+    #[test]
+    fn test_word_diff() {
+        let diff = WordDiff::new("On the theory of groups", "On a theory of finite groups");
+        assert_eq!(
+            diff.chunks,
+            vec![
+                Chunk::Equal("On"),
+                Chunk::Equal(" "),
+                Chunk::Delete("the"),
+                Chunk::Insert("a"),
+                Chunk::Equal(" "),
+                Chunk::Equal("theory"),
+                Chunk::Equal(" "),
+                Chunk::Equal("of"),
+                Chunk::Insert(" "),
+                Chunk::Insert("finite"),
+                Chunk::Equal(" "),
+                Chunk::Equal("groups"),
+            ]
+        );
+        assert_eq!(diff.current(false).to_string(), "On the theory of groups");
+        assert_eq!(
+            diff.incoming(false).to_string(),
+            "On a theory of finite groups"
+        );
+    }
+
+    #[test]
+    fn test_word_diff_disjoint() {
+        let diff = WordDiff::new("Alice", "Bob and Carol");
+        assert_eq!(diff.current(false).to_string(), "Alice");
+        assert_eq!(diff.incoming(false).to_string(), "Bob and Carol");
+    }
+}
@@ -1,9 +1,26 @@
-use std::{cmp::PartialEq, fmt::Display, io::Result, str::FromStr};
+use std::{
+    cmp::PartialEq,
+    fmt::Display,
+    fs::{self, File},
+    io::{self, Result, Write},
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    sync::OnceLock,
+};
 
 use edit::{Builder, edit_with_builder};
 
 use super::Confirm;
 
+/// The directory in which text which could not be parsed is saved, if it was set.
+static RECOVERY_DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+
+/// Save the text of edits which could not be parsed to files in the provided directory.
+pub fn set_recovery_dir(dir: PathBuf) {
+    let _ = RECOVERY_DIRECTORY.set(dir);
+}
+
 pub struct EditorConfig {
     /// The suffix for the temporary file.
     pub suffix: &'static str,
@@ -17,6 +34,7 @@ impl Default for EditorConfig {
 
 pub struct Editor {
     inner: Builder<'static, 'static>,
+    suffix: &'static str,
 }
 
 impl Default for Editor {
@@ -30,53 +48,182 @@ impl Editor {
     pub fn new(config: EditorConfig) -> Self {
         let mut inner = Builder::new();
         inner.suffix(config.suffix);
-        Self { inner }
+        Self {
+            inner,
+            suffix: config.suffix,
+        }
     }
 
     /// Initialize a new editor with good defaults for a BibTeX file.
     pub fn new_bibtex() -> Self {
-        let mut inner = Builder::new();
-        inner.suffix(".bib");
-        Self { inner }
+        Self::new(EditorConfig { suffix: ".bib" })
     }
 
     /// Edit the object and optionally return a new object. This will repeatedly prompt the user to
     /// edit until the object is changed. If this returns `Ok(Some(object)`, the new `object` is
     /// guaranteed to be different than the old object. This returns `Ok(None)` if the user cancelled
     /// the edit by quiting with making no changes to the text in the file.
+    ///
+    /// If the edited text cannot be parsed, it is saved to a recovery file so that the changes are
+    /// not lost, and the user is offered to reopen the text. For BibTeX files, the error is
+    /// described in a comment header.
     pub fn edit<T: ToString + FromStr + PartialEq>(&self, object: &T) -> Result<Option<T>>
     where
         <T as FromStr>::Err: Display,
     {
         let prompter = Confirm::new("Continue editing?", true);
+        let reopen_prompter = Confirm::new("Reopen the editor to fix the error?", true);
         let mut response = object.to_string();
+        let mut header = String::new();
+        let mut recovery_file = None;
 
-        loop {
-            let user_text = edit_with_builder(&response, &self.inner)?;
+        let edited = loop {
+            let buffer = edit_with_builder(format!("{header}{response}"), &self.inner)?;
+            // the header is only informational, so remove it if it was left intact
+            let user_text = match buffer.strip_prefix(&header) {
+                Some(text) => text.to_owned(),
+                None => buffer,
+            };
 
             // the text was unchanged
             if user_text == response {
-                break Ok(None);
+                break None;
             }
 
             match T::from_str(&user_text) {
                 Ok(new_object) => {
                     if &new_object != object {
-                        break Ok(Some(new_object));
+                        break Some(new_object);
                     } else {
                         eprint!("Text edited but contents unchanged! ");
                     }
+                    header.clear();
+                    if !prompter.confirm()? {
+                        break None;
+                    }
                 }
                 Err(err) => {
                     eprintln!("Contents invalid: {err}");
+                    match self.write_recovery_file(&user_text, recovery_file.as_deref()) {
+                        Ok(path) => {
+                            eprintln!("The edited text was saved to '{}'", path.display());
+                            recovery_file = Some(path);
+                        }
+                        Err(err) => eprintln!("Failed to save the edited text: {err}"),
+                    }
+                    header = self.error_header(&err);
+                    if !reopen_prompter.confirm()? {
+                        break None;
+                    }
                 }
             }
 
-            if prompter.confirm()? {
-                response = user_text;
-            } else {
-                break Ok(None);
+            response = user_text;
+        };
+
+        // the recovery file is no longer needed once the edit succeeds
+        if edited.is_some()
+            && let Some(path) = recovery_file
+        {
+            let _ = fs::remove_file(path);
+        }
+
+        Ok(edited)
+    }
+
+    /// A comment header describing an error in the edited text, which is empty unless the text
+    /// is BibTeX, since only BibTeX is parsed with `%` comments.
+    fn error_header<E: Display>(&self, err: &E) -> String {
+        if self.suffix == ".bib" {
+            error_header(err)
+        } else {
+            String::new()
+        }
+    }
+
+    /// Save text which could not be parsed to the recovery file created earlier in this edit, or
+    /// otherwise to a new file in the recovery directory, returning the path to the file.
+    fn write_recovery_file(&self, text: &str, existing: Option<&Path>) -> Result<PathBuf> {
+        if let Some(path) = existing {
+            fs::write(path, text)?;
+            return Ok(path.to_owned());
+        }
+        let Some(dir) = RECOVERY_DIRECTORY.get() else {
+            return Err(io::Error::other("no directory for recovery files"));
+        };
+        create_recovery_file(dir, self.suffix, text)
+    }
+}
+
+/// Write the text to a new file in the directory, which is created if it does not exist. Existing
+/// files are never overwritten.
+fn create_recovery_file(dir: &Path, suffix: &str, text: &str) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let mut idx = 0;
+    loop {
+        let path = dir.join(format!("edit-{}-{idx}{suffix}", process::id()));
+        match File::create_new(&path) {
+            Ok(mut file) => {
+                file.write_all(text.as_bytes())?;
+                return Ok(path);
             }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => idx += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// A comment header describing an error in the edited text.
+fn error_header<E: Display>(err: &E) -> String {
+    let mut header = String::from("% The text below could not be parsed:\n");
+    for line in err.to_string().lines() {
+        header.push_str("%   ");
+        header.push_str(line);
+        header.push('\n');
+    }
+    header.push_str(
+        "% Fix the error and save the file. These comment lines are removed automatically.\n",
+    );
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_create_recovery_file() {
+        let dir = TempDir::new().unwrap();
+        let recovery_dir = dir.path().join("recovery");
+
+        let first = create_recovery_file(&recovery_dir, ".bib", "first").unwrap();
+        let second = create_recovery_file(&recovery_dir, ".bib", "second").unwrap();
+        assert_ne!(first, second);
+        assert!(first.starts_with(&recovery_dir));
+        assert_eq!(fs::read_to_string(&first).unwrap(), "first");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "second");
+    }
+
+    #[test]
+    fn test_error_header() {
+        let header = error_header(&"unexpected token\nat line 2");
+        assert_eq!(
+            header,
+            "% The text below could not be parsed:\n\
+             %   unexpected token\n\
+             %   at line 2\n\
+             % Fix the error and save the file. These comment lines are removed automatically.\n"
+        );
+
+        assert_eq!(
+            Editor::new_bibtex().error_header(&"error"),
+            error_header(&"error")
+        );
+        for suffix in [".txt", ".tex"] {
+            let editor = Editor::new(EditorConfig { suffix });
+            assert_eq!(editor.error_header(&"error"), "");
         }
     }
 }
@@ -283,1255 +283,5968 @@ fn source() -> Result<()> {
     s.close()
 }
 
-/// Check that `autobib source --print-keys` works.
+/// Check that `autobib config get` and `autobib config set` read and modify the configuration.
 #[test]
-fn source_keys_only() -> Result<()> {
+fn config_get_set() -> Result<()> {
     let s = TestState::init()?;
+    fs::write(
+        s.config.as_ref(),
+        "# my providers\npreferred_providers = [\"mr\"]\n\n[on_insert]\n# whitespace\nnormalize_whitespace = false\n",
+    )?;
 
     let mut cmd = s.cmd()?;
-    cmd.args(["source", "tests/resources/source/main.tex", "--print-keys"]);
-
-    s.close()
-}
+    cmd.args(["config", "get", "on_insert.normalize_whitespace"]);
+    cmd.assert().success().stdout("false\n");
 
-/// Check that the `--skip*` and `--append` options for `autobib source`
-/// work as expected
-#[test]
-fn source_skip() -> Result<()> {
-    let s = TestState::init()?;
+    // values which are not set are taken from the default configuration
+    let mut cmd = s.cmd()?;
+    cmd.args(["config", "get", "normalize.name_format"]);
+    cmd.assert().success().stdout("last-first\n");
 
-    let output = NamedTempFile::new("out.bib")?;
-    output.write_str("@preprint{arxiv:1212.1873,}\n")?;
+    let mut cmd = s.cmd()?;
+    cmd.args(["config", "get", "on_insert.unknown"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Unknown configuration key"));
 
     let mut cmd = s.cmd()?;
+    cmd.args(["config", "set", "on_insert.normalize_whitespace", "true"]);
+    cmd.assert().success();
 
-    cmd.arg("source")
-        .arg("tests/resources/source_skip/main.tex");
-    cmd.args([
-        "--skip",
-        "isbn:9781119942399",
-        "--skip-from",
-        "tests/resources/source_skip/skip.tex",
-        "--skip-from",
-        "tests/resources/source_skip/skip.bib",
-        "--out",
-        &output.to_string_lossy(),
-        "--append",
-    ]);
+    let mut cmd = s.cmd()?;
+    cmd.args(["config", "set", "normalize.name_format", "first-last"]);
+    cmd.assert().success();
 
-    cmd.assert().success().stderr(predicate::str::is_empty());
+    let mut cmd = s.cmd()?;
+    cmd.args(["config", "set", "on_insert.normalize_whitespace", "maybe"]);
+    cmd.assert().failure().stderr(contains("Invalid value"));
 
-    let predicate_file =
-        predicate::path::eq_file(Path::new("tests/resources/source_skip/stdout.txt"))
-            .utf8()
-            .unwrap();
+    assert_eq!(
+        fs::read_to_string(s.config.as_ref())?,
+        "# my providers\npreferred_providers = [\"mr\"]\n\n[on_insert]\n# whitespace\nnormalize_whitespace = true\n\n[normalize]\nname_format = \"first-last\"\n"
+    );
 
-    assert!(predicate_file.eval(output.as_ref()));
+    let mut cmd = s.cmd()?;
+    cmd.args(["config", "get", "preferred_providers"]);
+    cmd.assert().success().stdout("[\"mr\"]\n");
 
     s.close()
 }
 
-/// Check that `autobib get` fails correctly when the resource does not exist.
+/// Check that the `output.annotate` configuration option writes comments above the entries.
 #[test]
-fn get_null() -> Result<()> {
+fn get_annotate() -> Result<()> {
     let s = TestState::init()?;
+    fs::write(s.config.as_ref(), "[output]\nannotate = true\n")?;
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbl:9999.28015"]);
-    cmd.assert().failure().stderr(contains("Null record"));
+    cmd.args(["local", "first", "--with-field", "title = {A}"]);
+    cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "--ignore-null", "zbl:9999.28015"]);
-    cmd.assert().success().stderr(predicate::str::is_empty());
+    cmd.args(["local", "second", "--with-field", "title = {B}"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:first", "local:second"]);
+    cmd.assert().success().stdout(
+        predicate::str::is_match(
+            r"\A% autobib: local:first \(modified \d{4}-\d{2}-\d{2}\)\n@misc\{local:first,\n  title = \{A\},\n\}\n\n% autobib: local:second \(modified \d{4}-\d{2}-\d{2}\)\n@misc\{local:second,",
+        )
+        .unwrap(),
+    );
 
     s.close()
 }
 
-/// Check that `autobib local` works as expected.
+/// Check that `--flavor` and the `output.flavor` configuration value convert the output.
 #[test]
-fn local() -> Result<()> {
+fn get_flavor() -> Result<()> {
     let s = TestState::init()?;
 
     let mut cmd = s.cmd()?;
     cmd.args([
         "local",
         "first",
-        "--from-bibtex",
-        "tests/resources/local/first.bib",
+        "--with-entry-type",
+        "article",
+        "--with-field",
+        "journal = {J}",
+        "--with-field",
+        "year = {2001}",
+        "--with-field",
+        "month = {mar}",
     ]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["--read-only", "local", "second"]);
-    cmd.assert()
-        .failure()
-        .stderr(contains("cannot be used in read-only mode"));
+    cmd.args(["get", "local:first", "--flavor", "biblatex"]);
+    cmd.assert().success().stdout(
+        contains("journaltitle = {J}")
+            .and(contains("date = {2001-03}"))
+            .and(contains("year").not()),
+    );
 
     let mut cmd = s.cmd()?;
     cmd.args(["get", "local:first"]);
-    let predicate_file = predicate::path::eq_file(Path::new("tests/resources/local/stdout.txt"))
-        .utf8()
-        .unwrap();
-    cmd.assert().success().stdout(predicate_file);
+    cmd.assert()
+        .success()
+        .stdout(contains("journal = {J}").and(contains("year = {2001}")));
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["local", "first"]);
-    cmd.assert().failure();
+    fs::write(s.config.as_ref(), "[output]\nflavor = \"biblatex\"\n")?;
 
     let mut cmd = s.cmd()?;
-    cmd.args([
-        "local",
-        "first",
-        "--from-bibtex",
-        "tests/resources/local/first.bib",
-    ]);
+    cmd.args(["get", "local:first"]);
     cmd.assert()
-        .failure()
-        .stderr(contains("Local record 'local:first' already exists"));
+        .success()
+        .stdout(contains("journaltitle = {J}"));
 
     let mut cmd = s.cmd()?;
-    cmd.args(["local", "second"]);
-    cmd.assert().success();
+    cmd.args(["get", "local:first", "--flavor", "bibtex"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("journal = {J}").and(contains("month = {mar}")));
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["get", "local:second"]);
-    let predicate_file =
-        predicate::path::eq_file(Path::new("tests/resources/local/stdout_short.txt"))
-            .utf8()
-            .unwrap();
-    cmd.assert().success().stdout(predicate_file);
+    s.close()
+}
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["local", " \n"]);
-    cmd.assert().failure().stderr(contains(
-        "local sub-id must contain non-whitespace characters",
-    ));
+/// Check that `--sort` and the `output.sort` configuration value change the order of the output.
+#[test]
+fn get_sort() -> Result<()> {
+    let s = TestState::init()?;
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["local", ":"]);
-    cmd.assert()
-        .failure()
-        .stderr(contains("local sub-id must not contain a colon"));
+    for (key, fields) in [
+        ("zeta", ["author = {Adams, Ann}", "year = {2005}"]),
+        ("alpha", ["author = {Brown, Bob}", "year = {2001}"]),
+        ("mid", ["title = {No author}", "date = {1999-05}"]),
+    ] {
+        let mut cmd = s.cmd()?;
+        cmd.args([
+            "local",
+            key,
+            "--with-field",
+            fields[0],
+            "--with-field",
+            fields[1],
+        ]);
+        cmd.assert().success();
+    }
+
+    let check = |args: &[&str], pattern: &str| -> Result<()> {
+        let mut cmd = s.cmd()?;
+        cmd.args(["get", "local:zeta", "local:alpha", "local:mid"]);
+        cmd.args(args);
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::is_match(pattern).unwrap());
+        Ok(())
+    };
+
+    check(&[], r"(?s)local:alpha.*local:mid.*local:zeta")?;
+    check(
+        &["--sort", "author"],
+        r"(?s)local:zeta.*local:alpha.*local:mid",
+    )?;
+    check(
+        &["--sort", "year"],
+        r"(?s)local:mid.*local:alpha.*local:zeta",
+    )?;
+    check(
+        &["--sort", "added"],
+        r"(?s)local:zeta.*local:alpha.*local:mid",
+    )?;
+
+    fs::write(s.config.as_ref(), "[output]\nsort = \"year\"\n")?;
+    check(&[], r"(?s)local:mid.*local:alpha.*local:zeta")?;
+    check(
+        &["--sort", "none"],
+        r"(?s)local:alpha.*local:mid.*local:zeta",
+    )?;
 
     s.close()
 }
 
-/// Check that `autobib alias` works as expected.
+/// Check that the formatting options change the written entries.
 #[test]
-fn alias() -> Result<()> {
+fn get_style() -> Result<()> {
     let s = TestState::init()?;
 
     let mut cmd = s.cmd()?;
     cmd.args([
         "local",
         "first",
-        "--from-bibtex",
-        "tests/resources/local/first.bib",
+        "--with-field",
+        "title = {A long title of a paper}",
+        "--with-field",
+        "year = {2001}",
     ]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "local:first"]);
-    cmd.assert().success();
-
-    let mut cmd = s.cmd()?;
-    cmd.args(["alias", "add", "my_alias", "local:first"]);
-    cmd.assert().success();
+    cmd.args([
+        "get",
+        "local:first",
+        "--indent",
+        "4",
+        "--align",
+        "--no-trailing-comma",
+    ]);
+    cmd.assert().success().stdout(predicate::str::diff(
+        "@misc{local:first,\n    title = {A long title of a paper},\n    year  = {2001}\n}\n",
+    ));
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["local", "second"]);
-    cmd.assert().success();
+    fs::write(s.config.as_ref(), "[output]\nwrap = 24\n")?;
 
     let mut cmd = s.cmd()?;
-    cmd.args(["alias", "add", "my_alias", "local:second"]);
-    cmd.assert()
-        .failure()
-        .stderr(contains("Alias already exists"));
+    cmd.args(["get", "local:first"]);
+    cmd.assert().success().stdout(predicate::str::diff(
+        "@misc{local:first,\n  title = {A long title\n           of a paper},\n  year = {2001},\n}\n",
+    ));
 
     let mut cmd = s.cmd()?;
-    cmd.arg("get").arg("my_alias");
-    let predicate_file = predicate::path::eq_file(Path::new("tests/resources/alias/stdout.txt"))
-        .utf8()
-        .unwrap();
-    cmd.assert().success().stdout(predicate_file);
+    cmd.args(["get", "local:first", "--wrap", "0"]);
+    cmd.assert().success().stdout(predicate::str::diff(
+        "@misc{local:first,\n  title = {A long title of a paper},\n  year = {2001},\n}\n",
+    ));
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["alias", "rename", "my_alias", "new_alias"]);
-    cmd.assert().success();
+    s.close()
+}
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["get", "new_alias"]);
-    cmd.assert().success().stdout(contains("@book{new_alias"));
+/// Check the duplicate key policy and that `--report-duplicates` writes the key groups.
+#[test]
+fn get_report_duplicates() -> Result<()> {
+    let s = TestState::init()?;
+    let report = NamedTempFile::new("duplicates.json")?;
 
     let mut cmd = s.cmd()?;
-    cmd.args(["alias", "delete", "new_alias"]);
+    cmd.args(["local", "first", "--with-field", "title = {A}"]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "my_alias"]);
-    cmd.assert().failure().stderr(contains("Undefined alias"));
+    cmd.args(["alias", "add", "fi", "local:first"]);
+    cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "new_alias"]);
-    cmd.assert().failure().stderr(contains("Undefined alias"));
+    cmd.args(["get", "local:first", "fi", "--report-duplicates"])
+        .arg(report.as_ref());
+    cmd.assert()
+        .success()
+        .stderr(contains("Multiple keys for 'local:first'"));
+    report.assert(
+        predicate::str::contains(r#""canonical": "local:first""#).and(
+            predicate::str::contains(r#""fi""#).and(predicate::str::contains(r#""local:first""#)),
+        ),
+    );
 
+    fs::write(s.config.as_ref(), "[output]\nduplicates = \"ignore\"\n")?;
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "--ignore-null", "new_alias"]);
-    cmd.assert().success().stderr(predicate::str::is_empty());
+    cmd.args(["get", "local:first", "fi"]);
+    cmd.assert()
+        .success()
+        .stderr(contains("Multiple keys").not());
 
+    fs::write(s.config.as_ref(), "[output]\nduplicates = \"error\"\n")?;
     let mut cmd = s.cmd()?;
-    cmd.args(["alias", "delete", "my_alias"]);
+    cmd.args(["get", "local:first", "fi"]);
     cmd.assert()
         .failure()
-        .stderr(contains("Could not delete alias which does not exist"));
+        .stderr(contains("Multiple keys for 'local:first'"))
+        .stdout(contains("@misc{fi,"));
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["alias", "add", "  ", "not_an_alias"]);
-    cmd.assert().failure().stderr(
-        contains("invalid value '  ' for '<ALIAS>'")
-            .and(contains("alias must contain non-whitespace characters")),
-    );
+    s.close()
+}
+
+/// Check that `autobib source` searches directories recursively.
+#[test]
+fn source_directory() -> Result<()> {
+    let s = TestState::init()?;
+    fs::write(
+        s.config.as_ref(),
+        "[source]\nignore = [\"build\", \"*-old.tex\"]\n",
+    )?;
+
+    let dir = TempDir::new()?;
+    dir.child("intro.tex").write_str(r"\cite{first}")?;
+    dir.child("chapters/one.md").write_str("See [@second].")?;
+    dir.child("chapters/two-old.tex")
+        .write_str(r"\cite{ignored1}")?;
+    dir.child("build/main.aux")
+        .write_str(r"\citation{ignored2}")?;
+    dir.child(".hidden/notes.tex")
+        .write_str(r"\cite{ignored3}")?;
+    dir.child("refs.bib").write_str("@misc{ignored4,}")?;
+    dir.child("README.txt").write_str("ignored5")?;
+
+    let mut cmd = s.cmd()?;
+    cmd.arg("source").arg(dir.path()).arg("--print-keys");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("first\nsecond\n"));
 
     let mut cmd = s.cmd()?;
-    cmd.args(["alias", "add", "\n\t", "not_an_alias"]);
-    cmd.assert().failure().stderr(
-        contains("invalid value '\n\t' for '<ALIAS>'")
-            .and(contains("alias must contain non-whitespace characters")),
-    );
+    cmd.arg("source")
+        .arg(dir.path())
+        .args(["--print-keys", "--file-type", "md"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("second\n"));
+
+    dir.close()?;
+    s.close()
+}
+
+/// Check that `autobib source` finds the same keys when the files are read in parallel.
+#[test]
+fn source_many_files() -> Result<()> {
+    let s = TestState::init()?;
+
+    let dir = TempDir::new()?;
+    let mut expected = Vec::new();
+    for i in 0..100 {
+        // every key is cited in two files
+        dir.child(format!("chapter{i:03}.tex"))
+            .write_str(&format!(r"\cite{{key{i:03},key{:03}}}", (i + 1) % 100))?;
+        expected.push(format!("key{i:03}\n"));
+    }
+    dir.child("chapter100.tex").write_str(r"\cite{skipped}")?;
 
     let mut cmd = s.cmd()?;
-    cmd.args(["alias", "add", "has ws", "not_an_alias"]);
+    cmd.arg("source")
+        .arg(dir.path())
+        .args(["--print-keys", "--skip", "skipped"]);
     cmd.assert()
-        .failure()
-        .stderr(contains("Cannot create alias for undefined alias"));
+        .success()
+        .stdout(predicate::str::diff(expected.concat()));
 
+    dir.close()?;
     s.close()
 }
 
-/// Check that `autobib alias` works as expected with null and existing remote records.
+/// Check that `autobib source --stream` writes every entry in the order of the keys.
 #[test]
-fn alias_remote() -> Result<()> {
+fn source_stream() -> Result<()> {
     let s = TestState::init()?;
+    s.create_test_db()?;
 
     let mut cmd = s.cmd()?;
-    cmd.args(["alias", "add", "al", "zbmath:06346461"]);
+    cmd.args(["alias", "add", "legacy", "local:first"]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "al"]);
-    let predicate_file =
-        predicate::path::eq_file(Path::new("tests/resources/alias_remote/stdout.txt"))
-            .utf8()
-            .unwrap();
-    cmd.assert().success().stdout(predicate_file);
+    cmd.args(["get", "legacy", "local:first", "local:second"]);
+    let output = cmd.output()?;
+    let expected = String::from_utf8(output.stdout)?;
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["alias", "add", "a2", "zbmath:96346461"]);
-    cmd.assert()
-        .failure()
-        .stderr(contains("Cannot create alias for null record"));
+    let dir = TempDir::new()?;
+    dir.child("main.tex")
+        .write_str(r"\cite{local:second,legacy}\cite{local:first}")?;
 
     let mut cmd = s.cmd()?;
-    cmd.args(["alias", "add", "a2", "alias-does-not-exist"]);
+    cmd.arg("source").arg(dir.path()).arg("--stream");
     cmd.assert()
-        .failure()
-        .stderr(contains("Cannot create alias for undefined alias"));
+        .success()
+        .stdout(predicate::str::diff(expected))
+        .stderr(contains(
+            "Multiple keys for 'local:first': legacy, local:first",
+        ));
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "a2"]);
-    cmd.assert().failure().stderr(contains("Undefined alias"));
+    cmd.arg("source")
+        .arg(dir.path())
+        .args(["--stream", "--sort", "key"]);
+    cmd.assert().failure().code(2);
 
+    dir.close()?;
     s.close()
 }
 
-/// Check that `autobib get` validates BibTeX citation keys and suggests alternatives on failure.
+/// Check that `autobib refactor-key` renames citations in source files and the alias.
 #[test]
-fn bibtex_key_validation() -> Result<()> {
+fn refactor_key() -> Result<()> {
     let s = TestState::init()?;
+    s.create_test_db()?;
 
     let mut cmd = s.cmd()?;
-    cmd.args([
-        "alias",
-        "add",
-        "cst1989",
-        "doi:10.1016/0021-8693(89)90256-1",
-    ]);
+    cmd.args(["alias", "add", "legacy", "local:first"]);
     cmd.assert().success();
 
+    let dir = TempDir::new()?;
+    dir.child("main.tex")
+        .write_str("\\cite{legacy, other}\n% legacy\n\\cite{legacy2}\n")?;
+    dir.child("notes/one.md")
+        .write_str("See [@legacy] and `@legacy`.\n")?;
+    dir.child("notes/two.typ").write_str("#cite(<other>)\n")?;
+
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "doi:10.1016/0021-8693(89)90256-1"]);
-    cmd.assert()
-        .failure()
-        .stderr(contains("Identifier contains invalid character").and(contains("cst1989")));
+    cmd.args(["refactor-key", "legacy", "first-book", "--dry-run", "--in"])
+        .arg(dir.path());
+    cmd.assert().success().stdout(contains(
+        "@@ -1 +1 @@\n-See [@legacy] and `@legacy`.\n+See [@first-book] and `@legacy`.\n",
+    ));
+    assert_eq!(
+        fs::read_to_string(dir.child("main.tex").path())?,
+        "\\cite{legacy, other}\n% legacy\n\\cite{legacy2}\n"
+    );
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "cst1989"]);
+    cmd.args(["refactor-key", "legacy", "first-book", "--in"])
+        .arg(dir.path());
     cmd.assert().success();
+    assert_eq!(
+        fs::read_to_string(dir.child("main.tex").path())?,
+        "\\cite{first-book, other}\n% legacy\n\\cite{legacy2}\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.child("notes/one.md").path())?,
+        "See [@first-book] and `@legacy`.\n"
+    );
 
     let mut cmd = s.cmd()?;
-    cmd.args([
-        "get",
-        "--retrieve-only",
-        "doi:10.1016/0021-8693(89)90256-1",
-        "cst1989",
-    ]);
+    cmd.args(["get", "first-book"]);
     cmd.assert()
         .success()
-        .stdout(predicate::str::is_empty())
-        .stderr(predicate::str::is_empty());
-
-    let mut cmd = s.cmd()?;
-    cmd.args(["alias", "add", "has ws", "cst1989"]);
-    cmd.assert().success();
+        .stdout(contains("@article{first-book,"));
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "has ws"]);
-    cmd.assert()
-        .failure()
-        .stderr(contains("Identifier contains invalid character"));
+    cmd.args(["get", "legacy"]);
+    cmd.assert().failure();
 
+    dir.close()?;
     s.close()
 }
 
-/// Test deletion, including of aliases.
+/// Check that `autobib source --emit-deps` writes the dependency list.
 #[test]
-fn delete() -> Result<()> {
+fn source_emit_deps() -> Result<()> {
     let s = TestState::init()?;
+    let out = NamedTempFile::new("main.bib")?;
+    let deps = NamedTempFile::new("main.d")?;
 
-    // single deletion OK even without `--force`
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "mr:3224722"]);
-    cmd.assert().success();
+    cmd.args([
+        "source",
+        "tests/resources/source_skip/main.tex",
+        "--skip-from",
+        "tests/resources/source_skip/skip.bib",
+        "--retrieve-only",
+        "--emit-deps",
+    ])
+    .arg(deps.as_ref())
+    .arg("--out")
+    .arg(out.as_ref());
+    cmd.assert().failure();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["delete", "mr:3224722"]);
+    cmd.args([
+        "source",
+        "tests/resources/source_skip/main.tex",
+        "--skip-from",
+        "tests/resources/source_skip/skip.bib",
+        "--emit-deps",
+    ])
+    .arg(deps.as_ref())
+    .arg("--out")
+    .arg(out.as_ref());
     cmd.assert().success();
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["delete", "mr:3224722"]);
-    cmd.assert().failure();
+    assert_eq!(
+        fs::read_to_string(deps.as_ref())?,
+        format!(
+            "{}: tests/resources/source_skip/main.tex tests/resources/source_skip/skip.bib\n\n\
+             tests/resources/source_skip/main.tex:\n\ntests/resources/source_skip/skip.bib:\n",
+            out.path().display()
+        )
+    );
+
+    s.close()
+}
+
+/// Check that `autobib source --target-style` warns about unsupported entries.
+#[test]
+fn source_target_style() -> Result<()> {
+    let s = TestState::init()?;
 
-    // multi deletion succeeds, and applies to all aliases
     let mut cmd = s.cmd()?;
     cmd.args([
         "local",
         "first",
-        "--from-bibtex",
-        "tests/resources/local/first.bib",
+        "--with-entry-type",
+        "article",
+        "--with-field",
+        "author = {A}",
+        "--with-field",
+        "title = {T}",
+        "--with-field",
+        "journaltitle = {J}",
+        "--with-field",
+        "date = {2000}",
     ]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["alias", "add", "my_alias", "local:first"]);
+    cmd.args([
+        "local",
+        "second",
+        "--with-entry-type",
+        "misc",
+        "--with-field",
+        "title = {T}",
+    ]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "local:first"]);
+    cmd.args([
+        "local",
+        "third",
+        "--with-entry-type",
+        "online",
+        "--with-field",
+        "title = {T}",
+    ]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["delete", "my_alias"]);
-    cmd.assert().success();
+    cmd.args([
+        "source",
+        "tests/resources/source_target_style/main.tex",
+        "--target-style",
+        "plain",
+    ]);
+    cmd.assert().success().stderr(
+        contains("Entry 'local:first' with entry type 'article' is missing field 'journal' required by style 'plain'")
+            .and(contains("is missing field 'year' required by style 'plain'"))
+            .and(contains("Entry 'local:second' with entry type 'misc' is missing field 'howpublished'"))
+            .and(contains("Entry 'local:third' has entry type 'online' which is not supported by style 'plain'")),
+    );
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["get", "my_alias"]);
-    cmd.assert().failure().stderr(contains("Deleted record"));
+    s.set_config("tests/resources/source_target_style/config.toml")?;
 
     let mut cmd = s.cmd()?;
-    cmd.args(["delete", "my_alias"]);
-    cmd.assert().failure().stderr(contains("already deleted"));
+    cmd.args([
+        "source",
+        "tests/resources/source_target_style/main.tex",
+        "--target-style",
+        "mystyle",
+    ]);
+    cmd.assert()
+        .success()
+        .stderr(contains("local:first").not().and(contains(
+            "Entry 'local:second' has entry type 'misc' which is not supported by style 'mystyle'",
+        )));
 
-    // deleting multiple
     let mut cmd = s.cmd()?;
-    cmd.args(["delete", "--hard", "local:first", "my_alias"]);
+    cmd.args([
+        "source",
+        "tests/resources/source_target_style/main.tex",
+        "--target-style",
+        "undefined",
+    ]);
     cmd.assert()
         .failure()
-        .stderr(contains("Cannot delete undefined alias"));
-
-    let mut cmd = s.cmd()?;
-    cmd.args(["get", "local:first"]);
-    cmd.assert().failure().stderr(contains(
-        "Cannot retrieve remote data for key with local provenance",
-    ));
+        .stderr(contains("Unknown target style 'undefined'"));
 
     s.close()
 }
 
-/// Test citation key listing.
+/// Check that `autobib source --print-keys` works.
 #[test]
-fn list() -> Result<()> {
+fn source_keys_only() -> Result<()> {
     let s = TestState::init()?;
 
     let mut cmd = s.cmd()?;
-    cmd.args([
-        "local",
-        "first",
-        "--from-bibtex",
-        "tests/resources/local/first.bib",
-    ]);
-    cmd.assert().success();
+    cmd.args(["source", "tests/resources/source/main.tex", "--print-keys"]);
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["alias", "add", "my_alias", "local:first"]);
-    cmd.assert().success();
+    s.close()
+}
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbl:1337.28015"]);
-    cmd.assert().success();
+/// Check that `autobib source --diff` compares the cited keys with a BibTeX file.
+#[test]
+fn source_diff() -> Result<()> {
+    let s = TestState::init()?;
+
+    let dir = TempDir::new()?;
+    dir.child("main.tex")
+        .write_str(r"\cite{alpha,beta} \cite{delta}")?;
+    dir.child("refs.bib")
+        .write_str("@misc{beta, title = {B}}\n@misc{gamma, title = {G}}\n@misc{delta,}\n")?;
 
     let mut cmd = s.cmd()?;
-    cmd.args(["util", "list"]);
-    cmd.assert()
-        .success()
-        .stdout(contains("zbmath:06346461").and(contains("my_alias")));
+    cmd.arg("source")
+        .arg(dir.child("main.tex").path())
+        .arg("--diff")
+        .arg(dir.child("refs.bib").path());
+    cmd.assert().success().stdout(
+        contains("Cited but missing from")
+            .and(contains("\n  alpha\nPresent in"))
+            .and(contains("but not cited:\n  gamma\n"))
+            .and(contains("beta").not())
+            .and(contains("delta").not()),
+    );
 
     let mut cmd = s.cmd()?;
-    cmd.args(["--read-only", "util", "list"]);
-    cmd.assert()
-        .success()
-        .stdout(contains("zbmath:06346461").and(contains("my_alias")));
+    cmd.arg("source")
+        .arg(dir.child("main.tex").path())
+        .args(["--skip", "alpha", "--skip", "gamma", "--diff"])
+        .arg(dir.child("refs.bib").path());
+    cmd.assert().success().stdout(predicate::str::is_empty());
 
     let mut cmd = s.cmd()?;
-    cmd.args(["util", "list", "--canonical"]);
-    cmd.assert()
-        .success()
-        .stdout(contains("my_alias").not().and(contains("local:first")));
+    cmd.arg("source")
+        .arg(dir.child("main.tex").path())
+        .args(["--print-keys", "--diff"])
+        .arg(dir.child("refs.bib").path());
+    cmd.assert().failure();
 
+    dir.close()?;
     s.close()
 }
 
+/// Check that the `--skip*` and `--append` options for `autobib source`
+/// work as expected
 #[test]
-fn info() -> Result<()> {
+fn source_skip() -> Result<()> {
     let s = TestState::init()?;
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["info", "zbl:1337.28015", "-r", "canonical"]);
-    cmd.assert()
-        .failure()
-        .stderr(contains("Cannot obtain report for record not in database"));
-
-    let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbl:1337.28015"]);
-    cmd.assert().success();
+    let output = NamedTempFile::new("out.bib")?;
+    output.write_str("@preprint{arxiv:1212.1873,}\n")?;
 
     let mut cmd = s.cmd()?;
-    cmd.args(["info", "zbl:1337.28015", "--report", "canonical"]);
-    cmd.assert().success().stdout("zbmath:06346461\n");
 
-    let mut cmd = s.cmd()?;
+    cmd.arg("source")
+        .arg("tests/resources/source_skip/main.tex");
     cmd.args([
-        "--read-only",
-        "info",
-        "zbl:1337.28015",
-        "--report",
-        "canonical",
+        "--skip",
+        "isbn:9781119942399",
+        "--skip-from",
+        "tests/resources/source_skip/skip.tex",
+        "--skip-from",
+        "tests/resources/source_skip/skip.bib",
+        "--out",
+        &output.to_string_lossy(),
+        "--append",
     ]);
-    cmd.assert().success().stdout("zbmath:06346461\n");
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["alias", "add", "%", "zbmath:06346461"]);
-    cmd.assert().success();
+    cmd.assert().success().stderr(predicate::str::is_empty());
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["info", "zbl:1337.28015", "-r", "equivalent"]);
-    cmd.assert().success().stdout(
-        contains("%")
-            .and(contains("zbmath:06346461"))
-            .and(contains("zbl:1337.28015")),
-    );
+    let predicate_file =
+        predicate::path::eq_file(Path::new("tests/resources/source_skip/stdout.txt"))
+            .utf8()
+            .unwrap();
+
+    assert!(predicate_file.eval(output.as_ref()));
+
+    s.close()
+}
+
+/// Check that `autobib get` fails correctly when the resource does not exist.
+#[test]
+fn get_null() -> Result<()> {
+    let s = TestState::init()?;
 
     let mut cmd = s.cmd()?;
-    cmd.args(["info", "%", "-r", "valid"]);
-    cmd.assert().failure().stderr(contains("Invalid BibTeX"));
+    cmd.args(["get", "zbl:9999.28015"]);
+    cmd.assert().failure().stderr(contains("Null record"));
 
     let mut cmd = s.cmd()?;
-    cmd.args(["info", "%"]);
-    cmd.assert().success().stdout(
-        contains("Data last modified:")
-            .and(contains("Equivalent references:"))
-            .and(contains("Canonical: zbmath:06346461\n"))
-            .and(contains("Valid BibTeX? no")),
-    );
+    cmd.args(["get", "--ignore-null", "zbl:9999.28015"]);
+    cmd.assert().success().stderr(predicate::str::is_empty());
 
     s.close()
 }
 
+/// Check that `autobib get` with records which are looked up at once and records which are
+/// looked up separately outputs every record.
 #[test]
-fn test_attach() -> Result<()> {
+fn get_many() -> Result<()> {
     let s = TestState::init()?;
 
-    let temp = assert_fs::NamedTempFile::new("attachment.txt")?;
-    let temp_contents = "test\ncontents";
-    temp.write_str(temp_contents)?;
-
-    let attachment_file = s.attachment("zbmath/JX/TT/CT/GA3DGNBWGQ3DC===/attachment.txt");
+    for key in ["a", "b", "c"] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["local", key, "--with-field", &format!("title = {{{key}}}")]);
+        cmd.assert().success();
+    }
 
     let mut cmd = s.cmd()?;
-    cmd.args(["attach", "zbl:1337.28015"]);
-    cmd.arg(temp.as_ref());
+    cmd.args(["alias", "add", "alias-b", "local:b"]);
     cmd.assert().success();
 
-    attachment_file.assert(predicate::eq(temp_contents));
-
     let mut cmd = s.cmd()?;
-    cmd.args(["attach", "zbl:1337.28015"]);
-    cmd.arg(temp.as_ref());
-    cmd.args(["--rename", "attach2.txt"]);
+    cmd.args(["delete", "local:c"]);
     cmd.assert().success();
 
-    s.attachment("zbmath/JX/TT/CT/GA3DGNBWGQ3DC===/attach2.txt")
-        .assert(predicate::eq(temp_contents));
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:a", "alias-b", "local:c"]);
+    cmd.assert()
+        .failure()
+        .stdout(
+            contains("@misc{local:a,")
+                .and(contains("@misc{alias-b,"))
+                .and(contains("local:c").not()),
+        )
+        .stderr(contains("Deleted record: 'local:c'"));
+
+    s.close()
+}
+
+/// Check the exit codes for the different kinds of failure.
+#[test]
+fn exit_codes() -> Result<()> {
+    let s = TestState::init()?;
 
     let mut cmd = s.cmd()?;
-    cmd.args(["attach", "zbl:1337.28015"]);
-    cmd.arg(temp.as_ref());
-    cmd.args(["--rename", ".."]);
-    cmd.assert().failure();
+    cmd.args(["get", "zbl:9999.28015"]);
+    cmd.assert().code(4);
 
     let mut cmd = s.cmd()?;
-    cmd.args(["attach", "zbl:1337.28015"]);
-    cmd.arg(temp.as_ref());
-    cmd.args(["--rename", "/invalid"]);
-    cmd.assert().failure();
+    cmd.args(["get", "invalid:0000"]);
+    cmd.assert().code(5).stderr(contains("Invalid key"));
 
     let mut cmd = s.cmd()?;
-    cmd.args(["attach", "zbl:1337.28015"]);
-    cmd.arg(temp.as_ref());
-    cmd.args(["--rename", ""]);
-    cmd.assert().failure();
+    cmd.args(["get", "undefined-alias"]);
+    cmd.assert().code(1);
 
+    // the first specific kind of failure determines the exit code
     let mut cmd = s.cmd()?;
-    cmd.args(["attach", "zbl:1337.28015"]);
-    cmd.arg(temp.as_ref());
-    cmd.args(["--rename", "."]);
-    cmd.assert().failure();
+    cmd.args(["get", "undefined-alias", "invalid:0000", "zbl:9999.28015"]);
+    cmd.assert().code(5);
 
-    temp.close()?;
     s.close()
 }
 
-/// Check that `autobib path` always returns the same values.
+/// Check that `autobib local` works as expected.
 #[test]
-fn test_path_platform_consistency() -> Result<()> {
+fn local() -> Result<()> {
     let s = TestState::init()?;
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbl:1337.28015"]);
+    cmd.args([
+        "local",
+        "first",
+        "--from-bibtex",
+        "tests/resources/local/first.bib",
+    ]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["path", "zbl:1337.28015"]);
-
-    #[cfg(windows)]
-    let value = "\\zbmath\\JX\\TT\\CT\\GA3DGNBWGQ3DC===\\\n";
-
-    #[cfg(not(windows))]
-    let value = "/zbmath/JX/TT/CT/GA3DGNBWGQ3DC===/\n";
-
+    cmd.args(["--read-only", "local", "second"]);
     cmd.assert()
-        .success()
-        .stdout(predicate::str::ends_with(value));
+        .failure()
+        .stderr(contains("cannot be used in read-only mode"));
 
     let mut cmd = s.cmd()?;
-    cmd.args([
-        "alias",
-        "add",
-        "my-alias",
-        "doi:10.1016/0021-8693(89)90256-1",
-    ]);
-    cmd.assert().success();
-
-    #[cfg(windows)]
-    let value = "\\doi\\XN\\UL\\PE\\GEYC4MJQGE3C6MBQGIYS2OBWHEZSQOBZFE4TAMRVGYWTC===\\\n";
-
-    #[cfg(not(windows))]
-    let value = "/doi/XN/UL/PE/GEYC4MJQGE3C6MBQGIYS2OBWHEZSQOBZFE4TAMRVGYWTC===/\n";
+    cmd.args(["get", "local:first"]);
+    let predicate_file = predicate::path::eq_file(Path::new("tests/resources/local/stdout.txt"))
+        .utf8()
+        .unwrap();
+    cmd.assert().success().stdout(predicate_file);
 
     let mut cmd = s.cmd()?;
-    cmd.args(["path", "my-alias"]);
-    cmd.assert()
-        .success()
-        .stdout(predicate::str::ends_with(value));
-
-    s.close()
-}
-
-#[test]
-fn edit() -> Result<()> {
-    let s = TestState::init()?;
+    cmd.args(["local", "first"]);
+    cmd.assert().failure();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["edit", "zbl:9999.28015"]);
+    cmd.args([
+        "local",
+        "first",
+        "--from-bibtex",
+        "tests/resources/local/first.bib",
+    ]);
     cmd.assert()
         .failure()
-        .stderr(contains("Cannot edit null record"));
+        .stderr(contains("Local record 'local:first' already exists"));
 
     let mut cmd = s.cmd()?;
-    cmd.args(["edit", "my_alias"]);
-    cmd.assert()
-        .failure()
-        .stderr(contains("Cannot edit undefined alias"));
+    cmd.args(["local", "second"]);
+    cmd.assert().success();
 
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:second"]);
     let predicate_file =
-        predicate::path::eq_file(Path::new("tests/resources/edit/stdout_unedited.txt"))
+        predicate::path::eq_file(Path::new("tests/resources/local/stdout_short.txt"))
             .utf8()
             .unwrap();
-    let mut cmd = s.cmd()?;
-    cmd.args(["get", "mr:3224722"]);
     cmd.assert().success().stdout(predicate_file);
 
     let mut cmd = s.cmd()?;
-    cmd.args(["edit", "--set-eprint=zbl,doi", "mr:3224722"]);
-    cmd.assert().success();
-
-    let mut cmd = s.cmd()?;
-    cmd.args(["edit", "mr:3224722", "--normalize-whitespace"]);
-    cmd.assert().success();
-
-    let predicate_file = predicate::path::eq_file(Path::new("tests/resources/edit/stdout.txt"))
-        .utf8()
-        .unwrap();
-    let mut cmd = s.cmd()?;
-    cmd.args(["get", "mr:3224722"]);
-    cmd.assert().success().stdout(predicate_file);
-
-    s.close()
-}
-
-#[test]
-fn update() -> Result<()> {
-    let s = TestState::init()?;
+    cmd.args(["local", " \n"]);
+    cmd.assert().failure().stderr(contains(
+        "local sub-id must contain non-whitespace characters",
+    ));
 
     let mut cmd = s.cmd()?;
-    cmd.args(["update", "zbmath:06346461"]);
+    cmd.args(["local", ":"]);
     cmd.assert()
         .failure()
-        .stderr(contains("does not exist in database").and(contains("Use `autobib get`")));
+        .stderr(contains("local sub-id must not contain a colon"));
 
     s.close()
 }
 
+/// Check that aliases which refer to other aliases are resolved.
 #[test]
-fn update_local() -> Result<()> {
+fn alias_link() -> Result<()> {
     let s = TestState::init()?;
 
+    for name in ["first", "second"] {
+        let mut cmd = s.cmd()?;
+        cmd.args([
+            "local",
+            name,
+            "--with-entry-type",
+            "article",
+            "--with-field",
+            &format!("title = {{{name}}}"),
+        ]);
+        cmd.assert().success();
+    }
+
     let mut cmd = s.cmd()?;
-    cmd.args(["local", "one"]);
+    cmd.args(["alias", "add", "base", "local:first"]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "local:one"]);
+    cmd.args(["alias", "link", "short", "base"]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["update", "local:one"]);
+    cmd.args(["alias", "link", "shorter", "short"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "link", "short", "base"]);
     cmd.assert()
         .failure()
-        .stderr(contains("Cannot update local record using remote data"));
+        .stderr(contains("Alias already exists"));
 
+    // a link cannot be replaced by an alias of a record
     let mut cmd = s.cmd()?;
-    cmd.args(["update", "local:two"]);
+    cmd.args(["alias", "add", "short", "local:second"]);
     cmd.assert()
         .failure()
-        .stderr(contains("does not exist in database").and(contains("Use `autobib get`").not()));
+        .stderr(contains("Alias already exists: 'short'"));
 
-    s.close()
-}
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "link", "other", "undefined"]);
+    cmd.assert().failure().stderr(contains("Undefined alias"));
 
-#[test]
-fn consistency() -> Result<()> {
-    use rusqlite::Connection;
+    // an alias cannot be deleted while another alias refers to it
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "delete", "short"]);
+    cmd.assert().failure().stderr(contains(
+        "Could not delete alias 'short' which is the target of the aliases: shorter",
+    ));
 
-    let s = TestState::init()?;
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "shorter"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("@article{shorter"))
+        .stdout(contains("title = {first}"));
 
     let mut cmd = s.cmd()?;
-    cmd.args([
-        "get",
-        "--retrieve-only",
-        "zbmath:06346461",
-        "zbl:1337.28015",
-        "mr:3224722",
-    ]);
+    cmd.args(["info", "shorter"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("Alias chain: shorter -> short -> base"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "reassign", "base", "local:second"]);
     cmd.assert().success();
 
-    // perform some destructive changes to the database
-    let conn = Connection::open(s.database.path())?;
-    conn.pragma_update(None, "foreign_keys", 0)?;
-    conn.prepare("DELETE FROM Records WHERE record_id = 'zbmath:06346461'")?
-        .execute(())?;
-    conn.prepare("DELETE FROM Identifiers WHERE name = 'mr:3224722'")?
-        .execute(())?;
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "shorter"]);
+    cmd.assert().success().stdout(contains("title = {second}"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "flatten", "shorter"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "reassign", "base", "local:first"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "shorter"]);
+    cmd.assert().success().stdout(contains("title = {second}"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "short"]);
+    cmd.assert().success().stdout(contains("title = {first}"));
+
+    let conn = rusqlite::Connection::open(s.database.path())?;
+    conn.execute(
+        "INSERT INTO Identifiers (name, record_key) SELECT 'short', record_key FROM Identifiers WHERE name = 'local:second'",
+        (),
+    )?;
     drop(conn);
 
-    // check that things are broken
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbmath:06346461"]);
-    cmd.assert().failure().stderr(predicate::str::contains(
-        "Database error: SQLite error: Query returned no rows",
+    cmd.args(["util", "check"]);
+    cmd.assert().failure().stderr(contains(
+        "Alias 'short' is both an identifier of a record and a link to another alias",
     ));
 
-    // check that the error report is correct
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check", "--fix"]);
+    cmd.assert().success();
+
     let mut cmd = s.cmd()?;
     cmd.args(["util", "check"]);
-    cmd.assert().failure().stderr(
-        predicate::str::contains("Record id 'mr:3224722' contains 0 active rows").and(
-            predicate::str::contains("2 identifiers which reference records which do not exist"),
-        ),
-    );
+    cmd.assert().success();
 
     s.close()
 }
 
-/// Check that `autobib get` warns if there are multiple references to the same key
 #[test]
-fn repeat() -> Result<()> {
+fn alias_list() -> Result<()> {
     let s = TestState::init()?;
 
+    for name in ["first", "second"] {
+        let mut cmd = s.cmd()?;
+        cmd.args([
+            "local",
+            name,
+            "--with-entry-type",
+            "article",
+            "--with-field",
+            &format!("title = {{{name}}}"),
+        ]);
+        cmd.assert().success();
+    }
+
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbmath:06346461", "zbl:1337.28015"]);
-    cmd.assert()
-        .success()
-        .stderr(contains("Multiple keys for "));
+    cmd.args(["alias", "add", "base", "local:first"]);
+    cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["alias", "add", "a", "zbl:1337.28015"]);
+    cmd.args(["alias", "add", "other", "local:second"]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbmath:06346461", "a"]);
+    cmd.args(["alias", "link", "short", "base"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "list"]);
     cmd.assert()
         .success()
-        .stderr(contains("Multiple keys for "));
+        .stdout(contains("base\tlocal:first\n"))
+        .stdout(contains("other\tlocal:second\n"))
+        .stdout(contains("short\tlocal:first\n"));
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "a", "a"]);
+    cmd.args(["alias", "list", "local:first"]);
     cmd.assert()
         .success()
-        .stderr(contains("Multiple keys for "));
+        .stdout(contains("base\n"))
+        .stdout(contains("short\n"))
+        .stdout(contains("other").not());
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "of", "short"]);
+    cmd.assert()
+        .success()
+        .stdout("short -> base -> local:first\n");
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "of", "undefined"]);
+    cmd.assert().failure().stderr(contains("Undefined alias"));
 
     s.close()
 }
 
 #[test]
-fn config() -> Result<()> {
+fn alias_import_export() -> Result<()> {
     let s = TestState::init()?;
+    let other = TestState::init()?;
+    let aliases = NamedTempFile::new("aliases.tsv")?;
+
+    for state in [&s, &other] {
+        for name in ["first", "second"] {
+            let mut cmd = state.cmd()?;
+            cmd.args([
+                "local",
+                name,
+                "--with-entry-type",
+                "article",
+                "--with-field",
+                &format!("title = {{{name}}}"),
+            ]);
+            cmd.assert().success();
+        }
+    }
 
-    s.set_config(Path::new("tests/resources/config/malformed.toml"))?;
     let mut cmd = s.cmd()?;
-    cmd.arg("get");
-    cmd.assert().failure();
+    cmd.args(["alias", "add", "base", "local:first"]);
+    cmd.assert().success();
 
-    s.set_config(Path::new("tests/resources/config/extra.toml"))?;
     let mut cmd = s.cmd()?;
-    cmd.arg("get");
-    cmd.assert().failure();
+    cmd.args(["alias", "link", "short", "base"]);
+    cmd.assert().success();
 
-    s.close()
-}
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "export"]).arg(aliases.as_ref());
+    cmd.assert().success();
+    assert_eq!(
+        fs::read_to_string(aliases.as_ref())?,
+        "base\tlocal:first\nshort\tlocal:first\n"
+    );
 
-/// Check that the `on_insert` methods work as expected.
-#[test]
-fn on_insert() -> Result<()> {
-    let s = TestState::init()?;
+    // an existing alias which refers to a different record is a conflict
+    let mut cmd = other.cmd()?;
+    cmd.args(["alias", "add", "short", "local:second"]);
+    cmd.assert().success();
 
-    s.set_config(Path::new("tests/resources/on_insert/config.toml"))?;
+    let mut cmd = other.cmd()?;
+    cmd.args(["alias", "import"]).arg(aliases.as_ref());
+    cmd.assert()
+        .failure()
+        .stderr(contains("Alias already exists: 'short'"));
 
-    let predicate_file =
-        predicate::path::eq_file(Path::new("tests/resources/on_insert/stdout.txt"))
-            .utf8()
-            .unwrap();
-    let mut cmd = s.cmd()?;
-    cmd.args(["get", "mr:3224722"]);
-    cmd.assert().success().stdout(predicate_file);
+    let mut cmd = other.cmd()?;
+    cmd.args(["get", "base"]);
+    cmd.assert().success().stdout(contains("title = {first}"));
+
+    let mut cmd = other.cmd()?;
+    cmd.args(["get", "short"]);
+    cmd.assert().success().stdout(contains("title = {second}"));
 
+    // importing again only reports the conflict
+    let mut cmd = other.cmd()?;
+    cmd.args(["alias", "import"]).arg(aliases.as_ref());
+    cmd.assert()
+        .failure()
+        .stderr(contains("Alias already exists: 'base'").not());
+
+    other.close()?;
     s.close()
 }
 
-/// Test identifiers which have previously caused errors
+/// Check that aliases are created automatically for new records, with a suffix when the alias
+/// already refers to a different record.
 #[test]
-fn test_identifier_exceptions() -> Result<()> {
+fn alias_template() -> Result<()> {
     let s = TestState::init()?;
+    s.set_config("tests/resources/alias_template/config.toml")?;
+
+    for (name, title) in [("first", "A"), ("second", "B")] {
+        let mut cmd = s.cmd()?;
+        cmd.args([
+            "local",
+            name,
+            "--with-entry-type",
+            "article",
+            "--with-field",
+            &format!("title = {{{title}}}"),
+            "--with-field",
+            "author = {Doe, Jane}",
+            "--with-field",
+            "year = {2020}",
+        ]);
+        cmd.assert().success();
+    }
 
+    // the template cannot be rendered without the `year` field
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "arxiv:2112.04570"]);
+    cmd.args([
+        "local",
+        "third",
+        "--with-entry-type",
+        "article",
+        "--with-field",
+        "author = {Doe, Jane}",
+    ]);
     cmd.assert().success();
 
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "doe2020"]);
+    cmd.assert().success().stdout(contains("title = {A}"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "doe2020a"]);
+    cmd.assert().success().stdout(contains("title = {B}"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "list", "local:third"]);
+    cmd.assert().success().stdout(contains("doe").not());
+
     s.close()
 }
 
+/// Check that `autobib alias` works as expected.
 #[test]
-fn test_quiet_returns_error() -> Result<()> {
+fn alias() -> Result<()> {
     let s = TestState::init()?;
 
     let mut cmd = s.cmd()?;
-    cmd.args(["-q", "-q", "get", "::invalid"]);
-    cmd.assert().failure();
+    cmd.args([
+        "local",
+        "first",
+        "--from-bibtex",
+        "tests/resources/local/first.bib",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:first"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "add", "my_alias", "local:first"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["local", "second"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "add", "my_alias", "local:second"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Alias already exists"));
+
+    let mut cmd = s.cmd()?;
+    cmd.arg("get").arg("my_alias");
+    let predicate_file = predicate::path::eq_file(Path::new("tests/resources/alias/stdout.txt"))
+        .utf8()
+        .unwrap();
+    cmd.assert().success().stdout(predicate_file);
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "rename", "my_alias", "new_alias"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "new_alias"]);
+    cmd.assert().success().stdout(contains("@book{new_alias"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "delete", "new_alias"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "my_alias"]);
+    cmd.assert().failure().stderr(contains("Undefined alias"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "new_alias"]);
+    cmd.assert().failure().stderr(contains("Undefined alias"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "--ignore-null", "new_alias"]);
+    cmd.assert().success().stderr(predicate::str::is_empty());
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "delete", "my_alias"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Could not delete alias which does not exist"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "add", "  ", "not_an_alias"]);
+    cmd.assert().failure().stderr(
+        contains("invalid value '  ' for '<ALIAS>'")
+            .and(contains("alias must contain non-whitespace characters")),
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "add", "\n\t", "not_an_alias"]);
+    cmd.assert().failure().stderr(
+        contains("invalid value '\n\t' for '<ALIAS>'")
+            .and(contains("alias must contain non-whitespace characters")),
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "add", "has ws", "not_an_alias"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Cannot create alias for undefined alias"));
+
+    s.close()
+}
+
+/// Check that `autobib alias` works as expected with null and existing remote records.
+#[test]
+fn alias_remote() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "add", "al", "zbmath:06346461"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "al"]);
+    let predicate_file =
+        predicate::path::eq_file(Path::new("tests/resources/alias_remote/stdout.txt"))
+            .utf8()
+            .unwrap();
+    cmd.assert().success().stdout(predicate_file);
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "add", "a2", "zbmath:96346461"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Cannot create alias for null record"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "add", "a2", "alias-does-not-exist"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Cannot create alias for undefined alias"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "a2"]);
+    cmd.assert().failure().stderr(contains("Undefined alias"));
+
+    s.close()
+}
+
+/// Check that `autobib get` validates BibTeX citation keys and suggests alternatives on failure.
+#[test]
+fn bibtex_key_validation() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "alias",
+        "add",
+        "cst1989",
+        "doi:10.1016/0021-8693(89)90256-1",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "doi:10.1016/0021-8693(89)90256-1"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Identifier contains invalid character").and(contains("cst1989")));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "cst1989"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "get",
+        "--retrieve-only",
+        "doi:10.1016/0021-8693(89)90256-1",
+        "cst1989",
+    ]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::is_empty());
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "add", "has ws", "cst1989"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "has ws"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Identifier contains invalid character"));
+
+    s.close()
+}
+
+/// Test deletion, including of aliases.
+#[test]
+fn delete() -> Result<()> {
+    let s = TestState::init()?;
+
+    // single deletion OK even without `--force`
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "mr:3224722"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["delete", "mr:3224722"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["delete", "mr:3224722"]);
+    cmd.assert().failure();
+
+    // multi deletion succeeds, and applies to all aliases
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "local",
+        "first",
+        "--from-bibtex",
+        "tests/resources/local/first.bib",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "add", "my_alias", "local:first"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:first"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["delete", "my_alias"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "my_alias"]);
+    cmd.assert().failure().stderr(contains("Deleted record"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["delete", "my_alias"]);
+    cmd.assert().failure().stderr(contains("already deleted"));
+
+    // deleting multiple
+    let mut cmd = s.cmd()?;
+    cmd.args(["delete", "--hard", "--yes", "local:first", "my_alias"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Cannot delete undefined alias"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:first"]);
+    cmd.assert().failure().stderr(contains(
+        "Cannot retrieve remote data for key with local provenance",
+    ));
+
+    s.close()
+}
+
+/// Test that hard deletion requires confirmation, and that the tombstone file can be used to
+/// restore the record.
+#[test]
+fn delete_hard_confirmation() -> Result<()> {
+    let s = TestState::init()?;
+    s.create_test_db()?;
+
+    let tombstone = NamedTempFile::new("tombstone.bib")?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "add", "my_alias", "local:second"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["delete", "--hard", "my_alias"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("local:second ("))
+        .stderr(contains("identifiers: "))
+        .stderr(contains("my_alias"))
+        .stderr(contains("use `--yes` to delete without confirmation"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "my_alias"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["delete", "--hard", "--yes", "my_alias", "--tombstone"]);
+    cmd.arg(tombstone.as_ref());
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:second"]);
+    cmd.assert().failure();
+
+    let contents = fs::read_to_string(tombstone.as_ref())?;
+    assert!(contents.contains("@manuscript{local:second,"));
+
+    let mut cmd = s.cmd()?;
+    cmd.arg("import");
+    cmd.arg(tombstone.as_ref());
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:second"]);
+    cmd.assert().success().stdout(contains("author = {A}"));
+
+    tombstone.close()?;
+    s.close()
+}
+
+/// Check that hard deletion removes the data which is keyed by the canonical identifier, so that
+/// it does not reappear when the record is added again, and that `util check` reports such data
+/// if it was left behind.
+#[test]
+fn delete_hard_cascades() -> Result<()> {
+    use rusqlite::Connection;
+
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["local", "a", "--with-field", "title = {A}"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "override",
+        "set",
+        "local:a",
+        "title",
+        "Overridden",
+        "--scope",
+        "project.bib",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["tag", "add", "local:a", "reading-list"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["collection", "create", "thesis"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["collection", "add", "thesis", "local:a"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["local", "b", "--with-field", "title = {B}"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["relate", "local:a", "local:b", "--kind", "translation-of"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["relate", "local:b", "local:a", "--kind", "erratum-of"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "local:a", "--report", "added"]);
+    let output = cmd.output()?;
+    let added = String::from_utf8(output.stdout)?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["delete", "--hard", "--yes", "local:a"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["local", "a", "--with-field", "title = {A}"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["override", "list"]);
+    cmd.assert().success().stdout("");
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["tag", "list"]);
+    cmd.assert().success().stdout("");
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["collection", "list", "thesis"]);
+    cmd.assert().success().stdout("");
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "local:b", "--report", "related"]);
+    cmd.assert().success().stdout("");
+
+    // the record is added again, rather than keeping the time at which it was first added
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "local:a", "--report", "added"]);
+    let output = cmd.output()?;
+    assert_ne!(String::from_utf8(output.stdout)?, added);
+
+    let conn = Connection::open(s.database.path())?;
+    conn.execute(
+        "INSERT INTO Overrides (record_id, scope, field, value) VALUES ('local:ghost', 'project.bib', 'title', 'Ghost')",
+        (),
+    )?;
+    conn.execute(
+        "INSERT INTO Tags (record_id, tag) VALUES ('local:ghost', 'reading-list')",
+        (),
+    )?;
+    conn.execute(
+        "INSERT INTO CollectionRecords (collection, record_id) VALUES ('thesis', 'local:ghost')",
+        (),
+    )?;
+    conn.execute("INSERT INTO Relations (record_id, target, kind) VALUES ('local:b', 'local:ghost', 'cites')", ())?;
+    conn.execute(
+        "INSERT INTO Added (record_id, added) VALUES ('local:ghost', '2024-01-01T00:00:00+00:00')",
+        (),
+    )?;
+    drop(conn);
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check"]);
+    let mut assert = cmd.assert().failure();
+    for table in [
+        "Overrides",
+        "Tags",
+        "CollectionRecords",
+        "Relations",
+        "Added",
+    ] {
+        assert = assert.stderr(contains(format!(
+            "Table '{table}' contains rows for record id 'local:ghost'"
+        )));
+    }
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check", "--fix"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check"]);
+    cmd.assert().success();
+
+    s.close()
+}
+
+/// Test citation key listing.
+#[test]
+fn list() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "local",
+        "first",
+        "--from-bibtex",
+        "tests/resources/local/first.bib",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "add", "my_alias", "local:first"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbl:1337.28015"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "list"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("zbmath:06346461").and(contains("my_alias")));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--read-only", "util", "list"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("zbmath:06346461").and(contains("my_alias")));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "list", "--canonical"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("my_alias").not().and(contains("local:first")));
+
+    s.close()
+}
+
+/// Test the identifiers printed for shell completions.
+#[test]
+fn complete_keys() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "local",
+        "first",
+        "--from-bibtex",
+        "tests/resources/local/first.bib",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "add", "my_alias", "local:first"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--read-only", "__complete-keys", "loc"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("local:first\n"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["__complete-keys"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("local:first").and(contains("my_alias")));
+
+    for shell in ["bash", "zsh", "fish"] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["completions", shell]);
+        cmd.assert().success().stdout(contains("__complete-keys"));
+    }
+
+    s.close()
+}
+
+/// Test that the interactive browser requires an interactive, writable session.
+#[test]
+fn tui_requires_interactive() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["tui"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("cannot run in non-interactive mode"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--read-only", "tui"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("cannot be used in read-only mode"));
+
+    s.close()
+}
+
+#[test]
+fn util_list_score_below() -> Result<()> {
+    let s = TestState::init()?;
+
+    for (sub_id, entry_type, fields) in [
+        (
+            "a",
+            "article",
+            &["author = {Knuth, Donald}", "title = {T}"][..],
+        ),
+        (
+            "b",
+            "book",
+            &["author = {Knuth, Donald}", "title = {T}", "year = {2000}"][..],
+        ),
+        ("c", "misc", &["title = {T}"][..]),
+    ] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["local", sub_id, "--with-entry-type", entry_type]);
+        for field in fields {
+            cmd.args(["--with-field", field]);
+        }
+        cmd.assert().success();
+    }
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "list", "--score-below", "0.5"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("local:c\nlocal:a\n"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "list", "--score-below", "0.8"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("local:c\nlocal:a\nlocal:b\n"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "edit",
+        "local:a",
+        "--set-field",
+        "journal = {J}",
+        "--set-field",
+        "year = {2024}",
+        "--set-field",
+        "doi = {10.1000/xyz}",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "list", "--score-below", "0.8", "--json"]);
+    cmd.assert()
+        .success()
+        .stdout(contains(r#""local:b""#).and(contains("local:a").not()));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "list", "--score-below", "0.8", "--deleted"]);
+    cmd.assert().failure();
+
+    s.close()
+}
+
+#[test]
+fn util_list_filters() -> Result<()> {
+    let s = TestState::init()?;
+
+    for (sub_id, entry_type, year) in [
+        ("a", "article", "2019"),
+        ("b", "article", "2021"),
+        ("c", "book", "2022"),
+    ] {
+        let mut cmd = s.cmd()?;
+        cmd.args([
+            "local",
+            sub_id,
+            "--with-entry-type",
+            entry_type,
+            "--with-field",
+            &format!("year = {{{year}}}"),
+        ]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "list", "--entry-type", "Article"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("local:a\nlocal:b\n"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "util",
+        "list",
+        "--field",
+        "year>=2020",
+        "--provider",
+        "local",
+    ]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("local:b\nlocal:c\n"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "util",
+        "list",
+        "--field",
+        "year>=2020",
+        "--entry-type",
+        "article",
+        "--template",
+        "{year}",
+    ]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("2021\n"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "list", "--provider", "zbmath"]);
+    cmd.assert().success().stdout(predicate::str::diff(""));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "list", "--modified-since", "2000-01-01", "--json"]);
+    cmd.assert()
+        .success()
+        .stdout(contains(r#""local:a""#).and(contains(r#""local:c""#)));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "list", "--modified-since", "3000-01-01"]);
+    cmd.assert().success().stdout(predicate::str::diff(""));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "list", "--field", "year>=2020", "--deleted"]);
+    cmd.assert().failure();
+
+    s.close()
+}
+
+#[test]
+fn util_list_sort_added() -> Result<()> {
+    let s = TestState::init()?;
+
+    for sub_id in ["b", "a", "c"] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["local", sub_id, "--with-field", "title = {T}"]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["edit", "local:b", "--set-field", "year = {2024}"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "list", "--sort", "added", "--limit", "2"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("local:c\nlocal:a\n"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "list", "--sort", "added"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("local:c\nlocal:a\nlocal:b\n"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "list", "--sort", "modified", "--limit", "1"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("local:b\n"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "local:b"]);
+    cmd.assert().success().stdout(contains("Added:"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "local:b", "--report", "added"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::is_empty().not());
+
+    s.close()
+}
+
+#[test]
+fn stats() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.arg("stats");
+    cmd.assert().success().stdout(predicate::str::diff(
+        "Records: 0\n  with attachments: 0\n  without attachments: 0\nNull records: 0\nRevisions: 0\nRuns: 0\n",
+    ));
+
+    for (sub_id, entry_type, field) in [
+        ("a", "article", "year = {2020}"),
+        ("b", "book", "date = {2021-03-01}"),
+        ("c", "article", "title = {T}"),
+    ] {
+        let mut cmd = s.cmd()?;
+        cmd.args([
+            "local",
+            sub_id,
+            "--with-entry-type",
+            entry_type,
+            "--with-field",
+            field,
+        ]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["edit", "local:a", "--set-field", "title = {New}"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.arg("stats");
+    cmd.assert().success().stdout(
+        contains("Records: 3\n  with attachments: 0\n  without attachments: 3\nNull records: 0\nRevisions: 4\n")
+            .and(contains("Providers:\n  local  3\nEntry types:\n  article  2\n  book     1\nYears:\n  2020     1\n  2021     1\n  unknown  1\n")),
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--read-only", "stats", "--json"]);
+    cmd.assert().success().stdout(
+        contains(r#""records": 3"#)
+            .and(contains(r#""revisions": 4"#))
+            .and(contains(r#""local": 3"#)),
+    );
+
+    s.close()
+}
+
+#[test]
+fn info() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "zbl:1337.28015", "-r", "canonical"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Cannot obtain report for record not in database"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbl:1337.28015"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "zbl:1337.28015", "--report", "canonical"]);
+    cmd.assert().success().stdout("zbmath:06346461\n");
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "--read-only",
+        "info",
+        "zbl:1337.28015",
+        "--report",
+        "canonical",
+    ]);
+    cmd.assert().success().stdout("zbmath:06346461\n");
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "add", "%", "zbmath:06346461"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "zbl:1337.28015", "-r", "equivalent"]);
+    cmd.assert().success().stdout(
+        contains("%")
+            .and(contains("zbmath:06346461"))
+            .and(contains("zbl:1337.28015")),
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "%", "-r", "valid"]);
+    cmd.assert().failure().stderr(contains("Invalid BibTeX"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "%"]);
+    cmd.assert().success().stdout(
+        contains("Data last modified:")
+            .and(contains("Equivalent references:"))
+            .and(contains("Canonical: zbmath:06346461\n"))
+            .and(contains("Valid BibTeX? no")),
+    );
+
+    s.close()
+}
+
+#[test]
+fn info_provenance() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbl:1337.28015"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "zbmath:06346461", "--report", "provenance"]);
+    cmd.assert().success().stdout(
+        contains("Provider: zbmath")
+            .and(contains("api.zbmath.org"))
+            .and(contains("SHA-256: ")),
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "zbmath:06346461"]);
+    cmd.assert().success().stdout(contains("Retrieved from: "));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["local", "first", "--with-field", "title = {A}"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["-v", "info", "local:first", "-r", "p"]);
+    cmd.assert()
+        .success()
+        .stdout("")
+        .stderr(contains("No provenance recorded"));
+
+    s.close()
+}
+
+#[test]
+fn relate() -> Result<()> {
+    let s = TestState::init()?;
+
+    for name in ["a", "b", "c"] {
+        let mut cmd = s.cmd()?;
+        cmd.args([
+            "local",
+            name,
+            "--with-field",
+            "title = {A}",
+            "--with-entry-type",
+            "article",
+        ]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["relate", "local:a", "local:b", "--kind", "translation-of"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["relate", "local:c", "local:a", "--kind", "erratum-of"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["relate", "local:a", "local:a", "--kind", "cites"]);
+    cmd.assert().failure();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "local:a", "--report", "related"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("local:a translation-of local:b"))
+        .stdout(contains("local:c erratum-of local:a"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:b"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("@article{local:a,").not());
+
+    // relations are followed in both directions
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:b", "--with-related"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("@article{local:a,"))
+        .stdout(contains("@article{local:c,"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "relate",
+        "local:a",
+        "local:b",
+        "--kind",
+        "translation-of",
+        "--delete",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["-v", "info", "local:b", "--report", "related"]);
+    cmd.assert()
+        .success()
+        .stderr(contains("No relations recorded for 'local:b'"));
+
+    s.close()
+}
+
+#[test]
+fn tag() -> Result<()> {
+    let s = TestState::init()?;
+
+    for name in ["a", "b"] {
+        let mut cmd = s.cmd()?;
+        cmd.args([
+            "local",
+            name,
+            "--with-field",
+            "title = {A}",
+            "--with-entry-type",
+            "article",
+        ]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["tag", "add", "local:a", "reading-list", "todo"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["tag", "add", "local:b", "reading-list"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["tag", "add", "local:b", "not a tag"]);
+    cmd.assert().failure();
+
+    // tags are preserved when the record is edited
+    let mut cmd = s.cmd()?;
+    cmd.args(["edit", "local:a", "--set-field", "title = {B}"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["tag", "list", "local:a"]);
+    cmd.assert().success().stdout("reading-list\ntodo\n");
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["tag", "list"]);
+    cmd.assert().success().stdout("reading-list\t2\ntodo\t1\n");
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "list", "--tag", "todo"]);
+    cmd.assert().success().stdout("local:a\n");
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "--tag", "reading-list"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("@article{local:a,"))
+        .stdout(contains("@article{local:b,"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["tag", "rm", "local:a", "reading-list"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["tag", "rm", "local:a", "reading-list"]);
+    cmd.assert().failure();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "--tag", "reading-list"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("@article{local:a,").not())
+        .stdout(contains("@article{local:b,"));
+
+    s.close()
+}
+
+#[test]
+fn collection() -> Result<()> {
+    let s = TestState::init()?;
+
+    for name in ["a", "b", "c"] {
+        let mut cmd = s.cmd()?;
+        cmd.args([
+            "local",
+            name,
+            "--with-field",
+            "title = {A}",
+            "--with-entry-type",
+            "article",
+        ]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["collection", "add", "thesis", "local:a"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Collection does not exist: 'thesis'"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["collection", "create", "thesis"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["collection", "add", "thesis", "local:a", "local:b"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "--collection", "thesis"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("@article{local:a,"))
+        .stdout(contains("@article{local:b,"))
+        .stdout(contains("@article{local:c,").not());
+
+    // collections follow the canonical identifier when a record is replaced
+    let mut cmd = s.cmd()?;
+    cmd.args(["replace", "local:a", "--with", "local:c"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["collection", "list", "thesis"]);
+    cmd.assert().success().stdout("local:b\nlocal:c\n");
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["collection", "rm", "thesis", "local:b"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["collection", "list"]);
+    cmd.assert().success().stdout("thesis\t1\n");
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["collection", "delete", "thesis"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "--collection", "thesis"]);
+    cmd.assert().failure();
+
+    s.close()
+}
+
+#[test]
+fn override_fields() -> Result<()> {
+    let s = TestState::init()?;
+    let project = NamedTempFile::new("project.bib")?;
+    let other = NamedTempFile::new("other.bib")?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "local",
+        "a",
+        "--with-field",
+        "title = {Original}",
+        "--with-entry-type",
+        "article",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "override",
+        "set",
+        "local:a",
+        "title",
+        "Overridden",
+        "--scope",
+        "project.bib",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["override", "list"]);
+    cmd.assert()
+        .success()
+        .stdout("local:a project.bib title = {Overridden}\n");
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:a", "--out"]).arg(project.as_ref());
+    cmd.assert().success();
+    let contents = fs::read_to_string(project.as_ref())?;
+    assert!(contents.contains("title = {Overridden}"));
+    assert!(!contents.contains("Original"));
+
+    // the override only applies to the output file in the scope
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:a", "--out"]).arg(other.as_ref());
+    cmd.assert().success();
+    assert!(fs::read_to_string(other.as_ref())?.contains("title = {Original}"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:a"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("title = {Original}"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "override",
+        "rm",
+        "local:a",
+        "title",
+        "--scope",
+        "project.bib",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:a", "--out"]).arg(project.as_ref());
+    cmd.assert().success();
+    assert!(fs::read_to_string(project.as_ref())?.contains("title = {Original}"));
+
+    s.close()
+}
+
+#[test]
+fn test_attach() -> Result<()> {
+    let s = TestState::init()?;
+
+    let temp = assert_fs::NamedTempFile::new("attachment.txt")?;
+    let temp_contents = "test\ncontents";
+    temp.write_str(temp_contents)?;
+
+    let attachment_file = s.attachment("zbmath/JX/TT/CT/GA3DGNBWGQ3DC===/attachment.txt");
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "zbl:1337.28015"]);
+    cmd.arg(temp.as_ref());
+    cmd.assert().success();
+
+    attachment_file.assert(predicate::eq(temp_contents));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "zbl:1337.28015"]);
+    cmd.arg(temp.as_ref());
+    cmd.args(["--rename", "attach2.txt"]);
+    cmd.assert().success();
+
+    s.attachment("zbmath/JX/TT/CT/GA3DGNBWGQ3DC===/attach2.txt")
+        .assert(predicate::eq(temp_contents));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "zbl:1337.28015"]);
+    cmd.arg(temp.as_ref());
+    cmd.args(["--rename", ".."]);
+    cmd.assert().failure();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "zbl:1337.28015"]);
+    cmd.arg(temp.as_ref());
+    cmd.args(["--rename", "/invalid"]);
+    cmd.assert().failure();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "zbl:1337.28015"]);
+    cmd.arg(temp.as_ref());
+    cmd.args(["--rename", ""]);
+    cmd.assert().failure();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "zbl:1337.28015"]);
+    cmd.arg(temp.as_ref());
+    cmd.args(["--rename", "."]);
+    cmd.assert().failure();
+
+    temp.close()?;
+    s.close()
+}
+
+#[test]
+fn test_attach_list_remove() -> Result<()> {
+    let s = TestState::init()?;
+
+    let temp = assert_fs::NamedTempFile::new("attachment.txt")?;
+    temp.write_str("contents")?;
+
+    s.create_test_db()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "local:first", "--list"]);
+    cmd.assert().success().stdout(predicate::str::is_empty());
+
+    for name in ["b.txt", "a.txt"] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["attach", "local:first"]);
+        cmd.arg(temp.as_ref());
+        cmd.args(["--rename", name]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "local:first", "--list"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("a.txt\nb.txt\n"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "local:first", "--remove", "c.txt"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Attachment does not exist: 'c.txt'"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "local:first", "--remove", "../a.txt"]);
+    cmd.assert().failure();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "local:first", "--remove", "a.txt"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--read-only", "attach", "local:first", "--list"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("b.txt\n"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "local:first", "--list", "b.txt"]);
+    cmd.assert().failure();
+
+    temp.close()?;
+    s.close()
+}
+
+#[test]
+fn test_attach_scan() -> Result<()> {
+    let s = TestState::init()?;
+    s.create_test_db()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "add", "sec", "local:second"]);
+    cmd.assert().success();
+
+    let dir = TempDir::new()?;
+    dir.child("local_first.pdf").write_str("%PDF-1.4\n%%EOF")?;
+    dir.child("nested/sec.pdf").write_str("%PDF-1.4\n%%EOF")?;
+    dir.child("unknown.pdf")
+        .write_str("%PDF-1.4\n(arXiv:1212.1873v1 [math.CA] 7 Dec 2012) Tj\n%%EOF")?;
+    dir.child("notes.txt").write_str("local:first")?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "--scan"]);
+    cmd.arg(dir.path());
+    cmd.arg("--move");
+    cmd.assert().failure().stdout(
+        contains("unknown.pdf")
+            .and(contains("local_first.pdf").not())
+            .and(contains("sec.pdf").not()),
+    );
+
+    dir.child("local_first.pdf")
+        .assert(predicate::path::missing());
+    dir.child("unknown.pdf").assert(predicate::path::exists());
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "local:first", "--list"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("local_first.pdf\n"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "local:second", "--list"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("sec.pdf\n"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "local:first", "--scan"]);
+    cmd.arg(dir.path());
+    cmd.assert().failure();
+
+    dir.close()?;
+    s.close()
+}
+
+#[test]
+fn test_util_check_attachments() -> Result<()> {
+    let s = TestState::init()?;
+    s.create_test_db()?;
+
+    let temp = assert_fs::NamedTempFile::new("attachment.txt")?;
+    temp.write_str("contents")?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "local:first"]);
+    cmd.arg(temp.as_ref());
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check", "--attachments"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["path", "local:first"]);
+    let output = cmd.output()?;
+    let target = String::from_utf8(output.stdout)?;
+    let attachment_dir = Path::new(target.trim_end());
+
+    // modified attachment
+    fs::write(attachment_dir.join("attachment.txt"), "modified")?;
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check", "--attachments"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("'attachment.txt' of 'local:first' was modified"));
+
+    // untracked attachment
+    fs::write(attachment_dir.join("untracked.txt"), "untracked")?;
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check", "--attachments"]);
+    cmd.assert()
+        .stderr(contains("'untracked.txt' of 'local:first' is not tracked"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check", "--attachments", "--fix"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check", "--attachments"]);
+    cmd.assert().success().stderr(predicate::str::is_empty());
+
+    // missing attachment
+    fs::remove_file(attachment_dir.join("attachment.txt"))?;
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check", "--attachments"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("'attachment.txt' of 'local:first' is missing"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "local:first", "--remove", "untracked.txt"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check", "--attachments", "--fix"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check", "--attachments"]);
+    cmd.assert().success().stderr(predicate::str::is_empty());
+
+    temp.close()?;
+    s.close()
+}
+
+#[test]
+fn test_attach_auto() -> Result<()> {
+    let s = TestState::init()?;
+    s.create_test_db()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "local:first", "--auto"]);
+    cmd.assert().failure().stderr(contains(
+        "Could not find an open-access PDF for 'local:first'",
+    ));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "local:first", "--auto", "file.pdf"]);
+    cmd.assert().failure();
+
+    s.close()
+}
+
+#[test]
+fn test_import_from_pdf_dir() -> Result<()> {
+    let s = TestState::init()?;
+
+    let dir = TempDir::new()?;
+    dir.child("papers/paper.pdf")
+        .write_str("%PDF-1.4\n(arXiv:1212.1873v1 [math.CA] 7 Dec 2012) Tj\n%%EOF")?;
+    dir.child("unknown.pdf").write_str("%PDF-1.4\n%%EOF")?;
+    dir.child("notes.txt")
+        .write_str("doi:10.1016/0021-8693(89)90256-1")?;
+
+    for _ in 0..2 {
+        let mut cmd = s.cmd()?;
+        cmd.args(["import", "--from-pdf-dir"]);
+        cmd.arg(dir.path());
+        cmd.assert()
+            .failure()
+            .stdout(contains("unknown.pdf").and(contains("paper.pdf").not()));
+    }
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "arxiv:1212.1873", "--list"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("paper.pdf\n"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["import", "--from-pdf-dir"]);
+    cmd.arg(dir.path());
+    cmd.arg("file.bib");
+    cmd.assert().failure();
+
+    dir.close()?;
+    s.close()
+}
+
+#[test]
+fn test_import_pdf() -> Result<()> {
+    let s = TestState::init()?;
+
+    let dir = TempDir::new()?;
+    dir.child("paper.pdf")
+        .write_str("%PDF-1.4\n(arXiv:1212.1873v1 [math.CA] 7 Dec 2012) Tj\n%%EOF")?;
+    dir.child("unknown.pdf").write_str("%PDF-1.4\n%%EOF")?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["import-pdf"]);
+    cmd.arg(dir.child("paper.pdf").path());
+    cmd.arg(dir.child("unknown.pdf").path());
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::diff("arxiv:1212.1873\n"))
+        .stderr(contains("unknown.pdf"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "arxiv:1212.1873", "--list"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("paper.pdf\n"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["import-pdf"]);
+    cmd.assert().failure();
+
+    dir.close()?;
+    s.close()
+}
+
+#[test]
+fn test_custom_provider() -> Result<()> {
+    let s = TestState::init()?;
+    fs::write(
+        s.config.as_ref(),
+        r#"[providers.mylab]
+id = 'TR-[0-9]{4}-[0-9]{2}'
+url = "https://mylab.example.org/reports/{sub_id}"
+"#,
+    )?;
+
+    let bibfile = NamedTempFile::new("file.bib")?;
+    bibfile.write_str("@techreport{mylab:TR-2024-07, title = {Report}}")?;
+
+    let mut cmd = s.cmd()?;
+    cmd.arg("import");
+    cmd.arg(bibfile.path());
+    cmd.assert().success().stdout(predicate::str::is_empty());
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "mylab:TR-2024-07"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("@techreport{mylab:TR-2024-07,"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "mylab:TR-2024-07"]);
+    cmd.assert().success().stdout(contains(
+        "URL: https://mylab.example.org/reports/TR-2024-07",
+    ));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "mylab:TR-2024-7"]);
+    cmd.assert().failure();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "mylab:TR-2024-08"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("user-defined provider"));
+
+    // identifiers of custom providers can be the target of a remap
+    let mut cmd = s.cmd()?;
+    cmd.args(["local", "report", "--with-field", "title = {Local report}"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "remap", "local:report", "mylab:TR-2024-09"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:report"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("title = {Local report}"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "list", "--canonical"]);
+    cmd.assert().success().stdout(contains("mylab:TR-2024-09"));
+
+    bibfile.close()?;
+    s.close()
+}
+
+/// Check that `autobib path` always returns the same values.
+#[test]
+fn test_path_platform_consistency() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbl:1337.28015"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["path", "zbl:1337.28015"]);
+
+    #[cfg(windows)]
+    let value = "\\zbmath\\JX\\TT\\CT\\GA3DGNBWGQ3DC===\\\n";
+
+    #[cfg(not(windows))]
+    let value = "/zbmath/JX/TT/CT/GA3DGNBWGQ3DC===/\n";
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::ends_with(value));
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "alias",
+        "add",
+        "my-alias",
+        "doi:10.1016/0021-8693(89)90256-1",
+    ]);
+    cmd.assert().success();
+
+    #[cfg(windows)]
+    let value = "\\doi\\XN\\UL\\PE\\GEYC4MJQGE3C6MBQGIYS2OBWHEZSQOBZFE4TAMRVGYWTC===\\\n";
+
+    #[cfg(not(windows))]
+    let value = "/doi/XN/UL/PE/GEYC4MJQGE3C6MBQGIYS2OBWHEZSQOBZFE4TAMRVGYWTC===/\n";
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["path", "my-alias"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::ends_with(value));
+
+    s.close()
+}
+
+#[test]
+fn edit() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["edit", "zbl:9999.28015"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Cannot edit null record"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["edit", "my_alias"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Cannot edit undefined alias"));
+
+    let predicate_file =
+        predicate::path::eq_file(Path::new("tests/resources/edit/stdout_unedited.txt"))
+            .utf8()
+            .unwrap();
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "mr:3224722"]);
+    cmd.assert().success().stdout(predicate_file);
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["edit", "--set-eprint=zbl,doi", "mr:3224722"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["edit", "mr:3224722", "--normalize-whitespace"]);
+    cmd.assert().success();
+
+    let predicate_file = predicate::path::eq_file(Path::new("tests/resources/edit/stdout.txt"))
+        .utf8()
+        .unwrap();
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "mr:3224722"]);
+    cmd.assert().success().stdout(predicate_file);
+
+    s.close()
+}
+
+#[test]
+fn edit_dry_run() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "local",
+        "dry",
+        "--with-field",
+        "title = {A  spaced    title}",
+        "--with-field",
+        "note = {unchanged}",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["edit", "local:dry", "--dry-run"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("No edit action specified"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "edit",
+        "local:dry",
+        "--normalize-whitespace",
+        "--set-field",
+        "year = {2024}",
+        "--dry-run",
+    ]);
+    cmd.assert().success().stdout(
+        "local:dry:\n  title\n    - A  spaced    title\n    + A spaced title\n  year (added)\n    + 2024\n",
+    );
+
+    // the record is not modified
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:dry"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("A  spaced    title").and(contains("2024").not()));
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "edit",
+        "local:missing",
+        "--normalize-whitespace",
+        "--dry-run",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Database does not contain key"));
+
+    s.close()
+}
+
+#[test]
+fn global_dry_run() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["local", "dry", "--with-field", "title = {Title}"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--dry-run", "--verbose", "delete", "local:dry"]);
+    cmd.assert()
+        .success()
+        .stderr(contains("Would delete 'local:dry'"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["delete", "local:dry", "--hard", "--dry-run"]);
+    cmd.assert()
+        .success()
+        .stderr(contains("would be permanently deleted"));
+
+    // the record is not modified
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:dry"]);
+    cmd.assert().success().stdout(contains("Title"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--dry-run", "get", "local:dry"]);
+    cmd.assert().failure().stderr(contains("only supported by"));
+
+    s.close()
+}
+
+#[test]
+fn database_lock() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["local", "first"]);
+    cmd.assert().success();
+
+    // simulate another process which holds the lock
+    let lock = fs::File::create(s.database.path().with_extension("db.lock"))?;
+    lock.lock()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--lock-timeout", "0", "local", "second"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("is locked by another autobib process"));
+
+    // read-only commands do not take the lock
+    let mut cmd = s.cmd()?;
+    cmd.args(["--read-only", "get", "local:first"]);
+    cmd.assert().success();
+
+    drop(lock);
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--lock-timeout", "0", "local", "second"]);
+    cmd.assert().success();
+
+    s.close()
+}
+
+/// Check that `autobib source --watch` only locks the database while it rebuilds the output.
+#[test]
+fn source_watch_lock() -> Result<()> {
+    use std::{
+        io::{BufRead, BufReader},
+        process::Stdio,
+    };
+
+    let s = TestState::init()?;
+    s.create_test_db()?;
+
+    let dir = TempDir::new()?;
+    let main = dir.child("main.tex");
+    main.write_str(r"\cite{local:first}")?;
+    let bibfile = dir.child("import.bib");
+    bibfile.write_str("@article{local:third,\n  title = {Third},\n}\n")?;
+
+    let mut cmd = s.cmd()?;
+    cmd.arg("source")
+        .arg(main.path())
+        .arg("--watch")
+        .arg("--out")
+        .arg(dir.child("out.bib").path())
+        .stderr(Stdio::piped());
+    let mut child = cmd.spawn()?;
+
+    let mut stderr = BufReader::new(child.stderr.take().ok_or("stderr is not piped")?);
+    let mut line = String::new();
+    while !line.contains("Watching") {
+        line.clear();
+        if stderr.read_line(&mut line)? == 0 {
+            break;
+        }
+    }
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--lock-timeout", "0", "import"])
+        .arg(bibfile.path());
+    let import = cmd.output()?;
+    child.kill()?;
+    child.wait()?;
+    import.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:third"]);
+    cmd.assert().success();
+
+    dir.close()?;
+    s.close()
+}
+
+/// Check that `autobib source --watch` appends the entries for new citations when a watched file
+/// is modified.
+#[test]
+fn source_watch_rebuild() -> Result<()> {
+    use std::{
+        io::{BufRead, BufReader},
+        process::Stdio,
+        thread::sleep,
+        time::{Duration, Instant},
+    };
+
+    let s = TestState::init()?;
+    s.create_test_db()?;
+
+    let dir = TempDir::new()?;
+    let main = dir.child("main.tex");
+    main.write_str(r"\cite{local:first}")?;
+    let out = dir.child("out.bib");
+
+    let mut cmd = s.cmd()?;
+    cmd.arg("source")
+        .arg(main.path())
+        .arg("--watch")
+        .arg("--out")
+        .arg(out.path())
+        .stderr(Stdio::piped());
+    let mut child = cmd.spawn()?;
+
+    let mut stderr = BufReader::new(child.stderr.take().ok_or("stderr is not piped")?);
+    let mut line = String::new();
+    while !line.contains("Watching") {
+        line.clear();
+        if stderr.read_line(&mut line)? == 0 {
+            break;
+        }
+    }
+    let initial = fs::read_to_string(out.path())?;
+
+    // make sure that the modification time changes, even with a coarse timestamp resolution
+    sleep(Duration::from_secs(1));
+    main.write_str(r"\cite{local:first} \cite{local:second}")?;
+    let start = Instant::now();
+    let mut rebuilt = String::new();
+    while start.elapsed() < Duration::from_secs(20) {
+        rebuilt = fs::read_to_string(out.path())?;
+        if rebuilt.contains("local:second") {
+            break;
+        }
+        sleep(Duration::from_millis(100));
+    }
+    child.kill()?;
+    child.wait()?;
+
+    assert!(initial.contains("@article{local:first,"));
+    assert!(!initial.contains("local:second"));
+    assert!(rebuilt.starts_with(&initial));
+    assert!(rebuilt.contains("@manuscript{local:second,"));
+    assert_eq!(rebuilt.matches("local:first").count(), 1);
+
+    dir.close()?;
+    s.close()
+}
+
+#[test]
+fn edit_where() -> Result<()> {
+    let s = TestState::init()?;
+
+    for (id, journal) in [("first", "arXiv preprint"), ("second", "Ann. Math.")] {
+        let mut cmd = s.cmd()?;
+        cmd.args([
+            "local",
+            id,
+            "--with-field",
+            &format!("journal = {{{journal}}}"),
+        ]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["edit", "--where", "journal ~ arXiv"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("No edit action specified for `--where`"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "edit",
+        "--where",
+        "journal ~ (",
+        "--delete-field",
+        "journal",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Expected a value, found '('"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "edit",
+        "--where",
+        r#"provider = local and journal ~ "^arXiv""#,
+        "--set-field",
+        "year = {2024}",
+        "--dry-run",
+    ]);
+    cmd.assert()
+        .success()
+        .stdout("local:first:\n  year (added)\n    + 2024\n");
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "edit",
+        "--where",
+        "not journal ~ arXiv",
+        "--set-field",
+        "year = {2024}",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:first", "local:second"]);
+    cmd.assert().success().stdout(
+        contains("@misc{local:first,\n  journal = {arXiv preprint},\n}")
+            .and(contains("year = {2024}")),
+    );
+
+    s.close()
+}
+
+#[test]
+fn nocite() -> Result<()> {
+    let s = TestState::init()?;
+
+    for (id, year) in [("first", "2019"), ("second", "2021"), ("third", "2022")] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["local", id, "--with-field", &format!("year = {{{year}}}")]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = s.cmd()?;
+    cmd.arg("nocite");
+    cmd.assert().failure();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["nocite", "local:first", "--where", "year > 2021"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("\\nocite{local:first,local:third}\n"));
+
+    let dir = TempDir::new()?;
+    let nocite = dir.child("nocite.tex");
+    let bib = dir.child("nocite.bib");
+    let mut cmd = s.cmd()?;
+    cmd.args(["nocite", "--where", "year >= 2020", "--out"])
+        .arg(nocite.path())
+        .arg("--bib")
+        .arg(bib.path());
+    cmd.assert().success().stdout(predicate::str::diff(""));
+    assert_eq!(
+        fs::read_to_string(nocite.path())?,
+        "\\nocite{local:second,local:third}\n"
+    );
+    let contents = fs::read_to_string(bib.path())?;
+    assert!(contents.contains("@misc{local:second,"));
+    assert!(contents.contains("@misc{local:third,"));
+
+    dir.close()?;
+    s.close()
+}
+
+#[test]
+fn update() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["update", "zbmath:06346461"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("does not exist in database").and(contains("Use `autobib get`")));
+
+    s.close()
+}
+
+#[test]
+fn update_local() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["local", "one"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:one"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["update", "local:one"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Cannot update local record using remote data"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["update", "local:two"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("does not exist in database").and(contains("Use `autobib get`").not()));
+
+    s.close()
+}
+
+#[test]
+fn update_field_sources() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["local", "one", "--with-field", "title = {A}"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "local",
+        "two",
+        "--with-field",
+        "title = {B}",
+        "--with-field",
+        "note = {N}",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["update", "local:one", "--from-record", "local:two"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "local:one", "--report", "provenance"]);
+    cmd.assert().success().stdout(
+        contains("Field sources:")
+            .and(contains("note  record local:two"))
+            .and(contains("title").not()),
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "diff", "local:one"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("Field sources of").and(contains("record local:two")));
+
+    s.close()
+}
+
+#[test]
+fn update_merge_rules() -> Result<()> {
+    let s = TestState::init()?;
+    fs::write(
+        s.config.as_ref(),
+        "[merge]\nnote = \"concat\"\ntitle = \"prefer-longest\"\n",
+    )?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "local",
+        "one",
+        "--with-field",
+        "title = {A}",
+        "--with-field",
+        "note = {X}",
+        "--with-field",
+        "year = {2000}",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "local",
+        "two",
+        "--with-field",
+        "title = {Longer}",
+        "--with-field",
+        "note = {Y}",
+        "--with-field",
+        "year = {2001}",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "update",
+        "local:one",
+        "--from-record",
+        "local:two",
+        "--on-conflict",
+        "prefer-current",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:one"]);
+    cmd.assert().success().stdout(
+        contains("{Longer}")
+            .and(contains("{X; Y}"))
+            .and(contains("{2000}")),
+    );
+
+    s.close()
+}
+
+#[test]
+fn update_conflicts_out() -> Result<()> {
+    let s = TestState::init()?;
+    let dir = TempDir::new()?;
+    let conflicts = dir.child("conflicts.toml");
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "local",
+        "one",
+        "--with-field",
+        "title = {A}",
+        "--with-field",
+        "year = {2000}",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "local",
+        "two",
+        "--with-field",
+        "title = {B}",
+        "--with-field",
+        "year = {2000}",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["update", "local:one", "--from-record", "local:two", "-n"])
+        .arg("prefer-current")
+        .arg("--conflicts-out")
+        .arg(conflicts.path());
+    cmd.assert().success();
+
+    let written = fs::read_to_string(conflicts.path())?;
+    assert!(written.contains("[[conflict]]"));
+    assert!(written.contains("field = \"title\""));
+    assert!(written.contains("resolved = \"A\""));
+    assert!(!written.contains("year"));
+
+    conflicts.write_str(&written.replace("resolved = \"A\"", "resolved = \"B\""))?;
+
+    let mut cmd = s.cmd()?;
+    cmd.arg("resolve").arg(conflicts.path());
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:one"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("{B}").and(contains("{A}").not()));
+
+    s.close()
+}
+
+#[test]
+fn update_all() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["local", "one"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["update", "--all"]);
+    cmd.assert()
+        .success()
+        .stderr(contains("Retrieved 0 records and changed 0"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["update", "--older-than", "180d"]);
+    cmd.assert().failure();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["update", "--all", "--older-than", "180x"]);
+    cmd.assert().failure().stderr(contains("such as '180d'"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["update", "--all", "local:one"]);
+    cmd.assert().failure();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbmath:06346461"]);
+    cmd.assert().success();
+
+    // the record was just retrieved
+    let mut cmd = s.cmd()?;
+    cmd.args(["update", "--all", "--older-than", "1d"]);
+    cmd.assert()
+        .success()
+        .stderr(contains("Retrieved 0 records and changed 0"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["update", "--all", "-n", "prefer-current"]);
+    cmd.assert()
+        .success()
+        .stderr(contains("Retrieved 1 record and changed 0"));
+
+    s.close()
+}
+
+#[test]
+fn consistency() -> Result<()> {
+    use rusqlite::Connection;
+
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "get",
+        "--retrieve-only",
+        "zbmath:06346461",
+        "zbl:1337.28015",
+        "mr:3224722",
+    ]);
+    cmd.assert().success();
+
+    // perform some destructive changes to the database
+    let conn = Connection::open(s.database.path())?;
+    conn.pragma_update(None, "foreign_keys", 0)?;
+    conn.prepare("DELETE FROM Records WHERE record_id = 'zbmath:06346461'")?
+        .execute(())?;
+    conn.prepare("DELETE FROM Identifiers WHERE name = 'mr:3224722'")?
+        .execute(())?;
+    drop(conn);
+
+    // check that things are broken
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbmath:06346461"]);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "Database error: SQLite error: Query returned no rows",
+    ));
+
+    // check that the error report is correct
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check"]);
+    cmd.assert().failure().stderr(
+        predicate::str::contains("Record id 'mr:3224722' contains 0 active rows").and(
+            predicate::str::contains("2 identifiers which reference records which do not exist"),
+        ),
+    );
+
+    s.close()
+}
+
+#[test]
+fn check_fix_normalize() -> Result<()> {
+    use rusqlite::Connection;
+
+    let s = TestState::init()?;
+
+    for (key, title) in [("a", "A"), ("b", "B"), ("c", "C")] {
+        let mut cmd = s.cmd()?;
+        cmd.args([
+            "local",
+            key,
+            "--with-field",
+            &format!("title = {{{title}}}"),
+        ]);
+        cmd.assert().success();
+    }
+
+    // create records with identifiers which are not normalized, one of which collides with an
+    // existing record
+    let conn = Connection::open(s.database.path())?;
+    for (local, remote) in [
+        ("local:a", "zbmath:6346461"),
+        ("local:b", "zbmath:06346461"),
+        ("local:c", "mr:123456"),
+    ] {
+        conn.prepare("UPDATE Records SET record_id = ?2 WHERE record_id = ?1")?
+            .execute((local, remote))?;
+        conn.prepare("UPDATE Added SET record_id = ?2 WHERE record_id = ?1")?
+            .execute((local, remote))?;
+        conn.prepare("UPDATE Identifiers SET name = ?2 WHERE name = ?1")?
+            .execute((local, remote))?;
+    }
+    drop(conn);
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check"]);
+    cmd.assert().failure().stderr(
+        contains("'zbmath:6346461' which is not normalized")
+            .and(contains("'mr:123456' which is not normalized")),
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check", "--fix"]);
+    cmd.assert()
+        .success()
+        .stderr(contains("DATABASE ERROR").not());
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "mr:0123456"]);
+    cmd.assert().success().stdout(contains("title = {C}"));
+
+    // the existing record remains active after merging
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbmath:06346461"]);
+    cmd.assert().success().stdout(contains("title = {B}"));
+
+    s.close()
+}
+
+/// Check that `autobib get` warns if there are multiple references to the same key
+#[test]
+fn repeat() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbmath:06346461", "zbl:1337.28015"]);
+    cmd.assert()
+        .success()
+        .stderr(contains("Multiple keys for "));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "add", "a", "zbl:1337.28015"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbmath:06346461", "a"]);
+    cmd.assert()
+        .success()
+        .stderr(contains("Multiple keys for "));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "a", "a"]);
+    cmd.assert()
+        .success()
+        .stderr(contains("Multiple keys for "));
+
+    s.close()
+}
+
+#[test]
+fn config() -> Result<()> {
+    let s = TestState::init()?;
+
+    s.set_config(Path::new("tests/resources/config/malformed.toml"))?;
+    let mut cmd = s.cmd()?;
+    cmd.arg("get");
+    cmd.assert().failure();
+
+    s.set_config(Path::new("tests/resources/config/extra.toml"))?;
+    let mut cmd = s.cmd()?;
+    cmd.arg("get");
+    cmd.assert().failure();
+
+    s.close()
+}
+
+/// Check that the `on_insert` methods work as expected.
+#[test]
+fn on_insert() -> Result<()> {
+    let s = TestState::init()?;
+
+    s.set_config(Path::new("tests/resources/on_insert/config.toml"))?;
+
+    let predicate_file =
+        predicate::path::eq_file(Path::new("tests/resources/on_insert/stdout.txt"))
+            .utf8()
+            .unwrap();
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "mr:3224722"]);
+    cmd.assert().success().stdout(predicate_file);
+
+    s.close()
+}
+
+/// Test identifiers which have previously caused errors
+#[test]
+fn test_identifier_exceptions() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "arxiv:2112.04570"]);
+    cmd.assert().success();
+
+    s.close()
+}
+
+#[test]
+fn test_quiet_returns_error() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["-q", "-q", "get", "::invalid"]);
+    cmd.assert().failure();
+
+    s.close()
+}
+
+#[test]
+fn test_cache_evict() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbmath:96346461"]);
+    cmd.assert().failure();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["-v", "util", "evict", "--max-age", "10000"]);
+    cmd.assert()
+        .success()
+        .stderr(contains("Removed 0 cached null"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["-v", "util", "evict"]);
+    cmd.assert()
+        .success()
+        .stderr(contains("Removed 1 cached null"));
+
+    s.close()
+}
+
+/// Test that `autobib source` retries cached null records which have expired.
+#[test]
+fn source_retry_null() -> Result<()> {
+    use rusqlite::Connection;
+
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbmath:96346461"]);
+    cmd.assert().failure();
+
+    // the null record was attempted too recently
+    let mut cmd = assert_cmd::Command::from_std(s.cmd()?);
+    cmd.args(["-v", "source", "--stdin", "tex", "--retry-null", "1"])
+        .write_stdin("\\cite{zbmath:96346461}");
+    cmd.assert()
+        .failure()
+        .stderr(contains("Retrying null record").not());
+
+    let conn = Connection::open(s.database.path())?;
+    conn.prepare("UPDATE NullRecords SET attempted = '2000-01-01 00:00:00+00:00'")?
+        .execute(())?;
+    drop(conn);
+
+    // the budget is zero by default
+    let mut cmd = assert_cmd::Command::from_std(s.cmd()?);
+    cmd.args(["-v", "source", "--stdin", "tex"])
+        .write_stdin("\\cite{zbmath:96346461}");
+    cmd.assert()
+        .failure()
+        .stderr(contains("Retrying null record").not());
+
+    let mut cmd = assert_cmd::Command::from_std(s.cmd()?);
+    cmd.args(["-v", "source", "--stdin", "tex", "--retry-null", "1"])
+        .write_stdin("\\cite{zbmath:96346461}");
+    cmd.assert()
+        .failure()
+        .stderr(contains("Retrying null record 'zbmath:96346461'"));
+
+    // the record is cached as null again, with a new attempt time
+    let mut cmd = assert_cmd::Command::from_std(s.cmd()?);
+    cmd.args(["-v", "source", "--stdin", "tex", "--retry-null", "1"])
+        .write_stdin("\\cite{zbmath:96346461}");
+    cmd.assert()
+        .failure()
+        .stderr(contains("Retrying null record").not());
+
+    s.close()
+}
+
+/// Test that `autobib get` retries cached null records which have expired or which are refreshed
+/// explicitly.
+#[test]
+fn get_refresh_null() -> Result<()> {
+    use rusqlite::Connection;
+
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbmath:96346461"]);
+    cmd.assert().failure();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["-v", "get", "zbmath:96346461"]);
+    cmd.assert().failure().stderr(contains("Retrying").not());
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["-v", "get", "--refresh-null", "zbmath:96346461"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Retrying null record 'zbmath:96346461'"));
+
+    let conn = Connection::open(s.database.path())?;
+    conn.prepare("UPDATE NullRecords SET attempted = '2000-01-01 00:00:00+00:00'")?
+        .execute(())?;
+    drop(conn);
+
+    // null records do not expire by default
+    let mut cmd = s.cmd()?;
+    cmd.args(["-v", "get", "zbmath:96346461"]);
+    cmd.assert().failure().stderr(contains("Retrying").not());
+
+    fs::write(s.config.as_ref(), "[cache]\nnull_ttl = \"30d\"\n")?;
+    let mut cmd = s.cmd()?;
+    cmd.args(["-v", "get", "zbmath:96346461"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Retrying expired null record 'zbmath:96346461'"));
+
+    // the record is cached as null again, with a new attempt time
+    let mut cmd = s.cmd()?;
+    cmd.args(["-v", "get", "zbmath:96346461"]);
+    cmd.assert().failure().stderr(contains("Retrying").not());
+
+    fs::write(s.config.as_ref(), "[cache]\nnull_ttl = \"30\"\n")?;
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Config 'cache.null_ttl' is invalid"));
+
+    s.close()
+}
+
+#[test]
+fn offline() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["local", "a"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--offline", "get", "local:a"]);
+    cmd.assert().success().stdout(contains("@misc{local:a,"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--offline", "get", "zbmath:96346461", "local:a"]);
+    cmd.assert()
+        .failure()
+        .stdout(contains("@misc{local:a,"))
+        .stderr(contains("network access is disabled with `--offline`"));
+
+    let mut cmd = assert_cmd::Command::from_std(s.cmd()?);
+    cmd.env("AUTOBIB_OFFLINE", "true")
+        .args(["source", "--stdin", "tex"])
+        .write_stdin("\\cite{zbmath:96346461}");
+    cmd.assert()
+        .failure()
+        .stderr(contains("network access is disabled with `--offline`"));
+
+    // missing identifiers are not cached as null records
+    let mut cmd = s.cmd()?;
+    cmd.args(["stats"]);
+    cmd.assert().success().stdout(contains("Null records: 0"));
+
+    s.close()
+}
+
+#[test]
+fn url_identifiers() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--offline", "get", "https://arxiv.org/abs/2112.04570"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("'arxiv:2112.04570'"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "--offline",
+        "get",
+        "url:https://doi.org/10.4007/annals.2014.180.2.7",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("'doi:10.4007/annals.2014.180.2.7'"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--offline", "get", "https://example.com/paper"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("'url:https://example.com/paper'"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "url:example.com"]);
+    cmd.assert().failure();
+
+    s.close()
+}
+
+#[test]
+fn http_cache() -> Result<()> {
+    let s = TestState::init()?;
+    let dir = TempDir::new()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.env_remove("AUTOBIB_REPLAY_HTTP_CACHE")
+        .args(["get", "--replay-http-cache"])
+        .arg(dir.path())
+        .arg("zbmath:06346461");
+    cmd.assert()
+        .failure()
+        .stderr(contains("Failed to open HTTP response cache"));
+
+    let mut cmd = s.cmd()?;
+    cmd.env_remove("AUTOBIB_REPLAY_HTTP_CACHE")
+        .args(["get", "--record-http-cache"])
+        .arg(dir.path())
+        .arg("local:a");
+    cmd.assert().failure();
+    dir.child("responses.dat")
+        .assert(predicate::path::is_file());
+
+    let mut cmd = s.cmd()?;
+    cmd.env_remove("AUTOBIB_REPLAY_HTTP_CACHE")
+        .args(["get", "--replay-http-cache"])
+        .arg(dir.path())
+        .arg("zbmath:06346461");
+    cmd.assert()
+        .failure()
+        .stderr(contains("does not exist in local response cache"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--offline", "--record-http-cache"])
+        .arg(dir.path())
+        .arg("get");
+    cmd.assert().failure();
+
+    dir.close()?;
+    s.close()
+}
+
+#[test]
+fn http_config() -> Result<()> {
+    let s = TestState::init()?;
+    let dir = TempDir::new()?;
+
+    fs::write(s.config.as_ref(), "[http]\nproxy = \"ftp://proxy\"\n")?;
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Config 'http.proxy' is invalid"));
+
+    fs::write(s.config.as_ref(), "[http]\nca_bundle = \"missing.pem\"\n")?;
+    let mut cmd = s.cmd()?;
+    cmd.env_remove("AUTOBIB_REPLAY_HTTP_CACHE")
+        .arg("--record-http-cache")
+        .arg(dir.path())
+        .args(["get", "zbmath:06346461"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Failed to read CA bundle"));
+
+    dir.close()?;
+    s.close()
+}
+
+#[test]
+fn util_diagnose() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--offline", "util", "diagnose", "arxiv"]);
+    cmd.assert()
+        .failure()
+        .stdout(contains("arxiv  offline"))
+        .stdout(contains("arXiv API not checked"))
+        .stdout(contains("doi").not())
+        .stderr(contains("1 of 1 providers failed"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "diagnose", "local"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Provider 'local' does not retrieve remote data"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "diagnose", "nonexistent"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Unknown provider 'nonexistent'"));
+
+    s.close()
+}
+
+#[test]
+fn test_log_file() -> Result<()> {
+    let s = TestState::init()?;
+    let log_file = NamedTempFile::new("autobib.log")?;
+
+    for _ in 0..2 {
+        let mut cmd = s.cmd()?;
+        cmd.args(["-v", "util", "evict", "--log-file"]);
+        cmd.arg(log_file.as_ref());
+        cmd.assert()
+            .success()
+            .stderr(contains("Removed 0 cached null"));
+    }
+
+    let contents = fs::read_to_string(log_file.as_ref())?;
+    assert_eq!(
+        contents
+            .lines()
+            .filter(|line| line.ends_with(" info: Removed 0 cached null records."))
+            .count(),
+        2
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "evict", "--log-file", "/nonexistent/autobib.log"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Failed to open log file"));
+
+    log_file.close()?;
+    s.close()
+}
+
+#[test]
+fn test_log_format_json() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--log-format", "json", "get", "local:missing"]);
+    cmd.assert().failure().stderr(
+        contains("\"level\":\"error\"")
+            .and(contains(
+                "\"message\":\"Provider error: Cannot retrieve remote data for key with local provenance",
+            ))
+            .and(predicate::str::is_match("(?m)^error:")?.not()),
+    );
+
+    s.close()
+}
+
+#[test]
+fn test_normalize() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbmath:6346461"]);
+    cmd.assert().success().stdout(
+        predicate::path::eq_file(Path::new("tests/resources/normalize/stdout.txt"))
+            .utf8()
+            .unwrap(),
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "zbmath:1111111"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("converted from 'zbmath:1111111'"));
+
+    s.close()
+}
+
+#[test]
+fn test_strip_journal_series() -> Result<()> {
+    let s = TestState::init()?;
+
+    s.set_config(Path::new(
+        "tests/resources/strip_journal_series/config.toml",
+    ))?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbl:1337.28015"]);
+    cmd.assert().success().stdout(
+        predicate::path::eq_file(Path::new("tests/resources/strip_journal_series/stdout.txt"))
+            .utf8()
+            .unwrap(),
+    );
+
+    s.close()
+}
+
+#[test]
+fn test_protect_case() -> Result<()> {
+    let s = TestState::init()?;
+
+    s.set_config(Path::new("tests/resources/protect_case/config.toml"))?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "local",
+        "pc",
+        "--with-entry-type",
+        "article",
+        "--with-field",
+        "title = {BibTeX and the Navier–Stokes equations}",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["edit", "local:pc", "--protect-case"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:pc"]);
+    cmd.assert().success().stdout(contains(
+        "title = {{BibTeX} and the {Navier–Stokes} equations}",
+    ));
+
+    s.close()
+}
+
+#[test]
+fn test_normalize_names() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "local",
+        "nn",
+        "--with-entry-type",
+        "article",
+        "--with-field",
+        "author = {Donald E. Knuth and Ludwig van Beethoven}",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["edit", "local:nn", "--normalize-names"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:nn"]);
+    cmd.assert().success().stdout(contains(
+        "author = {Knuth, Donald E. and van Beethoven, Ludwig}",
+    ));
+
+    s.close()
+}
+
+#[test]
+fn test_eid_to_pages() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "local",
+        "eonly",
+        "--with-entry-type",
+        "article",
+        "--with-field",
+        "eid = {e12}",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["edit", "local:eonly", "--eid-to-pages"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:eonly"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("pages = {e12}").and(contains("eid").not()));
+
+    s.close()
+}
+
+#[test]
+fn test_auto_alias() -> Result<()> {
+    let s = TestState::init()?;
+
+    s.set_config(Path::new("tests/resources/auto_alias/config.toml"))?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbMATH06346461"]);
+    cmd.assert().success().stdout(
+        predicate::path::eq_file(Path::new("tests/resources/auto_alias/stdout.txt"))
+            .utf8()
+            .unwrap(),
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbMATH6346461"]);
+    cmd.assert().failure().stderr(contains("Undefined alias"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbl:1337.28015"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "zbl:1337.28015", "--report", "equivalent"]);
+    cmd.assert().success().stdout(contains("zbMATH06346461"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "mr:3224722"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "MR3224722"]);
+    cmd.assert().success().stdout(
+        predicate::path::eq_file(Path::new("tests/resources/auto_alias/stdout_mr.txt"))
+            .utf8()
+            .unwrap(),
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "MR3224722", "--report", "equivalent"]);
+    cmd.assert().success().stdout(contains("mr:3224722"));
+
+    s.close()
+}
+
+#[test]
+fn import_basic() -> Result<()> {
+    let s = TestState::init()?;
+
+    s.set_config("tests/resources/import/config.toml")?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["import", "tests/resources/import/file.bib"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "attainable-assouad-spectra"]);
+    cmd.assert().success().stdout(
+        predicate::path::eq_file(Path::new("tests/resources/import/stdout_local.txt"))
+            .utf8()
+            .unwrap(),
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbmath:06346461"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("doi = {10.4007/annals.2014.180.2.7}"));
+
+    s.close()
+}
+
+#[test]
+fn import_idempotent() -> Result<()> {
+    let s = TestState::init()?;
+    s.set_config("tests/resources/import/config.toml")?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["import", "tests/resources/import/file.bib"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["import", "tests/resources/import/file.bib"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["import", "tests/resources/import/file.bib"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "undo", "attainable-assouad-spectra"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Cannot void record"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "undo", "zbMATH06346461"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Cannot void record"));
+
+    s.close()
+}
+
+#[test]
+fn import_no_alias() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["import", "tests/resources/import/file.bib", "--no-alias"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "attainable-assouad-spectra"]);
+    cmd.assert().failure().stderr(contains("Undefined alias"));
+
+    s.close()
+}
+
+#[test]
+fn no_key() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+
+    // no key for the import
+    cmd.args(["import", "tests/resources/import/no_ids.bib"]);
+    cmd.assert()
+        .failure()
+        .stdout(contains("Could not determine candidate key"));
+
+    // but succeeds with local fallback
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "import",
+        "tests/resources/import/no_ids.bib",
+        "--local-fallback",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:my-article"]);
+    cmd.assert().success().stdout(contains("John, Doe"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "import",
+        "tests/resources/import/file.bib",
+        "--local-fallback",
+    ]);
+    cmd.assert().success();
+
+    // local fallback is not used if the key could be determined
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:zbMATH06346461"]);
+    cmd.assert().failure();
+
+    // local fallback is not used if reference key is found
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "import",
+        "tests/resources/import/retrieve.bib",
+        "--local-fallback",
+    ]);
+    cmd.assert()
+        .failure()
+        .stdout(contains("Failed to determine canonical id"));
+
+    s.close()
+}
+
+#[test]
+fn import_string_macros() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "import",
+        "tests/resources/import/macros.bib",
+        "--local-fallback",
+    ]);
+    cmd.assert().success();
+
+    // the abbreviations are expanded on import
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:my-article"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("J. Amer. Math. Soc."))
+        .stdout(contains("Springer Verlag"))
+        .stdout(contains("@string").not());
+
+    fs::write(s.config.as_ref(), "[output]\nstring_preamble = true\n")?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:my-article"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("@string{jams = {J. Amer. Math. Soc.}}"))
+        .stdout(contains("@string{publ = {Springer Verlag}}"));
+
+    s.close()
+}
+
+#[test]
+fn import_crossref() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "import",
+        "tests/resources/import/crossref.bib",
+        "--local-fallback",
+        "--keep-relations",
+    ]);
+    cmd.assert().success();
+
+    // the fields are inherited from the parents, and the references are removed
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:talk"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("booktitle = {Proceedings of a conference}"))
+        .stdout(contains("year = {2000}"))
+        .stdout(contains("location = {Providence}"))
+        .stdout(contains("crossref").not())
+        .stdout(contains("xdata").not())
+        .stdout(contains("@proceedings").not());
+
+    // the xdata entry is not imported
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:ams"]);
+    cmd.assert().failure();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:talk", "--with-parents"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("@proceedings{local:proc,"));
+
+    s.close()
+}
+
+#[test]
+fn import_local_fallback_fails() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["local", "my-article"]);
+    cmd.assert().success();
+
+    // local key already exists
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "import",
+        "tests/resources/import/no_ids.bib",
+        "--local-fallback",
+    ]);
+    cmd.assert()
+        .failure()
+        .stdout(contains("Local id 'local:my-article' already exists"));
+
+    // contains colon
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "import",
+        "tests/resources/import/id_contains_colon.bib",
+        "--local-fallback",
+    ]);
+    cmd.assert()
+        .failure()
+        .stdout(contains("provider is invalid"));
+
+    s.close()
+}
+
+/// Check that an entry which cannot be imported does not affect the other entries in its batch.
+#[test]
+fn import_batches() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["local", "c"]);
+    cmd.assert().success();
+
+    let bib = NamedTempFile::new("entries.bib")?;
+    bib.write_str(
+        "@misc{a, title = {A}}\n@misc{b, title = {B}}\n@misc{c, title = {C}}\n@misc{d, title = {D}}\n@misc{e, title = {E}}\n",
+    )?;
+
+    let mut cmd = s.cmd()?;
+    cmd.arg("import")
+        .arg(bib.path())
+        .args(["--local-fallback", "--batch-size", "2"]);
+    cmd.assert()
+        .failure()
+        .stdout(contains("Local id 'local:c' already exists"));
+
+    for (key, title) in [("a", "A"), ("b", "B"), ("d", "D"), ("e", "E")] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["get", &format!("local:{key}")]);
+        cmd.assert()
+            .success()
+            .stdout(contains(format!("title = {{{title}}}")));
+    }
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:c"]);
+    cmd.assert().success().stdout(contains("title").not());
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["import", "--batch-size", "0"]).arg(bib.path());
+    cmd.assert().failure();
+
+    bib.close()?;
+    s.close()
+}
+
+#[test]
+fn import_retrieve() -> Result<()> {
+    let s = TestState::init()?;
+
+    s.set_config("tests/resources/import/config.toml")?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["import", "tests/resources/import/retrieve.bib"]);
+    cmd.assert()
+        .failure()
+        .stdout(contains("Failed to determine canonical id"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["import", "tests/resources/import/retrieve.bib", "--resolve"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["info", "abc", "--report", "canonical"]);
+    cmd.assert().success().stdout("zbmath:06346461\n");
+
+    s.close()
+}
+
+#[test]
+fn import_update() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbmath:6346461"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "import",
+        "tests/resources/import/retrieve.bib",
+        "--resolve",
+        "--update",
+        "prefer-current",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbmath:6346461"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("note = {extra}").and(contains("inverse theorems for entropy")));
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "import",
+        "tests/resources/import/retrieve.bib",
+        "--resolve",
+        "--update",
+        "prefer-incoming",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbmath:6346461"]);
+    cmd.assert().success().stdout(
+        contains("@book{")
+            .and(contains("note = {extra}"))
+            .and(contains("overlaps and typos")),
+    );
+
+    s.close()
+}
+
+#[test]
+fn read_only() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbl:1337.28015"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--read-only", "get", "zbl:1337.28015"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--read-only", "get", "arxiv:1212.1873"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Database does not contain key"));
+
+    for arg in ["check", "list"] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["--read-only", "util", arg]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--read-only", "info", "zbl:1337.28015"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--read-only", "info", "arxiv:1212.1873"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Cannot obtain report for record not in database"));
+
+    Ok(())
+}
+
+#[test]
+fn replace_auto() -> Result<()> {
+    let s = TestState::init()?;
+
+    s.set_config("tests/resources/import/config.toml")?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["import", "tests/resources/import/init.bib", "--resolve"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["replace", "arxiv:1212.1873", "--auto"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("is equivalent to the current identifier"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "add", "arx", "arxiv:1212.1873"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "edit",
+        "arxiv:1212.1873",
+        "--set-field",
+        "zbmath = {6346461}",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["replace", "arxiv:1212.1873", "--auto"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "arxiv:1212.1873"]);
+    cmd.assert().failure().stderr(contains(
+        "Perhaps use the replacement key: 'zbmath:06346461'",
+    ));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "arx"]);
+    cmd.assert().failure().stderr(contains(
+        "Perhaps use the replacement key: 'zbmath:06346461'",
+    ));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "zbmath:06346461"]);
+    cmd.assert().success().stdout(contains("@article{"));
+
+    s.close()
+}
+
+#[test]
+fn replace_migrates_attachments() -> Result<()> {
+    let s = TestState::init()?;
+
+    s.set_config("tests/resources/import/config.toml")?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["import", "tests/resources/import/init.bib", "--resolve"]);
+    cmd.assert().success();
+
+    let temp = assert_fs::NamedTempFile::new("attachment.txt")?;
+    let temp_contents = "test\ncontents";
+    temp.write_str(temp_contents)?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "arxiv:1212.1873"]);
+    cmd.arg(temp.as_ref());
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["replace", "arxiv:1212.1873", "--with", "zbmath:06346461"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["path", "zbmath:06346461"]);
+    let output = cmd.output()?;
+    let target = String::from_utf8(output.stdout)?;
+    let attachment = Path::new(target.trim_end()).join("attachment.txt");
+    assert_eq!(fs::read_to_string(attachment)?, temp_contents);
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check"]);
+    cmd.assert().success();
+
+    temp.close()?;
+    s.close()
+}
+
+#[test]
+fn util_migrate_attachments() -> Result<()> {
+    let s = TestState::init()?;
+
+    for key in ["a", "b"] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["local", key, "--with-field", "title = {T}"]);
+        cmd.assert().success();
+    }
+
+    // an attachment directory whose header is out of date
+    s.attachment("local/AA/AA/AA/ME======/note.txt")
+        .write_str("contents")?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["replace", "local:a", "--with", "local:b"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "migrate-attachments", "--dry-run"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("local:a -> local:b"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "local:b", "--list"]);
+    cmd.assert().success().stdout(predicate::str::is_empty());
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "migrate-attachments"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "local:b", "--list"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("note.txt\n"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "migrate-attachments", "--dry-run"]);
+    cmd.assert().success().stdout(predicate::str::is_empty());
+
+    s.close()
+}
+
+/// Check that `autobib util remap` changes the canonical identifier of a record, keeping its
+/// history, and that the old canonical identifier still refers to the record.
+#[test]
+fn util_remap() -> Result<()> {
+    let s = TestState::init()?;
+
+    for key in ["old", "other"] {
+        let mut cmd = s.cmd()?;
+        cmd.args([
+            "local",
+            key,
+            "--with-field",
+            "title = {A}",
+            "--with-entry-type",
+            "article",
+        ]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["edit", "local:old", "--set-field", "title = {B}"]);
+    cmd.assert().success();
+
+    let temp = assert_fs::NamedTempFile::new("attachment.txt")?;
+    temp.write_str("contents")?;
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "local:old"]).arg(temp.as_ref());
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["tag", "add", "local:old", "todo"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "remap", "local:old", "local:other"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("already refers to a different record"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "remap", "local:missing", "local:new"]);
+    cmd.assert().failure();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "remap", "local:old", "local:new"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "list", "--canonical"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("local:new").and(contains("local:old").not()));
+
+    // the old canonical identifier is a reference to the record
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:old"]);
+    cmd.assert().success().stdout(contains("title = {B}"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "undo", "local:new"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:new"]);
+    cmd.assert().success().stdout(contains("title = {A}"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["attach", "local:new", "--list"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("attachment.txt\n"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["tag", "list", "local:new"]);
+    cmd.assert().success().stdout("todo\n");
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "check"]);
+    cmd.assert().success();
+
+    temp.close()?;
+    s.close()
+}
+
+/// Check that a database written by `autobib util export` is restored by `autobib util import-dump`.
+#[test]
+fn util_export_import_dump() -> Result<()> {
+    let s = TestState::init()?;
+    s.create_test_db()?;
+
+    let dump = NamedTempFile::new("dump.jsonl")?;
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "export"]).arg(dump.as_ref());
+    cmd.assert().success();
+
+    let exported = fs::read_to_string(dump.as_ref())?;
+    assert!(exported.starts_with(r#"{"type":"header","format":"autobib-dump","version":1,"#));
+
+    let t = TestState::init()?;
+    let mut cmd = t.cmd()?;
+    cmd.args(["util", "import-dump"]).arg(dump.as_ref());
+    cmd.assert().success();
+
+    let mut cmd = t.cmd()?;
+    cmd.args(["get", "local:second"]);
+    cmd.assert().success().stdout(contains("author = {A}"));
+
+    let mut cmd = t.cmd()?;
+    cmd.args(["util", "check"]);
+    cmd.assert().success();
+
+    let reexported = NamedTempFile::new("reexported.jsonl")?;
+    let mut cmd = t.cmd()?;
+    cmd.args(["util", "export"]).arg(reexported.as_ref());
+    cmd.assert().success();
+    assert_eq!(exported, fs::read_to_string(reexported.as_ref())?);
+
+    // the database is no longer empty
+    let mut cmd = t.cmd()?;
+    cmd.args(["util", "import-dump"]).arg(dump.as_ref());
+    cmd.assert()
+        .failure()
+        .stderr(contains("already contains records"));
+
+    t.close()?;
+    s.close()
+}
+
+#[test]
+fn sync() -> Result<()> {
+    let laptop = TestState::init()?;
+    let desktop = TestState::init()?;
+
+    let mut cmd = laptop.cmd()?;
+    cmd.args([
+        "local",
+        "a",
+        "--with-entry-type",
+        "article",
+        "--with-field",
+        "title = {T}",
+        "--with-field",
+        "year = {2000}",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = laptop.cmd()?;
+    cmd.args(["alias", "add", "my-alias", "local:a"]);
+    cmd.assert().success();
+
+    let mut cmd = desktop.cmd()?;
+    cmd.args([
+        "local",
+        "b",
+        "--with-entry-type",
+        "misc",
+        "--with-field",
+        "title = {B}",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = laptop.cmd()?;
+    cmd.arg("sync").arg(desktop.database.as_ref());
+    cmd.assert().success();
+
+    let mut cmd = desktop.cmd()?;
+    cmd.args(["get", "my-alias"]);
+    cmd.assert().success().stdout(contains("title = {T}"));
+
+    let mut cmd = laptop.cmd()?;
+    cmd.args(["get", "local:b"]);
+    cmd.assert().success().stdout(contains("title = {B}"));
+
+    // changes to different fields are merged
+    let mut cmd = laptop.cmd()?;
+    cmd.args(["edit", "local:a", "--set-field", "title = {U}"]);
+    cmd.assert().success();
+
+    let mut cmd = desktop.cmd()?;
+    cmd.args(["edit", "local:a", "--set-field", "year = {2001}"]);
+    cmd.assert().success();
+
+    let mut cmd = laptop.cmd()?;
+    cmd.arg("sync").arg(desktop.database.as_ref());
+    cmd.assert().success();
+
+    for s in [&laptop, &desktop] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["get", "local:a"]);
+        cmd.assert()
+            .success()
+            .stdout(contains("title = {U}").and(contains("year = {2001}")));
+    }
+
+    // changes to the same field are reported
+    let mut cmd = laptop.cmd()?;
+    cmd.args(["edit", "local:a", "--set-field", "title = {V}"]);
+    cmd.assert().success();
+
+    let mut cmd = desktop.cmd()?;
+    cmd.args(["edit", "local:a", "--set-field", "title = {W}"]);
+    cmd.assert().success();
+
+    let mut cmd = desktop.cmd()?;
+    cmd.arg("sync").arg(laptop.database.as_ref());
+    cmd.assert().failure().stderr(contains(
+        "Record 'local:a' has conflicting changes to: title",
+    ));
+
+    let mut cmd = desktop.cmd()?;
+    cmd.args(["get", "local:a"]);
+    cmd.assert().success().stdout(contains("title = {W}"));
+
+    // a remote database which is in use is not changed
+    let lock = fs::File::create(desktop.database.path().with_extension("db.lock"))?;
+    lock.lock()?;
+    let mut cmd = laptop.cmd()?;
+    cmd.arg("sync").arg(desktop.database.as_ref());
+    cmd.assert()
+        .failure()
+        .stderr(contains("is locked by another autobib process"));
+    drop(lock);
+
+    let mut cmd = laptop.cmd()?;
+    cmd.args(["sync", "missing.db"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Remote database does not exist"));
+
+    desktop.close()?;
+    laptop.close()
+}
+
+/// Check that `autobib sync` copies the tags which are only present in one database.
+#[test]
+fn sync_tags() -> Result<()> {
+    let laptop = TestState::init()?;
+    let desktop = TestState::init()?;
+
+    for (s, name, tag) in [(&laptop, "a", "todo"), (&desktop, "b", "reading")] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["local", name, "--with-field", "title = {T}"]);
+        cmd.assert().success();
+
+        let mut cmd = s.cmd()?;
+        cmd.args(["tag", "add", &format!("local:{name}"), tag]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = laptop.cmd()?;
+    cmd.arg("sync").arg(desktop.database.as_ref());
+    cmd.assert().success();
+
+    for s in [&laptop, &desktop] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["tag", "list"]);
+        cmd.assert().success().stdout("reading\t1\ntodo\t1\n");
+    }
+
+    desktop.close()?;
+    laptop.close()
+}
+
+/// Check that `autobib sync` copies the relations which are only present in one database.
+#[test]
+fn sync_relations() -> Result<()> {
+    let laptop = TestState::init()?;
+    let desktop = TestState::init()?;
+
+    for (s, [first, second], kind) in [
+        (&laptop, ["a", "b"], "translation-of"),
+        (&desktop, ["c", "d"], "erratum-of"),
+    ] {
+        for name in [first, second] {
+            let mut cmd = s.cmd()?;
+            cmd.args(["local", name, "--with-field", "title = {T}"]);
+            cmd.assert().success();
+        }
+
+        let mut cmd = s.cmd()?;
+        cmd.args([
+            "relate",
+            &format!("local:{first}"),
+            &format!("local:{second}"),
+            "--kind",
+            kind,
+        ]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = laptop.cmd()?;
+    cmd.arg("sync").arg(desktop.database.as_ref());
+    cmd.assert().success();
+
+    let mut cmd = desktop.cmd()?;
+    cmd.args(["info", "local:a", "--report", "related"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("local:a translation-of local:b"));
+
+    let mut cmd = laptop.cmd()?;
+    cmd.args(["info", "local:c", "--report", "related"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("local:c erratum-of local:d"));
+
+    desktop.close()?;
+    laptop.close()
+}
+
+/// Check that `autobib sync` copies the collections and the records in each collection which
+/// are only present in one database.
+#[test]
+fn sync_collections() -> Result<()> {
+    let laptop = TestState::init()?;
+    let desktop = TestState::init()?;
+
+    for (s, name, collections) in [
+        (&laptop, "a", ["thesis", "paper"]),
+        (&desktop, "b", ["thesis", "talk"]),
+    ] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["local", name, "--with-field", "title = {T}"]);
+        cmd.assert().success();
+
+        for collection in collections {
+            let mut cmd = s.cmd()?;
+            cmd.args(["collection", "create", collection]);
+            cmd.assert().success();
+
+            let mut cmd = s.cmd()?;
+            cmd.args(["collection", "add", collection, &format!("local:{name}")]);
+            cmd.assert().success();
+        }
+    }
+
+    let mut cmd = laptop.cmd()?;
+    cmd.arg("sync").arg(desktop.database.as_ref());
+    cmd.assert().success();
+
+    for s in [&laptop, &desktop] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["collection", "list", "thesis"]);
+        cmd.assert().success().stdout("local:a\nlocal:b\n");
+
+        let mut cmd = s.cmd()?;
+        cmd.args(["collection", "list", "paper"]);
+        cmd.assert().success().stdout("local:a\n");
+
+        let mut cmd = s.cmd()?;
+        cmd.args(["collection", "list", "talk"]);
+        cmd.assert().success().stdout("local:b\n");
+    }
+
+    desktop.close()?;
+    laptop.close()
+}
+
+/// Check that `autobib sync` copies the field overrides which are only present in one database,
+/// and reports overrides with different values.
+#[test]
+fn sync_overrides() -> Result<()> {
+    let laptop = TestState::init()?;
+    let desktop = TestState::init()?;
+
+    for (s, name) in [(&laptop, "a"), (&desktop, "b")] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["local", name, "--with-field", "title = {T}"]);
+        cmd.assert().success();
+
+        let mut cmd = s.cmd()?;
+        cmd.args([
+            "override",
+            "set",
+            &format!("local:{name}"),
+            "title",
+            "Overridden",
+            "--scope",
+            "project.bib",
+        ]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = laptop.cmd()?;
+    cmd.arg("sync").arg(desktop.database.as_ref());
+    cmd.assert().success();
+
+    for s in [&laptop, &desktop] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["override", "list"]);
+        cmd.assert().success().stdout(
+            "local:a project.bib title = {Overridden}\nlocal:b project.bib title = {Overridden}\n",
+        );
+    }
+
+    let mut cmd = desktop.cmd()?;
+    cmd.args([
+        "override",
+        "set",
+        "local:a",
+        "title",
+        "Changed",
+        "--scope",
+        "project.bib",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = laptop.cmd()?;
+    cmd.arg("sync").arg(desktop.database.as_ref());
+    cmd.assert().failure().stderr(contains(
+        "Override of field 'title' of record 'local:a' in scope 'project.bib' has different values in the two databases",
+    ));
+
+    let mut cmd = laptop.cmd()?;
+    cmd.args(["override", "list", "local:a"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("title = {Overridden}"));
+
+    desktop.close()?;
+    laptop.close()
+}
+
+#[test]
+fn mirror() -> Result<()> {
+    let s = TestState::init()?;
+    let mirror = TempDir::new()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["local", "existing", "--with-field", "title = {E}"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.arg("--mirror-dir").arg(mirror.as_ref()).args([
+        "local",
+        "a",
+        "--with-entry-type",
+        "article",
+        "--with-field",
+        "title = {T}",
+    ]);
+    cmd.assert().success();
+
+    let path = mirror.child("local/a.bib");
+    let contents = fs::read_to_string(path.as_ref())?;
+    assert!(contents.starts_with("@article{local:a,"));
+    assert!(contents.contains("title = {T}"));
+    mirror
+        .child("local/existing.bib")
+        .assert(predicate::path::missing());
+
+    let mut cmd = s.cmd()?;
+    cmd.arg("--mirror-dir").arg(mirror.as_ref()).args([
+        "edit",
+        "local:a",
+        "--set-field",
+        "title = {U}",
+    ]);
+    cmd.assert().success();
+    assert!(fs::read_to_string(path.as_ref())?.contains("title = {U}"));
+
+    // write the records which were created without the mirror
+    let mut cmd = s.cmd()?;
+    cmd.arg("--mirror-dir")
+        .arg(mirror.as_ref())
+        .args(["util", "mirror"]);
+    cmd.assert().success();
+    mirror
+        .child("local/existing.bib")
+        .assert(predicate::str::contains("title = {E}"));
+
+    let mut cmd = s.cmd()?;
+    cmd.arg("--mirror-dir")
+        .arg(mirror.as_ref())
+        .args(["delete", "local:a"]);
+    cmd.assert().success();
+    path.assert(predicate::path::missing());
+
+    // the records imported in a batch are written once the batch is committed
+    let bibfile = mirror.child("import.bib");
+    bibfile.write_str(
+        "@article{local:b,\n  title = {B},\n}\n@article{local:c,\n  title = {C},\n}\n",
+    )?;
+    let mut cmd = s.cmd()?;
+    cmd.arg("--mirror-dir")
+        .arg(mirror.as_ref())
+        .arg("import")
+        .arg(bibfile.as_ref());
+    cmd.assert().success();
+    mirror
+        .child("local/b.bib")
+        .assert(predicate::str::contains("title = {B}"));
+    mirror
+        .child("local/c.bib")
+        .assert(predicate::str::contains("title = {C}"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "mirror"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("No mirror directory"));
+
+    s.close()
+}
+
+#[test]
+fn hist_last() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "last"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::is_empty())
+        .stderr(contains("No changes have been recorded"));
+
+    for key in ["a", "b"] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["local", key, "--with-field", "title = {T}"]);
+        cmd.assert().success();
+    }
+
+    // a summary is only printed if more than one record is changed
+    let mut cmd = s.cmd()?;
+    cmd.args(["edit", "local:a", "--set-field", "year = {2000}"]);
+    cmd.assert().success().stderr(contains("Changed").not());
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["edit", "local:a", "local:b", "--set-field", "year = {2001}"]);
+    cmd.assert().success().stderr(
+        contains("Changed 2 records with `autobib ")
+            .and(contains("edit local:a local:b --set-field year = {2001}"))
+            .and(contains("  local:a  updated    rev "))
+            .and(contains("  local:b  updated    rev ")),
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--read-only", "hist", "last"]);
+    cmd.assert().success().stdout(
+        contains("Changed 2 records")
+            .and(contains("  local:a  updated    rev "))
+            .and(contains("  local:b  updated    rev ")),
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "undo", "local:b"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "last", "--json"]);
+    cmd.assert().success().stdout(
+        contains(r#""canonical": "local:b""#)
+            .and(contains(r#""action": "activated""#))
+            .and(contains("local:a").not()),
+    );
+
+    s.close()
+}
+
+#[test]
+fn hist_sessions() -> Result<()> {
+    let s = TestState::init()?;
+
+    for key in ["a", "b"] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["local", key, "--with-field", "title = {T}"]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["edit", "local:a", "local:b", "--set-field", "year = {2001}"]);
+    cmd.assert()
+        .success()
+        .stderr(contains("Changed 2 records").and(contains("(session 3):")));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "show", "--sessions"]);
+    cmd.assert().success().stdout(
+        contains("3  ")
+            .and(contains("2 records  autobib "))
+            .and(contains("edit local:a local:b"))
+            .and(contains("1 record   autobib ")),
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "show", "--sessions", "--limit", "1", "--json"]);
+    cmd.assert().success().stdout(
+        contains(r#""session": 3"#)
+            .and(contains(r#""records": 2"#))
+            .and(contains(r#""session": 2"#).not()),
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "undo", "--session", "1"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Record 'local:a' was changed after session 1"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "undo", "--session", "10"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("No session with id 10"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "undo", "--session", "3"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:a", "local:b"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("title = {T}").count(2).and(contains("year").not()));
+
+    // the records now have the revisions created during the first sessions
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "undo", "--session", "1"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:a"]);
+    cmd.assert().failure();
+
+    s.close()
+}
+
+#[test]
+fn hist_diff() -> Result<()> {
+    let s = TestState::init()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["local", "a", "--with-field", "title = {Old title}"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "diff", "local:a"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("does not have an earlier revision"));
 
-    s.close()
-}
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "edit",
+        "local:a",
+        "--set-field",
+        "title = {New title}",
+        "--set-field",
+        "year = {2020}",
+    ]);
+    cmd.assert().success();
 
-#[test]
-fn test_cache_evict() -> Result<()> {
-    let s = TestState::init()?;
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "diff", "local:a"]);
+    cmd.assert().success().stdout(
+        "local:a: rev 0001 to rev 0002\n  title\n    - Old title\n    + New title\n  year (added)\n    + 2020\n",
+    );
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbmath:96346461"]);
-    cmd.assert().failure();
+    cmd.args(["hist", "diff", "local:a", "--rev", "2", "--rev", "1"]);
+    cmd.assert().success().stdout(
+        contains("local:a: rev 0002 to rev 0001").and(contains("  year (deleted)\n    - 2020")),
+    );
 
     let mut cmd = s.cmd()?;
-    cmd.args(["-v", "util", "evict", "--max-age", "10000"]);
+    cmd.args(["hist", "diff", "local:a", "--rev", "2"]);
     cmd.assert()
         .success()
-        .stderr(contains("Removed 0 cached null"));
+        .stdout("local:a: rev 0002 and rev 0002 are identical\n");
 
     let mut cmd = s.cmd()?;
-    cmd.args(["-v", "util", "evict"]);
+    cmd.args(["hist", "diff", "local:a", "--rev", "ff"]);
     cmd.assert()
-        .success()
-        .stderr(contains("Removed 1 cached null"));
+        .failure()
+        .stderr(contains("Record 'local:a' does not have rev 00ff"));
 
     s.close()
 }
 
 #[test]
-fn test_normalize() -> Result<()> {
+fn restore_backup() -> Result<()> {
     let s = TestState::init()?;
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbmath:6346461"]);
-    cmd.assert().success().stdout(
-        predicate::path::eq_file(Path::new("tests/resources/normalize/stdout.txt"))
-            .utf8()
-            .unwrap(),
-    );
+    cmd.args(["util", "restore-backup"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("No backups of the database were found"));
 
     let mut cmd = s.cmd()?;
-    cmd.args(["info", "zbmath:1111111"]);
+    cmd.args(["local", "a", "--with-field", "title = {Title}"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["delete", "--hard", "--yes", "local:a"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "restore-backup", "--list"]);
+    cmd.assert().success().stdout(contains("records-").count(1));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:a"]);
+    cmd.assert().failure();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "restore-backup"]);
     cmd.assert()
-        .failure()
-        .stderr(contains("converted from 'zbmath:1111111'"));
+        .success()
+        .stderr(contains("Undo the restore with"));
 
-    s.close()
-}
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:a"]);
+    cmd.assert().success().stdout(contains("title = {Title}"));
 
-#[test]
-fn test_strip_journal_series() -> Result<()> {
-    let s = TestState::init()?;
+    // the contents before the restore were saved
+    let mut cmd = s.cmd()?;
+    cmd.args(["util", "restore-backup", "--list"]);
+    cmd.assert().success().stdout(contains("records-").count(2));
 
-    s.set_config(Path::new(
-        "tests/resources/strip_journal_series/config.toml",
-    ))?;
+    let mut cmd = s.cmd()?;
+    cmd.args(["--no-backup", "delete", "--hard", "--yes", "local:a"]);
+    cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbl:1337.28015"]);
-    cmd.assert().success().stdout(
-        predicate::path::eq_file(Path::new("tests/resources/strip_journal_series/stdout.txt"))
-            .utf8()
-            .unwrap(),
-    );
+    cmd.args(["util", "restore-backup", "--list"]);
+    cmd.assert().success().stdout(contains("records-").count(2));
 
-    s.close()
+    Ok(())
 }
 
 #[test]
-fn test_auto_alias() -> Result<()> {
+#[cfg(unix)]
+fn relink_attachments() -> Result<()> {
     let s = TestState::init()?;
+    let layout = TempDir::new()?;
 
-    s.set_config(Path::new("tests/resources/auto_alias/config.toml"))?;
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "local",
+        "a",
+        "--with-field",
+        "author = {Jane Doe and Roe, Richard}",
+        "--with-field",
+        "year = {2020}",
+    ]);
+    cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbMATH06346461"]);
-    cmd.assert().success().stdout(
-        predicate::path::eq_file(Path::new("tests/resources/auto_alias/stdout.txt"))
-            .utf8()
-            .unwrap(),
-    );
+    cmd.args(["path", "local:a", "--layout"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("2020/Doe/local_a/\n"));
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbMATH6346461"]);
-    cmd.assert().failure().stderr(contains("Undefined alias"));
+    cmd.args(["path", "local:a", "--layout={%author1_last}/{title}"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("Doe/unknown/local_a/\n"));
+
+    let temp = assert_fs::NamedTempFile::new("attachment.txt")?;
+    temp.write_str("contents")?;
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbl:1337.28015"]);
+    cmd.args(["attach", "local:a"]);
+    cmd.arg(temp.as_ref());
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["info", "zbl:1337.28015", "--report", "equivalent"]);
-    cmd.assert().success().stdout(contains("zbMATH06346461"));
+    cmd.args(["util", "relink-attachments"])
+        .arg(layout.as_ref());
+    cmd.assert().success();
 
+    layout
+        .child("2020/Doe/local_a/attachment.txt")
+        .assert(predicate::eq("contents"));
+
+    // links are moved when the record changes
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "mr:3224722"]);
+    cmd.args(["edit", "local:a", "--set-field", "year = {2021}"]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "MR3224722"]);
-    cmd.assert().success().stdout(
-        predicate::path::eq_file(Path::new("tests/resources/auto_alias/stdout_mr.txt"))
-            .utf8()
-            .unwrap(),
-    );
+    cmd.args(["util", "relink-attachments"])
+        .arg(layout.as_ref());
+    cmd.assert().success();
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["info", "MR3224722", "--report", "equivalent"]);
-    cmd.assert().success().stdout(contains("mr:3224722"));
+    layout
+        .child("2021/Doe/local_a/attachment.txt")
+        .assert(predicate::eq("contents"));
+    layout.child("2020").assert(predicate::path::missing());
 
     s.close()
 }
 
 #[test]
-fn import_basic() -> Result<()> {
+fn overlay_database() -> Result<()> {
+    let shared = TestState::init()?;
     let s = TestState::init()?;
 
-    s.set_config("tests/resources/import/config.toml")?;
+    let mut cmd = shared.cmd()?;
+    cmd.args(["local", "a", "--with-field", "title = {T}"]);
+    cmd.assert().success();
+
+    let mut cmd = shared.cmd()?;
+    cmd.args(["alias", "add", "shared-alias", "local:a"]);
+    cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["import", "tests/resources/import/file.bib"]);
+    cmd.arg("--database")
+        .arg(shared.database.as_ref())
+        .args(["get", "shared-alias"]);
+    cmd.assert().success().stdout(contains("title = {T}"));
+
+    // the record was copied from the overlay
+    let mut cmd = s.cmd()?;
+    cmd.args(["edit", "local:a", "--set-field", "title = {U}"]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "attainable-assouad-spectra"]);
-    cmd.assert().success().stdout(
-        predicate::path::eq_file(Path::new("tests/resources/import/stdout_local.txt"))
-            .utf8()
-            .unwrap(),
-    );
+    cmd.arg("--database")
+        .arg(shared.database.as_ref())
+        .args(["get", "local:a"]);
+    cmd.assert().success().stdout(contains("title = {U}"));
+
+    // the overlay is not modified
+    let mut cmd = shared.cmd()?;
+    cmd.args(["get", "local:a"]);
+    cmd.assert().success().stdout(contains("title = {T}"));
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbmath:06346461"]);
+    cmd.args(["--database", "missing.db", "get", "local:a"]);
     cmd.assert()
-        .success()
-        .stdout(contains("doi = {10.4007/annals.2014.180.2.7}"));
+        .failure()
+        .stderr(contains("Overlay database file does not exist"));
 
+    shared.close()?;
     s.close()
 }
 
 #[test]
-fn import_idempotent() -> Result<()> {
+fn profile() -> Result<()> {
     let s = TestState::init()?;
-    s.set_config("tests/resources/import/config.toml")?;
-
-    let mut cmd = s.cmd()?;
-    cmd.args(["import", "tests/resources/import/file.bib"]);
-    cmd.assert().success();
+    fs::write(
+        s.config.as_ref(),
+        "[profile.work]\ndatabase = 'work.db'\npreferred_providers = ['local']\n",
+    )?;
+    let work_db = s
+        .config
+        .path()
+        .parent()
+        .expect("Temporary file has a parent directory")
+        .join("work.db");
+
+    let profile_cmd = |profile: &str| {
+        let mut cmd = Command::new(assert_cmd::cargo_bin!());
+        cmd.arg("--config")
+            .arg(s.config.as_ref())
+            .arg("--attachments-dir")
+            .arg(s.attach_dir.as_ref())
+            .args(["--no-interactive", "--profile", profile]);
+        cmd
+    };
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["import", "tests/resources/import/file.bib"]);
+    let mut cmd = profile_cmd("work");
+    cmd.args(["local", "a", "--with-field", "title = {T}"]);
     cmd.assert().success();
+    assert!(work_db.is_file());
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["import", "tests/resources/import/file.bib"]);
-    cmd.assert().success();
+    let mut cmd = profile_cmd("work");
+    cmd.args(["get", "local:a"]);
+    cmd.assert().success().stdout(contains("title = {T}"));
 
+    // an explicit database takes precedence over the profile
     let mut cmd = s.cmd()?;
-    cmd.args(["hist", "undo", "attainable-assouad-spectra"]);
-    cmd.assert()
-        .failure()
-        .stderr(contains("Cannot void record"));
+    cmd.args(["--profile", "work", "get", "local:a"]);
+    cmd.assert().failure().stderr(contains(
+        "Cannot retrieve remote data for key with local provenance",
+    ));
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["hist", "undo", "zbMATH06346461"]);
+    let mut cmd = profile_cmd("missing");
+    cmd.args(["get", "local:a"]);
     cmd.assert()
         .failure()
-        .stderr(contains("Cannot void record"));
+        .stderr(contains("Profile 'missing' is not defined"));
 
     s.close()
 }
 
 #[test]
-fn import_no_alias() -> Result<()> {
+fn project_config() -> Result<()> {
     let s = TestState::init()?;
+    let dir = TempDir::new()?;
+    dir.child(".autobib.toml")
+        .write_str("database = 'project.db'\n[output]\nindent = 4\n")?;
+    let paper = dir.child("paper");
+    fs::create_dir(paper.path())?;
+
+    let mut cmd = Command::new(assert_cmd::cargo_bin!());
+    cmd.current_dir(paper.path())
+        .arg("--config")
+        .arg(s.config.as_ref())
+        .arg("--attachments-dir")
+        .arg(s.attach_dir.as_ref())
+        .args([
+            "--no-interactive",
+            "local",
+            "a",
+            "--with-field",
+            "title = {T}",
+        ]);
+    cmd.assert().success();
+    assert!(dir.child("project.db").path().is_file());
 
+    // an explicit database takes precedence over the project configuration
     let mut cmd = s.cmd()?;
-    cmd.args(["import", "tests/resources/import/file.bib", "--no-alias"]);
+    cmd.current_dir(paper.path())
+        .args(["local", "b", "--with-field", "title = {U}"]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "attainable-assouad-spectra"]);
-    cmd.assert().failure().stderr(contains("Undefined alias"));
+    cmd.current_dir(paper.path()).args(["get", "local:b"]);
+    cmd.assert().success().stdout(contains("\n    title = {U}"));
+
+    let mut cmd = s.cmd()?;
+    cmd.current_dir(paper.path())
+        .args(["--no-project-config", "get", "local:b"]);
+    cmd.assert().success().stdout(contains("\n  title = {U}"));
 
     s.close()
 }
 
 #[test]
-fn no_key() -> Result<()> {
+fn get_default_out() -> Result<()> {
     let s = TestState::init()?;
+    let dir = TempDir::new()?;
+    dir.child(".autobib.toml")
+        .write_str("[get]\ndefault_out = 'references.bib'\ndefault_append = true\n")?;
+    let references = dir.child("references.bib");
+
+    for key in ["a", "b"] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["local", key, "--with-field", "title = {T}"]);
+        cmd.assert().success();
+    }
 
     let mut cmd = s.cmd()?;
+    cmd.current_dir(dir.path()).args(["get", "local:a"]);
+    cmd.assert().success().stdout(predicate::str::is_empty());
 
-    // no key for the import
-    cmd.args(["import", "tests/resources/import/no_ids.bib"]);
+    let mut cmd = s.cmd()?;
+    cmd.current_dir(dir.path())
+        .args(["get", "local:a", "local:b"]);
+    cmd.assert().success().stdout(predicate::str::is_empty());
+
+    let written = fs::read_to_string(references.path())?;
+    assert_eq!(written.matches("@misc{local:a,").count(), 1);
+    assert_eq!(written.matches("@misc{local:b,").count(), 1);
+
+    // without the project configuration, the entries are written to standard output
+    let mut cmd = s.cmd()?;
+    cmd.current_dir(dir.path())
+        .args(["--no-project-config", "get", "local:b"]);
+    cmd.assert().success().stdout(contains("@misc{local:b,"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "--append", "local:b"]);
     cmd.assert()
         .failure()
-        .stdout(contains("Could not determine candidate key"));
+        .stderr(contains("Option `--append` requires `--out`"));
+
+    s.close()
+}
+
+/// Check that `autobib util generate` populates the database.
+#[test]
+fn util_generate() -> Result<()> {
+    let s = TestState::init()?;
 
-    // but succeeds with local fallback
     let mut cmd = s.cmd()?;
     cmd.args([
-        "import",
-        "tests/resources/import/no_ids.bib",
-        "--local-fallback",
+        "util",
+        "generate",
+        "--records",
+        "20",
+        "--revisions",
+        "2",
+        "--attachments",
     ]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "local:my-article"]);
-    cmd.assert().success().stdout(contains("John, Doe"));
+    cmd.args(["get", "synthetic-6"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("@inproceedings{synthetic-6"))
+        .stdout(contains("Revision 2"));
 
     let mut cmd = s.cmd()?;
-    cmd.args([
-        "import",
-        "tests/resources/import/file.bib",
-        "--local-fallback",
-    ]);
-    cmd.assert().success();
+    cmd.args(["path", "local:synthetic-19"]);
+    let output = cmd.output()?;
+    let target = String::from_utf8(output.stdout)?;
+    assert!(Path::new(target.trim_end()).join("synthetic.txt").exists());
 
-    // local fallback is not used if the key could be determined
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "local:zbMATH06346461"]);
-    cmd.assert().failure();
+    cmd.args(["-v", "util", "generate", "--records", "25"]);
+    cmd.assert()
+        .success()
+        .stderr(contains("Skipped 20 records which already exist"))
+        .stderr(contains("Generated 5 synthetic records"));
 
-    // local fallback is not used if reference key is found
     let mut cmd = s.cmd()?;
-    cmd.args([
-        "import",
-        "tests/resources/import/retrieve.bib",
-        "--local-fallback",
-    ]);
-    cmd.assert()
-        .failure()
-        .stdout(contains("Failed to determine canonical id"));
+    cmd.args(["util", "check"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["--read-only", "util", "generate", "--records", "1"]);
+    cmd.assert().failure();
 
     s.close()
 }
 
 #[test]
-fn import_local_fallback_fails() -> Result<()> {
+fn replace_hard() -> Result<()> {
     let s = TestState::init()?;
 
+    s.set_config("tests/resources/import/config.toml")?;
+
     let mut cmd = s.cmd()?;
-    cmd.args(["local", "my-article"]);
+    cmd.args(["import", "tests/resources/import/init.bib", "--resolve"]);
     cmd.assert().success();
 
-    // local key already exists
     let mut cmd = s.cmd()?;
-    cmd.args([
-        "import",
-        "tests/resources/import/no_ids.bib",
-        "--local-fallback",
-    ]);
+    cmd.args(["replace", "arxiv:1212.1873", "--auto", "--hard"]);
     cmd.assert()
         .failure()
-        .stdout(contains("Local id 'local:my-article' already exists"));
+        .stderr(contains("is equivalent to the current identifier"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["alias", "add", "arx", "arxiv:1212.1873"]);
+    cmd.assert().success();
 
-    // contains colon
     let mut cmd = s.cmd()?;
     cmd.args([
-        "import",
-        "tests/resources/import/id_contains_colon.bib",
-        "--local-fallback",
+        "edit",
+        "arxiv:1212.1873",
+        "--set-field",
+        "zbmath = {6346461}",
     ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["replace", "arxiv:1212.1873", "--auto", "--hard"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "arx"]);
     cmd.assert()
-        .failure()
-        .stdout(contains("provider is invalid"));
+        .success()
+        .stdout(contains("zbmath = {6346461}"));
 
     s.close()
 }
 
 #[test]
-fn import_retrieve() -> Result<()> {
+fn check_bibtex() -> Result<()> {
     let s = TestState::init()?;
 
-    s.set_config("tests/resources/import/config.toml")?;
+    for (name, author, title, year) in [("first", "A", "T", "2000"), ("second", "B", "S", "2001")] {
+        let mut cmd = s.cmd()?;
+        cmd.args([
+            "local",
+            name,
+            "--with-entry-type",
+            "article",
+            "--with-field",
+            &format!("author = {{{author}}}"),
+            "--with-field",
+            "journal = {J}",
+            "--with-field",
+            &format!("title = {{{title}}}"),
+            "--with-field",
+            &format!("year = {{{year}}}"),
+        ]);
+        cmd.assert().success();
+    }
 
     let mut cmd = s.cmd()?;
-    cmd.args(["import", "tests/resources/import/retrieve.bib"]);
+    cmd.args(["check", "tests/resources/check/test.bib"]);
     cmd.assert()
         .failure()
-        .stdout(contains("Failed to determine canonical id"));
+        .stdout(
+            contains("local:second: field 'title' has value {Other} but database has {S}")
+                .and(contains("local:first: key appears 2 times"))
+                .and(contains("missing: not in database"))
+                .and(contains(
+                    "missing: entry type 'article' requires field 'author'",
+                ))
+                .and(contains(
+                    "missing: entry type 'article' requires field 'year' or 'date'",
+                ))
+                .and(contains("local:first: field").not()),
+        )
+        .stderr(contains("Found 6 problems"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["check", "--json", "tests/resources/check/test.bib"]);
+    cmd.assert().failure().stdout(
+        contains(r#""kind": "divergent-field""#)
+            .and(contains(r#""kind": "duplicate-key""#))
+            .and(contains(r#""entries": 4"#)),
+    );
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["import", "tests/resources/import/retrieve.bib", "--resolve"]);
-    cmd.assert().success();
+    let bib = assert_fs::NamedTempFile::new("valid.bib")?;
+    bib.write_str(
+        "@article{local:first, author = {A}, journal = {J}, title = {T}, year = {2000}}",
+    )?;
 
     let mut cmd = s.cmd()?;
-    cmd.args(["info", "abc", "--report", "canonical"]);
-    cmd.assert().success().stdout("zbmath:06346461\n");
+    cmd.arg("check").arg(bib.as_ref());
+    cmd.assert().success().stdout(predicate::str::is_empty());
 
+    bib.close()?;
     s.close()
 }
 
 #[test]
-fn import_update() -> Result<()> {
+fn json_output() -> Result<()> {
     let s = TestState::init()?;
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbmath:6346461"]);
-    cmd.assert().success();
-
     let mut cmd = s.cmd()?;
     cmd.args([
-        "import",
-        "tests/resources/import/retrieve.bib",
-        "--resolve",
-        "--update",
-        "prefer-current",
+        "local",
+        "a",
+        "--with-entry-type",
+        "article",
+        "--with-field",
+        "title = {1}",
     ]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbmath:6346461"]);
-    cmd.assert()
-        .success()
-        .stdout(contains("note = {extra}").and(contains("inverse theorems for entropy")));
+    cmd.args(["edit", "local:a", "--set-field", "title = {2}"]);
+    cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args([
-        "import",
-        "tests/resources/import/retrieve.bib",
-        "--resolve",
-        "--update",
-        "prefer-incoming",
-    ]);
+    cmd.args(["alias", "add", "b", "local:a"]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbmath:6346461"]);
+    cmd.args(["info", "--json", "b"]);
     cmd.assert().success().stdout(
-        contains("@book{")
-            .and(contains("note = {extra}"))
-            .and(contains("overlaps and typos")),
+        contains(r#""identifier": "b""#)
+            .and(contains(r#""canonical": "local:a""#))
+            .and(contains(r#""revision": "0002""#))
+            .and(contains(r#""status": "entry""#))
+            .and(contains(r#""title": "2""#))
+            .and(contains(r#""valid_bibtex": true"#)),
     );
 
-    s.close()
-}
-
-#[test]
-fn read_only() -> Result<()> {
-    let s = TestState::init()?;
-
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbl:1337.28015"]);
-    cmd.assert().success();
+    cmd.args(["info", "--json", "--report", "canonical", "b"]);
+    cmd.assert().failure();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["--read-only", "get", "zbl:1337.28015"]);
-    cmd.assert().success();
+    cmd.args(["log", "--json", "local:a"]);
+    cmd.assert().success().stdout(
+        contains(r#""revision": "0001""#)
+            .and(contains(r#""parent": "0001""#))
+            .and(contains(r#""active": true"#))
+            .and(contains(r#""title": "1""#)),
+    );
 
     let mut cmd = s.cmd()?;
-    cmd.args(["--read-only", "get", "arxiv:1212.1873"]);
+    cmd.args(["hist", "show", "--json", "--limit", "1"]);
     cmd.assert()
-        .failure()
-        .stderr(contains("Database does not contain key"));
-
-    for arg in ["check", "list"] {
-        let mut cmd = s.cmd()?;
-        cmd.args(["--read-only", "util", arg]);
-        cmd.assert().success();
-    }
+        .success()
+        .stdout(contains(r#""revision": "0002""#).and(contains(r#""revision": "0001""#).not()));
 
     let mut cmd = s.cmd()?;
-    cmd.args(["--read-only", "info", "zbl:1337.28015"]);
-    cmd.assert().success();
+    cmd.args(["util", "list", "--json"]);
+    cmd.assert()
+        .success()
+        .stdout(contains(r#""local:a""#).and(contains(r#""b""#)));
 
     let mut cmd = s.cmd()?;
-    cmd.args(["--read-only", "info", "arxiv:1212.1873"]);
+    cmd.args(["util", "list", "--json", "--canonical"]);
     cmd.assert()
-        .failure()
-        .stderr(contains("Cannot obtain report for record not in database"));
+        .success()
+        .stdout(contains(r#""local:a""#).and(contains(r#""b""#).not()));
 
-    Ok(())
+    s.close()
 }
 
 #[test]
-fn replace_auto() -> Result<()> {
+fn hist_squash() -> Result<()> {
     let s = TestState::init()?;
 
-    s.set_config("tests/resources/import/config.toml")?;
-
     let mut cmd = s.cmd()?;
-    cmd.args(["import", "tests/resources/import/init.bib", "--resolve"]);
+    cmd.args([
+        "local",
+        "a",
+        "--with-entry-type",
+        "article",
+        "--with-field",
+        "title = {1}",
+    ]);
     cmd.assert().success();
 
+    for title in ["2", "3", "4"] {
+        let mut cmd = s.cmd()?;
+        cmd.args([
+            "edit",
+            "local:a",
+            "--set-field",
+            &format!("title = {{{title}}}"),
+        ]);
+        cmd.assert().success();
+    }
+
     let mut cmd = s.cmd()?;
-    cmd.args(["replace", "arxiv:1212.1873", "--auto"]);
+    cmd.args([
+        "hist", "squash", "local:a", "--from", "0004", "--to", "0002",
+    ]);
     cmd.assert()
         .failure()
-        .stderr(contains("is equivalent to the current identifier"));
-
-    let mut cmd = s.cmd()?;
-    cmd.args(["alias", "add", "arx", "arxiv:1212.1873"]);
-    cmd.assert().success();
+        .stderr(contains("rev 0004 is not an ancestor of rev 0002"));
 
     let mut cmd = s.cmd()?;
     cmd.args([
-        "edit",
-        "arxiv:1212.1873",
-        "--set-field",
-        "zbmath = {6346461}",
+        "hist", "squash", "local:a", "--from", "0002", "--to", "0004",
     ]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["replace", "arxiv:1212.1873", "--auto"]);
+    cmd.args(["get", "local:a"]);
+    cmd.assert().success().stdout(contains("title = {4}"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["log", "local:a"]);
+    cmd.assert().success().stdout(
+        contains("rev 0004")
+            .and(contains("rev 0001"))
+            .and(contains("rev 0002").not())
+            .and(contains("rev 0003").not()),
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "undo", "local:a"]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "arxiv:1212.1873"]);
-    cmd.assert().failure().stderr(contains(
-        "Perhaps use the replacement key: 'zbmath:06346461'",
-    ));
+    cmd.args(["get", "local:a"]);
+    cmd.assert().success().stdout(contains("title = {1}"));
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "arx"]);
-    cmd.assert().failure().stderr(contains(
-        "Perhaps use the replacement key: 'zbmath:06346461'",
-    ));
+    cmd.args([
+        "hist", "squash", "local:a", "--from", "0001", "--to", "0004",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Cannot squash active revision"));
 
     let mut cmd = s.cmd()?;
-    cmd.args(["get", "zbmath:06346461"]);
-    cmd.assert().success().stdout(contains("@article{"));
+    cmd.args(["edit", "local:a", "--set-field", "title = {5}"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "hist", "squash", "local:a", "--from", "0001", "--to", "0005",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("with divergent changes"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "hist", "squash", "local:a", "--from", "0001", "--to", "ffff",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("does not exist for this record"));
 
     s.close()
 }
 
 #[test]
-fn replace_hard() -> Result<()> {
+fn serve() -> Result<()> {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpStream,
+        process::Stdio,
+    };
+
     let s = TestState::init()?;
 
-    s.set_config("tests/resources/import/config.toml")?;
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "local",
+        "a",
+        "--with-entry-type",
+        "article",
+        "--with-field",
+        "title = {On Spectral Theory}",
+    ]);
+    cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["import", "tests/resources/import/init.bib", "--resolve"]);
+    cmd.args(["alias", "add", "b", "local:a"]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["replace", "arxiv:1212.1873", "--auto", "--hard"]);
+    cmd.args(["serve", "--address", "127.0.0.1:0"])
+        .stdout(Stdio::piped());
+    let mut child = cmd.spawn()?;
+
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().ok_or("stdout is not piped")?).read_line(&mut line)?;
+    let address = line
+        .trim()
+        .strip_prefix("Listening on http://")
+        .ok_or("unexpected output")?
+        .to_owned();
+
+    let send = |raw: &str| -> Result<String> {
+        let mut stream = TcpStream::connect(&address)?;
+        stream.write_all(raw.as_bytes())?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        Ok(response)
+    };
+    let request = |target: &str| send(&format!("GET {target} HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+
+    let responses = [
+        request("/get?id=b"),
+        request("/get?id=local%3Amissing"),
+        request("/search?q=spectral+THEORY"),
+        request("/search?q=algebra"),
+        request("/list?canonical=true"),
+        request("/dump"),
+        request("/unknown"),
+        // the request is exactly as long as the limit, so that it is read completely, but the
+        // headers are never terminated
+        send(&format!(
+            "GET /get?id={} HTTP/1.1\r\n",
+            "a".repeat(16 * 1024 - 23)
+        )),
+    ];
+    child.kill()?;
+    child.wait()?;
+    let [
+        get,
+        get_missing,
+        search,
+        search_empty,
+        list,
+        dump,
+        unknown,
+        too_large,
+    ] = responses;
+
+    let get = get?;
+    assert!(get.starts_with("HTTP/1.1 200 OK"));
+    assert!(get.contains(r#""identifier":"b""#));
+    assert!(get.contains(r#""canonical":"local:a""#));
+    assert!(get.contains(r#""title":"On Spectral Theory""#));
+
+    assert!(get_missing?.starts_with("HTTP/1.1 404 Not Found"));
+
+    let search = search?;
+    assert!(search.starts_with("HTTP/1.1 200 OK"));
+    assert!(search.contains(r#""canonical":"local:a""#));
+    assert!(search_empty?.ends_with("[]"));
+
+    let list = list?;
+    assert!(list.ends_with(r#"["local:a"]"#));
+
+    let dump = dump?;
+    assert!(dump.contains("Content-Type: application/x-ndjson"));
+    assert!(dump.contains(r#"{"type":"header","format":"autobib-dump""#));
+    assert!(dump.contains(r#"{"type":"identifier","name":"b""#));
+
+    assert!(unknown?.starts_with("HTTP/1.1 404 Not Found"));
+
+    assert!(too_large?.starts_with("HTTP/1.1 431 Request Header Fields Too Large"));
+
+    s.close()
+}
+
+#[cfg(unix)]
+#[test]
+fn serve_stale_socket() -> Result<()> {
+    use std::{
+        io::{BufRead, BufReader},
+        os::unix::net::UnixListener,
+        process::Stdio,
+    };
+
+    let s = TestState::init()?;
+    let dir = TempDir::new()?;
+    let socket = dir.child("autobib.sock");
+
+    // a listening socket must not be removed
+    let listener = UnixListener::bind(socket.path())?;
+    let mut cmd = s.cmd()?;
+    cmd.args(["serve", "--socket"]).arg(socket.path());
     cmd.assert()
         .failure()
-        .stderr(contains("is equivalent to the current identifier"));
+        .stderr(contains("Another server is already listening"));
 
+    // a socket left behind by a stopped server is replaced
+    drop(listener);
     let mut cmd = s.cmd()?;
-    cmd.args(["alias", "add", "arx", "arxiv:1212.1873"]);
-    cmd.assert().success();
+    cmd.args(["serve", "--socket"])
+        .arg(socket.path())
+        .stdout(Stdio::piped());
+    let mut child = cmd.spawn()?;
+
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().ok_or("stdout is not piped")?).read_line(&mut line)?;
+    child.kill()?;
+    child.wait()?;
+    assert!(line.starts_with("Listening on"));
+
+    dir.close()?;
+    s.close()
+}
+
+#[test]
+fn lsp() -> Result<()> {
+    use std::{io::Write, process::Stdio};
+
+    let s = TestState::init()?;
 
     let mut cmd = s.cmd()?;
     cmd.args([
-        "edit",
-        "arxiv:1212.1873",
-        "--set-field",
-        "zbmath = {6346461}",
+        "local",
+        "first",
+        "--with-entry-type",
+        "article",
+        "--with-field",
+        "author = {Doe}",
+        "--with-field",
+        "title = {Spectral Theory}",
     ]);
     cmd.assert().success();
 
     let mut cmd = s.cmd()?;
-    cmd.args(["replace", "arxiv:1212.1873", "--auto", "--hard"]);
+    cmd.args(["alias", "add", "fi", "local:first"]);
     cmd.assert().success();
 
-    let mut cmd = s.cmd()?;
-    cmd.args(["get", "arx"]);
-    cmd.assert()
-        .success()
-        .stdout(contains("zbmath = {6346461}"));
+    let text = r"See \\cite{fi} and \\cite{loc";
+    let messages = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#.to_owned(),
+        r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#.to_owned(),
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"file:///doc.tex","languageId":"latex","version":1,"text":"{text}"}}}}}}"#
+        ),
+        r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/completion","params":{"textDocument":{"uri":"file:///doc.tex"},"position":{"line":0,"character":27}}}"#.to_owned(),
+        r#"{"jsonrpc":"2.0","id":3,"method":"textDocument/hover","params":{"textDocument":{"uri":"file:///doc.tex"},"position":{"line":0,"character":11}}}"#.to_owned(),
+        r#"{"jsonrpc":"2.0","id":4,"method":"shutdown"}"#.to_owned(),
+        r#"{"jsonrpc":"2.0","method":"exit"}"#.to_owned(),
+    ];
+
+    let mut cmd = s.cmd()?;
+    cmd.arg("lsp").stdin(Stdio::piped()).stdout(Stdio::piped());
+    let mut child = cmd.spawn()?;
+    {
+        let mut stdin = child.stdin.take().ok_or("stdin is not piped")?;
+        for message in messages {
+            write!(stdin, "Content-Length: {}\r\n\r\n{message}", message.len())?;
+        }
+    }
+    let output = child.wait_with_output()?;
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains(r#""hoverProvider":true"#));
+    assert!(stdout.contains(r#""newText":"local:first""#));
+    assert!(!stdout.contains(r#""newText":"fi""#));
+    assert!(stdout.contains("Doe ~ Spectral Theory"));
 
     s.close()
 }
@@ -1586,6 +6299,104 @@ fn changelog() -> Result<()> {
     s.close()
 }
 
+#[test]
+fn hist_undo_multiple() -> Result<()> {
+    let s = TestState::init()?;
+
+    for id in ["a", "b", "c"] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["local", id, "--with-field", "title = {Old}"]);
+        cmd.assert().success();
+    }
+
+    for id in ["local:a", "local:b"] {
+        let mut cmd = s.cmd()?;
+        cmd.args(["edit", id, "--set-field", "title = {New}"]);
+        cmd.assert().success();
+    }
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "undo", "local:*", "--atomic"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Cannot void record 'local:c'").and(contains(
+            "Not all records could be undone; no records were changed",
+        )));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:a", "local:b"]);
+    cmd.assert().success().stdout(
+        contains("title = {New}")
+            .count(2)
+            .and(contains("title = {Old}").not()),
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args([
+        "hist",
+        "undo",
+        "local:*",
+        "--since",
+        "2999-01-01T00:00:00+00:00",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "undo", "local:a", "local:b", "--atomic"]);
+    cmd.assert().success();
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:a", "local:b"]);
+    cmd.assert().success().stdout(
+        contains("title = {Old}")
+            .count(2)
+            .and(contains("title = {New}").not()),
+    );
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["hist", "undo", "local:?"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Cannot void record 'local:c'"));
+
+    s.close()
+}
+
+#[test]
+fn get_revision() -> Result<()> {
+    let s = TestState::init()?;
+    s.create_test_db()?;
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:first@0006"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("@book{local:first,").and(contains("title = {5}")));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "--rev", "0006", "local:first"]);
+    cmd.assert().success().stdout(contains("title = {5}"));
+
+    // the active version is not changed
+    let mut cmd = s.cmd()?;
+    cmd.args(["log", "local:first"]);
+    cmd.assert().success().stdout(contains("◉  rev 0006").not());
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "local:second@0006"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("Record 'local:second' does not have rev 0006"));
+
+    let mut cmd = s.cmd()?;
+    cmd.args(["get", "--rev", "0006", "local:first", "local:second"]);
+    cmd.assert()
+        .failure()
+        .stderr(contains("requires exactly one identifier"));
+
+    s.close()
+}
+
 #[test]
 fn test_prune() -> Result<()> {
     fn init() -> Result<(TestState, Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>)> {